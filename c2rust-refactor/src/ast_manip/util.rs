@@ -1,6 +1,8 @@
 //! Miscellaneous utility functions.
 use rustc::hir::def::{self, Namespace, Res};
 use smallvec::SmallVec;
+use std::collections::HashSet;
+use std::mem;
 use syntax::ast::*;
 use syntax::ptr::P;
 use syntax::source_map::{SourceMap, Span, DUMMY_SP};
@@ -8,7 +10,9 @@ use syntax::symbol::{kw, Symbol};
 use syntax_pos::sym;
 use smallvec::smallvec;
 
-use super::AstEquiv;
+use syntax::visit::{self, Visitor};
+
+use super::{AstEquiv, Visit};
 
 /// Extract the symbol from a pattern-like AST.
 pub trait PatternSymbol {
@@ -134,6 +138,23 @@ pub fn is_c2rust_attr(attr: &Attribute, name: &str) -> bool {
     }
 }
 
+/// Find the `#[c2rust::header_src = "/some/path:LINE"]` attribute, which the transpiler adds to
+/// items (usually modules) translated from a C header.
+pub fn source_header_attr(attrs: &[Attribute]) -> Option<&Attribute> {
+    attrs.iter().find(|attr| is_c2rust_attr(attr, "header_src"))
+}
+
+/// Get the header path recorded in the `header_src` attribute, without the include line number.
+pub fn source_header_path(attrs: &[Attribute]) -> Option<String> {
+    let value = source_header_attr(attrs)?.value_str()?.as_str();
+    let value: &str = &value;
+    let path = match value.rfind(':') {
+        Some(idx) if value[idx + 1..].bytes().all(|b| b.is_ascii_digit()) => &value[..idx],
+        _ => value,
+    };
+    Some(path.to_owned())
+}
+
 /// Get the text of a span, and pass it to a callback.  Returns `false` if the span text isn't
 /// available.
 pub fn with_span_text<F: FnOnce(&str)>(cm: &SourceMap, span: Span, callback: F) -> bool {
@@ -297,3 +318,70 @@ pub fn path_eq<T: AsRef<str>>(path: &Path, idents: &[T]) -> bool {
     path.segments.len() == idents.len()
         && path.segments.iter().zip(idents).all(|(p, i)| p.ident.as_str() == i.as_ref())
 }
+
+struct UnsafeExprs {
+    in_unsafe: bool,
+    ids: HashSet<NodeId>,
+}
+
+impl UnsafeExprs {
+    fn walk_with<F: FnOnce(&mut Self)>(&mut self, in_unsafe: bool, f: F) {
+        let old = mem::replace(&mut self.in_unsafe, in_unsafe);
+        f(self);
+        self.in_unsafe = old;
+    }
+}
+
+impl<'ast> Visitor<'ast> for UnsafeExprs {
+    fn visit_expr(&mut self, e: &'ast Expr) {
+        if self.in_unsafe {
+            self.ids.insert(e.id);
+        }
+        visit::walk_expr(self, e);
+    }
+
+    fn visit_block(&mut self, b: &'ast Block) {
+        let in_unsafe = self.in_unsafe || b.rules != BlockCheckMode::Default;
+        self.walk_with(in_unsafe, |this| visit::walk_block(this, b));
+    }
+
+    fn visit_item(&mut self, i: &'ast Item) {
+        let in_unsafe = match i.kind {
+            ItemKind::Fn(ref sig, _, _) => sig.header.unsafety == Unsafety::Unsafe,
+            _ => false,
+        };
+        self.walk_with(in_unsafe, |this| visit::walk_item(this, i));
+    }
+
+    fn visit_impl_item(&mut self, ii: &'ast ImplItem) {
+        let in_unsafe = match ii.kind {
+            ImplItemKind::Method(ref sig, _) => sig.header.unsafety == Unsafety::Unsafe,
+            _ => false,
+        };
+        self.walk_with(in_unsafe, |this| visit::walk_impl_item(this, ii));
+    }
+
+    fn visit_trait_item(&mut self, ti: &'ast TraitItem) {
+        let in_unsafe = match ti.kind {
+            TraitItemKind::Method(ref sig, _) => sig.header.unsafety == Unsafety::Unsafe,
+            _ => false,
+        };
+        self.walk_with(in_unsafe, |this| visit::walk_trait_item(this, ti));
+    }
+
+    fn visit_mac(&mut self, mac: &'ast Mac) {
+        visit::walk_mac(self, mac);
+    }
+}
+
+/// The IDs of the expressions in `target` that are in an unsafe context, i.e. in the body of an
+/// `unsafe fn` or in an `unsafe` block, where wrapping them in another `unsafe` block would only
+/// trigger the `unused_unsafe` lint.
+pub fn unsafe_exprs<T: Visit>(target: &T) -> HashSet<NodeId> {
+    let mut v = UnsafeExprs {
+        in_unsafe: false,
+        ids: HashSet::new(),
+    };
+    target.visit(&mut v);
+    v.ids
+}
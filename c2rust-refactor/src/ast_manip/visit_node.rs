@@ -108,6 +108,14 @@ gen_visit_node_impl! {
     walk = visit::walk_path(self, p);
 }
 
+gen_visit_node_impl! {
+    node = Ty;
+    visitor = TyNodeVisitor;
+    visitor_post = TyNodeVisitorPost;
+    fn visit_ty(&mut self, t: &'ast Ty);
+    walk = visit::walk_ty(self, t);
+}
+
 gen_visit_node_impl! {
     node = Block;
     visitor = BlockNodeVisitor;
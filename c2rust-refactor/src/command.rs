@@ -260,13 +260,10 @@ impl RefactorState {
     /// matches the text on disk) as the basis for rewriting.
     #[cfg_attr(feature = "profile", flame)]
     pub fn save_crate(&mut self) {
-        if let None = self.krate {
-            return;
-        }
-
-        let disk_state = self.disk_state.as_ref().unwrap();
-        let old = &disk_state.orig_krate;
-        let new = self.krate.as_ref().unwrap();
+        let new = match self.krate {
+            Some(ref krate) => krate,
+            None => return,
+        };
         let node_id_map = self.node_map.clone().into_inner();
 
         self.file_io
@@ -278,14 +275,32 @@ impl RefactorState {
             )
             .unwrap();
 
-        let rw = rewrite::rewrite(self.session(), old, new, &disk_state.comment_map, node_id_map, |map| {
-            map_ast_into(&self.parsed_nodes, map);
-        });
+        let rw = self.rewrite_crate().unwrap();
         // Note that `rewrite_files_with` does not read any files from disk - it uses the
         // `SourceMap` to get files' original source text.
         files::rewrite_files_with(self.source_map(), &rw, &*self.file_io).unwrap();
     }
 
+    /// Compute the rewrite from the original source to the current crate, or `None` if no
+    /// commands have been run since the crate was loaded.
+    fn rewrite_crate(&self) -> Option<rewrite::TextRewrite> {
+        let new = self.krate.as_ref()?;
+        let disk_state = self.disk_state.as_ref().unwrap();
+        let old = &disk_state.orig_krate;
+        let node_id_map = self.node_map.clone().into_inner();
+
+        Some(rewrite::rewrite(self.session(), old, new, &disk_state.comment_map, node_id_map, |map| {
+            map_ast_into(&self.parsed_nodes, map);
+        }))
+    }
+
+    /// Summarize the source text that `save_crate` would write, without writing anything.
+    #[cfg_attr(feature = "profile", flame)]
+    pub fn snapshot_crate(&self) -> files::RewriteSnapshot {
+        let rw = self.rewrite_crate();
+        files::snapshot_rewrite(self.source_map(), rw.as_ref())
+    }
+
     #[cfg_attr(feature = "profile", flame)]
     pub fn transform_crate<F, R>(&mut self, phase: Phase, f: F) -> interface::Result<R>
     where
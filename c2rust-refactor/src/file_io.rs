@@ -5,6 +5,7 @@ use std::mem;
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 
+use indexmap::IndexMap;
 use json::{self, JsonValue};
 use syntax::ast::*;
 use syntax::source_map::{FileLoader, SourceFile, SourceMap};
@@ -23,6 +24,11 @@ pub trait FileIO {
         Ok(())
     }
 
+    /// Called once all commands have run and all rewrites have been written.
+    fn finish(&self) -> io::Result<()> {
+        Ok(())
+    }
+
     fn file_exists(&self, path: &Path) -> bool {
         fs::metadata(path).is_ok()
     }
@@ -85,10 +91,31 @@ impl OutputMode {
     }
 }
 
+/// Settings for the `diff` output mode.
+#[derive(Clone, Debug)]
+pub struct DiffOptions {
+    /// Number of unchanged lines to include around each change.
+    pub context: usize,
+    /// File to write the diff to.  If this is `None`, the diff is printed to stdout.
+    pub out: Option<PathBuf>,
+}
+
+impl Default for DiffOptions {
+    fn default() -> DiffOptions {
+        DiffOptions {
+            context: 3,
+            out: None,
+        }
+    }
+}
+
 struct RealState {
     rewrite_counter: usize,
     rewrites_json: Vec<JsonValue>,
     file_state: HashMap<PathBuf, String>,
+    /// For the `diff` output mode: the text of each rewritten file before the first rewrite
+    /// (`None` for new files), and its latest text.
+    diffs: IndexMap<PathBuf, (Option<String>, String)>,
 }
 
 impl RealState {
@@ -97,12 +124,14 @@ impl RealState {
             rewrite_counter: 0,
             rewrites_json: Vec::new(),
             file_state: HashMap::new(),
+            diffs: IndexMap::new(),
         }
     }
 }
 
 pub struct RealFileIO {
     output_modes: Vec<OutputMode>,
+    diff_options: DiffOptions,
     state: Mutex<RealState>,
 }
 
@@ -110,9 +139,17 @@ impl RealFileIO {
     pub fn new(modes: Vec<OutputMode>) -> RealFileIO {
         RealFileIO {
             output_modes: modes,
+            diff_options: DiffOptions::default(),
             state: Mutex::new(RealState::new()),
         }
     }
+
+    pub fn with_diff_options(self, diff_options: DiffOptions) -> RealFileIO {
+        RealFileIO {
+            diff_options,
+            ..self
+        }
+    }
 }
 
 impl FileIO for RealFileIO {
@@ -134,6 +171,39 @@ impl FileIO for RealFileIO {
         Ok(())
     }
 
+    fn finish(&self) -> io::Result<()> {
+        if !self.output_modes.contains(&OutputMode::PrintDiff) {
+            return Ok(());
+        }
+
+        let mut state = self.state.lock().unwrap();
+        let mut patch = String::new();
+        for (path, (old_s, new_s)) in state.diffs.drain(..) {
+            let hunks = rewrite::files::unified_diff(
+                old_s.as_ref().map_or("", |s| s),
+                &new_s,
+                self.diff_options.context,
+            );
+            if hunks.is_empty() {
+                continue;
+            }
+            match old_s {
+                Some(_) => patch.push_str(&format!("--- a/{}\n", path.display())),
+                None => patch.push_str("--- /dev/null\n"),
+            }
+            patch.push_str(&format!("+++ b/{}\n", path.display()));
+            patch.push_str(&hunks);
+        }
+
+        match self.diff_options.out {
+            Some(ref out) => fs::write(out, patch),
+            None => {
+                print!("{}", patch);
+                Ok(())
+            }
+        }
+    }
+
     fn read_file(&self, path: &Path) -> io::Result<String> {
         let state = self.state.lock().unwrap();
         let path = fs::canonicalize(path)?;
@@ -154,11 +224,16 @@ impl FileIO for RealFileIO {
                     println!(" ==== {:?} ====\n{}\n =========", path, s);
                 }
                 OutputMode::PrintDiff => {
-                    let old_s = self.read_file(path)?;
-                    println!();
-                    println!("--- old/{}", path.display());
-                    println!("+++ new/{}", path.display());
-                    rewrite::files::print_diff(&old_s, s);
+                    // Files can be rewritten several times, so only record the text here, and
+                    // print a single diff per file from the original text in `finish`.
+                    let seen = self.state.lock().unwrap().diffs.contains_key(path);
+                    let old_s = if seen { None } else { self.read_file(path).ok() };
+                    let mut state = self.state.lock().unwrap();
+                    state
+                        .diffs
+                        .entry(path.to_owned())
+                        .or_insert_with(|| (old_s, String::new()))
+                        .1 = s.to_owned();
                 }
                 OutputMode::Json => {}  // Handled in end_rewrite
                 OutputMode::Marks => {} // Handled in save_marks
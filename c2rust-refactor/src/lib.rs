@@ -55,9 +55,13 @@ use std::collections::HashSet;
 use std::env;
 use std::path::{Path, PathBuf};
 use std::str::{self, FromStr};
+use std::cmp::Reverse;
 use std::sync::Arc;
 use syntax::ast::NodeId;
+use syntax::source_map::SourceMap;
 
+use crate::file_io::FileIO;
+use crate::rewrite::files::RewriteSnapshot;
 use c2rust_ast_builder::IntoSymbol;
 
 pub use crate::context::RefactorCtxt;
@@ -130,6 +134,11 @@ struct RustcArgs {
 
 pub struct Options {
     pub rewrite_modes: Vec<file_io::OutputMode>,
+    /// Settings for the `diff` rewrite mode.
+    pub diff_options: file_io::DiffOptions,
+    /// Run all commands, but write nothing to disk.  Instead, print a summary of the changes made
+    /// by each command.
+    pub dry_run: bool,
     pub commands: Vec<Command>,
     pub rustc_args: RustcArgSource,
     pub cursors: Vec<Cursor>,
@@ -455,14 +464,31 @@ fn main_impl(opts: Options) -> interface::Result<()> {
                 config,
                 cmd_reg,
                 opts.rewrite_modes.clone(),
+                opts.diff_options.clone(),
+                opts.dry_run,
             ).expect("Error loading user script");
         } else {
-            let file_io = Arc::new(file_io::RealFileIO::new(opts.rewrite_modes.clone()));
-            driver::run_refactoring(config, cmd_reg, file_io, marks, |mut state| {
+            // In a dry run, no output mode is active, so `commit` keeps the rewritten text in
+            // memory for the following commands instead of writing it out.
+            let rewrite_modes = if opts.dry_run {
+                vec![]
+            } else {
+                opts.rewrite_modes.clone()
+            };
+            let file_io = Arc::new(
+                file_io::RealFileIO::new(rewrite_modes)
+                    .with_diff_options(opts.diff_options.clone()),
+            );
+            driver::run_refactoring(config, cmd_reg, file_io.clone(), marks, |mut state| {
                 for cmd in opts.commands.clone() {
                     if &cmd.name == "interact" {
                         panic!("`interact` must be the only command");
                     } else {
+                        let before = if opts.dry_run {
+                            Some(state.snapshot_crate())
+                        } else {
+                            None
+                        };
                         match state.run(&cmd.name, &cmd.args) {
                             Ok(_) => {}
                             Err(e) => {
@@ -470,11 +496,18 @@ fn main_impl(opts: Options) -> interface::Result<()> {
                                 std::process::exit(1);
                             }
                         }
+                        if let Some(before) = before {
+                            let after = state.snapshot_crate();
+                            print_dry_run_summary(&cmd, state.source_map(), &before, &after);
+                        }
                     }
                 }
 
-                state.save_crate();
+                if !opts.dry_run {
+                    state.save_crate();
+                }
             });
+            file_io.finish().expect("Error writing diff output");
         }
 
         // We need to rebuild the crate metadata if this was a library and we
@@ -491,6 +524,59 @@ fn main_impl(opts: Options) -> interface::Result<()> {
     Ok(())
 }
 
+/// Number of changes listed in each `--dry-run` summary.
+const DRY_RUN_LISTED_CHANGES: usize = 5;
+
+/// Print the changes made by `cmd`, given snapshots of the rewritten crate from before and after
+/// it ran.  The largest changes are listed first.
+fn print_dry_run_summary(
+    cmd: &Command,
+    sm: &SourceMap,
+    before: &RewriteSnapshot,
+    after: &RewriteSnapshot,
+) {
+    let mut changes = after
+        .changes
+        .difference(&before.changes)
+        .map(|&(span, _)| span)
+        .collect::<Vec<_>>();
+    changes.sort_by_key(|span| (Reverse(span.hi() - span.lo()), span.lo()));
+
+    // Files that were only loaded by `cmd`, after a `commit`, are missing from `before`, but then
+    // their text as loaded is their text from before `cmd`.
+    let mut files = after
+        .file_hashes
+        .iter()
+        .filter(|&(path, &(loaded, new))| {
+            let old = before.file_hashes.get(path).map_or(loaded, |&(_, old)| old);
+            old != new
+        })
+        .map(|(path, _)| path)
+        .collect::<Vec<_>>();
+    files.sort();
+
+    let cmd_str = cmd.args.iter().fold(cmd.name.clone(), |mut s, arg| {
+        s.push(' ');
+        s.push_str(arg);
+        s
+    });
+    println!(
+        "dry run: {}: {} nodes rewritten, {} files would change",
+        cmd_str,
+        changes.len(),
+        files.len()
+    );
+    for path in files {
+        println!("  file: {}", path.display());
+    }
+    for &span in changes.iter().take(DRY_RUN_LISTED_CHANGES) {
+        println!("  change: {}", sm.span_to_string(span));
+    }
+    if changes.len() > DRY_RUN_LISTED_CHANGES {
+        println!("  ... and {} more", changes.len() - DRY_RUN_LISTED_CHANGES);
+    }
+}
+
 #[cfg(feature = "profile")]
 fn dump_profile() {
     flame::dump_html(&mut std::fs::File::create("flame-graph.html").unwrap()).unwrap();
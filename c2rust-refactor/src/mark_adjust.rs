@@ -1,12 +1,16 @@
 //! This module implements commands for manipulating the current set of marked nodes.
 use rustc::hir;
 use rustc::hir::def::{DefKind, Res};
+use json::{self, JsonValue};
 use rustc::ty::TyKind;
+use std::collections::{HashMap, HashSet};
+use std::fs;
 use std::str::FromStr;
 use syntax::ast;
 use syntax::ast::*;
+use syntax::source_map::{SourceMap, Span};
 use syntax::symbol::Symbol;
-use syntax::visit::{self, Visitor};
+use syntax::visit::{self, FnKind, Visitor};
 
 use crate::ast_manip::{visit_nodes, Visit};
 use crate::command::CommandState;
@@ -433,6 +437,213 @@ fn register_clear_marks(reg: &mut Registry) {
     });
 }
 
+/// A node location that stays the same between runs: the file name, the byte offsets of the start
+/// and end of the node's span within that file, and the node kind.
+type NodeLoc = (String, u32, u32, String);
+
+/// Collect the location of every markable node in the crate.
+struct NodeLocVisitor<'a> {
+    sm: &'a SourceMap,
+    locs: Vec<(NodeId, NodeLoc)>,
+}
+
+impl<'a> NodeLocVisitor<'a> {
+    fn record(&mut self, kind: &'static str, id: NodeId, span: Span) {
+        if span.is_dummy() {
+            return;
+        }
+        let lo = self.sm.lookup_byte_offset(span.lo());
+        let hi = self.sm.lookup_byte_offset(span.hi());
+        if lo.sf.start_pos != hi.sf.start_pos {
+            return;
+        }
+        let file = lo.sf.name.to_string();
+        self.locs.push((id, (file, lo.pos.0, hi.pos.0, kind.to_owned())));
+    }
+}
+
+impl<'a, 'ast> Visitor<'ast> for NodeLocVisitor<'a> {
+    fn visit_item(&mut self, x: &'ast Item) {
+        self.record("item", x.id, x.span);
+        visit::walk_item(self, x);
+    }
+
+    fn visit_trait_item(&mut self, x: &'ast TraitItem) {
+        self.record("trait_item", x.id, x.span);
+        visit::walk_trait_item(self, x);
+    }
+
+    fn visit_impl_item(&mut self, x: &'ast ImplItem) {
+        self.record("impl_item", x.id, x.span);
+        visit::walk_impl_item(self, x);
+    }
+
+    fn visit_foreign_item(&mut self, x: &'ast ForeignItem) {
+        self.record("foreign_item", x.id, x.span);
+        visit::walk_foreign_item(self, x);
+    }
+
+    fn visit_stmt(&mut self, x: &'ast Stmt) {
+        self.record("stmt", x.id, x.span);
+        visit::walk_stmt(self, x);
+    }
+
+    fn visit_expr(&mut self, x: &'ast Expr) {
+        self.record("expr", x.id, x.span);
+        visit::walk_expr(self, x);
+    }
+
+    fn visit_pat(&mut self, x: &'ast Pat) {
+        self.record("pat", x.id, x.span);
+        visit::walk_pat(self, x);
+    }
+
+    fn visit_ty(&mut self, x: &'ast Ty) {
+        self.record("ty", x.id, x.span);
+        visit::walk_ty(self, x);
+    }
+
+    fn visit_fn(&mut self, kind: FnKind<'ast>, fd: &'ast FnDecl, span: Span, _id: NodeId) {
+        for arg in &fd.inputs {
+            self.record("param", arg.id, arg.span);
+        }
+        visit::walk_fn(self, kind, fd, span);
+    }
+
+    fn visit_struct_field(&mut self, x: &'ast StructField) {
+        self.record("field", x.id, x.span);
+        visit::walk_struct_field(self, x);
+    }
+
+    fn visit_mac(&mut self, x: &'ast Mac) {
+        visit::walk_mac(self, x);
+    }
+}
+
+fn node_locs(krate: &Crate, sm: &SourceMap) -> Vec<(NodeId, NodeLoc)> {
+    let mut v = NodeLocVisitor {
+        sm,
+        locs: Vec::new(),
+    };
+    visit::walk_crate(&mut v, krate);
+    v.record("crate", CRATE_NODE_ID, krate.span);
+    v.locs
+}
+
+/// # `save_marks` Command
+///
+/// Usage: `save_marks PATH`
+///
+/// Marks: reads all marks
+///
+/// Write all current marks to the JSON file `PATH`, so they can be inspected or edited by hand,
+/// and restored in a later run with `load_marks`.  `NodeId`s change between runs, so each mark is
+/// saved as the file, byte range, and kind of the marked node, along with the mark's label.
+fn save_marks(st: &CommandState, sm: &SourceMap, path: &str) {
+    let marks = st.marks();
+    let mut labels_by_id = HashMap::new();
+    for &(id, label) in marks.iter() {
+        labels_by_id.entry(id).or_insert_with(Vec::new).push(label);
+    }
+
+    let mut entries = Vec::new();
+    let mut saved = HashSet::new();
+    for (id, (file, lo, hi, kind)) in node_locs(&st.krate(), sm) {
+        let labels = match_or!([labels_by_id.get(&id)] Some(x) => x; continue);
+        for &label in labels {
+            if !saved.insert((id, label)) {
+                continue;
+            }
+            entries.push((file.clone(), lo, hi, kind.clone(), label.as_str().to_string()));
+        }
+    }
+    entries.sort();
+
+    for &(id, label) in marks.iter() {
+        if !saved.contains(&(id, label)) {
+            warn!(
+                "save_marks: node {} has no source location; not saving mark {}",
+                id.as_usize(),
+                label.as_str()
+            );
+        }
+    }
+
+    let js = entries
+        .into_iter()
+        .map(|(file, lo, hi, kind, label)| {
+            object! {
+                "file" => file,
+                "lo" => lo,
+                "hi" => hi,
+                "kind" => kind,
+                "label" => label,
+            }
+        })
+        .collect();
+    fs::write(path, json::stringify_pretty(JsonValue::Array(js), 2))
+        .unwrap_or_else(|e| panic!("save_marks: could not write {:?}: {}", path, e));
+}
+
+/// # `load_marks` Command
+///
+/// Usage: `load_marks PATH`
+///
+/// Marks: sets the labels listed in `PATH`
+///
+/// Read marks saved by `save_marks` from the JSON file `PATH`, and add each one to the node with
+/// the same file, byte range, and kind in the current crate.  Marks whose node no longer exists,
+/// for example because the source was edited, are reported and skipped.
+fn load_marks(st: &CommandState, sm: &SourceMap, path: &str) {
+    let src = fs::read_to_string(path)
+        .unwrap_or_else(|e| panic!("load_marks: could not read {:?}: {}", path, e));
+    let entries = json::parse(&src)
+        .unwrap_or_else(|e| panic!("load_marks: could not parse {:?}: {}", path, e));
+
+    let mut ids_by_loc = HashMap::new();
+    for (id, loc) in node_locs(&st.krate(), sm) {
+        ids_by_loc.entry(loc).or_insert_with(Vec::new).push(id);
+    }
+
+    let mut loaded = 0;
+    let mut unresolved = 0;
+    for entry in entries.members() {
+        let (file, lo, hi, kind, label) = match (
+            entry["file"].as_str(),
+            entry["lo"].as_u32(),
+            entry["hi"].as_u32(),
+            entry["kind"].as_str(),
+            entry["label"].as_str(),
+        ) {
+            (Some(file), Some(lo), Some(hi), Some(kind), Some(label)) => {
+                (file, lo, hi, kind, label)
+            }
+            _ => {
+                warn!("load_marks: malformed mark {}; skipping", entry.dump());
+                unresolved += 1;
+                continue;
+            }
+        };
+
+        match ids_by_loc.get(&(file.to_owned(), lo, hi, kind.to_owned())) {
+            Some(ids) => {
+                for &id in ids {
+                    st.add_mark(id, label);
+                }
+                loaded += 1;
+            }
+            None => {
+                warn!(
+                    "load_marks: no {} at {}:{}-{} for mark {}; skipping",
+                    kind, file, lo, hi, label
+                );
+                unresolved += 1;
+            }
+        }
+    }
+    info!("load_marks: loaded {} marks, {} unresolved", loaded, unresolved);
+}
+
 pub fn register_commands(reg: &mut Registry) {
     reg.register("print_marks", |_| {
         Box::new(DriverCommand::new(Phase::Phase2, move |st, _cx| {
@@ -500,5 +711,19 @@ pub fn register_commands(reg: &mut Registry) {
         }))
     });
 
+    reg.register("save_marks", |args| {
+        let path = args[0].clone();
+        Box::new(DriverCommand::new(Phase::Phase2, move |st, cx| {
+            save_marks(st, cx.session().source_map(), &path);
+        }))
+    });
+
+    reg.register("load_marks", |args| {
+        let path = args[0].clone();
+        Box::new(DriverCommand::new(Phase::Phase2, move |st, cx| {
+            load_marks(st, cx.session().source_map(), &path);
+        }))
+    });
+
     register_clear_marks(reg);
 }
@@ -34,22 +34,36 @@
 //!    the resolved type of the node is converted back to an AST using the `reflect` module, and
 //!    the new AST is matched against `ty`.
 //!
+//!    Instead of a type, `ty` can be a list of trait bounds, written `impl Trait1 + Trait2` or
+//!    `?Sized + Trait1`, in which case the resolved type must implement each of the traits.  The
+//!    trait paths are compared to the absolute paths of the traits, as in `def!`, so
+//!    `typed!(__x, ?Sized + ::std::ops::Add)` matches any expression whose type implements `Add`.
+//!    When `x` is a lone metavariable such as `__x`, the constraint is also recorded in the
+//!    `MatchCtxt`, and applies to every later capture of `__x` in the same match.  A trailing
+//!    comma after `ty` is allowed; any other syntax error in `typed!` is a fatal error.
+//!
 //!  * `cast!(x)`: Matches the `Expr`s `x`, `x as __t`, `x as __t as __u`, etc.
 
-use rustc::hir::def_id::DefId;
+use rustc::hir::def_id::{DefId, LOCAL_CRATE};
 use rustc::session::Session;
+use rustc::traits::{self, ObligationCause};
+use rustc::ty::{self, ParamEnv, ToPredicate};
+use rustc::ty::subst::InternalSubsts;
 use smallvec::SmallVec;
 use std::cmp;
+use std::collections::HashMap;
 use std::result;
-use syntax::ast::{Block, Expr, ExprKind, Ident, Item, Label, Lit, MacArgs, Pat, Path, Stmt, Ty};
+use syntax::ast::{Block, Expr, ExprKind, GenericBound, GenericBounds, Ident, Item, Label, Lit};
+use syntax::ast::{MacArgs, NodeId, Pat, Path, Stmt, TraitBoundModifier, Ty, TyKind};
 use syntax::mut_visit::{self, MutVisitor};
+use syntax::print::pprust;
 use rustc_parse::parser::{Parser, PathStyle};
-use syntax::token::{TokenKind};
-use rustc_errors::PResult;
+use syntax::token::{Token, TokenKind};
+use rustc_errors::{FatalError, PResult};
 use syntax::ptr::P;
-use syntax::symbol::Symbol;
-use syntax::tokenstream::TokenStream;
-use syntax_pos::FileName;
+use syntax::symbol::{kw, Symbol};
+use syntax::tokenstream::{TokenStream, TokenTree};
+use syntax_pos::{FileName, DUMMY_SP};
 
 use crate::ast_manip::util::PatternSymbol;
 use crate::ast_manip::{remove_paren, GetNodeId, MutVisit};
@@ -98,12 +112,57 @@ pub enum Error {
     BadSpecialPattern(Symbol),
 }
 
+/// A constraint on the resolved type of a matched node, as written in the second argument of
+/// `typed!`.
+#[derive(Clone, Debug)]
+pub enum TypeConstraint {
+    /// The type must match this type pattern.
+    Type(P<Ty>),
+    /// The type must implement each of these traits.  `?Sized` bounds are ignored.
+    Bounds(GenericBounds),
+}
+
+impl TypeConstraint {
+    /// Parse a type constraint: a type, `impl Bounds`, or `?Sized + Bounds`, optionally followed by
+    /// a comma.  Syntax errors are reported as fatal errors.
+    pub fn parse_tts(sess: &Session, mut tts: Vec<TokenTree>) -> TypeConstraint {
+        if let Some(TokenTree::Token(Token { kind: TokenKind::Comma, .. })) = tts.last() {
+            tts.pop();
+        }
+        let starts_with_maybe = match tts.first() {
+            Some(TokenTree::Token(Token { kind: TokenKind::Question, .. })) => true,
+            _ => false,
+        };
+        if starts_with_maybe {
+            tts.insert(0, TokenTree::token(TokenKind::Ident(kw::Impl, false), DUMMY_SP));
+        }
+        let mut p = rustc_parse::new_parser_from_tts(&sess.parse_sess, tts);
+        let ty = match p.parse_ty() {
+            Ok(ty) => ty,
+            Err(mut db) => {
+                db.emit();
+                FatalError.raise()
+            }
+        };
+        if p.token.kind != TokenKind::Eof {
+            sess.span_fatal(p.token.span, "unexpected tokens after the type in `typed!`");
+        }
+        match ty.kind {
+            TyKind::ImplTrait(_, ref bounds) => TypeConstraint::Bounds(bounds.clone()),
+            _ => TypeConstraint::Type(ty),
+        }
+    }
+}
+
 /// Pattern-matching context.  Stores configuration that affects pattern matching behavior, and
 /// collects bindings captured during the match.
 #[derive(Clone)]
 pub struct MatchCtxt<'a, 'tcx: 'a> {
     pub bindings: Bindings,
     pub types: BindingTypes,
+    /// Constraints on the types of the expressions captured by some metavariables.  A capture
+    /// that doesn't satisfy its constraint makes the match fail.
+    pub ty_constraints: HashMap<Symbol, TypeConstraint>,
     st: &'a CommandState,
     cx: &'a RefactorCtxt<'a, 'tcx>,
     pub debug: bool,
@@ -114,6 +173,7 @@ impl<'a, 'tcx> MatchCtxt<'a, 'tcx> {
         MatchCtxt {
             bindings: Bindings::new(),
             types: BindingTypes::new(),
+            ty_constraints: HashMap::new(),
             st,
             cx,
             debug: false,
@@ -230,6 +290,77 @@ impl<'a, 'tcx> MatchCtxt<'a, 'tcx> {
         self.types.set_type(name, ty)
     }
 
+    /// Require the expressions captured by the metavariable `name` to satisfy `constraint`.
+    pub fn set_type_constraint<S: IntoSymbol>(&mut self, name: S, constraint: TypeConstraint) {
+        self.ty_constraints.insert(name.into_symbol(), constraint);
+    }
+
+    /// Check that the resolved type of node `id` satisfies `constraint`.
+    pub fn check_type_constraint(&mut self, constraint: &TypeConstraint, id: NodeId) -> Result<()> {
+        let tcx_ty = self.cx.opt_node_type(id).ok_or(Error::TypeUnavailable)?;
+        match *constraint {
+            TypeConstraint::Type(ref ty_pattern) => {
+                let ast_ty = reflect::reflect_tcx_ty(self.cx.ty_ctxt(), tcx_ty);
+                if self.debug {
+                    eprintln!(
+                        "typed!(): trying to match pattern {:?} against AST {:?}",
+                        ty_pattern, ast_ty
+                    );
+                }
+                if self.try_match(ty_pattern, &ast_ty).is_err() {
+                    return Err(Error::WrongType);
+                }
+            }
+            TypeConstraint::Bounds(ref bounds) => {
+                for bound in bounds {
+                    let path = match *bound {
+                        GenericBound::Trait(_, TraitBoundModifier::Maybe) |
+                        GenericBound::Outlives(_) => continue,
+                        GenericBound::Trait(ref poly, _) => &poly.trait_ref.path,
+                    };
+                    let trait_id = self.resolve_trait(path).ok_or(Error::WrongType)?;
+                    if !self.implements_trait(tcx_ty, trait_id) {
+                        return Err(Error::WrongType);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Find the trait whose absolute path is `path`.
+    fn resolve_trait(&self, path: &Path) -> Option<DefId> {
+        let tcx = self.cx.ty_ctxt();
+        let wanted = pprust::path_to_string(path);
+        let wanted = wanted.trim_start_matches("::");
+        tcx.all_traits(LOCAL_CRATE).iter().cloned().find(|&did| {
+            let (_, def_path) = reflect::reflect_def_path(tcx, did);
+            pprust::path_to_string(&def_path).trim_start_matches("::") == wanted
+        })
+    }
+
+    /// Check if `ty` may implement the trait `trait_id`, for some choice of the trait's other type
+    /// parameters.
+    fn implements_trait(&self, target_ty: ty::Ty<'tcx>, trait_id: DefId) -> bool {
+        let tcx = self.cx.ty_ctxt();
+        tcx.infer_ctxt().enter(|infcx| {
+            let substs = InternalSubsts::for_item(tcx, trait_id, |param, _| {
+                if param.index == 0 {
+                    target_ty.into()
+                } else {
+                    infcx.var_for_def(DUMMY_SP, param)
+                }
+            });
+            let trait_ref = ty::TraitRef::new(trait_id, substs);
+            let obligation = traits::Obligation::new(
+                ObligationCause::dummy(),
+                ParamEnv::empty(),
+                trait_ref.to_predicate(),
+            );
+            infcx.predicate_may_hold(&obligation)
+        })
+    }
+
     fn is_opt_binding<P: PatternSymbol>(&self, pattern: &P) -> bool {
         let sym = match pattern.pattern_symbol() {
             Some(x) => x,
@@ -389,6 +520,10 @@ impl<'a, 'tcx> MatchCtxt<'a, 'tcx> {
             _ => return Ok(false),
         }
 
+        if let Some(constraint) = self.ty_constraints.get(&sym).cloned() {
+            self.check_type_constraint(&constraint, target.id)?;
+        }
+
         let ok = self.bindings.try_add(sym, P(target.clone()));
         if ok {
             Ok(true)
@@ -558,35 +693,51 @@ impl<'a, 'tcx> MatchCtxt<'a, 'tcx> {
     /// Handle the `typed!(...)` matching form.
     pub fn do_typed<T, F>(&mut self, args: &MacArgs, func: F, target: &T) -> Result<()>
     where
-        T: TryMatch + GetNodeId,
-        F: for<'b> FnOnce(&mut Parser<'b>) -> PResult<'b, T>,
+        T: TryMatch + GetNodeId + PatternSymbol,
+        F: for<'b> Fn(&mut Parser<'b>) -> PResult<'b, T>,
     {
-        let mut p = Parser::new(
-            &self.cx.session().parse_sess,
-            args.inner_tokens(),
-            None,
-            false,
-            false,
-            None,
-        );
-        let pattern = func(&mut p).unwrap();
-        p.expect(&TokenKind::Comma).unwrap();
-        let ty_pattern = p.parse_ty().unwrap();
-
-        let tcx_ty = self
-            .cx
-            .opt_node_type(target.get_node_id())
-            .ok_or(Error::TypeUnavailable)?;
-        let ast_ty = reflect::reflect_tcx_ty(self.cx.ty_ctxt(), tcx_ty);
-
-        if self.debug {
-            eprintln!(
-                "typed!(): trying to match pattern {:?} against AST {:?}",
-                ty_pattern, ast_ty
-            );
+        // The pattern ends at the first comma where it parses completely.  Commas before that
+        // one are part of the pattern, as in `typed!(f(a, b), u32)`.
+        let sess = self.cx.session();
+        let tts = args.inner_tokens().into_trees().collect::<Vec<_>>();
+        let split = tts.iter().enumerate().filter_map(|(i, tt)| match tt {
+            TokenTree::Token(Token { kind: TokenKind::Comma, .. }) => {
+                let mut p = rustc_parse::new_parser_from_tts(&sess.parse_sess, tts[..i].to_vec());
+                match func(&mut p) {
+                    Ok(pattern) if p.token.kind == TokenKind::Eof => Some((i, pattern)),
+                    Ok(_) => None,
+                    Err(mut db) => {
+                        db.cancel();
+                        None
+                    }
+                }
+            }
+            _ => None,
+        }).next();
+        let (i, pattern) = match split {
+            Some(x) => x,
+            None => {
+                // Parse the whole argument list again to report what's wrong with it.
+                let mut p = rustc_parse::new_parser_from_tts(&sess.parse_sess, tts);
+                let err = match func(&mut p) {
+                    Ok(_) => p.expect(&TokenKind::Comma).err(),
+                    Err(db) => Some(db),
+                };
+                if let Some(mut db) = err {
+                    db.emit();
+                    FatalError.raise();
+                }
+                sess.span_fatal(p.token.span, "malformed `typed!` pattern");
+            }
+        };
+        if tts[i + 1..].is_empty() {
+            sess.span_fatal(tts[i].span(), "expected a type after `,` in `typed!`");
         }
-        if self.try_match(&ty_pattern, &ast_ty).is_err() {
-            return Err(Error::WrongType);
+        let constraint = TypeConstraint::parse_tts(sess, tts[i + 1..].to_vec());
+
+        self.check_type_constraint(&constraint, target.get_node_id())?;
+        if let Some(sym) = pattern.pattern_symbol() {
+            self.ty_constraints.insert(sym, constraint);
         }
 
         self.try_match(&pattern, target)
@@ -1,9 +1,14 @@
 //! Code for applying `TextRewrite`s to the actual source files.
 use diff;
-use std::collections::{HashMap, VecDeque};
+use std::cmp;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
 use std::io;
+use std::path::PathBuf;
+use std::slice;
 use syntax::source_map::{SourceFile, SourceMap};
-use syntax_pos::{BytePos, FileName};
+use syntax_pos::{BytePos, FileName, Span};
 
 use crate::file_io::FileIO;
 use crate::rewrite::cleanup::cleanup_rewrites;
@@ -58,6 +63,76 @@ pub fn rewrite_files_with(cm: &SourceMap, rw: &TextRewrite, io: &dyn FileIO) ->
     Ok(())
 }
 
+/// The source text that a rewrite would produce, summarized without writing any files.
+pub struct RewriteSnapshot {
+    /// Hashes of the current text and of the new text of each source file.
+    pub file_hashes: HashMap<PathBuf, (u64, u64)>,
+    /// The nodes the rewrite replaces, as the span of the old node and a hash of its new text.
+    pub changes: HashSet<(Span, u64)>,
+}
+
+fn hash_text(s: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    s.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Compute the `RewriteSnapshot` for `rw`, or for the unmodified source if `rw` is `None`.
+pub fn snapshot_rewrite(cm: &SourceMap, rw: Option<&TextRewrite>) -> RewriteSnapshot {
+    let mut snapshot = RewriteSnapshot {
+        file_hashes: HashMap::new(),
+        changes: HashSet::new(),
+    };
+
+    for sf in cm.files().iter() {
+        if let (&FileName::Real(ref path), Some(src)) = (&sf.name, sf.src.as_ref()) {
+            let hash = hash_text(src);
+            snapshot.file_hashes.insert(path.clone(), (hash, hash));
+        }
+    }
+
+    let rw = match rw {
+        Some(rw) => rw,
+        None => return snapshot,
+    };
+
+    let mut by_file = HashMap::new();
+    for rw in &rw.rewrites {
+        let sf = cm.lookup_byte_offset(rw.old_span.lo()).sf;
+        let ptr = (&sf as &SourceFile) as *const _;
+        by_file
+            .entry(ptr)
+            .or_insert_with(|| (Vec::new(), sf))
+            .0
+            .push(rw.clone());
+    }
+
+    for (_, (rewrites, sf)) in by_file {
+        let path = match sf.name {
+            FileName::Real(ref path) => path,
+            _ => continue,
+        };
+
+        let rewrites = cleanup_rewrites(cm, rewrites);
+        for rw in &rewrites {
+            let mut buf = String::new();
+            let (lo, hi) = (rw.old_span.lo(), rw.old_span.hi());
+            rewrite_range(cm, lo, hi, slice::from_ref(rw), &mut |s| buf.push_str(s));
+            snapshot.changes.insert((rw.old_span, hash_text(&buf)));
+        }
+
+        let mut buf = String::new();
+        rewrite_range(cm, sf.start_pos, sf.end_pos, &rewrites, &mut |s| {
+            buf.push_str(s)
+        });
+        if let Some(hashes) = snapshot.file_hashes.get_mut(path) {
+            hashes.1 = hash_text(&buf);
+        }
+    }
+
+    snapshot
+}
+
 #[allow(dead_code)] // Helper function for debugging
 fn print_rewrite(rw: &TextRewrite, depth: usize) {
     for _ in 0..depth {
@@ -143,169 +218,94 @@ fn emit_chunk<F: FnMut(&str)>(cm: &SourceMap, lo: BytePos, hi: BytePos, mut call
     callback(&src[lo.pos.0 as usize..hi.pos.0 as usize]);
 }
 
-/// Print a unified diff between lines of `s1` and lines of `s2`.
-pub fn print_diff(s1: &str, s2: &str) {
-    enum State {
-        /// We're not in a hunk, just keeping `buf` populated with `CONTEXT` lines of history.
-        History,
-        /// We're inside a hunk containing at least one changed line.
-        Hunk {
-            /// Number of unchanged lines we need to see to end this hunk.
-            unchanged_limit: usize,
-            l_start: usize,
-            r_start: usize,
-        },
+/// Split `s` into lines, keeping the `\n` at the end of each line.  The last line has no `\n` if
+/// `s` doesn't end with one.
+fn split_lines(s: &str) -> Vec<&str> {
+    let mut lines = Vec::new();
+    let mut start = 0;
+    for (i, b) in s.bytes().enumerate() {
+        if b == b'\n' {
+            lines.push(&s[start..i + 1]);
+            start = i + 1;
+        }
     }
+    if start < s.len() {
+        lines.push(&s[start..]);
+    }
+    lines
+}
 
-    const CONTEXT: usize = 3;
-
-    let mut buf = VecDeque::new();
-    let mut state = State::History;
-
-    let mut l_line = 1;
-    let mut r_line = 1;
-
-    for r in diff::lines(s1, s2) {
-        let changed = match r {
-            diff::Result::Both(l, r) => l != r,
-            _ => true,
-        };
-
-        // We need to update l/r_line before we move `r`, but after we move it, we may need access
-        // to the old l/r_line values to initialize state.l/r_start.
-        let (l_line_old, r_line_old) = (l_line, r_line);
-
-        match r {
-            diff::Result::Left(..) => {
-                l_line += 1;
-            }
-            diff::Result::Right(..) => {
-                r_line += 1;
-            }
-            diff::Result::Both(..) => {
-                l_line += 1;
-                r_line += 1;
-            }
+/// Render a unified diff between lines of `s1` and lines of `s2`, with `context` unchanged lines
+/// around each change.  Only the hunks are rendered; the caller is responsible for the `---` and
+/// `+++` file header.  Returns an empty string if the texts are identical.
+pub fn unified_diff(s1: &str, s2: &str, context: usize) -> String {
+    // Lines keep their `\n`, so a last line without one never compares equal to the same line
+    // with one, and a patch that only adds or removes the final newline still changes the file.
+    let (l1, l2) = (split_lines(s1), split_lines(s2));
+    let lines = diff::slice(&l1, &l2);
+
+    let in_old = |r: &diff::Result<&&str>| match r {
+        diff::Result::Right(_) => false,
+        _ => true,
+    };
+    let in_new = |r: &diff::Result<&&str>| match r {
+        diff::Result::Left(_) => false,
+        _ => true,
+    };
+
+    // Number of old and new lines before each entry of `lines`.
+    let mut before = Vec::with_capacity(lines.len() + 1);
+    let (mut old_count, mut new_count) = (0, 0);
+    for r in &lines {
+        before.push((old_count, new_count));
+        if in_old(r) {
+            old_count += 1;
         }
-
-        buf.push_back(r);
-
-        if !changed {
-            match state {
-                State::History => {
-                    while buf.len() > CONTEXT {
-                        buf.pop_front();
-                    }
-                }
-                State::Hunk {
-                    unchanged_limit,
-                    l_start,
-                    r_start,
-                } => {
-                    if unchanged_limit == 1 {
-                        // End of the hunk
-                        let end = buf.len() - CONTEXT;
-                        let suffix = buf.split_off(end);
-                        print_hunk(&buf, l_start, r_start);
-                        buf = suffix;
-                        state = State::History;
-                    } else {
-                        state = State::Hunk {
-                            unchanged_limit: unchanged_limit - 1,
-                            l_start,
-                            r_start,
-                        };
-                    }
-                }
-            }
-        } else {
-            match state {
-                State::History => {
-                    state = State::Hunk {
-                        unchanged_limit: 2 * CONTEXT,
-                        // Adjust start lines for context already stored in `buf`.
-                        l_start: l_line_old - (buf.len() - 1),
-                        r_start: r_line_old - (buf.len() - 1),
-                    };
-                }
-                State::Hunk {
-                    l_start, r_start, ..
-                } => {
-                    state = State::Hunk {
-                        unchanged_limit: 2 * CONTEXT,
-                        l_start,
-                        r_start,
-                    };
-                }
-            }
+        if in_new(r) {
+            new_count += 1;
         }
     }
-
-    match state {
-        State::Hunk {
-            unchanged_limit,
-            l_start,
-            r_start,
-        } => {
-            if unchanged_limit < CONTEXT {
-                let end = buf.len() - (CONTEXT - unchanged_limit);
-                buf.truncate(end);
-            }
-            print_hunk(&buf, l_start, r_start);
+    before.push((old_count, new_count));
+
+    // Group the changed lines into hunks.  Two changes belong to the same hunk if their context
+    // windows touch or overlap.
+    let mut hunks: Vec<(usize, usize)> = Vec::new();
+    for (i, r) in lines.iter().enumerate() {
+        if let diff::Result::Both(..) = r {
+            continue;
+        }
+        let start = i.saturating_sub(context);
+        let end = cmp::min(i + 1 + context, lines.len());
+        match hunks.last_mut() {
+            Some(hunk) if start <= hunk.1 => hunk.1 = end,
+            _ => hunks.push((start, end)),
         }
-        _ => {}
     }
-}
 
-/// Print a single diff hunk, starting at line `l_start` in the left file and `r_start` in the
-/// right file.
-fn print_hunk(buf: &VecDeque<diff::Result<&str>>, l_start: usize, r_start: usize) {
-    let l_size = buf
-        .iter()
-        .filter(|r| match r {
-            diff::Result::Right(_) => false,
-            _ => true,
-        })
-        .count();
-
-    let r_size = buf
-        .iter()
-        .filter(|r| match r {
-            diff::Result::Left(_) => false,
-            _ => true,
-        })
-        .count();
-
-    println!("@@ -{},{} +{},{} @@", l_start, l_size, r_start, r_size);
-
-    // Print all "left" lines immediately.  Keep all "right" lines and print them just before the
-    // next unchanged line.  This way we get the usual output, with separate old and new blocks:
-    //   unchanged
-    //  -old1
-    //  -old2
-    //  +new1
-    //  +new2
-    //   unchanged
-    let mut right_buf = Vec::new();
-    for r in buf {
-        match r {
-            diff::Result::Left(s) => {
-                println!("-{}", s);
-            }
-            diff::Result::Right(s) => {
-                right_buf.push(s);
-            }
-            diff::Result::Both(s1, s2) => {
-                if s1 != s2 {
-                    println!("-{}", s1);
-                    right_buf.push(s2);
-                } else {
-                    for s in right_buf.drain(..) {
-                        println!("+{}", s);
-                    }
-                    println!(" {}", s1);
-                }
+    let mut out = String::new();
+    for (start, end) in hunks {
+        let old_len = before[end].0 - before[start].0;
+        let new_len = before[end].1 - before[start].1;
+        // An empty range is written as the number of the line just before it.
+        let old_start = before[start].0 + if old_len == 0 { 0 } else { 1 };
+        let new_start = before[start].1 + if new_len == 0 { 0 } else { 1 };
+        out.push_str(&format!(
+            "@@ -{},{} +{},{} @@\n",
+            old_start, old_len, new_start, new_len
+        ));
+
+        for r in &lines[start..end] {
+            let (prefix, line) = match *r {
+                diff::Result::Left(l) => ('-', l),
+                diff::Result::Right(l) => ('+', l),
+                diff::Result::Both(l, _) => (' ', l),
+            };
+            out.push(prefix);
+            out.push_str(line);
+            if !line.ends_with('\n') {
+                out.push_str("\n\\ No newline at end of file\n");
             }
         }
     }
+    out
 }
@@ -26,7 +26,7 @@ use crate::ast_manip::MutVisit;
 use crate::ast_manip::fn_edit::{mut_visit_fns, FnLike};
 use crate::command::{self, CommandState, RefactorState};
 use crate::driver::{self, Phase};
-use crate::file_io::{OutputMode, RealFileIO};
+use crate::file_io::{DiffOptions, FileIO, OutputMode, RealFileIO};
 use crate::matcher::{self, mut_visit_match_with, replace_expr, MatchCtxt, Pattern, Subst, TryMatch};
 use crate::path_edit::fold_resolved_paths_with_id;
 use crate::reflect::reflect_tcx_ty;
@@ -61,24 +61,34 @@ pub fn validate_command(command: &Command) -> bool {
     true
 }
 
+/// Lua registry key holding whether this is a `--dry-run`, in which `run_command` prints a summary
+/// of each command's changes.
+const DRY_RUN_KEY: &str = "c2rust_refactor_dry_run";
+
 pub fn run_lua_file(
     script_path: &Path,
     config: interface::Config,
     registry: command::Registry,
     rewrite_modes: Vec<OutputMode>,
+    diff_options: DiffOptions,
+    dry_run: bool,
 ) -> io::Result<()> {
     let mut file = File::open(script_path)?;
     let mut script = vec![];
     file.read_to_end(&mut script)?;
-    let io = Arc::new(RealFileIO::new(rewrite_modes));
+    // As in `main_impl`, a dry run has no output modes, so `save_crate` writes nothing.
+    let rewrite_modes = if dry_run { vec![] } else { rewrite_modes };
+    let io = Arc::new(RealFileIO::new(rewrite_modes).with_diff_options(diff_options));
 
-    driver::run_refactoring(config, registry, io, HashSet::new(), |state| {
+    driver::run_refactoring(config, registry, io.clone(), HashSet::new(), |state| {
         // We use the unsafe _with_debug method because we want to be able to use
         // lua libraries which happen to support pretty printing. This should be fine
         // so long as we're confident they don't use riskier parts of the debug lib.
         let lua = unsafe { Lua::new_with_debug() };
 
         lua.context(|lua_ctx| {
+            lua_ctx.set_named_registry_value(DRY_RUN_KEY, dry_run)?;
+
             // Add the script's current directory to the lua path so that local
             // files can be imported
             let package: LuaTable = lua_ctx.globals().get("package")?;
@@ -124,7 +134,7 @@ pub fn run_lua_file(
     })
     .unwrap_or_else(|e| panic!("User script failed: {}", DisplayLuaError(e)));
 
-    Ok(())
+    io.finish()
 }
 
 /// Takes a set of marks and turns it into a lua table
@@ -171,8 +181,20 @@ impl UserData for RefactorState {
         // @tparam {string,...} args List of arguments for the command
         methods.add_method_mut(
             "run_command",
-            |_lua_ctx, this, (name, args): (String, Vec<String>)| {
-                this.run(&name, &args).map_err(LuaError::external)
+            |lua_ctx, this, (name, args): (String, Vec<String>)| {
+                let dry_run: bool = lua_ctx.named_registry_value(DRY_RUN_KEY)?;
+                let before = if dry_run {
+                    Some(this.snapshot_crate())
+                } else {
+                    None
+                };
+                this.run(&name, &args).map_err(LuaError::external)?;
+                if let Some(before) = before {
+                    let after = this.snapshot_crate();
+                    let cmd = Command { name, args };
+                    crate::print_dry_run_summary(&cmd, this.source_map(), &before, &after);
+                }
+                Ok(())
             },
         );
 
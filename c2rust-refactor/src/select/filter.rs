@@ -1,3 +1,4 @@
+use rustc::hir::CRATE_HIR_ID;
 use std::str::FromStr;
 use syntax::ast::*;
 use syntax::attr;
@@ -5,6 +6,7 @@ use syntax::source_map::Span;
 use syntax::symbol::Symbol;
 use syntax::visit::{self, FnKind, Visitor};
 
+use crate::ast_manip::util::source_header_path;
 use crate::ast_manip::AstEquiv;
 use crate::command::CommandState;
 use crate::matcher::MatchCtxt;
@@ -262,6 +264,27 @@ pub fn matches_filter(
         Filter::HasAttr(name) => node
             .attrs()
             .map_or(false, |attrs| attr::contains_name(attrs, name)),
+        Filter::FromHeader(ref re) => {
+            let header_matches = |attrs: &[Attribute]| {
+                source_header_path(attrs).map_or(false, |path| re.is_match(&path))
+            };
+            if node.attrs().map_or(false, |attrs| header_matches(attrs)) {
+                return true;
+            }
+
+            // The transpiler puts `header_src` on the modules it creates for each header, so
+            // also check the modules enclosing the node.
+            let hir_map = cx.hir_map();
+            let mut hir_id = match_or!([hir_map.opt_node_to_hir_id(node.id())] Some(x) => x;
+                                       return false);
+            while hir_id != CRATE_HIR_ID {
+                hir_id = hir_map.get_module_parent_node(hir_id);
+                if header_matches(hir_map.attrs(hir_id)) {
+                    return true;
+                }
+            }
+            false
+        }
         Filter::Matches(ref pat) => match (node, pat) {
             (AnyNode::Expr(target), &AnyPattern::Expr(ref pattern)) => {
                 MatchCtxt::from_match(st, cx, &**pattern, target).is_ok()
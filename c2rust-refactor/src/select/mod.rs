@@ -66,6 +66,11 @@ pub enum SelectOp {
 pub enum Filter {
     /// `kind(k)`: The node is of kind `k`.  See `pick_node::NodeKind` for a list of supported node
     /// kinds.  Shorthand: `k` is an alias for `kind(k)` if `k` is a valid node kind.
+    ///
+    /// `kind` also accepts the itemlike subkinds of `item_kind`, so `kind(static)` is the same as
+    /// `item_kind(static)`, and the refinements `foreign_fn` and `foreign_static` (aliases:
+    /// `foreignfn` and `foreignstatic`), which stand for `foreign_item && item_kind(fn)` and
+    /// `foreign_item && item_kind(static)`.  These are also accepted as shorthands.
     Kind(NodeKind),
     /// `item_kind(k)`: The node is an itemlike of subkind `k`.  See `select::filter::ItemLikeKind`
     /// for a list of supported itemlike subkinds.  Shorthand: `k` is an alias for `item_kind(k)`
@@ -76,13 +81,25 @@ pub enum Filter {
     /// `mut`: The node's mutability is set to "mutable".  This applies to statics, extern statics,
     /// and ident patterns.
     Mutable,
-    /// `name(re)`: The node's name matches regular expression `re`.
+    /// `name(re)`: The node's name matches regular expression `re`.  The regex must match the
+    /// whole name.
+    ///
+    /// `match_name(re)`: The node's name contains a match for regular expression `re`.  Use `^`
+    /// and `$` to anchor the match, as in `match_name("^png_.*_init$")`.
+    ///
+    /// Named nodes are items, trait and impl items, foreign items, fields, and params that bind a
+    /// single identifier.
     Name(Regex),
     /// `path_prefix(n, p)`: The prefix of the node's path, obtained by removing the last `n`
     /// segments, is `p`.  Shorthand: `path(p)` is an alias for `path_prefix(0, p)`.
     PathPrefix(usize, Box<Path>),
     /// `has_attr(a)`: The node has an attribute named `a`.
     HasAttr(Symbol),
+    /// `from_header(p)`: The node, or one of the modules enclosing it, has a
+    /// `#[c2rust::header_src]` attribute whose header path matches `p`.  `p` is a string that
+    /// must occur somewhere in the path, or a glob (containing `*` or `?`) that must match the
+    /// whole path.  Nodes with no such attribute on themselves or their modules never match.
+    FromHeader(Regex),
     /// `match_k(p)`: The node matches a pattern `p` of kind `k`, according to the `matcher`
     /// module.  This implies that the node kind must match the pattern kind.
     Matches(AnyPattern),
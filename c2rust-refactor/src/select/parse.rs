@@ -129,6 +129,31 @@ impl<'a> Stream<'a> {
         }
     }
 
+    /// Parse a string literal containing a regex.  The regex is compiled here, once per command,
+    /// and syntax errors are reported with the literal's byte offset in the select script.
+    fn regex(&mut self, anchored: bool) -> PResult<Regex> {
+        let tok = self.token()?;
+        let lit = match tok.kind {
+            TokenKind::Literal(lit) => lit,
+            t => fail!("expected string literal, but got {:?}", t),
+        };
+        let s = match lit.kind {
+            LitKind::Str | LitKind::StrRaw(_) => lit.symbol,
+            l => fail!("expected string literal, but got {:?}", l),
+        };
+        let pos = self.sess.source_map().lookup_byte_offset(tok.span.lo()).pos.0;
+        // First, make sure `s` parses as a regex on its own
+        let r = match Regex::new(&s.as_str()) {
+            Ok(r) => r,
+            Err(e) => fail!("invalid regex at offset {} of select script: {}", pos, e),
+        };
+        if !anchored {
+            return Ok(r);
+        }
+        // Then, add ^ ... $ so the regex has to match the entire item name
+        Ok(Regex::new(&format!("^({})$", s.as_str())).unwrap())
+    }
+
     fn path(&mut self) -> PResult<Path> {
         let ts = mem::replace(&mut self.toks, Vec::new().into_iter());
         let mut p = Parser::new(self.sess, ts.collect(), None, false, false, None);
@@ -187,11 +212,10 @@ impl<'a> Stream<'a> {
                     let kind_str = inner.name()?;
                     inner.last()?;
 
-                    let kind = match NodeKind::from_str(&kind_str.as_str()) {
-                        Ok(k) => k,
-                        Err(_) => fail!("invalid node kind `{}`", kind_str.as_str()),
-                    };
-                    Ok(Filter::Kind(kind))
+                    match kind_filter(&kind_str.as_str()) {
+                        Some(filt) => Ok(filt),
+                        None => fail!("invalid node kind `{}`", kind_str.as_str()),
+                    }
                 }
 
                 "item_kind" => {
@@ -236,6 +260,20 @@ impl<'a> Stream<'a> {
                 "mut" => Ok(Filter::Mutable),
 
                 "name" => {
+                    let mut inner = self.parens()?;
+                    let r = inner.regex(true)?;
+                    inner.last()?;
+                    Ok(Filter::Name(r))
+                }
+
+                "match_name" => {
+                    let mut inner = self.parens()?;
+                    let r = inner.regex(false)?;
+                    inner.last()?;
+                    Ok(Filter::Name(r))
+                }
+
+                "from_header" => {
                     let mut inner = self.parens()?;
                     let lit = inner.lit()?;
                     inner.last()?;
@@ -244,14 +282,7 @@ impl<'a> Stream<'a> {
                         LitKind::Str | LitKind::StrRaw(_) => lit.symbol,
                         l => fail!("expected string literal, but got {:?}", l),
                     };
-                    // First, make sure `s` parses as a regex on its own
-                    let _ = match Regex::new(&s.as_str()) {
-                        Ok(r) => r,
-                        Err(e) => fail!("invalid regex: {}", e),
-                    };
-                    // Then, add ^ ... $ so the regex has to match the entire item name
-                    let r = Regex::new(&format!("^({})$", s.as_str())).unwrap();
-                    Ok(Filter::Name(r))
+                    Ok(Filter::FromHeader(header_regex(&s.as_str())))
                 }
 
                 "has_attr" => {
@@ -359,12 +390,9 @@ impl<'a> Stream<'a> {
 
                 name => {
                     // Shorthand for `kind(x)` and `item_kind(x)`
-                    if let Ok(kind) = NodeKind::from_str(name) {
-                        Ok(Filter::Kind(kind))
-                    } else if let Ok(kind) = ItemLikeKind::from_str(name) {
-                        Ok(Filter::ItemKind(kind))
-                    } else {
-                        fail!("unknown filter op `{}`", name)
+                    match kind_filter(name) {
+                        Some(filt) => Ok(filt),
+                        None => fail!("unknown filter op `{}`", name),
                     }
                 }
             }
@@ -438,6 +466,45 @@ impl<'a> Stream<'a> {
     }
 }
 
+/// Build the regex for a `from_header` pattern.  A pattern containing `*` or `?` is a glob that
+/// must match the whole header path; any other pattern must occur somewhere in the path.
+fn header_regex(pat: &str) -> Regex {
+    if !pat.contains(|c: char| c == '*' || c == '?') {
+        return Regex::new(&regex::escape(pat)).unwrap();
+    }
+
+    let mut re = String::from("^");
+    for c in pat.chars() {
+        match c {
+            '*' => re.push_str(".*"),
+            '?' => re.push('.'),
+            c => re.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+        }
+    }
+    re.push('$');
+    Regex::new(&re).unwrap()
+}
+
+/// Build the filter for a node kind, an itemlike subkind, or one of the foreign item refinements
+/// `foreign_fn` and `foreign_static`.
+fn kind_filter(name: &str) -> Option<Filter> {
+    if let Ok(kind) = NodeKind::from_str(name) {
+        return Some(Filter::Kind(kind));
+    }
+    if let Ok(kind) = ItemLikeKind::from_str(name) {
+        return Some(Filter::ItemKind(kind));
+    }
+    let subkind = match name {
+        "foreign_fn" | "foreignfn" => ItemLikeKind::Fn,
+        "foreign_static" | "foreignstatic" => ItemLikeKind::Static,
+        _ => return None,
+    };
+    Some(Filter::And(vec![
+        Filter::Kind(NodeKind::ForeignItem),
+        Filter::ItemKind(subkind),
+    ]))
+}
+
 pub fn parse(sess: &Session, src: &str) -> Vec<SelectOp> {
     debug!("src = {:?}", src);
     let ts = rustc_parse::parse_stream_from_source_str(
@@ -0,0 +1,2052 @@
+//! Transforms that replace C heap allocation (`malloc`, `calloc`, `realloc` and `free`) with
+//! Rust-managed allocations (`Box`es, boxed slices and `Vec`s).
+
+use std::collections::{HashMap, HashSet};
+use std::mem;
+use rustc::hir::HirId;
+use rustc::hir::def::Res;
+use rustc::hir::def_id::DefId;
+use rustc::ty::{self, ParamEnv};
+use syntax::ast::*;
+use syntax::print::pprust;
+use syntax::ptr::P;
+use syntax::symbol::{sym, Symbol};
+use syntax_pos::{Span, DUMMY_SP};
+use smallvec::smallvec;
+
+use c2rust_ast_builder::{mk, IntoSymbol};
+use crate::ast_manip::{AstEquiv, FlatMapNodes, MutVisitNodes, visit_nodes};
+use crate::ast_manip::fn_edit::{mut_visit_fns, visit_fns};
+use crate::ast_manip::util::unsafe_exprs;
+use crate::command::{CommandState, Registry};
+use crate::contains_mark::contains_mark;
+use crate::driver::{Phase, parse_expr, parse_stmts};
+use crate::matcher::{Bindings, MatchCtxt, Subst};
+use crate::transform::Transform;
+use crate::transform::canonicalize_refs::strip_parens;
+use crate::transform::null_ptrs::{Nullable, is_null_ptr, null_check, nullable_key};
+use crate::transform::ownership::fold_returns;
+use crate::util::Lone;
+use crate::RefactorCtxt;
+
+
+/// Strip any casts wrapping `e`.
+fn strip_casts(e: &P<Expr>) -> &P<Expr> {
+    let mut e = e;
+    while let ExprKind::Cast(ref inner, _) = e.kind {
+        e = inner;
+    }
+    e
+}
+
+/// Collect the place expressions that allocations get stored into within `block`, either through
+/// `let p = ...;` or `p = ...;`, along with whatever `alloc_info` returns for each allocation.
+fn alloc_dests<T, F>(block: &Block, mut alloc_info: F) -> Vec<(P<Expr>, T)>
+    where F: FnMut(&P<Expr>) -> Option<T> {
+    let mut dests = Vec::new();
+    visit_nodes(block, |l: &Local| {
+        if let (PatKind::Ident(_, ident, None), Some(init)) = (&l.pat.kind, &l.init) {
+            if let Some(info) = alloc_info(init) {
+                dests.push((mk().ident_expr(*ident), info));
+            }
+        }
+    });
+    visit_nodes(block, |e: &Expr| {
+        if let ExprKind::Assign(ref lhs, ref rhs) = e.kind {
+            if let Some(info) = alloc_info(rhs) {
+                dests.push((lhs.clone(), info));
+            }
+        }
+    });
+    dests
+}
+
+/// The (cast-stripped) pointers passed to `free` within `block`.
+fn freed_ptrs(block: &Block, free_pat: &P<Expr>, mcx: &MatchCtxt) -> Vec<P<Expr>> {
+    let mut ptrs = Vec::new();
+    visit_nodes(block, |e: &Expr| {
+        if let Ok(mcx) = mcx.clone_match(&**free_pat, e) {
+            let ptr = mcx.bindings.get::<_, P<Expr>>("$ptr").unwrap();
+            ptrs.push(strip_casts(ptr).clone());
+        }
+    });
+    ptrs
+}
+
+/// Check whether `place` is assigned to anywhere in `block`.
+fn is_reassigned(block: &Block, place: &Expr) -> bool {
+    let mut found = false;
+    visit_nodes(block, |e: &Expr| {
+        match e.kind {
+            ExprKind::Assign(ref lhs, _) |
+            ExprKind::AssignOp(_, ref lhs, _) if lhs.ast_equiv(place) => found = true,
+            _ => {}
+        }
+    });
+    found
+}
+
+/// The locals in `block` that only ever hold allocations matching `is_alloc`, or null pointers,
+/// mapped to whether they may be null, and the allocations stored in those locals.  A local whose
+/// address is taken mutably may hold anything.  A local that's used other than by dereferencing
+/// it, checking it for null or passing it to `free_pat` lets its pointer escape, to be freed
+/// somewhere else, so it doesn't own its allocations either.
+fn alloc_owners<F>(cx: &RefactorCtxt, block: &Block, free_pat: &P<Expr>, mcx: &MatchCtxt,
+                   is_alloc: F) -> (HashMap<HirId, bool>, HashSet<NodeId>)
+    where F: Fn(&P<Expr>) -> bool {
+    let local_of = |e: &Expr| match cx.try_resolve_expr_hir(e) {
+        Some(Res::Local(hir_id)) => Some(hir_id),
+        _ => None,
+    };
+    // For each written local, whether it may be null, or `None` if it may hold any pointer.
+    let mut nullable: HashMap<HirId, Option<bool>> = HashMap::new();
+    let mut stored: HashMap<HirId, Vec<NodeId>> = HashMap::new();
+    let mut record = |hir_id: HirId, value: Option<&P<Expr>>| {
+        let state = nullable.entry(hir_id).or_insert(Some(false));
+        match value {
+            Some(v) if is_alloc(v) => stored.entry(hir_id).or_insert_with(Vec::new).push(v.id),
+            Some(v) if is_null_ptr(v) => *state = state.map(|_| true),
+            _ => *state = None,
+        }
+    };
+    visit_nodes(block, |l: &Local| {
+        if let (PatKind::Ident(_, _, None), Some(init)) = (&l.pat.kind, &l.init) {
+            record(cx.hir_map().node_to_hir_id(l.pat.id), Some(init));
+        }
+    });
+    visit_nodes(block, |e: &Expr| {
+        let (lhs, rhs) = match e.kind {
+            ExprKind::Assign(ref lhs, ref rhs) => (lhs, Some(rhs)),
+            ExprKind::AssignOp(_, ref lhs, _) |
+            ExprKind::AddrOf(_, Mutability::Mutable, ref lhs) => (lhs, None),
+            _ => return,
+        };
+        if let Some(hir_id) = local_of(lhs) {
+            record(hir_id, rhs);
+        }
+    });
+
+    // Count the uses of each local, and those of them that keep its pointer within the function.
+    let mut uses = HashMap::new();
+    let mut handled = HashMap::new();
+    visit_nodes(block, |e: &Expr| {
+        let mut handle = |p: &Expr| if let Some(hir_id) = local_of(strip_parens(p)) {
+            *handled.entry(hir_id).or_insert(0) += 1;
+        };
+        match e.kind {
+            ExprKind::Path(None, _) => if let Some(hir_id) = local_of(e) {
+                *uses.entry(hir_id).or_insert(0) += 1;
+            },
+            ExprKind::Unary(UnOp::Deref, ref p) => handle(p),
+            ExprKind::Assign(ref lhs, _) => handle(lhs),
+            _ => {}
+        }
+        // `!p.is_null()` is visited again as `p.is_null()`.
+        if let Some((p, false)) = null_check(e) {
+            handle(p);
+        }
+        if let Ok(mcx) = mcx.clone_match(&**free_pat, e) {
+            handle(strip_casts(mcx.bindings.get::<_, P<Expr>>("$ptr").unwrap()));
+        }
+    });
+
+    let mut owned = HashMap::new();
+    let mut allocs = HashSet::new();
+    for (hir_id, ids) in stored {
+        if uses.get(&hir_id) != handled.get(&hir_id) {
+            continue;
+        }
+        if let Some(may_be_null) = nullable[&hir_id] {
+            owned.insert(hir_id, may_be_null);
+            allocs.extend(ids);
+        }
+    }
+    (owned, allocs)
+}
+
+/// How to build the all-zero value that `calloc` fills an allocation with.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum ZeroValue {
+    /// `<T as Default>::default()` is zero, as for numbers and `bool`s.
+    Default,
+    /// `T` is plain data that is valid when zeroed, like raw pointers and structs or arrays of
+    /// them, so `mem::zeroed::<T>()` builds it.
+    Zeroed,
+}
+
+/// How to build a zeroed `ty`, or `None` if zeroes aren't a valid `ty`, as for references, function
+/// pointers and enums.
+fn zero_value<'tcx>(cx: &RefactorCtxt<'_, 'tcx>, ty: ty::Ty<'tcx>) -> Option<ZeroValue> {
+    match ty.kind {
+        ty::TyKind::Bool | ty::TyKind::Char | ty::TyKind::Int(_) | ty::TyKind::Uint(_) |
+        ty::TyKind::Float(_) => Some(ZeroValue::Default),
+        ty::TyKind::RawPtr(_) => Some(ZeroValue::Zeroed),
+        ty::TyKind::Array(elem, _) => zero_value(cx, elem).map(|_| ZeroValue::Zeroed),
+        ty::TyKind::Adt(def, substs) if def.is_struct() || def.is_union() => {
+            let tcx = cx.ty_ctxt();
+            if def.all_fields().all(|f| zero_value(cx, f.ty(tcx, substs)).is_some()) {
+                Some(ZeroValue::Zeroed)
+            } else {
+                None
+            }
+        }
+        _ => None,
+    }
+}
+
+/// How to build the elements of the `calloc` allocation `e`, a `calloc(...) as *mut T`.
+fn calloc_zero_value(cx: &RefactorCtxt, e: &Expr) -> Option<ZeroValue> {
+    match cx.opt_node_type(e.id)?.kind {
+        ty::TyKind::RawPtr(mt) => zero_value(cx, mt.ty),
+        _ => None,
+    }
+}
+
+
+/// # `malloc_to_box` Command
+///
+/// Usage: `malloc_to_box [uninit]`
+///
+/// Marks: `target`
+///
+/// Replace single-object heap allocations of the form
+/// `malloc(::std::mem::size_of::<T>() as libc::c_ulong) as *mut T`, and
+/// `calloc(1, ::std::mem::size_of::<T>() as libc::c_ulong) as *mut T`, with
+/// `Box::into_raw(Box::new(<T as Default>::default()))`.  Since `calloc` zeroes
+/// the memory, it only becomes that when the default of `T` is zero, as for
+/// numbers and `bool`s.  Other plain data, such as raw pointers and structs and
+/// arrays of them, uses `Box::into_raw(Box::new(::std::mem::zeroed::<T>()))`,
+/// with the `zeroed` call wrapped in an `unsafe` block outside unsafe code, and
+/// `calloc`s of any other type are skipped with a warning.
+///
+/// An allocation is only converted when it's stored into a local `p` that holds
+/// nothing but such allocations and null pointers, and that is only
+/// dereferenced, checked for null and freed, so that each
+/// `free(p as *mut libc::c_void)` in the same function can become
+/// `drop(Box::from_raw(p))`, or `if !p.is_null() { drop(Box::from_raw(p)); }`
+/// when `p` may be null.  Any other use of `p`, such as returning it, passing it
+/// to another function or storing it into a field, lets the pointer escape to a
+/// `free` that isn't converted.  Other allocations are skipped with a warning,
+/// and other `free`s are left unchanged, since their pointers may still come
+/// from `malloc`.
+///
+/// If any node is marked `target`, only allocations containing a marked node are
+/// converted.  Otherwise every allocation with a `size_of`-based size is.
+///
+/// With `uninit`, `T` doesn't need to implement `Default`: `malloc` becomes
+/// `Box::into_raw(Box::<T>::new_uninit()) as *mut T` and `calloc` becomes
+/// `Box::into_raw(Box::<T>::new_zeroed()) as *mut T`.
+///
+/// `malloc` calls whose size isn't `size_of::<T>()` are skipped with a warning.
+pub struct MallocToBox {
+    pub uninit: bool,
+}
+
+impl Transform for MallocToBox {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        let target = "target".into_symbol();
+        let marked_only = st.marks().iter().any(|&(_, label)| label == target);
+
+        let mut mcx = MatchCtxt::new(st, cx);
+        let malloc_pat = mcx.parse_expr(
+            "malloc(cast!(::std::mem::size_of::<$t:Ty>())) as *mut $t");
+        let calloc_pat = mcx.parse_expr(
+            "calloc(cast!(1), cast!(::std::mem::size_of::<$t:Ty>())) as *mut $t");
+        let sizeof_pat = mcx.parse_expr("cast!(::std::mem::size_of::<$t:Ty>())");
+        let any_malloc_pat = mcx.parse_expr("malloc($size:Expr)");
+        let free_pat = mcx.parse_expr("free($ptr:Expr)");
+
+        let (malloc_repl, calloc_repl) = if self.uninit {
+            (mcx.parse_expr("Box::into_raw(Box::<$t>::new_uninit()) as *mut $t"),
+             mcx.parse_expr("Box::into_raw(Box::<$t>::new_zeroed()) as *mut $t"))
+        } else {
+            let repl = mcx.parse_expr("Box::into_raw(Box::new(<$t as Default>::default()))");
+            (repl.clone(), repl)
+        };
+        let zeroed_repl = mcx.parse_expr("Box::into_raw(Box::new(::std::mem::zeroed::<$t>()))");
+        let unsafe_zeroed_repl = mcx.parse_expr(
+            "Box::into_raw(Box::new(unsafe { ::std::mem::zeroed::<$t>() }))");
+        let free_repl = mcx.parse_expr("drop(Box::from_raw($ptr))");
+        let checked_free_repl = mcx.parse_expr(
+            "if !$ptr.is_null() { drop(Box::from_raw($ptr)); }");
+
+        let in_unsafe = unsafe_exprs(krate);
+        let is_cand = |e: &P<Expr>| {
+            (!marked_only || contains_mark(&**e, target, st)) &&
+                (mcx.clone_match(&*malloc_pat, &*e).is_ok() ||
+                 mcx.clone_match(&*calloc_pat, &*e).is_ok())
+        };
+        // Without `uninit`, the `calloc` of a type that zeroes aren't a valid value of can't be
+        // converted.
+        let is_bad_calloc = |e: &P<Expr>| {
+            !self.uninit && mcx.clone_match(&*calloc_pat, &*e).is_ok() &&
+                calloc_zero_value(cx, e).is_none()
+        };
+        let is_alloc = |e: &P<Expr>| is_cand(e) && !is_bad_calloc(e);
+
+        mut_visit_fns(krate, |fl| {
+            let (owned, allocs) = match fl.block {
+                Some(ref block) => alloc_owners(cx, block, &free_pat, &mcx, |e| is_alloc(e)),
+                None => return,
+            };
+
+            MutVisitNodes::visit(&mut fl.block, |e: &mut P<Expr>| {
+                if let Ok(mcx) = mcx.clone_match(&*any_malloc_pat, &*e) {
+                    let size = mcx.bindings.get::<_, P<Expr>>("$size").unwrap();
+                    if mcx.clone_match(&*sizeof_pat, &*size).is_err() {
+                        warn!("malloc_to_box: skipping allocation of non-`size_of` size at {}",
+                              cx.session().source_map().span_to_string(e.span));
+                    }
+                    return;
+                }
+
+                if is_cand(e) && is_bad_calloc(e) {
+                    warn!("malloc_to_box: skipping allocation of a type that isn't valid when \
+                           zeroed at {}", cx.session().source_map().span_to_string(e.span));
+                    return;
+                }
+
+                if !is_alloc(e) {
+                    if let Ok(mcx) = mcx.clone_match(&*free_pat, &*e) {
+                        let ptr = mcx.bindings.get::<_, P<Expr>>("$ptr").unwrap();
+                        let ptr = strip_casts(ptr);
+                        let may_be_null = match cx.try_resolve_expr_hir(ptr) {
+                            Some(Res::Local(hir_id)) => owned.get(&hir_id).cloned(),
+                            _ => None,
+                        };
+                        if let Some(may_be_null) = may_be_null {
+                            let repl = if may_be_null { &checked_free_repl } else { &free_repl };
+                            let mut bnd = Bindings::new();
+                            bnd.add("$ptr", ptr.clone());
+                            *e = repl.clone().subst(st, cx, &bnd);
+                        }
+                    }
+                    return;
+                }
+
+                if !allocs.contains(&e.id) {
+                    warn!("malloc_to_box: skipping allocation that isn't stored in a local only \
+                           holding such allocations, or whose pointer escapes, at {}",
+                          cx.session().source_map().span_to_string(e.span));
+                    return;
+                }
+
+                if let Ok(mcx) = mcx.clone_match(&*malloc_pat, &*e) {
+                    *e = malloc_repl.clone().subst(st, cx, &mcx.bindings);
+                } else if let Ok(mcx) = mcx.clone_match(&*calloc_pat, &*e) {
+                    let repl = match (self.uninit, calloc_zero_value(cx, e)) {
+                        (false, Some(ZeroValue::Zeroed)) if in_unsafe.contains(&e.id) =>
+                            &zeroed_repl,
+                        (false, Some(ZeroValue::Zeroed)) => &unsafe_zeroed_repl,
+                        _ => &calloc_repl,
+                    };
+                    *e = repl.clone().subst(st, cx, &mcx.bindings);
+                }
+            });
+        });
+    }
+}
+
+
+/// # `calloc_to_vec` Command
+///
+/// Usage: `calloc_to_vec`
+///
+/// Marks: `target`
+///
+/// Replace array heap allocations of the form
+/// `calloc(n, ::std::mem::size_of::<T>() as libc::c_ulong) as *mut T` or
+/// `malloc(n.wrapping_mul(::std::mem::size_of::<T>() as libc::c_ulong)) as *mut T`,
+/// with the operands in either order, with a boxed slice of `n` default values
+/// that is kept as a raw pointer:
+/// `Box::into_raw((0..n as usize).map(|_| <T as Default>::default()).collect::<Box<[T]>>())
+/// as *mut T`.  Since `calloc` zeroes the memory, its elements are only
+/// `<T as Default>::default()` when that's zero, as for numbers and `bool`s.
+/// Other plain data, such as raw pointers and structs and arrays of them, uses
+/// `::std::mem::zeroed::<T>()`, wrapped in an `unsafe` block outside unsafe
+/// code, and `calloc`s of any other type are skipped with a warning.
+/// The paired `free(p as *mut libc::c_void)` becomes
+/// `drop(Box::from_raw(::std::ptr::slice_from_raw_parts_mut(p, n as usize)))`.
+///
+/// The length is needed again when the slice is freed, so an allocation is only
+/// converted when it's stored into a local or field `p` that is freed in the same
+/// function, and `n` is a local or field that isn't reassigned in that function.
+/// Other array allocations are skipped with a warning.
+///
+/// As with `malloc_to_box`, if any node is marked `target`, only allocations
+/// containing a marked node are converted.
+pub struct CallocToVec;
+
+impl Transform for CallocToVec {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        let target = "target".into_symbol();
+        let marked_only = st.marks().iter().any(|&(_, label)| label == target);
+
+        let mut mcx = MatchCtxt::new(st, cx);
+        let alloc_pats = [
+            "calloc(cast!($n:Expr), cast!(::std::mem::size_of::<$t:Ty>())) as *mut $t",
+            "calloc(cast!(::std::mem::size_of::<$t:Ty>()), cast!($n:Expr)) as *mut $t",
+            "malloc($n:Expr.wrapping_mul(cast!(::std::mem::size_of::<$t:Ty>()))) as *mut $t",
+            "malloc(cast!(::std::mem::size_of::<$t:Ty>()).wrapping_mul($n:Expr)) as *mut $t",
+        ].iter().map(|pat| mcx.parse_expr(pat)).collect::<Vec<_>>();
+        let free_pat = mcx.parse_expr("free($ptr:Expr)");
+        let alloc_repl = mcx.parse_expr(
+            "Box::into_raw((0..$n as usize).map(|_| $elem).collect::<Box<[$t]>>()) as *mut $t");
+        let default_repl = mcx.parse_expr("<$t as Default>::default()");
+        let zeroed_repl = mcx.parse_expr("::std::mem::zeroed::<$t>()");
+        let unsafe_zeroed_repl = mcx.parse_expr("unsafe { ::std::mem::zeroed::<$t>() }");
+        let free_repl = mcx.parse_expr(
+            "drop(Box::from_raw(::std::ptr::slice_from_raw_parts_mut($ptr, $n as usize)))");
+
+        let in_unsafe = unsafe_exprs(krate);
+
+        // Match `e` against the array allocation patterns, binding `$n` to the cast-stripped
+        // element count, and `$elem` to the value of each element, unless it's a `calloc` of a
+        // type that can't be zeroed.
+        let match_alloc = |e: &P<Expr>| -> Option<Bindings> {
+            if marked_only && !contains_mark(&**e, target, st) {
+                return None;
+            }
+            let (i, mcx) = alloc_pats.iter().enumerate()
+                .filter_map(|(i, pat)| mcx.clone_match(&**pat, &**e).ok().map(|m| (i, m)))
+                .next()?;
+            let mut bnd = Bindings::new();
+            bnd.add("$t", mcx.bindings.get::<_, P<Ty>>("$t").unwrap().clone());
+            let n = mcx.bindings.get::<_, P<Expr>>("$n").unwrap();
+            bnd.add("$n", strip_casts(n).clone());
+            // The first two patterns are `calloc`s.
+            let elem_repl = if i >= 2 {
+                Some(&default_repl)
+            } else {
+                match calloc_zero_value(cx, e) {
+                    Some(ZeroValue::Default) => Some(&default_repl),
+                    Some(ZeroValue::Zeroed) if in_unsafe.contains(&e.id) => Some(&zeroed_repl),
+                    Some(ZeroValue::Zeroed) => Some(&unsafe_zeroed_repl),
+                    None => None,
+                }
+            };
+            if let Some(elem_repl) = elem_repl {
+                let elem = elem_repl.clone().subst(st, cx, &bnd);
+                bnd.add("$elem", elem);
+            }
+            Some(bnd)
+        };
+        let has_elem = |bnd: &Bindings| bnd.get::<_, P<Expr>>("$elem").is_some();
+        let is_place = |e: &Expr| match e.kind {
+            ExprKind::Path(..) | ExprKind::Field(..) => true,
+            _ => false,
+        };
+
+        mut_visit_fns(krate, |fl| {
+            let block = match fl.block {
+                Some(ref mut block) => block,
+                None => return,
+            };
+            let freed = freed_ptrs(block, &free_pat, &mcx);
+
+            // Convert each allocation whose destination and length can be tracked, and remember
+            // the length to use when freeing that destination.
+            let mut lens = Vec::new();
+            for (dest, bnd) in alloc_dests(block, |e| match_alloc(e)) {
+                let n = bnd.get::<_, P<Expr>>("$n").unwrap().clone();
+                if has_elem(&bnd) && is_place(&dest) && is_place(&n) && !is_reassigned(block, &n) &&
+                   freed.iter().any(|p| p.ast_equiv(&dest)) {
+                    lens.push((dest, n));
+                }
+            }
+
+            MutVisitNodes::visit(block, |l: &mut P<Local>| {
+                let l = &mut **l;
+                if let (PatKind::Ident(_, ident, None), Some(init)) = (&l.pat.kind, &mut l.init) {
+                    let dest = mk().ident_expr(*ident);
+                    if lens.iter().any(|(d, _)| d.ast_equiv(&dest)) {
+                        if let Some(bnd) = match_alloc(init) {
+                            *init = alloc_repl.clone().subst(st, cx, &bnd);
+                        }
+                    }
+                }
+            });
+            MutVisitNodes::visit(block, |e: &mut P<Expr>| {
+                if let ExprKind::Assign(ref lhs, ref mut rhs) = e.kind {
+                    if lens.iter().any(|(d, _)| d.ast_equiv(lhs)) {
+                        if let Some(bnd) = match_alloc(rhs) {
+                            *rhs = alloc_repl.clone().subst(st, cx, &bnd);
+                        }
+                    }
+                }
+            });
+
+            MutVisitNodes::visit(block, |e: &mut P<Expr>| {
+                if let Some(bnd) = match_alloc(e) {
+                    let why = if has_elem(&bnd) {
+                        "with an untracked length"
+                    } else {
+                        "of a type that isn't valid when zeroed"
+                    };
+                    warn!("calloc_to_vec: skipping allocation {} at {}", why,
+                          cx.session().source_map().span_to_string(e.span));
+                    return;
+                }
+                let (ptr, n) = match mcx.clone_match(&*free_pat, &*e) {
+                    Ok(mcx) => {
+                        let ptr = strip_casts(mcx.bindings.get::<_, P<Expr>>("$ptr").unwrap());
+                        match lens.iter().find(|(d, _)| d.ast_equiv(ptr)) {
+                            Some((_, n)) => (ptr.clone(), n.clone()),
+                            None => return,
+                        }
+                    }
+                    Err(_) => return,
+                };
+                let mut bnd = Bindings::new();
+                bnd.add("$ptr", ptr);
+                bnd.add("$n", n);
+                *e = free_repl.clone().subst(st, cx, &bnd);
+            });
+        });
+    }
+}
+
+
+/// # `realloc_to_vec` Command
+///
+/// Usage: `realloc_to_vec`
+///
+/// Marks: `target`
+///
+/// Convert each pointer local whose pattern is marked `target` and that is grown
+/// with `realloc` into a `Vec`.  The local must be declared as
+/// `let mut p: *mut T = 0 as *mut T;` (or `::std::ptr::null_mut()`), and every use
+/// of `p` in its function must be one of:
+///
+///  * `p = realloc(p as *mut libc::c_void, n.wrapping_mul(::std::mem::size_of::<T>() as
+///    libc::c_ulong)) as *mut T`, with the product in either order, which becomes
+///    `p.resize(n as usize, Default::default())`;
+///  * `*p.offset(i as isize)`, which becomes `p[i as usize]`;
+///  * `free(p as *mut libc::c_void)`, which becomes `drop(p)`.
+///
+/// The declaration becomes `let mut p: Vec<T> = Vec::new();`.  Locals with any
+/// other use, such as being passed to another function, returned, or stored into a
+/// struct field, escape the function and are skipped with a warning.
+pub struct ReallocToVec;
+
+impl Transform for ReallocToVec {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        let mut mcx = MatchCtxt::new(st, cx);
+        let null_pats = [
+            mcx.parse_expr("0 as *mut $t:Ty"),
+            mcx.parse_expr("::std::ptr::null_mut()"),
+        ];
+        let realloc_pats = [
+            mcx.parse_expr("$p:Expr = realloc(cast!($p), $n:Expr.wrapping_mul(\
+                            cast!(::std::mem::size_of::<$t:Ty>()))) as *mut $t"),
+            mcx.parse_expr("$p:Expr = realloc(cast!($p), cast!(::std::mem::size_of::<$t:Ty>())\
+                            .wrapping_mul($n:Expr)) as *mut $t"),
+        ];
+        let index_pat = mcx.parse_expr("*$p:Expr.offset($i:Expr)");
+        let free_pat = mcx.parse_expr("free($ptr:Expr)");
+        let resize_repl = mcx.parse_expr("$p.resize($n as usize, Default::default())");
+        let index_repl = mcx.parse_expr("$p[$i as usize]");
+        let free_repl = mcx.parse_expr("drop($ptr)");
+        let vec_ty = mcx.parse_ty("Vec<$t>");
+        let vec_init = mcx.parse_expr("Vec::new()");
+
+        // Match `e` against one of the uses we know how to convert, returning the bindings for
+        // its replacement, with casts stripped from any captured pointer, count and index; the
+        // replacement itself; and the number of times the pointer appears in `e`.
+        let match_use = |e: &Expr| -> Option<(Bindings, P<Expr>, usize)> {
+            let strip = |bnd: &Bindings, names: &[&str]| {
+                let mut new_bnd = Bindings::new();
+                for &name in names {
+                    let x = bnd.get::<_, P<Expr>>(name).unwrap();
+                    new_bnd.add(name, strip_casts(x).clone());
+                }
+                new_bnd
+            };
+            for pat in &realloc_pats {
+                if let Ok(mcx) = mcx.clone_match(&**pat, e) {
+                    return Some((strip(&mcx.bindings, &["$p", "$n"]), resize_repl.clone(), 2));
+                }
+            }
+            if let Ok(mcx) = mcx.clone_match(&*index_pat, e) {
+                return Some((strip(&mcx.bindings, &["$p", "$i"]), index_repl.clone(), 1));
+            }
+            if let Ok(mcx) = mcx.clone_match(&*free_pat, e) {
+                return Some((strip(&mcx.bindings, &["$ptr"]), free_repl.clone(), 1));
+            }
+            None
+        };
+        let use_target = |bnd: &Bindings| {
+            let p = bnd.get::<_, P<Expr>>("$p")
+                .or_else(|| bnd.get::<_, P<Expr>>("$ptr"))
+                .unwrap();
+            cx.try_resolve_expr_to_hid(p)
+        };
+
+        mut_visit_fns(krate, |fl| {
+            let block = match fl.block {
+                Some(ref mut block) => block,
+                None => return,
+            };
+
+            // Find the marked, null-initialized `*mut T` locals.
+            let mut candidates: Vec<(HirId, Ident, P<Ty>)> = Vec::new();
+            visit_nodes(&**block, |l: &Local| {
+                if !st.marked(l.pat.id, "target") {
+                    return;
+                }
+                let ident = match l.pat.kind {
+                    PatKind::Ident(BindingMode::ByValue(Mutability::Mutable), ident, None) => {
+                        ident
+                    }
+                    _ => return,
+                };
+                let elem_ty = match l.ty.as_ref().map(|ty| &ty.kind) {
+                    Some(TyKind::Ptr(MutTy { ty, mutbl: Mutability::Mutable })) => ty.clone(),
+                    _ => return,
+                };
+                let null_init = l.init.as_ref().map_or(false, |init| {
+                    null_pats.iter().any(|pat| mcx.clone_match(&**pat, &**init).is_ok())
+                });
+                if null_init {
+                    candidates.push((cx.hir_map().node_to_hir_id(l.pat.id), ident, elem_ty));
+                } else {
+                    warn!("realloc_to_vec: `{}` is not initialized to null; skipping", ident);
+                }
+            });
+
+            // Keep only the candidates whose every use is one we can convert.
+            let mut converted = Vec::new();
+            for (hid, ident, elem_ty) in candidates {
+                let mut uses = 0;
+                let mut handled = 0;
+                visit_nodes(&**block, |e: &Expr| {
+                    if let ExprKind::Path(None, _) = e.kind {
+                        if cx.try_resolve_expr_to_hid(e) == Some(hid) {
+                            uses += 1;
+                        }
+                    }
+                    if let Some((bnd, _, count)) = match_use(e) {
+                        if use_target(&bnd) == Some(hid) {
+                            handled += count;
+                        }
+                    }
+                });
+                if uses == handled {
+                    converted.push((hid, elem_ty));
+                } else {
+                    warn!("realloc_to_vec: `{}` escapes function `{}`; skipping", ident, fl.ident);
+                }
+            }
+            if converted.is_empty() {
+                return;
+            }
+
+            MutVisitNodes::visit(block, |e: &mut P<Expr>| {
+                if let Some((bnd, repl, _)) = match_use(e) {
+                    let target = use_target(&bnd);
+                    if converted.iter().any(|&(hid, _)| target == Some(hid)) {
+                        *e = repl.subst(st, cx, &bnd);
+                    }
+                }
+            });
+            MutVisitNodes::visit(block, |l: &mut P<Local>| {
+                let hid = cx.hir_map().node_to_hir_id(l.pat.id);
+                if let Some((_, elem_ty)) = converted.iter().find(|&&(h, _)| h == hid) {
+                    let mut bnd = Bindings::new();
+                    bnd.add("$t", elem_ty.clone());
+                    l.ty = Some(vec_ty.clone().subst(st, cx, &bnd));
+                    l.init = Some(vec_init.clone());
+                }
+            });
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
+/// # `ret_ptr_to_box` Command
+///
+/// Usage: `ret_ptr_to_box`
+///
+/// Marks: `target`
+///
+/// Change each function marked `target` that returns a freshly allocated
+/// `*mut T` to return a `Box<T>`.  Every value the function returns must be one
+/// of:
+///
+///  * an allocation that `malloc_to_box` would convert, which becomes
+///    `Box::new(<T as Default>::default())`.  As with `calloc_to_vec`, a
+///    `calloc` becomes `Box::new(::std::mem::zeroed::<T>())` instead when the
+///    default of `T` isn't known to be zero, with the `zeroed` call wrapped in
+///    an `unsafe` block outside unsafe code, and a function that `calloc`s a
+///    type that isn't valid when zeroed is skipped with a warning;
+///  * `Box::into_raw(b)`, which becomes `b`;
+///  * a call of another function converted by this command;
+///  * a null pointer;
+///  * a local of the function that only ever holds one of these, which becomes
+///    `Box::from_raw(p)`.  The local may be dereferenced, checked for null and
+///    freed, but any other use of it, such as passing it to another function or
+///    storing it into a field, lets the pointer escape, and the function is
+///    skipped with a warning.
+///
+/// A function that may return null returns `Option<Box<T>>` instead, with
+/// `None` for the null pointer, and `Some` for the others.
+///
+/// Calls of a converted function get the raw pointer back with
+/// `Box::into_raw(f())`, or `f().map_or(::std::ptr::null_mut(), Box::into_raw)`,
+/// unless they're returned directly by another converted function.  A
+/// `free(p as *mut libc::c_void)` of a local or field that such a call was
+/// stored into becomes `drop(Box::from_raw(p))`, guarded by `!p.is_null()` when
+/// the function may return `None`.
+///
+/// Functions that are exported, or used other than by calling them, are skipped
+/// with a warning.
+pub struct RetPtrToBox;
+
+/// Where a pointer returned by a `ret_ptr_to_box` candidate comes from.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum RetSource {
+    Alloc,
+    Null,
+    Call(DefId),
+    Local(HirId),
+}
+
+impl RetSource {
+    fn may_be_null(self, nullable: &HashSet<DefId>) -> bool {
+        match self {
+            RetSource::Null => true,
+            RetSource::Call(def_id) => nullable.contains(&def_id),
+            RetSource::Alloc | RetSource::Local(_) => false,
+        }
+    }
+}
+
+/// The sources of the pointers returned by a function.
+struct RetSources {
+    /// The returned values, by the IDs of their cast-stripped expressions.
+    returns: Vec<(NodeId, RetSource)>,
+    /// The returned locals, with the sources of every value stored into them.
+    locals: HashMap<HirId, (Ident, Vec<RetSource>)>,
+    /// The allocations among those sources.
+    allocs: HashSet<NodeId>,
+}
+
+impl RetSources {
+    fn local_may_be_null(&self, hir_id: HirId, nullable: &HashSet<DefId>) -> bool {
+        self.locals[&hir_id].1.iter().any(|src| src.may_be_null(nullable))
+    }
+
+    fn may_return_null(&self, nullable: &HashSet<DefId>) -> bool {
+        self.returns.iter().any(|&(_, src)| match src {
+            RetSource::Local(hir_id) => self.local_may_be_null(hir_id, nullable),
+            _ => src.may_be_null(nullable),
+        })
+    }
+}
+
+impl Transform for RetPtrToBox {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        let mut mcx = MatchCtxt::new(st, cx);
+        let alloc_pats = [
+            mcx.parse_expr("malloc(cast!(::std::mem::size_of::<$t:Ty>())) as *mut $t"),
+            mcx.parse_expr("calloc(cast!(1), cast!(::std::mem::size_of::<$t:Ty>())) as *mut $t"),
+        ];
+        let into_raw_pat = mcx.parse_expr("Box::into_raw($b:Expr)");
+        let free_pat = mcx.parse_expr("free($ptr:Expr)");
+        let box_ty = mcx.parse_ty("Box<$t>");
+        let option_ty = mcx.parse_ty("Option<Box<$t>>");
+        let alloc_repl = mcx.parse_expr("Box::into_raw(Box::new($elem))");
+        let box_repl = mcx.parse_expr("Box::new($elem)");
+        let default_repl = mcx.parse_expr("<$t as Default>::default()");
+        let zeroed_repl = mcx.parse_expr("::std::mem::zeroed::<$t>()");
+        let unsafe_zeroed_repl = mcx.parse_expr("unsafe { ::std::mem::zeroed::<$t>() }");
+        let from_raw_repl = mcx.parse_expr("Box::from_raw($e)");
+        let checked_repl = mcx.parse_expr(
+            "if $e.is_null() { None } else { Some(Box::from_raw($e)) }");
+        let some_repl = mcx.parse_expr("Some($e)");
+        let none_repl = mcx.parse_expr("None");
+        let into_raw_repl = mcx.parse_expr("Box::into_raw($e)");
+        let option_into_raw_repl = mcx.parse_expr(
+            "$e.map_or(::std::ptr::null_mut(), Box::into_raw)");
+        let free_repl = mcx.parse_expr("drop(Box::from_raw($ptr))");
+        let checked_free_repl = mcx.parse_expr(
+            "if !$ptr.is_null() { drop(Box::from_raw($ptr)); }");
+
+        let span_str = |e: &Expr| cx.session().source_map().span_to_string(e.span);
+        let subst = |repl: &P<Expr>, e: P<Expr>| {
+            let mut bnd = Bindings::new();
+            bnd.add("$e", e);
+            repl.clone().subst(st, cx, &bnd)
+        };
+        // Check if `e` is a `calloc` of a type that zeroes aren't a valid value of.
+        let is_bad_calloc = |e: &Expr| {
+            mcx.clone_match(&*alloc_pats[1], e).is_ok() && calloc_zero_value(cx, e).is_none()
+        };
+        let in_unsafe = unsafe_exprs(krate);
+        // The bindings for replacing the allocation `e`, with `$elem` bound to the value to box.
+        let alloc_bindings = |e: &P<Expr>| -> Option<Bindings> {
+            let (i, m) = alloc_pats.iter().enumerate()
+                .filter_map(|(i, pat)| mcx.clone_match(&**pat, &**e).ok().map(|m| (i, m)))
+                .next()?;
+            let mut bnd = m.bindings;
+            // The second pattern is the `calloc`.
+            let elem_repl = match (i, calloc_zero_value(cx, e)) {
+                (1, Some(ZeroValue::Zeroed)) if in_unsafe.contains(&e.id) => &zeroed_repl,
+                (1, Some(ZeroValue::Zeroed)) => &unsafe_zeroed_repl,
+                _ => &default_repl,
+            };
+            let elem = elem_repl.clone().subst(st, cx, &bnd);
+            bnd.add("$elem", elem);
+            Some(bnd)
+        };
+
+        // (1) Find the marked functions returning `*mut T`.
+
+        let mut cands = HashMap::new();
+        visit_nodes(krate, |i: &Item| {
+            if !st.marked(i.id, "target") {
+                return;
+            }
+            let (sig, body) = match_or!([i.kind] ItemKind::Fn(ref sig, _, ref body) => (sig, body);
+                                        return);
+            let pointee = match sig.decl.output {
+                FunctionRetTy::Ty(ref ty) => match ty.kind {
+                    TyKind::Ptr(MutTy { ref ty, mutbl: Mutability::Mutable }) => Some(ty.clone()),
+                    _ => None,
+                },
+                FunctionRetTy::Default(_) => None,
+            };
+            let pointee = match_or!([pointee] Some(x) => x;
+                return warn!("ret_ptr_to_box: `{}` doesn't return a `*mut` pointer; skipping",
+                             i.ident));
+            let def_id = cx.node_def_id(i.id);
+            if cx.is_exported_def(def_id) {
+                return warn!("ret_ptr_to_box: `{}` is exported, so its signature can't change; \
+                              skipping", i.ident);
+            }
+            cands.insert(def_id, (i.ident, pointee, body.clone()));
+        });
+
+        let mut callees = HashSet::new();
+        visit_nodes(krate, |e: &Expr| {
+            if let ExprKind::Call(ref func, _) = e.kind {
+                callees.insert(func.id);
+            }
+        });
+        visit_nodes(krate, |e: &Expr| {
+            if let ExprKind::Path(..) = e.kind {
+                match cx.try_resolve_expr(e) {
+                    Some(def_id) if !callees.contains(&e.id) => {
+                        if let Some((name, _, _)) = cands.remove(&def_id) {
+                            warn!("ret_ptr_to_box: `{}` is used at {} other than in a call; \
+                                   skipping", name, span_str(e));
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        });
+
+        // (2) Check where the returned pointers come from, dropping the functions that return
+        // anything else, until the rest only return each other's results.
+
+        let source = |e: &P<Expr>, live: &HashSet<DefId>| -> Option<RetSource> {
+            if is_null_ptr(e) {
+                return Some(RetSource::Null);
+            }
+            if alloc_pats.iter().any(|pat| mcx.clone_match(&**pat, &**e).is_ok()) {
+                return Some(RetSource::Alloc);
+            }
+            let e = strip_casts(e);
+            if mcx.clone_match(&*into_raw_pat, &**e).is_ok() {
+                return Some(RetSource::Alloc);
+            }
+            match e.kind {
+                ExprKind::Call(..) =>
+                    cx.opt_callee(e).filter(|def_id| live.contains(def_id)).map(RetSource::Call),
+                ExprKind::Path(None, _) => cx.try_resolve_expr_to_hid(e).map(RetSource::Local),
+                _ => None,
+            }
+        };
+
+        let analyze = |body: &P<Block>, live: &HashSet<DefId>| -> Result<RetSources, String> {
+            let mut idents = HashMap::new();
+            visit_nodes(&**body, |l: &Local| {
+                if let PatKind::Ident(BindingMode::ByValue(_), ident, None) = l.pat.kind {
+                    idents.insert(cx.hir_map().node_to_hir_id(l.pat.id), ident);
+                }
+            });
+
+            let mut bad_calloc = None;
+            visit_nodes(&**body, |e: &Expr| if bad_calloc.is_none() && is_bad_calloc(e) {
+                bad_calloc = Some(span_str(e));
+            });
+            if let Some(span) = bad_calloc {
+                return Err(format!("`calloc`s a type that isn't valid when zeroed at {}", span));
+            }
+
+            let mut returns = Vec::new();
+            let mut allocs = HashSet::new();
+            let mut bad = None;
+            fold_returns(&mut body.clone(), |e| match source(e, live) {
+                Some(RetSource::Local(hir_id)) if !idents.contains_key(&hir_id) =>
+                    bad = bad.or_else(|| Some(span_str(e))),
+                Some(src) => {
+                    if src == RetSource::Alloc {
+                        allocs.insert(e.id);
+                    }
+                    returns.push((strip_casts(e).id, src));
+                }
+                None => bad = bad.or_else(|| Some(span_str(e))),
+            });
+            if let Some(span) = bad {
+                return Err(format!("returns a pointer that isn't a fresh allocation at {}", span));
+            }
+
+            let mut locals = returns.iter()
+                .filter_map(|&(_, src)| match src {
+                    RetSource::Local(hir_id) => Some((hir_id, (idents[&hir_id], Vec::new()))),
+                    _ => None,
+                })
+                .collect::<HashMap<_, _>>();
+
+            // Every value stored into a returned local must be fresh, and every use of it must
+            // be one that keeps the pointer within the function.
+            let mut stored = Vec::new();
+            visit_nodes(&**body, |l: &Local| {
+                let hir_id = cx.hir_map().node_to_hir_id(l.pat.id);
+                if let (true, Some(init)) = (locals.contains_key(&hir_id), &l.init) {
+                    stored.push((hir_id, init.clone()));
+                }
+            });
+            let mut uses = HashMap::new();
+            let mut handled = HashMap::new();
+            {
+                let resolve = |e: &Expr| {
+                    cx.try_resolve_expr_to_hid(strip_parens(e))
+                        .filter(|hir_id| locals.contains_key(hir_id))
+                };
+                visit_nodes(&**body, |e: &Expr| {
+                    let mut handle = |p: &Expr| if let Some(hir_id) = resolve(p) {
+                        *handled.entry(hir_id).or_insert(0) += 1;
+                    };
+                    match e.kind {
+                        ExprKind::Path(None, _) => if let Some(hir_id) = resolve(e) {
+                            *uses.entry(hir_id).or_insert(0) += 1;
+                        },
+                        ExprKind::Unary(UnOp::Deref, ref p) => handle(p),
+                        ExprKind::Assign(ref lhs, ref rhs) => if let Some(hir_id) = resolve(lhs) {
+                            handle(lhs);
+                            stored.push((hir_id, rhs.clone()));
+                        },
+                        _ => {}
+                    }
+                    // `!p.is_null()` is visited again as `p.is_null()`.
+                    if let Some((p, false)) = null_check(e) {
+                        handle(p);
+                    }
+                    if let Ok(mcx) = mcx.clone_match(&*free_pat, e) {
+                        handle(strip_casts(mcx.bindings.get::<_, P<Expr>>("$ptr").unwrap()));
+                    }
+                });
+            }
+            for &(_, src) in &returns {
+                if let RetSource::Local(hir_id) = src {
+                    *handled.entry(hir_id).or_insert(0) += 1;
+                }
+            }
+
+            for (hir_id, value) in stored {
+                match source(&value, live) {
+                    Some(RetSource::Local(_)) | None => {
+                        return Err(format!("stores a pointer that isn't a fresh allocation into \
+                                            `{}` at {}", locals[&hir_id].0, span_str(&value)));
+                    }
+                    Some(src) => {
+                        if src == RetSource::Alloc {
+                            allocs.insert(value.id);
+                        }
+                        locals.get_mut(&hir_id).unwrap().1.push(src);
+                    }
+                }
+            }
+            for (hir_id, &(ident, _)) in &locals {
+                if uses.get(hir_id) != handled.get(hir_id) {
+                    return Err(format!("lets the pointer in `{}` escape", ident));
+                }
+            }
+
+            Ok(RetSources { returns, locals, allocs })
+        };
+
+        let mut live = cands.keys().cloned().collect::<HashSet<_>>();
+        let mut fns = HashMap::new();
+        loop {
+            fns.clear();
+            let mut rejected = Vec::new();
+            for &def_id in &live {
+                let (name, _, ref body) = cands[&def_id];
+                match analyze(body, &live) {
+                    Ok(srcs) => {
+                        fns.insert(def_id, srcs);
+                    }
+                    Err(msg) => {
+                        warn!("ret_ptr_to_box: `{}` {}; skipping", name, msg);
+                        rejected.push(def_id);
+                    }
+                }
+            }
+            if rejected.is_empty() {
+                break;
+            }
+            for def_id in rejected {
+                live.remove(&def_id);
+            }
+        }
+
+        let mut nullable = HashSet::new();
+        loop {
+            let newly_nullable = fns.iter()
+                .filter(|&(def_id, srcs)| {
+                    !nullable.contains(def_id) && srcs.may_return_null(&nullable)
+                })
+                .map(|(&def_id, _)| def_id)
+                .collect::<Vec<_>>();
+            if newly_nullable.is_empty() {
+                break;
+            }
+            nullable.extend(newly_nullable);
+        }
+
+        let direct_calls = fns.values()
+            .flat_map(|srcs| srcs.returns.iter())
+            .filter_map(|&(id, src)| match src {
+                RetSource::Call(_) => Some(id),
+                _ => None,
+            })
+            .collect::<HashSet<_>>();
+        let converted_call = |e: &Expr| match e.kind {
+            ExprKind::Call(..) => cx.opt_callee(e).filter(|def_id| fns.contains_key(def_id)),
+            _ => None,
+        };
+
+        // (3) Rewrite the calls, and the signatures and returns of the converted functions.
+
+        mut_visit_fns(krate, |fl| {
+            let def_id = cx.node_def_id(fl.id);
+            let block = match fl.block {
+                Some(ref mut block) => block,
+                None => return,
+            };
+
+            // The places holding converted pointers, and whether each may be null.
+            let mut freed = alloc_dests(block, |e| {
+                converted_call(strip_casts(e)).map(|def_id| nullable.contains(&def_id))
+            });
+            if let Some(srcs) = fns.get(&def_id) {
+                for (&hir_id, &(ident, _)) in &srcs.locals {
+                    freed.push((mk().ident_expr(ident), srcs.local_may_be_null(hir_id, &nullable)));
+                }
+            }
+
+            MutVisitNodes::visit(block, |e: &mut P<Expr>| {
+                if direct_calls.contains(&e.id) {
+                    return;
+                }
+                if let Some(callee) = converted_call(e) {
+                    let repl = if nullable.contains(&callee) {
+                        &option_into_raw_repl
+                    } else {
+                        &into_raw_repl
+                    };
+                    *e = subst(repl, e.clone());
+                    return;
+                }
+                if let Ok(mcx) = mcx.clone_match(&*free_pat, &*e) {
+                    let ptr = strip_casts(mcx.bindings.get::<_, P<Expr>>("$ptr").unwrap());
+                    if let Some(&(_, may_be_null)) = freed.iter().find(|(d, _)| d.ast_equiv(ptr)) {
+                        let mut bnd = Bindings::new();
+                        bnd.add("$ptr", ptr.clone());
+                        let repl = if may_be_null { &checked_free_repl } else { &free_repl };
+                        *e = repl.clone().subst(st, cx, &bnd);
+                    }
+                }
+            });
+
+            let srcs = match_or!([fns.get(&def_id)] Some(x) => x; return);
+            let returns_option = nullable.contains(&def_id);
+            fold_returns(block, |e| {
+                let src = srcs.returns.iter().find(|&&(id, _)| id == strip_casts(e).id)
+                    .expect("unrecognized return value").1;
+                let boxed = match src {
+                    RetSource::Null => return *e = none_repl.clone(),
+                    RetSource::Alloc => {
+                        match alloc_bindings(e) {
+                            Some(bnd) => box_repl.clone().subst(st, cx, &bnd),
+                            None => {
+                                let m = mcx.clone_match(&*into_raw_pat, &**strip_casts(e))
+                                    .unwrap();
+                                m.bindings.get::<_, P<Expr>>("$b").unwrap().clone()
+                            }
+                        }
+                    }
+                    RetSource::Call(callee) => {
+                        let call = strip_casts(e).clone();
+                        if nullable.contains(&callee) {
+                            return *e = call;
+                        }
+                        call
+                    }
+                    RetSource::Local(hir_id) => {
+                        let p = strip_casts(e).clone();
+                        if returns_option && srcs.local_may_be_null(hir_id, &nullable) {
+                            return *e = subst(&checked_repl, p);
+                        }
+                        subst(&from_raw_repl, p)
+                    }
+                };
+                *e = if returns_option { subst(&some_repl, boxed) } else { boxed };
+            });
+
+            MutVisitNodes::visit(block, |e: &mut P<Expr>| {
+                if !srcs.allocs.contains(&e.id) {
+                    return;
+                }
+                if let Some(bnd) = alloc_bindings(e) {
+                    *e = alloc_repl.clone().subst(st, cx, &bnd);
+                }
+            });
+
+            let mut bnd = Bindings::new();
+            bnd.add("$t", cands[&def_id].1.clone());
+            let ty = if returns_option { &option_ty } else { &box_ty };
+            fl.decl = fl.decl.clone().map(|mut decl| {
+                decl.output = FunctionRetTy::Ty(ty.clone().subst(st, cx, &bnd));
+                decl
+            });
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
+/// # `dptr_outparam` Command
+///
+/// Usage: `dptr_outparam`
+///
+/// Marks: `target`
+///
+/// For each function marked `target`, change its `*mut *mut T` out-parameters
+/// to `&mut Option<Box<T>>`.  The function may only store through such a
+/// parameter `out`, or check it for null:
+///
+///  * `*out = p` becomes `*out = Some(Box::from_raw(p))`, `*out =
+///    Box::into_raw(b)` becomes `*out = Some(b)`, and storing an allocation
+///    that `malloc_to_box` would convert becomes
+///    `*out = Some(Box::new(<T as Default>::default()))`;
+///  * storing a null pointer becomes `*out = None`;
+///  * `out.is_null()` becomes `false`, and `!out.is_null()` becomes `true`,
+///    since a reference is never null.  An `if` testing one of those is
+///    replaced by the branch that runs, or removed if that's a missing `else`.
+///
+/// Every call must pass `&mut place` for each out-parameter:
+///
+///  * A local declared as `let mut p = null;` is retyped to `Option<Box<T>>`,
+///    if its other uses are all dereferences, null checks, `free`s and null
+///    assignments.  `*p` becomes `*p.as_deref_mut().unwrap()`, `p.is_null()`
+///    becomes `p.is_none()`, and `free(p as *mut libc::c_void)` and
+///    `p = null` become `p = None`.
+///  * A struct field is retyped the same way, if every use of it in the crate
+///    is one of those, or a struct literal initializing it to null, and its
+///    struct isn't `Copy`.  Otherwise the function is skipped with a warning.
+///  * Any other place gets the pointer back through a temporary, as in
+///    `{ let mut __out0: Option<Box<T>> = None; let __ret = f(&mut __out0);
+///    p = __out0.map_or(::std::ptr::null_mut(), Box::into_raw); __ret }`, so
+///    `p` is set to null if the function leaves `None` in it.
+///
+/// Functions that are exported, that are used other than by calling them, or
+/// that have a caller passing anything other than `&mut place` for an
+/// out-parameter (such as null, for an optional one) are skipped with a
+/// warning.
+pub struct DptrOutparam;
+
+/// A `*mut *mut T` parameter converted by `dptr_outparam`.
+struct DptrParam {
+    index: usize,
+    hir_id: HirId,
+    pointee: P<Ty>,
+}
+
+/// A `&mut place` passed for a `dptr_outparam` parameter.
+struct DptrArg {
+    callee: DefId,
+    place: Option<Nullable>,
+    pointee: P<Ty>,
+    call_span: Span,
+}
+
+impl Transform for DptrOutparam {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        let tcx = cx.ty_ctxt();
+        let mut mcx = MatchCtxt::new(st, cx);
+        let alloc_pats = [
+            mcx.parse_expr("malloc(cast!(::std::mem::size_of::<$t:Ty>())) as *mut $t"),
+            mcx.parse_expr("calloc(cast!(1), cast!(::std::mem::size_of::<$t:Ty>())) as *mut $t"),
+        ];
+        let into_raw_pat = mcx.parse_expr("Box::into_raw($b:Expr)");
+        let free_pat = mcx.parse_expr("free($ptr:Expr)");
+        let param_ty = mcx.parse_ty("&mut Option<Box<$t>>");
+        let option_ty = mcx.parse_ty("Option<Box<$t>>");
+        let box_repl = mcx.parse_expr("Some(Box::new(<$t as Default>::default()))");
+        let some_repl = mcx.parse_expr("Some($e)");
+        let from_raw_repl = mcx.parse_expr("Some(Box::from_raw($e))");
+        let none_repl = mcx.parse_expr("None");
+        let unwrap_repl = mcx.parse_expr("*$e.as_deref_mut().unwrap()");
+        let is_none_repl = mcx.parse_expr("$e.is_none()");
+        let clear_repl = mcx.parse_expr("$e = None");
+
+        let span_str = |span: Span| cx.session().source_map().span_to_string(span);
+        let subst = |repl: &P<Expr>, e: P<Expr>| {
+            let mut bnd = Bindings::new();
+            bnd.add("$e", e);
+            repl.clone().subst(st, cx, &bnd)
+        };
+        let subst_ty = |repl: &P<Ty>, t: P<Ty>| {
+            let mut bnd = Bindings::new();
+            bnd.add("$t", t);
+            repl.clone().subst(st, cx, &bnd)
+        };
+
+        // (1) Find the out-parameters of the marked functions.  Each must only be stored through
+        // or checked for null.
+
+        let mut fns: HashMap<DefId, (Ident, Vec<DptrParam>)> = HashMap::new();
+        visit_fns(krate, |fl| {
+            if !st.marked(fl.id, "target") {
+                return;
+            }
+            let block = match_or!([fl.block] Some(ref x) => x; return);
+            let def_id = cx.node_def_id(fl.id);
+            if cx.is_exported_def(def_id) {
+                return warn!("dptr_outparam: `{}` is exported, so its signature can't change; \
+                              skipping", fl.ident);
+            }
+            let mut params = Vec::new();
+            for (index, arg) in fl.decl.inputs.iter().enumerate() {
+                let pointee = match arg.ty.kind {
+                    TyKind::Ptr(MutTy { ref ty, mutbl: Mutability::Mutable }) => match ty.kind {
+                        TyKind::Ptr(MutTy { ref ty, mutbl: Mutability::Mutable }) => ty.clone(),
+                        _ => continue,
+                    },
+                    _ => continue,
+                };
+                let ident = match_or!([arg.pat.kind]
+                    PatKind::Ident(BindingMode::ByValue(_), ident, None) => ident; continue);
+                let hir_id = cx.hir_map().node_to_hir_id(arg.pat.id);
+                let is_param = |e: &Expr| match e.kind {
+                    ExprKind::Path(None, _) => cx.try_resolve_expr_to_hid(e) == Some(hir_id),
+                    _ => false,
+                };
+
+                let mut uses = 0;
+                let mut handled = 0;
+                visit_nodes(&**block, |e: &Expr| {
+                    match e.kind {
+                        ExprKind::Path(..) if is_param(e) => uses += 1,
+                        ExprKind::Assign(ref lhs, _) => match lhs.kind {
+                            ExprKind::Unary(UnOp::Deref, ref p) if is_param(strip_parens(p)) =>
+                                handled += 1,
+                            _ => {}
+                        },
+                        _ => {}
+                    }
+                    // `!out.is_null()` is visited again as `out.is_null()`.
+                    if let Some((p, false)) = null_check(e) {
+                        if is_param(strip_parens(p)) {
+                            handled += 1;
+                        }
+                    }
+                });
+                if uses == 0 {
+                    continue;
+                }
+                if uses != handled {
+                    warn!("dptr_outparam: `{}` uses `{}` other than by storing through it; \
+                           leaving it alone", fl.ident, ident);
+                    continue;
+                }
+                params.push(DptrParam { index, hir_id, pointee });
+            }
+            if params.is_empty() {
+                return warn!("dptr_outparam: `{}` has no `*mut *mut` out-parameters; skipping",
+                             fl.ident);
+            }
+            fns.insert(def_id, (fl.ident, params));
+        });
+
+        let mut callees = HashSet::new();
+        visit_nodes(krate, |e: &Expr| {
+            if let ExprKind::Call(ref func, _) = e.kind {
+                callees.insert(func.id);
+            }
+        });
+        visit_nodes(krate, |e: &Expr| {
+            if let ExprKind::Path(..) = e.kind {
+                match cx.try_resolve_expr(e) {
+                    Some(def_id) if !callees.contains(&e.id) => {
+                        if let Some((name, _)) = fns.remove(&def_id) {
+                            warn!("dptr_outparam: `{}` is used at {} other than in a call; \
+                                   skipping", name, span_str(e.span));
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        });
+
+        // (2) Check the arguments passed for the out-parameters.
+
+        let mut args = HashMap::new();
+        let mut rejected = HashSet::new();
+        visit_nodes(krate, |e: &Expr| {
+            let call_args = match_or!([e.kind] ExprKind::Call(_, ref a) => a; return);
+            let callee = match cx.opt_callee(e) {
+                Some(def_id) if fns.contains_key(&def_id) => def_id,
+                _ => return,
+            };
+            let (name, ref params) = fns[&callee];
+            for param in params {
+                let arg = strip_casts(&call_args[param.index]);
+                match arg.kind {
+                    ExprKind::AddrOf(_, Mutability::Mutable, ref place) => {
+                        args.insert(arg.id, DptrArg {
+                            callee,
+                            place: nullable_key(cx, place),
+                            pointee: param.pointee.clone(),
+                            call_span: e.span,
+                        });
+                        continue;
+                    }
+                    _ if is_null_ptr(arg) => {
+                        warn!("dptr_outparam: call at {} passes null for an out-parameter of \
+                               `{}`; skipping", span_str(e.span), name);
+                    }
+                    _ => {
+                        warn!("dptr_outparam: call at {} passes `{}` for an out-parameter of \
+                               `{}`; skipping", span_str(e.span), pprust::expr_to_string(arg),
+                              name);
+                    }
+                }
+                rejected.insert(callee);
+            }
+        });
+        fns.retain(|def_id, _| !rejected.contains(def_id));
+
+        // (3) Decide which of the passed locals and fields can be retyped.  A local or field can
+        // be retyped if every use of it is one we know how to rewrite.  Other locals get the
+        // pointer back through a temporary, but a function getting the address of any other
+        // field is skipped.
+
+        let mut null_locals = HashSet::new();
+        visit_nodes(krate, |l: &Local| {
+            let mutable = match l.pat.kind {
+                PatKind::Ident(BindingMode::ByValue(Mutability::Mutable), _, None) => true,
+                _ => false,
+            };
+            if let (true, Some(init)) = (mutable, &l.init) {
+                if is_null_ptr(init) {
+                    null_locals.insert(cx.hir_map().node_to_hir_id(l.pat.id));
+                }
+            }
+        });
+        let can_retype = |n: Nullable| match n {
+            Nullable::Local(hir_id) => null_locals.contains(&hir_id),
+            Nullable::Field(did, _) => did.is_local() &&
+                !tcx.type_of(did).is_copy_modulo_regions(tcx, ParamEnv::empty(), DUMMY_SP),
+        };
+
+        let mut uses = HashMap::new();
+        let mut handled = HashMap::new();
+        let mut bad_inits = HashSet::new();
+        visit_nodes(krate, |e: &Expr| {
+            let mut handle = |p: &Expr| if let Some(n) = nullable_key(cx, p) {
+                *handled.entry(n).or_insert(0) += 1;
+            };
+            match e.kind {
+                ExprKind::Path(None, _) | ExprKind::Field(..) => {
+                    if let Some(n) = nullable_key(cx, e) {
+                        *uses.entry(n).or_insert(0) += 1;
+                    }
+                }
+                ExprKind::Unary(UnOp::Deref, ref p) => handle(p),
+                ExprKind::Assign(ref lhs, ref rhs) if is_null_ptr(rhs) => handle(lhs),
+                ExprKind::Struct(_, ref fields, _) => {
+                    if let Some(&ty::Adt(def, _)) = cx.opt_node_type(e.id).map(|ty| &ty.kind) {
+                        for field in fields.iter().filter(|f| !is_null_ptr(&f.expr)) {
+                            bad_inits.insert(Nullable::Field(def.did, field.ident.name));
+                        }
+                    }
+                }
+                _ => {}
+            }
+            if let Some((p, false)) = null_check(e) {
+                handle(p);
+            }
+            if let Ok(mcx) = mcx.clone_match(&*free_pat, e) {
+                handle(strip_casts(mcx.bindings.get::<_, P<Expr>>("$ptr").unwrap()));
+            }
+        });
+
+        let mut retyped = HashMap::new();
+        loop {
+            let mut out_uses = HashMap::new();
+            for arg in args.values().filter(|arg| fns.contains_key(&arg.callee)) {
+                if let Some(n) = arg.place {
+                    *out_uses.entry(n).or_insert(0) += 1;
+                }
+            }
+            retyped.clear();
+            let mut rejected = HashSet::new();
+            for arg in args.values().filter(|arg| fns.contains_key(&arg.callee)) {
+                let n = match_or!([arg.place] Some(x) => x; continue);
+                let expected = handled.get(&n).cloned().unwrap_or(0) + out_uses[&n];
+                if can_retype(n) && !bad_inits.contains(&n) && uses.get(&n) == Some(&expected) {
+                    retyped.insert(n, arg.pointee.clone());
+                } else if let Nullable::Field(_, name) = n {
+                    if rejected.insert(arg.callee) {
+                        warn!("dptr_outparam: call at {} passes the address of field `{}`, which \
+                               can't be retyped; skipping `{}`", span_str(arg.call_span), name,
+                              fns[&arg.callee].0);
+                    }
+                }
+            }
+            if rejected.is_empty() {
+                break;
+            }
+            fns.retain(|def_id, _| !rejected.contains(def_id));
+        }
+        if fns.is_empty() {
+            return;
+        }
+
+        // (4) Rewrite the converted functions.
+
+        mut_visit_fns(krate, |fl| {
+            let params = match_or!([fns.get(&cx.node_def_id(fl.id))] Some(x) => &x.1; return);
+            let is_param = |e: &Expr| match e.kind {
+                ExprKind::Path(None, _) => cx.try_resolve_expr_to_hid(e)
+                    .map_or(false, |hir_id| params.iter().any(|p| p.hir_id == hir_id)),
+                _ => false,
+            };
+            // The null checks of the parameters, with their values, and the `if`s testing them.
+            let mut checks = HashMap::new();
+            let mut check_ifs = HashMap::new();
+            visit_nodes(&**fl.block.as_ref().unwrap(), |e: &Expr| {
+                if let Some((p, negated)) = null_check(e) {
+                    if is_param(strip_parens(p)) {
+                        checks.insert(e.id, negated);
+                    }
+                }
+                if let ExprKind::If(ref cond, _, _) = e.kind {
+                    if let Some((p, negated)) = null_check(cond) {
+                        if is_param(strip_parens(p)) {
+                            check_ifs.insert(e.id, negated);
+                        }
+                    }
+                }
+            });
+
+            // As in `ptr_to_nonnull`, `if`s whose missing `else` branch runs are removed first.
+            MutVisitNodes::visit(fl.block.as_mut().unwrap(), |b: &mut P<Block>| {
+                b.stmts.retain(|s| match s.kind {
+                    StmtKind::Expr(ref e) | StmtKind::Semi(ref e) => match e.kind {
+                        ExprKind::If(_, _, None) => check_ifs.get(&e.id) != Some(&false),
+                        _ => true,
+                    },
+                    _ => true,
+                });
+            });
+
+            MutVisitNodes::visit(fl.block.as_mut().unwrap(), |e: &mut P<Expr>| {
+                if let Some(&value) = checks.get(&e.id) {
+                    *e = parse_expr(cx.session(), if value { "true" } else { "false" });
+                    return;
+                }
+                if let Some(&value) = check_ifs.get(&e.id) {
+                    let taken = match e.kind {
+                        ExprKind::If(_, ref then, _) if value =>
+                            Some(ExprKind::Block(then.clone(), None)),
+                        ExprKind::If(_, _, ref els) => els.as_ref().map(|els| els.kind.clone()),
+                        _ => None,
+                    };
+                    if let Some(kind) = taken {
+                        e.kind = kind;
+                    }
+                    return;
+                }
+                let (lhs, rhs) = match_or!([e.kind]
+                    ExprKind::Assign(ref lhs, ref mut rhs) => (lhs, rhs); return);
+                match lhs.kind {
+                    ExprKind::Unary(UnOp::Deref, ref p) if is_param(strip_parens(p)) => {}
+                    _ => return,
+                }
+                let alloc = alloc_pats.iter().filter_map(|pat| mcx.clone_match(&**pat, &**rhs).ok())
+                    .next();
+                let new_rhs = if is_null_ptr(rhs) {
+                    none_repl.clone()
+                } else if let Some(m) = alloc {
+                    box_repl.clone().subst(st, cx, &m.bindings)
+                } else if let Ok(m) = mcx.clone_match(&*into_raw_pat, &**strip_casts(rhs)) {
+                    subst(&some_repl, m.bindings.get::<_, P<Expr>>("$b").unwrap().clone())
+                } else {
+                    subst(&from_raw_repl, rhs.clone())
+                };
+                *rhs = new_rhs;
+            });
+
+            fl.decl = fl.decl.clone().map(|mut decl| {
+                for param in params {
+                    decl.inputs[param.index].ty = subst_ty(&param_ty, param.pointee.clone());
+                }
+                decl
+            });
+        });
+
+        // (5) Retype the locals and fields, and rewrite their uses and the calls.
+
+        MutVisitNodes::visit(krate, |l: &mut P<Local>| {
+            let n = Nullable::Local(cx.hir_map().node_to_hir_id(l.pat.id));
+            if let Some(pointee) = retyped.get(&n) {
+                l.ty = Some(subst_ty(&option_ty, pointee.clone()));
+                l.init = Some(none_repl.clone());
+            }
+        });
+
+        FlatMapNodes::visit(krate, |mut i: P<Item>| {
+            let did = cx.node_def_id(i.id);
+            if let ItemKind::Struct(VariantData::Struct(ref mut fields, _), _) = i.kind {
+                for field in fields.iter_mut() {
+                    let n = match_or!([field.ident] Some(x) => Nullable::Field(did, x.name);
+                                      continue);
+                    if let Some(pointee) = retyped.get(&n) {
+                        field.ty = subst_ty(&option_ty, pointee.clone());
+                    }
+                }
+            }
+            smallvec![i]
+        });
+
+        let is_retyped = |e: &Expr| nullable_key(cx, e).map_or(false, |n| retyped.contains_key(&n));
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            let checked = null_check(e)
+                .filter(|&(p, negated)| !negated && is_retyped(p))
+                .map(|(p, _)| p.clone());
+            if let Some(p) = checked {
+                return *e = subst(&is_none_repl, p);
+            }
+            if let Ok(m) = mcx.clone_match(&*free_pat, &*e) {
+                let ptr = strip_casts(m.bindings.get::<_, P<Expr>>("$ptr").unwrap());
+                if is_retyped(ptr) {
+                    return *e = subst(&clear_repl, ptr.clone());
+                }
+            }
+
+            let id = e.id;
+            let new_e = match e.kind {
+                ExprKind::Unary(UnOp::Deref, ref p) if is_retyped(p) =>
+                    subst(&unwrap_repl, p.clone()),
+
+                ExprKind::Assign(ref lhs, ref mut rhs) => {
+                    if is_retyped(lhs) && is_null_ptr(rhs) {
+                        *rhs = none_repl.clone();
+                    }
+                    return;
+                }
+
+                ExprKind::Struct(_, ref mut fields, _) => {
+                    let did = match cx.opt_node_type(id).map(|ty| &ty.kind) {
+                        Some(&ty::Adt(def, _)) => def.did,
+                        _ => return,
+                    };
+                    for field in fields.iter_mut() {
+                        if retyped.contains_key(&Nullable::Field(did, field.ident.name)) {
+                            field.expr = none_repl.clone();
+                        }
+                    }
+                    return;
+                }
+
+                ExprKind::Call(..) => {
+                    let params = match cx.opt_callee(e).and_then(|def_id| fns.get(&def_id)) {
+                        Some(&(_, ref params)) => params,
+                        None => return,
+                    };
+                    let mut call = e.clone();
+                    let call_args = match_or!([call.kind] ExprKind::Call(_, ref mut a) => a;
+                                              unreachable!());
+                    let mut bnd = Bindings::new();
+                    let mut shims = 0;
+                    for param in params {
+                        let arg = strip_casts(&call_args[param.index]).clone();
+                        let place = match_or!([arg.kind] ExprKind::AddrOf(_, _, ref x) => x.clone();
+                                              unreachable!());
+                        if is_retyped(&place) {
+                            call_args[param.index] = arg;
+                            continue;
+                        }
+                        bnd.add(format!("$o{}", shims), place);
+                        bnd.add(format!("$t{}", shims), param.pointee.clone());
+                        call_args[param.index] =
+                            parse_expr(cx.session(), &format!("&mut __out{}", shims));
+                        shims += 1;
+                    }
+                    if shims == 0 {
+                        call
+                    } else {
+                        let decls = (0..shims)
+                            .map(|i| format!("let mut __out{0}: Option<Box<$t{0}>> = None;", i))
+                            .collect::<Vec<_>>();
+                        let assigns = (0..shims)
+                            .map(|i| format!("$o{0} = __out{0}.map_or(::std::ptr::null_mut(), \
+                                              Box::into_raw);", i))
+                            .collect::<Vec<_>>();
+                        bnd.add("$call", call);
+                        let src = format!("{{ {} let __ret = $call; {} __ret }}",
+                                          decls.join(" "), assigns.join(" "));
+                        mcx.parse_expr(&src).subst(st, cx, &bnd)
+                    }
+                }
+
+                _ => return,
+            };
+            *e = new_e;
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+/// # `destroy_to_drop` Command
+///
+/// Usage: `destroy_to_drop`
+///
+/// Marks: `target`
+///
+/// Turn each destructor function marked `target`, of the form
+/// `unsafe fn foo_destroy(p: *mut foo)`, into an `impl Drop for foo`.  The
+/// function must end by freeing `p` itself, with `free(p as *mut libc::c_void)`
+/// or `drop(Box::from_raw(p))`, and may start by returning when `p` is null.
+/// Everything in between, such as freeing the fields of `*p`, logging, or
+/// updating reference counts, becomes the body of `drop`, with `(*p).f` written
+/// as `self.f` and `p` as `self as *mut foo`.
+///
+/// Every call `foo_destroy(q)` becomes `drop(Box::from_raw(q))`, guarded by
+/// `!q.is_null()` if `foo_destroy` checked for null.  This includes the calls
+/// in other destructors, so a struct owning another through a pointer field
+/// drops it from its own `drop`.  `foo_destroy(Box::into_raw(b))` becomes
+/// `drop(b)`, and is removed when it's the last statement of the block declaring
+/// `b`, which drops `b` anyway.
+///
+/// Since the structs are freed as `Box`es, they must be allocated as `Box`es too,
+/// for example by running `malloc_to_box` first.  Destructors of structs that are
+/// still allocated with `malloc`, `calloc` or `realloc` are skipped with a
+/// warning, as are those that return early or free `p` anywhere but at the end.
+/// The structs must also only live on the heap: once `foo` implements `Drop`,
+/// a `foo` held by value in a local, parameter or field would free its fields
+/// when it goes out of scope, and `*q = foo { .. }` would free those of the
+/// value it overwrites.  Destructors of structs held by value anywhere, or
+/// assigned to as a whole, are skipped with a warning.
+/// `Copy` types can't implement `Drop`, so `Copy` is removed from the derives of
+/// the structs.
+///
+/// Destructors that are exported or used other than by calling them are kept as
+/// shims calling `drop(Box::from_raw(p))`, for code outside the crate.  The
+/// others are removed.
+pub struct DestroyToDrop;
+
+/// A destructor converted by `destroy_to_drop`.
+struct Destructor {
+    name: Ident,
+    /// The `DefId` of the struct it frees.
+    struct_def_id: DefId,
+    /// The type of the struct, as written in the parameter type.
+    ty: P<Ty>,
+    param: Ident,
+    param_hir_id: HirId,
+    /// Whether it starts by returning if its argument is null.
+    null_checked: bool,
+    /// Whether it has to be kept as a shim.
+    keep: bool,
+}
+
+impl Transform for DestroyToDrop {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        let mut mcx = MatchCtxt::new(st, cx);
+        let free_pat = mcx.parse_expr("free($ptr:Expr)");
+        let drop_box_pat = mcx.parse_expr("drop(Box::from_raw($ptr:Expr))");
+        let into_raw_pat = mcx.parse_expr("Box::into_raw($b:Expr)");
+        let drop_repl = mcx.parse_expr("drop(Box::from_raw($ptr))");
+        let checked_drop_repl = mcx.parse_expr(
+            "if !$ptr.is_null() { drop(Box::from_raw($ptr)); }");
+        let drop_box_repl = mcx.parse_expr("drop($b)");
+
+        let frees = |e: &Expr, hir_id: HirId| {
+            [&free_pat, &drop_box_pat].iter().any(|pat| match mcx.clone_match(&***pat, e) {
+                Ok(m) => {
+                    let ptr = strip_casts(m.bindings.get::<_, P<Expr>>("$ptr").unwrap());
+                    cx.try_resolve_expr_to_hid(strip_parens(ptr)) == Some(hir_id)
+                }
+                Err(_) => false,
+            })
+        };
+        let is_param = |e: &Expr, hir_id: HirId| {
+            e.id != DUMMY_NODE_ID && cx.try_resolve_expr_to_hid(e) == Some(hir_id)
+        };
+
+        // (1) Find the marked destructors, and check their bodies.
+
+        let mut dtors = HashMap::new();
+        let mut structs = HashSet::new();
+        visit_nodes(krate, |i: &Item| {
+            if !st.marked(i.id, "target") {
+                return;
+            }
+            let (sig, generics, body) = match_or!([i.kind]
+                ItemKind::Fn(ref sig, ref generics, ref body) => (sig, generics, body); return);
+            let report = |msg: &str| warn!("destroy_to_drop: `{}` {}; skipping", i.ident, msg);
+
+            let param = match (&sig.decl.inputs[..], &sig.decl.output) {
+                ([param], FunctionRetTy::Default(_)) if generics.params.is_empty() => param,
+                _ => return report("isn't a function of one pointer returning nothing"),
+            };
+            let (param_ident, ty) = match (&param.pat.kind, &param.ty.kind) {
+                (&PatKind::Ident(BindingMode::ByValue(_), ident, None),
+                 &TyKind::Ptr(MutTy { ref ty, mutbl: Mutability::Mutable })) => (ident, ty),
+                _ => return report("isn't a function of one pointer returning nothing"),
+            };
+            let struct_def_id = match cx.opt_node_type(param.pat.id).map(|ty| &ty.kind) {
+                Some(&ty::RawPtr(mt)) => match mt.ty.kind {
+                    ty::Adt(def, _) if def.is_struct() && def.did.is_local() => def.did,
+                    _ => return report("doesn't take a pointer to a struct of this crate"),
+                },
+                _ => return report("doesn't take a pointer to a struct of this crate"),
+            };
+            if !structs.insert(struct_def_id) {
+                return report("frees the same struct as another destructor");
+            }
+            let param_hir_id = cx.hir_map().node_to_hir_id(param.pat.id);
+
+            let stmts = &body.stmts;
+            let null_checked = stmts.first().map_or(false, |s| match s.kind {
+                StmtKind::Expr(ref e) | StmtKind::Semi(ref e) => match e.kind {
+                    ExprKind::If(ref cond, ref then, None) => {
+                        let checked = match null_check(cond) {
+                            Some((p, false)) => is_param(strip_parens(p), param_hir_id),
+                            _ => match cond.kind {
+                                ExprKind::Binary(op, ref p, ref null) if op.node == BinOpKind::Eq =>
+                                    is_param(strip_parens(p), param_hir_id) && is_null_ptr(null),
+                                _ => false,
+                            },
+                        };
+                        checked && then.stmts.len() == 1 && match then.stmts[0].kind {
+                            StmtKind::Expr(ref e) | StmtKind::Semi(ref e) => match e.kind {
+                                ExprKind::Ret(None) => true,
+                                _ => false,
+                            },
+                            _ => false,
+                        }
+                    }
+                    _ => false,
+                },
+                _ => false,
+            });
+            let ends_with_free = stmts.last().map_or(false, |s| match s.kind {
+                StmtKind::Expr(ref e) | StmtKind::Semi(ref e) => frees(e, param_hir_id),
+                _ => false,
+            });
+            if !ends_with_free {
+                return report("doesn't end by freeing its argument");
+            }
+            let start = if null_checked { 1 } else { 0 };
+            let mut bad = None;
+            for s in &stmts[start..stmts.len() - 1] {
+                visit_nodes(s, |e: &Expr| match e.kind {
+                    ExprKind::Ret(_) => bad = Some("returns early"),
+                    _ if frees(e, param_hir_id) => bad = Some("frees its argument before the end"),
+                    _ => {}
+                });
+            }
+            if let Some(msg) = bad {
+                return report(msg);
+            }
+
+            dtors.insert(cx.node_def_id(i.id), Destructor {
+                name: i.ident,
+                struct_def_id,
+                ty: ty.clone(),
+                param: param_ident,
+                param_hir_id,
+                null_checked,
+                keep: cx.is_exported_def(cx.node_def_id(i.id)),
+            });
+        });
+
+        // The structs must already be allocated as `Box`es.
+        visit_nodes(krate, |e: &Expr| {
+            let call = match_or!([e.kind] ExprKind::Cast(ref call, _) => strip_parens(call); return);
+            let func = match_or!([call.kind] ExprKind::Call(ref func, _) => func; return);
+            let name = match_or!([func.kind] ExprKind::Path(None, ref path) =>
+                path.segments.last().unwrap().ident.name; return);
+            if !["malloc", "calloc", "realloc"].contains(&&*name.as_str()) {
+                return;
+            }
+            let did = match cx.opt_node_type(e.id).map(|ty| &ty.kind) {
+                Some(&ty::RawPtr(mt)) => match mt.ty.kind {
+                    ty::Adt(def, _) => def.did,
+                    _ => return,
+                },
+                _ => return,
+            };
+            let dtor = dtors.iter().find(|&(_, d)| d.struct_def_id == did).map(|(&id, _)| id);
+            if let Some(def_id) = dtor {
+                warn!("destroy_to_drop: `{}` frees a struct that is allocated with `{}` at {}; \
+                       skipping", dtors[&def_id].name, name,
+                      cx.session().source_map().span_to_string(e.span));
+                dtors.remove(&def_id);
+            }
+        });
+
+        // Nor may they be held by value, in locals, parameters or fields, or assigned to.
+        let mut by_value = Vec::new();
+        visit_nodes(krate, |l: &Local| {
+            by_value.extend(cx.opt_node_type(l.pat.id).map(|ty| (ty, l.span)));
+        });
+        visit_fns(krate, |fl| {
+            for param in &fl.decl.inputs {
+                by_value.extend(cx.opt_node_type(param.pat.id).map(|ty| (ty, param.span)));
+            }
+        });
+        visit_nodes(krate, |e: &Expr| {
+            if let ExprKind::Assign(ref lhs, _) = e.kind {
+                by_value.extend(cx.opt_node_type(lhs.id).map(|ty| (ty, e.span)));
+            }
+        });
+        visit_nodes(krate, |i: &Item| {
+            if let ItemKind::Struct(ref vd, _) | ItemKind::Union(ref vd, _) = i.kind {
+                for field in vd.fields() {
+                    let ty = cx.ty_ctxt().type_of(cx.node_def_id(field.id));
+                    by_value.push((ty, field.span));
+                }
+            }
+        });
+        for (ty, span) in by_value {
+            let dtor = dtors.iter()
+                .find(|&(_, d)| holds_by_value(ty, d.struct_def_id))
+                .map(|(&id, _)| id);
+            if let Some(def_id) = dtor {
+                warn!("destroy_to_drop: `{}` frees a struct that is held by value at {}; \
+                       skipping", dtors[&def_id].name,
+                      cx.session().source_map().span_to_string(span));
+                dtors.remove(&def_id);
+            }
+        }
+
+        let mut callees = HashSet::new();
+        visit_nodes(krate, |e: &Expr| {
+            if let ExprKind::Call(ref func, _) = e.kind {
+                callees.insert(func.id);
+            }
+        });
+        visit_nodes(krate, |e: &Expr| {
+            if let ExprKind::Path(..) = e.kind {
+                if callees.contains(&e.id) {
+                    return;
+                }
+                if let Some(dtor) = cx.try_resolve_expr(e).and_then(|id| dtors.get_mut(&id)) {
+                    dtor.keep = true;
+                }
+            }
+        });
+
+        // (2) Rewrite the calls.  A call freeing a local `Box` at the end of its block goes away.
+
+        let dtor_call = |e: &Expr| match e.kind {
+            ExprKind::Call(..) => cx.opt_callee(e).filter(|def_id| dtors.contains_key(def_id)),
+            _ => None,
+        };
+        let boxed_arg = |e: &Expr| match e.kind {
+            ExprKind::Call(_, ref args) => mcx.clone_match(&*into_raw_pat, &*args[0]).ok()
+                .map(|m| m.bindings.get::<_, P<Expr>>("$b").unwrap().clone()),
+            _ => None,
+        };
+        MutVisitNodes::visit(krate, |b: &mut P<Block>| {
+            let last = match b.stmts.last().map(|s| &s.kind) {
+                Some(StmtKind::Semi(e)) | Some(StmtKind::Expr(e)) if dtor_call(e).is_some() => e,
+                _ => return,
+            };
+            let owner = match boxed_arg(last) {
+                Some(owner) => owner,
+                None => return,
+            };
+            let declared = b.stmts.iter().any(|s| match s.kind {
+                StmtKind::Local(ref l) =>
+                    cx.try_resolve_expr_to_hid(&owner) ==
+                        Some(cx.hir_map().node_to_hir_id(l.pat.id)),
+                _ => false,
+            });
+            if declared {
+                b.stmts.pop();
+            }
+        });
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            let callee = match_or!([dtor_call(e)] Some(x) => x; return);
+            let mut bnd = Bindings::new();
+            if let Some(owner) = boxed_arg(e) {
+                bnd.add("$b", owner);
+                *e = drop_box_repl.clone().subst(st, cx, &bnd);
+                return;
+            }
+            let ptr = expect!([e.kind] ExprKind::Call(_, ref args) => args[0].clone());
+            bnd.add("$ptr", ptr);
+            let repl = if dtors[&callee].null_checked { &checked_drop_repl } else { &drop_repl };
+            *e = repl.clone().subst(st, cx, &bnd);
+        });
+
+        // (3) Replace the destructors with `Drop` impls, and shims where needed.
+
+        FlatMapNodes::visit(krate, |mut i: P<Item>| {
+            let def_id = match i.kind {
+                ItemKind::Fn(..) | ItemKind::Struct(..) => cx.node_def_id(i.id),
+                _ => return smallvec![i],
+            };
+            if let ItemKind::Struct(..) = i.kind {
+                if dtors.values().any(|d| d.struct_def_id == def_id) {
+                    remove_copy_derive(&mut i, st, cx);
+                }
+                return smallvec![i];
+            }
+            let dtor = match_or!([dtors.get(&def_id)] Some(x) => x; return smallvec![i]);
+
+            let (unsafety, mut stmts) = expect!([i.kind]
+                ItemKind::Fn(ref sig, _, ref body) => (sig.header.unsafety, body.stmts.clone()));
+            stmts.pop();
+            if dtor.null_checked {
+                stmts.remove(0);
+            }
+            for s in &mut stmts {
+                MutVisitNodes::visit(s, |e: &mut P<Expr>| {
+                    if let ExprKind::Field(ref mut base, _) = e.kind {
+                        let is_deref = match strip_parens(base).kind {
+                            ExprKind::Unary(UnOp::Deref, ref p) => is_param(p, dtor.param_hir_id),
+                            _ => false,
+                        };
+                        if is_deref {
+                            *base = mk().ident_expr("self");
+                        }
+                    }
+                });
+                MutVisitNodes::visit(s, |e: &mut P<Expr>| {
+                    if let ExprKind::Unary(UnOp::Deref, ref mut p) = e.kind {
+                        if is_param(p, dtor.param_hir_id) {
+                            *p = mk().ident_expr("self");
+                        }
+                    }
+                });
+                MutVisitNodes::visit(s, |e: &mut P<Expr>| {
+                    if is_param(e, dtor.param_hir_id) {
+                        *e = parse_expr(cx.session(), &format!(
+                            "self as *mut {}", pprust::ty_to_string(&dtor.ty)));
+                    }
+                });
+            }
+
+            let body = if unsafety == Unsafety::Unsafe { "unsafe {}" } else { "" };
+            let mut imp = st.parse_items(cx, &format!(
+                "impl Drop for {} {{ fn drop(&mut self) {{ {} }} }}",
+                pprust::ty_to_string(&dtor.ty), body)).lone();
+            let mut stmts = Some(stmts);
+            MutVisitNodes::visit(&mut imp, |b: &mut P<Block>| {
+                if let Some(stmts) = stmts.take() {
+                    b.stmts = stmts;
+                }
+            });
+
+            if !dtor.keep {
+                return smallvec![imp];
+            }
+            if let ItemKind::Fn(_, _, ref mut body) = i.kind {
+                body.stmts.truncate(if dtor.null_checked { 1 } else { 0 });
+                body.stmts.extend(parse_stmts(cx.session(), &format!(
+                    "drop(Box::from_raw({}));", dtor.param)));
+            }
+            smallvec![i, imp]
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+/// Check whether a value of type `ty` contains the struct `did` itself, rather than a pointer to it.
+fn holds_by_value(ty: ty::Ty, did: DefId) -> bool {
+    match ty.kind {
+        ty::Adt(def, _) => def.did == did,
+        ty::Array(elem, _) | ty::Slice(elem) => holds_by_value(elem, did),
+        ty::Tuple(_) => ty.tuple_fields().any(|ty| holds_by_value(ty, did)),
+        _ => false,
+    }
+}
+
+/// Remove `Copy` from the derives of the struct `i`.
+fn remove_copy_derive(i: &mut P<Item>, st: &CommandState, cx: &RefactorCtxt) {
+    let copy = Symbol::intern("Copy");
+    let attrs = mem::replace(&mut i.attrs, Vec::new());
+    for attr in attrs {
+        let derived = match attr.meta_item_list() {
+            Some(ref items) if attr.check_name(sym::derive) => items.iter()
+                .filter_map(|m| m.ident())
+                .map(|ident| ident.name)
+                .collect::<Vec<_>>(),
+            _ => {
+                i.attrs.push(attr);
+                continue;
+            }
+        };
+        if !derived.contains(&copy) {
+            i.attrs.push(attr);
+            continue;
+        }
+        let kept = derived.iter()
+            .filter(|&&name| name != copy)
+            .map(|name| name.to_string())
+            .collect::<Vec<_>>();
+        if !kept.is_empty() {
+            let src = format!("#[derive({})] struct S;", kept.join(", "));
+            i.attrs.push(st.parse_items(cx, &src).lone().attrs[0].clone());
+        }
+    }
+}
+
+
+pub fn register_commands(reg: &mut Registry) {
+    use super::mk;
+
+    reg.register("malloc_to_box", |args| mk(MallocToBox {
+        uninit: args.iter().any(|arg| arg == "uninit"),
+    }));
+    reg.register("calloc_to_vec", |_args| mk(CallocToVec));
+    reg.register("realloc_to_vec", |_args| mk(ReallocToVec));
+    reg.register("ret_ptr_to_box", |_args| mk(RetPtrToBox));
+    reg.register("dptr_outparam", |_args| mk(DptrOutparam));
+    reg.register("destroy_to_drop", |_args| mk(DestroyToDrop));
+}
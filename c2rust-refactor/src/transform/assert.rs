@@ -0,0 +1,253 @@
+//! Transform that replaces C `assert`, `abort` and `exit` with their Rust equivalents.
+
+use std::str;
+use rustc_data_structures::sync::Lrc;
+use rustc::ty::TyKind;
+use syntax::ast::*;
+use syntax::ptr::P;
+use syntax::source_map::DUMMY_SP;
+use syntax::token::{Nonterminal, Token, TokenKind};
+use syntax::tokenstream::TokenTree;
+
+use c2rust_ast_builder::mk;
+use crate::ast_manip::MutVisitNodes;
+use crate::command::{CommandState, Registry};
+use crate::driver::Phase;
+use crate::matcher::{Bindings, MatchCtxt, Subst};
+use crate::transform::Transform;
+use crate::transform::canonicalize_refs::strip_parens;
+use crate::transform::enums::lit_value;
+use crate::transform::mem::strip_casts;
+use crate::RefactorCtxt;
+
+
+/// # `c_assert_to_rust` Command
+///
+/// Usage: `c_assert_to_rust [main_return]`
+///
+/// Replace the translations of C `assert`, `abort` and `exit` with their Rust
+/// equivalents:
+///
+///  * A C assertion, which is translated to `if cond == 0 { __assert_fail(msg,
+///    file, line, func); }`, `if cond {} else { __assert_fail(...); }` or
+///    `(cond != 0 || __assert_fail(...));`, becomes `assert!(cond, "msg")`,
+///    with the message taken from the `msg` byte string.  A condition like
+///    `(x > 0) as libc::c_int != 0` is simplified to `x > 0`.
+///  * `abort()` becomes `::std::process::abort()`.
+///  * `exit(n)` becomes `::std::process::exit(n)`, dropping the casts around
+///    `n` that don't change its type, as in `exit(1 as libc::c_int)`.
+///
+/// With `main_return`, an `exit(n);` that's the last statement of the
+/// translated C `main`, `main_0`, becomes `return n;` instead.  The generated
+/// `main` exits with the code `main_0` returns, so this only changes the
+/// behavior by dropping the locals of `main_0` first.
+///
+/// Only calls of `extern` functions are rewritten.
+pub struct CAssertToRust {
+    pub main_return: bool,
+}
+
+/// If `e` is a call of the `extern` function `name`, return its arguments.
+pub(super) fn foreign_call<'a>(cx: &RefactorCtxt, e: &'a Expr, name: &str)
+                               -> Option<&'a [P<Expr>]> {
+    let (func, args) = match_or!([e.kind] ExprKind::Call(ref f, ref a) => (f, a); return None);
+    let path = match_or!([func.kind] ExprKind::Path(None, ref p) => p; return None);
+    if &*path.segments.last()?.ident.as_str() != name ||
+       !cx.ty_ctxt().is_foreign_item(cx.opt_callee(e)?) {
+        return None;
+    }
+    Some(args)
+}
+
+/// If `e` is a call of `__assert_fail`, possibly compared with zero, cast or at the start of a
+/// block, return its arguments.
+fn assert_fail_args<'a>(cx: &RefactorCtxt, e: &'a Expr) -> Option<&'a [P<Expr>]> {
+    match e.kind {
+        ExprKind::Paren(ref inner) | ExprKind::Cast(ref inner, _) => assert_fail_args(cx, inner),
+        ExprKind::Binary(op, ref inner, _)
+                if op.node == BinOpKind::Eq || op.node == BinOpKind::Ne =>
+            assert_fail_args(cx, inner),
+        ExprKind::Block(ref b, None) => match b.stmts.first()?.kind {
+            StmtKind::Semi(ref e) | StmtKind::Expr(ref e) => assert_fail_args(cx, e),
+            _ => None,
+        },
+        _ => foreign_call(cx, e, "__assert_fail"),
+    }
+}
+
+/// If `b` consists of a call of `__assert_fail`, return its arguments.
+fn block_assert_fail<'a>(cx: &RefactorCtxt, b: &'a Block) -> Option<&'a [P<Expr>]> {
+    if b.stmts.len() != 1 {
+        return None;
+    }
+    match b.stmts[0].kind {
+        StmtKind::Semi(ref e) | StmtKind::Expr(ref e) => foreign_call(cx, e, "__assert_fail"),
+        _ => None,
+    }
+}
+
+/// Simplify a `bool` cast to an integer and compared with zero, as in `(x > 0) as c_int != 0`.
+fn simplify_cond(cx: &RefactorCtxt, cond: &P<Expr>) -> P<Expr> {
+    if let ExprKind::Binary(op, ref l, ref r) = strip_parens(cond).kind {
+        if op.node == BinOpKind::Ne && lit_value(r) == Some(0) {
+            if let ExprKind::Cast(ref inner, _) = strip_parens(l).kind {
+                if cx.opt_node_type(inner.id).map_or(false, |ty| ty.kind == TyKind::Bool) {
+                    return inner.clone();
+                }
+            }
+        }
+    }
+    cond.clone()
+}
+
+/// The message of an assertion, from the C source of its condition in the first argument of
+/// `__assert_fail`.
+fn assert_msg(args: &[P<Expr>]) -> Option<String> {
+    let lit = match_or!([strip_casts(args.first()?).kind] ExprKind::Lit(ref l) => l; return None);
+    let bytes = match_or!([lit.kind] LitKind::ByteStr(ref b) => b; return None);
+    let bytes = if bytes.last() == Some(&0) { &bytes[..bytes.len() - 1] } else { &bytes[..] };
+    str::from_utf8(bytes).ok().map(|s| s.to_owned())
+}
+
+/// Build `assert!(cond, "msg")`, or `assert!(cond)` if there's no message.
+fn assert_mac(cond: P<Expr>, msg: Option<String>) -> Mac {
+    let expr_tt = |mut e: P<Expr>| {
+        let span = e.span;
+        e.span = DUMMY_SP;
+        TokenTree::Token(Token {
+            kind: TokenKind::Interpolated(Lrc::new(Nonterminal::NtExpr(e))),
+            span,
+        })
+    };
+    let comma = || TokenTree::Token(Token { kind: TokenKind::Comma, span: DUMMY_SP });
+
+    let mut macro_tts = vec![expr_tt(cond)];
+    if let Some(msg) = msg {
+        // Don't let the message be taken for a format string.
+        if msg.contains('{') || msg.contains('}') {
+            macro_tts.push(comma());
+            macro_tts.push(expr_tt(mk().lit_expr("{}")));
+        }
+        macro_tts.push(comma());
+        macro_tts.push(expr_tt(mk().lit_expr(&msg)));
+    }
+    mk().mac(vec!["assert"], macro_tts, MacDelimiter::Parenthesis)
+}
+
+impl Transform for CAssertToRust {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        let mut mcx = MatchCtxt::new(st, cx);
+        let not_repl = mcx.parse_expr("!$c");
+        let abort_repl = mcx.parse_expr("::std::process::abort()");
+        let exit_repl = mcx.parse_expr("::std::process::exit($n)");
+        let return_repl = mcx.parse_expr("return $n");
+
+        // The argument of `exit`, without the casts that don't change its type.
+        let exit_code = |args: &[P<Expr>]| -> Option<P<Expr>> {
+            if args.len() != 1 {
+                return None;
+            }
+            let mut n = &args[0];
+            while let ExprKind::Cast(ref inner, _) = n.kind {
+                match (cx.opt_node_type(inner.id), cx.opt_node_type(n.id)) {
+                    (Some(inner_ty), Some(ty)) if inner_ty == ty => n = inner,
+                    _ => break,
+                }
+            }
+            Some(n.clone())
+        };
+        let subst = |repl: &P<Expr>, name: &str, e: P<Expr>| {
+            let mut bnd = Bindings::new();
+            bnd.add(name, e);
+            repl.clone().subst(st, cx, &bnd)
+        };
+
+        // (1) Return the code of an `exit` at the end of `main_0`.
+
+        if self.main_return {
+            MutVisitNodes::visit(krate, |i: &mut P<Item>| {
+                if &*i.ident.as_str() != "main_0" {
+                    return;
+                }
+                let body = match_or!([i.kind] ItemKind::Fn(_, _, ref mut body) => body; return);
+                let last = match body.stmts.last_mut() {
+                    Some(&mut Stmt { kind: StmtKind::Semi(ref mut e), .. }) |
+                    Some(&mut Stmt { kind: StmtKind::Expr(ref mut e), .. }) => e,
+                    _ => return,
+                };
+                let n = match foreign_call(cx, last, "exit").and_then(|args| exit_code(args)) {
+                    Some(n) => n,
+                    None => return,
+                };
+                *last = subst(&return_repl, "$n", n);
+            });
+        }
+
+        // (2) Rewrite the assertions.
+
+        MutVisitNodes::visit(krate, |b: &mut P<Block>| {
+            for s in &mut b.stmts {
+                let e = match s.kind {
+                    StmtKind::Semi(ref e) | StmtKind::Expr(ref e) => e,
+                    _ => continue,
+                };
+                let (cond, args) = match strip_parens(e).kind {
+                    ExprKind::If(ref cond, ref then, None) => {
+                        let args = match_or!([block_assert_fail(cx, then)] Some(x) => x;
+                                             continue);
+                        let cond = match strip_parens(cond).kind {
+                            ExprKind::Unary(UnOp::Not, ref inner) => simplify_cond(cx, inner),
+                            ExprKind::Binary(op, _, _) if op.node == BinOpKind::Eq => {
+                                let mut ne = P(strip_parens(cond).clone());
+                                if let ExprKind::Binary(ref mut op, _, _) = ne.kind {
+                                    op.node = BinOpKind::Ne;
+                                }
+                                simplify_cond(cx, &ne)
+                            }
+                            _ => subst(&not_repl, "$c", cond.clone()),
+                        };
+                        (cond, args)
+                    }
+                    ExprKind::If(ref cond, ref then, Some(ref els)) if then.stmts.is_empty() => {
+                        let els = match_or!([els.kind] ExprKind::Block(ref b, None) => b;
+                                            continue);
+                        let args = match_or!([block_assert_fail(cx, els)] Some(x) => x;
+                                             continue);
+                        (simplify_cond(cx, cond), args)
+                    }
+                    ExprKind::Binary(op, ref cond, ref fail) if op.node == BinOpKind::Or => {
+                        let args = match_or!([assert_fail_args(cx, fail)] Some(x) => x;
+                                             continue);
+                        (simplify_cond(cx, cond), args)
+                    }
+                    _ => continue,
+                };
+                let mac = assert_mac(cond, assert_msg(args));
+                *s = mk().span(s.span).semi_stmt(mk().mac_expr(mac));
+            }
+        });
+
+        // (3) Rewrite the other calls of `abort` and `exit`.
+
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            if foreign_call(cx, e, "abort").map_or(false, |args| args.is_empty()) {
+                *e = abort_repl.clone().subst(st, cx, &Bindings::new());
+            } else if let Some(n) = foreign_call(cx, e, "exit").and_then(|args| exit_code(args)) {
+                *e = subst(&exit_repl, "$n", n);
+            }
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
+pub fn register_commands(reg: &mut Registry) {
+    use super::mk;
+
+    reg.register("c_assert_to_rust", |args| mk(CAssertToRust {
+        main_return: args.iter().any(|arg| arg == "main_return"),
+    }));
+}
@@ -0,0 +1,427 @@
+//! Transform that adds typed accessors for `c2rust_bitfields` bitfields.
+
+use std::collections::{HashMap, HashSet};
+
+use rustc::hir::def_id::DefId;
+use rustc::ty::TyKind;
+use syntax::ast::*;
+use syntax::ptr::P;
+use syntax::symbol::Symbol;
+use smallvec::{smallvec, SmallVec};
+
+use c2rust_ast_builder::mk;
+use crate::ast_manip::{FlatMapNodes, MutVisitNodes, visit_nodes};
+use crate::command::{CommandState, Registry};
+use crate::driver::{self, Phase};
+use crate::transform::Transform;
+use crate::transform::enums::lit_value;
+use crate::transform::mem::strip_casts;
+use crate::RefactorCtxt;
+
+
+/// # `bitfield_cleanup` Command
+///
+/// Usage: `bitfield_cleanup [FIELD=ENUM...]`
+///
+/// Marks: `target`
+///
+/// For each struct marked `target` that declares bitfields through the `c2rust_bitfields`
+/// `#[bitfield(name = "..", ty = "..", bits = "..")]` attributes, add an `impl` block after
+/// the struct with typed accessors for some of the bitfields:
+///
+///  * a 1-bit bitfield `x` gets `x_flag(&self) -> bool` and `set_x_flag(&mut self, bool)`;
+///  * a bitfield `x` named in a `FIELD=ENUM` argument, where `ENUM` is a fieldless enum
+///    whose discriminants are the bitfield's values, gets `x_enum(&self) -> ENUM` and
+///    `set_x_enum(&mut self, ENUM)`.  The getter panics on a value that is not a
+///    discriminant of `ENUM`.
+///
+/// The new accessors call the `x()` and `set_x()` methods generated by the
+/// `BitfieldStruct` derive, so the bit layout is unchanged.
+///
+/// Calls to the derived methods are rewritten to use the new accessors where the value has
+/// an obvious typed form:
+///
+///  * `s.set_x(1)` and `s.set_x(0)` become `s.set_x_flag(true)` and `s.set_x_flag(false)`,
+///    and `s.set_x(b as T)` with a `bool` `b` becomes `s.set_x_flag(b)`;
+///  * `s.x() != 0` becomes `s.x_flag()`, and `s.x() == 0` becomes `!s.x_flag()`;
+///  * `s.set_x(V as T)` with a variant `V` of `ENUM`, or with an integer literal equal to
+///    the discriminant of `V`, becomes `s.set_x_enum(V)`;
+///  * `s.x() == k`, where `k` is such a variant or literal, becomes `s.x_enum() == V`, and
+///    likewise for `!=`.  This is only done if `ENUM` has a variant for every value the
+///    bitfield can hold, since `x_enum()` panics on the others.  Otherwise a literal `k`
+///    becomes `V as T` instead, and the raw value is still compared.
+///
+/// Other calls are left alone.
+pub struct BitfieldCleanup {
+    /// The enum to use for each bitfield, by bitfield name.
+    pub enums: HashMap<String, String>,
+}
+
+/// A bitfield declared by a `#[bitfield]` attribute.
+struct Bitfield {
+    name: String,
+    /// The integer type of the bitfield, as written in the attribute.
+    ty: String,
+    width: u32,
+}
+
+/// The typed accessors added for a bitfield.
+enum Accessors {
+    Flag,
+    Enum {
+        /// The path to the enum, as given on the command line.
+        path: String,
+        def_id: DefId,
+        /// The variants of the enum, with their discriminants.
+        variants: Vec<(Ident, i128)>,
+        /// The integer type of the bitfield, as written in the attribute.
+        ty: String,
+        /// Whether every value of the bitfield is the discriminant of a variant.
+        exhaustive: bool,
+    },
+}
+
+/// Get the bitfields declared on the fields of `vd`.
+fn bitfields(vd: &VariantData) -> Vec<Bitfield> {
+    let mut bitfields = Vec::new();
+    for field in vd.fields() {
+        for attr in &field.attrs {
+            if !attr.check_name(Symbol::intern("bitfield")) {
+                continue;
+            }
+            let mut name = None;
+            let mut ty = None;
+            let mut bits = None;
+            for item in attr.meta_item_list().unwrap_or_default() {
+                let value = match item.meta_item().and_then(|mi| mi.value_str()) {
+                    Some(value) => value.to_string(),
+                    None => continue,
+                };
+                match &*item.name_or_empty().as_str() {
+                    "name" => name = Some(value),
+                    "ty" => ty = Some(value),
+                    "bits" => bits = Some(value),
+                    _ => {}
+                }
+            }
+            // The `padding` attribute has no name.
+            let (name, ty, bits) = match (name, ty, bits) {
+                (Some(name), Some(ty), Some(bits)) => (name, ty, bits),
+                _ => continue,
+            };
+            let mut range = bits.splitn(2, "..=").map(|b| b.trim().parse::<u32>());
+            let width = match (range.next(), range.next()) {
+                (Some(Ok(lo)), Some(Ok(hi))) if lo <= hi => hi - lo + 1,
+                _ => {
+                    warn!("bitfield_cleanup: can't parse bits `{}` of `{}`", bits, name);
+                    continue;
+                }
+            };
+            bitfields.push(Bitfield { name, ty, width });
+        }
+    }
+    bitfields
+}
+
+/// Get the source for the accessors of bitfield `bf`.
+fn accessors_src(bf: &Bitfield, acc: &Accessors) -> String {
+    match *acc {
+        Accessors::Flag => format!(
+            "    pub fn {name}_flag(&self) -> bool {{\n        self.{name}() != 0\n    }}\n\
+             \n    pub fn set_{name}_flag(&mut self, value: bool) {{\n        \
+             self.set_{name}(value as {ty})\n    }}\n",
+            name = bf.name, ty = bf.ty),
+        Accessors::Enum { ref path, ref variants, .. } => {
+            let mut arms = String::new();
+            for &(ident, value) in variants {
+                arms.push_str(&format!("            {} => {}::{},\n", value, path, ident));
+            }
+            format!(
+                "    pub fn {name}_enum(&self) -> {path} {{\n        match self.{name}() {{\n\
+                 {arms}            \
+                 value => panic!(\"invalid `{path}` value: {{}}\", value),\n        }}\n    }}\n\
+                 \n    pub fn set_{name}_enum(&mut self, value: {path}) {{\n        \
+                 self.set_{name}(value as {ty})\n    }}\n",
+                name = bf.name, ty = bf.ty, path = path, arms = arms)
+        }
+    }
+}
+
+/// Check whether every value of a `width`-bit bitfield is among the discriminants of
+/// `variants`.
+fn covers_width(variants: &[(Ident, i128)], width: u32) -> bool {
+    if width >= 32 {
+        return false;
+    }
+    let values = variants.iter().map(|&(_, v)| v).collect::<HashSet<_>>();
+    (0..1i128 << width).all(|v| values.contains(&v))
+}
+
+impl BitfieldCleanup {
+    /// Find the fieldless enums named by the arguments.
+    fn find_enums(&self, krate: &Crate, cx: &RefactorCtxt)
+                  -> HashMap<String, (DefId, Vec<(Ident, i128)>)> {
+        let mut enums = HashMap::new();
+        visit_nodes(krate, |i: &Item| {
+            let def = match i.kind {
+                ItemKind::Enum(ref def, _) => def,
+                _ => return,
+            };
+            let name = i.ident.as_str();
+            let path = match self.enums.values().find(|p| p.rsplit("::").next() == Some(&*name)) {
+                Some(path) => path,
+                None => return,
+            };
+            let mut variants = Vec::new();
+            let mut next = 0;
+            for v in &def.variants {
+                if !v.data.fields().is_empty() {
+                    warn!("bitfield_cleanup: `{}` has fields; skipping", path);
+                    return;
+                }
+                let value = match v.disr_expr {
+                    Some(ref disr) => match lit_value(&disr.value) {
+                        Some(value) => value,
+                        None => {
+                            warn!("bitfield_cleanup: discriminant of `{}::{}` is not a \
+                                   literal; skipping", path, v.ident);
+                            return;
+                        }
+                    },
+                    None => next,
+                };
+                variants.push((v.ident, value));
+                next = value + 1;
+            }
+            enums.insert(path.clone(), (cx.node_def_id(i.id), variants));
+        });
+        enums
+    }
+}
+
+struct BitfieldCalls<'a, 'tcx: 'a> {
+    cx: &'a RefactorCtxt<'a, 'tcx>,
+    /// The accessors added for each bitfield of each marked struct.
+    structs: HashMap<DefId, HashMap<String, Accessors>>,
+}
+
+impl<'a, 'tcx> BitfieldCalls<'a, 'tcx> {
+    /// If `e` is a call `s.method(args)` with `s` a marked struct, and `method` is
+    /// `prefix` followed by a bitfield name, return `s`, the bitfield name and its accessors,
+    /// and the other arguments.
+    fn call<'e>(&self, e: &'e Expr, prefix: &str)
+                -> Option<(&'e P<Expr>, String, &Accessors, &'e [P<Expr>])> {
+        let (seg, args) = match e.kind {
+            ExprKind::MethodCall(ref seg, ref args) => (seg, args),
+            _ => return None,
+        };
+        let mut ty = self.cx.opt_node_type(args[0].id)?;
+        while let TyKind::Ref(_, inner, _) = ty.kind {
+            ty = inner;
+        }
+        let fields = match ty.kind {
+            TyKind::Adt(def, _) => self.structs.get(&def.did)?,
+            _ => return None,
+        };
+        let method = seg.ident.as_str();
+        if !method.starts_with(prefix) {
+            return None;
+        }
+        let name = &method[prefix.len()..];
+        let acc = fields.get(name)?;
+        Some((&args[0], name.to_owned(), acc, &args[1..]))
+    }
+
+    /// Get the variant of the enum of `acc` that `e` stands for: a variant cast to an
+    /// integer, or an integer literal.
+    fn variant(&self, acc: &Accessors, e: &P<Expr>) -> Option<P<Expr>> {
+        let (path, def_id, variants) = match *acc {
+            Accessors::Enum { ref path, def_id, ref variants, .. } => (path, def_id, variants),
+            Accessors::Flag => return None,
+        };
+        let inner = strip_casts(e);
+        let is_enum = self.cx.opt_node_type(inner.id).map_or(false, |ty| match ty.kind {
+            TyKind::Adt(def, _) => def.did == def_id,
+            _ => false,
+        });
+        if is_enum {
+            return Some(inner.clone());
+        }
+        let value = lit_value(e)?;
+        let &(ident, _) = variants.iter().find(|&&(_, v)| v == value)?;
+        Some(driver::parse_expr(self.cx.session(), &format!("{}::{}", path, ident)))
+    }
+
+    /// Get the `bool` that `e`, an argument of a 1-bit bitfield setter, stands for.
+    fn flag(&self, e: &P<Expr>) -> Option<P<Expr>> {
+        match lit_value(e) {
+            Some(0) => return Some(driver::parse_expr(self.cx.session(), "false")),
+            Some(1) => return Some(driver::parse_expr(self.cx.session(), "true")),
+            Some(_) => return None,
+            None => {}
+        }
+        let inner = strip_casts(e);
+        let is_bool = self.cx.opt_node_type(inner.id).map_or(false, |ty| match ty.kind {
+            TyKind::Bool => true,
+            _ => false,
+        });
+        if is_bool { Some(inner.clone()) } else { None }
+    }
+
+    /// Rewrite `e` to use the typed accessors, if it is a setter call or a comparison of a
+    /// getter call that has an obvious typed form.
+    fn rewrite(&self, e: &Expr) -> Option<P<Expr>> {
+        if let Some((recv, name, acc, args)) = self.call(e, "set_") {
+            if args.len() != 1 {
+                return None;
+            }
+            let (method, arg) = match *acc {
+                Accessors::Flag => ("flag", self.flag(&args[0])?),
+                Accessors::Enum { .. } => ("enum", self.variant(acc, &args[0])?),
+            };
+            return Some(mk().method_call_expr(recv.clone(),
+                                              &*format!("set_{}_{}", name, method),
+                                              vec![arg]));
+        }
+
+        let (op, l, r) = match e.kind {
+            ExprKind::Binary(op, ref l, ref r) => (op, l, r),
+            _ => return None,
+        };
+        if op.node != BinOpKind::Eq && op.node != BinOpKind::Ne {
+            return None;
+        }
+        let getter = |e: &P<Expr>| self.call(strip_casts(e), "")
+            .filter(|&(_, _, _, args)| args.is_empty());
+        let ((recv, name, acc, _), k) = match getter(l) {
+            Some(call) => (call, r),
+            None => (getter(r)?, l),
+        };
+        match *acc {
+            Accessors::Flag => {
+                let is_set = match lit_value(k)? {
+                    0 => op.node == BinOpKind::Ne,
+                    1 => op.node == BinOpKind::Eq,
+                    _ => return None,
+                };
+                let test = mk().method_call_expr(recv.clone(), &*format!("{}_flag", name),
+                                                 Vec::<P<Expr>>::new());
+                Some(if is_set { test } else { mk().unary_expr("!", test) })
+            }
+            Accessors::Enum { ref ty, exhaustive, .. } => {
+                let variant = self.variant(acc, k)?;
+                let mut new_e = P(e.clone());
+                if exhaustive {
+                    let get = mk().method_call_expr(recv.clone(), &*format!("{}_enum", name),
+                                                    Vec::<P<Expr>>::new());
+                    if let ExprKind::Binary(_, ref mut l, ref mut r) = new_e.kind {
+                        *l = get;
+                        *r = variant;
+                    }
+                } else {
+                    // Only a literal has anything to gain; a variant is already cast to `T`.
+                    lit_value(k)?;
+                    let raw = mk().cast_expr(variant, driver::parse_ty(self.cx.session(), ty));
+                    if let ExprKind::Binary(_, ref mut l, ref mut r) = new_e.kind {
+                        if l.id == k.id {
+                            *l = raw;
+                        } else {
+                            *r = raw;
+                        }
+                    }
+                }
+                Some(new_e)
+            }
+        }
+    }
+}
+
+impl Transform for BitfieldCleanup {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        let enums = self.find_enums(krate, cx);
+
+        // (1) Decide which accessors to add to each marked struct, and generate them.
+
+        let mut structs = HashMap::new();
+        let mut impls = HashMap::new();
+        visit_nodes(krate, |i: &Item| {
+            if !st.marked(i.id, "target") {
+                return;
+            }
+            let vd = match i.kind {
+                ItemKind::Struct(ref vd, _) => vd,
+                _ => return,
+            };
+            let mut fields = HashMap::new();
+            let mut src = String::new();
+            for bf in bitfields(vd) {
+                let acc = match self.enums.get(&bf.name) {
+                    Some(path) => match enums.get(path) {
+                        Some(&(def_id, ref variants)) => Accessors::Enum {
+                            path: path.clone(),
+                            def_id,
+                            variants: variants.clone(),
+                            ty: bf.ty.clone(),
+                            exhaustive: covers_width(variants, bf.width),
+                        },
+                        None => {
+                            warn!("bitfield_cleanup: found no fieldless enum `{}` for `{}`",
+                                  path, bf.name);
+                            continue;
+                        }
+                    },
+                    None if bf.width == 1 => Accessors::Flag,
+                    None => continue,
+                };
+                if !src.is_empty() {
+                    src.push('\n');
+                }
+                src.push_str(&accessors_src(&bf, &acc));
+                fields.insert(bf.name, acc);
+            }
+            if fields.is_empty() {
+                warn!("bitfield_cleanup: no accessors to add to `{}`", i.ident);
+                return;
+            }
+            impls.insert(i.id, format!("impl {} {{\n{}}}\n", i.ident, src));
+            structs.insert(cx.node_def_id(i.id), fields);
+        });
+
+        // (2) Rewrite the calls to the derived methods.
+
+        let calls = BitfieldCalls { cx, structs };
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            if let Some(new_e) = calls.rewrite(e) {
+                *e = new_e;
+            }
+        });
+
+        // (3) Add the accessors after the structs.
+
+        FlatMapNodes::visit(krate, |i: P<Item>| {
+            let src = match impls.get(&i.id) {
+                Some(src) => src,
+                None => return smallvec![i],
+            };
+            let mut items: SmallVec<[P<Item>; 1]> = smallvec![i];
+            items.extend(driver::parse_items(cx.session(), src));
+            items
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
+pub fn register_commands(reg: &mut Registry) {
+    use super::mk;
+
+    reg.register("bitfield_cleanup", |args| mk(BitfieldCleanup {
+        enums: args.iter().filter_map(|arg| {
+            let mut parts = arg.splitn(2, '=');
+            Some((parts.next()?.to_owned(), parts.next()?.to_owned()))
+        }).collect(),
+    }));
+}
@@ -0,0 +1,443 @@
+//! Transform that turns C integer flags into `bool`s.
+
+use std::collections::{HashMap, HashSet};
+
+use rustc::hir::HirId;
+use rustc::hir::def_id::DefId;
+use rustc::ty;
+use syntax::ast::*;
+use syntax::mut_visit::{self, MutVisitor};
+use syntax::print::pprust;
+use syntax::ptr::P;
+
+use c2rust_ast_builder::mk;
+use crate::ast_manip::{MutVisit, MutVisitNodes, visit_nodes};
+use crate::ast_manip::fn_edit::{mut_visit_fns, visit_fns, FnKind};
+use crate::command::{CommandState, Registry};
+use crate::driver::{self, Phase};
+use crate::transform::Transform;
+use crate::transform::enums::{field_def, lit_value};
+use crate::RefactorCtxt;
+
+
+/// # `int_to_bool` Command
+///
+/// Usage: `int_to_bool [auto]`
+///
+/// Marks: `target`
+///
+/// Retype the integer locals, arguments and struct fields marked `target` to `bool`, for
+/// C flags that only ever hold 0 or 1.  Assignments of `0` and `1` become `false` and
+/// `true`, assignments of comparisons cast to an integer, like `(a < b) as libc::c_int`,
+/// drop the cast, and tests of the flag against `0` or `1` become `x` or `!x`.  Where the
+/// value is still used as an integer, for instance in arithmetic or as an argument of an
+/// `extern` function, it is cast back to its integer type.  A field is rewritten at every
+/// access across the crate.
+///
+/// A flag is skipped with a warning if it may be assigned some other value, if it is
+/// updated by a compound assignment, if its address is taken, or if it is `match`ed on.
+/// Arguments of functions that are used other than by calling them are skipped, since
+/// retyping them changes the type of the function.
+///
+/// With `auto`, every integer local, argument and field is considered, and the ones that
+/// are only ever assigned 0 or 1 and are tested against 0 at least once are converted.
+/// Note that converting a field changes the layout of a `#[repr(C)]` struct.
+pub struct IntToBool {
+    pub auto: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+enum Flag {
+    Local(HirId),
+    Field(DefId),
+}
+
+struct FlagInfo {
+    name: Ident,
+    /// Source of the integer type, for casting the flag back where it's used as an integer.
+    int_ty: String,
+    marked: bool,
+}
+
+struct BoolFolder<'a, 'tcx: 'a> {
+    cx: &'a RefactorCtxt<'a, 'tcx>,
+    flags: &'a HashMap<Flag, FlagInfo>,
+    /// The flag arguments of each function, by position.
+    params: &'a HashMap<DefId, Vec<Option<Flag>>>,
+    /// Flags used in ways that can't be converted.
+    failed: HashSet<Flag>,
+    /// Flags that are tested against 0 or 1 somewhere.
+    tested: HashSet<Flag>,
+}
+
+impl<'a, 'tcx> BoolFolder<'a, 'tcx> {
+    fn flag_of(&self, e: &Expr) -> Option<Flag> {
+        let flag = match e.kind {
+            ExprKind::Paren(ref e) => return self.flag_of(e),
+            ExprKind::Path(..) => self.cx.try_resolve_expr_to_hid(e).map(Flag::Local),
+            ExprKind::Field(ref base, ident) => field_def(self.cx, base.id, ident).map(Flag::Field),
+            _ => None,
+        };
+        flag.filter(|flag| self.flags.contains_key(flag))
+    }
+
+    fn fail(&mut self, flag: Flag, e: &Expr, why: &str) {
+        let info = &self.flags[&flag];
+        if info.marked {
+            warn!("int_to_bool: `{}` {} at {}; skipping",
+                  info.name, why,
+                  self.cx.session().source_map().span_to_string(e.span));
+        }
+        self.failed.insert(flag);
+    }
+
+    /// Visit the children of the flag access `e`, leaving the access itself alone.
+    fn visit_flag(&mut self, e: &mut P<Expr>) {
+        match e.kind {
+            ExprKind::Paren(ref mut inner) => self.visit_flag(inner),
+            _ => mut_visit::noop_visit_expr(e, self),
+        }
+    }
+
+    /// Convert `e`, which is assigned to `flag`, to a `bool`.
+    fn to_bool(&mut self, flag: Flag, e: &mut P<Expr>) {
+        if self.flag_of(e).is_some() {
+            self.visit_flag(e);
+            return;
+        }
+        match lit_value(e) {
+            Some(0) => {
+                *e = driver::parse_expr(self.cx.session(), "false");
+                return;
+            }
+            Some(1) => {
+                *e = driver::parse_expr(self.cx.session(), "true");
+                return;
+            }
+            _ => {}
+        }
+
+        let mut inner = match e.kind {
+            ExprKind::Cast(ref inner, _) => inner.clone(),
+            _ => {
+                let src = pprust::expr_to_string(e);
+                return self.fail(flag, e, &format!("may be assigned `{}`", src));
+            }
+        };
+        while let ExprKind::Paren(ref x) = inner.kind {
+            inner = x.clone();
+        }
+        match self.cx.opt_node_type(inner.id).map(|ty| &ty.kind) {
+            Some(ty::TyKind::Bool) => {
+                self.visit_expr(&mut inner);
+                *e = inner;
+            }
+            _ => {
+                let src = pprust::expr_to_string(e);
+                self.fail(flag, e, &format!("may be assigned `{}`", src));
+            }
+        }
+    }
+
+    /// Convert the arguments of a call to a function whose arguments are `params`.
+    fn convert_args(&mut self, params: &[Option<Flag>], args: &mut [P<Expr>]) {
+        for (i, arg) in args.iter_mut().enumerate() {
+            match params.get(i).cloned().flatten() {
+                Some(flag) if self.flags.contains_key(&flag) => self.to_bool(flag, arg),
+                _ => self.visit_expr(arg),
+            }
+        }
+    }
+
+    /// If `e` tests a flag against 0 or 1, return the flag access and whether the test is
+    /// negated.
+    fn flag_test<'e>(&self, e: &'e mut Expr) -> Option<(Flag, &'e mut P<Expr>, bool)> {
+        let (op, l, r) = match e.kind {
+            ExprKind::Binary(op, ref mut l, ref mut r) => (op.node, l, r),
+            _ => return None,
+        };
+        let (x, k) = match (self.flag_of(l), self.flag_of(r)) {
+            (Some(flag), None) => ((flag, l), lit_value(r)?),
+            (None, Some(flag)) => ((flag, r), lit_value(l)?),
+            _ => return None,
+        };
+        let negated = match (op, k) {
+            (BinOpKind::Ne, 0) | (BinOpKind::Eq, 1) => false,
+            (BinOpKind::Eq, 0) | (BinOpKind::Ne, 1) => true,
+            _ => return None,
+        };
+        Some((x.0, x.1, negated))
+    }
+}
+
+impl<'a, 'tcx> MutVisitor for BoolFolder<'a, 'tcx> {
+    fn visit_expr(&mut self, e: &mut P<Expr>) {
+        if let Some((flag, x, negated)) = self.flag_test(&mut **e) {
+            let mut x = x.clone();
+            self.visit_flag(&mut x);
+            self.tested.insert(flag);
+            *e = if negated { mk().unary_expr("!", x) } else { x };
+            return;
+        }
+
+        let all_params = self.params;
+        if let Some(params) = self.cx.opt_callee(e).and_then(|def_id| all_params.get(&def_id)) {
+            match e.kind {
+                ExprKind::Call(ref mut func, ref mut args) => {
+                    self.visit_expr(func);
+                    self.convert_args(params, args);
+                    return;
+                }
+                ExprKind::MethodCall(_, ref mut args) => {
+                    self.convert_args(params, args);
+                    return;
+                }
+                _ => {}
+            }
+        }
+
+        let id = e.id;
+        let is_int = self.cx.opt_node_type(id).map_or(false, |ty| ty.is_integral());
+        match e.kind {
+            ExprKind::Path(..) | ExprKind::Field(..) => if let Some(flag) = self.flag_of(e) {
+                // The flag's value is used as an integer.
+                self.visit_flag(e);
+                let int_ty = driver::parse_ty(self.cx.session(), &self.flags[&flag].int_ty);
+                *e = mk().cast_expr(e.clone(), int_ty);
+                return;
+            },
+
+            ExprKind::Cast(ref mut inner, _) if is_int => if self.flag_of(inner).is_some() {
+                self.visit_flag(inner);
+                return;
+            },
+
+            ExprKind::Assign(ref mut lhs, ref mut rhs) => if let Some(flag) = self.flag_of(lhs) {
+                self.visit_flag(lhs);
+                self.to_bool(flag, rhs);
+                return;
+            },
+
+            ExprKind::AssignOp(_, ref lhs, _) => if let Some(flag) = self.flag_of(lhs) {
+                self.fail(flag, lhs, "is updated in place");
+            },
+
+            ExprKind::AddrOf(_, _, ref inner) => if let Some(flag) = self.flag_of(inner) {
+                self.fail(flag, inner, "has its address taken");
+            },
+
+            ExprKind::Match(ref scrutinee, _) => if let Some(flag) = self.flag_of(scrutinee) {
+                self.fail(flag, scrutinee, "is matched on");
+            },
+
+            ExprKind::Struct(_, ref mut fields, ref mut base) => {
+                for field in fields.iter_mut() {
+                    let flag = field_def(self.cx, id, field.ident)
+                        .map(Flag::Field)
+                        .filter(|flag| self.flags.contains_key(flag));
+                    match flag {
+                        Some(flag) => self.to_bool(flag, &mut field.expr),
+                        None => self.visit_expr(&mut field.expr),
+                    }
+                }
+                if let Some(base) = base {
+                    self.visit_expr(base);
+                }
+                return;
+            }
+
+            _ => {}
+        }
+        mut_visit::noop_visit_expr(e, self)
+    }
+
+    fn visit_local(&mut self, l: &mut P<Local>) {
+        let hir_id = self.cx.hir_map().node_to_hir_id(l.pat.id);
+        if self.flags.contains_key(&Flag::Local(hir_id)) {
+            if let Some(ref mut init) = l.init {
+                self.to_bool(Flag::Local(hir_id), init);
+            }
+            return;
+        }
+        mut_visit::noop_visit_local(l, self)
+    }
+
+    fn visit_mac(&mut self, mac: &mut Mac) {
+        mut_visit::noop_visit_mac(mac, self)
+    }
+}
+
+impl Transform for IntToBool {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        // (1) Find the candidate declarations.
+
+        let mut flags = HashMap::new();
+        let add = |id: NodeId, name: Ident, is_int: bool, int_ty: String, marked: bool| {
+            if !is_int {
+                if marked {
+                    warn!("int_to_bool: `{}` doesn't have an integer type; skipping", name);
+                }
+                return None;
+            }
+            let flag = Flag::Local(cx.hir_map().node_to_hir_id(id));
+            Some((flag, FlagInfo { name, int_ty, marked }))
+        };
+
+        let mut params = HashMap::new();
+        visit_fns(krate, |fl| {
+            if fl.kind == FnKind::Foreign || fl.block.is_none() {
+                return;
+            }
+            let fn_params = fl.decl.inputs.iter().map(|arg| {
+                let marked = st.marked(arg.id, "target");
+                if !marked && !self.auto {
+                    return None;
+                }
+                let name = match arg.pat.kind {
+                    PatKind::Ident(_, ident, None) => ident,
+                    _ => return None,
+                };
+                let int_ty = pprust::ty_to_string(&arg.ty);
+                let is_int = cx.node_type(arg.pat.id).is_integral();
+                let (flag, info) = add(arg.pat.id, name, is_int, int_ty, marked)?;
+                flags.insert(flag, info);
+                Some(flag)
+            }).collect::<Vec<_>>();
+            params.insert(cx.node_def_id(fl.id), fn_params);
+        });
+
+        visit_nodes(krate, |l: &Local| {
+            let marked = st.marked(l.pat.id, "target");
+            if !marked && !self.auto {
+                return;
+            }
+            let name = match l.pat.kind {
+                PatKind::Ident(_, ident, None) => ident,
+                _ => return,
+            };
+            let ty = cx.node_type(l.pat.id);
+            let int_ty = match l.ty {
+                Some(ref ty) => pprust::ty_to_string(ty),
+                None => ty.to_string(),
+            };
+            if let Some((flag, info)) = add(l.pat.id, name, ty.is_integral(), int_ty, marked) {
+                flags.insert(flag, info);
+            }
+        });
+
+        visit_nodes(krate, |i: &Item| {
+            let fields = match i.kind {
+                ItemKind::Struct(VariantData::Struct(ref fields, _), _) => fields,
+                _ => return,
+            };
+            for field in fields {
+                let marked = st.marked(field.id, "target");
+                if !marked && !self.auto {
+                    continue;
+                }
+                let name = match field.ident {
+                    Some(ident) => ident,
+                    None => continue,
+                };
+                let def_id = cx.node_def_id(field.id);
+                if cx.def_type(def_id).is_integral() {
+                    let int_ty = pprust::ty_to_string(&field.ty);
+                    flags.insert(Flag::Field(def_id), FlagInfo { name, int_ty, marked });
+                } else if marked {
+                    warn!("int_to_bool: `{}` doesn't have an integer type; skipping", name);
+                }
+            }
+        });
+
+        // Arguments of functions that are used other than by calling them keep their types.
+        let mut callees = HashSet::new();
+        visit_nodes(krate, |e: &Expr| {
+            if let ExprKind::Call(ref func, _) = e.kind {
+                callees.insert(func.id);
+            }
+        });
+        visit_nodes(krate, |e: &Expr| {
+            if let ExprKind::Path(..) = e.kind {
+                if callees.contains(&e.id) {
+                    return;
+                }
+                let fn_params = cx.try_resolve_expr(e).and_then(|def_id| params.remove(&def_id));
+                for flag in fn_params.into_iter().flatten().flatten() {
+                    let info = flags.remove(&flag).unwrap();
+                    if info.marked {
+                        warn!("int_to_bool: the function taking `{}` is used other than by \
+                               calling it; skipping", info.name);
+                    }
+                }
+            }
+        });
+
+        // (2) Convert the uses on a copy of the crate, dropping the flags that can't be
+        // converted, until every use of the remaining flags converts cleanly.
+
+        let new_krate = loop {
+            if flags.is_empty() {
+                return;
+            }
+            let mut new_krate = krate.clone();
+            let mut folder = BoolFolder {
+                cx,
+                flags: &flags,
+                params: &params,
+                failed: HashSet::new(),
+                tested: HashSet::new(),
+            };
+            new_krate.visit(&mut folder);
+            let (failed, tested) = (folder.failed, folder.tested);
+
+            let before = flags.len();
+            flags.retain(|flag, info| {
+                !failed.contains(flag) && (info.marked || tested.contains(flag))
+            });
+            if flags.len() == before {
+                break new_krate;
+            }
+        };
+        *krate = new_krate;
+
+        // (3) Retype the declarations.
+
+        let is_flag = |id: NodeId| {
+            flags.contains_key(&Flag::Local(cx.hir_map().node_to_hir_id(id)))
+        };
+        mut_visit_fns(krate, |fl| {
+            for arg in &mut fl.decl.inputs {
+                if is_flag(arg.pat.id) {
+                    arg.ty = mk().ident_ty("bool");
+                }
+            }
+        });
+        MutVisitNodes::visit(krate, |l: &mut P<Local>| {
+            if is_flag(l.pat.id) && l.ty.is_some() {
+                l.ty = Some(mk().ident_ty("bool"));
+            }
+        });
+        MutVisitNodes::visit(krate, |i: &mut P<Item>| {
+            if let ItemKind::Struct(VariantData::Struct(ref mut fields, _), _) = i.kind {
+                for field in fields {
+                    if flags.contains_key(&Flag::Field(cx.node_def_id(field.id))) {
+                        field.ty = mk().ident_ty("bool");
+                    }
+                }
+            }
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
+pub fn register_commands(reg: &mut Registry) {
+    use super::mk;
+
+    reg.register("int_to_bool", |args| mk(IntToBool {
+        auto: args.iter().any(|arg| arg == "auto"),
+    }));
+}
@@ -1,3 +1,5 @@
+use rustc::hir::Node;
+use rustc::ty::TyKind;
 use rustc::ty::adjustment::{Adjust, AutoBorrow, AutoBorrowMutability};
 use syntax::ast::{Crate, Expr, ExprKind, Mutability, UnOp};
 use syntax::ptr::P;
@@ -100,10 +102,76 @@ fn remove_reborrow(expr: &mut P<Expr>) {
     }
 }
 
+
+/// Transformation that rewrites `(*e).f` to `e.f`, and `(*e).m(...)` to
+/// `e.m(...)`, when `e` is a reference or a `Box`, so auto-deref reaches the
+/// same place.  Derefs of raw pointers are kept.
+///
+/// Method calls are only rewritten when the method takes `&self` or
+/// `&mut self`, so the receiver `*e` was auto-referenced.
+struct SimplifyDerefField;
+
+pub(super) fn strip_parens(mut expr: &Expr) -> &Expr {
+    while let ExprKind::Paren(inner) = &expr.kind {
+        expr = inner;
+    }
+    expr
+}
+
+/// If `expr` is `*e`, possibly in parentheses, and `e` is a reference or a
+/// `Box`, return `e`.
+fn auto_deref_operand<'a>(cx: &RefactorCtxt, expr: &'a Expr) -> Option<&'a P<Expr>> {
+    let inner = match &strip_parens(expr).kind {
+        ExprKind::Unary(UnOp::Deref, inner) => inner,
+        _ => return None,
+    };
+    let ty = cx.opt_node_type(strip_parens(inner).id)?;
+    match ty.kind {
+        TyKind::Ref(..) => Some(inner),
+        _ if ty.is_box() => Some(inner),
+        _ => None,
+    }
+}
+
+/// Is the method receiver `expr` auto-referenced?
+fn is_autoref(cx: &RefactorCtxt, expr: &Expr) -> bool {
+    let hir_expr = match cx.hir_map().find(strip_parens(expr).id) {
+        Some(Node::Expr(e)) => e,
+        _ => return false,
+    };
+    let parent = cx.hir_map().get_parent_did(hir_expr.hir_id);
+    let tables = cx.ty_ctxt().typeck_tables_of(parent);
+    tables.expr_adjustments(hir_expr).iter().any(|adjustment| match adjustment.kind {
+        Adjust::Borrow(AutoBorrow::Ref(..)) => true,
+        _ => false,
+    })
+}
+
+impl Transform for SimplifyDerefField {
+    fn transform(&self, krate: &mut Crate, _st: &CommandState, cx: &RefactorCtxt) {
+        MutVisitNodes::visit(krate, |expr: &mut P<Expr>| {
+            let base = match &mut expr.kind {
+                ExprKind::Field(base, _) => base,
+                ExprKind::MethodCall(_path, args) if is_autoref(cx, &args[0]) => &mut args[0],
+                _ => return,
+            };
+            if let Some(e) = auto_deref_operand(cx, base).cloned() {
+                *base = e;
+            }
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
 pub fn register_commands(reg: &mut Registry) {
     use super::mk;
 
     reg.register("canonicalize_refs", |_args| mk(CanonicalizeRefs));
 
     reg.register("remove_unnecessary_refs", |_args| mk(RemoveUnnecessaryRefs));
+
+    reg.register("simplify_deref_field", |_args| mk(SimplifyDerefField));
 }
@@ -4,6 +4,7 @@ use syntax::token;
 use syntax::ptr::P;
 use syntax_pos::Symbol;
 
+use crate::ast_manip::visit_nodes;
 use crate::command::{CommandState, Registry};
 use crate::driver::Phase;
 use crate::matcher::{mut_visit_match_with, replace_expr, MatchCtxt};
@@ -20,14 +21,32 @@ mod tests;
 ///
 /// Removes all casts of the form `$e as $t` where the expression already has the `$t` type,
 /// and double casts like `$e as $t1 as $t2` where the inner cast is redundant.
+/// Casts of literals are folded into the literal's suffix, e.g. `0 as libc::c_int`
+/// becomes `0i32`.  Casts inside macro arguments are left alone.
 pub struct RemoveRedundantCasts;
 
 impl Transform for RemoveRedundantCasts {
     fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
         let tcx = cx.ty_ctxt();
+
+        // Rewriting the arguments of a macro invocation can make the invocation impossible to
+        // collapse back, so collect the call sites of all expanded macros and skip anything
+        // inside them.
+        let mut macro_spans = Vec::new();
+        visit_nodes(krate, |e: &Expr| {
+            if e.span.from_expansion() {
+                macro_spans.push(e.span.source_callsite());
+            }
+        });
+
         let mut mcx = MatchCtxt::new(st, cx);
         let pat = mcx.parse_expr("$oe:Expr as $ot:Ty");
         mut_visit_match_with(mcx, pat, krate, |ast, mcx| {
+            if ast.span.from_expansion() || macro_spans.iter().any(|sp| sp.contains(ast.span)) {
+                debug!("skipping cast in macro: {:?}", ast);
+                return;
+            }
+
             let oe = mcx.bindings.get::<_, P<Expr>>("$oe").unwrap();
             let oe_ty = cx.node_type(oe.id);
             let oe_ty = tcx.normalize_erasing_regions(ParamEnv::empty(), oe_ty);
@@ -1,49 +1,228 @@
+//! Transform that replaces integer character codes with byte and char literals.
+
+use rustc::ty;
 use syntax::ast::*;
+use syntax::print::pprust;
 use syntax::ptr::P;
 
 use c2rust_ast_builder::mk;
+use crate::ast_manip::MutVisitNodes;
 use crate::command::{CommandState, Registry};
-use crate::RefactorCtxt;
-use crate::driver::{self, Phase};
+use crate::driver::{Phase, parse_expr};
 use crate::matcher::{Bindings, BindingType, MatchCtxt, Subst, mut_visit_match_with};
 use crate::transform::Transform;
+use crate::RefactorCtxt;
+
 
 /// # `char_literals` Command
-/// 
-/// Obsolete - the translator now does this automatically.
-/// 
-/// Usage: `char_literals`
-/// 
+///
+/// Usage: `char_literals [typed]`
+///
 /// Replace integer literals cast to `libc::c_char` with actual char literals.
 /// For example, replaces `65 as libc::c_char` with `'A' as libc::c_char`.
-struct CharLits {
+///
+/// With `typed`, instead replace integer literals that stand for ASCII
+/// characters with byte or char literals, where typeck says they're compared
+/// with (`==`, `!=`, `<`, `<=`, `>` or `>=`), matched against, or assigned to a
+/// `u8`, `c_char` or `char`:
+///
+///  * `c == 45` becomes `c == b'-'` when `c` is a `u8`, and
+///    `c == 45 as libc::c_char` becomes `c == b'-' as libc::c_char`;
+///  * `c == 45u8 as char` becomes `c == '-'` when `c` is a `char`;
+///  * the literal and range patterns of a `match` on a `u8`, as in
+///    `48..=57 => ...`, become `b'0'..=b'9' => ...`.  A pattern can't contain
+///    a cast, so a `match c` on a `c_char` becomes `match c as u8` first, if
+///    its patterns are all wildcards and literals or ranges of non-negative
+///    integers, which match the same values either way;
+///  * `c = 44` and `let c: u8 = 44;` become `c = b','` and `let c: u8 = b',';`.
+///
+/// Only printable ASCII characters, tab, newline and carriage return are
+/// rewritten; other values, including 0, stay numeric, and so do literals used
+/// in arithmetic, like the `48` in `c - 48`.
+pub struct CharLiterals {
+    pub typed: bool,
+}
+
+/// If `e` is an integer literal standing for a printable ASCII character, tab, newline or
+/// carriage return, return the character as it's written inside a byte or char literal.
+fn ascii_char_src(e: &Expr) -> Option<String> {
+    let c = match e.kind {
+        ExprKind::Lit(Lit { kind: LitKind::Int(v, _), .. }) if v < 0x80 => v as u8 as char,
+        _ => return None,
+    };
+    match c {
+        // `escape_default` also escapes `"`, which needs no escape within single quotes.
+        '"' => Some(c.to_string()),
+        ' ' | '\t' | '\n' | '\r' => Some(c.escape_default().to_string()),
+        _ if c.is_ascii_graphic() => Some(c.escape_default().to_string()),
+        _ => None,
+    }
+}
+
+/// The byte or char literal to replace `e` with, if `e` is an ASCII integer literal, possibly
+/// cast, whose type is `u8`, `c_char` or `char`.
+fn char_lit_src(cx: &RefactorCtxt, e: &Expr) -> Option<String> {
+    let (c, cast_ty) = match e.kind {
+        ExprKind::Cast(ref inner, ref ty) => (ascii_char_src(inner)?, Some(ty)),
+        _ => (ascii_char_src(e)?, None),
+    };
+    match cx.opt_node_type(e.id)?.kind {
+        ty::TyKind::Uint(UintTy::U8) => Some(format!("b'{}'", c)),
+        ty::TyKind::Int(IntTy::I8) => {
+            let ty = cast_ty.map_or_else(|| "i8".to_owned(), |ty| pprust::ty_to_string(ty));
+            Some(format!("b'{}' as {}", c, ty))
+        }
+        ty::TyKind::Char => Some(format!("'{}'", c)),
+        _ => None,
+    }
+}
+
+/// Replace the literal patterns in `p`, which matches a `u8`, with byte literals.  Returns
+/// whether any were replaced.
+fn byte_lit_pats(cx: &RefactorCtxt, p: &mut P<Pat>) -> bool {
+    let byte_lit = |e: &Expr| {
+        ascii_char_src(e).map(|c| parse_expr(cx.session(), &format!("b'{}'", c)))
+    };
+    match p.kind {
+        PatKind::Or(ref mut pats) => {
+            pats.iter_mut().fold(false, |changed, p| byte_lit_pats(cx, p) || changed)
+        }
+        PatKind::Paren(ref mut p) => byte_lit_pats(cx, p),
+        PatKind::Lit(ref mut e) => match byte_lit(e) {
+            Some(new_e) => {
+                *e = new_e;
+                true
+            }
+            None => false,
+        },
+        // Rewrite both ends or neither, so the range isn't half numeric.
+        PatKind::Range(ref mut lo, ref mut hi, _) => match (byte_lit(lo), byte_lit(hi)) {
+            (Some(new_lo), Some(new_hi)) => {
+                *lo = new_lo;
+                *hi = new_hi;
+                true
+            }
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// Check that `p` only matches wildcards and literals or ranges of non-negative integers, so it
+/// matches the same values of a `c_char` as of the `c_char` cast to `u8`, and binds nothing.
+fn is_nonneg_int_pat(p: &Pat) -> bool {
+    let is_int_lit = |e: &Expr| match e.kind {
+        ExprKind::Lit(Lit { kind: LitKind::Int(..), .. }) => true,
+        _ => false,
+    };
+    match p.kind {
+        PatKind::Wild => true,
+        PatKind::Or(ref pats) => pats.iter().all(|p| is_nonneg_int_pat(p)),
+        PatKind::Paren(ref p) => is_nonneg_int_pat(p),
+        PatKind::Lit(ref e) => is_int_lit(e),
+        PatKind::Range(ref lo, ref hi, _) => is_int_lit(lo) && is_int_lit(hi),
+        _ => false,
+    }
 }
 
-impl Transform for CharLits {
-    fn min_phase(&self) -> Phase { Phase::Phase2 }
+/// Replace integer literals cast to `libc::c_char` with char literals, as in `char_literals`
+/// without `typed`.
+fn cast_char_lits(krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+    let pattern = parse_expr(cx.session(), "__number as libc::c_char");
+    let mut mcx = MatchCtxt::new(st, cx);
+    mcx.set_type("__number", BindingType::Expr);
+
+    mut_visit_match_with(mcx, pattern.clone(), krate, |e, mcx| {
+        let field: &P<Expr> = mcx.bindings.get::<_, P<Expr>>("__number").unwrap();
+        if let ExprKind::Lit(ref l) = field.kind {
+            if let LitKind::Int(i, _) = l.kind {
+                if i < 256 {
+                    let mut bnd = Bindings::new();
+                    bnd.add("__number", mk().lit_expr(i as u8 as char));
+                    *e = pattern.clone().subst(st, cx, &bnd);
+                }
+            }
+        }
+    });
+}
+
+impl Transform for CharLiterals {
     fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        if !self.typed {
+            return cast_char_lits(krate, st, cx);
+        }
 
-        let pattern = driver::parse_expr(cx.session(), "__number as libc::c_char");
-        let mut mcx = MatchCtxt::new(st, cx);
-        mcx.set_type("__number", BindingType::Expr);
-
-        mut_visit_match_with(mcx, pattern.clone(), krate, |e, mcx| {
-            let field: &P<Expr> = mcx.bindings.get::<_, P<Expr>>("__number").unwrap();
-            if let ExprKind::Lit(ref l) = field.kind {
-                if let LitKind::Int(i, _) = l.kind {
-                    if i < 256 {
-                        let mut bnd = Bindings::new();
-                        bnd.add("__number", mk().lit_expr(i as u8 as char));
-                        *e = pattern.clone().subst(st, cx, &bnd);
+        let replace = |e: &mut P<Expr>| {
+            if let Some(src) = char_lit_src(cx, e) {
+                *e = parse_expr(cx.session(), &src);
+            }
+        };
+        let is_u8 = |e: &Expr| match cx.opt_node_type(e.id).map(|ty| &ty.kind) {
+            Some(ty::TyKind::Uint(UintTy::U8)) => true,
+            _ => false,
+        };
+        let is_i8 = |e: &Expr| match cx.opt_node_type(e.id).map(|ty| &ty.kind) {
+            Some(ty::TyKind::Int(IntTy::I8)) => true,
+            _ => false,
+        };
+
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            match e.kind {
+                ExprKind::Binary(op, ref mut l, ref mut r) => match op.node {
+                    BinOpKind::Eq | BinOpKind::Ne | BinOpKind::Lt | BinOpKind::Le |
+                    BinOpKind::Gt | BinOpKind::Ge => {
+                        replace(l);
+                        replace(r);
+                    }
+                    _ => {}
+                },
+                ExprKind::Assign(_, ref mut rhs) => replace(rhs),
+                ExprKind::Match(ref mut scrutinee, ref mut arms) => {
+                    if is_u8(scrutinee) {
+                        for arm in arms {
+                            byte_lit_pats(cx, &mut arm.pat);
+                        }
+                        return;
+                    }
+                    if !is_i8(scrutinee) || !arms.iter().all(|arm| is_nonneg_int_pat(&arm.pat)) {
+                        return;
                     }
+                    let mut pats = arms.iter().map(|arm| arm.pat.clone()).collect::<Vec<_>>();
+                    let changed = pats.iter_mut()
+                        .fold(false, |changed, p| byte_lit_pats(cx, p) || changed);
+                    if !changed {
+                        return;
+                    }
+                    for (arm, pat) in arms.iter_mut().zip(pats) {
+                        arm.pat = pat;
+                    }
+                    let mut cast = parse_expr(cx.session(), "__scrutinee as u8");
+                    if let ExprKind::Cast(ref mut inner, _) = cast.kind {
+                        *inner = scrutinee.clone();
+                    }
+                    *scrutinee = cast;
                 }
+                _ => {}
             }
         });
+
+        MutVisitNodes::visit(krate, |l: &mut P<Local>| {
+            if let Some(ref mut init) = l.init {
+                replace(init);
+            }
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        if self.typed { Phase::Phase3 } else { Phase::Phase2 }
     }
 }
 
+
 pub fn register_commands(reg: &mut Registry) {
     use super::mk;
 
-    reg.register("char_literals", |_args| mk(CharLits{}))
+    reg.register("char_literals", |args| mk(CharLiterals {
+        typed: args.iter().any(|arg| arg == "typed"),
+    }));
 }
@@ -1,14 +1,31 @@
+use std::collections::{HashMap, HashSet};
 use rustc::hir::{self, HirId};
+use rustc::hir::def::{CtorKind, CtorOf, DefKind, Res};
+use rustc::hir::def_id::DefId;
 use rustc::ty::{self, ParamEnv};
+use rustc::ty::adjustment::{Adjust, AutoBorrow, AutoBorrowMutability};
 use rustc_typeck::expr_use_visitor::*;
-use syntax::ast::{Crate, Expr, ExprKind, Lit, LitKind, Stmt, StmtKind};
+use syntax::ast::{BinOpKind, BindingMode, Block, BlockCheckMode, Crate, Expr, ExprKind, Ident};
+use syntax::ast::{Item, ItemKind, Label, Lit, LitIntType, LitKind, Mac, NodeId, Pat, PatKind};
+use syntax::ast::{Stmt, StmtKind};
+use syntax::ast::{Mutability, RangeLimits, UintTy, UnOp};
+use syntax::print::pprust;
 use syntax::ptr::P;
+use syntax::symbol::Symbol;
+use syntax::visit::{self, Visitor};
 
+use crate::ast_manip::{visit_nodes, AstEquiv, MutVisitNodes};
+use crate::ast_manip::fn_edit::mut_visit_fns;
+use crate::ast_manip::lr_expr;
 use crate::command::{CommandState, Registry};
 use crate::context::HirMap;
-use crate::driver::Phase;
-use crate::matcher::{MatchCtxt, Subst, replace_expr, mut_visit_match_with, find_first};
+use crate::driver::{Phase, parse_pat, parse_stmts};
+use crate::matcher::{Bindings, MatchCtxt, Subst, replace_expr, mut_visit_match_with, find_first};
 use crate::transform::Transform;
+use crate::transform::enums::{is_simple_place, lit_value};
+use crate::transform::mem::{classify_buffer, is_zero_lit, strip_casts, Buffer};
+use crate::transform::ptr_loops::is_unsigned_expr;
+use crate::reflect;
 use crate::RefactorCtxt;
 use c2rust_ast_builder::mk;
 
@@ -98,41 +115,18 @@ impl Transform for ReconstructForRange {
                 return;
             }
 
-            let hir_map = cx.hir_map();
-			let while_hir_id = hir_map.node_to_hir_id(orig[1].id);
-			let parent_hir_id = hir_map.get_parent_item(while_hir_id);
             let var_expr = mcx.bindings.get::<_, P<Expr>>("$i")
                 .unwrap().clone();
             let var_hir_id = match_or!([cx.try_resolve_expr_hir(&var_expr)]
                                        Some(hir::def::Res::Local(x)) => x; return);
-            let mut delegate = ForRangeDelegate {
-                hir_map,
-                while_hir_id,
-                parent_hir_id,
-                var_hir_id,
-
-                writes_inside_loop: 0,
-                reads_outside_loop: 0,
-            };
-
-            let tcx = cx.ty_ctxt();
-            let parent_did = match_or!([hir_map.opt_local_def_id(parent_hir_id)]
-                                       Some(x) => x; return);
-			let parent_body_id = match_or!([hir_map.maybe_body_owned_by(parent_hir_id)]
-                                           Some(x) => x; return);
-            let parent_body = hir_map.body(parent_body_id);
-			let tables = tcx.body_tables(parent_body_id);
-            tcx.infer_ctxt().enter(|infcx| {
-                ExprUseVisitor::new(&mut delegate, &infcx, parent_did,
-                                    ParamEnv::empty(), tables)
-                    .consume_body(&parent_body);
-            });
-            assert!(delegate.writes_inside_loop > 0);
+            let (writes_inside_loop, reads_outside_loop) =
+                match_or!([loop_var_uses(cx, orig[1].id, var_hir_id)] Some(x) => x; return);
+            assert!(writes_inside_loop > 0);
             debug!("Loop variable '{:?}' writes:{} reads:{}",
                    var_expr,
-                   delegate.writes_inside_loop,
-                   delegate.reads_outside_loop);
-            if delegate.writes_inside_loop > 1 || delegate.reads_outside_loop > 0 {
+                   writes_inside_loop,
+                   reads_outside_loop);
+            if writes_inside_loop > 1 || reads_outside_loop > 0 {
                 return;
             }
 
@@ -172,6 +166,1201 @@ impl Transform for ReconstructForRange {
     }
 }
 
+
+/// # `for_range_loop` Command
+///
+/// Usage: `for_range_loop`
+///
+/// Replaces the counting loops the translator emits for C `for` loops,
+/// `let mut i = start; while i < end { ...; i += step; }`, with
+/// `for i in start .. end { ...; }`.  The condition may also be `<=`, and the
+/// increment may be written `i = i + step` or `i = i.wrapping_add(step)`.
+/// Non-unit steps become `(start .. end).step_by(step)`.  Downward-counting
+/// loops, with `>` or `>=` and a decrement, become
+/// `(end + 1 ..= start).rev()` or `(end ..= start).rev()`.
+///
+/// The loop is only replaced if the induction variable is written exactly once
+/// inside the loop (by the increment statement), is never read after the loop,
+/// and the increment isn't skipped by a `continue`.  The bound must be a
+/// literal, a constant, or a local that the loop doesn't modify.  The `let` of
+/// the induction variable is removed along with the loop.
+pub struct ForRangeLoop;
+
+impl Transform for ForRangeLoop {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        let mut mcx = MatchCtxt::new(st, cx);
+        let pats = [
+            mcx.parse_stmts(r#"
+                let $lpat:Pat = $start:Expr;
+                $'label:?Ident: while $cond:Expr {
+                    $body:MultiStmt;
+                    $incr:Stmt;
+                }"#),
+            mcx.parse_stmts(r#"
+                let $lpat:Pat: $lty:Ty = $start:Expr;
+                $'label:?Ident: while $cond:Expr {
+                    $body:MultiStmt;
+                    $incr:Stmt;
+                }"#),
+        ];
+
+        let up_conds = [
+            (mcx.parse_expr("$i:Expr < $end:Expr"), true),
+            (mcx.parse_expr("$i:Expr <= $end:Expr"), false),
+        ];
+        let down_conds = [
+            (mcx.parse_expr("$i:Expr > $end:Expr"), true),
+            (mcx.parse_expr("$i:Expr >= $end:Expr"), false),
+        ];
+        let up_incrs = [
+            mcx.parse_expr("$i += $step:Expr"),
+            mcx.parse_expr("$i = $i + $step:Expr"),
+            mcx.parse_expr("$i = $i.wrapping_add($step:Expr)"),
+        ];
+        let down_incrs = [
+            mcx.parse_expr("$i -= $step:Expr"),
+            mcx.parse_expr("$i = $i - $step:Expr"),
+            mcx.parse_expr("$i = $i.wrapping_sub($step:Expr)"),
+        ];
+
+        let up_excl = mcx.parse_expr("$start .. $end");
+        let up_incl = mcx.parse_expr("$start ..= $end");
+        let down_excl = mcx.parse_expr("($end + 1 ..= $start).rev()");
+        let down_incl = mcx.parse_expr("($end ..= $start).rev()");
+        let step_by = mcx.parse_expr("$range.step_by($step as usize)");
+        let repl = mcx.parse_stmts("$'label: for $ipat:Pat in $range:Expr { $body; }");
+
+        for pat in pats.iter() {
+            mut_visit_match_with(mcx.clone(), pat.clone(), krate, |orig, mut mcx| {
+                let ident = match mcx.bindings.get::<_, P<Pat>>("$lpat").unwrap().kind {
+                    PatKind::Ident(BindingMode::ByValue(_), ident, None) => ident,
+                    _ => return,
+                };
+                let var_hir_id = cx.hir_map().node_to_hir_id(
+                    mcx.bindings.get::<_, P<Pat>>("$lpat").unwrap().id);
+
+                let cond = mcx.bindings.get::<_, P<Expr>>("$cond").unwrap().clone();
+                let mut matched = None;
+                for &(conds, up) in &[(&up_conds, true), (&down_conds, false)] {
+                    for &(ref pat, excl) in conds.iter() {
+                        if matched.is_some() {
+                            break;
+                        }
+                        if let Ok(m) = mcx.clone_match(&**pat, &cond) {
+                            matched = Some((m, excl, up));
+                        }
+                    }
+                }
+                let (excl, up) = match matched {
+                    Some((m, excl, up)) => {
+                        mcx = m;
+                        (excl, up)
+                    }
+                    None => return,
+                };
+
+                let var_expr = mcx.bindings.get::<_, P<Expr>>("$i").unwrap().clone();
+                match cx.try_resolve_expr_hir(&var_expr) {
+                    Some(Res::Local(id)) if id == var_hir_id => {}
+                    _ => return,
+                }
+
+                let incr = match mcx.bindings.get::<_, Stmt>("$incr").unwrap().kind {
+                    StmtKind::Semi(ref e) |
+                    StmtKind::Expr(ref e) => e.clone(),
+                    _ => { return; }
+                };
+                let incrs = if up { &up_incrs } else { &down_incrs };
+                match incrs.iter().find_map(|pat| mcx.clone_match(&**pat, &incr).ok()) {
+                    Some(m) => mcx = m,
+                    None => return,
+                }
+
+                let (writes_inside_loop, reads_outside_loop) =
+                    match_or!([loop_var_uses(cx, orig[1].id, var_hir_id)] Some(x) => x; return);
+                debug!("Loop variable '{:?}' writes:{} reads:{}",
+                       ident,
+                       writes_inside_loop,
+                       reads_outside_loop);
+                if writes_inside_loop != 1 || reads_outside_loop > 0 {
+                    return;
+                }
+
+                let end = strip_casts(mcx.bindings.get::<_, P<Expr>>("$end").unwrap());
+                let end_invariant = match end.kind {
+                    ExprKind::Lit(_) => true,
+                    ExprKind::Path(..) => match cx.try_resolve_expr_hir(end) {
+                        Some(Res::Local(id)) => loop_var_uses(cx, orig[1].id, id)
+                            .map_or(false, |(writes, _)| writes == 0),
+                        Some(Res::Def(DefKind::Const, _)) => true,
+                        _ => false,
+                    },
+                    _ => false,
+                };
+                if !end_invariant {
+                    return;
+                }
+
+                if let StmtKind::Expr(ref e) = orig[1].kind {
+                    if let ExprKind::While(_, ref body, ref label) = e.kind {
+                        let mut finder = LoopJumpFinder::new(label.as_ref().map(|l| l.ident));
+                        visit::walk_block(&mut finder, body);
+                        if finder.continues > 0 {
+                            return;
+                        }
+                    }
+                }
+
+                let range = match (up, excl) {
+                    (true, true) => &up_excl,
+                    (true, false) => &up_incl,
+                    (false, true) => &down_excl,
+                    (false, false) => &down_incl,
+                };
+                let mut range = range.clone().subst(st, cx, &mcx.bindings);
+                let step = mcx.bindings.get::<_, P<Expr>>("$step").unwrap();
+                if !is_one_expr(strip_casts(step)) {
+                    let mut bnd = mcx.bindings.clone();
+                    bnd.add("$range", range);
+                    range = step_by.clone().subst(st, cx, &bnd);
+                }
+                mcx.bindings.add("$range", range);
+                mcx.bindings.add("$ipat", mk().span(var_expr.span).ident_pat(ident));
+                *orig = repl.clone().subst(st, cx, &mcx.bindings);
+            });
+        }
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
+/// # `copy_loop_to_slice` Command
+///
+/// Usage: `copy_loop_to_slice`
+///
+/// Replaces element-by-element copy loops,
+/// `let mut i = start; while i < n { dst[i] = src[i]; i += 1 }`, with
+/// `dst[start..n].copy_from_slice(&src[start..n])`, where `dst` and `src` are
+/// arrays or slices.  The source element may also be written `src[i].clone()`.
+/// Elements that aren't `Copy` use `clone_from_slice` instead.
+///
+/// An index may be offset by a constant or an unmodified local, as in
+/// `dst[i + k] = src[i]`, which takes the subslice `dst[start + k..n + k]`.
+/// Loops with any other index, a step other than 1, or a `<=` condition are
+/// left alone.
+///
+/// Since a negative bound would wrap around when converted to `usize`, the
+/// start, end and offsets must each be unsigned, a literal or constant that
+/// isn't negative, or a local that an enclosing `if n >= 0 { ... }` or an
+/// earlier `if n < 0 { return ...; }` in the same block shows isn't negative.
+/// Loops with other bounds are skipped with a warning.
+///
+/// As with `for_range_loop`, the induction variable must not be used after the
+/// loop, and the `let` of it is removed along with the loop.  `dst` and `src`
+/// must be distinct locals or statics, or distinct fields of one; copies
+/// between buffers that may overlap are skipped with a warning.
+pub struct CopyLoopToSlice;
+
+/// The local or static that the place `e` is a part of, if `e` isn't behind a dereference.
+fn place_root(cx: &RefactorCtxt, e: &Expr) -> Option<Res> {
+    match e.kind {
+        ExprKind::Path(..) => match cx.try_resolve_expr_hir(e)? {
+            res @ Res::Local(_) | res @ Res::Def(DefKind::Static, _) => Some(res),
+            _ => None,
+        },
+        ExprKind::Field(ref base, _) | ExprKind::Paren(ref base) => place_root(cx, base),
+        _ => None,
+    }
+}
+
+/// Check if `e` mentions the local `hir_id`.
+fn uses_local(cx: &RefactorCtxt, e: &Expr, hir_id: HirId) -> bool {
+    let mut found = false;
+    visit_nodes(e, |e: &Expr| {
+        found |= cx.try_resolve_expr_hir(e) == Some(Res::Local(hir_id));
+    });
+    found
+}
+
+/// Check if the block `b` modifies the local `hir_id`.
+fn writes_local(cx: &RefactorCtxt, b: &P<Block>, hir_id: HirId) -> bool {
+    let mut found = false;
+    let mut b = b.clone();
+    lr_expr::fold_exprs_with_context(&mut b, |e, ctx| {
+        if ctx == lr_expr::Context::LvalueMut &&
+           cx.try_resolve_expr_hir(e) == Some(Res::Local(hir_id)) {
+            found = true;
+        }
+    });
+    found
+}
+
+/// Add to `locals` the locals that can't be negative when `cond` evaluates to `holds`, from
+/// comparisons such as `n >= 0` or `n > 0` when it holds, and `n < 0` or `n <= 0` when it
+/// doesn't.
+fn nonneg_if(cx: &RefactorCtxt, cond: &Expr, holds: bool, locals: &mut HashSet<HirId>) {
+    match cond.kind {
+        ExprKind::Paren(ref e) => nonneg_if(cx, e, holds, locals),
+        ExprKind::Unary(UnOp::Not, ref e) => nonneg_if(cx, e, !holds, locals),
+        ExprKind::Binary(op, ref l, ref r) => match op.node {
+            BinOpKind::And if holds => {
+                nonneg_if(cx, l, holds, locals);
+                nonneg_if(cx, r, holds, locals);
+            }
+            BinOpKind::Or if !holds => {
+                nonneg_if(cx, l, holds, locals);
+                nonneg_if(cx, r, holds, locals);
+            }
+            BinOpKind::Lt | BinOpKind::Le | BinOpKind::Gt | BinOpKind::Ge => {
+                // Whether `cond` holds when `var` is greater than `value`.
+                let (var, value, greater) = match (lit_value(r), lit_value(l)) {
+                    (Some(v), _) => (l, v, op.node == BinOpKind::Gt || op.node == BinOpKind::Ge),
+                    (None, Some(v)) => (r, v, op.node == BinOpKind::Lt || op.node == BinOpKind::Le),
+                    _ => return,
+                };
+                if greater != holds || value < 0 {
+                    return;
+                }
+                if let Some(Res::Local(id)) = cx.try_resolve_expr_hir(var) {
+                    locals.insert(id);
+                }
+            }
+            _ => {}
+        },
+        _ => {}
+    }
+}
+
+/// Check if the block `b` always ends with a `return`, `break` or `continue`.
+fn ends_in_jump(b: &Block) -> bool {
+    match b.stmts.last().map(|s| &s.kind) {
+        Some(StmtKind::Semi(e)) | Some(StmtKind::Expr(e)) => match e.kind {
+            ExprKind::Ret(..) | ExprKind::Break(..) | ExprKind::Continue(..) => true,
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// Collects, for each statement, the locals that can't be negative there, because an earlier
+/// statement in the same block is `if n < 0 { return ...; }`, or the statement is inside
+/// `if n >= 0 { ... }`, and `n` isn't modified in between.
+struct NonNegGuards<'a, 'tcx: 'a> {
+    cx: &'a RefactorCtxt<'a, 'tcx>,
+    current: HashSet<HirId>,
+    at_stmt: HashMap<NodeId, HashSet<HirId>>,
+}
+
+impl<'a, 'tcx, 'ast> Visitor<'ast> for NonNegGuards<'a, 'tcx> {
+    fn visit_block(&mut self, b: &'ast Block) {
+        let saved = self.current.clone();
+        for s in &b.stmts {
+            let cx = self.cx;
+            let block = mk().block(vec![s.clone()]);
+            self.current.retain(|&id| !writes_local(cx, &block, id));
+            self.at_stmt.insert(s.id, self.current.clone());
+            self.visit_stmt(s);
+
+            let e = match s.kind {
+                StmtKind::Semi(ref e) | StmtKind::Expr(ref e) => e,
+                _ => continue,
+            };
+            if let ExprKind::If(ref cond, ref then, None) = e.kind {
+                if ends_in_jump(then) {
+                    nonneg_if(cx, cond, false, &mut self.current);
+                }
+            }
+        }
+        self.current = saved;
+    }
+
+    fn visit_expr(&mut self, e: &'ast Expr) {
+        let cx = self.cx;
+        match e.kind {
+            ExprKind::If(ref cond, ref then, ref els) => {
+                self.visit_expr(cond);
+                let saved = self.current.clone();
+                nonneg_if(cx, cond, true, &mut self.current);
+                self.visit_block(then);
+                self.current = saved.clone();
+                if let Some(ref els) = *els {
+                    nonneg_if(cx, cond, false, &mut self.current);
+                    self.visit_expr(els);
+                }
+                self.current = saved;
+                return;
+            }
+            // A later iteration may see the local modified further down the body.
+            ExprKind::While(_, ref body, _) |
+            ExprKind::ForLoop(_, _, ref body, _) |
+            ExprKind::Loop(ref body, _) => {
+                let saved = self.current.clone();
+                self.current.retain(|&id| !writes_local(cx, body, id));
+                visit::walk_expr(self, e);
+                self.current = saved;
+                return;
+            }
+            ExprKind::Closure(..) => {
+                let saved = self.current.clone();
+                self.current.clear();
+                visit::walk_expr(self, e);
+                self.current = saved;
+                return;
+            }
+            _ => {}
+        }
+        visit::walk_expr(self, e);
+    }
+
+    fn visit_mac(&mut self, _mac: &'ast Mac) {}
+}
+
+/// The bounds that can be converted to `usize` without wrapping.
+struct NonNegative {
+    /// Constants initialized with a literal that isn't negative.
+    consts: HashSet<DefId>,
+    at_stmt: HashMap<NodeId, HashSet<HirId>>,
+}
+
+impl NonNegative {
+    fn new(cx: &RefactorCtxt, krate: &Crate) -> NonNegative {
+        let mut consts = HashSet::new();
+        visit_nodes(krate, |i: &Item| {
+            if let ItemKind::Const(_, ref init) = i.kind {
+                if lit_value(init).map_or(false, |v| v >= 0) {
+                    consts.insert(cx.node_def_id(i.id));
+                }
+            }
+        });
+        let mut guards = NonNegGuards { cx, current: HashSet::new(), at_stmt: HashMap::new() };
+        visit::walk_crate(&mut guards, krate);
+        NonNegative { consts, at_stmt: guards.at_stmt }
+    }
+
+    /// Check if `e` can't be negative at the statement `stmt_id`: it's unsigned, a literal or
+    /// constant that isn't negative, or a local guarded by a check that it isn't.
+    fn check(&self, cx: &RefactorCtxt, e: &Expr, stmt_id: NodeId) -> bool {
+        if is_unsigned_expr(cx, e) || lit_value(e).map_or(false, |v| v >= 0) {
+            return true;
+        }
+        match cx.try_resolve_expr_hir(e) {
+            Some(Res::Local(id)) => self.at_stmt.get(&stmt_id).map_or(false, |s| s.contains(&id)),
+            Some(Res::Def(DefKind::Const, def_id)) => self.consts.contains(&def_id),
+            _ => false,
+        }
+    }
+}
+
+impl Transform for CopyLoopToSlice {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        let mut mcx = MatchCtxt::new(st, cx);
+        let pats = [
+            mcx.parse_stmts(r#"
+                let $lpat:Pat = $start:Expr;
+                while $i:Expr < $end:Expr {
+                    $lhs:Expr = $rhs:Expr;
+                    $incr:Stmt;
+                }"#),
+            mcx.parse_stmts(r#"
+                let $lpat:Pat: $lty:Ty = $start:Expr;
+                while $i:Expr < $end:Expr {
+                    $lhs:Expr = $rhs:Expr;
+                    $incr:Stmt;
+                }"#),
+        ];
+        let incrs = [
+            mcx.parse_expr("$i += $step:Expr"),
+            mcx.parse_expr("$i = $i + $step:Expr"),
+            mcx.parse_expr("$i = $i.wrapping_add($step:Expr)"),
+        ];
+        let clone_pat = mcx.parse_expr("$e:Expr.clone()");
+        let sum_repl = mcx.parse_expr("$a + $b");
+        let usize_repl = mcx.parse_expr("$e as usize");
+        let prefix_repl = mcx.parse_expr("$buf[..$hi]");
+        let range_repl = mcx.parse_expr("$buf[$lo..$hi]");
+        let copy_repl = mcx.parse_stmts("$dst.copy_from_slice(&$src);");
+        let clone_repl = mcx.parse_stmts("$dst.clone_from_slice(&$src);");
+        let nonneg = NonNegative::new(cx, krate);
+
+        let is_usize = |e: &Expr| match cx.opt_node_type(e.id).map(|ty| &ty.kind) {
+            Some(ty::TyKind::Uint(UintTy::Usize)) => true,
+            _ => false,
+        };
+        // `a + b`, converted to `usize`.
+        let bound = |a: &P<Expr>, b: Option<&P<Expr>>| {
+            let mut bnd = Bindings::new();
+            let e = match b {
+                Some(b) => {
+                    bnd.add("$a", a.clone());
+                    bnd.add("$b", b.clone());
+                    sum_repl.clone().subst(st, cx, &bnd)
+                }
+                None => a.clone(),
+            };
+            if is_usize(a) && b.map_or(true, |b| is_usize(b)) {
+                return e;
+            }
+            let mut bnd = Bindings::new();
+            bnd.add("$e", e);
+            usize_repl.clone().subst(st, cx, &bnd)
+        };
+
+        for pat in pats.iter() {
+            mut_visit_match_with(mcx.clone(), pat.clone(), krate, |orig, mut mcx| {
+                let lpat = mcx.bindings.get::<_, P<Pat>>("$lpat").unwrap();
+                match lpat.kind {
+                    PatKind::Ident(BindingMode::ByValue(_), _, None) => {}
+                    _ => return,
+                }
+                let var_hir_id = cx.hir_map().node_to_hir_id(lpat.id);
+                let var_expr = mcx.bindings.get::<_, P<Expr>>("$i").unwrap();
+                match cx.try_resolve_expr_hir(var_expr) {
+                    Some(Res::Local(id)) if id == var_hir_id => {}
+                    _ => return,
+                }
+
+                let incr = match mcx.bindings.get::<_, Stmt>("$incr").unwrap().kind {
+                    StmtKind::Semi(ref e) | StmtKind::Expr(ref e) => e.clone(),
+                    _ => return,
+                };
+                match incrs.iter().find_map(|pat| mcx.clone_match(&**pat, &incr).ok()) {
+                    Some(m) => mcx = m,
+                    None => return,
+                }
+                if !is_one_expr(strip_casts(mcx.bindings.get::<_, P<Expr>>("$step").unwrap())) {
+                    return;
+                }
+
+                let (writes_inside_loop, reads_outside_loop) =
+                    match_or!([loop_var_uses(cx, orig[1].id, var_hir_id)] Some(x) => x; return);
+                if writes_inside_loop != 1 || reads_outside_loop > 0 {
+                    return;
+                }
+
+                // The start and end must not change between the loop and the copy.
+                let invariant = |e: &P<Expr>| match e.kind {
+                    ExprKind::Lit(_) => true,
+                    ExprKind::Path(..) => match cx.try_resolve_expr_hir(e) {
+                        Some(Res::Local(id)) => loop_var_uses(cx, orig[1].id, id)
+                            .map_or(false, |(writes, _)| writes == 0),
+                        Some(Res::Def(DefKind::Const, _)) => true,
+                        _ => false,
+                    },
+                    _ => false,
+                };
+                let start = strip_casts(mcx.bindings.get::<_, P<Expr>>("$start").unwrap());
+                let end = strip_casts(mcx.bindings.get::<_, P<Expr>>("$end").unwrap());
+                if !invariant(start) || !invariant(end) {
+                    return;
+                }
+                // A negative bound would wrap around when converted to `usize`, so the copy
+                // would panic where the loop did nothing.
+                let loop_id = orig[1].id;
+                let nonneg_bound = |e: &P<Expr>| nonneg.check(cx, e, loop_id);
+                if !nonneg_bound(start) || !nonneg_bound(end) {
+                    warn!("copy_loop_to_slice: skipping loop with a bound that may be negative \
+                           at {}", cx.session().source_map().span_to_string(orig[1].span));
+                    return;
+                }
+
+                // Each side indexes a buffer at `i`, or at `i + k`.
+                let side = |e: &P<Expr>| -> Option<(P<Expr>, Option<P<Expr>>)> {
+                    let (buf, idx) = match_or!([e.kind] ExprKind::Index(ref b, ref i) => (b, i);
+                                               return None);
+                    let idx = strip_casts(idx);
+                    let offset = match idx.kind {
+                        ExprKind::Path(..) if uses_local(cx, idx, var_hir_id) => None,
+                        ExprKind::Binary(op, ref l, ref r) if op.node == BinOpKind::Add => {
+                            let (var, k) =
+                                if uses_local(cx, l, var_hir_id) { (l, r) } else { (r, l) };
+                            let k = strip_casts(k);
+                            match var.kind {
+                                ExprKind::Path(..) if uses_local(cx, var, var_hir_id) => {}
+                                _ => return None,
+                            }
+                            if !invariant(k) || !nonneg_bound(k) {
+                                return None;
+                            }
+                            Some(k.clone())
+                        }
+                        _ => return None,
+                    };
+                    if !is_simple_place(buf) || uses_local(cx, buf, var_hir_id) {
+                        return None;
+                    }
+                    Some((buf.clone(), offset))
+                };
+                let lhs = mcx.bindings.get::<_, P<Expr>>("$lhs").unwrap();
+                let rhs = mcx.bindings.get::<_, P<Expr>>("$rhs").unwrap();
+                let rhs = match mcx.clone_match(&*clone_pat, &**rhs) {
+                    Ok(m) => m.bindings.get::<_, P<Expr>>("$e").unwrap().clone(),
+                    Err(_) => rhs.clone(),
+                };
+                let (dst, dst_offset) = match_or!([side(lhs)] Some(x) => x; return);
+                let (src, src_offset) = match_or!([side(&rhs)] Some(x) => x; return);
+
+                let elem_ty = match (classify_buffer(cx, &dst), classify_buffer(cx, &src)) {
+                    (Buffer::Slice(d), Buffer::Slice(s)) if d == s => d,
+                    _ => return,
+                };
+                let disjoint = match (place_root(cx, &dst), place_root(cx, &src)) {
+                    (Some(d), Some(s)) => d != s || !dst.ast_equiv(&src),
+                    _ => false,
+                };
+                if !disjoint {
+                    warn!("copy_loop_to_slice: skipping copy between buffers that may overlap \
+                           at {}", cx.session().source_map().span_to_string(orig[1].span));
+                    return;
+                }
+
+                let slice = |buf: P<Expr>, offset: Option<P<Expr>>| {
+                    let mut bnd = Bindings::new();
+                    bnd.add("$buf", buf);
+                    bnd.add("$hi", bound(end, offset.as_ref()));
+                    let lo = match (is_zero_lit(start), offset) {
+                        (true, None) => None,
+                        (true, Some(k)) => Some(bound(&k, None)),
+                        (false, k) => Some(bound(start, k.as_ref())),
+                    };
+                    match lo {
+                        Some(lo) => {
+                            bnd.add("$lo", lo);
+                            range_repl.clone().subst(st, cx, &bnd)
+                        }
+                        None => prefix_repl.clone().subst(st, cx, &bnd),
+                    }
+                };
+                let mut bnd = Bindings::new();
+                bnd.add("$dst", slice(dst, dst_offset));
+                bnd.add("$src", slice(src, src_offset));
+                let tcx = cx.ty_ctxt();
+                let repl = if elem_ty.is_copy_modulo_regions(tcx, ParamEnv::empty(), orig[1].span) {
+                    &copy_repl
+                } else {
+                    &clone_repl
+                };
+                *orig = repl.clone().subst(st, cx, &bnd);
+            });
+        }
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
+/// # `index_iter` Command
+///
+/// Usage: `index_iter`
+///
+/// Replaces counting loops whose induction variable is only used to index
+/// arrays, slices or `Vec`s, such as
+/// `for i in 0..self.count { let item = &self.items[i as usize]; ... }`, with
+/// iteration over the elements, as in
+/// `for item in &self.items[..self.count as usize] { ... }`.  The range must
+/// start at 0; if its end is `buf.len()`, all of `buf` is iterated over.
+///
+/// Each `buf[i]` in the body becomes `*x`, or just `x` where autoderef allows,
+/// for an element variable `x` named after the collection (`item` for `items`,
+/// `buf_elem` for `buf`), or after a leading `let x = &buf[i];` in the body,
+/// which is removed.  If any element is modified, the loop iterates with
+/// `&mut` or `iter_mut()`.  A loop indexing two collections iterates over them
+/// together with `zip`, and a loop that uses the induction variable for
+/// anything else as well adds `.enumerate()`, converting the index back to the
+/// original type with `let i = i as T;` if needed.
+///
+/// The end of the range must be a literal, a constant, a local or a field that
+/// the loop doesn't modify, or the length of one of the collections.  As with
+/// `copy_loop_to_slice`, a signed end must also be known not to be negative,
+/// so that it doesn't wrap around when converted to `usize`.  Each collection
+/// must be a local, static or field
+/// that the loop uses only through `buf[i]`, so it can't be resized or
+/// borrowed otherwise while it's iterated over.  Loops that fail these checks
+/// are reported and left alone, as are loops indexing more than two
+/// collections.
+pub struct IndexIter;
+
+/// A collection indexed by the induction variable of a loop.
+struct IndexedBuf {
+    buf: P<Expr>,
+    /// The `buf[i]` expressions in the loop body.
+    sites: HashSet<NodeId>,
+    mutable: bool,
+    /// The index of a leading `let x = &buf[i];` in the body, and `x`.
+    binding: Option<(usize, Ident)>,
+}
+
+/// Check if `e` is an array, slice or `Vec`, possibly behind references.
+fn is_indexable(cx: &RefactorCtxt, e: &Expr) -> bool {
+    let mut ty = match cx.opt_node_type(e.id) {
+        Some(ty) => ty,
+        None => return false,
+    };
+    while let ty::TyKind::Ref(_, inner, _) = ty.kind {
+        ty = inner;
+    }
+    match ty.kind {
+        ty::TyKind::Array(..) | ty::TyKind::Slice(..) => true,
+        ty::TyKind::Adt(def, _) => &*cx.ty_ctxt().item_name(def.did).as_str() == "Vec",
+        _ => false,
+    }
+}
+
+/// Check if the method call receiver `e` is mutably autoborrowed.
+fn has_mut_autoref(cx: &RefactorCtxt, e: &Expr) -> bool {
+    let hir_id = match cx.hir_map().opt_node_to_hir_id(e.id) {
+        Some(x) => x,
+        None => return false,
+    };
+    let hir_expr = match cx.hir_map().find_by_hir_id(hir_id) {
+        Some(hir::Node::Expr(x)) => x,
+        _ => return false,
+    };
+    let parent = cx.hir_map().get_parent_did(hir_id);
+    let tables = cx.ty_ctxt().typeck_tables_of(parent);
+    tables.expr_adjustments(hir_expr).iter().any(|adj| match adj.kind {
+        Adjust::Borrow(AutoBorrow::Ref(_, AutoBorrowMutability::Mutable { .. })) => true,
+        _ => false,
+    })
+}
+
+/// The place `e` and the places it's a field of or dereferences, outermost first.
+fn place_prefixes(e: &P<Expr>) -> Vec<&P<Expr>> {
+    let mut prefixes = vec![e];
+    let mut cur = e;
+    loop {
+        cur = match cur.kind {
+            ExprKind::Field(ref base, _) |
+            ExprKind::Paren(ref base) |
+            ExprKind::Unary(UnOp::Deref, ref base) => base,
+            _ => return prefixes,
+        };
+        prefixes.push(cur);
+    }
+}
+
+/// Check that nothing in `body` uses the place `place` or the places it's
+/// part of, other than through the index expressions in `sites`, or by
+/// reading `place` itself if `reads` is set.  Fields of those places that
+/// aren't part of `place` may be used freely.
+fn place_unused_in(
+    cx: &RefactorCtxt,
+    body: &P<Block>,
+    place: &P<Expr>,
+    sites: &HashSet<NodeId>,
+    reads: bool,
+) -> bool {
+    let prefixes = place_prefixes(place);
+    let prefix_of = |e: &Expr| prefixes.iter().position(|p| p.ast_equiv(e));
+
+    let mut lvalue_mut = HashSet::new();
+    let mut body_copy = body.clone();
+    lr_expr::fold_exprs_with_context(&mut body_copy, |e, ctx| {
+        if ctx == lr_expr::Context::LvalueMut {
+            lvalue_mut.insert(e.id);
+        }
+    });
+    // For each expression that's the base of another, the parent's ID, whether
+    // it's a field access, and whether it's one of the prefixes itself.
+    let mut parents = HashMap::new();
+    let mut receivers = HashSet::new();
+    visit_nodes(&**body, |e: &Expr| match e.kind {
+        ExprKind::Field(ref base, _) |
+        ExprKind::Paren(ref base) |
+        ExprKind::Unary(UnOp::Deref, ref base) |
+        ExprKind::Index(ref base, _) => {
+            let is_field = match e.kind {
+                ExprKind::Field(..) => true,
+                _ => false,
+            };
+            parents.insert(base.id, (e.id, is_field, prefix_of(e).is_some()));
+        }
+        ExprKind::MethodCall(_, ref args) => {
+            receivers.insert(args[0].id);
+        }
+        _ => {}
+    });
+
+    let mut ok = true;
+    visit_nodes(&**body, |e: &Expr| {
+        let k = match prefix_of(e) {
+            Some(k) => k,
+            None => return,
+        };
+        match parents.get(&e.id) {
+            // Uses of the parent are checked on their own, and other fields
+            // and the index sites are fine.
+            Some(&(_, _, true)) |
+            Some(&(_, true, false)) => return,
+            Some(&(parent_id, false, false)) if sites.contains(&parent_id) => return,
+            _ => {}
+        }
+        let read = k == 0 && !lvalue_mut.contains(&e.id) &&
+            !(receivers.contains(&e.id) && has_mut_autoref(cx, e));
+        if !(reads && read) {
+            ok = false;
+        }
+    });
+    ok
+}
+
+/// The name of the element variable for iterating over `buf`.
+fn elem_name(buf: &Expr) -> String {
+    let name = match buf.kind {
+        ExprKind::Path(_, ref path) => path.segments.last().unwrap().ident.to_string(),
+        ExprKind::Field(_, ident) => ident.to_string(),
+        ExprKind::Paren(ref e) | ExprKind::Unary(UnOp::Deref, ref e) => return elem_name(e),
+        _ => "buf".to_owned(),
+    };
+    if name.len() > 1 && name.ends_with('s') && !name.ends_with("ss") {
+        name[..name.len() - 1].to_owned()
+    } else {
+        format!("{}_elem", name)
+    }
+}
+
+impl IndexIter {
+    /// Build the replacement for the loop `e` in statement `stmt_id`, if it's
+    /// an index loop over collections.
+    fn convert(
+        &self,
+        st: &CommandState,
+        cx: &RefactorCtxt,
+        nonneg: &NonNegative,
+        stmt_id: NodeId,
+        e: &P<Expr>,
+    ) -> Option<P<Expr>> {
+        let (pat, range, body, label) = match e.kind {
+            ExprKind::ForLoop(ref pat, ref range, ref body, label) => (pat, range, body, label),
+            _ => return None,
+        };
+        let var_ident = match pat.kind {
+            PatKind::Ident(BindingMode::ByValue(Mutability::Immutable), ident, None) => ident,
+            _ => return None,
+        };
+        let pat_id = pat.id;
+        let var_hir_id = cx.hir_map().opt_node_to_hir_id(pat_id)?;
+        let end = match range.kind {
+            ExprKind::Range(Some(ref start), Some(ref end), RangeLimits::HalfOpen)
+                if is_zero_lit(strip_casts(start)) => end,
+            _ => return None,
+        };
+        let span = cx.session().source_map().span_to_string(e.span);
+
+        // Sort the uses of the induction variable into indexing and other uses.
+        let mut bufs: Vec<IndexedBuf> = vec![];
+        let mut index_uses = 0;
+        visit_nodes(&**body, |e: &Expr| {
+            let (buf, idx) = match e.kind {
+                ExprKind::Index(ref buf, ref idx) => (buf, idx),
+                _ => return,
+            };
+            let idx = strip_casts(idx);
+            match idx.kind {
+                ExprKind::Path(..) if uses_local(cx, idx, var_hir_id) => {}
+                _ => return,
+            }
+            index_uses += 1;
+            if !is_simple_place(buf) || uses_local(cx, buf, var_hir_id) ||
+               !is_indexable(cx, buf) {
+                // Counted as an index use but not as a site, so the loop is rejected.
+                return;
+            }
+            match bufs.iter_mut().find(|b| b.buf.ast_equiv(buf)) {
+                Some(b) => {
+                    b.sites.insert(e.id);
+                }
+                None => bufs.push(IndexedBuf {
+                    buf: buf.clone(),
+                    sites: vec![e.id].into_iter().collect(),
+                    mutable: false,
+                    binding: None,
+                }),
+            }
+        });
+        let num_sites: usize = bufs.iter().map(|b| b.sites.len()).sum();
+        if bufs.is_empty() || bufs.len() > 2 || num_sites != index_uses {
+            return None;
+        }
+        let mut var_uses = 0;
+        visit_nodes(&**body, |e: &Expr| {
+            if let ExprKind::Path(..) = e.kind {
+                if cx.try_resolve_expr_hir(e) == Some(Res::Local(var_hir_id)) {
+                    var_uses += 1;
+                }
+            }
+        });
+        let enumerate = var_uses > index_uses;
+
+        // The bound must be loop-invariant.
+        let end_place = strip_casts(end);
+        let end_invariant = match end_place.kind {
+            ExprKind::Lit(_) => true,
+            ExprKind::Path(..) => match cx.try_resolve_expr_hir(end_place) {
+                Some(Res::Local(id)) => loop_var_uses(cx, stmt_id, id)
+                    .map_or(false, |(writes, _)| writes == 0),
+                Some(Res::Def(DefKind::Const, _)) => true,
+                _ => false,
+            },
+            ExprKind::Field(..) => is_simple_place(end_place) &&
+                place_unused_in(cx, body, end_place, &HashSet::new(), true),
+            // The collections are checked below.
+            ExprKind::MethodCall(ref seg, ref args) if &*seg.ident.as_str() == "len" =>
+                bufs.iter().any(|b| b.buf.ast_equiv(&args[0])),
+            _ => false,
+        };
+        if !end_invariant {
+            warn!("index_iter: the bound of the loop at {} may change inside it; skipping", span);
+            return None;
+        }
+        if !nonneg.check(cx, end_place, stmt_id) {
+            warn!("index_iter: the bound of the loop at {} may be negative; skipping", span);
+            return None;
+        }
+
+        // Each collection must be accessed only through the loop index.
+        let mut lvalue_mut = HashSet::new();
+        let mut body_copy = body.clone();
+        lr_expr::fold_exprs_with_context(&mut body_copy, |e, ctx| {
+            if ctx == lr_expr::Context::LvalueMut {
+                lvalue_mut.insert(e.id);
+            }
+        });
+        let mut mut_receivers = HashSet::new();
+        visit_nodes(&**body, |e: &Expr| {
+            if let ExprKind::MethodCall(_, ref args) = e.kind {
+                let mut recv = &args[0];
+                while let ExprKind::Field(ref base, _) | ExprKind::Paren(ref base) = recv.kind {
+                    recv = base;
+                }
+                if has_mut_autoref(cx, &args[0]) {
+                    mut_receivers.insert(recv.id);
+                }
+            }
+        });
+        for b in &mut bufs {
+            if !place_unused_in(cx, body, &b.buf, &b.sites, false) {
+                warn!("index_iter: `{}` may be resized or borrowed inside the loop at {}; \
+                       skipping", pprust::expr_to_string(&b.buf), span);
+                return None;
+            }
+            b.mutable = b.sites.iter()
+                .any(|id| lvalue_mut.contains(id) || mut_receivers.contains(id));
+        }
+
+        // Name the elements after leading `let x = &buf[i];` statements, or
+        // after the collections.
+        let mut used_names = HashSet::new();
+        visit_nodes(&**body, |e: &Expr| {
+            if let ExprKind::Path(None, ref path) = e.kind {
+                used_names.insert(path.segments[0].ident.name);
+            }
+        });
+        visit_nodes(&**body, |p: &Pat| {
+            if let PatKind::Ident(_, ident, _) = p.kind {
+                used_names.insert(ident.name);
+            }
+        });
+        for (idx, s) in body.stmts.iter().enumerate() {
+            let local = match s.kind {
+                StmtKind::Local(ref l) => l,
+                _ => break,
+            };
+            let ident = match local.pat.kind {
+                PatKind::Ident(BindingMode::ByValue(Mutability::Immutable), ident, None) => ident,
+                _ => continue,
+            };
+            let (mutbl, place) = match local.init.as_ref().map(|init| &init.kind) {
+                Some(&ExprKind::AddrOf(_, mutbl, ref place)) => (mutbl, place),
+                _ => continue,
+            };
+            for b in &mut bufs {
+                if b.binding.is_none() && b.sites.contains(&place.id) &&
+                   (mutbl == Mutability::Mutable) == b.mutable {
+                    b.binding = Some((idx, ident));
+                }
+            }
+        }
+        let names: Vec<Ident> = bufs.iter().map(|b| match b.binding {
+            Some((_, ident)) => ident,
+            None => {
+                let mut name = elem_name(&b.buf);
+                while used_names.contains(&Symbol::intern(&name)) {
+                    name.push('_');
+                }
+                used_names.insert(Symbol::intern(&name));
+                Ident::from_str(&name)
+            }
+        }).collect();
+
+        // Build the iterator.
+        let mut bnd = Bindings::new();
+        bnd.add("$end", end.clone());
+        let n = match cx.opt_node_type(end.id).map(|ty| &ty.kind) {
+            Some(ty::TyKind::Uint(UintTy::Usize)) => "$end",
+            _ => "$end as usize",
+        };
+        let mut mcx = MatchCtxt::new(st, cx);
+        let len_pat = mcx.parse_expr("$buf:Expr.len()");
+        // The part of each collection to iterate over, to borrow and to call
+        // `iter` on.  Borrowing all of `buf` takes `buf[..]`, in case `buf` is
+        // itself a reference.
+        let slices: Vec<(String, String)> = bufs.iter().enumerate().map(|(k, b)| {
+            let var = format!("$buf{}", k);
+            bnd.add(&*var, b.buf.clone());
+            let covers = mcx.clone_match(&*len_pat, &**end_place).map_or(false, |m| {
+                m.bindings.get::<_, P<Expr>>("$buf").unwrap().ast_equiv(&b.buf)
+            });
+            if covers {
+                (format!("{}[..]", var), var)
+            } else {
+                let slice = format!("{}[..{}]", var, n);
+                (slice.clone(), slice)
+            }
+        }).collect();
+        let by_ref = |k: usize| {
+            let mutbl = if bufs[k].mutable { "mut " } else { "" };
+            format!("&{}{}", mutbl, slices[k].0)
+        };
+        let (mut iter, mut pat) = if bufs.len() == 1 && !enumerate {
+            (by_ref(0), names[0].to_string())
+        } else {
+            let method = if bufs[0].mutable { "iter_mut" } else { "iter" };
+            (format!("{}.{}()", slices[0].1, method), names[0].to_string())
+        };
+        if bufs.len() == 2 {
+            iter = format!("{}.zip({})", iter, by_ref(1));
+            pat = format!("({}, {})", pat, names[1]);
+        }
+        if enumerate {
+            iter = format!("{}.enumerate()", iter);
+            pat = format!("({}, {})", var_ident, pat);
+        }
+        let iter = mcx.parse_expr(&iter).subst(st, cx, &bnd);
+        let pat = parse_pat(cx.session(), &pat);
+
+        // Rewrite the body: remove the `let`s the element names came from, and
+        // replace `buf[i]` with the element.
+        let mut new_body = body.clone();
+        let mut removed: Vec<usize> = bufs.iter()
+            .filter_map(|b| b.binding.map(|(idx, _)| idx))
+            .collect();
+        removed.sort();
+        for &idx in removed.iter().rev() {
+            new_body.stmts.remove(idx);
+        }
+        let sites: HashMap<NodeId, Ident> = bufs.iter().zip(&names)
+            .flat_map(|(b, &name)| b.sites.iter().map(move |&id| (id, name)))
+            .collect();
+        MutVisitNodes::visit(&mut new_body, |e: &mut P<Expr>| {
+            if let Some(&name) = sites.get(&e.id) {
+                *e = mk().unary_expr("*", mk().ident_expr(name));
+            }
+        });
+
+        // Let autoderef take care of `(*x).f` and `(*x).m()`, and turn `&*x`
+        // into `x` for shared elements.
+        let elem_of = |e: &Expr| match e.kind {
+            ExprKind::Unary(UnOp::Deref, ref inner) => match inner.kind {
+                ExprKind::Path(None, ref path) if path.segments.len() == 1 &&
+                    names.contains(&path.segments[0].ident) => Some(path.segments[0].ident),
+                _ => None,
+            },
+            _ => None,
+        };
+        let shared: Vec<Ident> = bufs.iter().zip(&names)
+            .filter(|&(b, _)| !b.mutable)
+            .map(|(_, &name)| name)
+            .collect();
+        MutVisitNodes::visit(&mut new_body, |e: &mut P<Expr>| {
+            let base = match e.kind {
+                ExprKind::Field(ref mut base, _) => base,
+                ExprKind::MethodCall(_, ref mut args) => &mut args[0],
+                ExprKind::AddrOf(_, Mutability::Immutable, ref place) => {
+                    match elem_of(place) {
+                        Some(name) if shared.contains(&name) => *e = mk().ident_expr(name),
+                        _ => {}
+                    }
+                    return;
+                }
+                _ => return,
+            };
+            if let Some(name) = elem_of(base) {
+                *base = mk().ident_expr(name);
+            }
+        });
+
+        if enumerate {
+            let var_ty = cx.opt_node_type(pat_id)?;
+            match var_ty.kind {
+                ty::TyKind::Uint(UintTy::Usize) => {}
+                _ => {
+                    let var_ty = reflect::reflect_tcx_ty(cx.ty_ctxt(), var_ty);
+                    let src = format!("let {} = {} as {};",
+                                      var_ident, var_ident, pprust::ty_to_string(&var_ty));
+                    for (k, s) in parse_stmts(cx.session(), &src).into_iter().enumerate() {
+                        new_body.stmts.insert(k, s);
+                    }
+                }
+            }
+        }
+
+        let mut new_loop = e.clone();
+        new_loop.kind = ExprKind::ForLoop(pat, iter, new_body, label);
+        Some(new_loop)
+    }
+}
+
+impl Transform for IndexIter {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        let nonneg = NonNegative::new(cx, krate);
+        MutVisitNodes::visit(krate, |b: &mut P<Block>| {
+            for stmt in &mut b.stmts {
+                let stmt_id = stmt.id;
+                match stmt.kind {
+                    StmtKind::Expr(ref mut e) | StmtKind::Semi(ref mut e) => {
+                        if let Some(new_loop) = self.convert(st, cx, &nonneg, stmt_id, e) {
+                            *e = new_loop;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
+/// # `canonicalize_do_while` Command
+///
+/// Usage: `canonicalize_do_while [first_flag]`
+///
+/// Recognizes the loops the translator emits for C `do { ... } while (cond);`
+/// loops, `loop { ...; if !cond { break; } }`, where the final check is the
+/// only `break` that targets the loop and no `continue` targets it.
+///
+/// A `do`-`while` loop may only become a `while` loop when that doesn't drop
+/// the unconditional first iteration, so by default only loops with an empty
+/// body are rewritten, to `while cond {}`.  With `first_flag`, the others
+/// become
+///
+///     {
+///         let mut do_while_first = true;
+///         while do_while_first || cond {
+///             do_while_first = false;
+///             ...;
+///         }
+///     }
+///
+/// Loop labels are kept.
+pub struct CanonicalizeDoWhile {
+    pub first_flag: bool,
+}
+
+impl Transform for CanonicalizeDoWhile {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        let mut mcx = MatchCtxt::new(st, cx);
+        let pats = [
+            mcx.parse_expr(r#"
+                $'label:?Ident: loop {
+                    $body:MultiStmt;
+                    if !($cond:Expr) {
+                        break;
+                    }
+                }"#),
+            mcx.parse_expr(r#"
+                $'label:?Ident: loop {
+                    $body:MultiStmt;
+                    if !($cond:Expr) {
+                        break $'label;
+                    }
+                }"#),
+        ];
+        let while_repl = mcx.parse_expr("$'label: while $cond {}");
+        let flag_repl = mcx.parse_expr(r#"
+            {
+                let mut do_while_first = true;
+                $'label: while do_while_first || $cond {
+                    do_while_first = false;
+                    $body;
+                }
+            }"#);
+
+        for pat in pats.iter() {
+            mut_visit_match_with(mcx.clone(), pat.clone(), krate, |orig, mcx| {
+                let label = mcx.bindings.get_opt::<_, Ident>("$'label").and_then(|l| l.cloned());
+                let body = mcx.bindings.get::<_, Vec<Stmt>>("$body").unwrap();
+
+                let mut finder = LoopJumpFinder::new(label);
+                for s in body {
+                    visit::walk_stmt(&mut finder, s);
+                }
+                if finder.breaks > 0 || finder.continues > 0 {
+                    return;
+                }
+
+                let repl = if body.is_empty() {
+                    &while_repl
+                } else if self.first_flag {
+                    &flag_repl
+                } else {
+                    return;
+                };
+                *orig = repl.clone().subst(st, cx, &mcx.bindings);
+            });
+        }
+    }
+}
+
+/// Counts the `break`s and `continue`s that target the loop whose body is being visited.
+pub(super) struct LoopJumpFinder {
+    label: Option<Ident>,
+    depth: usize,
+    pub(super) breaks: usize,
+    pub(super) continues: usize,
+}
+
+impl LoopJumpFinder {
+    pub(super) fn new(label: Option<Ident>) -> LoopJumpFinder {
+        LoopJumpFinder { label, depth: 0, breaks: 0, continues: 0 }
+    }
+
+    fn targets_loop(&self, label: &Option<Label>) -> bool {
+        match *label {
+            Some(ref l) => Some(l.ident) == self.label,
+            None => self.depth == 0,
+        }
+    }
+}
+
+impl<'ast> Visitor<'ast> for LoopJumpFinder {
+    fn visit_expr(&mut self, e: &'ast Expr) {
+        match e.kind {
+            ExprKind::Break(ref label, _) if self.targets_loop(label) => self.breaks += 1,
+            ExprKind::Continue(ref label) if self.targets_loop(label) => self.continues += 1,
+            ExprKind::While(..) | ExprKind::ForLoop(..) | ExprKind::Loop(..) => {
+                self.depth += 1;
+                visit::walk_expr(self, e);
+                self.depth -= 1;
+                return;
+            }
+            ExprKind::Closure(..) => return,
+            _ => {}
+        }
+        visit::walk_expr(self, e);
+    }
+
+    fn visit_mac(&mut self, _mac: &'ast Mac) {}
+}
+
 fn is_one_expr(e: &Expr) -> bool {
     match e.kind {
         ExprKind::Lit(ref l) => is_one_lit(l),
@@ -186,6 +1375,39 @@ fn is_one_lit(l: &Lit) -> bool {
     }
 }
 
+/// Count the writes to the local `var_hir_id` inside the loop statement `loop_id`, and the reads
+/// of it outside the loop but within the enclosing function.
+fn loop_var_uses(
+    cx: &RefactorCtxt,
+    loop_id: NodeId,
+    var_hir_id: HirId,
+) -> Option<(usize, usize)> {
+    let hir_map = cx.hir_map();
+    let while_hir_id = hir_map.node_to_hir_id(loop_id);
+    let parent_hir_id = hir_map.get_parent_item(while_hir_id);
+    let mut delegate = ForRangeDelegate {
+        hir_map,
+        while_hir_id,
+        parent_hir_id,
+        var_hir_id,
+
+        writes_inside_loop: 0,
+        reads_outside_loop: 0,
+    };
+
+    let tcx = cx.ty_ctxt();
+    let parent_did = hir_map.opt_local_def_id(parent_hir_id)?;
+    let parent_body_id = hir_map.maybe_body_owned_by(parent_hir_id)?;
+    let parent_body = hir_map.body(parent_body_id);
+    let tables = tcx.body_tables(parent_body_id);
+    tcx.infer_ctxt().enter(|infcx| {
+        ExprUseVisitor::new(&mut delegate, &infcx, parent_did,
+                            ParamEnv::empty(), tables)
+            .consume_body(&parent_body);
+    });
+    Some((delegate.writes_inside_loop, delegate.reads_outside_loop))
+}
+
 struct ForRangeDelegate<'a, 'hir: 'a> {
     hir_map: HirMap<'a, 'hir>,
     while_hir_id: HirId,
@@ -314,10 +1536,285 @@ impl Transform for RemoveUnusedLabels {
 }
 
 
+/// # `remove_trailing_return` Command
+///
+/// Usage: `remove_trailing_return`
+///
+/// Turns `return e;` at the end of a function body into the tail expression
+/// `e`, and removes `return;` at the end of functions returning `()`.  Returns
+/// elsewhere in the body are left alone.
+///
+/// Before that, single-iteration wrappers such as `'a: loop { ...; break 'a; }`
+/// and `'a: { ...; break 'a; }` become ordinary blocks, as long as the final
+/// `break` is the only `break` or `continue` that targets them.  A block that
+/// ends a function body is spliced into the body, which exposes a `return` at
+/// its end.
+pub struct RemoveTrailingReturn;
+
+/// Check if `e` is a `break` without a value that targets the labeled block or loop `label`.
+fn is_break_to(e: &Expr, label: Option<Label>, is_loop: bool) -> bool {
+    match e.kind {
+        ExprKind::Break(Some(ref l), None) => label.map(|l| l.ident) == Some(l.ident),
+        ExprKind::Break(None, None) => is_loop,
+        _ => false,
+    }
+}
+
+/// If `e` is a single-iteration loop or a labeled block that nothing jumps out of early, return
+/// its statements without the final `break`.
+fn collapse_wrapper(e: &Expr) -> Option<Vec<Stmt>> {
+    let (body, label, is_loop) = match e.kind {
+        ExprKind::Loop(ref body, label) => (body, label, true),
+        ExprKind::Block(ref body, Some(label)) => (body, Some(label), false),
+        _ => return None,
+    };
+    if body.rules != BlockCheckMode::Default {
+        return None;
+    }
+
+    let mut stmts = body.stmts.clone();
+    let ends_with_break = match stmts.last().map(|s| &s.kind) {
+        Some(StmtKind::Semi(e)) | Some(StmtKind::Expr(e)) => is_break_to(e, label, is_loop),
+        _ => false,
+    };
+    if ends_with_break {
+        stmts.pop();
+    } else if is_loop {
+        return None;
+    }
+
+    let mut finder = LoopJumpFinder::new(label.map(|l| l.ident));
+    for s in &stmts {
+        visit::walk_stmt(&mut finder, s);
+    }
+    if finder.breaks > 0 || finder.continues > 0 {
+        return None;
+    }
+    Some(stmts)
+}
+
+/// Remove the `return` at the end of the function body `b`, splicing in any ordinary block that
+/// ends the body first.
+fn remove_trailing_return(b: &mut Block) {
+    loop {
+        let stmts = match b.stmts.last().map(|s| &s.kind) {
+            Some(StmtKind::Semi(e)) | Some(StmtKind::Expr(e)) => match e.kind {
+                ExprKind::Block(ref inner, None) if inner.rules == BlockCheckMode::Default => {
+                    inner.stmts.clone()
+                }
+                _ => break,
+            },
+            _ => break,
+        };
+        b.stmts.pop();
+        b.stmts.extend(stmts);
+    }
+
+    let ret = match b.stmts.last().map(|s| &s.kind) {
+        Some(StmtKind::Semi(e)) | Some(StmtKind::Expr(e)) => match e.kind {
+            ExprKind::Ret(ref val) => val.clone(),
+            _ => return,
+        },
+        _ => return,
+    };
+    b.stmts.pop();
+    if let Some(val) = ret {
+        b.stmts.push(mk().expr_stmt(val));
+    }
+}
+
+impl Transform for RemoveTrailingReturn {
+    fn transform(&self, krate: &mut Crate, _st: &CommandState, _cx: &RefactorCtxt) {
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            if let Some(stmts) = collapse_wrapper(e) {
+                *e = mk().block_expr(mk().block(stmts));
+            }
+        });
+
+        mut_visit_fns(krate, |fl| {
+            if let Some(ref mut block) = fl.block {
+                remove_trailing_return(block);
+            }
+        });
+    }
+}
+
+/// # `if_chain_to_match` Command
+///
+/// Usage: `if_chain_to_match [min_arms=N]`
+///
+/// Turns `if`-`else if` chains that compare the same side-effect-free place
+/// against literals into a `match`:
+///
+/// ```ignore
+///     if x == 1 {
+///         a();
+///     } else if x == 2 || x == 3 {
+///         b();
+///     } else {
+///         c();
+///     }
+/// ```
+///
+/// becomes
+///
+/// ```ignore
+///     match x {
+///         1 => { a(); }
+///         2 | 3 => { b(); }
+///         _ => { c(); }
+///     }
+/// ```
+///
+/// Each condition must be an `==` comparison, or an `||` of them, against an
+/// integer, character or byte literal, or a fieldless enum variant.  The arms
+/// keep the order of the chain, and the trailing `else`, if any, becomes the `_`
+/// arm.  Chains with other conditions or with different scrutinees are left
+/// alone.  Only chains that produce at least `N` arms, counting the `_` arm, are
+/// rewritten (default 3).
+pub struct IfChainToMatch {
+    pub min_arms: usize,
+}
+
+/// If `e` can be used as a pattern matching the value it evaluates to, return the pattern's
+/// source.
+fn pat_src(cx: &RefactorCtxt, e: &P<Expr>) -> Option<String> {
+    let inner = strip_casts(e);
+    // A cast literal may only keep its value as a pattern if its type isn't spelled out.
+    let is_cast = !std::ptr::eq(inner, e);
+    let lit = match inner.kind {
+        ExprKind::Unary(UnOp::Neg, ref lit) => &**lit,
+        _ => &**inner,
+    };
+    let ok = match lit.kind {
+        ExprKind::Lit(ref l) => match l.kind {
+            LitKind::Int(_, LitIntType::Unsuffixed) => true,
+            LitKind::Int(..) | LitKind::Char(_) | LitKind::Byte(_) => !is_cast,
+            _ => false,
+        },
+        ExprKind::Path(..) if !is_cast && std::ptr::eq(lit, &**inner) => {
+            matches!([cx.try_resolve_expr_hir(lit)]
+                     Some(Res::Def(DefKind::Ctor(CtorOf::Variant, CtorKind::Const), _)))
+        }
+        _ => false,
+    };
+    if ok {
+        Some(pprust::expr_to_string(inner))
+    } else {
+        None
+    }
+}
+
+/// Split the condition `cond` into the place it compares and the patterns it compares it against.
+fn cond_pats<'a>(cx: &RefactorCtxt, cond: &'a Expr) -> Option<(&'a P<Expr>, Vec<String>)> {
+    match cond.kind {
+        ExprKind::Paren(ref inner) => cond_pats(cx, inner),
+        ExprKind::Binary(op, ref lhs, ref rhs) if op.node == BinOpKind::Or => {
+            let (place, mut pats) = cond_pats(cx, lhs)?;
+            let (other, more) = cond_pats(cx, rhs)?;
+            if !place.ast_equiv(other) {
+                return None;
+            }
+            pats.extend(more);
+            Some((place, pats))
+        }
+        ExprKind::Binary(op, ref lhs, ref rhs) if op.node == BinOpKind::Eq => {
+            if is_simple_place(lhs) {
+                if let Some(pat) = pat_src(cx, rhs) {
+                    return Some((lhs, vec![pat]));
+                }
+            }
+            if is_simple_place(rhs) {
+                if let Some(pat) = pat_src(cx, lhs) {
+                    return Some((rhs, vec![pat]));
+                }
+            }
+            None
+        }
+        _ => None,
+    }
+}
+
+impl Transform for IfChainToMatch {
+    fn transform(&self, krate: &mut Crate, _st: &CommandState, cx: &RefactorCtxt) {
+        // The `else if` parts of chains that weren't rewritten, which mustn't be rewritten on
+        // their own, as `else match` isn't valid syntax.
+        let mut inner_ifs = HashSet::new();
+
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            if inner_ifs.contains(&e.id) {
+                return;
+            }
+            match e.kind {
+                ExprKind::If(..) => {}
+                _ => return,
+            }
+
+            let mut place = None;
+            let mut arms = Vec::new();
+            let mut cur: &Expr = &**e;
+            let mut ok = true;
+            loop {
+                match cur.kind {
+                    ExprKind::If(ref cond, ref then, ref els) => {
+                        if cur.id != e.id {
+                            inner_ifs.insert(cur.id);
+                        }
+                        match cond_pats(cx, cond) {
+                            Some((p, pats)) if place.map_or(true, |q: &P<Expr>| q.ast_equiv(p)) => {
+                                place = Some(p);
+                                let pat = parse_pat(cx.session(), &pats.join(" | "));
+                                arms.push(mk().arm(pat, None, mk().block_expr(then.clone())));
+                            }
+                            _ => ok = false,
+                        }
+                        match *els {
+                            Some(ref els) => cur = &**els,
+                            None => {
+                                let body = mk().block_expr(mk().block(Vec::<Stmt>::new()));
+                                arms.push(mk().arm(mk().wild_pat(), None, body));
+                                break;
+                            }
+                        }
+                    }
+                    _ => {
+                        arms.push(mk().arm(mk().wild_pat(), None, P(cur.clone())));
+                        break;
+                    }
+                }
+            }
+            if !ok || arms.len() < self.min_arms {
+                return;
+            }
+
+            let place = place.unwrap().clone();
+            *e = mk().match_expr(place, arms);
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
 pub fn register_commands(reg: &mut Registry) {
     use super::mk;
 
     reg.register("reconstruct_while", |_args| mk(ReconstructWhile));
     reg.register("reconstruct_for_range", |_args| mk(ReconstructForRange));
+    reg.register("for_range_loop", |_args| mk(ForRangeLoop));
+    reg.register("copy_loop_to_slice", |_args| mk(CopyLoopToSlice));
+    reg.register("index_iter", |_args| mk(IndexIter));
+    reg.register("canonicalize_do_while", |args| mk(CanonicalizeDoWhile {
+        first_flag: args.iter().any(|arg| arg == "first_flag"),
+    }));
     reg.register("remove_unused_labels", |_args| mk(RemoveUnusedLabels));
+    reg.register("remove_trailing_return", |_args| mk(RemoveTrailingReturn));
+    reg.register("if_chain_to_match", |args| mk(IfChainToMatch {
+        min_arms: args.iter()
+            .filter(|arg| arg.starts_with("min_arms="))
+            .map(|arg| arg["min_arms=".len()..].parse().unwrap())
+            .next()
+            .unwrap_or(3),
+    }));
 }
@@ -0,0 +1,217 @@
+//! Transform that replaces C `<ctype.h>` calls with the ASCII methods of `u8` and `char`.
+
+use rustc::ty::{self, TyKind};
+use syntax::ast::*;
+use syntax::print::pprust;
+use syntax::ptr::P;
+
+use crate::ast_manip::MutVisitNodes;
+use crate::command::{CommandState, Registry};
+use crate::driver::{Phase, parse_expr};
+use crate::matcher::{Bindings, MatchCtxt, Subst};
+use crate::transform::Transform;
+use crate::transform::enums::lit_value;
+use crate::RefactorCtxt;
+
+
+/// # `ctype_to_methods` Command
+///
+/// Usage: `ctype_to_methods`
+///
+/// Replace calls to the `extern` ctype functions with the ASCII methods of `u8`
+/// and `char`, when the argument is known to fit in a `u8`:
+///
+///  * `isdigit(c) != 0` becomes `c.is_ascii_digit()`, and `isdigit(c) == 0`
+///    becomes `!c.is_ascii_digit()`.  Elsewhere, the result is cast back to an
+///    integer, as in `c.is_ascii_digit() as libc::c_int`.  `isalpha`,
+///    `isalnum`, `isupper` and `islower` work the same way.
+///  * `isspace(c)` becomes `matches!(c, b'\t'..=b'\r' | b' ')`, since
+///    `is_ascii_whitespace` doesn't accept `'\x0b'`, and is otherwise handled
+///    like the other predicates.
+///  * `toupper(c)` becomes `c.to_ascii_uppercase() as libc::c_int`, and
+///    `toupper(c) as T` becomes `c.to_ascii_uppercase() as T`, dropping the
+///    cast when `T` is already the type of the result.  `tolower` works the same
+///    way.
+///
+/// The argument must be a `u8`, `c_char` or `char` cast to an integer, like
+/// `c as libc::c_int`, or an integer literal from 0 to 255; a `c_char`
+/// argument is converted with `c as u8`.  Calls with any other argument, such
+/// as the result of `getc`, which may be `EOF`, are left alone with a warning.
+pub struct CtypeToMethods;
+
+/// The ctype functions, and the methods that replace them.  `isspace` is replaced with a
+/// `matches!` instead.
+static CTYPE_FNS: &[(&str, &str)] = &[
+    ("isdigit", "is_ascii_digit"),
+    ("isalpha", "is_ascii_alphabetic"),
+    ("isalnum", "is_ascii_alphanumeric"),
+    ("isspace", "is_ascii_whitespace"),
+    ("isupper", "is_ascii_uppercase"),
+    ("islower", "is_ascii_lowercase"),
+    ("toupper", "to_ascii_uppercase"),
+    ("tolower", "to_ascii_lowercase"),
+];
+
+/// A call to a ctype function.
+struct CtypeCall<'a> {
+    name: &'static str,
+    method: &'static str,
+    arg: &'a P<Expr>,
+}
+
+impl<'a> CtypeCall<'a> {
+    fn is_predicate(&self) -> bool {
+        self.name.starts_with("is")
+    }
+}
+
+fn ctype_call<'a>(cx: &RefactorCtxt, e: &'a Expr) -> Option<CtypeCall<'a>> {
+    let (func, args) = match_or!([e.kind] ExprKind::Call(ref f, ref a) => (f, a); return None);
+    let path = match_or!([func.kind] ExprKind::Path(None, ref p) => p; return None);
+    let seg = path.segments.last()?;
+    let &(name, method) = CTYPE_FNS.iter().find(|&&(name, _)| &*seg.ident.as_str() == name)?;
+    if args.len() != 1 || !cx.ty_ctxt().is_foreign_item(cx.opt_callee(e)?) {
+        return None;
+    }
+    Some(CtypeCall { name, method, arg: &args[0] })
+}
+
+fn is_u8(ty: ty::Ty) -> bool {
+    match ty.kind {
+        TyKind::Uint(UintTy::U8) => true,
+        _ => false,
+    }
+}
+
+/// How to get the receiver of the replacement method from the argument of a ctype call.
+enum Receiver<'a> {
+    /// The argument is a cast of this `u8` or `char`.
+    Direct(&'a P<Expr>),
+    /// The argument needs a cast to `u8`.
+    Byte(&'a P<Expr>),
+}
+
+fn receiver<'a>(cx: &RefactorCtxt, arg: &'a P<Expr>) -> Option<Receiver<'a>> {
+    if let Some(i) = lit_value(arg) {
+        return if 0 <= i && i <= 255 { Some(Receiver::Byte(arg)) } else { None };
+    }
+    let inner = match_or!([arg.kind] ExprKind::Cast(ref inner, _) => inner; return None);
+    match cx.opt_node_type(inner.id)?.kind {
+        TyKind::Uint(UintTy::U8) | TyKind::Char => Some(Receiver::Direct(inner)),
+        TyKind::Int(IntTy::I8) => Some(Receiver::Byte(inner)),
+        _ => None,
+    }
+}
+
+impl Transform for CtypeToMethods {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        let mut mcx = MatchCtxt::new(st, cx);
+        let byte_repl = mcx.parse_expr("$c as u8");
+        let int_repl = mcx.parse_expr("$e as libc::c_int");
+        let cast_repl = mcx.parse_expr("$e as $t");
+        let not_repl = mcx.parse_expr("!$e");
+        let method_repls = CTYPE_FNS.iter()
+            .map(|&(_, method)| (method, mcx.parse_expr(&format!("$c.{}()", method))))
+            .collect::<Vec<_>>();
+
+        // The method call replacing `call`, and whether its receiver is a `u8`.
+        let method_call = |call: &CtypeCall| -> Option<(P<Expr>, bool)> {
+            let mut bnd = Bindings::new();
+            let is_byte = match receiver(cx, call.arg)? {
+                Receiver::Direct(c) => {
+                    bnd.add("$c", c.clone());
+                    cx.opt_node_type(c.id).map_or(false, is_u8)
+                }
+                Receiver::Byte(c) => {
+                    let mut byte_bnd = Bindings::new();
+                    byte_bnd.add("$c", c.clone());
+                    bnd.add("$c", byte_repl.clone().subst(st, cx, &byte_bnd));
+                    true
+                }
+            };
+            if call.name == "isspace" {
+                let c = bnd.get::<_, P<Expr>>("$c").unwrap();
+                let (lo, hi, space) = if is_byte {
+                    ("b'\\t'", "b'\\r'", "b' '")
+                } else {
+                    ("'\\t'", "'\\r'", "' '")
+                };
+                let src = format!("matches!({}, {}..={} | {})",
+                                  pprust::expr_to_string(c), lo, hi, space);
+                return Some((parse_expr(cx.session(), &src), is_byte));
+            }
+            let repl = &method_repls.iter().find(|&&(m, _)| m == call.method).unwrap().1;
+            Some((repl.clone().subst(st, cx, &bnd), is_byte))
+        };
+        let wrap = |repl: &P<Expr>, e: P<Expr>| {
+            let mut bnd = Bindings::new();
+            bnd.add("$e", e);
+            repl.clone().subst(st, cx, &bnd)
+        };
+
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            let id = e.id;
+            let new_e = match e.kind {
+                // A predicate tested against zero becomes a `bool`.
+                ExprKind::Binary(op, ref l, ref r)
+                        if op.node == BinOpKind::Eq || op.node == BinOpKind::Ne => {
+                    let call = match (ctype_call(cx, l), ctype_call(cx, r)) {
+                        (Some(call), None) if lit_value(r) == Some(0) => call,
+                        (None, Some(call)) if lit_value(l) == Some(0) => call,
+                        _ => return,
+                    };
+                    if !call.is_predicate() {
+                        return;
+                    }
+                    let m = match_or!([method_call(&call)] Some((m, _)) => m; return);
+                    if op.node == BinOpKind::Eq { wrap(&not_repl, m) } else { m }
+                }
+
+                // A cast of the result of `toupper` or `tolower` replaces the cast to `c_int`.
+                ExprKind::Cast(ref inner, ref ty) => {
+                    let call = match_or!([ctype_call(cx, inner)] Some(x) => x; return);
+                    if call.is_predicate() {
+                        return;
+                    }
+                    let (m, is_byte) = match_or!([method_call(&call)] Some(x) => x; return);
+                    let cast_ty = cx.opt_node_type(id);
+                    if is_byte && cast_ty.map_or(false, is_u8) {
+                        m
+                    } else {
+                        let mut bnd = Bindings::new();
+                        bnd.add("$e", m);
+                        bnd.add("$t", ty.clone());
+                        cast_repl.clone().subst(st, cx, &bnd)
+                    }
+                }
+
+                ExprKind::Call(..) => {
+                    let call = match_or!([ctype_call(cx, e)] Some(x) => x; return);
+                    match method_call(&call) {
+                        Some((m, _)) => wrap(&int_repl, m),
+                        None => {
+                            warn!("ctype_to_methods: skipping `{}` call whose argument may not \
+                                   fit in a `u8` (such as `EOF`) at {}", call.name,
+                                  cx.session().source_map().span_to_string(e.span));
+                            return;
+                        }
+                    }
+                }
+
+                _ => return,
+            };
+            *e = new_e;
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
+pub fn register_commands(reg: &mut Registry) {
+    use super::mk;
+
+    reg.register("ctype_to_methods", |_args| mk(CtypeToMethods));
+}
@@ -0,0 +1,1095 @@
+//! Transforms that turn groups of C enum and flag constants into Rust types.
+
+use std::collections::{HashMap, HashSet};
+
+use rustc::hir::HirId;
+use rustc::hir::def_id::DefId;
+use rustc::ty;
+use syntax::ast::*;
+use syntax::print::pprust;
+use syntax::ptr::P;
+use smallvec::smallvec;
+
+use c2rust_ast_builder::mk;
+use crate::ast_manip::{AstEquiv, FlatMapNodes, MutVisitNodes, fold_output_exprs, visit_nodes};
+use crate::ast_manip::fn_edit::{mut_visit_fns, visit_fns, FnKind};
+use crate::command::{CommandState, Registry};
+use crate::driver::{self, Phase};
+use crate::matcher::{Bindings, MatchCtxt, Subst};
+use crate::path_edit::fold_resolved_paths;
+use crate::transform::Transform;
+use crate::RefactorCtxt;
+
+
+/// # `enum_consts_to_enum` Command
+///
+/// Usage: `enum_consts_to_enum`
+///
+/// Marks: `target`
+///
+/// For each type alias marked `target`, such as `pub type color_t = libc::c_uint;`, gather
+/// the constants of that type declared next to it, and replace the alias and the constants
+/// with an enum of the same name:
+///
+/// ```ignore
+///     #[repr(u32)]
+///     #[derive(Clone, Copy, PartialEq, Eq)]
+///     pub enum color_t {
+///         RED = 0,
+///         GREEN = 1,
+///     }
+/// ```
+///
+/// When several constants have the same value, the first one becomes the variant and the
+/// others become associated constants referring to it.  References to the constants become
+/// paths into the enum, and chains of `if c == RED { .. } else if c == GREEN { .. }` on a
+/// single value become `match`es.
+///
+/// Values of the alias type are tracked through the constants and through locals,
+/// arguments, fields, statics and function results declared with the alias type.  Where
+/// such a value flows into arithmetic, an ordering comparison, or an argument of integer
+/// type, it is cast back to the integer type.  `extern` functions keep taking the integer
+/// type, so values passed to them are cast too.  An integer literal used where a value of
+/// the alias type is expected becomes the variant with that value, and so do literal
+/// patterns in a `match` on such a value.
+///
+/// An alias is skipped with a warning if one of its constants is not an integer literal,
+/// if an integer that is not the literal value of one of the constants is used where a
+/// value of the alias type is expected, if a value of the alias type is updated by a
+/// compound assignment, or if an `extern` function returns the alias type.
+pub struct EnumConstsToEnum;
+
+/// A marked type alias and the constants of its type.
+struct AliasInfo {
+    ident: Ident,
+    vis: Visibility,
+    /// The integer type the alias stood for, as written.
+    int_ty: P<Ty>,
+    /// The name of the integer type, for the enum's `#[repr]` and for casts.
+    repr: String,
+    /// The constants of the alias type, in order, with their values.
+    consts: Vec<(DefId, Ident, Visibility, i128)>,
+}
+
+/// Where values of the marked aliases' types can be found.
+struct AliasTypes<'a, 'tcx: 'a> {
+    cx: &'a RefactorCtxt<'a, 'tcx>,
+    /// The aliases still being converted.
+    aliases: HashMap<DefId, AliasInfo>,
+    /// Maps each constant to its alias.
+    consts: HashMap<DefId, DefId>,
+    /// Locals and arguments declared with an alias type.
+    locals: HashMap<HirId, DefId>,
+    /// Functions returning an alias type, and fields and statics declared with one.
+    defs: HashMap<DefId, DefId>,
+    /// The alias type of each argument of each function, if any.
+    params: HashMap<DefId, Vec<Option<DefId>>>,
+    /// `extern` functions returning an alias type.  These keep the integer types in their
+    /// signatures.
+    extern_rets: Vec<(Ident, DefId)>,
+}
+
+/// Get the value of an integer literal, looking through negation, casts and parentheses.
+pub(super) fn lit_value(e: &Expr) -> Option<i128> {
+    match e.kind {
+        ExprKind::Lit(ref lit) => match lit.kind {
+            LitKind::Int(i, _) => Some(i as i128),
+            _ => None,
+        },
+        ExprKind::Unary(UnOp::Neg, ref e) => lit_value(e).map(|i| -i),
+        ExprKind::Cast(ref e, _) | ExprKind::Paren(ref e) => lit_value(e),
+        _ => None,
+    }
+}
+
+/// Check if `e` is a place that can be read repeatedly without side effects.
+pub(super) fn is_simple_place(e: &Expr) -> bool {
+    match e.kind {
+        ExprKind::Path(..) => true,
+        ExprKind::Field(ref base, _) |
+        ExprKind::Paren(ref base) |
+        ExprKind::Unary(UnOp::Deref, ref base) => is_simple_place(base),
+        _ => false,
+    }
+}
+
+/// Get the `DefId` of field `ident` of the struct that is the type of node `id`.
+pub(super) fn field_def(cx: &RefactorCtxt, id: NodeId, ident: Ident) -> Option<DefId> {
+    let mut struct_ty = cx.opt_node_type(id)?;
+    while let ty::TyKind::Ref(_, inner, _) = struct_ty.kind {
+        struct_ty = inner;
+    }
+    match struct_ty.kind {
+        ty::TyKind::Adt(def, _) if !def.is_enum() => def.non_enum_variant().fields.iter()
+            .find(|f| f.ident.name == ident.name)
+            .map(|f| f.did),
+        _ => None,
+    }
+}
+
+/// Build the `AliasInfo` for the type alias `i` in `m`, gathering the constants of its type
+/// declared next to it.  Warnings are prefixed with `cmd`.
+fn alias_info(cx: &RefactorCtxt, cmd: &str, m: &Mod, i: &Item) -> Option<AliasInfo> {
+    let int_ty = match i.kind {
+        ItemKind::TyAlias(ref ty, _) => ty,
+        _ => return None,
+    };
+    let alias = cx.node_def_id(i.id);
+    let repr = cx.ty_ctxt().type_of(alias);
+    let signed = match repr.kind {
+        ty::TyKind::Int(_) => true,
+        ty::TyKind::Uint(_) => false,
+        _ => {
+            warn!("{}: `{}` is not an integer type; skipping", cmd, i.ident);
+            return None;
+        }
+    };
+
+    let mut info = AliasInfo {
+        ident: i.ident,
+        vis: i.vis.clone(),
+        int_ty: int_ty.clone(),
+        repr: repr.to_string(),
+        consts: Vec::new(),
+    };
+    let mut ok = true;
+    for c in &m.items {
+        let (ty, init) = match c.kind {
+            ItemKind::Const(ref ty, ref init) => (ty, init),
+            _ => continue,
+        };
+        if cx.try_resolve_ty(ty) != Some(alias) {
+            continue;
+        }
+        match lit_value(init) {
+            Some(v) if signed || v >= 0 => {
+                info.consts.push((cx.node_def_id(c.id), c.ident, c.vis.clone(), v));
+            }
+            _ => {
+                warn!("{}: value of `{}` is not a literal `{}`; skipping `{}`",
+                      cmd, c.ident, info.repr, i.ident);
+                ok = false;
+            }
+        }
+    }
+    if info.consts.is_empty() {
+        warn!("{}: found no constants of type `{}`; skipping", cmd, i.ident);
+        ok = false;
+    }
+    if ok { Some(info) } else { None }
+}
+
+/// Give the `extern` functions back the integer types in place of the aliases.
+fn retype_extern_fns(krate: &mut Crate, at: &AliasTypes) {
+    FlatMapNodes::visit(krate, |mut fi: ForeignItem| {
+        if let ForeignItemKind::Fn(ref mut decl, _) = fi.kind {
+            for arg in &mut decl.inputs {
+                if let Some(alias) = at.alias_of_ty(&arg.ty) {
+                    arg.ty = at.aliases[&alias].int_ty.clone();
+                }
+            }
+            if let FunctionRetTy::Ty(ref mut ty) = decl.output {
+                if let Some(alias) = at.alias_of_ty(ty) {
+                    *ty = at.aliases[&alias].int_ty.clone();
+                }
+            }
+        }
+        smallvec![fi]
+    });
+}
+
+impl<'a, 'tcx> AliasTypes<'a, 'tcx> {
+    /// Find the declarations in `krate` with the types of `aliases`.
+    fn new(cx: &'a RefactorCtxt<'a, 'tcx>, krate: &Crate, aliases: HashMap<DefId, AliasInfo>)
+           -> AliasTypes<'a, 'tcx> {
+        let consts = aliases.iter()
+            .flat_map(|(&alias, info)| info.consts.iter().map(move |c| (c.0, alias)))
+            .collect();
+        let mut at = AliasTypes {
+            cx,
+            aliases,
+            consts,
+            locals: HashMap::new(),
+            defs: HashMap::new(),
+            params: HashMap::new(),
+            extern_rets: Vec::new(),
+        };
+
+        let mut locals = HashMap::new();
+        let mut defs = HashMap::new();
+        let mut params = HashMap::new();
+        let mut extern_rets = Vec::new();
+        visit_nodes(krate, |l: &Local| {
+            if let Some(alias) = l.ty.as_ref().and_then(|ty| at.alias_of_ty(ty)) {
+                locals.insert(cx.hir_map().node_to_hir_id(l.pat.id), alias);
+            }
+        });
+        visit_nodes(krate, |i: &Item| {
+            match i.kind {
+                ItemKind::Static(ref ty, _, _) => {
+                    if let Some(alias) = at.alias_of_ty(ty) {
+                        defs.insert(cx.node_def_id(i.id), alias);
+                    }
+                }
+                ItemKind::Struct(ref vd, _) | ItemKind::Union(ref vd, _) => {
+                    for field in vd.fields() {
+                        if let Some(alias) = at.alias_of_ty(&field.ty) {
+                            defs.insert(cx.node_def_id(field.id), alias);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        });
+        visit_fns(krate, |fl| {
+            let ret_alias = match fl.decl.output {
+                FunctionRetTy::Ty(ref ty) => at.alias_of_ty(ty),
+                FunctionRetTy::Default(_) => None,
+            };
+            if fl.kind == FnKind::Foreign {
+                if let Some(alias) = ret_alias {
+                    extern_rets.push((fl.ident, alias));
+                }
+                return;
+            }
+
+            let def_id = cx.node_def_id(fl.id);
+            let fn_params = fl.decl.inputs.iter().map(|arg| {
+                let alias = at.alias_of_ty(&arg.ty);
+                if let Some(alias) = alias {
+                    locals.insert(cx.hir_map().node_to_hir_id(arg.pat.id), alias);
+                }
+                alias
+            }).collect();
+            params.insert(def_id, fn_params);
+            if let Some(alias) = ret_alias {
+                defs.insert(def_id, alias);
+            }
+        });
+        at.locals = locals;
+        at.defs = defs;
+        at.params = params;
+        at.extern_rets = extern_rets;
+        at
+    }
+
+    fn alias_of_ty(&self, ty: &Ty) -> Option<DefId> {
+        self.cx.try_resolve_ty(ty).filter(|def_id| self.aliases.contains_key(def_id))
+    }
+
+    /// Get the alias whose type `e` has, if any.
+    fn alias_of(&self, e: &Expr) -> Option<DefId> {
+        let alias = match e.kind {
+            ExprKind::Paren(ref e) => return self.alias_of(e),
+            ExprKind::Path(..) => self.cx.try_resolve_expr_to_hid(e)
+                .and_then(|hir_id| self.locals.get(&hir_id).cloned())
+                .or_else(|| self.cx.try_resolve_expr(e).and_then(|def_id| {
+                    self.consts.get(&def_id).or_else(|| self.defs.get(&def_id)).cloned()
+                })),
+            ExprKind::Field(ref base, ident) => field_def(self.cx, base.id, ident)
+                .and_then(|def_id| self.defs.get(&def_id).cloned()),
+            ExprKind::Call(..) | ExprKind::MethodCall(..) => self.cx.opt_callee(e)
+                .and_then(|def_id| self.defs.get(&def_id).cloned()),
+            ExprKind::Cast(_, ref ty) => self.alias_of_ty(ty),
+            _ => None,
+        };
+        alias.filter(|def_id| self.aliases.contains_key(def_id))
+    }
+
+    /// Get the source for the path to the variant or associated constant of `alias` that `k`
+    /// refers to.  `k` may be one of the constants or an integer literal.
+    fn variant_src(&self, alias: DefId, k: &Expr) -> Option<String> {
+        let info = &self.aliases[&alias];
+        let name = match self.cx.try_resolve_expr(k) {
+            Some(def_id) if self.consts.get(&def_id) == Some(&alias) => info.consts.iter()
+                .find(|c| c.0 == def_id)
+                .map(|c| c.1),
+            _ => lit_value(k).and_then(|v| info.consts.iter().find(|c| c.3 == v)).map(|c| c.1),
+        };
+        name.map(|name| format!("{}::{}", info.ident, name))
+    }
+
+    fn to_int(&self, alias: DefId, e: P<Expr>) -> P<Expr> {
+        mk().cast_expr(e, mk().ident_ty(self.aliases[&alias].repr.as_str()))
+    }
+
+    /// Convert `e`, which is used as a value of `alias`'s type, to an enum value.  Records
+    /// `alias` in `failed` if `e` can't be converted.
+    fn convert_value(&self, alias: DefId, e: &mut P<Expr>, failed: &mut HashSet<DefId>) {
+        if self.alias_of(e) == Some(alias) {
+            return;
+        }
+        if lit_value(e).is_some() {
+            if let Some(src) = self.variant_src(alias, e) {
+                *e = driver::parse_expr(self.cx.session(), &src);
+                return;
+            }
+        }
+        warn!("enum_consts_to_enum: can't use `{}` at {} as a `{}`; skipping",
+              pprust::expr_to_string(e),
+              self.cx.session().source_map().span_to_string(e.span),
+              self.aliases[&alias].ident);
+        failed.insert(alias);
+    }
+
+    /// Replace literal patterns in `p`, which matches a value of `alias`'s type, with patterns
+    /// for the variants.
+    fn convert_pat(&self, alias: DefId, p: &mut P<Pat>, failed: &mut HashSet<DefId>) {
+        let src = match p.kind {
+            PatKind::Or(ref mut pats) => {
+                for p in pats {
+                    self.convert_pat(alias, p, failed);
+                }
+                return;
+            }
+            PatKind::Lit(ref e) => match self.variant_src(alias, e) {
+                Some(src) => src,
+                None => {
+                    warn!("enum_consts_to_enum: pattern `{}` doesn't match any `{}`; skipping",
+                          pprust::expr_to_string(e), self.aliases[&alias].ident);
+                    failed.insert(alias);
+                    return;
+                }
+            },
+            PatKind::Range(..) => {
+                warn!("enum_consts_to_enum: can't match a `{}` against a range; skipping",
+                      self.aliases[&alias].ident);
+                failed.insert(alias);
+                return;
+            }
+            _ => return,
+        };
+        *p = driver::parse_pat(self.cx.session(), &src);
+    }
+
+    /// If `e` is a chain of `if x == A { .. } else if x == B { .. }` comparing one value with
+    /// two or more constants of the same alias, return `x`, the arms as pattern source and
+    /// body, and the final `else`, if any.
+    fn if_chain<'e>(&self, e: &'e Expr)
+                    -> Option<(&'e P<Expr>, Vec<(String, &'e P<Block>)>, Option<&'e P<Expr>>)> {
+        let mut scrutinee: Option<(&P<Expr>, DefId)> = None;
+        let mut arms = Vec::new();
+        let mut cur = e;
+        let final_else = loop {
+            let (cond, then, els) = match cur.kind {
+                ExprKind::If(ref cond, ref then, ref els) => (cond, then, els),
+                _ => return None,
+            };
+            let (l, r) = match cond.kind {
+                ExprKind::Binary(op, ref l, ref r) if op.node == BinOpKind::Eq => (l, r),
+                _ => return None,
+            };
+            let is_key = |e: &Expr| lit_value(e).is_some() || self.cx.try_resolve_expr(e)
+                .map_or(false, |def_id| self.consts.contains_key(&def_id));
+            let (x, k) = if is_key(r) { (l, r) } else { (r, l) };
+            let alias = self.alias_of(x).filter(|_| is_simple_place(x))?;
+            match scrutinee {
+                Some((first, first_alias)) if first_alias != alias || !first.ast_equiv(x) =>
+                    return None,
+                Some(_) => {}
+                None => scrutinee = Some((x, alias)),
+            }
+            arms.push((self.variant_src(alias, k)?, then));
+
+            match *els {
+                Some(ref els) => match els.kind {
+                    ExprKind::If(..) => cur = els,
+                    _ => break Some(els),
+                },
+                None => break None,
+            }
+        };
+        if arms.len() < 2 {
+            return None;
+        }
+        scrutinee.map(|(x, _)| (x, arms, final_else))
+    }
+
+    /// Rewrite the uses of enum values in `krate`.  Returns the aliases whose values are used
+    /// in ways that can't be converted.
+    fn convert_uses(&self, krate: &mut Crate, st: &CommandState) -> HashSet<DefId> {
+        let cx = self.cx;
+        let mut failed = HashSet::new();
+
+        // (1) Turn `if` chains into `match`es.
+
+        let mut mcx = MatchCtxt::new(st, cx);
+        let empty_block = driver::parse_expr(cx.session(), "{}");
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            let new_e = {
+                let (x, arms, final_else) = match self.if_chain(e) {
+                    Some(chain) => chain,
+                    None => return,
+                };
+                let mut src = "match $x {".to_owned();
+                let mut bnd = Bindings::new();
+                bnd.add("$x", x.clone());
+                for (i, (pat, body)) in arms.into_iter().enumerate() {
+                    src.push_str(&format!(" {} => $body{},", pat, i));
+                    bnd.add(format!("$body{}", i), mk().block_expr(body.clone()));
+                }
+                src.push_str(" _ => $rest }");
+                bnd.add("$rest", final_else.cloned().unwrap_or_else(|| empty_block.clone()));
+                mcx.parse_expr(&src).subst(st, cx, &bnd)
+            };
+            *e = new_e;
+        });
+
+        // (2) Convert values flowing into and out of the alias types.
+
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            let id = e.id;
+            let span = e.span;
+            let callee = cx.opt_callee(e);
+
+            // Casts to an alias type become the enum value itself.
+            if let ExprKind::Cast(ref inner, ref ty) = e.kind {
+                if let Some(alias) = self.alias_of_ty(ty) {
+                    let mut inner = inner.clone();
+                    self.convert_value(alias, &mut inner, &mut failed);
+                    *e = inner;
+                    return;
+                }
+            }
+
+            match e.kind {
+                ExprKind::Binary(op, ref mut l, ref mut r) => match op.node {
+                    BinOpKind::And | BinOpKind::Or => {}
+                    BinOpKind::Eq | BinOpKind::Ne => match (self.alias_of(l), self.alias_of(r)) {
+                        (Some(alias), None) => match self.variant_src(alias, r) {
+                            Some(_) if lit_value(r).is_some() =>
+                                self.convert_value(alias, r, &mut failed),
+                            _ => *l = self.to_int(alias, l.clone()),
+                        },
+                        (None, Some(alias)) => match self.variant_src(alias, l) {
+                            Some(_) if lit_value(l).is_some() =>
+                                self.convert_value(alias, l, &mut failed),
+                            _ => *r = self.to_int(alias, r.clone()),
+                        },
+                        _ => {}
+                    },
+                    _ => {
+                        for side in vec![l, r] {
+                            if let Some(alias) = self.alias_of(side) {
+                                *side = self.to_int(alias, side.clone());
+                            }
+                        }
+                    }
+                },
+
+                ExprKind::Unary(UnOp::Neg, ref mut x) | ExprKind::Unary(UnOp::Not, ref mut x) => {
+                    if let Some(alias) = self.alias_of(x) {
+                        *x = self.to_int(alias, x.clone());
+                    }
+                }
+
+                ExprKind::Call(_, ref mut args) | ExprKind::MethodCall(_, ref mut args) => {
+                    let params = callee.and_then(|def_id| self.params.get(&def_id));
+                    for (i, arg) in args.iter_mut().enumerate() {
+                        let param = params.and_then(|params| params.get(i).cloned()).flatten();
+                        match param.filter(|alias| self.aliases.contains_key(alias)) {
+                            Some(alias) => self.convert_value(alias, arg, &mut failed),
+                            None => if let Some(alias) = self.alias_of(arg) {
+                                *arg = self.to_int(alias, arg.clone());
+                            },
+                        }
+                    }
+                }
+
+                ExprKind::Assign(ref lhs, ref mut rhs) => {
+                    if let Some(alias) = self.alias_of(lhs) {
+                        self.convert_value(alias, rhs, &mut failed);
+                    }
+                }
+
+                ExprKind::AssignOp(_, ref lhs, _) => {
+                    if let Some(alias) = self.alias_of(lhs) {
+                        warn!("enum_consts_to_enum: `{}` is updated in place at {}; skipping",
+                              self.aliases[&alias].ident,
+                              cx.session().source_map().span_to_string(span));
+                        failed.insert(alias);
+                    }
+                }
+
+                ExprKind::Struct(_, ref mut fields, _) => {
+                    for field in fields {
+                        let alias = field_def(cx, id, field.ident)
+                            .and_then(|def_id| self.defs.get(&def_id).cloned())
+                            .filter(|alias| self.aliases.contains_key(alias));
+                        if let Some(alias) = alias {
+                            self.convert_value(alias, &mut field.expr, &mut failed);
+                        }
+                    }
+                }
+
+                ExprKind::Match(ref scrutinee, ref mut arms) => {
+                    if let Some(alias) = self.alias_of(scrutinee) {
+                        for arm in arms {
+                            self.convert_pat(alias, &mut arm.pat, &mut failed);
+                        }
+                    }
+                }
+
+                _ => {}
+            }
+        });
+
+        MutVisitNodes::visit(krate, |l: &mut P<Local>| {
+            let alias = match l.ty {
+                Some(ref ty) => self.alias_of_ty(ty),
+                None => None,
+            };
+            if let (Some(alias), Some(init)) = (alias, l.init.as_mut()) {
+                self.convert_value(alias, init, &mut failed);
+            }
+        });
+
+        mut_visit_fns(krate, |fl| {
+            let alias = match self.defs.get(&cx.node_def_id(fl.id)) {
+                Some(&alias) if self.aliases.contains_key(&alias) => alias,
+                _ => return,
+            };
+            if let Some(ref mut block) = fl.block {
+                fold_output_exprs(block, true, |e| self.convert_value(alias, e, &mut failed));
+                MutVisitNodes::visit(block, |e: &mut P<Expr>| {
+                    if let ExprKind::Ret(Some(ref mut ret)) = e.kind {
+                        self.convert_value(alias, ret, &mut failed);
+                    }
+                });
+            }
+        });
+
+        failed
+    }
+}
+
+impl Transform for EnumConstsToEnum {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        // (1) Find the marked aliases, and the constants declared next to them.
+
+        let mut aliases = HashMap::new();
+        let mut collect = |m: &Mod| {
+            for i in &m.items {
+                if !st.marked(i.id, "target") {
+                    continue;
+                }
+                if let Some(info) = alias_info(cx, "enum_consts_to_enum", m, i) {
+                    aliases.insert(cx.node_def_id(i.id), info);
+                }
+            }
+        };
+        collect(&krate.module);
+        visit_nodes(krate, |i: &Item| {
+            if let ItemKind::Mod(ref m) = i.kind {
+                collect(m);
+            }
+        });
+
+        // (2) Find the other declarations with an alias type.
+
+        let mut et = AliasTypes::new(cx, krate, aliases);
+        let mut failed = HashSet::new();
+        for &(ident, alias) in &et.extern_rets {
+            warn!("enum_consts_to_enum: extern fn `{}` returns `{}`; skipping",
+                  ident, et.aliases[&alias].ident);
+            failed.insert(alias);
+        }
+
+        // (3) Convert the uses on a copy of the crate, dropping the aliases that can't be
+        // converted, until every use of the remaining aliases converts cleanly.
+
+        let new_krate = loop {
+            et.aliases.retain(|alias, _| !failed.contains(alias));
+            if et.aliases.is_empty() {
+                return;
+            }
+            let mut new_krate = krate.clone();
+            failed = et.convert_uses(&mut new_krate, st);
+            if failed.is_empty() {
+                break new_krate;
+            }
+        };
+        *krate = new_krate;
+
+        // (4) Point references to the constants at the enum.
+
+        fold_resolved_paths(krate, cx, |qself, mut path, res| {
+            let alias = res[0].opt_def_id()
+                .and_then(|def_id| et.consts.get(&def_id))
+                .filter(|alias| et.aliases.contains_key(alias));
+            if let Some(alias) = alias {
+                let last = path.segments.pop().unwrap();
+                path.segments.push(PathSegment::from_ident(et.aliases[alias].ident));
+                path.segments.push(last);
+            }
+            (qself, path)
+        });
+
+        // (5) Replace the aliases with enums, and remove the constants.  `extern` functions
+        // keep the integer type.
+
+        FlatMapNodes::visit(krate, |i: P<Item>| {
+            let def_id = cx.node_def_id(i.id);
+            if let Some(&alias) = et.consts.get(&def_id) {
+                if et.aliases.contains_key(&alias) {
+                    return smallvec![];
+                }
+                return smallvec![i];
+            }
+            let info = match et.aliases.get(&def_id) {
+                Some(info) => info,
+                None => return smallvec![i],
+            };
+
+            let vis = pprust::vis_to_string(&info.vis);
+            let mut variants = String::new();
+            let mut assoc_consts = String::new();
+            let mut seen = HashMap::new();
+            for &(_, ident, ref const_vis, value) in &info.consts {
+                match seen.get(&value) {
+                    None => {
+                        variants.push_str(&format!("    {} = {},\n", ident, value));
+                        seen.insert(value, ident);
+                    }
+                    Some(first) => {
+                        assoc_consts.push_str(&format!("    {}const {}: {} = {}::{};\n",
+                                                       pprust::vis_to_string(const_vis), ident,
+                                                       info.ident, info.ident, first));
+                    }
+                }
+            }
+            let mut src = format!("#[repr({})]\n#[derive(Clone, Copy, PartialEq, Eq)]\n\
+                                   {}enum {} {{\n{}}}\n",
+                                  info.repr, vis, info.ident, variants);
+            if !assoc_consts.is_empty() {
+                src.push_str(&format!("impl {} {{\n{}}}\n", info.ident, assoc_consts));
+            }
+            driver::parse_items(cx.session(), &src).into()
+        });
+
+        retype_extern_fns(krate, &et);
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
+/// # `consts_to_bitflags` Command
+///
+/// Usage: `consts_to_bitflags NAME`
+///
+/// Marks: `target`
+///
+/// Replace the type alias marked `target`, such as `pub type open_flags_t = libc::c_uint;`,
+/// and the constants of that type declared next to it with a `bitflags!` struct called
+/// `NAME`:
+///
+/// ```ignore
+///     ::bitflags::bitflags! {
+///         pub struct OpenFlags: u32 {
+///             const OPEN_READ = 0x1;
+///             const OPEN_WRITE = 0x2;
+///         }
+///     }
+/// ```
+///
+/// Marking some of the constants instead selects their alias, and all of the alias's
+/// constants are converted.  Each constant must be a single bit or a combination of the
+/// bits of the other constants; otherwise the alias is skipped with a warning.
+///
+/// References to the constants become `NAME::C`, and locals, arguments, fields, statics and
+/// function results declared with the alias type get the type `NAME`.  Bitwise operators
+/// keep working on the new type.  A test `x & C != 0` becomes `x.contains(NAME::C)`, or
+/// `x.intersects(..)` when the mask is not a single-bit constant, and `x == 0` becomes
+/// `x.is_empty()`.  An integer used where flags are expected becomes `NAME::empty()` or a
+/// constant when it is a matching literal, and goes through `NAME::from_bits_truncate`
+/// otherwise.  Flags used as integers, as in arithmetic or in arguments to `extern`
+/// functions, which keep the integer type, go through `.bits()`.
+///
+/// The alias is skipped with a warning if a value of its type is updated by a compound
+/// assignment other than `|=`, `&=` and `^=`, or is matched on.  The crate must depend on
+/// `bitflags`.
+pub struct ConstsToBitflags {
+    pub name: String,
+}
+
+/// Check that each constant of `info` is a single bit or a combination of the single-bit
+/// constants.
+fn check_bits(info: &AliasInfo) -> bool {
+    let bits = info.consts.iter()
+        .map(|c| c.3)
+        .filter(|&v| v > 0 && v & (v - 1) == 0)
+        .fold(0, |acc, v| acc | v);
+    let mut ok = true;
+    for &(_, ident, _, value) in &info.consts {
+        if value < 0 || value & !bits != 0 {
+            warn!("consts_to_bitflags: `{}` = {:#x} is not a single bit or a combination of \
+                   the other flags; skipping `{}`", ident, value, info.ident);
+            ok = false;
+        }
+    }
+    ok
+}
+
+/// Converts the values of the alias being replaced by a `bitflags!` struct.
+struct FlagsConv<'a, 'tcx: 'a> {
+    at: AliasTypes<'a, 'tcx>,
+    alias: DefId,
+    name: String,
+}
+
+impl<'a, 'tcx> FlagsConv<'a, 'tcx> {
+    /// Check if `e` has the flags type, counting bitwise operations on flags.
+    fn is_flags(&self, e: &Expr) -> bool {
+        match e.kind {
+            ExprKind::Paren(ref e) | ExprKind::Unary(UnOp::Not, ref e) => self.is_flags(e),
+            ExprKind::Binary(op, ref l, ref r) => match op.node {
+                BinOpKind::BitAnd | BinOpKind::BitOr | BinOpKind::BitXor =>
+                    self.is_flags(l) || self.is_flags(r),
+                _ => false,
+            },
+            _ if e.id == DUMMY_NODE_ID => false,
+            _ => self.at.alias_of(e) == Some(self.alias),
+        }
+    }
+
+    /// Get the value of `k`, if it refers to one of the constants.
+    fn const_value(&self, k: &Expr) -> Option<i128> {
+        let def_id = self.at.cx.try_resolve_expr(k)?;
+        self.at.aliases[&self.alias].consts.iter().find(|c| c.0 == def_id).map(|c| c.3)
+    }
+
+    /// Convert `e`, which is used where flags are expected, to flags.
+    fn to_flags(&self, e: &mut P<Expr>) {
+        if self.is_flags(e) {
+            return;
+        }
+        let consts = &self.at.aliases[&self.alias].consts;
+        *e = match lit_value(e) {
+            Some(0) => mk().call_expr(mk().path_expr(vec![&*self.name, "empty"]),
+                                      Vec::<P<Expr>>::new()),
+            Some(v) if consts.iter().any(|c| c.3 == v) => {
+                let ident = consts.iter().find(|c| c.3 == v).unwrap().1;
+                mk().path_expr(vec![&*self.name, &*ident.as_str()])
+            }
+            _ => mk().call_expr(mk().path_expr(vec![&*self.name, "from_bits_truncate"]),
+                                vec![e.clone()]),
+        };
+    }
+
+    /// Convert `e`, which is used as an integer, to one if it has the flags type.
+    fn to_bits(&self, e: &mut P<Expr>) {
+        if self.is_flags(e) {
+            *e = mk().method_call_expr(e.clone(), "bits", Vec::<P<Expr>>::new());
+        }
+    }
+
+    /// Rewrite `x & k != 0` to `x.contains(k)` or `x.intersects(k)`, and `x == 0` to
+    /// `x.is_empty()`, along with the negated tests.
+    fn flag_test(&self, e: &Expr) -> Option<P<Expr>> {
+        let (op, l, r) = match e.kind {
+            ExprKind::Binary(op, ref l, ref r) => (op.node, l, r),
+            _ => return None,
+        };
+        if op != BinOpKind::Eq && op != BinOpKind::Ne {
+            return None;
+        }
+        let mut x = match (lit_value(l), lit_value(r)) {
+            (_, Some(0)) => l,
+            (Some(0), _) => r,
+            _ => return None,
+        };
+        if !self.is_flags(x) {
+            return None;
+        }
+        while let ExprKind::Paren(ref inner) = x.kind {
+            x = inner;
+        }
+
+        let (test, negate) = match x.kind {
+            ExprKind::Binary(and, ref a, ref b) if and.node == BinOpKind::BitAnd => {
+                let (mut a, mut b) = if self.const_value(a).is_some() {
+                    (b.clone(), a.clone())
+                } else {
+                    (a.clone(), b.clone())
+                };
+                let single_bit = self.const_value(&b)
+                    .map_or(false, |v| v > 0 && v & (v - 1) == 0);
+                self.to_flags(&mut a);
+                self.to_flags(&mut b);
+                let method = if single_bit { "contains" } else { "intersects" };
+                (mk().method_call_expr(a, method, vec![b]), op == BinOpKind::Eq)
+            }
+            _ => (mk().method_call_expr(x.clone(), "is_empty", Vec::<P<Expr>>::new()),
+                  op == BinOpKind::Ne),
+        };
+        Some(if negate { mk().unary_expr("!", test) } else { test })
+    }
+
+    /// Check for uses of the flags that can't be converted.
+    fn check_uses(&self, krate: &Crate) -> bool {
+        let cx = self.at.cx;
+        let ident = self.at.aliases[&self.alias].ident;
+        let mut ok = true;
+        visit_nodes(krate, |e: &Expr| {
+            match e.kind {
+                ExprKind::AssignOp(op, ref lhs, _) if self.is_flags(lhs) => match op.node {
+                    BinOpKind::BitAnd | BinOpKind::BitOr | BinOpKind::BitXor => {}
+                    _ => {
+                        warn!("consts_to_bitflags: `{}` is updated with `{}=` at {}; skipping",
+                              ident, op.node.to_string(),
+                              cx.session().source_map().span_to_string(e.span));
+                        ok = false;
+                    }
+                },
+                ExprKind::Match(ref scrutinee, _) if self.is_flags(scrutinee) => {
+                    warn!("consts_to_bitflags: `{}` is matched on at {}; skipping",
+                          ident, cx.session().source_map().span_to_string(e.span));
+                    ok = false;
+                }
+                _ => {}
+            }
+        });
+        ok
+    }
+
+    /// Rewrite the uses of flags in `krate`.
+    fn convert_uses(&self, krate: &mut Crate) {
+        let cx = self.at.cx;
+
+        // Values of declared types are converted first, so the expressions built for them are
+        // skipped by the other conversions.
+
+        MutVisitNodes::visit(krate, |l: &mut P<Local>| {
+            let is_alias = match l.ty {
+                Some(ref ty) => self.at.alias_of_ty(ty) == Some(self.alias),
+                None => return,
+            };
+            if let Some(ref mut init) = l.init {
+                if is_alias {
+                    self.to_flags(init);
+                } else {
+                    self.to_bits(init);
+                }
+            }
+        });
+
+        mut_visit_fns(krate, |fl| {
+            let is_alias = self.at.defs.get(&cx.node_def_id(fl.id)) == Some(&self.alias);
+            let convert = |e: &mut P<Expr>| if is_alias {
+                self.to_flags(e);
+            } else {
+                self.to_bits(e);
+            };
+            if let Some(ref mut block) = fl.block {
+                fold_output_exprs(block, true, |e| convert(e));
+                MutVisitNodes::visit(block, |e: &mut P<Expr>| {
+                    if let ExprKind::Ret(Some(ref mut ret)) = e.kind {
+                        convert(ret);
+                    }
+                });
+            }
+        });
+
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            // Built expressions are left alone, but their operands are still visited.
+            if e.id == DUMMY_NODE_ID {
+                return;
+            }
+            let id = e.id;
+            let callee = cx.opt_callee(e);
+
+            if let ExprKind::Cast(ref inner, ref ty) = e.kind {
+                if self.at.alias_of_ty(ty) == Some(self.alias) {
+                    let mut inner = inner.clone();
+                    self.to_flags(&mut inner);
+                    *e = inner;
+                    return;
+                }
+            }
+            if let Some(test) = self.flag_test(e) {
+                *e = test;
+                return;
+            }
+
+            match e.kind {
+                ExprKind::Cast(ref mut inner, _) => self.to_bits(inner),
+
+                ExprKind::Binary(op, ref mut l, ref mut r) => match op.node {
+                    BinOpKind::And | BinOpKind::Or => {}
+                    BinOpKind::BitAnd | BinOpKind::BitOr | BinOpKind::BitXor |
+                    BinOpKind::Eq | BinOpKind::Ne => {
+                        if self.is_flags(l) || self.is_flags(r) {
+                            self.to_flags(l);
+                            self.to_flags(r);
+                        }
+                    }
+                    _ => {
+                        self.to_bits(l);
+                        self.to_bits(r);
+                    }
+                },
+
+                ExprKind::Unary(UnOp::Neg, ref mut x) => self.to_bits(x),
+
+                ExprKind::Call(_, ref mut args) | ExprKind::MethodCall(_, ref mut args) => {
+                    let params = callee.and_then(|def_id| self.at.params.get(&def_id));
+                    for (i, arg) in args.iter_mut().enumerate() {
+                        let param = params.and_then(|params| params.get(i).cloned()).flatten();
+                        if param == Some(self.alias) {
+                            self.to_flags(arg);
+                        } else {
+                            self.to_bits(arg);
+                        }
+                    }
+                }
+
+                ExprKind::Assign(ref lhs, ref mut rhs) |
+                ExprKind::AssignOp(_, ref lhs, ref mut rhs) => {
+                    if self.is_flags(lhs) {
+                        self.to_flags(rhs);
+                    } else {
+                        self.to_bits(rhs);
+                    }
+                }
+
+                ExprKind::Struct(_, ref mut fields, _) => {
+                    for field in fields {
+                        let alias = field_def(self.at.cx, id, field.ident)
+                            .and_then(|def_id| self.at.defs.get(&def_id).cloned());
+                        if alias == Some(self.alias) {
+                            self.to_flags(&mut field.expr);
+                        } else {
+                            self.to_bits(&mut field.expr);
+                        }
+                    }
+                }
+
+                _ => {}
+            }
+        });
+
+    }
+}
+
+impl Transform for ConstsToBitflags {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        // (1) Find the marked alias, or the alias of the marked constants, and the constants
+        // declared next to it.
+
+        let mut found = Vec::new();
+        let mut collect = |m: &Mod| {
+            let mut marked = HashSet::new();
+            for i in &m.items {
+                if !st.marked(i.id, "target") {
+                    continue;
+                }
+                match i.kind {
+                    ItemKind::TyAlias(..) => {
+                        marked.insert(cx.node_def_id(i.id));
+                    }
+                    ItemKind::Const(ref ty, _) => {
+                        marked.extend(cx.try_resolve_ty(ty));
+                    }
+                    _ => {}
+                }
+            }
+            for i in &m.items {
+                if let ItemKind::TyAlias(..) = i.kind {
+                    let alias = cx.node_def_id(i.id);
+                    if !marked.contains(&alias) {
+                        continue;
+                    }
+                    if let Some(info) = alias_info(cx, "consts_to_bitflags", m, i) {
+                        found.push((alias, info));
+                    }
+                }
+            }
+        };
+        collect(&krate.module);
+        visit_nodes(krate, |i: &Item| {
+            if let ItemKind::Mod(ref m) = i.kind {
+                collect(m);
+            }
+        });
+
+        if found.len() != 1 {
+            warn!("consts_to_bitflags: expected one marked alias, but found {}", found.len());
+            return;
+        }
+        let (alias, info) = found.pop().unwrap();
+        if !check_bits(&info) {
+            return;
+        }
+
+        // (2) Find the declarations with the alias type, and convert the uses of their values.
+
+        let mut aliases = HashMap::new();
+        aliases.insert(alias, info);
+        let conv = FlagsConv {
+            at: AliasTypes::new(cx, krate, aliases),
+            alias,
+            name: self.name.clone(),
+        };
+        if !conv.check_uses(krate) {
+            return;
+        }
+        conv.convert_uses(krate);
+        retype_extern_fns(krate, &conv.at);
+
+        // (3) Point references to the alias at the struct, and references to the constants
+        // into it.
+
+        fold_resolved_paths(krate, cx, |qself, mut path, res| {
+            match res[0].opt_def_id() {
+                Some(def_id) if def_id == alias => {
+                    path.segments.last_mut().unwrap().ident = Ident::from_str(&self.name);
+                }
+                Some(def_id) if conv.at.consts.contains_key(&def_id) => {
+                    let last = path.segments.pop().unwrap();
+                    path.segments.push(PathSegment::from_ident(Ident::from_str(&self.name)));
+                    path.segments.push(last);
+                }
+                _ => {}
+            }
+            (qself, path)
+        });
+
+        // (4) Replace the alias with the struct, and remove the constants.
+
+        FlatMapNodes::visit(krate, |i: P<Item>| {
+            let def_id = cx.node_def_id(i.id);
+            if conv.at.consts.contains_key(&def_id) {
+                return smallvec![];
+            }
+            if def_id != alias {
+                return smallvec![i];
+            }
+
+            let info = &conv.at.aliases[&alias];
+            let mut flags = String::new();
+            for &(_, ident, _, value) in &info.consts {
+                flags.push_str(&format!("        const {} = {:#x};\n", ident, value));
+            }
+            let src = format!("::bitflags::bitflags! {{\n    {}struct {}: {} {{\n{}    }}\n}}\n",
+                              pprust::vis_to_string(&info.vis), self.name, info.repr, flags);
+            driver::parse_items(cx.session(), &src).into()
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
+pub fn register_commands(reg: &mut Registry) {
+    use super::mk;
+
+    reg.register("enum_consts_to_enum", |_args| mk(EnumConstsToEnum));
+    reg.register("consts_to_bitflags", |args| mk(ConstsToBitflags {
+        name: args[0].clone(),
+    }));
+}
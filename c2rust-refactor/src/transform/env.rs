@@ -0,0 +1,338 @@
+//! Transform that replaces the C environment functions with `std::env`.
+
+use std::ascii;
+use std::collections::HashSet;
+use syntax::ast::*;
+use syntax::ptr::P;
+
+use crate::ast_manip::{MutVisitNodes, visit_nodes};
+use crate::command::{CommandState, Registry};
+use crate::driver::{self, Phase};
+use crate::matcher::{Bindings, MatchCtxt, Subst};
+use crate::transform::Transform;
+use crate::transform::assert::foreign_call;
+use crate::transform::enums::lit_value;
+use crate::transform::mem::strip_casts;
+use crate::transform::null_ptrs::null_check;
+use crate::transform::strings::StrOperand;
+use crate::RefactorCtxt;
+
+
+/// # `env_to_std` Command
+///
+/// Usage: `env_to_std`
+///
+/// Replace calls to the C environment functions with `std::env`:
+///
+///  * A local `p` initialized with `getenv(name)` becomes
+///    `::std::env::var_os(name)` when it's only used by null checks and by the
+///    `CStr::from_ptr(p).to_bytes()` and `CStr::from_ptr(p).to_str().unwrap()`
+///    conversions that `strcmp_to_eq`, `strlen_to_len` and `strtox_to_parse`
+///    leave behind.  `p.is_null()` becomes `p.is_none()` and `!p.is_null()`
+///    becomes `p.is_some()`, and the conversions become
+///    `OsStrExt::as_bytes(p.as_ref().unwrap().as_os_str())` and
+///    `p.as_ref().unwrap().to_str().unwrap()`.  A null check of a `getenv`
+///    call, as in `getenv(name).is_null()`, is rewritten the same way.
+///  * Any other `getenv(name)` becomes `crate::getenv_cstring(name)`, a
+///    generated helper returning the same nullable `*mut c_char`.  It points to
+///    a `CString` cached by the current thread, which stays valid until the
+///    variable is read again after it changed.
+///  * `setenv(name, value, overwrite);` becomes
+///    `::std::env::set_var(name, value);`.  When `overwrite` is 0, the
+///    variable is only set if `::std::env::var_os(name)` is `None`, and when it
+///    isn't a literal, that check is made unless `overwrite != 0`.
+///  * `unsetenv(name);` becomes `::std::env::remove_var(name);`.
+///
+/// A UTF-8 string literal name or value is passed directly, and any other
+/// string is passed as an `OsStr` of its bytes, as in
+/// `OsStrExt::from_bytes(CStr::from_ptr(s).to_bytes())`, so the rewritten code
+/// only builds on Unix.  `setenv` and `unsetenv` calls whose result is used
+/// are left alone with a warning.
+///
+/// Note that `set_var` panics on the invalid names that make `setenv` fail.
+pub struct EnvToStd;
+
+/// If `e` is a call of `getenv`, return its argument.
+fn getenv_name<'a>(cx: &RefactorCtxt, e: &'a Expr) -> Option<&'a P<Expr>> {
+    foreign_call(cx, e, "getenv").filter(|args| args.len() == 1).map(|args| &args[0])
+}
+
+/// If `e` is `CStr::from_ptr(p)`, return `p` without its casts.
+fn cstr_from_ptr_arg(e: &Expr) -> Option<&P<Expr>> {
+    let (func, args) = match_or!([e.kind] ExprKind::Call(ref f, ref a) => (f, a); return None);
+    let path = match_or!([func.kind] ExprKind::Path(None, ref p) => p; return None);
+    let n = path.segments.len();
+    if args.len() != 1 || n < 2 || &*path.segments[n - 2].ident.as_str() != "CStr" ||
+       &*path.segments[n - 1].ident.as_str() != "from_ptr" {
+        return None;
+    }
+    Some(strip_casts(&args[0]))
+}
+
+/// A use of the C string `p` that `env::var` can replace.
+enum EnvUse<'a> {
+    /// `p.is_null()`, or `!p.is_null()` if negated.
+    NullCheck(&'a P<Expr>, bool),
+    /// `CStr::from_ptr(p).to_bytes()`.
+    Bytes(&'a P<Expr>),
+    /// `CStr::from_ptr(p).to_str().unwrap()`.
+    Str(&'a P<Expr>),
+}
+
+impl<'a> EnvUse<'a> {
+    fn from_expr(e: &'a Expr) -> Option<EnvUse<'a>> {
+        if let Some((p, negated)) = null_check(e) {
+            return Some(EnvUse::NullCheck(p, negated));
+        }
+        let (seg, args) = match_or!([e.kind] ExprKind::MethodCall(ref s, ref a) => (s, a);
+                                    return None);
+        match &*seg.ident.as_str() {
+            "to_bytes" => cstr_from_ptr_arg(&args[0]).map(EnvUse::Bytes),
+            "unwrap" => {
+                let (seg, args) = match_or!([args[0].kind]
+                                            ExprKind::MethodCall(ref s, ref a) => (s, a);
+                                            return None);
+                if &*seg.ident.as_str() != "to_str" {
+                    return None;
+                }
+                cstr_from_ptr_arg(&args[0]).map(EnvUse::Str)
+            }
+            _ => None,
+        }
+    }
+
+    fn ptr(&self) -> &'a P<Expr> {
+        match *self {
+            EnvUse::NullCheck(p, _) | EnvUse::Bytes(p) | EnvUse::Str(p) => p,
+        }
+    }
+}
+
+impl Transform for EnvToStd {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        let mut mcx = MatchCtxt::new(st, cx);
+        let os_bytes_repl = mcx.parse_expr("::std::os::unix::ffi::OsStrExt::from_bytes($b)");
+        let cstr_bytes_repl = mcx.parse_expr("$s.to_bytes()");
+        let ptr_bytes_repl = mcx.parse_expr(
+            "unsafe { ::std::ffi::CStr::from_ptr($s as *const libc::c_char).to_bytes() }");
+        let var_repl = mcx.parse_expr("::std::env::var_os($name)");
+        let var_ty = mcx.parse_ty("::std::option::Option<::std::ffi::OsString>");
+        let is_none_repl = mcx.parse_expr("$p.is_none()");
+        let is_some_repl = mcx.parse_expr("$p.is_some()");
+        let bytes_repl = mcx.parse_expr(
+            "::std::os::unix::ffi::OsStrExt::as_bytes($p.as_ref().unwrap().as_os_str())");
+        let str_repl = mcx.parse_expr("$p.as_ref().unwrap().to_str().unwrap()");
+        let cstring_repl = mcx.parse_expr("crate::getenv_cstring($name)");
+        let set_repl = mcx.parse_expr("::std::env::set_var($name, $value)");
+        let set_unset_repl = mcx.parse_expr(
+            "if ::std::env::var_os($name).is_none() { ::std::env::set_var($name, $value) }");
+        let set_checked_repl = mcx.parse_expr(
+            "if $overwrite != 0 || ::std::env::var_os($name).is_none() {
+                 ::std::env::set_var($name, $value)
+             }");
+        let remove_repl = mcx.parse_expr("::std::env::remove_var($name)");
+        let mut need_cstring = false;
+
+        let subst = |repl: &P<Expr>, args: Vec<(&str, P<Expr>)>| {
+            let mut bnd = Bindings::new();
+            for (name, e) in args {
+                bnd.add(name, e);
+            }
+            repl.clone().subst(st, cx, &bnd)
+        };
+        // The `&str` or `&OsStr` to pass to `std::env` for the C string `e`.
+        let os_arg = |e: &P<Expr>| -> P<Expr> {
+            let bytes = match StrOperand::classify(cx, e) {
+                StrOperand::Lit(bs) => match String::from_utf8(bs) {
+                    Ok(s) => return driver::parse_expr(cx.session(), &format!("{:?}", s)),
+                    Err(err) => {
+                        let escaped = err.into_bytes().into_iter()
+                            .flat_map(ascii::escape_default)
+                            .map(char::from)
+                            .collect::<String>();
+                        driver::parse_expr(cx.session(), &format!("b\"{}\"", escaped))
+                    }
+                },
+                StrOperand::CStr(s) => subst(&cstr_bytes_repl, vec![("$s", s.clone())]),
+                StrOperand::Str(s) => return s.clone(),
+                StrOperand::Ptr(s) => subst(&ptr_bytes_repl, vec![("$s", s.clone())]),
+            };
+            subst(&os_bytes_repl, vec![("$b", bytes)])
+        };
+        let warn_skip = |why: &str, e: &Expr| {
+            warn!("env_to_std: skipping call that {} at {}", why,
+                  cx.session().source_map().span_to_string(e.span));
+        };
+
+        // (1) Find the locals holding a `getenv` result, and the null checks of `getenv` calls,
+        // that `env::var` can replace.
+
+        let mut env_locals = HashSet::new();
+        visit_nodes(krate, |l: &Local| {
+            match_or!([l.pat.kind] PatKind::Ident(BindingMode::ByValue(_), _, None) => {}; return);
+            let init = match_or!([l.init] Some(ref x) => x; return);
+            if getenv_name(cx, init).is_some() {
+                env_locals.insert(cx.hir_map().node_to_hir_id(l.pat.id));
+            }
+        });
+
+        let mut handled = HashSet::new();
+        let mut negated_checks = HashSet::new();
+        let mut inline_checks = HashSet::new();
+        visit_nodes(krate, |e: &Expr| {
+            let u = match_or!([EnvUse::from_expr(e)] Some(x) => x; return);
+            handled.insert(u.ptr().id);
+            if let EnvUse::NullCheck(p, negated) = u {
+                // The check inside the negation is rewritten along with it.
+                if negated {
+                    if let ExprKind::Unary(_, ref inner) = e.kind {
+                        negated_checks.insert(inner.id);
+                    }
+                }
+                if getenv_name(cx, p).is_some() {
+                    inline_checks.insert(p.id);
+                }
+            }
+        });
+
+        let mut raw = HashSet::new();
+        visit_nodes(krate, |e: &Expr| {
+            if let ExprKind::Path(None, _) = e.kind {
+                if let Some(hir_id) = cx.try_resolve_expr_to_hid(e) {
+                    if env_locals.contains(&hir_id) && !handled.contains(&e.id) {
+                        raw.insert(hir_id);
+                    }
+                }
+            }
+        });
+        env_locals.retain(|hir_id| !raw.contains(hir_id));
+
+        // (2) Rewrite the `getenv` calls and the uses of their results.
+
+        MutVisitNodes::visit(krate, |l: &mut P<Local>| {
+            if !env_locals.contains(&cx.hir_map().node_to_hir_id(l.pat.id)) {
+                return;
+            }
+            let name = l.init.as_ref().and_then(|init| getenv_name(cx, init))
+                .map(|name| os_arg(name));
+            if let Some(name) = name {
+                l.ty = Some(var_ty.clone());
+                l.init = Some(subst(&var_repl, vec![("$name", name)]));
+            }
+        });
+
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            if negated_checks.contains(&e.id) || inline_checks.contains(&e.id) {
+                return;
+            }
+
+            let new_e = if let Some(u) = EnvUse::from_expr(e) {
+                let p = u.ptr();
+                let p = if inline_checks.contains(&p.id) {
+                    let name = os_arg(getenv_name(cx, p).unwrap());
+                    subst(&var_repl, vec![("$name", name)])
+                } else {
+                    match cx.try_resolve_expr_to_hid(p) {
+                        Some(hir_id) if env_locals.contains(&hir_id) => p.clone(),
+                        _ => return,
+                    }
+                };
+                let repl = match u {
+                    EnvUse::NullCheck(_, false) => &is_none_repl,
+                    EnvUse::NullCheck(_, true) => &is_some_repl,
+                    EnvUse::Bytes(_) => &bytes_repl,
+                    EnvUse::Str(_) => &str_repl,
+                };
+                subst(repl, vec![("$p", p)])
+            } else if let Some(name) = getenv_name(cx, e) {
+                need_cstring = true;
+                subst(&cstring_repl, vec![("$name", os_arg(name))])
+            } else {
+                return;
+            };
+            *e = new_e;
+        });
+
+        // (3) Rewrite the `setenv` and `unsetenv` statements.
+
+        MutVisitNodes::visit(krate, |b: &mut P<Block>| {
+            for s in &mut b.stmts {
+                let e = match_or!([s.kind] StmtKind::Semi(ref e) => e; continue);
+                let new_e = if let Some(args) = foreign_call(cx, e, "setenv") {
+                    if args.len() != 3 {
+                        continue;
+                    }
+                    let (name, value) = (os_arg(&args[0]), os_arg(&args[1]));
+                    let overwrite = strip_casts(&args[2]);
+                    let mut repl_args = vec![("$name", name), ("$value", value)];
+                    let repl = match lit_value(overwrite) {
+                        Some(0) => &set_unset_repl,
+                        Some(_) => &set_repl,
+                        None => {
+                            repl_args.push(("$overwrite", overwrite.clone()));
+                            &set_checked_repl
+                        }
+                    };
+                    subst(repl, repl_args)
+                } else if let Some(args) = foreign_call(cx, e, "unsetenv") {
+                    let name = match_or!([args.first()] Some(x) => x; continue);
+                    subst(&remove_repl, vec![("$name", os_arg(name))])
+                } else {
+                    continue;
+                };
+                let is_if = match new_e.kind {
+                    ExprKind::If(..) => true,
+                    _ => false,
+                };
+                s.kind = if is_if { StmtKind::Expr(new_e) } else { StmtKind::Semi(new_e) };
+            }
+        });
+
+        visit_nodes(krate, |e: &Expr| {
+            if foreign_call(cx, e, "setenv").is_some() ||
+               foreign_call(cx, e, "unsetenv").is_some() {
+                warn_skip("uses its result", e);
+            }
+        });
+
+        if need_cstring {
+            krate.module.items.extend(st.parse_items(cx, r#"
+                /// Read the environment variable `name` into a C string, or return null if it
+                /// isn't set, like `getenv`.  The string stays valid until the variable is read
+                /// again by the current thread after it changed.
+                #[cfg(unix)]
+                fn getenv_cstring<K: AsRef<::std::ffi::OsStr>>(name: K) -> *mut libc::c_char {
+                    thread_local! {
+                        static CACHE: ::std::cell::RefCell<::std::collections::HashMap<
+                            ::std::ffi::OsString, ::std::ffi::CString>> =
+                            ::std::cell::RefCell::new(::std::collections::HashMap::new());
+                    }
+                    let name = name.as_ref();
+                    let value = match ::std::env::var_os(name) {
+                        Some(x) => ::std::os::unix::ffi::OsStringExt::into_vec(x),
+                        None => return ::std::ptr::null_mut(),
+                    };
+                    let value = ::std::ffi::CString::new(value).unwrap();
+                    CACHE.with(|cache| {
+                        let mut cache = cache.borrow_mut();
+                        if cache.get(name) != Some(&value) {
+                            cache.insert(name.to_owned(), value);
+                        }
+                        cache[name].as_ptr() as *mut libc::c_char
+                    })
+                }
+            "#));
+        }
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
+pub fn register_commands(reg: &mut Registry) {
+    use super::mk;
+
+    reg.register("env_to_std", |_args| mk(EnvToStd));
+}
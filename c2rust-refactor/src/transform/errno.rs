@@ -0,0 +1,161 @@
+//! Transform that replaces uses of C `errno` with `std::io::Error::last_os_error`.
+
+use std::collections::{HashMap, HashSet};
+use rustc::hir::def::{DefKind, Res};
+use syntax::ast::*;
+use syntax::ptr::P;
+
+use crate::ast_manip::{MutVisitNodes, visit_nodes};
+use crate::command::{CommandState, Registry};
+use crate::driver::{Phase, parse_expr};
+use crate::transform::Transform;
+use crate::transform::canonicalize_refs::strip_parens;
+use crate::transform::mem::strip_casts;
+use crate::RefactorCtxt;
+
+
+/// # `errno_to_io_error` Command
+///
+/// Usage: `errno_to_io_error`
+///
+/// Replace reads of `errno`, spelled `*__errno_location()`, `*__error()` or
+/// `*_errno()` depending on the platform, with
+/// `::std::io::Error::last_os_error().raw_os_error().unwrap_or(0)`.
+///
+/// A comparison of `errno` with one of the constants below, as in
+/// `*__errno_location() == EINTR`, becomes a comparison of the error's kind
+/// instead, like
+/// `::std::io::Error::last_os_error().kind() == ::std::io::ErrorKind::Interrupted`.
+/// The constant may be a `const` of the crate or of `libc`:
+///
+/// | Constant | `ErrorKind` |
+/// |---|---|
+/// | `EINTR` | `Interrupted` |
+/// | `EAGAIN`, `EWOULDBLOCK` | `WouldBlock` |
+/// | `ENOENT` | `NotFound` |
+/// | `EEXIST` | `AlreadyExists` |
+/// | `EINVAL` | `InvalidInput` |
+/// | `EPIPE` | `BrokenPipe` |
+/// | `ETIMEDOUT` | `TimedOut` |
+/// | `ECONNREFUSED` | `ConnectionRefused` |
+/// | `ECONNRESET` | `ConnectionReset` |
+/// | `ECONNABORTED` | `ConnectionAborted` |
+/// | `ENOTCONN` | `NotConnected` |
+/// | `EADDRINUSE` | `AddrInUse` |
+/// | `EADDRNOTAVAIL` | `AddrNotAvailable` |
+///
+/// Comparisons with any other value keep comparing the raw error code.  This
+/// includes `EPERM` and `EACCES`, since both map to `PermissionDenied`.
+///
+/// Writes to `errno` are left alone with a warning, since Rust code shouldn't
+/// set it.
+pub struct ErrnoToIoError;
+
+/// The functions returning a pointer to `errno` on each platform.
+static ERRNO_FNS: &[&str] = &["__errno_location", "__error", "_errno"];
+
+/// The errno constants that `std` maps to an `io::ErrorKind` of their own, with that kind.
+static ERROR_KINDS: &[(&str, &str)] = &[
+    ("EINTR", "Interrupted"),
+    ("EAGAIN", "WouldBlock"),
+    ("EWOULDBLOCK", "WouldBlock"),
+    ("ENOENT", "NotFound"),
+    ("EEXIST", "AlreadyExists"),
+    ("EINVAL", "InvalidInput"),
+    ("EPIPE", "BrokenPipe"),
+    ("ETIMEDOUT", "TimedOut"),
+    ("ECONNREFUSED", "ConnectionRefused"),
+    ("ECONNRESET", "ConnectionReset"),
+    ("ECONNABORTED", "ConnectionAborted"),
+    ("ENOTCONN", "NotConnected"),
+    ("EADDRINUSE", "AddrInUse"),
+    ("EADDRNOTAVAIL", "AddrNotAvailable"),
+];
+
+/// Check if `e` is `*__errno_location()`, or one of the other spellings of `errno`.
+fn is_errno(cx: &RefactorCtxt, e: &Expr) -> bool {
+    let call = match_or!([e.kind] ExprKind::Unary(UnOp::Deref, ref c) => c; return false);
+    let name = match call.kind {
+        ExprKind::Call(ref func, ref args) if args.is_empty() => match func.kind {
+            ExprKind::Path(None, ref path) => path.segments.last().map(|seg| seg.ident.name),
+            _ => None,
+        },
+        _ => None,
+    };
+    let is_errno_fn = name.map_or(false, |name| ERRNO_FNS.iter().any(|&f| &*name.as_str() == f));
+    is_errno_fn && cx.opt_callee(call).map_or(false, |def_id| cx.ty_ctxt().is_foreign_item(def_id))
+}
+
+/// If `e` is one of the constants in `ERROR_KINDS`, possibly cast, return its `io::ErrorKind`.
+fn error_kind(cx: &RefactorCtxt, e: &P<Expr>) -> Option<&'static str> {
+    let e = strip_casts(e);
+    let name = match_or!([e.kind] ExprKind::Path(_, ref path) => path.segments.last()?.ident.name;
+                         return None);
+    match cx.try_resolve_expr_hir(e) {
+        Some(Res::Def(DefKind::Const, _)) => {}
+        _ => return None,
+    }
+    ERROR_KINDS.iter().find(|&&(c, _)| &*name.as_str() == c).map(|&(_, kind)| kind)
+}
+
+impl Transform for ErrnoToIoError {
+    fn transform(&self, krate: &mut Crate, _st: &CommandState, cx: &RefactorCtxt) {
+        // (1) Find the writes to `errno`, and the comparisons that can check the error kind.
+        // The `errno` in each of these is left for the comparison, or left alone.
+
+        let mut kind_checks = HashMap::new();
+        let mut skipped = HashSet::new();
+        visit_nodes(krate, |e: &Expr| {
+            match e.kind {
+                ExprKind::Assign(ref lhs, _) | ExprKind::AssignOp(_, ref lhs, _) |
+                ExprKind::AddrOf(_, Mutability::Mutable, ref lhs)
+                        if is_errno(cx, strip_parens(lhs)) => {
+                    warn!("errno_to_io_error: Rust code shouldn't set `errno`; leaving the write \
+                           at {} alone", cx.session().source_map().span_to_string(e.span));
+                    skipped.insert(strip_parens(lhs).id);
+                }
+                ExprKind::Binary(op, ref l, ref r)
+                        if op.node == BinOpKind::Eq || op.node == BinOpKind::Ne => {
+                    let (errno, kind) = if is_errno(cx, strip_parens(l)) {
+                        (l, error_kind(cx, r))
+                    } else if is_errno(cx, strip_parens(r)) {
+                        (r, error_kind(cx, l))
+                    } else {
+                        return;
+                    };
+                    if let Some(kind) = kind {
+                        kind_checks.insert(e.id, (op.node, kind));
+                        skipped.insert(strip_parens(errno).id);
+                    }
+                }
+                _ => {}
+            }
+        });
+
+        // (2) Rewrite the comparisons and the other reads.
+
+        let read_repl = parse_expr(cx.session(),
+                                   "::std::io::Error::last_os_error().raw_os_error().unwrap_or(0)");
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            if let Some(&(op, kind)) = kind_checks.get(&e.id) {
+                let op = if op == BinOpKind::Eq { "==" } else { "!=" };
+                *e = parse_expr(cx.session(), &format!(
+                    "::std::io::Error::last_os_error().kind() {} ::std::io::ErrorKind::{}",
+                    op, kind));
+            } else if !skipped.contains(&e.id) && is_errno(cx, e) {
+                *e = read_repl.clone();
+            }
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
+pub fn register_commands(reg: &mut Registry) {
+    use super::mk;
+
+    reg.register("errno_to_io_error", |_args| mk(ErrnoToIoError));
+}
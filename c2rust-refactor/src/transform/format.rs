@@ -1,10 +1,14 @@
 use std::collections::{HashMap, HashSet};
+use std::ptr;
 use std::str;
 use std::str::FromStr;
 use rustc_data_structures::sync::Lrc;
+use rustc::hir::HirId;
+use rustc::hir::def::Res;
 use rustc::hir::def_id::DefId;
 use syntax::ast::*;
 use syntax::attr;
+use syntax::print::pprust;
 use syntax::source_map::DUMMY_SP;
 use syntax::ptr::P;
 use syntax::token::{Token, TokenKind, Nonterminal};
@@ -15,7 +19,12 @@ use smallvec::smallvec;
 use c2rust_ast_builder::mk;
 use crate::ast_manip::{FlatMapNodes, MutVisitNodes, visit_nodes};
 use crate::command::{CommandState, Registry};
+use crate::driver::{self, Phase};
 use crate::transform::Transform;
+use crate::transform::assert::foreign_call;
+use crate::transform::enums::lit_value;
+use crate::transform::funcs::declared_fn_decls;
+use crate::transform::mem::{strip_casts, strip_ptr_conv};
 use crate::RefactorCtxt;
 
 
@@ -83,7 +92,7 @@ impl Transform for ConvertFormatArgs {
                     old_fmt_str_expr = Some(P(e.clone()));
                 }
             });
-            let mac = build_format_macro("format_args", None, old_fmt_str_expr, &args[fmt_idx..], None);
+            let mac = build_format_macro("format_args", None, None, old_fmt_str_expr, &args[fmt_idx..], None);
             let mut new_args = args[..fmt_idx].to_owned();
             new_args.push(mk().mac_expr(mac));
 
@@ -93,18 +102,9 @@ impl Transform for ConvertFormatArgs {
 }
 
 
-fn build_format_macro(
-    macro_name: &str,
-    ln_macro_name: Option<&str>,
-    old_fmt_str_expr: Option<P<Expr>>,
-    fmt_args: &[P<Expr>],
-    span: Option<Span>,
-) -> Mac {
-    let old_fmt_str_expr = old_fmt_str_expr.unwrap_or_else(|| fmt_args[0].clone());
-
-    info!("  found fmt str {:?}", old_fmt_str_expr);
-
-    let mut ep = &old_fmt_str_expr;
+/// Get the contents of the string literal `e`, peeling off any casts and `.as_ptr()` calls.
+fn fmt_str_lit(e: &P<Expr>) -> Option<String> {
+    let mut ep = e;
     let lit = loop {
         // Peel off any casts and retrieve the inner string
         match ep.kind {
@@ -115,12 +115,30 @@ fn build_format_macro(
             ExprKind::MethodCall(ref ps, ref args) if args.len() == 1 &&
                 (ps.ident.as_str() == "as_ptr" ||
                  ps.ident.as_str() == "as_mut_ptr") => ep = &args[0],
-            _ => panic!("unexpected format string: {:?}", old_fmt_str_expr)
+            _ => return None,
         }
     };
-    let s = expect!([lit.kind]
-        LitKind::Str(s, _) => (&s.as_str() as &str).to_owned(),
-        LitKind::ByteStr(ref b) => str::from_utf8(b).unwrap().to_owned());
+    match lit.kind {
+        LitKind::Str(s, _) => Some((&s.as_str() as &str).to_owned()),
+        LitKind::ByteStr(ref b) => str::from_utf8(b).ok().map(|s| s.to_owned()),
+        _ => None,
+    }
+}
+
+fn build_format_macro(
+    macro_name: &str,
+    ln_macro_name: Option<&str>,
+    dest: Option<P<Expr>>,
+    old_fmt_str_expr: Option<P<Expr>>,
+    fmt_args: &[P<Expr>],
+    span: Option<Span>,
+) -> Mac {
+    let old_fmt_str_expr = old_fmt_str_expr.unwrap_or_else(|| fmt_args[0].clone());
+
+    info!("  found fmt str {:?}", old_fmt_str_expr);
+
+    let s = fmt_str_lit(&old_fmt_str_expr)
+        .unwrap_or_else(|| panic!("unexpected format string: {:?}", old_fmt_str_expr));
 
     let mut new_s = String::with_capacity(s.len());
     let mut casts = HashMap::new();
@@ -179,6 +197,11 @@ fn build_format_macro(
             span,
         })
     };
+    if let Some(dest) = dest {
+        // `write!(dest, ...)`
+        macro_tts.push(expr_tt(dest));
+        macro_tts.push(TokenTree::Token(Token {kind: TokenKind::Comma, span: DUMMY_SP}));
+    }
     macro_tts.push(expr_tt(new_fmt_str_expr));
     for (i, arg) in fmt_args[1..].iter().enumerate() {
         if let Some(cast) = casts.get(&i) {
@@ -252,11 +275,11 @@ impl Transform for ConvertPrintfs {
                         match (cx.try_resolve_expr(f), cx.try_resolve_expr(&*args[0])) {
                             (Some(ref f_id), Some(ref arg0_id)) if fprintf_defs.contains(f_id) &&
                                 stderr_defs.contains(arg0_id) => {
-                                let mac = build_format_macro("eprint", Some("eprintln"), None, &args[1..], Some(expr.span));
+                                let mac = build_format_macro("eprint", Some("eprintln"), None, None, &args[1..], Some(expr.span));
                                 return smallvec![mk().span(s.span).mac_stmt(mac)];
                             }
                             (Some(ref f_id), _) if printf_defs.contains(f_id) => {
-                                let mac = build_format_macro("print", Some("println"), None, &args[..], Some(expr.span));
+                                let mac = build_format_macro("print", Some("println"), None, None, &args[..], Some(expr.span));
                                 return smallvec![mk().span(s.span).mac_stmt(mac)];
                             },
                             _ => {}
@@ -271,6 +294,361 @@ impl Transform for ConvertPrintfs {
 }
 
 
+/// # `snprintf_chain_to_string` Command
+///
+/// Usage: `snprintf_chain_to_string`
+///
+/// Marks: none
+///
+/// Converts chains of `snprintf` calls appending to the same fixed-size buffer into building a
+/// `String`.  A chain starts with `n = snprintf(buf, ...)`, or with `n += snprintf(buf + n, ...)`
+/// right after `let n = 0`, and goes on with more `n += snprintf(buf + n, sizeof(buf) - n, ...)`
+/// statements.  The buffer becomes a `String`, and each append becomes a `write!` call, or a
+/// `push_str` if it has no format arguments.
+///
+/// The uses of the buffer after the chain are converted too: `puts(buf);` becomes
+/// `println!("{}", buf);` and `strlen(buf)` becomes `buf.len()`, while any other call gets a
+/// `CString` made from the `String`.  If the offset is used after the chain, it's set to the
+/// length of the `String`.  A chain is left alone if the buffer has any other use after it.
+///
+/// Unlike `snprintf`, the `String` grows as needed, so output the C code truncated to the size
+/// of the buffer is now kept whole.  A warning notes this for each chain converted.
+///
+/// Example:
+///
+/// ```ignore
+///     let mut buf: [libc::c_char; 64] = [0; 64];
+///     let mut n: libc::c_int = 0;
+///     n = snprintf(buf.as_mut_ptr(), 64, b"x=%d\x00" as *const u8 as *const libc::c_char, x);
+///     n += snprintf(buf.as_mut_ptr().offset(n as isize), (64 - n) as libc::c_ulong,
+///                   b", y=%d\x00" as *const u8 as *const libc::c_char, y);
+///     puts(buf.as_mut_ptr());
+/// ```
+///
+/// gets converted to:
+///
+/// ```ignore
+///     let mut n: libc::c_int = 0;
+///     use std::fmt::Write;
+///     let mut buf = String::new();
+///     write!(buf, "x={:}", x as libc::c_int).unwrap();
+///     write!(buf, ", y={:}", y as libc::c_int).unwrap();
+///     println!("{}", buf);
+/// ```
+pub struct SnprintfChainToString;
+
+/// An `off = snprintf(buf, ...)` or `off += snprintf(buf + off, ...)` statement.
+struct SnprintfAppend<'a> {
+    buf: HirId,
+    off: HirId,
+    off_expr: &'a P<Expr>,
+    /// Whether this is the `off = ...` form, which starts the string over.
+    starts: bool,
+    /// The format string and its arguments.
+    fmt_args: &'a [P<Expr>],
+}
+
+/// If `e` is a path to a local variable, return its `HirId`.
+fn local_hid(cx: &RefactorCtxt, e: &Expr) -> Option<HirId> {
+    match e.kind {
+        ExprKind::Path(None, _) => match cx.try_resolve_expr_hir(e)? {
+            Res::Local(hid) => Some(hid),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// If `e` is `buf.as_ptr()` or `buf.as_mut_ptr()`, possibly cast, return the `HirId` of the
+/// local `buf`.
+fn buf_ptr(cx: &RefactorCtxt, e: &P<Expr>) -> Option<HirId> {
+    let e = strip_casts(e);
+    let buf = strip_ptr_conv(e);
+    if ptr::eq(buf, e) {
+        return None;
+    }
+    local_hid(cx, buf)
+}
+
+fn snprintf_append<'a>(cx: &RefactorCtxt, s: &'a Stmt) -> Option<SnprintfAppend<'a>> {
+    let e = match_or!([s.kind] StmtKind::Semi(ref e) => e; return None);
+    let (starts, lhs, rhs) = match e.kind {
+        ExprKind::Assign(ref lhs, ref rhs) => (true, lhs, rhs),
+        ExprKind::AssignOp(op, ref lhs, ref rhs) if op.node == BinOpKind::Add => (false, lhs, rhs),
+        _ => return None,
+    };
+    let off = local_hid(cx, lhs)?;
+    let args = foreign_call(cx, strip_casts(rhs), "snprintf")?;
+    if args.len() < 3 || fmt_str_lit(&args[2]).is_none() {
+        return None;
+    }
+    let dst = if starts {
+        &args[0]
+    } else {
+        // `buf.as_mut_ptr().offset(off as isize)`
+        let (seg, margs) = match_or!([strip_casts(&args[0]).kind]
+            ExprKind::MethodCall(ref seg, ref margs) => (seg, margs); return None);
+        if &*seg.ident.as_str() != "offset" || margs.len() != 2 ||
+           local_hid(cx, strip_casts(&margs[1])) != Some(off) {
+            return None;
+        }
+        &margs[0]
+    };
+    Some(SnprintfAppend {
+        buf: buf_ptr(cx, dst)?,
+        off,
+        off_expr: lhs,
+        starts,
+        fmt_args: &args[2..],
+    })
+}
+
+/// Try to convert the chain of `snprintf` appends starting at `stmts[start]`.  On success,
+/// return the new statements of the block, and the index of the first one after the chain.
+fn convert_snprintf_chain(
+    cx: &RefactorCtxt,
+    decls: &HashMap<DefId, P<FnDecl>>,
+    stmts: &[Stmt],
+    start: usize,
+    have_write: &mut bool,
+) -> Option<(Vec<Stmt>, usize)> {
+    let first = snprintf_append(cx, &stmts[start])?;
+    if !first.starts {
+        // `off += ...` only starts a chain right after `let off = 0`.
+        let zero_init = start > 0 && match stmts[start - 1].kind {
+            StmtKind::Local(ref l) =>
+                cx.hir_map().opt_node_to_hir_id(l.pat.id) == Some(first.off) &&
+                l.init.as_ref().and_then(|init| lit_value(init)) == Some(0),
+            _ => false,
+        };
+        if !zero_init {
+            return None;
+        }
+    }
+    let mut end = start + 1;
+    while end < stmts.len() {
+        match snprintf_append(cx, &stmts[end]) {
+            Some(ref a) if !a.starts && a.buf == first.buf && a.off == first.off => end += 1,
+            _ => break,
+        }
+    }
+    if end - start < 2 {
+        return None;
+    }
+
+    // The buffer must be declared in this block, so all of its later uses are in `rest`.
+    let (decl, name) = stmts[..start].iter().enumerate().find_map(|(i, s)| {
+        let l = match_or!([s.kind] StmtKind::Local(ref l) => l; return None);
+        let ident = match_or!([l.pat.kind] PatKind::Ident(_, ident, None) => ident; return None);
+        if cx.hir_map().opt_node_to_hir_id(l.pat.id) == Some(first.buf) {
+            Some((i, ident))
+        } else {
+            None
+        }
+    })?;
+
+    let count_uses = |stmts: &[Stmt], hid: HirId| {
+        let mut uses = 0;
+        for s in stmts {
+            visit_nodes(s, |e: &Expr| {
+                if local_hid(cx, e) == Some(hid) {
+                    uses += 1;
+                }
+            });
+        }
+        uses
+    };
+
+    // Sort out the uses of the buffer after the chain.
+    let rest = &stmts[end..];
+    let mut printed = HashSet::new();
+    let mut measured = HashMap::new();
+    let mut passed = HashMap::new();
+    for s in rest {
+        visit_nodes(s, |s: &Stmt| {
+            if let StmtKind::Semi(ref e) = s.kind {
+                match foreign_call(cx, e, "puts") {
+                    Some(args) if args.len() == 1 && buf_ptr(cx, &args[0]) == Some(first.buf) => {
+                        printed.insert(e.id);
+                    }
+                    _ => {}
+                }
+            }
+        });
+        visit_nodes(s, |e: &Expr| {
+            // Casts use the types from the declared signatures of the callees.
+            let decl = cx.opt_callee(e).and_then(|def_id| decls.get(&def_id));
+            match foreign_call(cx, e, "strlen") {
+                Some(args) if args.len() == 1 && buf_ptr(cx, &args[0]) == Some(first.buf) => {
+                    let ret_ty = decl.and_then(|decl| match decl.output {
+                        FunctionRetTy::Ty(ref ty) => Some(ty.clone()),
+                        FunctionRetTy::Default(_) => None,
+                    });
+                    measured.insert(e.id, ret_ty);
+                    return;
+                }
+                _ => {}
+            }
+            if printed.contains(&e.id) {
+                return;
+            }
+            if let ExprKind::Call(_, ref args) = e.kind {
+                for (i, arg) in args.iter().enumerate() {
+                    if buf_ptr(cx, arg) == Some(first.buf) {
+                        let param_ty = decl.and_then(|decl| decl.inputs.get(i))
+                            .map(|param| param.ty.clone());
+                        passed.insert(strip_casts(arg).id, param_ty);
+                    }
+                }
+            }
+        });
+    }
+    if count_uses(rest, first.buf) != printed.len() + measured.len() + passed.len() {
+        warn!("snprintf_chain_to_string: `{}` has other uses after the appends; skipping", name);
+        return None;
+    }
+    warn!("snprintf_chain_to_string: `{}` is now a `String`, which is not truncated to the \
+           size of the buffer like the output of `snprintf`", name);
+
+    // Build the `String` in place of the chain.
+    let mut new_stmts = stmts[..start].to_owned();
+    if count_uses(&stmts[decl + 1..start], first.buf) == 0 {
+        new_stmts.remove(decl);
+    }
+    let buf_expr = mk().ident_expr(name);
+    let mut appends = Vec::with_capacity(end - start);
+    let mut need_write = false;
+    for s in &stmts[start..end] {
+        let a = snprintf_append(cx, s).unwrap();
+        let fmt = fmt_str_lit(&a.fmt_args[0]).unwrap();
+        if a.fmt_args.len() == 1 && !fmt.contains('%') {
+            let text = mk().lit_expr(fmt.trim_end_matches('\0'));
+            let call = mk().method_call_expr(buf_expr.clone(), "push_str", vec![text]);
+            appends.push(mk().semi_stmt(call));
+        } else {
+            let mac = build_format_macro(
+                "write", Some("writeln"), Some(buf_expr.clone()), None, a.fmt_args, None);
+            let call = mk().method_call_expr(mk().mac_expr(mac), "unwrap", Vec::<P<Expr>>::new());
+            appends.push(mk().semi_stmt(call));
+            need_write = true;
+        }
+    }
+    if need_write && !*have_write {
+        new_stmts.extend(driver::parse_stmts(cx.session(), "use std::fmt::Write;"));
+        *have_write = true;
+    }
+    new_stmts.extend(driver::parse_stmts(
+        cx.session(), &format!("let mut {} = String::new();", name)));
+    new_stmts.extend(appends);
+    if count_uses(rest, first.off) > 0 {
+        // Cast to the declared type of the offset, or let the assignment infer it.
+        let off_ty = stmts[..start].iter().find_map(|s| match s.kind {
+            StmtKind::Local(ref l) if cx.hir_map().opt_node_to_hir_id(l.pat.id) ==
+                    Some(first.off) => l.ty.as_ref().map(|ty| pprust::ty_to_string(ty)),
+            _ => None,
+        }).unwrap_or_else(|| "_".to_owned());
+        new_stmts.extend(driver::parse_stmts(cx.session(), &format!(
+            "{} = {}.len() as {};",
+            pprust::expr_to_string(first.off_expr), name, off_ty)));
+    }
+    let c_name = format!("{}_c", name);
+    if !passed.is_empty() {
+        new_stmts.extend(driver::parse_stmts(cx.session(), &format!(
+            "let {} = ::std::ffi::CString::new({}.as_str()).unwrap();", c_name, name)));
+    }
+
+    // Convert the uses of the buffer.
+    let mut rest = rest.to_owned();
+    FlatMapNodes::visit(&mut rest, |s: Stmt| {
+        let is_printed = match s.kind {
+            StmtKind::Semi(ref e) => printed.contains(&e.id),
+            _ => false,
+        };
+        if is_printed {
+            driver::parse_stmts(cx.session(), &format!("println!(\"{{}}\", {});", name))
+                .into_iter().collect()
+        } else {
+            smallvec![s]
+        }
+    });
+    MutVisitNodes::visit(&mut rest, |e: &mut P<Expr>| {
+        let src = if let Some(ret_ty) = measured.get(&e.id) {
+            let ty = ret_ty.as_ref()
+                .map_or("libc::size_t".to_owned(), |ty| pprust::ty_to_string(ty));
+            format!("{}.len() as {}", name, ty)
+        } else if let Some(param_ty) = passed.get(&e.id) {
+            let is_mut = match e.kind {
+                ExprKind::MethodCall(ref seg, _) => &*seg.ident.as_str() == "as_mut_ptr",
+                _ => false,
+            };
+            if is_mut {
+                let ty = param_ty.as_ref()
+                    .map_or("*mut libc::c_char".to_owned(), |ty| pprust::ty_to_string(ty));
+                format!("{}.as_ptr() as {}", c_name, ty)
+            } else {
+                format!("{}.as_ptr()", c_name)
+            }
+        } else {
+            return;
+        };
+        *e = driver::parse_expr(cx.session(), &src);
+    });
+
+    let next = new_stmts.len();
+    new_stmts.extend(rest);
+    Some((new_stmts, next))
+}
+
+impl Transform for SnprintfChainToString {
+    fn transform(&self, krate: &mut Crate, _st: &CommandState, cx: &RefactorCtxt) {
+        let decls = declared_fn_decls(cx, krate);
+        MutVisitNodes::visit(krate, |b: &mut P<Block>| {
+            let mut have_write = false;
+            let mut i = 0;
+            while i < b.stmts.len() {
+                match convert_snprintf_chain(cx, &decls, &b.stmts, i, &mut have_write) {
+                    Some((stmts, next)) => {
+                        b.stmts = stmts;
+                        i = next;
+                    }
+                    None => i += 1,
+                }
+            }
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
+/// Build `unsafe { CStr::from_ptr(e as *const libc::c_char).to_str().unwrap() }`, to read the C
+/// string `e` as a `&str`.
+pub(super) fn c_str_to_str(e: P<Expr>) -> P<Expr> {
+    let e = mk().cast_expr(e, mk().ptr_ty(mk().path_ty(vec!["libc", "c_char"])));
+    let cs = mk().call_expr(
+        // TODO(kkysen) change `"std"` to `"core"` after `#![feature(core_c_str)]` is stabilized in `1.63.0`
+        mk().path_expr(vec!["std", "ffi", "CStr", "from_ptr"]),
+        vec![e]);
+    let s = mk().method_call_expr(cs, "to_str", Vec::new());
+    let call = mk().method_call_expr(s, "unwrap", Vec::new());
+    let b = mk().unsafe_().block(vec![mk().expr_stmt(call)]);
+    mk().block_expr(b)
+}
+
+/// Build `unsafe { CStr::from_ptr(e as *const libc::c_char).to_bytes() }`, to read the C string
+/// `e` as a `&[u8]`.
+pub(super) fn c_str_to_bytes(e: P<Expr>) -> P<Expr> {
+    let e = mk().cast_expr(e, mk().ptr_ty(mk().path_ty(vec!["libc", "c_char"])));
+    let cs = mk().call_expr(
+        mk().path_expr(vec!["std", "ffi", "CStr", "from_ptr"]),
+        vec![e]);
+    let bytes = mk().method_call_expr(cs, "to_bytes", Vec::new());
+    let b = mk().unsafe_().block(vec![mk().expr_stmt(bytes)]);
+    mk().block_expr(b)
+}
+
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 enum CastType {
     Int(Length),
@@ -299,16 +677,9 @@ impl CastType {
                 mk().span(span).cast_expr(e, mk().ident_ty("char"))
             },
             CastType::Str => {
-                // CStr::from_ptr(e as *const libc::c_char).to_str().unwrap()
-                let e = mk().cast_expr(e, mk().ptr_ty(mk().path_ty(vec!["libc", "c_char"])));
-                let cs = mk().call_expr(
-                    // TODO(kkysen) change `"std"` to `"core"` after `#![feature(core_c_str)]` is stabilized in `1.63.0`
-                    mk().path_expr(vec!["std", "ffi", "CStr", "from_ptr"]),
-                    vec![e]);
-                let s = mk().method_call_expr(cs, "to_str", Vec::new());
-                let call = mk().method_call_expr(s, "unwrap", Vec::new());
-                let b = mk().unsafe_().block(vec![mk().expr_stmt(call)]);
-                mk().span(span).block_expr(b)
+                let mut e = c_str_to_str(e);
+                e.span = span;
+                e
             },
         }
     }
@@ -587,4 +958,5 @@ pub fn register_commands(reg: &mut Registry) {
 
     reg.register("convert_format_args", |_args| mk(ConvertFormatArgs));
     reg.register("convert_printfs", |_| mk(ConvertPrintfs));
+    reg.register("snprintf_chain_to_string", |_| mk(SnprintfChainToString));
 }
@@ -1,21 +1,34 @@
 use std::collections::{HashMap, HashSet};
-use rustc::hir::def_id::DefId;
+use std::mem;
+use regex::Regex;
+use rustc::hir::{self, HirId};
+use rustc::hir::def::DefKind;
+use rustc::hir::def_id::{DefId, LOCAL_CRATE};
 use rustc::ty::TyKind;
 use syntax::ast;
 use syntax::ast::*;
 use syntax::attr;
 use syntax::mut_visit::{self, MutVisitor};
+use syntax::print::pprust;
 use syntax::ptr::P;
-use syntax_pos::sym;
+use syntax::util::classify::expr_requires_semi_to_be_stmt;
+use syntax::visit::{self, Visitor};
+use syntax_pos::{sym, Span};
 use smallvec::{smallvec, SmallVec};
 
 use c2rust_ast_builder::{mk, IntoSymbol};
-use crate::ast_manip::{FlatMapNodes, MutVisitNodes, fold_modules, visit_nodes, MutVisit};
-use crate::command::{CommandState, Registry};
-use crate::driver::{Phase, parse_expr};
-use crate::matcher::{BindingType, MatchCtxt, Subst, mut_visit_match_with};
+use crate::ast_manip::{FlatMapNodes, MutVisitNodes, fold_modules, fold_output_exprs, visit_nodes};
+use crate::ast_manip::MutVisit;
+use crate::ast_manip::fn_edit::{mut_visit_fns, visit_fns};
+use crate::command::{Command, CommandState, RefactorState, Registry};
+use crate::driver::{Phase, parse_expr, parse_ty};
+use crate::matcher::{BindingType, Bindings, MatchCtxt, Subst, mut_visit_match_with};
 use crate::path_edit::{fold_resolved_paths, fold_resolved_paths_with_id};
 use crate::transform::Transform;
+use crate::transform::items::prune_use_tree;
+use crate::transform::mem::{is_zero_lit, strip_casts};
+use crate::transform::null_ptrs::is_null_ptr;
+use crate::transform::vars::is_pure_expr;
 use crate::util::Lone;
 use crate::RefactorCtxt;
 
@@ -263,6 +276,323 @@ impl Transform for ToMethod {
 }
 
 
+/// # `prefix_to_methods` Command
+///
+/// Usage: `prefix_to_methods type=TYPE prefix=PREFIX`
+///
+/// Move the free functions whose names start with `PREFIX` into an inherent
+/// `impl` of the struct, union or enum `TYPE`, naming them without the prefix,
+/// so that `widget_draw` becomes `widget::draw`.  The functions are added to an
+/// existing `impl TYPE`, or to a new one after the type.
+///
+/// A function whose first argument is a `*mut TYPE`, `*const TYPE`, `&mut TYPE`
+/// or `&TYPE` becomes a method taking `&mut self` or `&self`.  In its body, `*w`
+/// becomes `*self`, and other uses of a pointer argument become `self as *mut
+/// TYPE`.  Calls like `widget_draw(w, x)` become `(*w).draw(x)` for a pointer
+/// `w`, and `w.draw(x)` otherwise.  Other functions become associated
+/// functions, called as `widget::create(x)`.
+///
+/// Functions marked `#[no_mangle]` keep their symbol: a wrapper with the
+/// original name and signature that forwards to the new function is left in
+/// their place.  Other functions are skipped with a warning if they are used
+/// other than by calling them, and so are functions whose name without the
+/// prefix is a keyword.  Paths in the moved bodies are not adjusted, so the
+/// functions should be declared in the same module as `TYPE`.
+pub struct PrefixToMethods {
+    pub ty: String,
+    pub prefix: String,
+}
+
+/// A function that `prefix_to_methods` moves into the `impl`.
+struct PrefixFn {
+    ident: Ident,
+    new_ident: Ident,
+    /// For a function that becomes a method, whether its first argument is a raw pointer, and
+    /// whether it is mutable.
+    self_arg: Option<(bool, bool)>,
+    is_unsafe: bool,
+    exported: bool,
+}
+
+impl Transform for PrefixToMethods {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        // (1) Find the type.
+
+        let mut ty_id = None;
+        visit_nodes(krate, |i: &Item| {
+            match i.kind {
+                ItemKind::Struct(..) | ItemKind::Union(..) | ItemKind::Enum(..) => {
+                    if ty_id.is_none() && &*i.ident.as_str() == self.ty {
+                        ty_id = Some(i.id);
+                    }
+                }
+                _ => {}
+            }
+        });
+        let ty_id = match ty_id {
+            Some(id) => id,
+            None => {
+                warn!("prefix_to_methods: found no type named `{}`", self.ty);
+                return;
+            }
+        };
+        let ty_def_id = cx.node_def_id(ty_id);
+        let ty_path = cx.def_path(ty_def_id);
+        let self_ty = cx.def_type(ty_def_id);
+
+        // (2) Find the functions to move.
+
+        let mut fns = HashMap::new();
+        visit_nodes(krate, |i: &Item| {
+            let sig = match_or!([i.kind] ItemKind::Fn(ref sig, ..) => sig; return);
+            let name = i.ident.as_str();
+            if !name.starts_with(&self.prefix) || name.len() == self.prefix.len() {
+                return;
+            }
+            let new_ident = Ident::from_str(&name[self.prefix.len()..]);
+            if new_ident.is_reserved() {
+                warn!("prefix_to_methods: `{}` without its prefix is a keyword; skipping",
+                      i.ident);
+                return;
+            }
+            if !sig.decl.inputs.iter().all(|arg| matches!([arg.pat.kind] PatKind::Ident(..))) {
+                warn!("prefix_to_methods: `{}` has an argument that isn't a plain name; skipping",
+                      i.ident);
+                return;
+            }
+
+            let self_arg = sig.decl.inputs.get(0).and_then(|arg| {
+                match cx.node_type(arg.pat.id).kind {
+                    TyKind::RawPtr(mt) if mt.ty == self_ty =>
+                        Some((true, mt.mutbl == hir::Mutability::Mutable)),
+                    TyKind::Ref(_, ty, mutbl) if ty == self_ty =>
+                        Some((false, mutbl == hir::Mutability::Mutable)),
+                    _ => None,
+                }
+            });
+            fns.insert(cx.node_def_id(i.id), PrefixFn {
+                ident: i.ident,
+                new_ident,
+                self_arg,
+                is_unsafe: sig.header.unsafety == Unsafety::Unsafe,
+                exported: attr::contains_name(&i.attrs, sym::no_mangle),
+            });
+        });
+
+        // Exported functions keep a wrapper that other uses can refer to.  The others must only
+        // be called.
+        let mut callees = HashSet::new();
+        visit_nodes(krate, |e: &Expr| {
+            if let ExprKind::Call(ref func, _) = e.kind {
+                callees.insert(func.id);
+            }
+        });
+        visit_nodes(krate, |e: &Expr| {
+            if let ExprKind::Path(..) = e.kind {
+                if callees.contains(&e.id) {
+                    return;
+                }
+                let def_id = match_or!([cx.try_resolve_expr(e)] Some(x) => x; return);
+                if fns.get(&def_id).map_or(false, |f| !f.exported) {
+                    warn!("prefix_to_methods: `{}` is used other than by calling it; skipping",
+                          fns[&def_id].ident);
+                    fns.remove(&def_id);
+                }
+            }
+        });
+        if fns.is_empty() {
+            return;
+        }
+
+        // (3) Find the uses of the arguments that become `self`.
+
+        let mut self_uses = HashSet::new();
+        let mut self_derefs = HashSet::new();
+        visit_nodes(krate, |i: &Item| {
+            let (sig, block) = match_or!([i.kind] ItemKind::Fn(ref sig, _, ref block) =>
+                                         (sig, block); return);
+            match fns.get(&cx.node_def_id(i.id)) {
+                Some(f) if f.self_arg.is_some() => {}
+                _ => return,
+            }
+            let arg_hir_id = cx.hir_map().node_to_hir_id(sig.decl.inputs[0].pat.id);
+            visit_nodes(&**block, |e: &Expr| {
+                if let ExprKind::Path(..) = e.kind {
+                    if cx.try_resolve_expr_to_hid(e) == Some(arg_hir_id) {
+                        self_uses.insert(e.id);
+                    }
+                }
+            });
+            visit_nodes(&**block, |e: &Expr| {
+                if let ExprKind::Unary(UnOp::Deref, ref inner) = e.kind {
+                    if self_uses.contains(&inner.id) {
+                        self_derefs.insert(e.id);
+                    }
+                }
+            });
+        });
+
+        // (4) Rewrite the calls.
+
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            let (def_id, mut args) = match e.kind {
+                ExprKind::Call(ref func, ref args) if func.id != DUMMY_NODE_ID => {
+                    match_or!([cx.try_resolve_expr(func)] Some(x) => (x, args.clone()); return)
+                }
+                _ => return,
+            };
+            let f = match_or!([fns.get(&def_id)] Some(x) => x; return);
+
+            let mut needs_unsafe = false;
+            e.kind = match f.self_arg {
+                Some(_) => {
+                    let recv = args.remove(0);
+                    let recv = match cx.opt_node_type(recv.id).map(|ty| &ty.kind) {
+                        _ if self_uses.contains(&recv.id) => parse_expr(cx.session(), "self"),
+                        Some(TyKind::RawPtr(_)) => {
+                            needs_unsafe = !f.is_unsafe;
+                            mk().paren_expr(mk().unary_expr("*", recv))
+                        }
+                        _ => recv,
+                    };
+                    args.insert(0, recv);
+                    ExprKind::MethodCall(mk().path_segment(&f.new_ident), args)
+                }
+                None => {
+                    let mut path = ty_path.clone();
+                    path.segments.push(mk().path_segment(&f.new_ident));
+                    ExprKind::Call(mk().path_expr(path), args)
+                }
+            };
+            if needs_unsafe {
+                let call = e.clone();
+                *e = mk().block_expr(mk().unsafe_().block(vec![mk().expr_stmt(call)]));
+            }
+        });
+
+        // (5) Turn the functions into methods, leaving wrappers for the exported ones.
+
+        let mut methods = Vec::new();
+        FlatMapNodes::visit(krate, |i: P<Item>| {
+            let f = match_or!([fns.get(&cx.node_def_id(i.id))] Some(x) => x; return smallvec![i]);
+            let (mut sig, generics, mut block) = expect!([i.kind]
+                ItemKind::Fn(ref sig, ref generics, ref block) =>
+                (sig.clone(), generics.clone(), block.clone()));
+
+            let names = sig.decl.inputs.iter()
+                .map(|arg| expect!([arg.pat.kind] PatKind::Ident(_, ident, _) => ident.to_string()))
+                .collect::<Vec<_>>();
+            let call_src = match f.self_arg {
+                Some((is_ptr, _)) => {
+                    let call = format!("{}{}{}.{}({})",
+                                       if is_ptr { "(*" } else { "" }, names[0],
+                                       if is_ptr { ")" } else { "" }, f.new_ident,
+                                       names[1..].join(", "));
+                    if is_ptr && !f.is_unsafe { format!("unsafe {{ {} }}", call) } else { call }
+                }
+                None => format!("{}::{}({})", pprust::path_to_string(&ty_path), f.new_ident,
+                                names.join(", ")),
+            };
+
+            if let Some((is_ptr, mutable)) = f.self_arg {
+                let ptr_src = format!("self as {}", pprust::ty_to_string(&sig.decl.inputs[0].ty));
+                MutVisitNodes::visit(&mut block, |e: &mut P<Expr>| {
+                    if self_derefs.contains(&e.id) {
+                        *e = parse_expr(cx.session(), "*self");
+                    } else if self_uses.contains(&e.id) {
+                        *e = parse_expr(cx.session(), if is_ptr { &ptr_src } else { "self" });
+                    }
+                });
+                let mutbl = if mutable { Mutability::Mutable } else { Mutability::Immutable };
+                sig.decl = sig.decl.map(|mut decl| {
+                    decl.inputs[0] = mk().self_arg(SelfKind::Region(None, mutbl));
+                    decl
+                });
+            }
+            sig.header.ext = Extern::None;
+            methods.push(ImplItem {
+                id: DUMMY_NODE_ID,
+                ident: f.new_ident,
+                vis: i.vis.clone(),
+                defaultness: Defaultness::Final,
+                attrs: i.attrs.iter().filter(|a| !a.check_name(sym::no_mangle)).cloned().collect(),
+                generics,
+                kind: ImplItemKind::Method(sig, block),
+                span: i.span,
+                tokens: None,
+            });
+
+            if !f.exported {
+                return smallvec![];
+            }
+            let body = mk().block(vec![mk().expr_stmt(parse_expr(cx.session(), &call_src))]);
+            smallvec![i.map(|mut i| {
+                if let ItemKind::Fn(_, _, ref mut block) = i.kind {
+                    *block = body;
+                }
+                i
+            })]
+        });
+
+        // (6) Add the methods to the type's `impl`, creating one after the type if needed.
+
+        let mut methods_by_ty = HashMap::new();
+        methods_by_ty.insert(ty_def_id, methods);
+        add_inherent_methods(krate, st, cx, methods_by_ty);
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
+/// Add `methods` to the inherent `impl` of each struct, union or enum they're keyed by, creating
+/// one after the type if it has none.
+fn add_inherent_methods(krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt,
+                        mut methods: HashMap<DefId, Vec<ImplItem>>) {
+    let mut impl_tys = HashMap::new();
+    let mut has_impl = HashSet::new();
+    visit_nodes(krate, |i: &Item| {
+        if let ItemKind::Impl(_, _, _, _, None, ref ty, _) = i.kind {
+            match cx.try_resolve_ty(ty) {
+                Some(def_id) if methods.contains_key(&def_id) && has_impl.insert(def_id) => {
+                    impl_tys.insert(i.id, def_id);
+                }
+                _ => {}
+            }
+        }
+    });
+
+    FlatMapNodes::visit(krate, |i: P<Item>| {
+        let add = |i: P<Item>, new_items: Vec<ImplItem>| i.map(|mut i| {
+            if let ItemKind::Impl(_, _, _, _, _, _, ref mut items) = i.kind {
+                items.extend(new_items);
+            }
+            i
+        });
+        if let Some(def_id) = impl_tys.get(&i.id) {
+            let new_items = methods.remove(def_id).unwrap_or_default();
+            return smallvec![add(i, new_items)];
+        }
+        match i.kind {
+            ItemKind::Struct(..) | ItemKind::Union(..) | ItemKind::Enum(..) => {}
+            _ => return smallvec![i],
+        }
+        let def_id = cx.node_def_id(i.id);
+        if has_impl.contains(&def_id) {
+            return smallvec![i];
+        }
+        match methods.remove(&def_id) {
+            Some(new_items) => {
+                let new_impl = st.parse_items(cx, &format!("impl {} {{}}", i.ident)).lone();
+                smallvec![i, add(new_impl, new_items)]
+            }
+            None => smallvec![i],
+        }
+    });
+}
+
 /// # `fix_unused_unsafe` Command
 ///
 /// Usage: `fix_unused_unsafe`
@@ -358,117 +688,629 @@ impl Transform for SinkUnsafe {
 }
 
 
-/// # `wrap_extern` Command
-///
-/// Usage: `wrap_extern`
-///
-/// Marks: `target`, `dest`
-///
-/// For each foreign function marked `target`, generate a wrapper function in the
-/// module marked `dest`, and rewrite all uses of the function to call the wrapper
-/// instead.
-///
-///
-/// Example:
-///
-/// ```ignore
-///     extern "C" {
-///         fn foo(x: i32) -> i32;
-///     }
-///
-///     mod wrappers {
-///         // empty
-///     }
-///
-///     fn main() {
-///         let x = unsafe { foo(123) };
-///     }
-/// ```
-///
-/// After transformation, with `fn foo` marked `target` and `mod wrappers` marked
-/// `dest`:
-///
-/// ```ignore
-///     extern "C" {
-///         fn foo(x: i32) -> i32;
-///     }
+/// # `remove_unneeded_unsafe` Command
 ///
-///     mod wrappers {
-///         unsafe fn foo(x: i32) -> i32 {
-///             ::foo(x)
-///         }
-///     }
+/// Usage: `remove_unneeded_unsafe`
 ///
-///     fn main() {
-///         let x = unsafe { ::wrappers::foo(123) };
-///     }
-/// ```
+/// Remove the `unsafe` qualifier from functions whose bodies don't perform any
+/// unsafe operation outside of `unsafe` blocks, and turn `unsafe` blocks that
+/// contain no unsafe operation into ordinary blocks.  Unsafe operations are
+/// dereferences of raw pointers, union field accesses, calls to `unsafe`
+/// functions, uses of `static mut` and `extern` statics, and inline assembly.
+/// Calls to functions that this command makes safe don't count, so `unsafe`
+/// blocks that were only needed to call them are removed as well.  An `unsafe`
+/// block that is the whole body of another block, or that holds only a single
+/// expression, is unwrapped.
 ///
-/// Note that this also replaces the function in expressions that take its address,
-/// which may cause problem as the wrapper function has a different type that the
-/// original (it lacks the `extern "C"` ABI qualifier).
-pub struct WrapExtern;
+/// Trait methods are left alone, as are functions that are used other than by
+/// calling them, since dropping `unsafe` changes the type of the function.  The
+/// ABI of `extern "C"` functions is kept.
+pub struct RemoveUnneededUnsafe;
+
+/// Looks for operations that need an `unsafe` context, not counting those inside nested `unsafe`
+/// blocks or nested items.
+struct UnsafeOpFinder<'a, 'tcx: 'a> {
+    cx: &'a RefactorCtxt<'a, 'tcx>,
+    /// Functions that no longer count as `unsafe`.
+    safe_fns: &'a HashSet<DefId>,
+    found: bool,
+}
 
-impl Transform for WrapExtern {
-    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
-        // (1) Collect the marked externs.
-        #[derive(Debug)]
-        struct FuncInfo {
-            id: NodeId,
-            def_id: DefId,
-            ident: Ident,
-            decl: P<FnDecl>,
+impl<'a, 'tcx> UnsafeOpFinder<'a, 'tcx> {
+    fn is_unsafe_op(&self, e: &Expr) -> bool {
+        let tcx = self.cx.ty_ctxt();
+        match e.kind {
+            ExprKind::Unary(UnOp::Deref, ref inner) => {
+                self.cx.opt_node_type(inner.id)
+                    .map_or(true, |ty| matches!([ty.kind] TyKind::RawPtr(..)))
+            }
+            ExprKind::Field(ref base, _) => {
+                let mut ty = match_or!([self.cx.opt_adjusted_node_type(base.id)]
+                                       Some(x) => x; return true);
+                while let TyKind::Ref(_, inner, _) = ty.kind {
+                    ty = inner;
+                }
+                match ty.kind {
+                    TyKind::Adt(def, _) => def.is_union(),
+                    _ => false,
+                }
+            }
+            ExprKind::Call(..) | ExprKind::MethodCall(..) => {
+                if let Some(def_id) = self.cx.opt_callee(e) {
+                    if self.safe_fns.contains(&def_id) {
+                        return false;
+                    }
+                }
+                self.cx.opt_callee_fn_sig(e)
+                    .map_or(true, |sig| sig.unsafety == hir::Unsafety::Unsafe)
+            }
+            ExprKind::Path(..) => {
+                let def_id = match_or!([self.cx.try_resolve_expr(e)] Some(x) => x; return false);
+                matches!([tcx.def_kind(def_id)] Some(DefKind::Static)) &&
+                    (tcx.is_mutable_static(def_id) || tcx.is_foreign_item(def_id))
+            }
+            ExprKind::InlineAsm(..) => true,
+            _ => false,
         }
-        let mut fns = Vec::new();
+    }
+}
 
-        visit_nodes(krate, |fi: &ForeignItem| {
-            if !st.marked(fi.id, "target") {
-                return;
-            }
+impl<'a, 'tcx, 'ast> Visitor<'ast> for UnsafeOpFinder<'a, 'tcx> {
+    fn visit_expr(&mut self, e: &'ast Expr) {
+        if self.found {
+            return;
+        }
+        if self.is_unsafe_op(e) {
+            self.found = true;
+            return;
+        }
+        visit::walk_expr(self, e);
+    }
 
-            match fi.kind {
-                ForeignItemKind::Fn(ref decl, _) => {
-                    fns.push(FuncInfo {
-                        id: fi.id,
-                        def_id: cx.node_def_id(fi.id),
-                        ident: fi.ident,
-                        decl: decl.clone(),
-                    });
-                },
+    fn visit_block(&mut self, b: &'ast Block) {
+        match b.rules {
+            BlockCheckMode::Unsafe(_) => {}
+            BlockCheckMode::Default => visit::walk_block(self, b),
+        }
+    }
 
-                _ => {},
+    fn visit_item(&mut self, _i: &'ast Item) {}
+
+    fn visit_mac(&mut self, _mac: &'ast Mac) {}
+}
+
+/// Check if `b` performs an unsafe operation outside of any `unsafe` block it contains.  The
+/// `unsafe` qualifier of `b` itself is ignored.
+pub(super) fn needs_unsafe<'a, 'tcx>(cx: &'a RefactorCtxt<'a, 'tcx>,
+                                     safe_fns: &'a HashSet<DefId>,
+                                     b: &Block) -> bool {
+    let mut finder = UnsafeOpFinder { cx, safe_fns, found: false };
+    visit::walk_block(&mut finder, b);
+    finder.found
+}
+
+/// Check if `b` is a user-written `unsafe` block that doesn't need to be one.
+fn is_unneeded_unsafe<'a, 'tcx>(cx: &'a RefactorCtxt<'a, 'tcx>,
+                                safe_fns: &'a HashSet<DefId>,
+                                b: &Block) -> bool {
+    b.rules == BlockCheckMode::Unsafe(UnsafeSource::UserProvided) && !needs_unsafe(cx, safe_fns, b)
+}
+
+impl Transform for RemoveUnneededUnsafe {
+    fn transform(&self, krate: &mut Crate, _st: &CommandState, cx: &RefactorCtxt) {
+        // (1) Collect the bodies of `unsafe` free functions and inherent methods.
+        let mut bodies = HashMap::new();
+        visit_nodes(krate, |i: &Item| {
+            match i.kind {
+                ItemKind::Fn(ref sig, _, ref block) => {
+                    if sig.header.unsafety == Unsafety::Unsafe {
+                        bodies.insert(cx.node_def_id(i.id), block.clone());
+                    }
+                }
+                ItemKind::Impl(_, _, _, _, None, _, ref items) => {
+                    for ii in items {
+                        if let ImplItemKind::Method(ref sig, ref block) = ii.kind {
+                            if sig.header.unsafety == Unsafety::Unsafe {
+                                bodies.insert(cx.node_def_id(ii.id), block.clone());
+                            }
+                        }
+                    }
+                }
+                _ => {}
             }
         });
 
-        info!("found {} fns", fns.len());
-        for i in &fns {
-            info!("  {:?}", i);
-        }
+        // (2) Skip functions that are referenced other than by calling them.
+        let mut callees = HashSet::new();
+        visit_nodes(krate, |e: &Expr| {
+            if let ExprKind::Call(ref func, _) = e.kind {
+                callees.insert(func.id);
+            }
+        });
+        visit_nodes(krate, |e: &Expr| {
+            if let ExprKind::Path(..) = e.kind {
+                if !callees.contains(&e.id) {
+                    if let Some(def_id) = cx.try_resolve_expr(e) {
+                        bodies.remove(&def_id);
+                    }
+                }
+            }
+        });
 
-        // (2) Generate wrappers in the destination module.
-        let mut dest_path = None;
-        FlatMapNodes::visit(krate, |i: P<Item>| {
-            if !st.marked(i.id, "dest") {
-                return smallvec![i];
+        // (3) Start by assuming all of them can be made safe, and drop the ones that still
+        // perform unsafe operations until nothing changes.  Starting optimistically lets
+        // recursive functions become safe too.
+        let mut safe_fns = bodies.keys().cloned().collect::<HashSet<_>>();
+        loop {
+            let still_unsafe = safe_fns.iter()
+                .filter(|def_id| needs_unsafe(cx, &safe_fns, &bodies[*def_id]))
+                .cloned()
+                .collect::<Vec<_>>();
+            if still_unsafe.is_empty() {
+                break;
+            }
+            for def_id in still_unsafe {
+                safe_fns.remove(&def_id);
             }
+        }
 
-            if dest_path.is_some() {
-                info!("warning: found multiple \"dest\" marks");
-                return smallvec![i];
+        // (4) Remove the `unsafe` qualifiers.
+        MutVisitNodes::visit(krate, |i: &mut P<Item>| {
+            let def_id = cx.node_def_id(i.id);
+            match i.kind {
+                ItemKind::Fn(ref mut sig, _, _) => {
+                    if safe_fns.contains(&def_id) {
+                        sig.header.unsafety = Unsafety::Normal;
+                    }
+                }
+                ItemKind::Impl(_, _, _, _, None, _, ref mut items) => {
+                    for ii in items {
+                        if let ImplItemKind::Method(ref mut sig, _) = ii.kind {
+                            if safe_fns.contains(&cx.node_def_id(ii.id)) {
+                                sig.header.unsafety = Unsafety::Normal;
+                            }
+                        }
+                    }
+                }
+                _ => {}
             }
-            dest_path = Some(cx.def_path(cx.node_def_id(i.id)));
+        });
 
-            smallvec![i.map(|i| {
-                unpack!([i.kind] ItemKind::Mod(m));
-                let mut m = m;
+        // (5) Unwrap unneeded `unsafe` blocks holding a single expression.
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            let inner = match e.kind {
+                ExprKind::Block(ref b, None) if is_unneeded_unsafe(cx, &safe_fns, b) => {
+                    match &b.stmts[..] {
+                        [Stmt { kind: StmtKind::Expr(inner), .. }] => inner.clone(),
+                        _ => return,
+                    }
+                }
+                _ => return,
+            };
+            *e = inner;
+        });
 
-                for f in &fns {
-                    let func_path = cx.def_path(cx.node_def_id(f.id));
-                    let arg_names = f.decl.inputs.iter().enumerate().map(|(idx, arg)| {
-                        // TODO: match_arg("__i: __t", arg).ident("__i")
-                        match arg.pat.kind {
-                            PatKind::Ident(BindingMode::ByValue(Mutability::Immutable),
-                                           ident,
+        // (6) Turn the remaining unneeded `unsafe` blocks into ordinary ones, and splice them into
+        // blocks that contain nothing else.
+        MutVisitNodes::visit(krate, |b: &mut P<Block>| {
+            if is_unneeded_unsafe(cx, &safe_fns, b) {
+                b.rules = BlockCheckMode::Default;
+            }
+            let stmts = match &b.stmts[..] {
+                [Stmt { kind: StmtKind::Expr(e), .. }] => match e.kind {
+                    ExprKind::Block(ref inner, None)
+                        if is_unneeded_unsafe(cx, &safe_fns, inner) => inner.stmts.clone(),
+                    _ => return,
+                },
+                _ => return,
+            };
+            b.stmts = stmts;
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
+/// # `unsafe_block_cleanup` Command
+///
+/// Usage: `unsafe_block_cleanup mode=merge|shrink [gap=N]`
+///
+/// Tidy up the `unsafe` blocks of the crate.
+///
+/// With `mode=merge`, `unsafe` blocks in the same block that are separated by
+/// at most `N` other statements (`1` by default) are merged into one, along
+/// with the statements between them.  Only expression statements are merged,
+/// and only `unsafe` blocks that don't declare locals of their own, so no
+/// local changes scope or drop order.
+///
+/// With `mode=shrink`, a safe function whose whole body is one `unsafe` block
+/// gets its body back, with the `unsafe` operations wrapped in the smallest
+/// `unsafe` blocks that cover them, as in `remove_unneeded_unsafe`.  Runs of
+/// statements that perform them share a block.  A `let` gets its initializer
+/// wrapped, and an `if`, `while`, `loop`, `for` or block whose head is safe has
+/// its inner statements narrowed instead, so no local changes scope.
+///
+/// Either way, the crate is typechecked again afterward, and the command fails
+/// if that no longer succeeds.
+pub struct UnsafeBlockCleanup {
+    pub mode: UnsafeCleanupMode,
+    pub gap: usize,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum UnsafeCleanupMode {
+    Merge,
+    Shrink,
+}
+
+/// If `s` is a statement holding just an unlabeled, user-written `unsafe` block, return the block.
+fn unsafe_block_stmt(s: &Stmt) -> Option<&P<Block>> {
+    let e = match s.kind {
+        StmtKind::Expr(ref e) | StmtKind::Semi(ref e) => e,
+        _ => return None,
+    };
+    match e.kind {
+        ExprKind::Block(ref b, None)
+            if e.attrs.is_empty() &&
+               b.rules == BlockCheckMode::Unsafe(UnsafeSource::UserProvided) => Some(b),
+        _ => None,
+    }
+}
+
+fn declares_locals(b: &Block) -> bool {
+    b.stmts.iter().any(|s| match s.kind {
+        StmtKind::Local(..) | StmtKind::Item(..) | StmtKind::Mac(..) => true,
+        _ => false,
+    })
+}
+
+fn unsafe_block(stmts: Vec<Stmt>) -> P<Expr> {
+    mk().block_expr(mk().unsafe_().block(stmts))
+}
+
+/// Merge the `unsafe` blocks among `stmts` that are at most `gap` statements apart.
+fn merge_unsafe_blocks(stmts: Vec<Stmt>, gap: usize) -> Vec<Stmt> {
+    let mergeable = |s: &Stmt| unsafe_block_stmt(s).map_or(false, |b| !declares_locals(b));
+    let mut out: Vec<Stmt> = Vec::with_capacity(stmts.len());
+    let mut i = 0;
+    while i < stmts.len() {
+        if !mergeable(&stmts[i]) {
+            out.push(stmts[i].clone());
+            i += 1;
+            continue;
+        }
+
+        // Extend the run for as long as another mergeable block follows closely enough, with
+        // only expression statements in between.
+        let mut end = i;
+        for j in i + 1..stmts.len() {
+            if mergeable(&stmts[j]) {
+                end = j;
+                continue;
+            }
+            let is_gap = unsafe_block_stmt(&stmts[j]).is_none() && match stmts[j].kind {
+                StmtKind::Expr(..) | StmtKind::Semi(..) => true,
+                _ => false,
+            };
+            if !is_gap || j - end > gap {
+                break;
+            }
+        }
+        if end == i {
+            out.push(stmts[i].clone());
+            i += 1;
+            continue;
+        }
+
+        let mut inner = Vec::new();
+        for s in &stmts[i..=end] {
+            match unsafe_block_stmt(s) {
+                Some(b) => inner.extend(b.stmts.iter().cloned()),
+                None => inner.push(s.clone()),
+            }
+        }
+        // Only the last statement of the merged block keeps its place as the block's value.
+        let last = inner.len() - 1;
+        for s in &mut inner[..last] {
+            if let StmtKind::Expr(ref e) = s.kind {
+                if !expr_requires_semi_to_be_stmt(e) {
+                    continue;
+                }
+                s.kind = StmtKind::Semi(e.clone());
+            }
+        }
+        let block = unsafe_block(inner);
+        out.push(match stmts[end].kind {
+            StmtKind::Expr(..) => mk().expr_stmt(block),
+            _ => mk().semi_stmt(block),
+        });
+        i = end + 1;
+    }
+    out
+}
+
+struct UnsafeShrinker<'a, 'tcx: 'a> {
+    cx: &'a RefactorCtxt<'a, 'tcx>,
+    /// Always empty: every `unsafe` function still counts.
+    safe_fns: HashSet<DefId>,
+}
+
+impl<'a, 'tcx> UnsafeShrinker<'a, 'tcx> {
+    fn finder(&self) -> UnsafeOpFinder<'_, 'tcx> {
+        UnsafeOpFinder { cx: self.cx, safe_fns: &self.safe_fns, found: false }
+    }
+
+    fn needs_unsafe(&self, e: &Expr) -> bool {
+        let mut finder = self.finder();
+        finder.visit_expr(e);
+        finder.found
+    }
+
+    fn stmt_needs_unsafe(&self, s: &Stmt) -> bool {
+        let mut finder = self.finder();
+        finder.visit_stmt(s);
+        finder.found
+    }
+
+    /// Wrap the unsafe operations of `stmts`, which run in a safe context, in `unsafe` blocks.
+    fn shrink_stmts(&self, stmts: Vec<Stmt>) -> Vec<Stmt> {
+        let mut out = Vec::with_capacity(stmts.len());
+        let mut run = Vec::new();
+        let flush = |run: &mut Vec<Stmt>, out: &mut Vec<Stmt>| {
+            // The new block is either unit-typed or the value of the whole block, so it never
+            // needs a semicolon.
+            if !run.is_empty() {
+                out.push(mk().expr_stmt(unsafe_block(mem::replace(run, Vec::new()))));
+            }
+        };
+
+        for mut s in stmts {
+            if !self.stmt_needs_unsafe(&s) {
+                flush(&mut run, &mut out);
+                out.push(s);
+                continue;
+            }
+            let narrowed = match s.kind {
+                // The unsafe operation can only be in the initializer.
+                StmtKind::Local(ref mut l) => {
+                    match l.init {
+                        Some(ref mut init) => {
+                            *init = unsafe_block(vec![mk().expr_stmt(init.clone())]);
+                            true
+                        }
+                        None => false,
+                    }
+                }
+                StmtKind::Expr(ref mut e) | StmtKind::Semi(ref mut e) => self.shrink_expr(e),
+                _ => false,
+            };
+            if narrowed {
+                flush(&mut run, &mut out);
+                out.push(s);
+            } else {
+                run.push(s);
+            }
+        }
+        flush(&mut run, &mut out);
+        out
+    }
+
+    /// Narrow the unsafe operations inside the blocks of `e`, if the rest of `e` is safe.
+    /// Returns `false`, leaving `e` alone, if it isn't.
+    fn shrink_expr(&self, e: &mut P<Expr>) -> bool {
+        if !e.attrs.is_empty() {
+            return false;
+        }
+        match e.kind {
+            ExprKind::If(ref cond, _, _) |
+            ExprKind::While(ref cond, _, _) |
+            ExprKind::ForLoop(_, ref cond, _, _) if self.needs_unsafe(cond) => return false,
+            ExprKind::If(_, _, Some(ref els)) if !self.can_shrink_else(els) => return false,
+            ExprKind::If(..) | ExprKind::While(..) | ExprKind::ForLoop(..) |
+            ExprKind::Loop(..) => {}
+            ExprKind::Block(ref b, _) if b.rules == BlockCheckMode::Default => {}
+            _ => return false,
+        }
+        match e.kind {
+            ExprKind::If(_, ref mut then, ref mut els) => {
+                self.shrink_block(then);
+                if let Some(ref mut els) = *els {
+                    self.shrink_expr(els);
+                }
+            }
+            ExprKind::While(_, ref mut body, _) |
+            ExprKind::ForLoop(_, _, ref mut body, _) |
+            ExprKind::Loop(ref mut body, _) |
+            ExprKind::Block(ref mut body, _) => self.shrink_block(body),
+            _ => unreachable!(),
+        }
+        true
+    }
+
+    fn can_shrink_else(&self, e: &Expr) -> bool {
+        match e.kind {
+            ExprKind::If(ref cond, _, ref els) => {
+                !self.needs_unsafe(cond) && els.as_ref().map_or(true, |e| self.can_shrink_else(e))
+            }
+            ExprKind::Block(..) => true,
+            _ => false,
+        }
+    }
+
+    fn shrink_block(&self, b: &mut P<Block>) {
+        let stmts = mem::replace(&mut b.stmts, Vec::new());
+        b.stmts = self.shrink_stmts(stmts);
+    }
+}
+
+impl UnsafeBlockCleanup {
+    fn rewrite(&self, krate: &mut Crate, cx: &RefactorCtxt) {
+        match self.mode {
+            UnsafeCleanupMode::Merge => {
+                MutVisitNodes::visit(krate, |b: &mut P<Block>| {
+                    let stmts = mem::replace(&mut b.stmts, Vec::new());
+                    b.stmts = merge_unsafe_blocks(stmts, self.gap);
+                });
+            }
+            UnsafeCleanupMode::Shrink => {
+                let shrinker = UnsafeShrinker { cx, safe_fns: HashSet::new() };
+                let shrink_body = |sig: &FnSig, body: &mut P<Block>| {
+                    if sig.header.unsafety == Unsafety::Unsafe || body.stmts.len() != 1 {
+                        return;
+                    }
+                    let inner = match_or!([unsafe_block_stmt(&body.stmts[0])] Some(x) => x;
+                                          return);
+                    body.stmts = shrinker.shrink_stmts(inner.stmts.clone());
+                };
+                MutVisitNodes::visit(krate, |i: &mut P<Item>| {
+                    match i.kind {
+                        ItemKind::Fn(ref sig, _, ref mut body) => shrink_body(sig, body),
+                        ItemKind::Impl(_, _, _, _, _, _, ref mut items) => {
+                            for ii in items {
+                                if let ImplItemKind::Method(ref sig, ref mut body) = ii.kind {
+                                    shrink_body(sig, body);
+                                }
+                            }
+                        }
+                        _ => {}
+                    }
+                });
+            }
+        }
+    }
+}
+
+impl Command for UnsafeBlockCleanup {
+    fn run(&mut self, state: &mut RefactorState) {
+        state.transform_crate(Phase::Phase3, |st, cx| {
+            self.rewrite(&mut *st.krate_mut(), cx);
+        }).expect("Failed to run compiler");
+
+        // Make sure the new blocks didn't break anything.
+        let typechecks = state.transform_crate(Phase::Phase3, |_st, cx| {
+            cx.ty_ctxt().analysis(LOCAL_CRATE).is_ok()
+        }).expect("Failed to run compiler");
+        if !typechecks {
+            panic!("unsafe_block_cleanup: the crate no longer typechecks");
+        }
+    }
+}
+
+
+/// # `wrap_extern` Command
+///
+/// Usage: `wrap_extern`
+///
+/// Marks: `target`, `dest`
+///
+/// For each foreign function marked `target`, generate a wrapper function in the
+/// module marked `dest`, and rewrite all uses of the function to call the wrapper
+/// instead.
+///
+///
+/// Example:
+///
+/// ```ignore
+///     extern "C" {
+///         fn foo(x: i32) -> i32;
+///     }
+///
+///     mod wrappers {
+///         // empty
+///     }
+///
+///     fn main() {
+///         let x = unsafe { foo(123) };
+///     }
+/// ```
+///
+/// After transformation, with `fn foo` marked `target` and `mod wrappers` marked
+/// `dest`:
+///
+/// ```ignore
+///     extern "C" {
+///         fn foo(x: i32) -> i32;
+///     }
+///
+///     mod wrappers {
+///         unsafe fn foo(x: i32) -> i32 {
+///             ::foo(x)
+///         }
+///     }
+///
+///     fn main() {
+///         let x = unsafe { ::wrappers::foo(123) };
+///     }
+/// ```
+///
+/// Note that this also replaces the function in expressions that take its address,
+/// which may cause problem as the wrapper function has a different type that the
+/// original (it lacks the `extern "C"` ABI qualifier).
+pub struct WrapExtern;
+
+impl Transform for WrapExtern {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        // (1) Collect the marked externs.
+        #[derive(Debug)]
+        struct FuncInfo {
+            id: NodeId,
+            def_id: DefId,
+            ident: Ident,
+            decl: P<FnDecl>,
+        }
+        let mut fns = Vec::new();
+
+        visit_nodes(krate, |fi: &ForeignItem| {
+            if !st.marked(fi.id, "target") {
+                return;
+            }
+
+            match fi.kind {
+                ForeignItemKind::Fn(ref decl, _) => {
+                    fns.push(FuncInfo {
+                        id: fi.id,
+                        def_id: cx.node_def_id(fi.id),
+                        ident: fi.ident,
+                        decl: decl.clone(),
+                    });
+                },
+
+                _ => {},
+            }
+        });
+
+        info!("found {} fns", fns.len());
+        for i in &fns {
+            info!("  {:?}", i);
+        }
+
+        // (2) Generate wrappers in the destination module.
+        let mut dest_path = None;
+        FlatMapNodes::visit(krate, |i: P<Item>| {
+            if !st.marked(i.id, "dest") {
+                return smallvec![i];
+            }
+
+            if dest_path.is_some() {
+                info!("warning: found multiple \"dest\" marks");
+                return smallvec![i];
+            }
+            dest_path = Some(cx.def_path(cx.node_def_id(i.id)));
+
+            smallvec![i.map(|i| {
+                unpack!([i.kind] ItemKind::Mod(m));
+                let mut m = m;
+
+                for f in &fns {
+                    let func_path = cx.def_path(cx.node_def_id(f.id));
+                    let arg_names = f.decl.inputs.iter().enumerate().map(|(idx, arg)| {
+                        // TODO: match_arg("__i: __t", arg).ident("__i")
+                        match arg.pat.kind {
+                            PatKind::Ident(BindingMode::ByValue(Mutability::Immutable),
+                                           ident,
                                            None) => {
                                 ident
                             },
@@ -796,18 +1638,1394 @@ impl Transform for Abstract {
     }
 }
 
+/// Collect the signatures of the functions and foreign functions of `krate`, as declared.  The
+/// types in them, such as `libc::c_ulong`, keep their meaning on every target, unlike the
+/// primitive types the typechecker resolves them to.
+pub(super) fn declared_fn_decls(cx: &RefactorCtxt, krate: &Crate) -> HashMap<DefId, P<FnDecl>> {
+    let mut decls = HashMap::new();
+    visit_fns(krate, |fl| {
+        decls.insert(cx.node_def_id(fl.id), fl.decl);
+    });
+    decls
+}
 
-pub fn register_commands(reg: &mut Registry) {
-    use super::mk;
+/// # `outparams_to_tuple` Command
+///
+/// Usage: `outparams_to_tuple`
+///
+/// Marks: `target`
+///
+/// For each function marked `target`, turn its out-parameters into extra return
+/// values.  An out-parameter is a `*mut T` argument `p` that is used only
+/// through `*p`, and whose first use is a top-level `*p = ...;` statement of
+/// the function body that doesn't otherwise mention `p`.  No `return` or `?`
+/// may come before that write, so it happens on every path out of the
+/// function; a parameter that is only written on some paths is skipped with a
+/// warning.
+///
+/// Since the caller's value is never read, each out-parameter is removed from
+/// the signature and its first write becomes the local `let p: T = ...;`
+/// (`let mut`, if it's written again), and the function returns
+/// `(OrigRet, T1, T2, ...)`.  If the original return type is `()`, or the
+/// return value is ignored at every call site, the original value is dropped
+/// and the function returns `T1` or `(T1, T2, ...)` instead.
+///
+/// Every call `f(a, &mut x)` is rewritten to `f(a)`, assigning the returned
+/// values to `x`.  A function is skipped with a warning if any of its callers
+/// passes something other than `&mut place` for an out-parameter (in
+/// particular, null for an optional out-parameter), if `place` has side
+/// effects, or if the function is used other than by calling it.
+pub struct OutparamsToTuple;
+
+/// An out-parameter found by `outparams_to_tuple`.
+struct OutParam {
+    index: usize,
+    ident: Ident,
+    hir_id: HirId,
+    ty: P<Ty>,
+    /// The index of the body statement that first writes the parameter.
+    first: usize,
+    /// Whether the parameter is written again after that.
+    rewritten: bool,
+}
 
-    reg.register("func_to_method", |_args| mk(ToMethod));
-    reg.register("fix_unused_unsafe", |_args| mk(FixUnusedUnsafe));
-    reg.register("sink_unsafe", |_args| mk(SinkUnsafe));
-    reg.register("wrap_extern", |_args| mk(WrapExtern));
-    reg.register("wrap_api", |_args| mk(WrapApi));
-    reg.register("abstract", |args| mk(Abstract {
-        sig: args[0].clone(),
-        pat: args[1].clone(),
-        body: args.get(2).cloned(),
+/// A function whose out-parameters are being turned into return values.
+struct OutParamFn {
+    outs: Vec<OutParam>,
+    ret_unit: bool,
+    /// Whether the original return value is kept as the first element of the result.
+    keep_ret: bool,
+}
+
+/// Find the out-parameters of the function with signature `decl` and body `block`.
+fn find_out_params(cx: &RefactorCtxt, decl: &FnDecl, block: &Block) -> Vec<OutParam> {
+    let mut outs = Vec::new();
+    for (index, arg) in decl.inputs.iter().enumerate() {
+        let ty = match arg.ty.kind {
+            ast::TyKind::Ptr(MutTy { ref ty, mutbl: Mutability::Mutable }) => ty.clone(),
+            _ => continue,
+        };
+        let ident = match arg.pat.kind {
+            PatKind::Ident(BindingMode::ByValue(_), ident, None) => ident,
+            _ => continue,
+        };
+        let hir_id = cx.hir_map().node_to_hir_id(arg.pat.id);
+        let is_use = |e: &Expr| match e.kind {
+            ExprKind::Path(None, _) => cx.try_resolve_expr_to_hid(e) == Some(hir_id),
+            _ => false,
+        };
+        let mentions = |e: &Expr| {
+            let mut found = false;
+            visit_nodes(e, |e: &Expr| found |= is_use(e));
+            found
+        };
+
+        // Every use must be dereferenced, so `p` itself never escapes.
+        let mut uses = 0;
+        let mut derefs = 0;
+        visit_nodes(block, |e: &Expr| {
+            if is_use(e) {
+                uses += 1;
+            }
+            if let ExprKind::Unary(UnOp::Deref, ref inner) = e.kind {
+                if is_use(inner) {
+                    derefs += 1;
+                }
+            }
+        });
+        if uses == 0 || uses != derefs {
+            continue;
+        }
+
+        // The first statement that mentions `p` must write it without reading it.
+        let first = block.stmts.iter().position(|s| {
+            let mut found = false;
+            visit_nodes(s, |e: &Expr| found |= is_use(e));
+            found
+        });
+        let first = match first {
+            Some(first) => first,
+            None => continue,
+        };
+        let rhs = match block.stmts[first].kind {
+            StmtKind::Semi(ref e) => match e.kind {
+                ExprKind::Assign(ref lhs, ref rhs) => match lhs.kind {
+                    ExprKind::Unary(UnOp::Deref, ref inner)
+                        if is_use(inner) && !mentions(rhs) => rhs,
+                    _ => continue,
+                },
+                _ => continue,
+            },
+            _ => continue,
+        };
+
+        // The write must happen before every exit from the function, or some callers would
+        // see their variable overwritten where the original left it alone.
+        let exits_early = block.stmts[..first].iter().any(|s| {
+            let mut found = false;
+            visit_nodes(s, |e: &Expr| found |= is_exit(e));
+            found
+        }) || {
+            let mut found = false;
+            visit_nodes(&**rhs, |e: &Expr| found |= is_exit(e));
+            found
+        };
+        if exits_early {
+            warn!("outparams_to_tuple: `{}` is not written on every path out of the function; \
+                   skipping it", ident);
+            continue;
+        }
+
+        // Any later use of `*p` as a place may write it again.
+        let is_out_place = |e: &Expr| match place_root(e).kind {
+            ExprKind::Unary(UnOp::Deref, ref inner) => is_use(inner),
+            _ => false,
+        };
+        let mut rewritten = false;
+        for s in &block.stmts[first + 1..] {
+            visit_nodes(s, |e: &Expr| match e.kind {
+                ExprKind::Assign(ref lhs, _) |
+                ExprKind::AssignOp(_, ref lhs, _) |
+                ExprKind::AddrOf(_, Mutability::Mutable, ref lhs) => rewritten |= is_out_place(lhs),
+                ExprKind::MethodCall(_, ref args) => rewritten |= is_out_place(&args[0]),
+                _ => {}
+            });
+        }
+
+        outs.push(OutParam { index, ident, hir_id, ty, first, rewritten });
+    }
+    outs
+}
+
+/// Strip the field accesses, indexing and parentheses from the place expression `e`.
+fn place_root(e: &Expr) -> &Expr {
+    match e.kind {
+        ExprKind::Field(ref base, _) |
+        ExprKind::Index(ref base, _) |
+        ExprKind::Paren(ref base) => place_root(base),
+        _ => e,
+    }
+}
+
+/// Check whether `e` can leave the enclosing function early.
+fn is_exit(e: &Expr) -> bool {
+    match e.kind {
+        ExprKind::Ret(_) | ExprKind::Try(_) => true,
+        _ => false,
+    }
+}
+
+impl Transform for OutparamsToTuple {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        // (1) Find the out-parameters of each marked function.
+
+        let mut fns: HashMap<DefId, OutParamFn> = HashMap::new();
+        visit_fns(krate, |fl| {
+            if !st.marked(fl.id, "target") {
+                return;
+            }
+            let block = match fl.block {
+                Some(ref block) => block,
+                None => return,
+            };
+            let outs = find_out_params(cx, &fl.decl, block);
+            if outs.is_empty() {
+                warn!("outparams_to_tuple: `{}` has no out-parameters; skipping", fl.ident);
+                return;
+            }
+            let ret_unit = match fl.decl.output {
+                FunctionRetTy::Default(_) => true,
+                FunctionRetTy::Ty(ref ty) => match ty.kind {
+                    ast::TyKind::Tup(ref elems) => elems.is_empty(),
+                    _ => false,
+                },
+            };
+            fns.insert(cx.node_def_id(fl.id), OutParamFn { outs, ret_unit, keep_ret: !ret_unit });
+        });
+
+        // (2) Check the uses of each function.  Every use must be a call that passes `&mut
+        // place` for each out-parameter.
+
+        let mut rejected = HashSet::new();
+        let mut calls: HashMap<DefId, usize> = HashMap::new();
+        let mut ignored_calls: HashMap<DefId, usize> = HashMap::new();
+        let mut refs: HashMap<DefId, usize> = HashMap::new();
+        visit_nodes(krate, |e: &Expr| {
+            if let ExprKind::Path(..) = e.kind {
+                if let Some(def_id) = cx.try_resolve_expr(e) {
+                    if fns.contains_key(&def_id) {
+                        *refs.entry(def_id).or_insert(0) += 1;
+                    }
+                }
+            }
+
+            let args = match e.kind {
+                ExprKind::Call(_, ref args) | ExprKind::MethodCall(_, ref args) => args,
+                _ => return,
+            };
+            let def_id = match cx.opt_callee(e) {
+                Some(def_id) if fns.contains_key(&def_id) => def_id,
+                _ => return,
+            };
+            if let ExprKind::Call(..) = e.kind {
+                *calls.entry(def_id).or_insert(0) += 1;
+            }
+            for out in &fns[&def_id].outs {
+                let arg = &args[out.index];
+                match strip_casts(arg).kind {
+                    ExprKind::AddrOf(_, Mutability::Mutable, ref place)
+                        if is_pure_expr(cx, place) => continue,
+                    ExprKind::AddrOf(_, Mutability::Mutable, _) => {
+                        warn!("outparams_to_tuple: call at {} passes `{}` for `{}`, which has \
+                               side effects; skipping",
+                              cx.session().source_map().span_to_string(e.span),
+                              pprust::expr_to_string(arg), out.ident);
+                    }
+                    _ if is_null_ptr(arg) => {
+                        warn!("outparams_to_tuple: call at {} passes null for `{}`; skipping",
+                              cx.session().source_map().span_to_string(e.span), out.ident);
+                    }
+                    _ => {
+                        warn!("outparams_to_tuple: call at {} passes `{}` for `{}`; skipping",
+                              cx.session().source_map().span_to_string(e.span),
+                              pprust::expr_to_string(arg), out.ident);
+                    }
+                }
+                rejected.insert(def_id);
+            }
+        });
+        visit_nodes(krate, |s: &Stmt| {
+            if let StmtKind::Semi(ref e) = s.kind {
+                if let Some(def_id) = cx.opt_callee(e) {
+                    *ignored_calls.entry(def_id).or_insert(0) += 1;
+                }
+            }
+        });
+
+        for (&def_id, &count) in &refs {
+            if calls.get(&def_id).cloned().unwrap_or(0) != count {
+                warn!("outparams_to_tuple: `{}` is used other than by calling it; skipping",
+                      cx.ty_ctxt().def_path_str(def_id));
+                rejected.insert(def_id);
+            }
+        }
+        fns.retain(|def_id, _| !rejected.contains(def_id));
+        for (def_id, f) in &mut fns {
+            let ignored = ignored_calls.get(def_id).cloned().unwrap_or(0);
+            let calls = calls.get(def_id).cloned().unwrap_or(0);
+            f.keep_ret = !f.ret_unit && !(calls > 0 && ignored == calls);
+        }
+        if fns.is_empty() {
+            return;
+        }
+
+        // (3) Rewrite the functions.
+
+        let mut mcx = MatchCtxt::new(st, cx);
+        mut_visit_fns(krate, |fl| {
+            let f = match fns.get(&cx.node_def_id(fl.id)) {
+                Some(f) => f,
+                None => return,
+            };
+            let block = fl.block.as_mut().unwrap();
+            let out_ids = f.outs.iter().map(|out| out.hir_id).collect::<HashSet<_>>();
+
+            // `*p` becomes a use of the new local `p`.
+            MutVisitNodes::visit(block, |e: &mut P<Expr>| {
+                let local = match e.kind {
+                    ExprKind::Unary(UnOp::Deref, ref inner) => match inner.kind {
+                        ExprKind::Path(None, _) => cx.try_resolve_expr_to_hid(inner)
+                            .filter(|hir_id| out_ids.contains(hir_id))
+                            .map(|_| inner.clone()),
+                        _ => None,
+                    },
+                    _ => None,
+                };
+                if let Some(local) = local {
+                    *e = local;
+                }
+            });
+
+            // The first write of each out-parameter declares its local.
+            for out in &f.outs {
+                let stmt = &mut block.stmts[out.first];
+                let rhs = match stmt.kind {
+                    StmtKind::Semi(ref e) => match e.kind {
+                        ExprKind::Assign(_, ref rhs) => rhs.clone(),
+                        _ => unreachable!(),
+                    },
+                    _ => unreachable!(),
+                };
+                let mutbl = if out.rewritten { Mutability::Mutable } else { Mutability::Immutable };
+                let pat = mk().set_mutbl(mutbl).ident_pat(out.ident);
+                *stmt = mk().local_stmt(P(mk().local(pat, Some(out.ty.clone()), Some(rhs))));
+            }
+
+            // Return the locals along with (or instead of) the original return value.
+            let names = f.outs.iter().map(|out| out.ident.to_string()).collect::<Vec<_>>();
+            let outs_src = if names.len() == 1 {
+                names[0].clone()
+            } else {
+                format!("({})", names.join(", "))
+            };
+            let pack_src = if f.keep_ret {
+                format!("($e, {})", names.join(", "))
+            } else {
+                format!("{{ $e; {} }}", outs_src)
+            };
+            let pack_tmpl = mcx.parse_expr(&pack_src);
+            let outs_expr = parse_expr(cx.session(), &outs_src);
+            let pack = |e: P<Expr>| {
+                let mut bnd = Bindings::new();
+                bnd.add("$e", e);
+                pack_tmpl.clone().subst(st, cx, &bnd)
+            };
+
+            MutVisitNodes::visit(block, |e: &mut P<Expr>| {
+                if let ExprKind::Ret(ref mut val) = e.kind {
+                    *val = Some(match val.take() {
+                        Some(val) => pack(val),
+                        None => outs_expr.clone(),
+                    });
+                }
+            });
+            if f.ret_unit {
+                if let Some(last) = block.stmts.last_mut() {
+                    if let StmtKind::Expr(ref e) = last.kind {
+                        last.kind = StmtKind::Semi(e.clone());
+                    }
+                }
+                block.stmts.push(mk().expr_stmt(outs_expr.clone()));
+            } else {
+                fold_output_exprs(block, true, |e| *e = pack(e.clone()));
+            }
+
+            // Update the signature.
+            let mut tys = f.outs.iter()
+                .map(|out| pprust::ty_to_string(&out.ty))
+                .collect::<Vec<_>>();
+            if f.keep_ret {
+                let ret = match fl.decl.output {
+                    FunctionRetTy::Ty(ref ty) => pprust::ty_to_string(ty),
+                    FunctionRetTy::Default(_) => unreachable!(),
+                };
+                tys.insert(0, ret);
+            }
+            let ret_src = if tys.len() == 1 {
+                tys[0].clone()
+            } else {
+                format!("({})", tys.join(", "))
+            };
+            fl.decl.output = FunctionRetTy::Ty(parse_ty(cx.session(), &ret_src));
+            for out in f.outs.iter().rev() {
+                fl.decl.inputs.remove(out.index);
+            }
+        });
+
+        // (4) Rewrite the call sites.
+
+        // The rewritten call is nested inside its replacement, so track which calls we've
+        // already handled.
+        let mut rewritten_nodes = HashSet::new();
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            if rewritten_nodes.contains(&e.id) {
+                return;
+            }
+            let f = match cx.opt_callee(e).and_then(|def_id| fns.get(&def_id)) {
+                Some(f) => f,
+                None => return,
+            };
+            rewritten_nodes.insert(e.id);
+
+            let mut call = e.clone();
+            let args = match call.kind {
+                ExprKind::Call(_, ref mut args) | ExprKind::MethodCall(_, ref mut args) => args,
+                _ => return,
+            };
+            let mut bnd = Bindings::new();
+            for (i, out) in f.outs.iter().enumerate().rev() {
+                let place = match strip_casts(&args[out.index]).kind {
+                    ExprKind::AddrOf(_, _, ref place) => place.clone(),
+                    _ => unreachable!(),
+                };
+                bnd.add(format!("$o{}", i), place);
+                args.remove(out.index);
+            }
+            bnd.add("$call", call);
+
+            let src = if !f.keep_ret && f.outs.len() == 1 {
+                "$o0 = $call".to_owned()
+            } else {
+                let mut pats = (0..f.outs.len()).map(|i| format!("__out{}", i)).collect::<Vec<_>>();
+                if f.keep_ret {
+                    pats.insert(0, "__ret".to_owned());
+                }
+                let assigns = (0..f.outs.len())
+                    .map(|i| format!("$o{0} = __out{0};", i))
+                    .collect::<Vec<_>>();
+                format!("{{ let ({}) = $call; {} {} }}",
+                        pats.join(", "), assigns.join(" "), if f.keep_ret { "__ret" } else { "" })
+            };
+            *e = mcx.parse_expr(&src).subst(st, cx, &bnd);
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+/// # `errcode_to_result` Command
+///
+/// Usage: `errcode_to_result [ERROR_TY]`
+///
+/// Marks: `target`
+///
+/// For each function marked `target` that returns `c_int`, using `0` for success
+/// and negative codes for failure, change its return type to
+/// `Result<(), ERROR_TY>` (`ERROR_TY` defaults to `i32`).  `return 0` becomes
+/// `return Ok(())`, and `return -N` becomes `return Err(-N)`, as does `return
+/// rc` inside an `if rc < 0` or `if rc != 0` check.  With an `ERROR_TY` other
+/// than `i32`, the error value is converted with `ERROR_TY::from`.  A function
+/// that returns anything else, such as a positive byte count, is skipped with a
+/// warning.
+///
+/// At call sites:
+///
+///  * `let rc = f(); if rc < 0 { ... }` (or `rc != 0`), where `rc` is not used
+///    elsewhere, becomes `if let Err(rc) = f() { ... }`.  If the body is just
+///    `return rc;` and the caller is also being converted, it becomes `f()?;`
+///    instead.
+///  * `if f() < 0 { ... }` (or `!= 0`) becomes `if f().is_err() { ... }`.
+///  * Any other use of the result converts it back to an integer with a
+///    `match`.
+pub struct ErrcodeToResult {
+    pub error_ty: String,
+}
+
+/// How a return value of a function converted by `errcode_to_result` is rewritten.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum ErrcodeRet {
+    Ok,
+    Err,
+}
+
+/// If `e` is `x < 0` or `x != 0`, return `x`.
+fn error_check(e: &Expr) -> Option<&P<Expr>> {
+    match e.kind {
+        ExprKind::Paren(ref inner) => error_check(inner),
+        ExprKind::Binary(op, ref lhs, ref rhs)
+                if (op.node == BinOpKind::Lt || op.node == BinOpKind::Ne) &&
+                   is_zero_lit(strip_casts(rhs)) => Some(lhs),
+        _ => None,
+    }
+}
+
+/// Classifies the return values of a function for `errcode_to_result`.
+struct ErrcodeClassifier<'a, 'tcx: 'a> {
+    cx: &'a RefactorCtxt<'a, 'tcx>,
+    /// Locals known to hold an error code, because an enclosing `if` checked them.
+    guards: Vec<HirId>,
+    rets: HashMap<NodeId, ErrcodeRet>,
+    /// Span of the first return value that isn't a success or error code.
+    mixed: Option<Span>,
+}
+
+impl<'a, 'tcx> ErrcodeClassifier<'a, 'tcx> {
+    fn classify(&mut self, e: &P<Expr>) {
+        let kind = match strip_casts(e).kind {
+            _ if is_zero_lit(strip_casts(e)) => Some(ErrcodeRet::Ok),
+            ExprKind::Unary(UnOp::Neg, ref inner) => match strip_casts(inner).kind {
+                ExprKind::Lit(Lit { kind: LitKind::Int(..), .. }) => Some(ErrcodeRet::Err),
+                _ => None,
+            },
+            ExprKind::Path(None, _) => self.cx.try_resolve_expr_to_hid(strip_casts(e))
+                .filter(|hir_id| self.guards.contains(hir_id))
+                .map(|_| ErrcodeRet::Err),
+            _ => None,
+        };
+        match kind {
+            Some(kind) => {
+                self.rets.insert(e.id, kind);
+            }
+            None => {
+                self.mixed = self.mixed.or(Some(e.span));
+            }
+        }
+    }
+}
+
+impl<'a, 'ast, 'tcx> Visitor<'ast> for ErrcodeClassifier<'a, 'tcx> {
+    fn visit_expr(&mut self, e: &'ast Expr) {
+        match e.kind {
+            ExprKind::If(ref cond, ref then, ref els) => {
+                let guard = error_check(cond).and_then(|x| match x.kind {
+                    ExprKind::Path(None, _) => self.cx.try_resolve_expr_to_hid(x),
+                    _ => None,
+                });
+                self.visit_expr(cond);
+                self.guards.extend(guard);
+                self.visit_block(then);
+                if guard.is_some() {
+                    self.guards.pop();
+                }
+                if let Some(ref els) = *els {
+                    self.visit_expr(els);
+                }
+            }
+            ExprKind::Ret(ref val) => {
+                match *val {
+                    Some(ref val) => self.classify(val),
+                    None => self.mixed = self.mixed.or(Some(e.span)),
+                }
+                visit::walk_expr(self, e);
+            }
+            // Returns inside closures don't return from the function.
+            ExprKind::Closure(..) => {}
+            _ => visit::walk_expr(self, e),
+        }
+    }
+
+    fn visit_mac(&mut self, _mac: &'ast Mac) {}
+}
+
+impl Transform for ErrcodeToResult {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        // (1) Classify the return values of each marked function.
+
+        let mut fns: HashMap<DefId, HashMap<NodeId, ErrcodeRet>> = HashMap::new();
+        visit_fns(krate, |fl| {
+            if !st.marked(fl.id, "target") {
+                return;
+            }
+            let block = match fl.block {
+                Some(ref block) => block,
+                None => return,
+            };
+            let def_id = cx.node_def_id(fl.id);
+            let ret_ty = cx.ty_ctxt().fn_sig(def_id).skip_binder().output();
+            if ret_ty.kind != TyKind::Int(IntTy::I32) {
+                warn!("errcode_to_result: `{}` doesn't return `c_int`; skipping", fl.ident);
+                return;
+            }
+
+            let mut classifier = ErrcodeClassifier {
+                cx,
+                guards: Vec::new(),
+                rets: HashMap::new(),
+                mixed: None,
+            };
+            classifier.visit_block(block);
+            let mut tail = block.clone();
+            fold_output_exprs(&mut tail, true, |e| classifier.classify(e));
+
+            if let Some(span) = classifier.mixed {
+                warn!("errcode_to_result: `{}` returns a value at {} that isn't `0` or an \
+                       error code; skipping",
+                      fl.ident, cx.session().source_map().span_to_string(span));
+                return;
+            }
+            fns.insert(def_id, classifier.rets);
+        });
+        if fns.is_empty() {
+            return;
+        }
+
+        // (2) Rewrite call sites and return values.
+
+        let mut mcx = MatchCtxt::new(st, cx);
+        let ok_repl = mcx.parse_expr("Ok(())");
+        let err_repl = if self.error_ty == "i32" {
+            mcx.parse_expr("Err($e)")
+        } else {
+            mcx.parse_expr(&format!("Err(<{}>::from($e))", self.error_ty))
+        };
+        let try_repl = mcx.parse_expr("$e?");
+        let is_err_repl = mcx.parse_expr("$e.is_err()");
+        let to_int_repl = if self.error_ty == "i32" {
+            mcx.parse_expr("match $e { Ok(()) => 0, Err(e) => e }")
+        } else {
+            mcx.parse_expr("match $e { Ok(()) => 0, Err(e) => e.into() }")
+        };
+        let wrap = |repl: &P<Expr>, e: P<Expr>| {
+            let mut bnd = Bindings::new();
+            bnd.add("$e", e);
+            repl.clone().subst(st, cx, &bnd)
+        };
+        let is_converted_call = |e: &Expr| match e.kind {
+            ExprKind::Call(..) => cx.opt_callee(e)
+                .map_or(false, |def_id| fns.contains_key(&def_id)),
+            _ => false,
+        };
+        let new_ret_ty = parse_ty(cx.session(), &format!("Result<(), {}>", self.error_ty));
+
+        mut_visit_fns(krate, |fl| {
+            let rets = fns.get(&cx.node_def_id(fl.id));
+            let block = match fl.block {
+                Some(ref mut block) => block,
+                None => return,
+            };
+            let mut handled = HashSet::new();
+
+            // `let rc = f(); if rc < 0 { ... }`
+            MutVisitNodes::visit(block, |b: &mut P<Block>| {
+                let mut i = 0;
+                while i + 1 < b.stmts.len() {
+                    let (ident, hir_id, call) = match b.stmts[i].kind {
+                        StmtKind::Local(ref l) => match (&l.pat.kind, &l.init) {
+                            (&PatKind::Ident(_, ident, None), &Some(ref init))
+                                    if l.ty.is_none() && is_converted_call(init) =>
+                                (ident, cx.hir_map().node_to_hir_id(l.pat.id), init.clone()),
+                            _ => {
+                                i += 1;
+                                continue;
+                            }
+                        },
+                        _ => {
+                            i += 1;
+                            continue;
+                        }
+                    };
+                    let is_use = |e: &Expr| match e.kind {
+                        ExprKind::Path(None, _) => cx.try_resolve_expr_to_hid(e) == Some(hir_id),
+                        _ => false,
+                    };
+                    let count_uses = |stmts: &[Stmt]| {
+                        let mut count = 0;
+                        for s in stmts {
+                            visit_nodes(s, |e: &Expr| if is_use(e) { count += 1 });
+                        }
+                        count
+                    };
+
+                    let (then, els) = match b.stmts[i + 1].kind {
+                        StmtKind::Expr(ref e) | StmtKind::Semi(ref e) => match e.kind {
+                            ExprKind::If(ref cond, ref then, ref els)
+                                    if error_check(cond).map_or(false, |x| is_use(x)) =>
+                                (then.clone(), els.clone()),
+                            _ => {
+                                i += 1;
+                                continue;
+                            }
+                        },
+                        _ => {
+                            i += 1;
+                            continue;
+                        }
+                    };
+                    if count_uses(&b.stmts[i + 1..]) != 1 + count_uses(&then.stmts) {
+                        i += 1;
+                        continue;
+                    }
+                    handled.insert(call.id);
+
+                    let returns_rc = then.stmts.len() == 1 && match then.stmts[0].kind {
+                        StmtKind::Semi(ref e) | StmtKind::Expr(ref e) => match e.kind {
+                            ExprKind::Ret(Some(ref val)) => is_use(val),
+                            _ => false,
+                        },
+                        _ => false,
+                    };
+                    let use_try = rets.is_some() && els.is_none() && returns_rc;
+                    let new_expr = if use_try {
+                        wrap(&try_repl, call)
+                    } else {
+                        let src = format!("if let Err({}) = __call {{}}", ident);
+                        let mut new_if = parse_expr(cx.session(), &src);
+                        if let ExprKind::If(ref mut cond, ref mut new_then, ref mut new_els) =
+                                new_if.kind {
+                            if let ExprKind::Let(_, ref mut scrutinee) = cond.kind {
+                                *scrutinee = call;
+                            }
+                            *new_then = then;
+                            *new_els = els;
+                        }
+                        new_if
+                    };
+                    let mut stmt = b.stmts.remove(i + 1);
+                    stmt.kind = match stmt.kind {
+                        StmtKind::Expr(_) if !use_try => StmtKind::Expr(new_expr),
+                        _ => StmtKind::Semi(new_expr),
+                    };
+                    b.stmts[i] = stmt;
+                    i += 1;
+                }
+            });
+
+            // `if f() < 0 { ... }`, and any other use of a converted function's result.
+            MutVisitNodes::visit(block, |e: &mut P<Expr>| {
+                if let ExprKind::If(ref mut cond, _, _) = e.kind {
+                    let call = error_check(cond).filter(|x| is_converted_call(x)).cloned();
+                    if let Some(call) = call {
+                        handled.insert(call.id);
+                        *cond = wrap(&is_err_repl, call);
+                    }
+                }
+                if is_converted_call(e) && !handled.contains(&e.id) {
+                    handled.insert(e.id);
+                    *e = wrap(&to_int_repl, e.clone());
+                }
+            });
+
+            // Rewrite the returns of converted functions.
+            let rets = match rets {
+                Some(rets) => rets,
+                None => return,
+            };
+            let rewrite_ret = |e: &mut P<Expr>| match rets.get(&e.id) {
+                Some(ErrcodeRet::Ok) => *e = ok_repl.clone(),
+                Some(ErrcodeRet::Err) => *e = wrap(&err_repl, e.clone()),
+                None => {}
+            };
+            MutVisitNodes::visit(block, |e: &mut P<Expr>| {
+                if let ExprKind::Ret(Some(ref mut val)) = e.kind {
+                    rewrite_ret(val);
+                }
+            });
+            fold_output_exprs(block, true, rewrite_ret);
+            fl.decl.output = FunctionRetTy::Ty(new_ret_ty.clone());
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
+/// # `inline_trivial_fns` Command
+///
+/// Usage: `inline_trivial_fns [keep=REGEX]`
+///
+/// Find functions whose body is a single call that forwards all of their
+/// arguments in order, like `fn foo(a: i32) -> i32 { foo_impl(a) }` or `fn
+/// bar(w: *mut widget) { unsafe { (*w).bar() } }`.  The forwarded arguments may
+/// be adjusted with `&`, `&mut` or `*`.  Every call to such a function is
+/// replaced with the body of the function, so that it calls the target
+/// directly.  Chains of forwarding functions are followed to the final target.
+///
+/// Afterward, forwarding functions that are no longer used are removed, unless
+/// they are public, `#[no_mangle]` or `#[export_name]`.  Functions whose names
+/// match `REGEX` are left alone entirely, and calls to them are kept.
+///
+/// Calls from another module use the absolute path of the target, and are left
+/// unchanged if the target isn't visible there.
+pub struct InlineTrivialFns {
+    pub keep: Option<Regex>,
+}
+
+/// Remove the functions in `fns` that are no longer used, along with the imports of them.
+fn remove_unused_fns(krate: &mut Crate, cx: &RefactorCtxt, fns: HashSet<DefId>) {
+    let mut used = HashSet::new();
+    visit_nodes(krate, |e: &Expr| {
+        if let Some(def_id) = cx.try_resolve_expr(e) {
+            if fns.contains(&def_id) {
+                used.insert(def_id);
+            }
+        }
+    });
+    let removed = fns.difference(&used).cloned().collect::<HashSet<_>>();
+
+    FlatMapNodes::visit(krate, |i: P<Item>| {
+        match i.kind {
+            ItemKind::Fn(..) if removed.contains(&cx.node_def_id(i.id)) => smallvec![],
+            ItemKind::Use(..) => {
+                let mut i = i;
+                let id = i.id;
+                let keep = match i.kind {
+                    ItemKind::Use(ref mut tree) => prune_use_tree(cx, id, tree, &removed),
+                    _ => unreachable!(),
+                };
+                if keep { smallvec![i] } else { smallvec![] }
+            }
+            _ => smallvec![i],
+        }
+    });
+}
+
+/// A function that `inline_trivial_fns` inlines.
+struct TrivialFn {
+    /// The body of the function, to be copied into each call site.
+    body: P<Expr>,
+    /// Uses of each argument in `body`, mapped to the index of the argument.
+    arg_uses: HashMap<NodeId, usize>,
+    /// The `NodeId` of the path to the callee in `body`, if it's not a method call.
+    callee_path: Option<NodeId>,
+    target: DefId,
+    /// The module containing the function.
+    module: HirId,
+    removable: bool,
+}
+
+/// Strip `&`, `&mut`, `*` and parentheses from `e`.
+fn strip_ref_adjustments(e: &Expr) -> &Expr {
+    match e.kind {
+        ExprKind::Paren(ref inner) |
+        ExprKind::AddrOf(_, _, ref inner) |
+        ExprKind::Unary(UnOp::Deref, ref inner) => strip_ref_adjustments(inner),
+        _ => e,
+    }
+}
+
+/// If `i` is a function that `inline_trivial_fns` can inline, build its `TrivialFn`.
+fn trivial_fn(cx: &RefactorCtxt, i: &Item) -> Option<TrivialFn> {
+    let (sig, generics, block) = match_or!([i.kind]
+        ItemKind::Fn(ref sig, ref generics, ref block) => (sig, generics, block); return None);
+    if !generics.params.is_empty() || sig.decl.c_variadic() {
+        return None;
+    }
+    let arg_ids = sig.decl.inputs.iter().map(|arg| match arg.pat.kind {
+        PatKind::Ident(BindingMode::ByValue(_), _, None) =>
+            Some(cx.hir_map().node_to_hir_id(arg.pat.id)),
+        _ => None,
+    }).collect::<Option<Vec<_>>>()?;
+
+    let body = match block.stmts[..] {
+        [Stmt { kind: StmtKind::Expr(ref e), .. }] => e,
+        [Stmt { kind: StmtKind::Semi(ref e), .. }] if cx.node_type(e.id).is_unit() => e,
+        _ => return None,
+    };
+    // Look through an `unsafe` block around the call.
+    let call = match body.kind {
+        ExprKind::Block(ref b, None) if b.rules != BlockCheckMode::Default => {
+            match b.stmts[..] {
+                [Stmt { kind: StmtKind::Expr(ref e), .. }] => e,
+                [Stmt { kind: StmtKind::Semi(ref e), .. }] if cx.node_type(e.id).is_unit() => e,
+                _ => return None,
+            }
+        }
+        _ => body,
+    };
+
+    let (args, callee_path, target) = match call.kind {
+        ExprKind::Call(ref func, ref args) => {
+            match func.kind {
+                ExprKind::Path(_, ref path) if path.segments.iter().all(|s| s.args.is_none()) => {}
+                _ => return None,
+            }
+            (args, Some(func.id), cx.try_resolve_expr(func)?)
+        }
+        ExprKind::MethodCall(_, ref args) => (args, None, cx.opt_callee(call)?),
+        _ => return None,
+    };
+    if args.len() != arg_ids.len() || target == cx.node_def_id(i.id) {
+        return None;
+    }
+    let mut arg_uses = HashMap::new();
+    for (idx, (arg, &arg_id)) in args.iter().zip(arg_ids.iter()).enumerate() {
+        let arg = strip_ref_adjustments(arg);
+        if cx.try_resolve_expr_to_hid(arg) != Some(arg_id) {
+            return None;
+        }
+        arg_uses.insert(arg.id, idx);
+    }
+
+    let hir_id = cx.hir_map().node_to_hir_id(i.id);
+    Some(TrivialFn {
+        body: body.clone(),
+        arg_uses,
+        callee_path,
+        target,
+        module: cx.hir_map().get_module_parent_node(hir_id),
+        removable: !i.vis.node.is_pub() &&
+            !attr::contains_name(&i.attrs, sym::no_mangle) &&
+            !attr::contains_name(&i.attrs, sym::export_name),
+    })
+}
+
+/// Copies the body of a `TrivialFn` into a call site, replacing uses of its arguments.
+struct ArgSubstFolder<'a, 'tcx: 'a> {
+    cx: &'a RefactorCtxt<'a, 'tcx>,
+    f: &'a TrivialFn,
+    args: &'a [P<Expr>],
+    /// Whether the call site is in another module than the function.
+    other_module: bool,
+}
+
+impl<'a, 'tcx> ArgSubstFolder<'a, 'tcx> {
+    fn arg(&self, e: &Expr) -> Option<&'a P<Expr>> {
+        let args = self.args;
+        self.f.arg_uses.get(&e.id).map(|&idx| &args[idx])
+    }
+}
+
+/// Wrap `e` in parentheses, unless it binds tightly enough to be used as an operand.
+fn operand(e: &P<Expr>) -> P<Expr> {
+    match e.kind {
+        ExprKind::Path(..) | ExprKind::Lit(..) | ExprKind::Paren(..) |
+        ExprKind::Call(..) | ExprKind::MethodCall(..) |
+        ExprKind::Field(..) | ExprKind::Index(..) => e.clone(),
+        _ => mk().paren_expr(e.clone()),
+    }
+}
+
+impl<'a, 'tcx> MutVisitor for ArgSubstFolder<'a, 'tcx> {
+    fn visit_expr(&mut self, e: &mut P<Expr>) {
+        if let Some(arg) = self.arg(e) {
+            *e = operand(arg);
+            return;
+        }
+
+        match e.kind {
+            // `*&x` is just `x`.
+            ExprKind::Unary(UnOp::Deref, ref inner) => {
+                if let Some(arg) = self.arg(inner) {
+                    if let ExprKind::AddrOf(_, _, ref place) = arg.kind {
+                        *e = operand(place);
+                        return;
+                    }
+                }
+            }
+            // Arguments don't need extra parentheses.
+            ExprKind::Call(_, ref mut args) => {
+                for a in args.iter_mut() {
+                    if let Some(arg) = self.arg(a) {
+                        *a = arg.clone();
+                    }
+                }
+            }
+            ExprKind::MethodCall(_, ref mut args) => {
+                for a in args.iter_mut().skip(1) {
+                    if let Some(arg) = self.arg(a) {
+                        *a = arg.clone();
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        if self.other_module && self.f.callee_path == Some(e.id) {
+            *e = mk().path_expr(self.cx.def_path(self.f.target));
+            return;
+        }
+        mut_visit::noop_visit_expr(e, self)
+    }
+
+    fn visit_mac(&mut self, mac: &mut Mac) {
+        mut_visit::noop_visit_mac(mac, self)
+    }
+}
+
+/// Replaces calls to `TrivialFn`s with their bodies.
+struct InlineFolder<'a, 'tcx: 'a> {
+    cx: &'a RefactorCtxt<'a, 'tcx>,
+    fns: &'a HashMap<DefId, TrivialFn>,
+    /// The module containing the current node.
+    module: HirId,
+}
+
+impl<'a, 'tcx> MutVisitor for InlineFolder<'a, 'tcx> {
+    fn flat_map_item(&mut self, i: P<Item>) -> SmallVec<[P<Item>; 1]> {
+        if !matches!([i.kind] ItemKind::Mod(..)) {
+            return mut_visit::noop_flat_map_item(i, self);
+        }
+        let module = self.cx.hir_map().node_to_hir_id(i.id);
+        let old = mem::replace(&mut self.module, module);
+        let i = mut_visit::noop_flat_map_item(i, self);
+        self.module = old;
+        i
+    }
+
+    fn visit_expr(&mut self, e: &mut P<Expr>) {
+        // Rewrite the arguments first, so they don't get visited again once they're moved into
+        // the inlined body.
+        mut_visit::noop_visit_expr(e, self);
+
+        let (def_id, args) = match e.kind {
+            ExprKind::Call(ref func, ref args) => {
+                match_or!([self.cx.try_resolve_expr(func)] Some(x) => (x, args.clone()); return)
+            }
+            _ => return,
+        };
+        let f = match_or!([self.fns.get(&def_id)] Some(x) => x; return);
+        let other_module = f.module != self.module;
+        if other_module {
+            let tcx = self.cx.ty_ctxt();
+            let module = self.cx.hir_map().local_def_id(self.module);
+            if !tcx.visibility(f.target).is_accessible_from(module, tcx) {
+                return;
+            }
+        }
+
+        let mut new_e = f.body.clone();
+        new_e.visit(&mut ArgSubstFolder { cx: self.cx, f, args: &args, other_module });
+        *e = new_e;
+        // The body may call another forwarding function.
+        self.visit_expr(e);
+    }
+
+    fn visit_mac(&mut self, mac: &mut Mac) {
+        mut_visit::noop_visit_mac(mac, self)
+    }
+}
+
+impl Transform for InlineTrivialFns {
+    fn transform(&self, krate: &mut Crate, _st: &CommandState, cx: &RefactorCtxt) {
+        // (1) Find the forwarding functions.
+
+        let mut fns = HashMap::new();
+        visit_nodes(krate, |i: &Item| {
+            if let Some(ref keep) = self.keep {
+                if keep.is_match(&i.ident.as_str()) {
+                    return;
+                }
+            }
+            if let Some(f) = trivial_fn(cx, i) {
+                fns.insert(cx.node_def_id(i.id), f);
+            }
+        });
+
+        // Drop functions that forward to themselves through a cycle.
+        let cyclic = fns.keys().filter(|&&def_id| {
+            let mut seen = HashSet::new();
+            let mut cur = def_id;
+            while let Some(f) = fns.get(&cur) {
+                if !seen.insert(cur) {
+                    return true;
+                }
+                cur = f.target;
+            }
+            false
+        }).cloned().collect::<Vec<_>>();
+        for def_id in cyclic {
+            fns.remove(&def_id);
+        }
+
+        // (2) Inline the calls.
+
+        krate.visit(&mut InlineFolder {
+            cx,
+            fns: &fns,
+            module: hir::CRATE_HIR_ID,
+        });
+
+        // (3) Remove the functions that are no longer used, and the imports of them.
+
+        let removable = fns.iter()
+            .filter(|&(_, f)| f.removable)
+            .map(|(&def_id, _)| def_id)
+            .collect();
+        remove_unused_fns(krate, cx, removable);
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+/// # `inline_accessors` Command
+///
+/// Usage: `inline_accessors [mode=methods]`
+///
+/// Find field accessors: functions whose body reads a field of their first
+/// argument, like `fn widget_get_width(w: *const widget) -> i32 { (*w).width
+/// }`, and functions that set a field of their first argument to their second,
+/// like `fn widget_set_width(w: *mut widget, v: i32) { (*w).width = v; }`.  The
+/// first argument must be a pointer or a reference to a struct.  Every call is
+/// replaced with the field access, so `widget_get_width(w)` becomes
+/// `(*w).width`, or `w.width` for a reference `w`, and `widget_set_width(w, 3)`
+/// becomes `(*w).width = 3`.  Calls from where the field isn't visible are
+/// kept.
+///
+/// With `mode=methods`, the accessors become methods in the struct's `impl`
+/// instead, `fn width(&self) -> i32` and `fn set_width(&mut self, v: i32)`, and
+/// the calls become `(*w).width()` and `(*w).set_width(3)`.  Accessors are
+/// skipped if the struct already has a method of the same name.
+///
+/// Afterward, accessors that are no longer used are removed, unless they are
+/// public, `#[no_mangle]` or `#[export_name]`.
+pub struct InlineAccessors {
+    pub methods: bool,
+}
+
+/// A field getter or setter found by `inline_accessors`.
+struct Accessor {
+    ident: Ident,
+    field: Ident,
+    field_did: DefId,
+    /// The struct the field belongs to.
+    ty: DefId,
+    /// For a setter, the name of the argument holding the new value.
+    setter: Option<Ident>,
+    /// The type of the field, as written in the accessor's signature.
+    value_ty: P<Ty>,
+    vis: Visibility,
+    is_unsafe: bool,
+    removable: bool,
+}
+
+impl Accessor {
+    fn method_name(&self) -> Ident {
+        match self.setter {
+            Some(_) => Ident::from_str(&format!("set_{}", self.field)),
+            None => self.field,
+        }
+    }
+}
+
+/// Get the expression that makes up the body of an accessor, looking through an `unsafe` block
+/// and a `return`.
+fn accessor_body(b: &Block) -> Option<&P<Expr>> {
+    let e = match b.stmts[..] {
+        [Stmt { kind: StmtKind::Expr(ref e), .. }] |
+        [Stmt { kind: StmtKind::Semi(ref e), .. }] => e,
+        _ => return None,
+    };
+    match e.kind {
+        ExprKind::Block(ref b, None) if b.rules != BlockCheckMode::Default => accessor_body(b),
+        ExprKind::Ret(Some(ref val)) => Some(val),
+        _ => Some(e),
+    }
+}
+
+/// If `i` is a field getter or setter, build its `Accessor`.
+fn accessor(cx: &RefactorCtxt, i: &Item) -> Option<Accessor> {
+    let (sig, generics, block) = match_or!([i.kind]
+        ItemKind::Fn(ref sig, ref generics, ref block) => (sig, generics, block); return None);
+    if !generics.params.is_empty() || sig.decl.c_variadic() {
+        return None;
+    }
+    let args = sig.decl.inputs.iter().map(|arg| match arg.pat.kind {
+        PatKind::Ident(BindingMode::ByValue(_), ident, None) =>
+            Some((cx.hir_map().node_to_hir_id(arg.pat.id), ident)),
+        _ => None,
+    }).collect::<Option<Vec<_>>>()?;
+    let (ptr_ty, mutable) = match cx.node_type(sig.decl.inputs.get(0)?.pat.id).kind {
+        TyKind::RawPtr(mt) => (mt.ty, mt.mutbl == hir::Mutability::Mutable),
+        TyKind::Ref(_, ty, mutbl) => (ty, mutbl == hir::Mutability::Mutable),
+        _ => return None,
+    };
+    let adt = match ptr_ty.kind {
+        TyKind::Adt(adt, _) if adt.is_struct() && adt.did.is_local() => adt,
+        _ => return None,
+    };
+
+    // `(*w).field` or `w.field`, where `w` is the first argument.
+    let arg_field = |e: &Expr| {
+        let (base, field) = match_or!([e.kind]
+            ExprKind::Field(ref base, field) => (base, field); return None);
+        let mut base = base;
+        while let ExprKind::Paren(ref inner) | ExprKind::Unary(UnOp::Deref, ref inner) =
+                base.kind {
+            base = inner;
+        }
+        if cx.try_resolve_expr_to_hid(base) == Some(args[0].0) {
+            Some(field)
+        } else {
+            None
+        }
+    };
+    let body = accessor_body(block)?;
+    let (field, setter, value_ty) = match (&sig.decl.output, args.len()) {
+        (FunctionRetTy::Ty(ty), 1) => (arg_field(body)?, None, ty.clone()),
+        (FunctionRetTy::Default(_), 2) if mutable => {
+            let (lhs, rhs) = match_or!([body.kind]
+                ExprKind::Assign(ref lhs, ref rhs) => (lhs, rhs); return None);
+            if cx.try_resolve_expr_to_hid(rhs) != Some(args[1].0) {
+                return None;
+            }
+            (arg_field(lhs)?, Some(args[1].1), sig.decl.inputs[1].ty.clone())
+        }
+        _ => return None,
+    };
+    let field_did = adt.non_enum_variant().fields.iter()
+        .find(|f| f.ident.name == field.name)?.did;
+
+    Some(Accessor {
+        ident: i.ident,
+        field,
+        field_did,
+        ty: adt.did,
+        setter,
+        value_ty,
+        vis: i.vis.clone(),
+        is_unsafe: sig.header.unsafety == Unsafety::Unsafe,
+        removable: !i.vis.node.is_pub() &&
+            !attr::contains_name(&i.attrs, sym::no_mangle) &&
+            !attr::contains_name(&i.attrs, sym::export_name),
+    })
+}
+
+impl Transform for InlineAccessors {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        // (1) Find the accessors.
+
+        let mut accessors = HashMap::new();
+        let mut order = Vec::new();
+        visit_nodes(krate, |i: &Item| {
+            if let Some(a) = accessor(cx, i) {
+                let def_id = cx.node_def_id(i.id);
+                accessors.insert(def_id, a);
+                order.push(def_id);
+            }
+        });
+        if self.methods {
+            let mut existing = HashSet::new();
+            visit_nodes(krate, |i: &Item| {
+                if let ItemKind::Impl(_, _, _, _, None, ref ty, ref items) = i.kind {
+                    if let Some(def_id) = cx.try_resolve_ty(ty) {
+                        existing.extend(items.iter().map(|item| (def_id, item.ident.name)));
+                    }
+                }
+            });
+            accessors.retain(|_, a| {
+                if existing.contains(&(a.ty, a.method_name().name)) {
+                    warn!("inline_accessors: `{}` already has a method `{}`; skipping `{}`",
+                          cx.ty_ctxt().def_path_str(a.ty), a.method_name(), a.ident);
+                    return false;
+                }
+                true
+            });
+        }
+        if accessors.is_empty() {
+            return;
+        }
+
+        // (2) Rewrite the calls.  Note which receivers are raw pointers first, since the
+        // arguments may be rewritten before the call.
+
+        let mut ptr_recvs = HashSet::new();
+        visit_nodes(krate, |e: &Expr| {
+            if let ExprKind::Call(_, ref args) = e.kind {
+                match args.get(0).and_then(|recv| cx.opt_node_type(recv.id)) {
+                    Some(ty) if matches!([ty.kind] TyKind::RawPtr(_)) => {
+                        ptr_recvs.insert(e.id);
+                    }
+                    _ => {}
+                }
+            }
+        });
+
+        let tcx = cx.ty_ctxt();
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            let (def_id, mut args) = match e.kind {
+                ExprKind::Call(ref func, ref args) if func.id != DUMMY_NODE_ID => {
+                    match_or!([cx.try_resolve_expr(func)] Some(x) => (x, args.clone()); return)
+                }
+                _ => return,
+            };
+            let a = match_or!([accessors.get(&def_id)] Some(x) => x; return);
+            if args.len() != if a.setter.is_some() { 2 } else { 1 } {
+                return;
+            }
+            if !self.methods {
+                let hir_id = cx.hir_map().node_to_hir_id(e.id);
+                let module = cx.hir_map().local_def_id(cx.hir_map().get_module_parent_node(hir_id));
+                if !tcx.visibility(a.field_did).is_accessible_from(module, tcx) {
+                    return;
+                }
+            }
+
+            let recv = args.remove(0);
+            let mut needs_unsafe = false;
+            let base = match strip_casts(&recv).kind {
+                // `f(&mut x)` => `x.field`
+                ExprKind::AddrOf(_, _, ref place) => operand(place),
+                _ if ptr_recvs.contains(&e.id) => {
+                    needs_unsafe = !a.is_unsafe;
+                    mk().paren_expr(mk().unary_expr("*", operand(&recv)))
+                }
+                _ => operand(&recv),
+            };
+            let new_e = if self.methods {
+                mk().method_call_expr(base, a.method_name(), args)
+            } else if a.setter.is_some() {
+                mk().assign_expr(mk().field_expr(base, a.field), args.remove(0))
+            } else {
+                mk().field_expr(base, a.field)
+            };
+            *e = if needs_unsafe {
+                mk().block_expr(mk().unsafe_().block(vec![mk().expr_stmt(new_e)]))
+            } else {
+                new_e
+            };
+        });
+
+        // (3) Remove the accessors that are no longer used.
+
+        let removable = accessors.iter()
+            .filter(|&(_, a)| a.removable)
+            .map(|(&def_id, _)| def_id)
+            .collect();
+        remove_unused_fns(krate, cx, removable);
+
+        // (4) With `mode=methods`, add the methods to the structs.
+
+        if !self.methods {
+            return;
+        }
+        let mut methods = HashMap::<DefId, Vec<ImplItem>>::new();
+        let mut added = HashSet::new();
+        for def_id in &order {
+            let a = match_or!([accessors.get(def_id)] Some(x) => x; continue);
+            if !added.insert((a.ty, a.method_name().name)) {
+                continue;
+            }
+            let vis = pprust::vis_to_string(&a.vis);
+            let value_ty = pprust::ty_to_string(&a.value_ty);
+            let method_src = match a.setter {
+                Some(value) => format!("{}fn {}(&mut self, {}: {}) {{ self.{} = {}; }}",
+                                       vis, a.method_name(), value, value_ty, a.field, value),
+                None => format!("{}fn {}(&self) -> {} {{ self.{} }}",
+                                vis, a.method_name(), value_ty, a.field),
+            };
+            let i = st.parse_items(cx, &format!("impl S {{ {} }}", method_src)).lone();
+            let method = expect!([i.kind]
+                ItemKind::Impl(_, _, _, _, _, _, ref items) => items[0].clone());
+            methods.entry(a.ty).or_insert_with(Vec::new).push(method);
+        }
+        add_inherent_methods(krate, st, cx, methods);
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
+pub fn register_commands(reg: &mut Registry) {
+    use super::mk;
+
+    reg.register("func_to_method", |_args| mk(ToMethod));
+    reg.register("prefix_to_methods", |args| {
+        let arg = |name: &str| args.iter()
+            .find(|arg| arg.starts_with(name) && arg[name.len()..].starts_with('='))
+            .map(|arg| arg[name.len() + 1..].to_owned())
+            .unwrap_or_else(|| panic!("prefix_to_methods: missing `{}=` argument", name));
+        mk(PrefixToMethods {
+            ty: arg("type"),
+            prefix: arg("prefix"),
+        })
+    });
+    reg.register("fix_unused_unsafe", |_args| mk(FixUnusedUnsafe));
+    reg.register("sink_unsafe", |_args| mk(SinkUnsafe));
+    reg.register("remove_unneeded_unsafe", |_args| mk(RemoveUnneededUnsafe));
+    reg.register("unsafe_block_cleanup", |args| Box::new(UnsafeBlockCleanup {
+        mode: match args.iter().find(|arg| arg.starts_with("mode=")) {
+            Some(arg) if arg == "mode=merge" => UnsafeCleanupMode::Merge,
+            Some(arg) if arg == "mode=shrink" => UnsafeCleanupMode::Shrink,
+            _ => panic!("unsafe_block_cleanup: expected `mode=merge` or `mode=shrink`"),
+        },
+        gap: args.iter().find(|arg| arg.starts_with("gap="))
+            .map_or(1, |arg| arg["gap=".len()..].parse().expect("bad gap")),
+    }));
+    reg.register("wrap_extern", |_args| mk(WrapExtern));
+    reg.register("wrap_api", |_args| mk(WrapApi));
+    reg.register("abstract", |args| mk(Abstract {
+        sig: args[0].clone(),
+        pat: args[1].clone(),
+        body: args.get(2).cloned(),
+    }));
+    reg.register("outparams_to_tuple", |_args| mk(OutparamsToTuple));
+    reg.register("inline_trivial_fns", |args| mk(InlineTrivialFns {
+        keep: args.iter()
+            .find(|arg| arg.starts_with("keep="))
+            .map(|arg| Regex::new(&arg["keep=".len()..]).unwrap()),
+    }));
+    reg.register("inline_accessors", |args| mk(InlineAccessors {
+        methods: args.iter().any(|arg| arg == "mode=methods"),
+    }));
+    reg.register("errcode_to_result", |args| mk(ErrcodeToResult {
+        error_ty: args.get(0).cloned().unwrap_or_else(|| "i32".to_owned()),
     }));
 }
@@ -0,0 +1,408 @@
+//! Transform that replaces `getopt` loops with a generated argument parser.
+
+use std::collections::{HashMap, HashSet};
+use syntax::ast::*;
+use syntax::print::pprust;
+use syntax::ptr::P;
+use smallvec::smallvec;
+
+use crate::ast_manip::{FlatMapNodes, MutVisitNodes, visit_nodes};
+use crate::ast_manip::fn_edit::mut_visit_fns;
+use crate::command::{CommandState, Registry};
+use crate::driver::{self, Phase};
+use crate::reflect;
+use crate::transform::Transform;
+use crate::transform::assert::foreign_call;
+use crate::transform::mem::strip_casts;
+use crate::transform::strings::StrOperand;
+use crate::RefactorCtxt;
+
+
+/// # `getopt_to_args` Command
+///
+/// Usage: `getopt_to_args`
+///
+/// Replace the loops that parse the command line with `getopt`, as
+/// translated from `while ((c = getopt(argc, argv, "ab:c")) != -1) switch (c)
+/// { ... }`:
+///
+/// ```ignore
+///     loop {
+///         c = getopt(argc, argv, b"ab:c\x00" as *const u8 as *const libc::c_char);
+///         if !(c != -(1 as libc::c_int)) {
+///             break;
+///         }
+///         match c { ... }
+///     }
+/// ```
+///
+/// with a loop over `::std::env::args()` that accepts the options of the
+/// literal option string the same way `getopt` does: grouped flags as in
+/// `-ac`, values attached as in `-bvalue` or separate as in `-b value`, and
+/// options ending at `--` or at the first argument that isn't one.  Unlike
+/// glibc's `getopt`, the parser doesn't permute the arguments, so options
+/// after the first operand aren't parsed, as with POSIX `getopt` or an option
+/// string starting with `+`.  Each
+/// option sets `c` and runs the `match` as before, so the arms set the same
+/// variables; flags set to `1` stay integers, which `int_to_bool` can change.
+/// An unknown option sets `c` to `'?'`, as does a missing value, unless the
+/// option string starts with `:`, which makes it `':'`.
+///
+/// In the arms, `CStr::from_ptr(optarg).to_str().unwrap()`, as left by the
+/// string conversions, becomes the value as a `&str`, and any other `optarg`
+/// becomes a pointer to a `CString` of the value, up to any NUL.  The parser
+/// builds one such `CString` for each option and keeps them all until the
+/// function returns, so the pointers stay valid as long as the arms could
+/// have kept ones into `argv` from a function like `main`.  After the loop, `optind` becomes the index of the first operand,
+/// so the operands are still found in `argv` from `optind` on.
+///
+/// Functions that call `getopt_long` or `getopt_long_only`, that use `opterr`,
+/// `optopt` or `optreset`, that assign to `optind`, or that have more than one
+/// `getopt` loop are skipped, as are loops whose arms `break` or `continue`,
+/// and option strings with optional values (`::`) or starting with `-`.  Note
+/// that `std::env::args()` panics when an argument isn't Unicode.
+pub struct GetoptToArgs;
+
+/// The options of a `getopt` option string.
+struct OptSpec {
+    /// All the options.
+    all: Vec<u8>,
+    /// The options that take a value.
+    with_value: Vec<u8>,
+    /// What `getopt` returns for a missing value.
+    missing: u8,
+}
+
+fn parse_optstring(s: &[u8]) -> Option<OptSpec> {
+    let mut s = s;
+    if s.first() == Some(&b'+') {
+        s = &s[1..];
+    }
+    let missing = if s.first() == Some(&b':') {
+        s = &s[1..];
+        b':'
+    } else {
+        b'?'
+    };
+    if s.first() == Some(&b'-') {
+        return None;
+    }
+
+    let mut spec = OptSpec { all: Vec::new(), with_value: Vec::new(), missing };
+    let mut i = 0;
+    while i < s.len() {
+        let opt = s[i];
+        i += 1;
+        if opt == b':' {
+            return None;
+        }
+        spec.all.push(opt);
+        if s.get(i) == Some(&b':') {
+            i += 1;
+            if s.get(i) == Some(&b':') {
+                return None;
+            }
+            spec.with_value.push(opt);
+        }
+    }
+    Some(spec)
+}
+
+fn byte_str_src(bytes: &[u8]) -> String {
+    let escaped = bytes.iter()
+        .flat_map(|&b| std::ascii::escape_default(b))
+        .map(|b| b as char)
+        .collect::<String>();
+    format!("b\"{}\"", escaped)
+}
+
+/// If `e` refers to a foreign static, return its name.
+fn foreign_static_name(cx: &RefactorCtxt, e: &Expr) -> Option<String> {
+    match e.kind {
+        ExprKind::Path(None, ref path) => {
+            let def_id = cx.try_resolve_expr(e)?;
+            if !cx.ty_ctxt().is_foreign_item(def_id) {
+                return None;
+            }
+            Some(path.segments.last()?.ident.to_string())
+        }
+        _ => None,
+    }
+}
+
+/// If `e` is `CStr::from_ptr(optarg).to_str().unwrap()`, return the `optarg`.
+fn optarg_as_str<'a>(cx: &RefactorCtxt, e: &'a Expr) -> Option<&'a P<Expr>> {
+    let method_arg = |e: &'a Expr, name: &str| match e.kind {
+        ExprKind::MethodCall(ref seg, ref args)
+            if &*seg.ident.as_str() == name && args.len() == 1 => Some(&args[0]),
+        _ => None,
+    };
+    let to_str = method_arg(e, "unwrap")?;
+    let from_ptr = method_arg(to_str, "to_str")?;
+    let (func, args) = match_or!([from_ptr.kind] ExprKind::Call(ref f, ref a) => (f, a);
+                                 return None);
+    let path = match_or!([func.kind] ExprKind::Path(None, ref p) => p; return None);
+    let names = path.segments.iter().rev().take(2)
+        .map(|seg| seg.ident.to_string()).collect::<Vec<_>>();
+    if names != ["from_ptr", "CStr"] || args.len() != 1 {
+        return None;
+    }
+    let optarg = strip_casts(&args[0]);
+    match foreign_static_name(cx, optarg) {
+        Some(ref name) if name == "optarg" => Some(optarg),
+        _ => None,
+    }
+}
+
+/// A `getopt` loop.
+struct GetoptLoop<'a> {
+    /// The variable the result of `getopt` is stored in.
+    opt_var: &'a P<Expr>,
+    spec: OptSpec,
+    /// The `match` on the result.
+    match_expr: &'a P<Expr>,
+}
+
+fn match_getopt_loop<'a>(cx: &RefactorCtxt, e: &'a Expr) -> Option<GetoptLoop<'a>> {
+    let body = match_or!([e.kind] ExprKind::Loop(ref b, None) => b; return None);
+    let stmt_expr = |s: &'a Stmt| match s.kind {
+        StmtKind::Expr(ref e) | StmtKind::Semi(ref e) => Some(e),
+        _ => None,
+    };
+    if body.stmts.len() != 3 {
+        return None;
+    }
+
+    let (opt_var, call) = match_or!([stmt_expr(&body.stmts[0])?.kind]
+                                    ExprKind::Assign(ref l, ref r) => (l, r); return None);
+    let args = foreign_call(cx, call, "getopt").filter(|args| args.len() == 3)?;
+    let optstring = match StrOperand::classify(cx, &args[2]) {
+        StrOperand::Lit(bytes) => bytes,
+        _ => return None,
+    };
+
+    let is_break = |s: &Stmt| match stmt_expr(s).map(|e| &e.kind) {
+        Some(ExprKind::Break(None, None)) => true,
+        _ => false,
+    };
+    match stmt_expr(&body.stmts[1])?.kind {
+        ExprKind::If(_, ref then, None) if then.stmts.len() == 1 && is_break(&then.stmts[0]) => {}
+        _ => return None,
+    }
+
+    let match_expr = stmt_expr(&body.stmts[2])?;
+    let scrutinee = match_or!([match_expr.kind] ExprKind::Match(ref s, _) => s; return None);
+    if pprust::expr_to_string(strip_casts(scrutinee)) != pprust::expr_to_string(opt_var) {
+        return None;
+    }
+
+    let spec = match_or!([parse_optstring(&optstring)] Some(x) => x; {
+        warn!("getopt_to_args: can't handle the option string {:?}",
+              String::from_utf8_lossy(&optstring));
+        return None;
+    });
+    Some(GetoptLoop { opt_var, spec, match_expr })
+}
+
+/// Build the statements that replace the `getopt` loop `l`.
+fn build_parser(cx: &RefactorCtxt, l: &GetoptLoop) -> Vec<Stmt> {
+    // (1) Rewrite the uses of `optarg` in the arms.
+
+    let spec = &l.spec;
+    let mut as_strs = HashSet::new();
+    let mut in_as_strs = HashSet::new();
+    visit_nodes(&**l.match_expr, |e: &Expr| {
+        if let Some(optarg) = optarg_as_str(cx, e) {
+            as_strs.insert(e.id);
+            in_as_strs.insert(optarg.id);
+        }
+    });
+    let mut uses_ptr = false;
+    let mut match_expr = l.match_expr.clone();
+    MutVisitNodes::visit(&mut match_expr, |e: &mut P<Expr>| {
+        let src = if as_strs.contains(&e.id) {
+            "getopt_optarg.as_str()"
+        } else if !in_as_strs.contains(&e.id) &&
+                  foreign_static_name(cx, e).map_or(false, |name| name == "optarg") {
+            if spec.with_value.is_empty() {
+                // No option has a value, so `optarg` is never set.
+                "::std::ptr::null_mut()"
+            } else {
+                uses_ptr = true;
+                "getopt_optarg_ptr"
+            }
+        } else {
+            return;
+        };
+        *e = driver::parse_expr(cx.session(), src);
+    });
+
+    // (2) Build the parser around the `match`.
+
+    let opt_ty = reflect::reflect_tcx_ty(cx.ty_ctxt(), cx.node_type(l.opt_var.id));
+    let mut opt_checks = String::new();
+    if !spec.with_value.is_empty() {
+        opt_checks.push_str(&format!(r#"
+            let mut getopt_optarg = String::new();
+            if {}.contains(&getopt_opt) {{
+                if getopt_pos < getopt_arg.len() {{
+                    getopt_optarg =
+                        String::from_utf8_lossy(&getopt_arg.as_bytes()[getopt_pos..]).into_owned();
+                    getopt_pos = getopt_arg.len();
+                }} else if getopt_ind < getopt_args.len() {{
+                    getopt_optarg = getopt_args[getopt_ind].clone();
+                    getopt_ind += 1;
+                }} else {{
+                    getopt_opt = b{:?};
+                }}
+            }} else "#, byte_str_src(&spec.with_value), spec.missing as char));
+    }
+    opt_checks.push_str(&format!(r#"
+        if !{}.contains(&getopt_opt) {{
+            getopt_opt = b'?';
+        }}
+    "#, byte_str_src(&spec.all)));
+    let (optargs_decl, optarg_ptr) = if uses_ptr {
+        ("let mut getopt_optargs: Vec<::std::ffi::CString> = Vec::new();", r#"
+            let getopt_optarg_c = ::std::ffi::CString::new(
+                getopt_optarg.split('\0').next().unwrap_or_default()).unwrap_or_default();
+            let getopt_optarg_ptr = getopt_optarg_c.as_ptr() as *mut ::std::os::raw::c_char;
+            getopt_optargs.push(getopt_optarg_c);
+        "#)
+    } else {
+        ("", "")
+    };
+
+    let src = format!(r#"
+        let getopt_args: Vec<String> = ::std::env::args().collect();
+        {}
+        let mut getopt_ind: usize = 1;
+        while getopt_ind < getopt_args.len() {{
+            let getopt_arg = getopt_args[getopt_ind].clone();
+            if getopt_arg == "--" {{
+                getopt_ind += 1;
+                break;
+            }}
+            if !getopt_arg.starts_with('-') || getopt_arg == "-" {{
+                break;
+            }}
+            getopt_ind += 1;
+            let mut getopt_pos = 1;
+            while getopt_pos < getopt_arg.len() {{
+                let mut getopt_opt = getopt_arg.as_bytes()[getopt_pos];
+                getopt_pos += 1;
+                {}
+                {}
+                {} = getopt_opt as {};
+                __getopt_match;
+            }}
+        }}
+    "#, optargs_decl, opt_checks, optarg_ptr, pprust::expr_to_string(l.opt_var),
+        pprust::ty_to_string(&opt_ty));
+    let mut stmts = driver::parse_stmts(cx.session(), &src);
+    FlatMapNodes::visit(&mut stmts, |s: Stmt| {
+        let is_placeholder = match s.kind {
+            StmtKind::Semi(ref e) => pprust::expr_to_string(e) == "__getopt_match",
+            _ => false,
+        };
+        if is_placeholder {
+            smallvec![Stmt { kind: StmtKind::Expr(match_expr.clone()), ..s }]
+        } else {
+            smallvec![s]
+        }
+    });
+    stmts
+}
+
+impl Transform for GetoptToArgs {
+    fn transform(&self, krate: &mut Crate, _st: &CommandState, cx: &RefactorCtxt) {
+        mut_visit_fns(krate, |fl| {
+            // (1) Find the `getopt` loop, and check that the function can be rewritten.
+
+            let block = match_or!([fl.block] Some(ref b) => b; return);
+            let mut loops = HashMap::new();
+            let mut skip_reason = None;
+            visit_nodes(&**block, |e: &Expr| {
+                if let Some(l) = match_getopt_loop(cx, e) {
+                    let mut breaks = false;
+                    visit_nodes(&**l.match_expr, |e: &Expr| match e.kind {
+                        ExprKind::Break(None, _) | ExprKind::Continue(None) => breaks = true,
+                        _ => {}
+                    });
+                    if breaks {
+                        skip_reason = Some("its getopt loop breaks out of the `match`");
+                    } else {
+                        loops.insert(e.id, build_parser(cx, &l));
+                    }
+                }
+                if foreign_call(cx, e, "getopt_long").is_some() ||
+                   foreign_call(cx, e, "getopt_long_only").is_some() {
+                    skip_reason = Some("it calls getopt_long");
+                }
+                match foreign_static_name(cx, e).as_ref().map(|s| &s[..]) {
+                    Some("opterr") | Some("optopt") | Some("optreset") => {
+                        skip_reason = Some("it uses opterr, optopt or optreset");
+                    }
+                    _ => {}
+                }
+                match e.kind {
+                    ExprKind::Assign(ref lhs, _) | ExprKind::AssignOp(_, ref lhs, _)
+                        if foreign_static_name(cx, lhs).map_or(false, |n| n == "optind") => {
+                        skip_reason = Some("it assigns to optind");
+                    }
+                    _ => {}
+                }
+            });
+            if loops.is_empty() {
+                return;
+            }
+            if loops.len() > 1 {
+                skip_reason = Some("it has more than one getopt loop");
+            }
+            if let Some(reason) = skip_reason {
+                warn!("getopt_to_args: skipping {}, as {}", fl.ident, reason);
+                return;
+            }
+
+            // (2) Replace the loop, and the uses of `optind`.
+
+            MutVisitNodes::visit(&mut fl.block, |b: &mut P<Block>| {
+                let is_loop = |s: &Stmt| match s.kind {
+                    StmtKind::Expr(ref e) | StmtKind::Semi(ref e) => loops.contains_key(&e.id),
+                    _ => false,
+                };
+                if !b.stmts.iter().any(is_loop) {
+                    return;
+                }
+                let old_stmts = std::mem::replace(&mut b.stmts, Vec::new());
+                for s in old_stmts {
+                    match s.kind {
+                        StmtKind::Expr(ref e) | StmtKind::Semi(ref e) if is_loop(&s) => {
+                            b.stmts.extend(loops[&e.id].iter().cloned());
+                        }
+                        _ => b.stmts.push(s),
+                    }
+                }
+            });
+
+            MutVisitNodes::visit(&mut fl.block, |e: &mut P<Expr>| {
+                if foreign_static_name(cx, e).map_or(false, |name| name == "optind") {
+                    let ty = reflect::reflect_tcx_ty(cx.ty_ctxt(), cx.node_type(e.id));
+                    let src = format!("getopt_ind as {}", pprust::ty_to_string(&ty));
+                    *e = driver::parse_expr(cx.session(), &src);
+                }
+            });
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
+pub fn register_commands(reg: &mut Registry) {
+    use super::mk;
+
+    reg.register("getopt_to_args", |_args| mk(GetoptToArgs));
+}
@@ -1,20 +1,25 @@
+use rustc::hir::HirId;
 use rustc::hir::def_id::DefId;
-use rustc::ty::TyKind;
-use std::collections::HashSet;
+use rustc::ty::{ParamEnv, TyKind};
+use std::collections::{HashMap, HashSet};
 use std::fmt::Display;
 use syntax::ast::*;
 use syntax::mut_visit::MutVisitor;
+use syntax::print::pprust;
 use syntax::ptr::P;
+use syntax::symbol::{sym, Symbol};
 use smallvec::smallvec;
 
 use c2rust_ast_builder::mk;
-use crate::ast_manip::{FlatMapNodes, MutVisit, visit_nodes};
+use crate::ast_manip::{AstEquiv, FlatMapNodes, MutVisit, MutVisitNodes, Visit, visit_nodes};
 use crate::ast_manip::lr_expr::{self, fold_expr_with_context};
 use crate::command::{CommandState, Registry};
-use crate::driver::{Phase, parse_impl_items, parse_stmts, parse_expr};
+use crate::driver::{Phase, parse_impl_items, parse_items, parse_pat, parse_stmts, parse_expr};
 use crate::reflect::reflect_def_path;
 use crate::matcher::{Bindings, BindingType, MatchCtxt, Subst, mut_visit_match_with};
 use crate::transform::Transform;
+use crate::transform::enums::{is_simple_place, lit_value};
+use crate::transform::structs::is_zeroed;
 use crate::RefactorCtxt;
 
 /// # `ionize` Command
@@ -191,8 +196,800 @@ impl Transform for Ionize {
     }
 }
 
+/// # `tagged_union_to_enum` Command
+///
+/// Usage: `tagged_union_to_enum TAG variant=VALUE:FIELD...`
+///
+/// Marks: `target`
+///
+/// For each struct marked `target` that has a field named `TAG` and a union field, replace
+/// the union with an enum of the same name, with one variant for each union field, and
+/// remove `TAG` from the struct.  Each `variant=VALUE:FIELD` argument says that union field
+/// `FIELD` is the active one when `TAG` is `VALUE`, and every union field needs one.
+///
+/// Accesses through the struct are rewritten as follows:
+///
+///  * a test `s.TAG == VALUE` becomes `matches!(s.u, U::FIELD(_))`;
+///  * in `if s.TAG == VALUE { .. }`, reads of `s.u.FIELD` become reads of the binding of
+///    `if let U::FIELD(FIELD) = s.u { .. }`, and a `match s.TAG` becomes a `match s.u`
+///    whose arms bind the fields the same way, unless the branch also writes to the union;
+///  * a write `s.u.FIELD = e` becomes `s.u = U::FIELD(e)`, and a write `s.TAG = VALUE` is
+///    removed when the same block writes to the matching union field;
+///  * a struct literal setting `TAG` to `VALUE` and the union to a `FIELD` value becomes
+///    one setting the enum.
+///
+/// Any other access to the tag or the union fields, such as a read that is not guarded by a
+/// tag test, is left in place with a warning, to be fixed by hand.  A struct is skipped with
+/// a warning if its union is used anywhere else.
+pub struct TaggedUnionToEnum {
+    pub tag: String,
+    pub variants: Vec<(i128, String)>,
+}
+
+/// A marked struct whose tag and union fields are being merged into an enum.
+struct TaggedStruct {
+    tag: Ident,
+    /// The struct's union field.
+    field: Ident,
+    /// The union, which the enum replaces.
+    union: DefId,
+    /// The name of the union, which the enum takes over.
+    name: Ident,
+    /// The union field for each tag value.
+    members: HashMap<i128, Ident>,
+}
+
+struct TaggedUnions<'a, 'tcx: 'a> {
+    cx: &'a RefactorCtxt<'a, 'tcx>,
+    structs: HashMap<DefId, TaggedStruct>,
+    /// The values of the constants initialized with integer literals, for tag values given by
+    /// name.
+    consts: HashMap<DefId, i128>,
+}
+
+impl<'a, 'tcx> TaggedUnions<'a, 'tcx> {
+    /// Get the marked struct that is the type of `e`, if any.
+    fn struct_of(&self, e: &Expr) -> Option<&TaggedStruct> {
+        if e.id == DUMMY_NODE_ID {
+            return None;
+        }
+        let mut ty = self.cx.opt_node_type(e.id)?;
+        while let TyKind::Ref(_, inner, _) = ty.kind {
+            ty = inner;
+        }
+        match ty.kind {
+            TyKind::Adt(def, _) => self.structs.get(&def.did),
+            _ => None,
+        }
+    }
+
+    /// If `e` is `s.TAG`, return `s` and its struct.
+    fn tag_access<'e>(&self, e: &'e Expr) -> Option<(&'e P<Expr>, &TaggedStruct)> {
+        match e.kind {
+            ExprKind::Field(ref base, ident) => self.struct_of(base)
+                .filter(|ts| ts.tag.name == ident.name)
+                .map(|ts| (base, ts)),
+            _ => None,
+        }
+    }
+
+    /// If `e` is `s.u.FIELD`, return `s`, its struct and `FIELD`.
+    fn member_access<'e>(&self, e: &'e Expr) -> Option<(&'e P<Expr>, &TaggedStruct, Ident)> {
+        let (inner, member) = match e.kind {
+            ExprKind::Field(ref inner, member) => (inner, member),
+            _ => return None,
+        };
+        match inner.kind {
+            ExprKind::Field(ref base, field) => self.struct_of(base)
+                .filter(|ts| ts.field.name == field.name)
+                .map(|ts| (base, ts, member)),
+            _ => None,
+        }
+    }
+
+    /// Check if `e` is a read or write of `base.u.member`.
+    fn is_member(&self, e: &Expr, base: &P<Expr>, member: Ident) -> bool {
+        self.member_access(e)
+            .map_or(false, |(b, _, m)| b.ast_equiv(base) && m.name == member.name)
+    }
+
+    fn tag_value(&self, e: &Expr) -> Option<i128> {
+        lit_value(e).or_else(|| {
+            self.cx.try_resolve_expr(e).and_then(|def_id| self.consts.get(&def_id).cloned())
+        })
+    }
+
+    /// Get the union fields for the tag values matched by `p`.
+    fn pat_members(&self, ts: &TaggedStruct, p: &Pat) -> Option<Vec<Ident>> {
+        let value = match p.kind {
+            PatKind::Or(ref pats) => {
+                let mut members = Vec::new();
+                for p in pats {
+                    members.extend(self.pat_members(ts, p)?);
+                }
+                return Some(members);
+            }
+            PatKind::Lit(ref e) => self.tag_value(e),
+            PatKind::Path(..) => self.cx.try_resolve_pat_hir(p)
+                .and_then(|res| res.opt_def_id())
+                .and_then(|def_id| self.consts.get(&def_id).cloned()),
+            _ => None,
+        };
+        value.and_then(|v| ts.members.get(&v)).map(|&m| vec![m])
+    }
+
+    /// If `e` is `s.TAG == VALUE` or `s.TAG != VALUE`, return `s`, its struct, the union field
+    /// for `VALUE`, and whether the test is negated.
+    fn tag_test<'e>(&self, e: &'e Expr) -> Option<(&'e P<Expr>, &TaggedStruct, Ident, bool)> {
+        let (op, l, r) = match e.kind {
+            ExprKind::Binary(op, ref l, ref r) => (op.node, l, r),
+            _ => return None,
+        };
+        let negated = match op {
+            BinOpKind::Eq => false,
+            BinOpKind::Ne => true,
+            _ => return None,
+        };
+        let ((base, ts), k) = match self.tag_access(l) {
+            Some(access) => (access, r),
+            None => (self.tag_access(r)?, l),
+        };
+        let member = *ts.members.get(&self.tag_value(k)?)?;
+        Some((base, ts, member, negated))
+    }
+
+    /// Check if `x` reads `base.u.member` without writing to any field of `base.u`.
+    fn only_reads<T: Visit>(&self, x: &T, base: &P<Expr>, member: Ident) -> bool {
+        let mut reads = false;
+        let mut writes = false;
+        visit_nodes(x, |e: &Expr| {
+            match e.kind {
+                ExprKind::Assign(ref lhs, _) |
+                ExprKind::AssignOp(_, ref lhs, _) |
+                ExprKind::AddrOf(_, Mutability::Mutable, ref lhs) => {
+                    writes |= self.member_access(lhs).map_or(false, |(b, _, _)| b.ast_equiv(base));
+                }
+                _ => {}
+            }
+            reads |= self.is_member(e, base, member);
+        });
+        reads && !writes
+    }
+
+    /// Replace the reads of `base.u.member` in `x` with reads of a binding named `member`.
+    fn bind_reads<T: MutVisit>(&self, x: &mut T, base: &P<Expr>, member: Ident) {
+        MutVisitNodes::visit(x, |e: &mut P<Expr>| {
+            if self.is_member(e, base, member) {
+                *e = mk().ident_expr(member);
+            }
+        });
+    }
+
+    /// Rewrite the accesses to the tags and unions of the marked structs.
+    fn convert_uses(&self, krate: &mut Crate, st: &CommandState) {
+        let cx = self.cx;
+
+        // Remove writes to a tag that go with a write to the matching union field.
+        MutVisitNodes::visit(krate, |b: &mut P<Block>| {
+            let is_write = |s: &Stmt, base: &P<Expr>, member: Ident| match s.kind {
+                StmtKind::Semi(ref e) | StmtKind::Expr(ref e) => match e.kind {
+                    ExprKind::Assign(ref lhs, _) => self.is_member(lhs, base, member),
+                    _ => false,
+                },
+                _ => false,
+            };
+            let redundant = b.stmts.iter().map(|s| {
+                let (lhs, rhs) = match s.kind {
+                    StmtKind::Semi(ref e) | StmtKind::Expr(ref e) => match e.kind {
+                        ExprKind::Assign(ref lhs, ref rhs) => (lhs, rhs),
+                        _ => return false,
+                    },
+                    _ => return false,
+                };
+                let (base, ts) = match self.tag_access(lhs) {
+                    Some(access) => access,
+                    None => return false,
+                };
+                match self.tag_value(rhs).and_then(|v| ts.members.get(&v)) {
+                    Some(&member) => b.stmts.iter().any(|s| is_write(s, base, member)),
+                    None => false,
+                }
+            }).collect::<Vec<_>>();
+            let mut redundant = redundant.into_iter();
+            b.stmts.retain(|_| !redundant.next().unwrap());
+        });
+
+        let mut mcx = MatchCtxt::new(st, cx);
+        let assign_repl = mcx.parse_expr("$lhs = $rhs");
+
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            let new_e = match e.kind {
+                ExprKind::If(ref cond, ref then, ref els) => {
+                    let (base, ts, member) = match self.tag_test(cond) {
+                        Some((base, ts, member, false)) if is_simple_place(base) =>
+                            (base, ts, member),
+                        _ => return,
+                    };
+                    if !self.only_reads(&**then, base, member) {
+                        return;
+                    }
+                    let mut then = then.clone();
+                    self.bind_reads(&mut then, base, member);
+                    let src = format!("if let {}::{}({}) = x {{}}", ts.name, member, member);
+                    let mut new_e = parse_expr(cx.session(), &src);
+                    if let ExprKind::If(ref mut cond, ref mut new_then, ref mut new_els) =
+                            new_e.kind {
+                        if let ExprKind::Let(_, ref mut scrutinee) = cond.kind {
+                            *scrutinee = mk().field_expr(base.clone(), ts.field);
+                        }
+                        *new_then = then;
+                        *new_els = els.clone();
+                    }
+                    new_e
+                }
+
+                ExprKind::Binary(..) => match self.tag_test(e) {
+                    Some((base, ts, member, negated)) => {
+                        let src = format!("{}matches!({}.{}, {}::{}(_))",
+                                          if negated { "!" } else { "" },
+                                          pprust::expr_to_string(base), ts.field,
+                                          ts.name, member);
+                        parse_expr(cx.session(), &src)
+                    }
+                    None => return,
+                },
+
+                ExprKind::Match(ref scrutinee, ref arms) => {
+                    let (base, ts) = match self.tag_access(scrutinee) {
+                        Some(access) => access,
+                        None => return,
+                    };
+                    let mut new_arms = arms.clone();
+                    for arm in &mut new_arms {
+                        if let PatKind::Wild = arm.pat.kind {
+                            continue;
+                        }
+                        // Leave the `match` alone, so its scrutinee is reported below.
+                        let members = match self.pat_members(ts, &arm.pat) {
+                            Some(members) => members,
+                            None => return,
+                        };
+                        let bound = match members[..] {
+                            [member] if arm.guard.is_none() &&
+                                        self.only_reads(&*arm.body, base, member) =>
+                                Some(member),
+                            _ => None,
+                        };
+                        let src = match bound {
+                            Some(member) => {
+                                self.bind_reads(&mut arm.body, base, member);
+                                format!("{}::{}({})", ts.name, member, member)
+                            }
+                            None => members.iter()
+                                .map(|member| format!("{}::{}(_)", ts.name, member))
+                                .collect::<Vec<_>>()
+                                .join(" | "),
+                        };
+                        arm.pat = parse_pat(cx.session(), &src);
+                    }
+                    let mut new_e = e.clone();
+                    if let ExprKind::Match(ref mut scrutinee, ref mut arms) = new_e.kind {
+                        *scrutinee = mk().field_expr(base.clone(), ts.field);
+                        *arms = new_arms;
+                    }
+                    new_e
+                }
+
+                ExprKind::Assign(ref lhs, ref rhs) => match self.member_access(lhs) {
+                    Some((base, ts, member)) => {
+                        let mut bnd = Bindings::new();
+                        bnd.add("$lhs", mk().field_expr(base.clone(), ts.field));
+                        bnd.add("$rhs", mk().call_expr(mk().path_expr(vec![ts.name, member]),
+                                                       vec![rhs.clone()]));
+                        assign_repl.clone().subst(st, cx, &bnd)
+                    }
+                    None => return,
+                },
+
+                ExprKind::Struct(_, ref fields, _) => {
+                    let ts = match self.struct_of(e) {
+                        Some(ts) => ts,
+                        None => return,
+                    };
+                    let member = fields.iter()
+                        .find(|f| f.ident.name == ts.tag.name)
+                        .and_then(|f| self.tag_value(&f.expr))
+                        .and_then(|v| ts.members.get(&v));
+                    let init = fields.iter()
+                        .find(|f| f.ident.name == ts.field.name)
+                        .and_then(|f| match f.expr.kind {
+                            ExprKind::Struct(_, ref fields, None) if fields.len() == 1 =>
+                                Some(&fields[0]),
+                            _ => None,
+                        });
+                    let init = match (member, init) {
+                        (Some(member), Some(init)) if init.ident.name == member.name => init,
+                        _ => {
+                            warn!("tagged_union_to_enum: can't convert `{}` at {}; \
+                                   fix it manually",
+                                  pprust::expr_to_string(e),
+                                  cx.session().source_map().span_to_string(e.span));
+                            return;
+                        }
+                    };
+                    let mut new_e = e.clone();
+                    if let ExprKind::Struct(_, ref mut fields, _) = new_e.kind {
+                        fields.retain(|f| f.ident.name != ts.tag.name);
+                        for f in fields.iter_mut().filter(|f| f.ident.name == ts.field.name) {
+                            f.expr = mk().call_expr(mk().path_expr(vec![ts.name, init.ident]),
+                                                    vec![init.expr.clone()]);
+                        }
+                    }
+                    new_e
+                }
+
+                _ => {
+                    if self.tag_access(e).is_some() || self.member_access(e).is_some() {
+                        warn!("tagged_union_to_enum: unchecked access `{}` at {}; \
+                               fix it manually",
+                              pprust::expr_to_string(e),
+                              cx.session().source_map().span_to_string(e.span));
+                    }
+                    return;
+                }
+            };
+            *e = new_e;
+        });
+    }
+}
+
+impl Transform for TaggedUnionToEnum {
+    fn min_phase(&self) -> Phase { Phase::Phase3 }
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+
+        // Find the unions, the constants with literal values, and the uses of union types
+        let mut unions = HashMap::new();
+        let mut consts = HashMap::new();
+        visit_nodes(krate, |i: &Item| {
+            match i.kind {
+                ItemKind::Union(VariantData::Struct(ref fields, _), _) => {
+                    unions.insert(cx.node_def_id(i.id), (i.ident, fields.clone()));
+                }
+                ItemKind::Const(_, ref init) => {
+                    if let Some(value) = lit_value(init) {
+                        consts.insert(cx.node_def_id(i.id), value);
+                    }
+                }
+                _ => {}
+            }
+        });
+        let mut union_uses = HashMap::new();
+        visit_nodes(krate, |t: &Ty| {
+            if let Some(def_id) = cx.try_resolve_ty(t) {
+                if unions.contains_key(&def_id) {
+                    *union_uses.entry(def_id).or_insert(0) += 1;
+                }
+            }
+        });
+
+        // Find the marked structs, their tag fields and their union fields
+        let mut structs = HashMap::new();
+        visit_nodes(krate, |i: &Item| {
+            if !st.marked(i.id, "target") {
+                return;
+            }
+            let fields = match i.kind {
+                ItemKind::Struct(VariantData::Struct(ref fields, _), _) => fields,
+                _ => return,
+            };
+            if !fields.iter().any(|f| f.ident.map_or(false, |ident| *ident.as_str() == *self.tag)) {
+                warn!("tagged_union_to_enum: `{}` has no field `{}`; skipping", i.ident, self.tag);
+                return;
+            }
+            let union_fields = fields.iter()
+                .filter_map(|f| cx.try_resolve_ty(&f.ty).map(|def_id| (f, def_id)))
+                .filter(|&(_, def_id)| unions.contains_key(&def_id))
+                .collect::<Vec<_>>();
+            let (field, union) = match union_fields[..] {
+                [(field, union)] => (field.ident.unwrap(), union),
+                _ => {
+                    warn!("tagged_union_to_enum: `{}` has no single union field; skipping",
+                          i.ident);
+                    return;
+                }
+            };
+            let (name, ref union_fields) = unions[&union];
+            if union_uses[&union] > 1 {
+                warn!("tagged_union_to_enum: `{}` is used outside `{}`; skipping",
+                      name, i.ident);
+                return;
+            }
+
+            let mut members = HashMap::new();
+            for &(value, ref member) in &self.variants {
+                let found = union_fields.iter()
+                    .filter_map(|f| f.ident)
+                    .find(|m| *m.as_str() == **member);
+                match found {
+                    Some(member) => {
+                        members.insert(value, member);
+                    }
+                    None => {
+                        warn!("tagged_union_to_enum: `{}` has no field `{}`; skipping",
+                              name, member);
+                        return;
+                    }
+                }
+            }
+            let missing = union_fields.iter()
+                .filter_map(|f| f.ident)
+                .find(|ident| !members.values().any(|m| m.name == ident.name));
+            if let Some(missing) = missing {
+                warn!("tagged_union_to_enum: no tag value given for `{}::{}`; skipping",
+                      name, missing);
+                return;
+            }
+
+            structs.insert(cx.node_def_id(i.id), TaggedStruct {
+                tag: Ident::from_str(&self.tag),
+                field,
+                union,
+                name,
+                members,
+            });
+        });
+        if structs.is_empty() {
+            return;
+        }
+
+        // Rewrite the accesses through the structs
+        let tu = TaggedUnions { cx, structs, consts };
+        tu.convert_uses(krate, st);
+
+        // Remove the tag fields, and replace the unions with enums
+        FlatMapNodes::visit(krate, |i: P<Item>| {
+            let def_id = cx.node_def_id(i.id);
+            if let Some(ts) = tu.structs.get(&def_id) {
+                return smallvec![i.map(|mut i| {
+                    if let ItemKind::Struct(VariantData::Struct(ref mut fields, _), _) = i.kind {
+                        fields.retain(|f| f.ident.map_or(true, |ident| ident.name != ts.tag.name));
+                    }
+                    i
+                })];
+            }
+            if !tu.structs.values().any(|ts| ts.union == def_id) {
+                return smallvec![i];
+            }
+
+            let fields = match i.kind {
+                ItemKind::Union(VariantData::Struct(ref fields, _), _) => fields,
+                _ => return smallvec![i],
+            };
+            let mut src = String::new();
+            for attr in i.attrs.iter().filter(|attr| attr.check_name(sym::derive)) {
+                src.push_str(&pprust::attribute_to_string(attr));
+                src.push('\n');
+            }
+            src.push_str(&format!("{}enum {} {{\n", pprust::vis_to_string(&i.vis), i.ident));
+            for f in fields {
+                src.push_str(&format!("    {}({}),\n",
+                                      f.ident.unwrap(), pprust::ty_to_string(&f.ty)));
+            }
+            src.push_str("}\n");
+            parse_items(cx.session(), &src).into()
+        });
+    }
+}
+
+/// # `punning_to_bits` Command
+///
+/// Usage: `punning_to_bits`
+///
+/// Marks: `target`
+///
+/// For each union marked `target` with exactly two members of the same size,
+/// replace each local of the union's type with a local of one member's type,
+/// and convert the accesses to the other member from it:
+///
+///  * for `{ f: f32, i: u32 }` or `{ f: f64, i: u64 }`, the local holds the
+///    float: `u.f` becomes `u`, a read of `u.i` becomes `u.to_bits()`, and
+///    `u.i = e` becomes `u = f32::from_bits(e)`;
+///  * for other pairs of integers, floats and `u8` arrays, the local holds the
+///    float member, or else the first one, and the other member is converted
+///    through its bytes instead of with a `transmute`, as in
+///    `i32::from_ne_bytes(u.to_ne_bytes())`.
+///
+/// The local's initializer may be a union literal, which is converted the same
+/// way, or `mem::zeroed()`, which becomes zero.  A local is skipped with a
+/// warning if it's used other than through its members, as by taking its
+/// address or passing it to a function, or if part of the member it doesn't
+/// hold is modified or borrowed, as in `&mut u.i` or `u.bytes[0] = 1`.  Unions
+/// with more than two members, or with members of other types or of different
+/// sizes, are skipped with a warning.
+pub struct PunningToBits;
+
+/// A member of a union converted by `punning_to_bits`.
+struct PunMember {
+    name: Symbol,
+    ty: P<Ty>,
+    /// The name of the type, as in `f32::from_bits`, or `None` for a `u8` array.
+    prim: Option<String>,
+    is_float: bool,
+    size: u64,
+}
+
+impl PunMember {
+    fn new(cx: &RefactorCtxt, f: &StructField) -> Option<PunMember> {
+        let tcx = cx.ty_ctxt();
+        let ty = tcx.type_of(cx.node_def_id(f.id));
+        let (prim, is_float, bits) = match ty.kind {
+            TyKind::Float(ft) => (Some(ty.to_string()), true, ft.bit_width()),
+            TyKind::Int(it) => (Some(ty.to_string()), false, it.bit_width()?),
+            TyKind::Uint(ut) => (Some(ty.to_string()), false, ut.bit_width()?),
+            TyKind::Array(elem, len) => match elem.kind {
+                TyKind::Uint(UintTy::U8) =>
+                    (None, false, len.try_eval_usize(tcx, ParamEnv::empty())? as usize * 8),
+                _ => return None,
+            },
+            _ => return None,
+        };
+        Some(PunMember {
+            name: f.ident?.name,
+            ty: f.ty.clone(),
+            prim,
+            is_float,
+            size: bits as u64 / 8,
+        })
+    }
+
+    /// The source of a zero value of this member's type.
+    fn zero_src(&self) -> String {
+        match self.prim {
+            Some(_) if self.is_float => "0.0".to_owned(),
+            Some(_) => "0".to_owned(),
+            None => format!("[0; {}]", self.size),
+        }
+    }
+
+    /// The source of the conversion of `$e` from this member's type to `to`'s.
+    fn conv_src(&self, to: &PunMember) -> String {
+        let is_unsigned = |m: &PunMember| m.prim.as_ref().map_or(false, |p| p.starts_with('u'));
+        match (&self.prim, &to.prim) {
+            _ if self.is_float && is_unsigned(to) => "$e.to_bits()".to_owned(),
+            (_, &Some(ref to_prim)) if to.is_float && is_unsigned(self) =>
+                format!("{}::from_bits($e)", to_prim),
+            (&Some(_), &Some(ref to_prim)) =>
+                format!("{}::from_ne_bytes($e.to_ne_bytes())", to_prim),
+            (&Some(_), &None) => "$e.to_ne_bytes()".to_owned(),
+            (&None, &Some(ref to_prim)) => format!("{}::from_ne_bytes($e)", to_prim),
+            (&None, &None) => "$e".to_owned(),
+        }
+    }
+}
+
+/// A union converted by `punning_to_bits`, with the member its locals hold and the other one.
+struct PunUnion {
+    stored: PunMember,
+    other: PunMember,
+    to_other: P<Expr>,
+    from_other: P<Expr>,
+}
+
+/// If `e` is `u.member` for one of `locals`, return the local and the member.
+fn pun_member_access<T>(cx: &RefactorCtxt, locals: &HashMap<HirId, T>, e: &Expr)
+                        -> Option<(HirId, Symbol)> {
+    let (base, member) = match_or!([e.kind] ExprKind::Field(ref b, m) => (b, m); return None);
+    match base.kind {
+        ExprKind::Path(None, _) => cx.try_resolve_expr_to_hid(base)
+            .filter(|hir_id| locals.contains_key(hir_id))
+            .map(|hir_id| (hir_id, member.name)),
+        _ => None,
+    }
+}
+
+impl Transform for PunningToBits {
+    fn min_phase(&self) -> Phase { Phase::Phase3 }
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        let mut mcx = MatchCtxt::new(st, cx);
+
+        // (1) Find the marked unions whose members can be converted into each other.
+
+        let mut unions = HashMap::new();
+        visit_nodes(krate, |i: &Item| {
+            if !st.marked(i.id, "target") {
+                return;
+            }
+            let fields = match_or!([i.kind] ItemKind::Union(VariantData::Struct(ref x, _), _) => x;
+                                   return);
+            if fields.len() != 2 {
+                warn!("punning_to_bits: `{}` doesn't have exactly two members; skipping", i.ident);
+                return;
+            }
+            let members: Option<Vec<_>> = fields.iter().map(|f| PunMember::new(cx, f)).collect();
+            let mut members = match members {
+                Some(x) => x,
+                None => {
+                    warn!("punning_to_bits: the members of `{}` aren't all integers, floats or \
+                           byte arrays; skipping", i.ident);
+                    return;
+                }
+            };
+            if members[0].size != members[1].size {
+                warn!("punning_to_bits: the members of `{}` have different sizes; skipping",
+                      i.ident);
+                return;
+            }
+            if members[1].is_float && !members[0].is_float {
+                members.swap(0, 1);
+            }
+            let other = members.pop().unwrap();
+            let stored = members.pop().unwrap();
+            let to_other = mcx.parse_expr(&stored.conv_src(&other));
+            let from_other = mcx.parse_expr(&other.conv_src(&stored));
+            unions.insert(cx.node_def_id(i.id), PunUnion { stored, other, to_other, from_other });
+        });
+        if unions.is_empty() {
+            return;
+        }
+
+        // (2) Find the locals of those unions, and check that they're only used through their
+        // members.
+
+        let mut locals: HashMap<HirId, (Ident, DefId)> = HashMap::new();
+        visit_nodes(krate, |l: &Local| {
+            let ident = match_or!([l.pat.kind]
+                                  PatKind::Ident(BindingMode::ByValue(_), x, None) => x; return);
+            let did = match cx.opt_node_type(l.pat.id).map(|ty| &ty.kind) {
+                Some(&TyKind::Adt(def, _)) if unions.contains_key(&def.did) => def.did,
+                _ => return,
+            };
+            let init_ok = match l.init {
+                None => true,
+                Some(ref init) => match init.kind {
+                    ExprKind::Struct(_, ref fields, None) => fields.len() == 1,
+                    _ => is_zeroed(init),
+                },
+            };
+            if !init_ok {
+                warn!("punning_to_bits: can't convert the initializer of `{}`; skipping", ident);
+                return;
+            }
+            locals.insert(cx.hir_map().node_to_hir_id(l.pat.id), (ident, did));
+        });
+
+        // The local whose other member the place `e` is a part of, like the `u.b` in `u.b[0]`.
+        let other_member_place = |e: &Expr| -> Option<HirId> {
+            let mut e = e;
+            loop {
+                if let Some((hir_id, member)) = pun_member_access(cx, &locals, e) {
+                    let pu = &unions[&locals[&hir_id].1];
+                    return if member == pu.other.name { Some(hir_id) } else { None };
+                }
+                e = match e.kind {
+                    ExprKind::Field(ref base, _) | ExprKind::Index(ref base, _) |
+                    ExprKind::Paren(ref base) => base,
+                    _ => return None,
+                };
+            }
+        };
+
+        let mut uses = HashMap::new();
+        let mut handled = HashMap::new();
+        let mut escaped = HashSet::new();
+        let mut writes = HashSet::new();
+        visit_nodes(krate, |e: &Expr| {
+            if let ExprKind::Path(None, _) = e.kind {
+                if let Some(hir_id) = cx.try_resolve_expr_to_hid(e) {
+                    *uses.entry(hir_id).or_insert(0) += 1;
+                }
+            }
+            if let Some((hir_id, _)) = pun_member_access(cx, &locals, e) {
+                *handled.entry(hir_id).or_insert(0) += 1;
+            }
+            match e.kind {
+                ExprKind::Assign(ref lhs, _) => {
+                    if pun_member_access(cx, &locals, lhs).is_some() {
+                        writes.insert(lhs.id);
+                    } else if let Some(hir_id) = other_member_place(lhs) {
+                        escaped.insert(hir_id);
+                    }
+                }
+                ExprKind::AssignOp(_, ref place, _) | ExprKind::AddrOf(_, _, ref place) => {
+                    if let Some(hir_id) = other_member_place(place) {
+                        escaped.insert(hir_id);
+                    }
+                }
+                // The methods of a byte array may modify the temporary copy of it instead.
+                ExprKind::MethodCall(_, ref args) => {
+                    if let Some(hir_id) = other_member_place(&args[0]) {
+                        if unions[&locals[&hir_id].1].other.prim.is_none() {
+                            escaped.insert(hir_id);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        });
+        let converted = locals.iter()
+            .filter(|&(hir_id, &(ident, _))| {
+                if escaped.contains(hir_id) || uses.get(hir_id) != handled.get(hir_id) {
+                    warn!("punning_to_bits: `{}` is used other than through its members; \
+                           skipping", ident);
+                    return false;
+                }
+                true
+            })
+            .map(|(&hir_id, &(_, did))| (hir_id, did))
+            .collect::<HashMap<_, _>>();
+        if converted.is_empty() {
+            return;
+        }
+
+        // (3) Retype the locals, and rewrite their member accesses.
+
+        let conv = |tmpl: &P<Expr>, e: P<Expr>| {
+            let mut bnd = Bindings::new();
+            bnd.add("$e", e);
+            tmpl.clone().subst(st, cx, &bnd)
+        };
+
+        MutVisitNodes::visit(krate, |l: &mut P<Local>| {
+            let pu = match converted.get(&cx.hir_map().node_to_hir_id(l.pat.id)) {
+                Some(did) => &unions[did],
+                None => return,
+            };
+            l.ty = Some(pu.stored.ty.clone());
+            l.init = l.init.take().map(|init| match init.kind {
+                ExprKind::Struct(_, ref fields, _) => {
+                    let value = fields[0].expr.clone();
+                    if fields[0].ident.name == pu.stored.name {
+                        value
+                    } else {
+                        conv(&pu.from_other, value)
+                    }
+                }
+                _ => parse_expr(cx.session(), &pu.stored.zero_src()),
+            });
+        });
+
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            // A write to the other member is rewritten along with the assignment.
+            if let Some((hir_id, member)) = pun_member_access(cx, &converted, e) {
+                let base = match_or!([e.kind] ExprKind::Field(ref b, _) => b.clone(); return);
+                let pu = &unions[&converted[&hir_id]];
+                if member == pu.stored.name {
+                    *e = base;
+                } else if !writes.contains(&e.id) {
+                    *e = conv(&pu.to_other, base);
+                }
+                return;
+            }
+            if let ExprKind::Assign(ref mut lhs, ref mut rhs) = e.kind {
+                let (hir_id, member) = match_or!([pun_member_access(cx, &converted, lhs)]
+                                                 Some(x) => x; return);
+                let pu = &unions[&converted[&hir_id]];
+                if member == pu.other.name {
+                    *lhs = match_or!([lhs.kind] ExprKind::Field(ref b, _) => b.clone(); return);
+                    *rhs = conv(&pu.from_other, rhs.clone());
+                }
+            }
+        });
+    }
+}
+
 pub fn register_commands(reg: &mut Registry) {
     use super::mk;
 
-    reg.register("ionize", |_args| mk(Ionize{}))
+    reg.register("ionize", |_args| mk(Ionize{}));
+    reg.register("punning_to_bits", |_args| mk(PunningToBits));
+    reg.register("tagged_union_to_enum", |args| mk(TaggedUnionToEnum {
+        tag: args[0].clone(),
+        variants: args[1..].iter().map(|arg| {
+            let spec = arg.trim_start_matches("variant=");
+            let mut parts = spec.splitn(2, ':');
+            let value = parts.next().unwrap().parse().expect("bad tag value");
+            let field = parts.next().expect("expected variant=VALUE:FIELD");
+            (value, field.to_owned())
+        }).collect(),
+    }));
 }
@@ -1,17 +1,24 @@
 use std::borrow::Cow;
 use std::collections::{HashMap, HashSet};
+use std::mem;
 use regex::Regex;
 use rustc::hir::HirId;
+use rustc::hir::def::Res;
+use rustc::hir::def_id::DefId;
 use rustc_parse::parser::FollowedByType;
 use syntax::ast::*;
+use syntax::attr;
 use syntax::source_map::DUMMY_SP;
 use syntax::mut_visit::{self, MutVisitor};
 use syntax::ptr::P;
-use syntax::symbol::Symbol;
+use syntax::symbol::{sym, Symbol};
+use syntax::token::{Token, TokenKind};
+use syntax::tokenstream::{TokenStream, TokenTree};
+use syntax::visit::{self, Visitor};
 use smallvec::{smallvec, SmallVec};
 
 use c2rust_ast_builder::{mk, Make, IntoSymbol};
-use crate::ast_manip::{FlatMapNodes, MutVisit, AstEquiv};
+use crate::ast_manip::{FlatMapNodes, MutVisit, AstEquiv, visit_nodes};
 use crate::command::{CommandState, Registry};
 use crate::driver::{self, Phase};
 use crate::path_edit::fold_resolved_paths;
@@ -21,57 +28,229 @@ use crate::RefactorCtxt;
 
 /// # `rename_items_regex` Command
 ///
-/// Usage: `rename_items_regex PAT REPL [FILTER]`
+/// Usage: `rename_items_regex PAT REPL [FILTER]`, or
+/// `rename_items_regex [kind=KINDS] pattern=PAT replace=REPL [no_mangle=skip|export_name]`
 ///
 /// Marks: reads `FILTER`
 ///
 /// Replace `PAT` (a regular expression) with `REPL` in all item names.  If `FILTER` is provided,
-/// only items bearing the `FILTER` mark will be renamed.
+/// only items bearing the `FILTER` mark will be renamed.  `KINDS` is a comma-separated list of
+/// `fn`, `struct`, `static`, `const` and `ty`; if it is provided, only items of those kinds will
+/// be renamed.  All references to the renamed items, including `use` items, are updated using the
+/// names they resolve to.
+///
+/// A rename that would give an item the same name as another item in the same module or block,
+/// or that would turn its name into a keyword, is not performed, and a warning is printed.
+/// Items marked `#[no_mangle]` are skipped by default.  With `no_mangle=export_name`, they are
+/// renamed, and their `#[no_mangle]` attribute is replaced with `#[export_name = "OLD_NAME"]`
+/// to keep their symbol.
 pub struct RenameRegex {
     pattern: String,
     repl: String,
     filter: Option<Symbol>,
+    kinds: Option<Vec<String>>,
+    export_name: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+enum Namespace {
+    Type,
+    Value,
+}
+
+/// The name an item defines, for detecting collisions between renamed items.
+struct ItemName {
+    id: NodeId,
+    /// The module, block-containing item, or crate that the name is defined in.
+    scope: NodeId,
+    namespaces: &'static [Namespace],
+    name: Symbol,
+    new_name: Option<Symbol>,
+}
+
+fn item_namespaces(kind: &ItemKind) -> &'static [Namespace] {
+    match *kind {
+        ItemKind::Fn(..) | ItemKind::Static(..) | ItemKind::Const(..) => &[Namespace::Value],
+        ItemKind::Struct(VariantData::Struct(..), _) |
+        ItemKind::Union(..) | ItemKind::Enum(..) | ItemKind::TyAlias(..) |
+        ItemKind::Trait(..) | ItemKind::TraitAlias(..) |
+        ItemKind::Mod(..) | ItemKind::ExternCrate(..) => &[Namespace::Type],
+        ItemKind::Struct(..) => &[Namespace::Type, Namespace::Value],
+        _ => &[],
+    }
+}
+
+/// Collects the names defined by all items, along with the scopes defining them.
+struct ItemNameCollector {
+    scopes: Vec<NodeId>,
+    names: Vec<ItemName>,
+}
+
+impl ItemNameCollector {
+    fn add(&mut self, id: NodeId, namespaces: &'static [Namespace], name: Symbol) {
+        if namespaces.is_empty() {
+            return;
+        }
+        self.names.push(ItemName {
+            id,
+            scope: *self.scopes.last().unwrap(),
+            namespaces,
+            name,
+            new_name: None,
+        });
+    }
+}
+
+impl<'ast> Visitor<'ast> for ItemNameCollector {
+    fn visit_item(&mut self, i: &'ast Item) {
+        self.add(i.id, item_namespaces(&i.kind), i.ident.name);
+        // The items of an `extern` block are defined in the enclosing scope.
+        if let ItemKind::ForeignMod(..) = i.kind {
+            visit::walk_item(self, i);
+            return;
+        }
+        self.scopes.push(i.id);
+        visit::walk_item(self, i);
+        self.scopes.pop();
+    }
+
+    fn visit_foreign_item(&mut self, i: &'ast ForeignItem) {
+        match i.kind {
+            ForeignItemKind::Fn(..) | ForeignItemKind::Static(..) =>
+                self.add(i.id, &[Namespace::Value], i.ident.name),
+            ForeignItemKind::Ty => self.add(i.id, &[Namespace::Type], i.ident.name),
+            ForeignItemKind::Macro(..) => {}
+        }
+        visit::walk_foreign_item(self, i);
+    }
+
+    fn visit_mac(&mut self, mac: &'ast Mac) {
+        visit::walk_mac(self, mac);
+    }
+}
+
+impl RenameRegex {
+    fn kind_matches(&self, kind: &ItemKind) -> bool {
+        let kinds = match self.kinds {
+            Some(ref kinds) => kinds,
+            None => return true,
+        };
+        let name = match *kind {
+            ItemKind::Fn(..) => "fn",
+            ItemKind::Struct(..) => "struct",
+            ItemKind::Static(..) => "static",
+            ItemKind::Const(..) => "const",
+            ItemKind::TyAlias(..) => "ty",
+            _ => return false,
+        };
+        kinds.iter().any(|k| k == name)
+    }
 }
 
 impl Transform for RenameRegex {
     fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
         let re = Regex::new(&self.pattern).unwrap();
 
-        // (1) Fold over items and rewrite their `ident`s.  Records the new paths of modified items
-        // into `new_paths`.
+        // (1) Compute the new name of each item to rename.
 
-        let mut new_idents = HashMap::new();
-        FlatMapNodes::visit(krate, |i: P<Item>| {
+        let mut new_names = HashMap::new();
+        visit_nodes(krate, |i: &Item| {
             if let Some(label) = self.filter {
                 if !st.marked(i.id, label) {
-                    return smallvec![i];
+                    return;
                 }
             }
+            if !self.kind_matches(&i.kind) {
+                return;
+            }
+            if !self.export_name && attr::contains_name(&i.attrs, sym::no_mangle) {
+                return;
+            }
 
             let name = i.ident.name.as_str();
-            let new_name = re.replace(&name, &self.repl as &str);
-            if let Cow::Owned(new_name) = new_name {
-                new_idents.insert(cx.hir_map().node_to_hir_id(i.id), mk().ident(&new_name));
-
-                smallvec![i.map(|i| {
-                    Item {
-                        ident: mk().ident(&new_name),
-                        .. i
-                    }
-                })]
-            } else {
-                smallvec![i]
+            if let Cow::Owned(new_name) = re.replace(&name, &self.repl as &str) {
+                if Ident::from_str(&new_name).is_reserved() {
+                    warn!("rename_items_regex: not renaming `{}` to `{}`, which is a keyword",
+                          name, new_name);
+                    return;
+                }
+                new_names.insert(i.id, Symbol::intern(&new_name));
             }
         });
 
-        // (2) Rewrite paths referring to renamed defs
+        // (2) Drop renames that would make two items in the same scope and namespace share a
+        // name.  Keeping an item's old name can cause a new collision, so repeat until there are
+        // none left.
 
-        fold_resolved_paths(krate, cx, |qself, mut path, def| {
-            if let Some(hir_id) = cx.res_to_hir_id(&def[0]) {
-                if let Some(new_ident) = new_idents.get(&hir_id) {
-                    path.segments.last_mut().unwrap().ident = *new_ident;
+        let mut collector = ItemNameCollector {
+            scopes: vec![CRATE_NODE_ID],
+            names: Vec::new(),
+        };
+        visit::walk_crate(&mut collector, krate);
+        let mut names = collector.names;
+        for n in &mut names {
+            n.new_name = new_names.get(&n.id).cloned();
+        }
+
+        loop {
+            let mut defs: HashMap<_, Vec<usize>> = HashMap::new();
+            for (idx, n) in names.iter().enumerate() {
+                let name = n.new_name.unwrap_or(n.name);
+                for &ns in n.namespaces {
+                    defs.entry((n.scope, ns, name)).or_default().push(idx);
                 }
             }
+
+            let mut changed = false;
+            for idxs in defs.values().filter(|idxs| idxs.len() > 1) {
+                for &idx in idxs {
+                    let n = &mut names[idx];
+                    if let Some(new_name) = n.new_name.take() {
+                        warn!("rename_items_regex: not renaming `{}` to `{}`, which would \
+                               collide with another item in the same scope",
+                              n.name, new_name);
+                        new_names.remove(&n.id);
+                        changed = true;
+                    }
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+
+        // (3) Fold over items and rewrite their `ident`s.  Records the new idents of modified
+        // items into `new_idents`.
+
+        let mut new_idents = HashMap::new();
+        FlatMapNodes::visit(krate, |i: P<Item>| {
+            let new_name = match_or!([new_names.get(&i.id)] Some(&x) => x; return smallvec![i]);
+            let new_ident = mk().ident(&*new_name.as_str());
+            new_idents.insert(cx.hir_map().node_to_hir_id(i.id), new_ident);
+
+            smallvec![i.map(|mut i| {
+                if attr::contains_name(&i.attrs, sym::no_mangle) {
+                    let old_name = i.ident.name.to_string();
+                    i.attrs.retain(|a| !a.check_name(sym::no_mangle));
+                    i.attrs.extend(mk().str_attr("export_name", old_name).into_attrs());
+                }
+                Item {
+                    ident: new_ident,
+                    .. i
+                }
+            })]
+        });
+
+        // (4) Rewrite paths referring to renamed defs
+
+        fold_resolved_paths(krate, cx, |qself, mut path, defs| {
+            let new_ident = defs.iter()
+                .filter_map(|def| cx.res_to_hir_id(def))
+                .filter_map(|hir_id| new_idents.get(&hir_id))
+                .next();
+            if let Some(new_ident) = new_ident {
+                path.segments.last_mut().unwrap().ident = *new_ident;
+            }
             (qself, path)
         });
     }
@@ -611,14 +790,295 @@ impl Transform for DeleteItems {
 }
 
 
+/// # `remove_dead_defs` Command
+///
+/// Usage: `remove_dead_defs [only=foreign]`
+///
+/// Remove definitions that can't be reached from the rest of the crate.  Public items, items
+/// marked `#[no_mangle]` or `#[export_name]`, the crate's `main` function, and all items that
+/// this command doesn't remove (like impls, structs and modules) are live, and so is anything
+/// referred to from a live item, according to the results of name resolution.  The remaining
+/// non-public functions, statics, consts and type aliases are removed, along with non-public
+/// declarations in `extern` blocks.  `extern` blocks left empty are removed too, as are `use`
+/// items whose imports were all removed.
+///
+/// With `only=foreign`, only declarations in `extern` blocks are removed.
+///
+/// References from code that is configured out or inside macros are not visible after
+/// expansion.  To be careful, items with a `#[cfg]` or `#[cfg_attr]` attribute are never removed,
+/// and neither are items whose name appears in a macro invocation or definition.
+pub struct RemoveDeadDefs {
+    pub only_foreign: bool,
+}
+
+fn is_cfg_guarded(attrs: &[Attribute]) -> bool {
+    attr::contains_name(attrs, sym::cfg) || attr::contains_name(attrs, sym::cfg_attr)
+}
+
+fn collect_token_idents(tts: TokenStream, idents: &mut HashSet<Symbol>) {
+    for tt in tts.into_trees() {
+        match tt {
+            TokenTree::Token(Token { kind: TokenKind::Ident(name, _), .. }) => {
+                idents.insert(name);
+            }
+            TokenTree::Delimited(_, _, tts) => collect_token_idents(tts, idents),
+            _ => {}
+        }
+    }
+}
+
+/// Finds the items `remove_dead_defs` may remove, and the references between items.
+struct DeadDefFinder<'a, 'tcx: 'a> {
+    cx: &'a RefactorCtxt<'a, 'tcx>,
+    only_foreign: bool,
+    /// Depth of the item currently being visited.  Top-level items have depth 1.
+    depth: usize,
+    /// The innermost removable item enclosing the current node.
+    owner: Option<DefId>,
+    candidates: HashMap<DefId, Symbol>,
+    /// References made from each removable item.
+    refs: HashMap<DefId, HashSet<DefId>>,
+    /// References made from outside any removable item.
+    root_refs: HashSet<DefId>,
+    macro_idents: HashSet<Symbol>,
+}
+
+impl<'a, 'tcx> DeadDefFinder<'a, 'tcx> {
+    fn is_candidate(&self, i: &Item) -> bool {
+        if self.only_foreign || i.vis.node.is_pub() || is_cfg_guarded(&i.attrs) {
+            return false;
+        }
+        if attr::contains_name(&i.attrs, sym::no_mangle) ||
+           attr::contains_name(&i.attrs, sym::export_name) {
+            return false;
+        }
+        if self.depth == 1 && i.ident.name == sym::main {
+            return false;
+        }
+        match i.kind {
+            ItemKind::Fn(..) | ItemKind::Static(..) | ItemKind::Const(..) |
+            ItemKind::TyAlias(..) => true,
+            _ => false,
+        }
+    }
+
+    fn with_owner<F: FnOnce(&mut Self)>(&mut self, id: NodeId, name: Symbol, f: F) {
+        let def_id = self.cx.node_def_id(id);
+        self.candidates.insert(def_id, name);
+        self.refs.entry(def_id).or_default();
+        let old = self.owner.replace(def_id);
+        f(self);
+        self.owner = old;
+    }
+
+    fn add_ref(&mut self, res: Option<Res>) {
+        let def_id = match_or!([res.and_then(|res| res.opt_def_id())] Some(x) => x; return);
+        match self.owner {
+            Some(owner) => { self.refs.get_mut(&owner).unwrap().insert(def_id); }
+            None => { self.root_refs.insert(def_id); }
+        }
+    }
+}
+
+impl<'a, 'tcx, 'ast> Visitor<'ast> for DeadDefFinder<'a, 'tcx> {
+    fn visit_item(&mut self, i: &'ast Item) {
+        match i.kind {
+            // Imports don't keep anything alive.  Unused ones are cleaned up afterward.
+            ItemKind::Use(..) => return,
+            ItemKind::MacroDef(ref def) => {
+                collect_token_idents(def.body.inner_tokens(), &mut self.macro_idents);
+            }
+            _ => {}
+        }
+
+        self.depth += 1;
+        if self.is_candidate(i) {
+            self.with_owner(i.id, i.ident.name, |this| visit::walk_item(this, i));
+        } else {
+            visit::walk_item(self, i);
+        }
+        self.depth -= 1;
+    }
+
+    fn visit_foreign_item(&mut self, i: &'ast ForeignItem) {
+        let removable = match i.kind {
+            ForeignItemKind::Fn(..) | ForeignItemKind::Static(..) => true,
+            _ => false,
+        };
+        if removable && !i.vis.node.is_pub() && !is_cfg_guarded(&i.attrs) {
+            self.with_owner(i.id, i.ident.name, |this| visit::walk_foreign_item(this, i));
+        } else {
+            visit::walk_foreign_item(self, i);
+        }
+    }
+
+    fn visit_expr(&mut self, e: &'ast Expr) {
+        let res = self.cx.try_resolve_expr_hir(e);
+        self.add_ref(res);
+        visit::walk_expr(self, e);
+    }
+
+    fn visit_ty(&mut self, t: &'ast Ty) {
+        let res = self.cx.try_resolve_ty_hir(t);
+        self.add_ref(res);
+        visit::walk_ty(self, t);
+    }
+
+    fn visit_pat(&mut self, p: &'ast Pat) {
+        let res = self.cx.try_resolve_pat_hir(p);
+        self.add_ref(res);
+        visit::walk_pat(self, p);
+    }
+
+    fn visit_mac(&mut self, mac: &'ast Mac) {
+        collect_token_idents(mac.args.inner_tokens(), &mut self.macro_idents);
+    }
+}
+
+/// The definitions imported by the `use` tree with the given `NodeId`.
+fn use_tree_defs(cx: &RefactorCtxt, id: NodeId, tree: &UseTree) -> Vec<DefId> {
+    let mut ids = vec![id];
+    if let UseTreeKind::Simple(_, id1, id2) = tree.kind {
+        ids.push(id1);
+        ids.push(id2);
+    }
+    ids.into_iter()
+        .filter_map(|id| cx.try_resolve_use_id(id))
+        .filter_map(|path| path.res.opt_def_id())
+        .collect()
+}
+
+/// Remove the parts of a `use` tree that import only removed definitions.  Returns `false` if
+/// nothing is left of the tree.
+pub(super) fn prune_use_tree(cx: &RefactorCtxt, id: NodeId, tree: &mut UseTree,
+                              removed: &HashSet<DefId>) -> bool {
+    match tree.kind {
+        UseTreeKind::Simple(..) => {
+            let defs = use_tree_defs(cx, id, tree);
+            defs.is_empty() || !defs.iter().all(|def_id| removed.contains(def_id))
+        }
+        UseTreeKind::Nested(ref mut trees) => {
+            if trees.is_empty() {
+                return true;
+            }
+            *trees = mem::replace(trees, Vec::new()).into_iter()
+                .filter_map(|(mut tree, id)| {
+                    if prune_use_tree(cx, id, &mut tree, removed) { Some((tree, id)) } else { None }
+                })
+                .collect();
+            !trees.is_empty()
+        }
+        UseTreeKind::Glob => true,
+    }
+}
+
+impl Transform for RemoveDeadDefs {
+    fn transform(&self, krate: &mut Crate, _st: &CommandState, cx: &RefactorCtxt) {
+        // (1) Find the removable items and their references.
+
+        let mut finder = DeadDefFinder {
+            cx,
+            only_foreign: self.only_foreign,
+            depth: 0,
+            owner: None,
+            candidates: HashMap::new(),
+            refs: HashMap::new(),
+            root_refs: HashSet::new(),
+            macro_idents: HashSet::new(),
+        };
+        visit::walk_crate(&mut finder, krate);
+
+        // (2) Propagate liveness from the references made by live code.
+
+        let mut live = HashSet::new();
+        let mut queue = finder.root_refs.iter().cloned().collect::<Vec<_>>();
+        queue.extend(finder.candidates.iter()
+                     .filter(|&(_, name)| finder.macro_idents.contains(name))
+                     .map(|(&def_id, _)| def_id));
+        while let Some(def_id) = queue.pop() {
+            if !finder.candidates.contains_key(&def_id) || !live.insert(def_id) {
+                continue;
+            }
+            queue.extend(finder.refs[&def_id].iter().cloned());
+        }
+
+        let removed = finder.candidates.keys()
+            .filter(|def_id| !live.contains(def_id))
+            .cloned()
+            .collect::<HashSet<_>>();
+        if removed.is_empty() {
+            return;
+        }
+
+        // (3) Remove the dead items, and then the imports of them.
+
+        let is_dead = |id| cx.hir_map().opt_local_def_id(id)
+            .map_or(false, |def_id| removed.contains(&def_id));
+        FlatMapNodes::visit(krate, |i: P<Item>| {
+            if is_dead(i.id) {
+                return smallvec![];
+            }
+            if let ItemKind::ForeignMod(ref fm) = i.kind {
+                if !fm.items.iter().any(|fi| is_dead(fi.id)) {
+                    return smallvec![i];
+                }
+                if fm.items.iter().all(|fi| is_dead(fi.id)) {
+                    return smallvec![];
+                }
+                return smallvec![i.map(|mut i| {
+                    if let ItemKind::ForeignMod(ref mut fm) = i.kind {
+                        fm.items.retain(|fi| !is_dead(fi.id));
+                    }
+                    i
+                })];
+            }
+            smallvec![i]
+        });
+
+        FlatMapNodes::visit(krate, |i: P<Item>| {
+            if !matches!([i.kind] ItemKind::Use(..)) {
+                return smallvec![i];
+            }
+            let mut i = i;
+            let id = i.id;
+            let keep = match i.kind {
+                ItemKind::Use(ref mut tree) => prune_use_tree(cx, id, tree, &removed),
+                _ => unreachable!(),
+            };
+            if keep { smallvec![i] } else { smallvec![] }
+        });
+    }
+}
+
+
 pub fn register_commands(reg: &mut Registry) {
     use super::mk;
 
-    reg.register("rename_items_regex", |args| mk(RenameRegex {
-        pattern: args[0].clone(),
-        repl: args[1].clone(),
-        filter: args.get(2).map(|x| (x as &str).into_symbol()),
-    }));
+    reg.register("rename_items_regex", |args| {
+        let arg = |name: &str| args.iter()
+            .find(|arg| arg.starts_with(name) && arg[name.len()..].starts_with('='))
+            .map(|arg| &arg[name.len() + 1..]);
+        match arg("pattern") {
+            Some(pattern) => mk(RenameRegex {
+                pattern: pattern.to_owned(),
+                repl: arg("replace").unwrap_or("").to_owned(),
+                filter: None,
+                kinds: arg("kind").map(|kinds| kinds.split(',').map(|k| k.to_owned()).collect()),
+                export_name: match arg("no_mangle") {
+                    None | Some("skip") => false,
+                    Some("export_name") => true,
+                    Some(x) => panic!("rename_items_regex: bad no_mangle option `{}`", x),
+                },
+            }),
+            None => mk(RenameRegex {
+                pattern: args[0].clone(),
+                repl: args[1].clone(),
+                filter: args.get(2).map(|x| (x as &str).into_symbol()),
+                kinds: None,
+                export_name: false,
+            }),
+        }
+    });
 
     reg.register("rename_unnamed", |_args| mk(RenameUnnamed));
 
@@ -644,5 +1104,9 @@ pub fn register_commands(reg: &mut Registry) {
     }));
 
     reg.register("delete_items", |_args| mk(DeleteItems));
+
+    reg.register("remove_dead_defs", |args| mk(RemoveDeadDefs {
+        only_foreign: args.iter().any(|arg| arg == "only=foreign"),
+    }));
 }
 
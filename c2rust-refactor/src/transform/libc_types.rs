@@ -0,0 +1,230 @@
+//! Transform that replaces `libc` type aliases with native Rust types.
+
+use std::collections::HashMap;
+use std::mem;
+
+use rustc::session::Session;
+use syntax::ast::*;
+use syntax::mut_visit::{self, MutVisitor};
+use syntax::ptr::P;
+use syntax::symbol::{kw, Symbol};
+use syntax::visit::{self, Visitor};
+use smallvec::{smallvec, SmallVec};
+
+use crate::ast_manip::MutVisit;
+use crate::command::{CommandState, Registry};
+use crate::driver::{self, Phase};
+use crate::transform::Transform;
+use crate::RefactorCtxt;
+
+
+/// If `path` is `libc::NAME` or `::libc::NAME`, return `NAME`.
+fn libc_type_name(path: &Path) -> Option<Symbol> {
+    let segs = match path.segments.len() {
+        2 => &path.segments[..],
+        3 if path.segments[0].ident.name == kw::PathRoot => &path.segments[1..],
+        _ => return None,
+    };
+    if &*segs[0].ident.as_str() != "libc" || segs[1].args.is_some() {
+        return None;
+    }
+    Some(segs[1].ident.name)
+}
+
+/// Check if `path` refers to something through `libc`.
+fn is_libc_path(path: &Path) -> bool {
+    let first = path.segments.iter().find(|seg| seg.ident.name != kw::PathRoot);
+    first.map_or(false, |seg| &*seg.ident.as_str() == "libc")
+}
+
+/// Native Rust equivalents of the `libc` type aliases, for the session's target.
+fn native_types(sess: &Session, keep_char: bool) -> HashMap<&'static str, &'static str> {
+    let target = &sess.target.target;
+    let (c_int, c_uint) = match &*target.target_c_int_width {
+        "16" => ("i16", "u16"),
+        _ => ("i32", "u32"),
+    };
+    let (c_long, c_ulong) = if target.target_os == "windows" || sess.target.ptr_width == 32 {
+        ("i32", "u32")
+    } else {
+        ("i64", "u64")
+    };
+    let unsigned_char = match &*target.arch {
+        "aarch64" => target.target_os != "macos" && target.target_os != "ios",
+        "arm" | "powerpc" | "powerpc64" | "s390x" | "riscv32" | "riscv64" => true,
+        _ => false,
+    };
+
+    let mut types = HashMap::new();
+    types.insert("c_schar", "i8");
+    types.insert("c_uchar", "u8");
+    types.insert("c_short", "i16");
+    types.insert("c_ushort", "u16");
+    types.insert("c_int", c_int);
+    types.insert("c_uint", c_uint);
+    types.insert("c_long", c_long);
+    types.insert("c_ulong", c_ulong);
+    types.insert("c_longlong", "i64");
+    types.insert("c_ulonglong", "u64");
+    types.insert("c_float", "f32");
+    types.insert("c_double", "f64");
+    types.insert("int8_t", "i8");
+    types.insert("uint8_t", "u8");
+    types.insert("int16_t", "i16");
+    types.insert("uint16_t", "u16");
+    types.insert("int32_t", "i32");
+    types.insert("uint32_t", "u32");
+    types.insert("int64_t", "i64");
+    types.insert("uint64_t", "u64");
+    types.insert("size_t", "usize");
+    types.insert("ssize_t", "isize");
+    types.insert("ptrdiff_t", "isize");
+    types.insert("intptr_t", "isize");
+    types.insert("uintptr_t", "usize");
+    if !keep_char {
+        types.insert("c_char", if unsigned_char { "u8" } else { "i8" });
+    }
+    types
+}
+
+
+/// # `libc_types_to_native` Command
+///
+/// Usage: `libc_types_to_native [keep_char=1]`
+///
+/// Replace references to `libc` integer and float type aliases, such as
+/// `libc::c_int` and `libc::size_t`, with the native Rust types they stand for
+/// on the session's target.  This covers local, field and static types, casts,
+/// and the signatures of Rust-ABI functions.  The signatures of `extern`
+/// functions and the contents of `extern` blocks are left alone, since those
+/// describe the C interface.
+///
+/// Afterwards, `use libc;` items are removed from modules that no longer refer
+/// to anything through `libc`.
+///
+/// With `keep_char=1`, `libc::c_char` is kept, as its signedness depends on the
+/// target.
+pub struct LibcTypesToNative {
+    pub keep_char: bool,
+}
+
+struct NativeTypeFolder<'a> {
+    sess: &'a Session,
+    types: HashMap<&'static str, &'static str>,
+    /// Set while visiting an `extern` function, until its signature has been skipped.
+    skip_fn_decl: bool,
+}
+
+impl<'a> MutVisitor for NativeTypeFolder<'a> {
+    fn flat_map_item(&mut self, i: P<Item>) -> SmallVec<[P<Item>; 1]> {
+        let is_extern = match i.kind {
+            ItemKind::Fn(ref sig, _, _) => match sig.header.ext {
+                Extern::None => false,
+                _ => true,
+            },
+            _ => false,
+        };
+        let old = mem::replace(&mut self.skip_fn_decl, is_extern);
+        let i = mut_visit::noop_flat_map_item(i, self);
+        self.skip_fn_decl = old;
+        i
+    }
+
+    fn flat_map_foreign_item(&mut self, i: ForeignItem) -> SmallVec<[ForeignItem; 1]> {
+        smallvec![i]
+    }
+
+    fn visit_fn_decl(&mut self, d: &mut P<FnDecl>) {
+        if mem::replace(&mut self.skip_fn_decl, false) {
+            return;
+        }
+        mut_visit::noop_visit_fn_decl(d, self)
+    }
+
+    fn visit_ty(&mut self, t: &mut P<Ty>) {
+        let native = match t.kind {
+            TyKind::Path(None, ref path) => libc_type_name(path)
+                .and_then(|name| self.types.get(&*name.as_str()).cloned()),
+            _ => None,
+        };
+        if let Some(native) = native {
+            let mut new_ty = driver::parse_ty(self.sess, native);
+            new_ty.id = t.id;
+            new_ty.span = t.span;
+            *t = new_ty;
+            return;
+        }
+        mut_visit::noop_visit_ty(t, self)
+    }
+
+    fn visit_mac(&mut self, mac: &mut Mac) {
+        mut_visit::noop_visit_mac(mac, self)
+    }
+}
+
+/// Looks for paths through `libc`, not counting `use` items and nested modules.
+struct LibcPathFinder {
+    found: bool,
+}
+
+impl<'ast> Visitor<'ast> for LibcPathFinder {
+    fn visit_item(&mut self, i: &'ast Item) {
+        match i.kind {
+            ItemKind::Use(..) | ItemKind::Mod(..) => {}
+            _ => visit::walk_item(self, i),
+        }
+    }
+
+    fn visit_path(&mut self, path: &'ast Path, _id: NodeId) {
+        self.found |= is_libc_path(path);
+        visit::walk_path(self, path);
+    }
+
+    fn visit_mac(&mut self, _mac: &'ast Mac) {}
+}
+
+struct UnusedLibcUseFolder;
+
+impl MutVisitor for UnusedLibcUseFolder {
+    fn visit_mod(&mut self, m: &mut Mod) {
+        let mut finder = LibcPathFinder { found: false };
+        for i in &m.items {
+            finder.visit_item(i);
+        }
+        if !finder.found {
+            m.items.retain(|i| match i.kind {
+                ItemKind::Use(ref tree) => !is_libc_path(&tree.prefix),
+                _ => true,
+            });
+        }
+        mut_visit::noop_visit_mod(m, self)
+    }
+
+    fn visit_mac(&mut self, mac: &mut Mac) {
+        mut_visit::noop_visit_mac(mac, self)
+    }
+}
+
+impl Transform for LibcTypesToNative {
+    fn transform(&self, krate: &mut Crate, _st: &CommandState, cx: &RefactorCtxt) {
+        krate.visit(&mut NativeTypeFolder {
+            sess: cx.session(),
+            types: native_types(cx.session(), self.keep_char),
+            skip_fn_decl: false,
+        });
+        krate.visit(&mut UnusedLibcUseFolder);
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase2
+    }
+}
+
+
+pub fn register_commands(reg: &mut Registry) {
+    use super::mk;
+
+    reg.register("libc_types_to_native", |args| mk(LibcTypesToNative {
+        keep_char: args.iter().any(|arg| arg == "keep_char=1"),
+    }));
+}
@@ -14,11 +14,13 @@ use std::cell::Cell;
 use std::collections::{HashMap, HashSet};
 use std::marker::PhantomData;
 
-use crate::ast_manip::MutVisitNodes;
+use crate::ast_manip::{MutVisitNodes, visit_nodes};
 use crate::command::{CommandState, Registry};
-use crate::driver::Phase;
+use crate::driver::{self, Phase};
+use crate::matcher::{Bindings, MatchCtxt, Subst};
 use crate::transform::Transform;
 use crate::transform::casts::sym_token_kind;
+use crate::transform::mem::strip_casts;
 use crate::RefactorCtxt;
 
 
@@ -110,6 +112,243 @@ impl Transform for RemoveNullTerminator {
 }
 
 
+/// # `byte_strings` Command
+///
+/// Usage: `byte_strings [mode=cstr|str]`
+///
+/// Rewrite the C string literals that the translator emits inline, like
+/// `b"hello\0" as *const u8 as *const libc::c_char`.
+///
+/// With `mode=cstr`, the default, each one becomes
+/// `::std::ffi::CStr::from_bytes_with_nul(b"hello\0").unwrap().as_ptr()`.  A
+/// literal that appears more than once is hoisted into a `const` at the crate
+/// root, like `const C_STR_02935131: &[u8] = b"hello\0";`, which its uses read
+/// through `crate::C_STR_02935131`.  The name comes from a hash of the bytes,
+/// so it stays the same when the command runs again, and an existing `const`
+/// with that name and the same bytes is reused.  If the name is already taken
+/// by another item or by a string with the same hash, a suffix like `_1` is
+/// added.
+///
+/// With `mode=str`, a literal read as a `&str`, as in
+/// `CStr::from_ptr(b"hello\0" as ...).to_str().unwrap()` where its consumer
+/// was already converted to take a `&str`, becomes `"hello"`.  An `unsafe`
+/// block around the conversion goes away with it.
+///
+/// Literals with a NUL before the end are left in byte form, and so are
+/// literals that aren't UTF-8 in `mode=str`.
+pub struct ByteStrings {
+    pub mode: ByteStringMode,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ByteStringMode {
+    CStr,
+    Str,
+}
+
+/// If `e` is a C string literal like `b"hello\0" as *const u8 as *const libc::c_char`, return
+/// its bytes, including the terminator.
+fn c_str_lit(e: &Expr) -> Option<&[u8]> {
+    let (inner, ty) = match_or!([e.kind] ExprKind::Cast(ref i, ref t) => (i, t); return None);
+    let lit = match_or!([inner.kind] ExprKind::Cast(ref l, _) => l; return None);
+    let bytes = match lit.kind {
+        ExprKind::Lit(Lit { kind: LitKind::ByteStr(ref bs), .. }) => bs,
+        _ => return None,
+    };
+    let pointee = match_or!([ty.kind] TyKind::Ptr(MutTy { ref ty, mutbl: Mutability::Immutable })
+                            => ty; return None);
+    let is_c_char = match pointee.kind {
+        TyKind::Path(None, ref path) =>
+            path.segments.last().map_or(false, |seg| &*seg.ident.as_str() == "c_char"),
+        _ => false,
+    };
+    // The terminator must be the only NUL.
+    if !is_c_char || bytes.iter().position(|&b| b == 0) != Some(bytes.len() - 1) {
+        return None;
+    }
+    Some(bytes)
+}
+
+/// If `e` is `CStr::from_ptr(lit).to_str().unwrap()` for a C string literal, return its bytes.
+fn c_str_lit_as_str(e: &Expr) -> Option<&[u8]> {
+    let (seg, args) = match_or!([e.kind] ExprKind::MethodCall(ref s, ref a) => (s, a);
+                                return None);
+    if &*seg.ident.as_str() != "unwrap" {
+        return None;
+    }
+    let (seg, args) = match_or!([args[0].kind] ExprKind::MethodCall(ref s, ref a) => (s, a);
+                                return None);
+    if &*seg.ident.as_str() != "to_str" {
+        return None;
+    }
+    let (func, args) = match_or!([args[0].kind] ExprKind::Call(ref f, ref a) => (f, a);
+                                 return None);
+    let path = match_or!([func.kind] ExprKind::Path(None, ref p) => p; return None);
+    let is_from_ptr = path.segments.last().map_or(false, |s| &*s.ident.as_str() == "from_ptr");
+    if args.len() != 1 || !is_from_ptr {
+        return None;
+    }
+    // `c_str_to_str` adds another cast to `*const c_char`.
+    match args[0].kind {
+        ExprKind::Cast(ref inner, _) if c_str_lit(inner).is_some() => c_str_lit(inner),
+        _ => c_str_lit(&args[0]),
+    }
+}
+
+/// The base name of the `const` that `byte_strings` hoists the C string `bytes` into, from its
+/// FNV-1a hash.
+fn c_str_const_name(bytes: &[u8]) -> String {
+    let hash = bytes.iter().fold(0x811c_9dc5_u32, |h, &b| (h ^ b as u32).wrapping_mul(0x0100_0193));
+    format!("C_STR_{:08X}", hash)
+}
+
+/// Name the `const`s for the C strings `shared`, given the names of the crate's top-level items
+/// in `taken`, along with the bytes of those that are byte string `const`s.  A name that is
+/// taken by anything but a `const` with the same bytes gets a `_1`, `_2`, ... suffix.  Returns
+/// the name for each string, and whether its `const` needs to be added.
+fn c_str_const_names(
+    shared: Vec<Vec<u8>>,
+    mut taken: HashMap<Symbol, Option<Vec<u8>>>,
+) -> HashMap<Vec<u8>, (Symbol, bool)> {
+    let mut names = HashMap::new();
+    for bytes in shared {
+        let base = c_str_const_name(&bytes);
+        let mut name = Symbol::intern(&base);
+        let mut suffix = 0;
+        let is_new = loop {
+            match taken.get(&name) {
+                None => break true,
+                Some(Some(b)) if *b == bytes => break false,
+                Some(_) => {
+                    suffix += 1;
+                    name = Symbol::intern(&format!("{}_{}", base, suffix));
+                }
+            }
+        };
+        taken.insert(name, Some(bytes.clone()));
+        names.insert(bytes, (name, is_new));
+    }
+    names
+}
+
+impl Transform for ByteStrings {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        match self.mode {
+            ByteStringMode::CStr => self.to_c_str(krate, st, cx),
+            ByteStringMode::Str => self.to_str(krate, cx),
+        }
+    }
+}
+
+impl ByteStrings {
+    fn to_c_str(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        let mut mcx = MatchCtxt::new(st, cx);
+        let c_str_repl = mcx.parse_expr(
+            "::std::ffi::CStr::from_bytes_with_nul($b).unwrap().as_ptr()");
+
+        let mut counts = HashMap::new();
+        visit_nodes(krate, |e: &Expr| {
+            if let Some(bytes) = c_str_lit(e) {
+                *counts.entry(bytes.to_owned()).or_insert(0) += 1;
+            }
+        });
+
+        let taken = krate.module.items.iter().map(|i| {
+            let bytes = match i.kind {
+                ItemKind::Const(_, ref init) => match init.kind {
+                    ExprKind::Lit(Lit { kind: LitKind::ByteStr(ref bs), .. }) =>
+                        Some((**bs).clone()),
+                    _ => None,
+                },
+                _ => None,
+            };
+            (i.ident.name, bytes)
+        }).collect();
+        let mut shared = counts.iter()
+            .filter(|&(_, &count)| count > 1)
+            .map(|(bytes, _)| bytes.clone())
+            .collect::<Vec<_>>();
+        shared.sort();
+        let names = c_str_const_names(shared, taken);
+
+        let mut hoisted = HashSet::new();
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            let bytes = match_or!([c_str_lit(e)] Some(x) => x.to_owned(); return);
+            let b = if let Some(&(name, _)) = names.get(&bytes) {
+                hoisted.insert(bytes);
+                driver::parse_expr(cx.session(), &format!("crate::{}", name))
+            } else {
+                match e.kind {
+                    ExprKind::Cast(ref inner, _) => strip_casts(inner).clone(),
+                    _ => return,
+                }
+            };
+            let mut bnd = Bindings::new();
+            bnd.add("$b", b);
+            *e = c_str_repl.clone().subst(st, cx, &bnd);
+        });
+
+        let mut hoisted = hoisted.into_iter().collect::<Vec<_>>();
+        hoisted.sort();
+        for bytes in hoisted {
+            let (name, is_new) = names[&bytes];
+            if !is_new {
+                continue;
+            }
+            let escaped = bytes.iter()
+                .flat_map(|&b| std::ascii::escape_default(b))
+                .map(|b| b as char)
+                .collect::<String>();
+            krate.module.items.extend(
+                st.parse_items(cx, &format!("const {}: &[u8] = b\"{}\";", name, escaped)));
+        }
+    }
+
+    fn to_str(&self, krate: &mut Crate, cx: &RefactorCtxt) {
+        let str_lit = |bytes: &[u8]| -> Option<P<Expr>> {
+            let s = std::str::from_utf8(&bytes[..bytes.len() - 1]).ok()?;
+            Some(driver::parse_expr(cx.session(), &format!("{:?}", s)))
+        };
+
+        // The conversions that are all an `unsafe` block contains are rewritten along with it.
+        let mut in_unsafe = HashSet::new();
+        visit_nodes(krate, |e: &Expr| {
+            if let Some(inner) = unsafe_block_expr(e) {
+                if c_str_lit_as_str(inner).is_some() {
+                    in_unsafe.insert(inner.id);
+                }
+            }
+        });
+
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            if in_unsafe.contains(&e.id) {
+                return;
+            }
+            let conv = unsafe_block_expr(e).unwrap_or(&*e);
+            let bytes = match_or!([c_str_lit_as_str(conv)] Some(x) => x.to_owned(); return);
+            match str_lit(&bytes) {
+                Some(new_e) => *e = new_e,
+                None => warn!("byte_strings: leaving literal that isn't UTF-8 at {}",
+                              cx.session().source_map().span_to_string(e.span)),
+            }
+        });
+    }
+}
+
+/// If `e` is an `unsafe` block of a single expression, return the expression.
+fn unsafe_block_expr(e: &Expr) -> Option<&P<Expr>> {
+    let b = match_or!([e.kind] ExprKind::Block(ref b, None) => b; return None);
+    match b.rules {
+        BlockCheckMode::Unsafe(UnsafeSource::UserProvided) if b.stmts.len() == 1 => {}
+        _ => return None,
+    }
+    match b.stmts[0].kind {
+        StmtKind::Expr(ref e) => Some(e),
+        _ => None,
+    }
+}
+
+
 /// # `remove_literal_suffixes` Command
 ///
 /// Usage: `remove_literal_suffixes`
@@ -1181,6 +1420,16 @@ pub fn register_commands(reg: &mut Registry) {
     use super::mk;
     reg.register("bytestr_to_str", |_args| mk(ByteStrToStr));
     reg.register("remove_null_terminator", |_args| mk(RemoveNullTerminator));
+    reg.register("byte_strings", |args| mk(ByteStrings {
+        mode: match args.iter().find(|arg| arg.starts_with("mode=")) {
+            None => ByteStringMode::CStr,
+            Some(arg) => match &arg["mode=".len()..] {
+                "cstr" => ByteStringMode::CStr,
+                "str" => ByteStringMode::Str,
+                mode => panic!("byte_strings: unknown mode `{}`", mode),
+            },
+        },
+    }));
     reg.register("remove_literal_suffixes", |_| mk(RemoveLiteralSuffixes));
 }
 
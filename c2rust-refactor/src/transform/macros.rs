@@ -0,0 +1,412 @@
+//! Transform that recovers named constants from expanded C macros.
+
+use std::collections::{BTreeSet, HashSet};
+use std::fs;
+use rustc::ty;
+use syntax::ast::*;
+use syntax::print::pprust;
+use syntax::ptr::P;
+
+use crate::ast_manip::{MutVisitNodes, visit_nodes};
+use crate::ast_manip::util::is_c2rust_attr;
+use crate::command::{CommandState, Registry};
+use crate::driver::{Phase, parse_expr};
+use crate::reflect;
+use crate::transform::Transform;
+use crate::transform::reorganize_definitions::{SrcLoc, parse_source_header};
+use crate::RefactorCtxt;
+
+
+/// # `rematerialize_macros` Command
+///
+/// Usage: `rematerialize_macros MACROS [min=N]`
+///
+/// Recreate the numeric object-like C macros listed in the JSON file `MACROS`
+/// as `pub const` items, and rewrite the literals they were expanded into back
+/// into uses of the constants.  The file lists the name of each macro, its
+/// replacement tokens, and the header that defines it:
+///
+/// ```json
+/// [
+///     { "name": "WIDTH", "tokens": "640", "header": "/src/config.h" },
+///     { "name": "SCALE", "tokens": "(2.5f)", "header": "/src/config.h" }
+/// ]
+/// ```
+///
+/// Macros whose tokens aren't a single, possibly negated and parenthesized,
+/// integer or floating-point literal are ignored.  The type of each constant
+/// follows the C type of its literal: `i32`, `u32`, `i64` or `u64` for integers,
+/// depending on their value and `U`/`L` suffixes, and `f64`, or `f32` with an
+/// `F` suffix, for floats.
+///
+/// This works on the modules produced by the transpiler, and should run before
+/// `reorganize_definitions`: each translation unit is a module with a child
+/// module, bearing a `#[c2rust::header_src]` attribute, for each header it
+/// includes.  A literal in an item of a translation unit is only rewritten to a
+/// macro whose header is included before the item's `#[c2rust::src_loc]` line,
+/// and a literal in a header module only to a macro of that header.  Literals
+/// with a magnitude below `N` (default: 2) and literals in patterns are left
+/// alone, and a negated literal only matches a negative macro.  Literals that
+/// match more than one macro are reported and skipped.  A literal of a
+/// different type than the constant is replaced with a cast of the constant,
+/// unless it's already being cast.
+///
+/// Each constant that's used is added to the header module for its header, and
+/// imported into the translation unit if it's used there, the same way as the
+/// header's other declarations.  `reorganize_definitions` then merges the
+/// copies from different translation units.
+pub struct RematerializeMacros {
+    path: String,
+    min: f64,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum MacroValue {
+    Int(i128),
+    Float(f64),
+}
+
+impl MacroValue {
+    fn neg(self) -> MacroValue {
+        match self {
+            MacroValue::Int(i) => MacroValue::Int(-i),
+            MacroValue::Float(f) => MacroValue::Float(-f),
+        }
+    }
+
+    fn magnitude(self) -> f64 {
+        match self {
+            MacroValue::Int(i) => (i as f64).abs(),
+            MacroValue::Float(f) => f.abs(),
+        }
+    }
+}
+
+/// A macro definition from the `MACROS` file.
+struct MacroDef {
+    name: String,
+    header: String,
+    value: MacroValue,
+    ty: &'static str,
+    /// The value, as a Rust literal.
+    lit: String,
+}
+
+/// Parse the replacement tokens of a numeric macro, returning its value, its
+/// type, and the value as a Rust literal.
+fn parse_macro_value(tokens: &str) -> Option<(MacroValue, &'static str, String)> {
+    let mut s = tokens.trim();
+    while s.starts_with('(') && s.ends_with(')') {
+        s = s[1 .. s.len() - 1].trim();
+    }
+    let neg = s.starts_with('-');
+    if neg {
+        s = s[1..].trim_start();
+    }
+    let s = s.to_ascii_lowercase();
+    let sign = if neg { "-" } else { "" };
+
+    let hex = s.starts_with("0x");
+    if !hex && s.contains(|c| c == '.' || c == 'e') {
+        let digits = s.trim_end_matches(|c| c == 'f' || c == 'l');
+        let f: f64 = digits.parse().ok()?;
+        let ty = if s.ends_with('f') { "f32" } else { "f64" };
+        let lit = if digits.starts_with('.') {
+            format!("{}0{}", sign, digits)
+        } else {
+            format!("{}{}", sign, digits)
+        };
+        let value = MacroValue::Float(f);
+        return Some((if neg { value.neg() } else { value }, ty, lit));
+    }
+
+    let digits = s.trim_end_matches(|c| c == 'u' || c == 'l');
+    let suffix = &s[digits.len()..];
+    let (radix, body) = if hex {
+        (16, &digits[2..])
+    } else if digits.len() > 1 && digits.starts_with('0') {
+        (8, &digits[1..])
+    } else {
+        (10, digits)
+    };
+    let v = u64::from_str_radix(body, radix).ok()?;
+
+    // The first of these types that can hold the value, as in C, where decimal
+    // literals without a `U` suffix are always signed.
+    let unsigned = suffix.contains('u');
+    let long = suffix.contains('l');
+    let types = [
+        ("i32", i32::max_value() as u64),
+        ("u32", u32::max_value() as u64),
+        ("i64", i64::max_value() as u64),
+        ("u64", u64::max_value()),
+    ];
+    let ty = types.iter().filter(|&&(ty, _)| {
+        let ty_unsigned = ty.starts_with('u');
+        (!long || ty.ends_with("64")) && if unsigned {
+            ty_unsigned
+        } else {
+            !ty_unsigned || radix != 10
+        }
+    }).find(|&&(_, max)| v <= max)?.0;
+    if neg && ty.starts_with('u') {
+        return None;
+    }
+
+    let lit = if radix == 8 {
+        format!("{}{}", sign, v)
+    } else {
+        format!("{}{}", sign, digits)
+    };
+    let value = MacroValue::Int(v as i128);
+    Some((if neg { value.neg() } else { value }, ty, lit))
+}
+
+/// Read a `rematerialize_macros` definitions file.  Malformed entries are
+/// reported and skipped.
+fn read_macros(path: &str) -> Vec<MacroDef> {
+    let src = fs::read_to_string(path)
+        .unwrap_or_else(|e| panic!("Could not read macro definitions {:?}: {}", path, e));
+    let defs = json::parse(&src)
+        .unwrap_or_else(|e| panic!("Could not parse macro definitions {:?}: {}", path, e));
+
+    defs.members().filter_map(|def| {
+        let (name, tokens, header) = match (
+            def["name"].as_str(),
+            def["tokens"].as_str(),
+            def["header"].as_str(),
+        ) {
+            (Some(name), Some(tokens), Some(header)) => (name, tokens, header),
+            _ => {
+                warn!("rematerialize_macros: malformed macro definition {}; skipping",
+                      def.dump());
+                return None;
+            }
+        };
+        let (value, ty, lit) = parse_macro_value(tokens)?;
+        Some(MacroDef {
+            name: name.to_owned(),
+            header: header.to_owned(),
+            value,
+            ty,
+            lit,
+        })
+    }).collect()
+}
+
+/// The value of a literal, or of a negated literal.
+fn literal_value(e: &Expr) -> Option<MacroValue> {
+    match &e.kind {
+        ExprKind::Lit(lit) => match lit.kind {
+            LitKind::Int(i, _) => Some(MacroValue::Int(i as i128)),
+            LitKind::Float(sym, _) => sym.as_str().parse().ok().map(MacroValue::Float),
+            _ => None,
+        },
+        ExprKind::Unary(UnOp::Neg, inner) => match inner.kind {
+            ExprKind::Lit(_) => literal_value(inner).map(MacroValue::neg),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Rewrite the literals in `item` that match one of the macros in `scope`,
+/// returning the indices of the macros used.
+fn rewrite_literals(
+    item: &mut P<Item>,
+    scope: &[usize],
+    macros: &[MacroDef],
+    min: f64,
+    cx: &RefactorCtxt,
+) -> Vec<usize> {
+    let mut used = vec![];
+    if scope.is_empty() {
+        return used;
+    }
+
+    // Literals in patterns can't be cast, and the operands of negations are
+    // matched along with the negation.  The operands of casts don't need a cast
+    // of their own, as converting the value to the type of the literal and
+    // then to the target type doesn't change it.
+    let mut skip = HashSet::new();
+    let mut cast_operands = HashSet::new();
+    visit_nodes(&**item, |p: &Pat| visit_nodes(p, |e: &Expr| {
+        skip.insert(e.id);
+    }));
+    visit_nodes(&**item, |e: &Expr| match &e.kind {
+        ExprKind::Unary(UnOp::Neg, inner) => {
+            skip.insert(inner.id);
+        }
+        ExprKind::Cast(inner, _) => {
+            cast_operands.insert(inner.id);
+        }
+        _ => {}
+    });
+
+    let item_ident = item.ident;
+    MutVisitNodes::visit(item, |e: &mut P<Expr>| {
+        if skip.contains(&e.id) {
+            return;
+        }
+        let value = match literal_value(e) {
+            Some(v) if v.magnitude() >= min => v,
+            _ => return,
+        };
+
+        let mut candidates: Vec<usize> = vec![];
+        for &i in scope {
+            if macros[i].value == value
+                && !candidates.iter().any(|&j| macros[j].name == macros[i].name)
+            {
+                candidates.push(i);
+            }
+        }
+        let i = match candidates[..] {
+            [] => return,
+            [i] => i,
+            _ => {
+                let names: Vec<&str> = candidates.iter().map(|&i| &macros[i].name[..]).collect();
+                warn!("rematerialize_macros: `{}` in `{}` could be any of {}; skipping",
+                      pprust::expr_to_string(e), item_ident, names.join(", "));
+                return;
+            }
+        };
+
+        let ty = match cx.opt_node_type(e.id) {
+            Some(ty) => ty,
+            None => return,
+        };
+        match ty.kind {
+            ty::Int(_) | ty::Uint(_) | ty::Float(_) => {}
+            _ => return,
+        }
+        let ty = pprust::ty_to_string(&reflect::reflect_tcx_ty(cx.ty_ctxt(), ty));
+
+        let def = &macros[i];
+        let src = if ty == def.ty || cast_operands.contains(&e.id) {
+            def.name.clone()
+        } else {
+            format!("{} as {}", def.name, ty)
+        };
+        *e = parse_expr(cx.session(), &src);
+        used.push(i);
+    });
+    used
+}
+
+/// Rematerialize `macros` in the translation unit module `m`.
+fn rematerialize_in_mod(
+    m: &mut Mod,
+    macros: &[MacroDef],
+    min: f64,
+    st: &CommandState,
+    cx: &RefactorCtxt,
+) {
+    // The header modules of `m`, with the index of the module item, the header
+    // path and the line it's included on.
+    let headers: Vec<(usize, String, usize)> = m.items.iter().enumerate()
+        .filter_map(|(idx, item)| match item.kind {
+            ItemKind::Mod(_) => parse_source_header(&item.attrs)
+                .map(|(path, line)| (idx, path, line)),
+            _ => None,
+        })
+        .collect();
+    if headers.is_empty() {
+        return;
+    }
+
+    let mut used = BTreeSet::new();
+    let mut imported = BTreeSet::new();
+    for (idx, item) in m.items.iter_mut().enumerate() {
+        if let Some((_, path, _)) = headers.iter().find(|h| h.0 == idx) {
+            let scope: Vec<usize> = (0 .. macros.len())
+                .filter(|&i| macros[i].header == *path)
+                .collect();
+            if let ItemKind::Mod(hm) = &mut item.kind {
+                for hi in &mut hm.items {
+                    used.extend(rewrite_literals(hi, &scope, macros, min, cx));
+                }
+            }
+            continue;
+        }
+        if let ItemKind::Mod(_) = item.kind {
+            continue;
+        }
+
+        let line = match item.attrs.iter().find(|attr| is_c2rust_attr(attr, "src_loc")) {
+            Some(attr) => SrcLoc::from(attr).line,
+            None => continue,
+        };
+        let scope: Vec<usize> = (0 .. macros.len())
+            .filter(|&i| headers.iter().any(|(_, path, include_line)| {
+                *path == macros[i].header && *include_line < line
+            }))
+            .collect();
+        let names = rewrite_literals(item, &scope, macros, min, cx);
+        used.extend(names.iter().cloned());
+        imported.extend(names);
+    }
+
+    let header_mod = |def: &MacroDef| {
+        headers.iter().find(|(_, path, _)| *path == def.header).unwrap().0
+    };
+    for &i in &used {
+        let def = &macros[i];
+        if let ItemKind::Mod(hm) = &mut m.items[header_mod(def)].kind {
+            if hm.items.iter().any(|item| item.ident.as_str() == def.name) {
+                continue;
+            }
+            let src = format!("pub const {}: {} = {};", def.name, def.ty, def.lit);
+            hm.items.extend(st.parse_items(cx, &src));
+        }
+    }
+
+    // Import the constants after the header modules and the existing imports.
+    let mut pos = m.items.iter().enumerate()
+        .rposition(|(idx, item)| match item.kind {
+            ItemKind::Use(_) => true,
+            _ => headers.iter().any(|h| h.0 == idx),
+        })
+        .unwrap() + 1;
+    for &i in &imported {
+        let def = &macros[i];
+        let hm_ident = m.items[header_mod(def)].ident;
+        for item in st.parse_items(cx, &format!("use self::{}::{};", hm_ident, def.name)) {
+            m.items.insert(pos, item);
+            pos += 1;
+        }
+    }
+}
+
+impl Transform for RematerializeMacros {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        let macros = read_macros(&self.path);
+
+        MutVisitNodes::visit(krate, |i: &mut P<Item>| {
+            if parse_source_header(&i.attrs).is_some() {
+                return;
+            }
+            if let ItemKind::Mod(m) = &mut i.kind {
+                rematerialize_in_mod(m, &macros, self.min, st, cx);
+            }
+        });
+        rematerialize_in_mod(&mut krate.module, &macros, self.min, st, cx);
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
+pub fn register_commands(reg: &mut Registry) {
+    use super::mk;
+
+    reg.register("rematerialize_macros", |args| mk(RematerializeMacros {
+        path: args[0].clone(),
+        min: args[1..].iter()
+            .find(|arg| arg.starts_with("min="))
+            .map_or(2., |arg| arg["min=".len()..].parse()
+                .unwrap_or_else(|_| panic!("rematerialize_macros: bad threshold `{}`", arg))),
+    }));
+}
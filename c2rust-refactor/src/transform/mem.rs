@@ -0,0 +1,594 @@
+//! Transforms that replace C memory-manipulation calls (`memcpy`, `memmove` and `memset`) with
+//! their Rust equivalents, and that simplify the byte counts passed to them.
+
+use std::collections::{HashMap, HashSet};
+use rustc::ty::{self, TyKind};
+use syntax::ast;
+use syntax::ast::*;
+use syntax::print::pprust;
+use syntax::ptr::P;
+
+use crate::ast_manip::{MutVisitNodes, visit_nodes};
+use crate::command::{CommandState, Registry};
+use crate::driver::{self, Phase};
+use crate::matcher::{Bindings, MatchCtxt, Subst};
+use crate::reflect;
+use crate::transform::Transform;
+use crate::RefactorCtxt;
+
+
+/// Strip any casts wrapping `e`.
+pub(super) fn strip_casts(e: &P<Expr>) -> &P<Expr> {
+    let mut e = e;
+    while let ExprKind::Cast(ref inner, _) = e.kind {
+        e = inner;
+    }
+    e
+}
+
+/// Strip the casts to `*mut c_void` or `*const c_void` wrapping `e`, keeping any cast to a typed
+/// pointer below them, as in `&mut s as *mut S as *mut c_void`.
+fn strip_void_casts(e: &P<Expr>) -> &P<Expr> {
+    let mut e = e;
+    while let ExprKind::Cast(ref inner, ref ty) = e.kind {
+        let is_void_ptr = match ty.kind {
+            ast::TyKind::Ptr(MutTy { ref ty, .. }) => match ty.kind {
+                ast::TyKind::Path(None, ref path) =>
+                    path.segments.last().map_or(false, |seg| &*seg.ident.as_str() == "c_void"),
+                _ => false,
+            },
+            _ => false,
+        };
+        if !is_void_ptr {
+            break;
+        }
+        e = inner;
+    }
+    e
+}
+
+/// Strip casts and `.as_ptr()`/`.as_mut_ptr()` calls from `e`, to get at the buffer a pointer
+/// argument was taken from.
+pub(super) fn strip_ptr_conv(e: &P<Expr>) -> &P<Expr> {
+    let mut e = strip_casts(e);
+    while let ExprKind::MethodCall(ref seg, ref args) = e.kind {
+        let name = seg.ident.as_str();
+        if args.len() != 1 || (&*name != "as_ptr" && &*name != "as_mut_ptr") {
+            break;
+        }
+        e = strip_casts(&args[0]);
+    }
+    e
+}
+
+/// What a pointer argument to a C memory function points into.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(super) enum Buffer<'tcx> {
+    /// An array or slice (possibly behind a reference) with the given element type.
+    Slice(ty::Ty<'tcx>),
+    /// A raw pointer with the given pointee type.
+    Ptr(ty::Ty<'tcx>),
+    Unknown,
+}
+
+pub(super) fn classify_buffer<'tcx>(cx: &RefactorCtxt<'_, 'tcx>, e: &Expr) -> Buffer<'tcx> {
+    let mut ty = match cx.opt_node_type(e.id) {
+        Some(ty) => ty,
+        None => return Buffer::Unknown,
+    };
+    while let TyKind::Ref(_, inner, _) = ty.kind {
+        ty = inner;
+    }
+    match ty.kind {
+        TyKind::Array(elem, _) | TyKind::Slice(elem) => Buffer::Slice(elem),
+        TyKind::RawPtr(ty::TypeAndMut { ty, .. }) => Buffer::Ptr(ty),
+        _ => Buffer::Unknown,
+    }
+}
+
+fn is_int_ty(ty: ty::Ty) -> bool {
+    match ty.kind {
+        TyKind::Int(_) | TyKind::Uint(_) => true,
+        _ => false,
+    }
+}
+
+pub(super) fn is_byte_ty(ty: ty::Ty) -> bool {
+    match ty.kind {
+        TyKind::Int(IntTy::I8) | TyKind::Uint(UintTy::U8) => true,
+        _ => false,
+    }
+}
+
+pub(super) fn is_zero_lit(e: &Expr) -> bool {
+    match e.kind {
+        ExprKind::Lit(ref lit) => match lit.kind {
+            LitKind::Int(0, _) => true,
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// Patterns for byte counts of the form `n * size_of::<T>()`.
+struct SizePatterns {
+    pats: Vec<P<Expr>>,
+    single: P<Expr>,
+}
+
+impl SizePatterns {
+    fn new(mcx: &mut MatchCtxt) -> SizePatterns {
+        let pats = [
+            "$n:Expr.wrapping_mul(cast!(::std::mem::size_of::<$t:Ty>()))",
+            "cast!(::std::mem::size_of::<$t:Ty>()).wrapping_mul($n:Expr)",
+            "$n:Expr * cast!(::std::mem::size_of::<$t:Ty>())",
+            "cast!(::std::mem::size_of::<$t:Ty>()) * $n:Expr",
+        ].iter().map(|pat| mcx.parse_expr(pat)).collect();
+        let single = mcx.parse_expr("::std::mem::size_of::<$t:Ty>()");
+        SizePatterns { pats, single }
+    }
+
+    /// If `size` is a whole number of `T`s, return the element count (with casts stripped) and
+    /// the type `T`.
+    fn split<'tcx>(
+        &self,
+        mcx: &MatchCtxt,
+        cx: &RefactorCtxt<'_, 'tcx>,
+        size: &P<Expr>,
+    ) -> Option<(P<Expr>, ty::Ty<'tcx>)> {
+        let size = strip_casts(size);
+        let elem_ty = |bnd: &Bindings| {
+            cx.opt_node_type(bnd.get::<_, P<Ty>>("$t").unwrap().id)
+        };
+        for pat in &self.pats {
+            if let Ok(mcx) = mcx.clone_match(&**pat, &**size) {
+                let n = strip_casts(mcx.bindings.get::<_, P<Expr>>("$n").unwrap()).clone();
+                return elem_ty(&mcx.bindings).map(|ty| (n, ty));
+            }
+        }
+        if let Ok(mcx) = mcx.clone_match(&*self.single, &**size) {
+            let one = driver::parse_expr(cx.session(), "1");
+            return elem_ty(&mcx.bindings).map(|ty| (one, ty));
+        }
+        None
+    }
+}
+
+
+/// # `memcpy_to_copy` Command
+///
+/// Usage: `memcpy_to_copy`
+///
+/// Rewrite statement-position `memcpy(dst, src, size)` and `memmove(dst, src,
+/// size)` calls, dropping the `c_void` casts on the pointers, but keeping the
+/// casts to typed pointers under them, as in `&mut s as *mut S as *mut c_void`:
+///
+///  * when `dst` and `src` both point into arrays or slices (including through
+///    `.as_mut_ptr()`/`.as_ptr()`) of element type `T`, and `size` is
+///    `n * size_of::<T>()`, emit `dst[..n as usize].copy_from_slice(&src[..n as usize])`;
+///  * otherwise, when both are `T` pointers and `size` is `n * size_of::<T>()`,
+///    emit `::std::ptr::copy_nonoverlapping(src, dst, n as usize)` for `memcpy`
+///    and `::std::ptr::copy(src, dst, n as usize)` for `memmove`;
+///  * otherwise, keep a byte-wise copy:
+///    `::std::ptr::copy_nonoverlapping(src as *const u8, dst as *mut u8, size as usize)`.
+///
+/// Uses type information to find the element types, so it needs a crate that
+/// typechecks.
+pub struct MemcpyToCopy;
+
+impl Transform for MemcpyToCopy {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        let mut mcx = MatchCtxt::new(st, cx);
+        let memcpy_pat = mcx.parse_expr("memcpy($dst:Expr, $src:Expr, $size:Expr)");
+        let memmove_pat = mcx.parse_expr("memmove($dst:Expr, $src:Expr, $size:Expr)");
+        let sizes = SizePatterns::new(&mut mcx);
+        let slice_repl = mcx.parse_expr(
+            "$dst[..$n as usize].copy_from_slice(&$src[..$n as usize])");
+        let memcpy_repl = mcx.parse_expr(
+            "::std::ptr::copy_nonoverlapping($src, $dst, $n as usize)");
+        let memmove_repl = mcx.parse_expr("::std::ptr::copy($src, $dst, $n as usize)");
+        let memcpy_bytes_repl = mcx.parse_expr(
+            "::std::ptr::copy_nonoverlapping($src as *const u8, $dst as *mut u8, $size as usize)");
+        let memmove_bytes_repl = mcx.parse_expr(
+            "::std::ptr::copy($src as *const u8, $dst as *mut u8, $size as usize)");
+
+        MutVisitNodes::visit(krate, |b: &mut P<Block>| {
+            for s in &mut b.stmts {
+                let e = match s.kind {
+                    StmtKind::Semi(ref mut e) => e,
+                    _ => continue,
+                };
+                let (m, is_memmove) = match mcx.clone_match(&*memcpy_pat, &*e) {
+                    Ok(m) => (m, false),
+                    Err(_) => match mcx.clone_match(&*memmove_pat, &*e) {
+                        Ok(m) => (m, true),
+                        Err(_) => continue,
+                    },
+                };
+                let dst = m.bindings.get::<_, P<Expr>>("$dst").unwrap();
+                let src = m.bindings.get::<_, P<Expr>>("$src").unwrap();
+                let size = m.bindings.get::<_, P<Expr>>("$size").unwrap();
+
+                let mut bnd = Bindings::new();
+                let repl = match sizes.split(&mcx, cx, size) {
+                    Some((n, elem_ty)) => {
+                        let (dst_buf, src_buf) = (strip_ptr_conv(dst), strip_ptr_conv(src));
+                        let (dst_ptr, src_ptr) = (strip_void_casts(dst), strip_void_casts(src));
+                        bnd.add("$n", n);
+                        if classify_buffer(cx, dst_buf) == Buffer::Slice(elem_ty) &&
+                           classify_buffer(cx, src_buf) == Buffer::Slice(elem_ty) {
+                            bnd.add("$dst", dst_buf.clone());
+                            bnd.add("$src", src_buf.clone());
+                            &slice_repl
+                        } else if classify_buffer(cx, dst_ptr) == Buffer::Ptr(elem_ty) &&
+                                  classify_buffer(cx, src_ptr) == Buffer::Ptr(elem_ty) {
+                            bnd.add("$dst", dst_ptr.clone());
+                            bnd.add("$src", src_ptr.clone());
+                            if is_memmove { &memmove_repl } else { &memcpy_repl }
+                        } else {
+                            bnd = Bindings::new();
+                            bnd.add("$dst", dst_ptr.clone());
+                            bnd.add("$src", src_ptr.clone());
+                            bnd.add("$size", strip_casts(size).clone());
+                            if is_memmove { &memmove_bytes_repl } else { &memcpy_bytes_repl }
+                        }
+                    }
+                    None => {
+                        bnd.add("$dst", strip_void_casts(dst).clone());
+                        bnd.add("$src", strip_void_casts(src).clone());
+                        bnd.add("$size", strip_casts(size).clone());
+                        if is_memmove { &memmove_bytes_repl } else { &memcpy_bytes_repl }
+                    }
+                };
+                *e = repl.clone().subst(st, cx, &bnd);
+            }
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
+/// # `memset_to_fill` Command
+///
+/// Usage: `memset_to_fill [zeroed]`
+///
+/// Rewrite statement-position `memset(dst, val, size)` calls, dropping the
+/// `c_void` cast on the pointer, but keeping the cast to a typed pointer under
+/// it:
+///
+///  * when `dst` points into an array or slice (including through
+///    `.as_mut_ptr()`) of integer element type `T`, and `size` is
+///    `n * size_of::<T>()`, emit `dst[..n as usize].iter_mut().for_each(|x| *x = val as _)`;
+///  * otherwise, when `dst` is a `*mut T` and `size` is `n * size_of::<T>()`,
+///    emit `::std::ptr::write_bytes(dst, val as u8, n as usize)`;
+///  * otherwise, emit the byte-wise
+///    `::std::ptr::write_bytes(dst as *mut u8, val as u8, size as usize)`.
+///
+/// A byte-sized `T` counts `size` directly.  A non-zero `val` only makes sense
+/// when `T` is byte-sized; other such calls are left alone with a warning.
+///
+/// With `zeroed`, zeroing a whole struct through `memset(&mut s as *mut S as
+/// *mut c_void, 0, size_of::<S>())` becomes `s = ::std::mem::zeroed()`.
+///
+/// Uses type information to find the element types, so it needs a crate that
+/// typechecks.
+pub struct MemsetToFill {
+    pub zeroed: bool,
+}
+
+impl Transform for MemsetToFill {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        let mut mcx = MatchCtxt::new(st, cx);
+        let memset_pat = mcx.parse_expr("memset($dst:Expr, $val:Expr, $size:Expr)");
+        let addr_pat = mcx.parse_expr("&mut $s:Expr");
+        let sizes = SizePatterns::new(&mut mcx);
+        let slice_repl = mcx.parse_expr(
+            "$dst[..$n as usize].iter_mut().for_each(|x| *x = $val as _)");
+        let slice_zero_repl = mcx.parse_expr(
+            "$dst[..$n as usize].iter_mut().for_each(|x| *x = 0)");
+        let ptr_repl = mcx.parse_expr("::std::ptr::write_bytes($dst, $val as u8, $n as usize)");
+        let ptr_zero_repl = mcx.parse_expr("::std::ptr::write_bytes($dst, 0, $n as usize)");
+        let bytes_repl = mcx.parse_expr(
+            "::std::ptr::write_bytes($dst as *mut u8, $val as u8, $size as usize)");
+        let bytes_zero_repl = mcx.parse_expr(
+            "::std::ptr::write_bytes($dst as *mut u8, 0, $size as usize)");
+        let zeroed_repl = mcx.parse_expr("$s = ::std::mem::zeroed()");
+
+        MutVisitNodes::visit(krate, |b: &mut P<Block>| {
+            for s in &mut b.stmts {
+                let e = match s.kind {
+                    StmtKind::Semi(ref mut e) => e,
+                    _ => continue,
+                };
+                let m = match mcx.clone_match(&*memset_pat, &*e) {
+                    Ok(m) => m,
+                    Err(_) => continue,
+                };
+                let dst = m.bindings.get::<_, P<Expr>>("$dst").unwrap();
+                let val = strip_casts(m.bindings.get::<_, P<Expr>>("$val").unwrap());
+                let size = m.bindings.get::<_, P<Expr>>("$size").unwrap();
+                let zero = is_zero_lit(val);
+                let (dst_buf, dst_ptr) = (strip_ptr_conv(dst), strip_void_casts(dst));
+
+                let mut bnd = Bindings::new();
+                bnd.add("$val", val.clone());
+
+                // Whole-struct zeroing.
+                if self.zeroed && zero {
+                    if let Ok(am) = mcx.clone_match(&*addr_pat, &**strip_casts(dst)) {
+                        let target = am.bindings.get::<_, P<Expr>>("$s").unwrap();
+                        let target_ty = cx.opt_node_type(target.id);
+                        if let Ok(sm) = mcx.clone_match(&*sizes.single, &**strip_casts(size)) {
+                            let size_ty = cx.opt_node_type(
+                                sm.bindings.get::<_, P<Ty>>("$t").unwrap().id);
+                            if target_ty.is_some() && target_ty == size_ty {
+                                let mut bnd = Bindings::new();
+                                bnd.add("$s", target.clone());
+                                *e = zeroed_repl.clone().subst(st, cx, &bnd);
+                                continue;
+                            }
+                        }
+                    }
+                }
+
+                let (dst_kind, dst_expr) = match classify_buffer(cx, dst_buf) {
+                    Buffer::Slice(ty) => (Buffer::Slice(ty), dst_buf),
+                    _ => (classify_buffer(cx, dst_ptr), dst_ptr),
+                };
+                let elem_ty = match dst_kind {
+                    Buffer::Slice(ty) | Buffer::Ptr(ty) => Some(ty),
+                    Buffer::Unknown => None,
+                };
+                if !zero && !elem_ty.map_or(false, is_byte_ty) {
+                    warn!("memset_to_fill: skipping non-zero fill of a non-byte buffer at {}",
+                          cx.session().source_map().span_to_string(e.span));
+                    continue;
+                }
+
+                let count = match (sizes.split(&mcx, cx, size), elem_ty) {
+                    (Some((n, ty)), Some(elem_ty)) if ty == elem_ty => Some(n),
+                    (_, Some(elem_ty)) if is_byte_ty(elem_ty) => Some(strip_casts(size).clone()),
+                    _ => None,
+                };
+                let repl = match (count, dst_kind) {
+                    (Some(n), Buffer::Slice(ty)) if is_int_ty(ty) => {
+                        bnd.add("$dst", dst_expr.clone());
+                        bnd.add("$n", n);
+                        if zero { &slice_zero_repl } else { &slice_repl }
+                    }
+                    (Some(n), Buffer::Ptr(_)) => {
+                        bnd.add("$dst", dst_expr.clone());
+                        bnd.add("$n", n);
+                        if zero { &ptr_zero_repl } else { &ptr_repl }
+                    }
+                    _ => {
+                        bnd.add("$dst", dst_ptr.clone());
+                        bnd.add("$size", strip_casts(size).clone());
+                        if zero { &bytes_zero_repl } else { &bytes_repl }
+                    }
+                };
+                *e = repl.clone().subst(st, cx, &bnd);
+            }
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
+/// # `simplify_size_exprs` Command
+///
+/// Usage: `simplify_size_exprs [keep_wrapping=1]`
+///
+/// Simplify the byte counts built from `size_of` and `align_of` calls, like
+/// `(::std::mem::size_of::<T>() as libc::c_ulong).wrapping_mul(n as
+/// libc::c_ulong) as usize`, which becomes `::std::mem::size_of::<T>() * n as
+/// usize`:
+///
+///  * The arithmetic is done on `usize`s, so the casts of the operands are
+///    dropped, and the other operands are cast to `usize` instead.  The result
+///    is cast back to the type the original expression had, unless that is
+///    `usize`.
+///  * `wrapping_mul` and `wrapping_add` become `*` and `+`, unless
+///    `keep_wrapping=1` is passed.  Multiplying by `1` is dropped.
+///  * `size_of` and `align_of` of `u8` and `i8` (such as `c_char`) become `1`.
+///  * `size_of_val(&*p)`, where `p` is a raw pointer to `T`, becomes
+///    `size_of::<T>()`.
+pub struct SimplifySizeExprs {
+    pub keep_wrapping: bool,
+}
+
+/// A byte count, rewritten as `usize` arithmetic.
+struct SizeExpr {
+    /// The source of the rewritten expression, which has type `usize`.
+    src: String,
+    /// The first type that a part of the byte count was cast to.
+    cast_ty: Option<P<Ty>>,
+    /// The operand that is all that is left of the byte count, once the multiplications by `1`
+    /// are dropped, with its casts stripped.
+    operand: Option<P<Expr>>,
+}
+
+/// If `e` is a call of `size_of::<T>()` or `align_of::<T>()`, return `T`.
+fn size_of_arg(e: &Expr) -> Option<&P<Ty>> {
+    let (func, args) = match_or!([e.kind] ExprKind::Call(ref f, ref a) => (f, a); return None);
+    let path = match_or!([func.kind] ExprKind::Path(None, ref p) => p; return None);
+    let seg = path.segments.last()?;
+    let name = seg.ident.as_str();
+    if !args.is_empty() || (&*name != "size_of" && &*name != "align_of") {
+        return None;
+    }
+    match **seg.args.as_ref()? {
+        GenericArgs::AngleBracketed(ref data) => match data.args[..] {
+            [GenericArg::Type(ref ty)] => Some(ty),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// If `e` is `size_of_val(&*p)` with `p` a raw pointer, return the pointee type.
+fn size_of_val_pointee<'tcx>(cx: &RefactorCtxt<'_, 'tcx>, e: &Expr) -> Option<ty::Ty<'tcx>> {
+    let (func, args) = match_or!([e.kind] ExprKind::Call(ref f, ref a) => (f, a); return None);
+    let path = match_or!([func.kind] ExprKind::Path(None, ref p) => p; return None);
+    if &*path.segments.last()?.ident.as_str() != "size_of_val" || args.len() != 1 {
+        return None;
+    }
+    let place = match_or!([args[0].kind] ExprKind::AddrOf(_, ref p) => p; return None);
+    let ptr = match_or!([place.kind] ExprKind::Unary(UnOp::Deref, ref p) => p; return None);
+    match cx.opt_node_type(ptr.id)?.kind {
+        TyKind::RawPtr(ty::TypeAndMut { ty, .. }) if ty.is_sized(cx.ty_ctxt().at(e.span),
+                                                               ty::ParamEnv::empty()) => Some(ty),
+        _ => None,
+    }
+}
+
+fn is_usize(ty: ty::Ty) -> bool {
+    match ty.kind {
+        TyKind::Uint(UintTy::Usize) => true,
+        _ => false,
+    }
+}
+
+impl SimplifySizeExprs {
+    /// If `e` is a byte count, rewrite it as `usize` arithmetic.
+    fn size_expr(&self, cx: &RefactorCtxt, e: &Expr) -> Option<SizeExpr> {
+        let no_cast = |src: String| Some(SizeExpr { src, cast_ty: None, operand: None });
+        if let Some(ty) = size_of_arg(e) {
+            let byte = cx.opt_node_type(ty.id).map_or(false, is_byte_ty);
+            return no_cast(if byte { "1".to_owned() } else { pprust::expr_to_string(e) });
+        }
+        if let Some(ty) = size_of_val_pointee(cx, e) {
+            let ty = reflect::reflect_tcx_ty(cx.ty_ctxt(), ty);
+            return no_cast(format!("::std::mem::size_of::<{}>()", pprust::ty_to_string(&ty)));
+        }
+        let (op, l, r) = match e.kind {
+            ExprKind::Paren(ref inner) => return self.size_expr(cx, inner),
+            ExprKind::Cast(ref inner, ref ty) => {
+                let inner = self.size_expr(cx, inner)?;
+                return Some(SizeExpr { cast_ty: inner.cast_ty.or_else(|| Some(ty.clone())),
+                                       ..inner });
+            }
+            ExprKind::Binary(op, ref l, ref r) => match op.node {
+                BinOpKind::Mul => ("*", l, r),
+                BinOpKind::Add => ("+", l, r),
+                _ => return None,
+            },
+            ExprKind::MethodCall(ref seg, ref args) if args.len() == 2 => {
+                match &*seg.ident.as_str() {
+                    "wrapping_mul" => ("*", &args[0], &args[1]),
+                    "wrapping_add" => ("+", &args[0], &args[1]),
+                    _ => return None,
+                }
+            }
+            _ => return None,
+        };
+
+        let (l_size, r_size) = (self.size_expr(cx, l), self.size_expr(cx, r));
+        if l_size.is_none() && r_size.is_none() {
+            return None;
+        }
+        let cast_ty = l_size.as_ref().and_then(|l| l.cast_ty.clone())
+            .or_else(|| r_size.as_ref().and_then(|r| r.cast_ty.clone()));
+        let operand = |size: Option<SizeExpr>, e: &P<Expr>| match size {
+            Some(size) => (size.src, size.operand),
+            None => {
+                let stripped = strip_casts(e);
+                let is_int = cx.opt_node_type(stripped.id).map_or(false, is_int_ty);
+                let e = if is_int { stripped } else { e };
+                (self.usize_operand(cx, e), Some(e.clone()))
+            }
+        };
+        let ((l, l_operand), (r, r_operand)) = (operand(l_size, l), operand(r_size, r));
+        if op == "*" && l == "1" {
+            return Some(SizeExpr { src: r, cast_ty, operand: r_operand });
+        } else if op == "*" && r == "1" {
+            return Some(SizeExpr { src: l, cast_ty, operand: l_operand });
+        }
+        let src = if self.keep_wrapping {
+            let method = if op == "*" { "wrapping_mul" } else { "wrapping_add" };
+            format!("({}).{}({})", l, method, r)
+        } else {
+            format!("({}) {} ({})", l, op, r)
+        };
+        Some(SizeExpr { src, cast_ty, operand: None })
+    }
+
+    /// The source of the operand `e` cast to `usize`, if it isn't one already.
+    fn usize_operand(&self, cx: &RefactorCtxt, e: &Expr) -> String {
+        let src = pprust::expr_to_string(e);
+        match e.kind {
+            ExprKind::Lit(Lit { kind: LitKind::Int(_, LitIntType::Unsuffixed), .. }) => src,
+            _ if cx.opt_node_type(e.id).map_or(false, is_usize) => src,
+            _ => format!("({}) as usize", src),
+        }
+    }
+}
+
+impl Transform for SimplifySizeExprs {
+    fn transform(&self, krate: &mut Crate, _st: &CommandState, cx: &RefactorCtxt) {
+        // (1) Find the outermost byte counts, and work out their replacements, while the types of
+        // the original expressions are still known.
+
+        let mut inner = HashSet::new();
+        let mut repls = HashMap::new();
+        visit_nodes(krate, |e: &Expr| {
+            if inner.contains(&e.id) {
+                return;
+            }
+            let size = match_or!([self.size_expr(cx, e)] Some(x) => x; return);
+            visit_nodes(e, |sub: &Expr| {
+                inner.insert(sub.id);
+            });
+
+            // Cast the result back to the type of `e`.  If all that is left is an operand of that
+            // type, it's used as it is.
+            let e_ty = cx.opt_node_type(e.id);
+            let wants_usize = e_ty.map_or(false, is_usize);
+            let src = match (size.cast_ty, size.operand) {
+                (Some(_), Some(ref operand)) if cx.opt_node_type(operand.id) == e_ty => {
+                    pprust::expr_to_string(operand)
+                }
+                (Some(ref ty), operand) if !wants_usize => {
+                    let src = operand.map_or(size.src, |operand| pprust::expr_to_string(&operand));
+                    format!("({}) as {}", src, pprust::ty_to_string(ty))
+                }
+                _ => size.src,
+            };
+            let new_e = driver::parse_expr(cx.session(), &src);
+            if pprust::expr_to_string(&new_e) != pprust::expr_to_string(e) {
+                repls.insert(e.id, new_e);
+            }
+        });
+
+        // (2) Replace them.
+
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            if let Some(new_e) = repls.remove(&e.id) {
+                *e = new_e;
+            }
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
+pub fn register_commands(reg: &mut Registry) {
+    use super::mk;
+
+    reg.register("memcpy_to_copy", |_args| mk(MemcpyToCopy));
+    reg.register("memset_to_fill", |args| mk(MemsetToFill {
+        zeroed: args.iter().any(|arg| arg == "zeroed"),
+    }));
+    reg.register("simplify_size_exprs", |args| mk(SimplifySizeExprs {
+        keep_wrapping: args.iter().any(|arg| arg == "keep_wrapping=1"),
+    }));
+}
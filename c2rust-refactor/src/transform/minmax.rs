@@ -0,0 +1,350 @@
+//! Transform that replaces C min, max and clamp conditionals with the `min`, `max` and `clamp`
+//! methods.
+
+use std::collections::HashSet;
+use rustc::ty::TyKind;
+use syntax::ast::*;
+use syntax::ptr::P;
+
+use crate::ast_manip::{AstEquiv, MutVisitNodes, visit_nodes};
+use crate::command::{CommandState, Registry};
+use crate::driver::Phase;
+use crate::matcher::{Bindings, MatchCtxt, Subst};
+use crate::transform::Transform;
+use crate::transform::canonicalize_refs::strip_parens;
+use crate::transform::enums::{is_simple_place, lit_value};
+use crate::transform::mem::strip_casts;
+use crate::RefactorCtxt;
+
+
+/// # `minmax_patterns` Command
+///
+/// Usage: `minmax_patterns [floats]`
+///
+/// Replace the conditionals that compute a minimum, maximum or clamped value
+/// with the methods that do:
+///
+///  * `if a < b { a } else { b }` becomes `a.min(b)`, and
+///    `if a < b { b } else { a }` becomes `a.max(b)`.  Any of `<`, `<=`, `>`
+///    and `>=` works the same way.
+///  * `if a < b { x = a; } else { x = b; }` becomes `x = a.min(b);`, and
+///    `if x > b { x = b; }` becomes `x = x.min(b);`.
+///  * `if x < lo { lo } else if x > hi { hi } else { x }`, with the checks in
+///    either order, becomes `x.clamp(lo, hi)`.  When `lo` and `hi` are both
+///    literals, `lo` must not be greater than `hi`, since `clamp` panics then.
+///
+/// Since each operand is repeated in the conditional, it must be a literal or
+/// a place that can be read without side effects, like `s.len` or `*p`,
+/// possibly cast.  Both operands must be integers.  Float operands are only
+/// converted with `floats`, because `f32::min` and `f32::max` return the other
+/// operand when one of them is NaN, while the conditional depends on the order
+/// of the comparison.  A conditional in the `else` of another `if` is left
+/// alone.
+pub struct MinmaxPatterns {
+    pub floats: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum MinMax {
+    Min,
+    Max,
+}
+
+/// Check if `e` is a literal, possibly negated, whose type is inferred from its context.
+fn is_lit(e: &Expr) -> bool {
+    match strip_parens(e).kind {
+        ExprKind::Lit(_) => true,
+        ExprKind::Unary(UnOp::Neg, ref inner) => is_lit(inner),
+        _ => false,
+    }
+}
+
+/// Check if `e` can be repeated without side effects: a literal, or a place like `s.len` or
+/// `*p`, possibly cast.
+fn is_trivial(e: &Expr) -> bool {
+    match e.kind {
+        ExprKind::Cast(ref inner, _) | ExprKind::Paren(ref inner) => is_trivial(inner),
+        _ => is_lit(e) || is_simple_place(e),
+    }
+}
+
+/// The value of a literal number, as in the bounds of a `clamp`.
+fn lit_num(e: &Expr) -> Option<f64> {
+    if let Some(i) = lit_value(e) {
+        return Some(i as f64);
+    }
+    match strip_casts(e).kind {
+        ExprKind::Lit(ref lit) => match lit.kind {
+            LitKind::Float(sym, _) | LitKind::FloatUnsuffixed(sym) => sym.as_str().parse().ok(),
+            _ => None,
+        },
+        ExprKind::Unary(UnOp::Neg, ref inner) => lit_num(inner).map(|x| -x),
+        ExprKind::Paren(ref inner) => lit_num(inner),
+        _ => None,
+    }
+}
+
+fn equiv(a: &Expr, b: &Expr) -> bool {
+    strip_parens(a).ast_equiv(strip_parens(b))
+}
+
+/// If `e` compares two operands, return them with the smaller one first, as in the `(a, b)` of
+/// `b > a`.
+fn ordered(e: &Expr) -> Option<(&P<Expr>, &P<Expr>)> {
+    match strip_parens(e).kind {
+        ExprKind::Binary(op, ref l, ref r) => match op.node {
+            BinOpKind::Lt | BinOpKind::Le => Some((l, r)),
+            BinOpKind::Gt | BinOpKind::Ge => Some((r, l)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// `e` without the parentheses and blocks around it, as in the `a` of `({ a })`.
+fn strip_blocks(e: &P<Expr>) -> &P<Expr> {
+    match e.kind {
+        ExprKind::Paren(ref inner) => strip_blocks(inner),
+        ExprKind::Block(ref b, None) if b.rules == BlockCheckMode::Default =>
+            block_value(b).unwrap_or(e),
+        _ => e,
+    }
+}
+
+/// If `b` consists of a single expression, return it.
+fn block_value(b: &Block) -> Option<&P<Expr>> {
+    if b.stmts.len() != 1 {
+        return None;
+    }
+    match b.stmts[0].kind {
+        StmtKind::Expr(ref e) => Some(strip_blocks(e)),
+        _ => None,
+    }
+}
+
+/// If `b` consists of a single assignment `lhs = rhs`, return `lhs` and `rhs`.
+fn block_assign(b: &Block) -> Option<(&P<Expr>, &P<Expr>)> {
+    if b.stmts.len() != 1 {
+        return None;
+    }
+    let e = match b.stmts[0].kind {
+        StmtKind::Semi(ref e) | StmtKind::Expr(ref e) => e,
+        _ => return None,
+    };
+    match e.kind {
+        ExprKind::Assign(ref lhs, ref rhs) => Some((lhs, strip_blocks(rhs))),
+        _ => None,
+    }
+}
+
+/// Which of the minimum and the maximum `if cond { t } else { f }` computes, and its operands in
+/// the order of the comparison.
+fn minmax<'a>(cond: &'a Expr, t: &Expr, f: &Expr)
+              -> Option<(MinMax, &'a P<Expr>, &'a P<Expr>)> {
+    let (small, big) = ordered(cond)?;
+    let kind = if equiv(small, t) && equiv(big, f) {
+        MinMax::Min
+    } else if equiv(big, t) && equiv(small, f) {
+        MinMax::Max
+    } else {
+        return None;
+    };
+    match strip_parens(cond).kind {
+        ExprKind::Binary(_, ref l, ref r) => Some((kind, l, r)),
+        _ => None,
+    }
+}
+
+impl Transform for MinmaxPatterns {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        let mut mcx = MatchCtxt::new(st, cx);
+        let min_repl = mcx.parse_expr("$a.min($b)");
+        let max_repl = mcx.parse_expr("$a.max($b)");
+        let clamp_repl = mcx.parse_expr("$x.clamp($lo, $hi)");
+        let assign_repl = mcx.parse_expr("$v = $e");
+
+        // Check that the operands can be repeated, and have a type the methods agree with the
+        // conditional on.
+        let operands_ok = |ops: &[&P<Expr>]| -> bool {
+            if !ops.iter().all(|op| is_trivial(op)) {
+                return false;
+            }
+            let ty = ops.iter().filter_map(|op| cx.opt_node_type(op.id)).next();
+            match ty.map(|ty| &ty.kind) {
+                Some(&TyKind::Int(_)) | Some(&TyKind::Uint(_)) => true,
+                Some(&TyKind::Float(_)) => {
+                    if !self.floats {
+                        warn!("minmax_patterns: skipping float conditional at {}; pass \
+                               `floats` to convert it",
+                              cx.session().source_map().span_to_string(ops[0].span));
+                    }
+                    self.floats
+                }
+                _ => false,
+            }
+        };
+
+        // `a.min(b)` or `a.max(b)`, with a receiver whose type isn't left to inference.
+        let minmax_call = |kind: MinMax, a: &P<Expr>, b: &P<Expr>| -> Option<P<Expr>> {
+            let (a, b) = match (is_lit(a), is_lit(b)) {
+                (true, true) => return None,
+                (true, false) => (b, a),
+                _ => (a, b),
+            };
+            let mut bnd = Bindings::new();
+            bnd.add("$a", a.clone());
+            bnd.add("$b", b.clone());
+            let repl = match kind {
+                MinMax::Min => &min_repl,
+                MinMax::Max => &max_repl,
+            };
+            Some(repl.clone().subst(st, cx, &bnd))
+        };
+
+        // An `if` in the `else` of another one must stay an `if`.
+        let mut else_ifs = HashSet::new();
+        visit_nodes(krate, |e: &Expr| {
+            if let ExprKind::If(_, _, Some(ref els)) = e.kind {
+                if let ExprKind::If(..) = els.kind {
+                    else_ifs.insert(els.id);
+                }
+            }
+        });
+
+        // (1) Rewrite the clamps, before their inner conditionals become `min` or `max`.
+
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            if else_ifs.contains(&e.id) {
+                return;
+            }
+            let (c1, then1, els1) = match_or!([e.kind]
+                                              ExprKind::If(ref c, ref t, Some(ref f)) => (c, t, f);
+                                              return);
+            let (c2, then2, els2) = match_or!([strip_blocks(els1).kind]
+                                              ExprKind::If(ref c, ref t, Some(ref f)) => (c, t, f);
+                                              return);
+            let (t1, t2) = match (block_value(then1), block_value(then2)) {
+                (Some(t1), Some(t2)) => (t1, t2),
+                _ => return,
+            };
+            let x = strip_blocks(els2);
+
+            // Whether `if c { t } else { ... x }` clamps `x` to the lower bound `t`, or the
+            // upper one.
+            let bound = |c: &Expr, t: &Expr| -> Option<bool> {
+                let (small, big) = ordered(c)?;
+                if equiv(small, x) && equiv(big, t) {
+                    Some(true)
+                } else if equiv(big, x) && equiv(small, t) {
+                    Some(false)
+                } else {
+                    None
+                }
+            };
+            let (lo, hi) = match (bound(c1, t1), bound(c2, t2)) {
+                (Some(true), Some(false)) => (t1, t2),
+                (Some(false), Some(true)) => (t2, t1),
+                _ => return,
+            };
+            if is_lit(x) || !operands_ok(&[x, lo, hi]) {
+                return;
+            }
+            if let (Some(lo_val), Some(hi_val)) = (lit_num(lo), lit_num(hi)) {
+                if lo_val > hi_val {
+                    warn!("minmax_patterns: skipping clamp with empty range at {}",
+                          cx.session().source_map().span_to_string(e.span));
+                    return;
+                }
+            }
+
+            let mut bnd = Bindings::new();
+            bnd.add("$x", x.clone());
+            bnd.add("$lo", lo.clone());
+            bnd.add("$hi", hi.clone());
+            *e = clamp_repl.clone().subst(st, cx, &bnd);
+        });
+
+        // (2) Rewrite the conditional assignments.
+
+        MutVisitNodes::visit(krate, |b: &mut P<Block>| {
+            for s in &mut b.stmts {
+                let e = match s.kind {
+                    StmtKind::Semi(ref e) | StmtKind::Expr(ref e) => e,
+                    _ => continue,
+                };
+                let (cond, then, els) = match_or!([e.kind]
+                                                  ExprKind::If(ref c, ref t, ref f) => (c, t, f);
+                                                  continue);
+                let (v, value) = match *els {
+                    // `v` is still assigned once, so it doesn't need to be trivial.
+                    Some(ref els) => {
+                        let els = match_or!([els.kind] ExprKind::Block(ref b, None) => b;
+                                            continue);
+                        let ((v, t), (v2, f)) = match (block_assign(then), block_assign(els)) {
+                            (Some(x), Some(y)) => (x, y),
+                            _ => continue,
+                        };
+                        let (kind, a, b) = match_or!([minmax(cond, t, f)] Some(x) => x; continue);
+                        if !equiv(v, v2) || !operands_ok(&[a, b]) {
+                            continue;
+                        }
+                        (v, match_or!([minmax_call(kind, a, b)] Some(x) => x; continue))
+                    }
+                    None => {
+                        let (v, r) = match_or!([block_assign(then)] Some(x) => x; continue);
+                        let (small, big) = match_or!([ordered(cond)] Some(x) => x; continue);
+                        let kind = if equiv(big, v) && equiv(small, r) {
+                            MinMax::Min
+                        } else if equiv(small, v) && equiv(big, r) {
+                            MinMax::Max
+                        } else {
+                            continue;
+                        };
+                        if !operands_ok(&[v, r]) {
+                            continue;
+                        }
+                        (v, match_or!([minmax_call(kind, v, r)] Some(x) => x; continue))
+                    }
+                };
+
+                let mut bnd = Bindings::new();
+                bnd.add("$v", v.clone());
+                bnd.add("$e", value);
+                s.kind = StmtKind::Semi(assign_repl.clone().subst(st, cx, &bnd));
+            }
+        });
+
+        // (3) Rewrite the conditional expressions.
+
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            if else_ifs.contains(&e.id) {
+                return;
+            }
+            let (cond, then, els) = match_or!([e.kind]
+                                              ExprKind::If(ref c, ref t, Some(ref f)) => (c, t, f);
+                                              return);
+            let t = match_or!([block_value(then)] Some(x) => x; return);
+            let (kind, a, b) = match_or!([minmax(cond, t, strip_blocks(els))] Some(x) => x;
+                                         return);
+            if !operands_ok(&[a, b]) {
+                return;
+            }
+            if let Some(new_e) = minmax_call(kind, a, b) {
+                *e = new_e;
+            }
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
+pub fn register_commands(reg: &mut Registry) {
+    use super::mk;
+
+    reg.register("minmax_patterns", |args| mk(MinmaxPatterns {
+        floats: args.iter().any(|arg| arg == "floats"),
+    }));
+}
@@ -49,25 +49,49 @@ macro_rules! transform_modules {
 }
 
 transform_modules! {
+    alloc,
+    assert,
+    bools,
+    bitfields,
     canonicalize_refs,
     casts,
     char_literals,
     control_flow,
+    ctype,
+    enums,
+    env,
+    errno,
     externs,
     format,
     funcs,
     generics,
+    getopt,
     ionize,
     items,
+    libc_types,
     lifetime_analysis,
     linkage,
     literals,
+    macros,
+    mem,
+    minmax,
+    null_ptrs,
+    ptr_loops,
+    random,
+    relooper,
     reorganize_definitions,
     ownership,
     retype,
     rewrite,
+    setjmp,
+    sort,
     statics,
+    stdio,
+    strings,
     structs,
+    sync,
     test,
+    time,
     vars,
+    wrappers,
 }
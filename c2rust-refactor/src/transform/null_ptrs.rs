@@ -0,0 +1,1155 @@
+//! Transforms that normalize null pointers, and turn nullable raw pointers into `Option`s of
+//! references or into `NonNull`s.
+
+use std::collections::{HashMap, HashSet};
+
+use rustc::hir::HirId;
+use rustc::hir::def_id::DefId;
+use rustc::ty;
+use syntax::ast::*;
+use syntax::mut_visit::{self, MutVisitor};
+use syntax::print::pprust;
+use syntax::ptr::P;
+use syntax::symbol::Symbol;
+use syntax::visit::{self, Visitor};
+use syntax_pos::Span;
+use smallvec::smallvec;
+
+use crate::ast_manip::{FlatMapNodes, MutVisit, MutVisitNodes, visit_nodes};
+use crate::ast_manip::fn_edit::mut_visit_fns;
+use crate::ast_manip::lr_expr::{self, fold_exprs_with_context};
+use crate::command::{CommandState, Registry};
+use crate::driver::{self, Phase};
+use crate::matcher::{Bindings, MatchCtxt, Subst};
+use crate::transform::Transform;
+use crate::transform::canonicalize_refs::strip_parens;
+use crate::transform::mem::{is_zero_lit, strip_casts};
+use crate::RefactorCtxt;
+
+
+/// # `option_null_checks` Command
+///
+/// Usage: `option_null_checks`
+///
+/// Marks: `target`
+///
+/// For each local, struct field, or function argument marked `target` whose
+/// type is a raw pointer, change its type from `*const T` to `Option<&T>` (or
+/// from `*mut T` to `Option<&mut T>`), and rewrite its uses to match:
+///
+///  * `p.is_null()` becomes `p.is_none()`, and `!p.is_null()` becomes
+///    `p.is_some()`;
+///  * `if !p.is_null() { ... }`, for a local or argument `p` that is not
+///    reassigned inside the `if`, becomes `if let Some(p) = p { ... }`, and
+///    dereferences of `p` inside it are left as they are;
+///  * other dereferences `*p` become `*p.unwrap()`;
+///  * null pointers (`ptr::null()`, `ptr::null_mut()`, `0 as *mut T`) assigned
+///    to `p`, used as its initializer, or passed as a retyped argument become
+///    `None`, and `&x` / `&mut x` become `Some(&x)` / `Some(&mut x)`.  Any other
+///    raw pointer `q` is converted with `unsafe { q.as_ref() }`;
+///  * any remaining use, such as passing `p` to an `extern` function, converts
+///    it back with `p.map_or(ptr::null(), |r| r as *const T)`.
+///
+/// Retyped fields use the lifetime parameter of their struct, which must have
+/// exactly one; fields of other structs are skipped with a warning.
+///
+/// For `*mut T` pointers, `.as_deref_mut()` is inserted before `.unwrap()` and
+/// `.map_or(..)` and in `if let` scrutinees, and the binding of a retyped local
+/// or argument is made mutable.
+pub struct OptionNullChecks;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub(super) enum Nullable {
+    /// A local variable or function argument.
+    Local(HirId),
+    /// A field of the struct with the given `DefId`.
+    Field(DefId, Symbol),
+}
+
+/// Expression templates for converting between a nullable pointer and its `Option`.  Each
+/// template uses `$p` for the expression being converted, and `$t` for the pointee type.
+struct Conv {
+    unwrap: P<Expr>,
+    to_raw: P<Expr>,
+    bound_to_raw: P<Expr>,
+    from_raw: P<Expr>,
+    /// Appended to the name of a local when building the scrutinee of an `if let`.
+    scrutinee_suffix: &'static str,
+}
+
+impl Conv {
+    fn new(mcx: &mut MatchCtxt, mutbl: Mutability) -> Conv {
+        match mutbl {
+            Mutability::Immutable => Conv {
+                unwrap: mcx.parse_expr("$p.unwrap()"),
+                to_raw: mcx.parse_expr("$p.map_or(::std::ptr::null(), |r| r as *const $t)"),
+                bound_to_raw: mcx.parse_expr("$p as *const $t"),
+                from_raw: mcx.parse_expr("unsafe { $p.as_ref() }"),
+                scrutinee_suffix: "",
+            },
+            Mutability::Mutable => Conv {
+                unwrap: mcx.parse_expr("$p.as_deref_mut().unwrap()"),
+                to_raw: mcx.parse_expr(
+                    "$p.as_deref_mut().map_or(::std::ptr::null_mut(), |r| r as *mut $t)"),
+                bound_to_raw: mcx.parse_expr("$p as *mut $t"),
+                from_raw: mcx.parse_expr("unsafe { $p.as_mut() }"),
+                scrutinee_suffix: ".as_deref_mut()",
+            },
+        }
+    }
+}
+
+/// Build the `Option` type replacing the raw pointer type `ty`, using `lifetime` for the reference
+/// if it's provided.
+fn option_ty(cx: &RefactorCtxt, ty: &Ty, lifetime: Option<Ident>)
+             -> Option<(P<Ty>, Mutability, P<Ty>)> {
+    let (pointee, mutbl) = match ty.kind {
+        TyKind::Ptr(MutTy { ref ty, mutbl }) => (ty, mutbl),
+        _ => return None,
+    };
+    let lifetime = lifetime.map_or(String::new(), |lt| format!("{} ", lt));
+    let prefix = match mutbl {
+        Mutability::Immutable => "",
+        Mutability::Mutable => "mut ",
+    };
+    let src = format!("Option<&{}{}{}>", lifetime, prefix, pprust::ty_to_string(pointee));
+    Some((driver::parse_ty(cx.session(), &src), mutbl, pointee.clone()))
+}
+
+/// Make an identifier binding mutable, so `.as_deref_mut()` can borrow it.
+fn make_binding_mut(pat: &mut Pat) {
+    if let PatKind::Ident(BindingMode::ByValue(ref mut mutbl), _, _) = pat.kind {
+        *mutbl = Mutability::Mutable;
+    }
+}
+
+/// Check if `e` is a null pointer constant: `ptr::null()`, `ptr::null_mut()`, or a cast of `0`.
+pub(super) fn is_null_ptr(e: &P<Expr>) -> bool {
+    let e = strip_casts(e);
+    match e.kind {
+        ExprKind::Call(ref func, ref args) if args.is_empty() => match func.kind {
+            ExprKind::Path(None, ref path) => path.segments.last().map_or(false, |seg| {
+                let name = seg.ident.as_str();
+                &*name == "null" || &*name == "null_mut"
+            }),
+            _ => false,
+        },
+        _ => is_zero_lit(e),
+    }
+}
+
+/// If `e` is `p.is_null()` or `!p.is_null()`, return `p` and whether the check was negated.
+pub(super) fn null_check(e: &Expr) -> Option<(&P<Expr>, bool)> {
+    match e.kind {
+        ExprKind::MethodCall(ref seg, ref args)
+                if &*seg.ident.as_str() == "is_null" && args.len() == 1 =>
+            Some((&args[0], false)),
+        ExprKind::Unary(UnOp::Not, ref inner) =>
+            null_check(inner).and_then(|(p, negated)| if negated { None } else { Some((p, true)) }),
+        _ => None,
+    }
+}
+
+/// The local, argument, or field that `e` refers to, if any.
+pub(super) fn nullable_key(cx: &RefactorCtxt, e: &Expr) -> Option<Nullable> {
+    match e.kind {
+        ExprKind::Paren(ref inner) => nullable_key(cx, inner),
+        ExprKind::Path(None, _) => Some(Nullable::Local(cx.try_resolve_expr_to_hid(e)?)),
+        ExprKind::Field(ref base, ident) => {
+            let mut ty = cx.opt_node_type(base.id)?;
+            while let ty::Ref(_, inner, _) = ty.kind {
+                ty = inner;
+            }
+            match ty.kind {
+                ty::Adt(def, _) => Some(Nullable::Field(def.did, ident.name)),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Check if `block` assigns to the local `hir_id`.
+fn assigns_to(cx: &RefactorCtxt, block: &Block, hir_id: HirId) -> bool {
+    let mut found = false;
+    visit_nodes(block, |e: &Expr| match e.kind {
+        ExprKind::Assign(ref lhs, _) | ExprKind::AssignOp(_, ref lhs, _) => {
+            found |= cx.try_resolve_expr_to_hid(lhs) == Some(hir_id);
+        }
+        _ => {}
+    });
+    found
+}
+
+/// Build the condition `let $pat = $scrutinee` of an `if let`.
+fn if_let_cond(cx: &RefactorCtxt, pat: &str, scrutinee: &str) -> P<Expr> {
+    let src = format!("if let {} = {} {{}}", pat, scrutinee);
+    match driver::parse_expr(cx.session(), &src).into_inner().kind {
+        ExprKind::If(cond, _, _) => cond,
+        _ => unreachable!(),
+    }
+}
+
+struct NullableFolder<'a, 'tcx: 'a> {
+    st: &'a CommandState,
+    cx: &'a RefactorCtxt<'a, 'tcx>,
+    nullables: HashMap<Nullable, Mutability>,
+    pointees: HashMap<Nullable, P<Ty>>,
+    /// Retyped argument positions of each function.
+    fn_args: HashMap<DefId, Vec<(usize, Mutability)>>,
+    /// Locals currently rebound to a reference by an enclosing `if let Some(p) = p`.
+    bound: HashSet<HirId>,
+    imm: Conv,
+    mutbl: Conv,
+    is_none: P<Expr>,
+    is_some: P<Expr>,
+    some: P<Expr>,
+    none: P<Expr>,
+}
+
+impl<'a, 'tcx> NullableFolder<'a, 'tcx> {
+    fn conv(&self, mutbl: Mutability) -> &Conv {
+        match mutbl {
+            Mutability::Immutable => &self.imm,
+            Mutability::Mutable => &self.mutbl,
+        }
+    }
+
+    fn subst(&self, tmpl: &P<Expr>, p: P<Expr>) -> P<Expr> {
+        let mut bnd = Bindings::new();
+        bnd.add("$p", p);
+        tmpl.clone().subst(self.st, self.cx, &bnd)
+    }
+
+    /// If `e` refers to a retyped local, argument, or field, return it and its mutability.
+    fn nullable(&self, e: &Expr) -> Option<(Nullable, Mutability)> {
+        let n = nullable_key(self.cx, e)?;
+        self.nullables.get(&n).map(|&mutbl| (n, mutbl))
+    }
+
+    fn is_bound(&self, n: Nullable) -> bool {
+        match n {
+            Nullable::Local(hir_id) => self.bound.contains(&hir_id),
+            Nullable::Field(..) => false,
+        }
+    }
+
+    /// Visit the subexpressions of a reference to a nullable, without rewriting the reference
+    /// itself.
+    fn visit_nullable(&mut self, e: &mut P<Expr>) {
+        match e.kind {
+            ExprKind::Paren(ref mut inner) => self.visit_nullable(inner),
+            ExprKind::Field(ref mut base, _) => self.visit_expr(base),
+            _ => {}
+        }
+    }
+
+    /// Rewrite `e`, a raw pointer assigned to a nullable, into an `Option`.
+    fn to_option(&mut self, e: &mut P<Expr>, mutbl: Mutability) {
+        if is_null_ptr(e) {
+            *e = self.none.clone();
+            return;
+        }
+        if let Some((n, _)) = self.nullable(e) {
+            self.visit_nullable(e);
+            if self.is_bound(n) {
+                *e = self.subst(&self.some, e.clone());
+            }
+            return;
+        }
+        match strip_casts(e).kind {
+            ExprKind::AddrOf(_, m, _) if m == mutbl => {
+                let mut inner = strip_casts(e).clone();
+                self.visit_expr(&mut inner);
+                *e = self.subst(&self.some, inner);
+            }
+            _ => {
+                self.visit_expr(e);
+                *e = self.subst(&self.conv(mutbl).from_raw, e.clone());
+            }
+        }
+    }
+
+    /// Rewrite `if !p.is_null() { ... }` into `if let Some(p) = p { ... }`.  Returns `false` if
+    /// `e` is not such an `if`.
+    fn rewrite_if_non_null(&mut self, e: &mut P<Expr>) -> bool {
+        let (cond, then, els) = match e.kind {
+            ExprKind::If(ref mut cond, ref mut then, ref mut els) => (cond, then, els),
+            _ => return false,
+        };
+        let (hir_id, mutbl, ident) = match null_check(cond) {
+            Some((p, true)) => match (self.nullable(p), &p.kind) {
+                (Some((Nullable::Local(hir_id), mutbl)), &ExprKind::Path(None, ref path))
+                        if path.segments.len() == 1 && !self.bound.contains(&hir_id) =>
+                    (hir_id, mutbl, path.segments[0].ident),
+                _ => return false,
+            },
+            _ => return false,
+        };
+        if assigns_to(self.cx, then, hir_id) {
+            return false;
+        }
+
+        let scrutinee = format!("{}{}", ident, self.conv(mutbl).scrutinee_suffix);
+        *cond = if_let_cond(self.cx, &format!("Some({})", ident), &scrutinee);
+
+        self.bound.insert(hir_id);
+        self.visit_block(then);
+        self.bound.remove(&hir_id);
+        if let Some(els) = els {
+            self.visit_expr(els);
+        }
+        true
+    }
+}
+
+impl<'a, 'tcx> MutVisitor for NullableFolder<'a, 'tcx> {
+    fn visit_expr(&mut self, e: &mut P<Expr>) {
+        if self.rewrite_if_non_null(e) {
+            return;
+        }
+
+        if let Some((p, negated)) = null_check(e) {
+            if let Some((n, _)) = self.nullable(p) {
+                if !self.is_bound(n) {
+                    let mut p = p.clone();
+                    self.visit_nullable(&mut p);
+                    let tmpl = if negated { &self.is_some } else { &self.is_none };
+                    *e = self.subst(tmpl, p);
+                    return;
+                }
+            }
+        }
+
+        if let Some((n, mutbl)) = self.nullable(e) {
+            self.visit_nullable(e);
+            let tmpl = if self.is_bound(n) {
+                &self.conv(mutbl).bound_to_raw
+            } else {
+                &self.conv(mutbl).to_raw
+            };
+            let mut bnd = Bindings::new();
+            bnd.add("$p", e.clone());
+            bnd.add("$t", self.pointees[&n].clone());
+            *e = tmpl.clone().subst(self.st, self.cx, &bnd);
+            return;
+        }
+
+        let retyped = match e.kind {
+            ExprKind::Call(..) | ExprKind::MethodCall(..) => self.cx.opt_callee(e)
+                .and_then(|def_id| self.fn_args.get(&def_id))
+                .cloned(),
+            _ => None,
+        };
+        if let Some(retyped) = retyped {
+            let args = match e.kind {
+                ExprKind::Call(ref mut func, ref mut args) => {
+                    self.visit_expr(func);
+                    args
+                }
+                ExprKind::MethodCall(_, ref mut args) => args,
+                _ => unreachable!(),
+            };
+            for (i, arg) in args.iter_mut().enumerate() {
+                match retyped.iter().find(|&&(idx, _)| idx == i) {
+                    Some(&(_, mutbl)) => self.to_option(arg, mutbl),
+                    None => self.visit_expr(arg),
+                }
+            }
+            return;
+        }
+
+        let ty_kind = self.cx.opt_node_type(e.id).map(|ty| &ty.kind);
+        match e.kind {
+            ExprKind::Unary(UnOp::Deref, ref mut p) => {
+                if let Some((n, mutbl)) = self.nullable(p) {
+                    self.visit_nullable(p);
+                    if !self.is_bound(n) {
+                        *p = self.subst(&self.conv(mutbl).unwrap, p.clone());
+                    }
+                    return;
+                }
+            }
+
+            ExprKind::Assign(ref mut lhs, ref mut rhs) => {
+                if let Some((_, mutbl)) = self.nullable(lhs) {
+                    self.visit_nullable(lhs);
+                    self.to_option(rhs, mutbl);
+                    return;
+                }
+            }
+
+            ExprKind::Struct(_, ref mut fields, ref mut base) => {
+                let did = match ty_kind {
+                    Some(&ty::Adt(def, _)) => def.did,
+                    _ => return mut_visit::noop_visit_expr(e, self),
+                };
+                for field in fields.iter_mut() {
+                    match self.nullables.get(&Nullable::Field(did, field.ident.name)) {
+                        Some(&mutbl) => self.to_option(&mut field.expr, mutbl),
+                        None => self.visit_expr(&mut field.expr),
+                    }
+                }
+                if let Some(base) = base {
+                    self.visit_expr(base);
+                }
+                return;
+            }
+
+            _ => {}
+        }
+        mut_visit::noop_visit_expr(e, self)
+    }
+
+    fn visit_local(&mut self, l: &mut P<Local>) {
+        let hir_id = self.cx.hir_map().node_to_hir_id(l.pat.id);
+        if let Some(&mutbl) = self.nullables.get(&Nullable::Local(hir_id)) {
+            if let Some(ref mut init) = l.init {
+                self.to_option(init, mutbl);
+            }
+            return;
+        }
+        mut_visit::noop_visit_local(l, self)
+    }
+
+    fn visit_mac(&mut self, mac: &mut Mac) {
+        mut_visit::noop_visit_mac(mac, self)
+    }
+}
+
+impl Transform for OptionNullChecks {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        let mut nullables = HashMap::new();
+        let mut pointees = HashMap::new();
+        let mut fn_args: HashMap<DefId, Vec<(usize, Mutability)>> = HashMap::new();
+
+        // (1) Retype the marked declarations.
+
+        mut_visit_fns(krate, |fl| {
+            for (i, arg) in fl.decl.inputs.iter_mut().enumerate() {
+                if !st.marked(arg.id, "target") {
+                    continue;
+                }
+                let (new_ty, mutbl, pointee) = match option_ty(cx, &arg.ty, None) {
+                    Some(x) => x,
+                    None => continue,
+                };
+                arg.ty = new_ty;
+                if mutbl == Mutability::Mutable {
+                    make_binding_mut(&mut arg.pat);
+                }
+                let hir_id = cx.hir_map().node_to_hir_id(arg.pat.id);
+                nullables.insert(Nullable::Local(hir_id), mutbl);
+                pointees.insert(Nullable::Local(hir_id), pointee);
+                fn_args.entry(cx.node_def_id(fl.id)).or_insert_with(Vec::new).push((i, mutbl));
+            }
+        });
+
+        MutVisitNodes::visit(krate, |l: &mut P<Local>| {
+            if !st.marked(l.pat.id, "target") {
+                return;
+            }
+            let (new_ty, mutbl, pointee) = match l.ty {
+                Some(ref ty) => match option_ty(cx, ty, None) {
+                    Some(x) => x,
+                    None => return,
+                },
+                None => return,
+            };
+            l.ty = Some(new_ty);
+            if mutbl == Mutability::Mutable {
+                make_binding_mut(&mut l.pat);
+            }
+            let hir_id = cx.hir_map().node_to_hir_id(l.pat.id);
+            nullables.insert(Nullable::Local(hir_id), mutbl);
+            pointees.insert(Nullable::Local(hir_id), pointee);
+        });
+
+        FlatMapNodes::visit(krate, |mut i: P<Item>| {
+            let (item_id, item_ident) = (i.id, i.ident);
+            let (fields, generics) = match i.kind {
+                ItemKind::Struct(VariantData::Struct(ref mut fields, _), ref generics) =>
+                    (fields, generics),
+                _ => return smallvec![i],
+            };
+            let mut lifetimes = generics.params.iter().filter_map(|param| match param.kind {
+                GenericParamKind::Lifetime => Some(param.ident),
+                _ => None,
+            });
+            let lifetime = match (lifetimes.next(), lifetimes.next()) {
+                (Some(lt), None) => Some(lt),
+                _ => None,
+            };
+            for field in fields.iter_mut() {
+                if !st.marked(field.id, "target") {
+                    continue;
+                }
+                if lifetime.is_none() {
+                    warn!("option_null_checks: struct `{}` needs exactly one lifetime parameter \
+                           for its fields to hold references; skipping", item_ident);
+                    continue;
+                }
+                let name = match field.ident {
+                    Some(ident) => ident.name,
+                    None => continue,
+                };
+                let (new_ty, mutbl, pointee) = match option_ty(cx, &field.ty, lifetime) {
+                    Some(x) => x,
+                    None => continue,
+                };
+                field.ty = new_ty;
+                let struct_did = cx.node_def_id(item_id);
+                nullables.insert(Nullable::Field(struct_did, name), mutbl);
+                pointees.insert(Nullable::Field(struct_did, name), pointee);
+            }
+            smallvec![i]
+        });
+
+        if nullables.is_empty() {
+            return;
+        }
+
+        // (2) Rewrite uses of the retyped declarations.
+
+        let mut mcx = MatchCtxt::new(st, cx);
+        krate.visit(&mut NullableFolder {
+            st,
+            cx,
+            nullables,
+            pointees,
+            fn_args,
+            bound: HashSet::new(),
+            imm: Conv::new(&mut mcx, Mutability::Immutable),
+            mutbl: Conv::new(&mut mcx, Mutability::Mutable),
+            is_none: mcx.parse_expr("$p.is_none()"),
+            is_some: mcx.parse_expr("$p.is_some()"),
+            some: mcx.parse_expr("Some($p)"),
+            none: mcx.parse_expr("None"),
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
+/// # `normalize_null` Command
+///
+/// Usage: `normalize_null`
+///
+/// Rewrite the spellings of null pointer constants found in transpiled code
+/// into `::std::ptr::null()` or `::std::ptr::null_mut()`, following the pointer
+/// type the expression has (after coercions) in the type-checked crate:
+///
+///  * `0 as *mut T`, including chains of casts like `0 as *mut c_void as *mut T`;
+///  * `::std::ptr::null::<T>()` and `::std::ptr::null_mut::<T>()`;
+///  * `::std::mem::transmute(0)`, when it produces a pointer;
+///  * `NULL` and `NULL as *mut T`, where `NULL` is a `const` holding a null
+///    pointer or `0`.  Such a `const` is deleted once no path names it.
+///
+/// The type argument is kept, taken from the cast or from the original call, in
+/// the initializer of a `let` without a type and in method call receivers,
+/// where nothing else determines the pointee type.  A null in either of those
+/// places that doesn't spell out its type is left alone.
+///
+/// Comparisons of a pointer `p` against any of these, like `p == 0 as *mut T`,
+/// become `p.is_null()`, and `!p.is_null()` for `!=`.
+///
+/// Casts of any other integer to a pointer are left alone.
+pub struct NormalizeNull;
+
+/// Check if `e` is a null pointer constant: any of the spellings accepted by
+/// `is_null_ptr`, `transmute(0)`, or one of the `NULL` constants `null_consts`.
+fn is_null_spelling(cx: &RefactorCtxt, null_consts: &HashSet<DefId>, e: &P<Expr>) -> bool {
+    if is_null_ptr(e) {
+        return true;
+    }
+    let e = strip_casts(e);
+    match e.kind {
+        ExprKind::Path(..) =>
+            cx.try_resolve_expr(e).map_or(false, |def_id| null_consts.contains(&def_id)),
+        ExprKind::Call(_, ref args) if args.len() == 1 && is_zero_lit(strip_casts(&args[0])) =>
+            cx.opt_callee(e).map_or(false, |def_id| {
+                !def_id.is_local() && &*cx.ty_ctxt().item_name(def_id).as_str() == "transmute"
+            }),
+        _ => false,
+    }
+}
+
+/// The pointee type spelled out in the null pointer constant `e`, either as the
+/// target of a cast or as the type argument of a call.
+fn null_pointee(e: &Expr) -> Option<P<Ty>> {
+    match e.kind {
+        ExprKind::Cast(_, ref ty) => match ty.kind {
+            TyKind::Ptr(MutTy { ref ty, .. }) => Some(ty.clone()),
+            _ => None,
+        },
+        ExprKind::Call(ref func, _) => {
+            let path = match_or!([func.kind] ExprKind::Path(None, ref p) => p; return None);
+            let args = path.segments.last()?.args.as_ref()?;
+            match **args {
+                GenericArgs::AngleBracketed(ref data) => data.args.iter().find_map(|arg| match arg {
+                    GenericArg::Type(ty) => Some(ty.clone()),
+                    _ => None,
+                }),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+impl Transform for NormalizeNull {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        // (1) Find the `NULL` constants, and the places that need the pointee type.
+
+        let mut null_consts = HashSet::new();
+        visit_nodes(krate, |i: &Item| {
+            if let ItemKind::Const(ref ty, ref init) = i.kind {
+                let is_ptr = match ty.kind {
+                    TyKind::Ptr(_) => true,
+                    _ => false,
+                };
+                if (is_ptr || &*i.ident.as_str() == "NULL") && is_null_ptr(init) {
+                    null_consts.insert(cx.node_def_id(i.id));
+                }
+            }
+        });
+
+        let mut needs_pointee = HashSet::new();
+        visit_nodes(krate, |l: &Local| {
+            if let (None, Some(init)) = (&l.ty, &l.init) {
+                needs_pointee.insert(init.id);
+            }
+        });
+        visit_nodes(krate, |e: &Expr| {
+            if let ExprKind::MethodCall(_, ref args) = e.kind {
+                needs_pointee.insert(args[0].id);
+            }
+        });
+
+        // (2) Rewrite the null pointers, and the comparisons against them.  The
+        // visit is bottom-up, so the operands of a comparison are already
+        // rewritten when it's reached.
+
+        let mut mcx = MatchCtxt::new(st, cx);
+        let is_null_repl = mcx.parse_expr("$p.is_null()");
+        let not_null_repl = mcx.parse_expr("!$p.is_null()");
+
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            if let ExprKind::Binary(op, ref l, ref r) = e.kind {
+                let repl = match op.node {
+                    BinOpKind::Eq => &is_null_repl,
+                    BinOpKind::Ne => &not_null_repl,
+                    _ => return,
+                };
+                let is_ptr = |e: &Expr| match cx.opt_node_type(e.id) {
+                    Some(ty) => match ty.kind {
+                        ty::TyKind::RawPtr(_) => true,
+                        _ => false,
+                    },
+                    None => false,
+                };
+                let p = if is_null_spelling(cx, &null_consts, r) && is_ptr(l) {
+                    l
+                } else if is_null_spelling(cx, &null_consts, l) && is_ptr(r) {
+                    r
+                } else {
+                    return;
+                };
+                let mut bnd = Bindings::new();
+                bnd.add("$p", p.clone());
+                *e = repl.clone().subst(st, cx, &bnd);
+                return;
+            }
+
+            let mutbl = match cx.opt_adjusted_node_type(e.id).map(|ty| &ty.kind) {
+                Some(&ty::TyKind::RawPtr(ty::TypeAndMut { mutbl, .. })) => mutbl,
+                _ => return,
+            };
+            if !is_null_spelling(cx, &null_consts, e) {
+                return;
+            }
+            let func = match mutbl {
+                Mutability::Immutable => "null",
+                Mutability::Mutable => "null_mut",
+            };
+            let ty_args = if needs_pointee.contains(&e.id) {
+                let pointee = match null_pointee(e) {
+                    Some(x) => x,
+                    None => return,
+                };
+                format!("::<{}>", pprust::ty_to_string(&pointee))
+            } else {
+                String::new()
+            };
+            let src = format!("::std::ptr::{}{}()", func, ty_args);
+            if pprust::expr_to_string(e) != src {
+                *e = driver::parse_expr(cx.session(), &src);
+            }
+        });
+
+        // (3) Delete the `NULL` constants that are no longer used.  Uses are
+        // found by name, which also catches `use` items and patterns naming them.
+
+        let mut used = HashSet::new();
+        visit_nodes(krate, |p: &Path| {
+            if let Some(seg) = p.segments.last() {
+                used.insert(seg.ident.name);
+            }
+        });
+
+        FlatMapNodes::visit(krate, |i: P<Item>| {
+            if let ItemKind::Const(..) = i.kind {
+                let def_id = cx.node_def_id(i.id);
+                if null_consts.contains(&def_id) && !used.contains(&i.ident.name) {
+                    return smallvec![];
+                }
+            }
+            smallvec![i]
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
+/// # `ptr_to_nonnull` Command
+///
+/// Usage: `ptr_to_nonnull [extern=unwrap]`
+///
+/// Marks: `target`
+///
+/// For each local or struct field marked `target` whose declared type is
+/// `*mut T`, change its type to `::std::ptr::NonNull<T>`, if every value
+/// assigned to it anywhere in the crate is known to be non-null:
+///
+///  * `Box::into_raw(b)` or `&mut x`, possibly cast, which becomes
+///    `unsafe { ::std::ptr::NonNull::new_unchecked(..) }`;
+///  * another converted local or field, which is copied as it is;
+///  * a local `q` inside `if !q.is_null() { ... }` that is not reassigned
+///    there.  The check becomes
+///    `if let Some(q_nonnull) = ::std::ptr::NonNull::new(q) { ... }`, and the
+///    assignment uses `q_nonnull`.
+///
+/// With `extern=unwrap`, the result of a call to an `extern` function is also
+/// accepted, and is converted with `::std::ptr::NonNull::new(..).unwrap()`, so
+/// a null result panics.
+///
+/// A local or field that is assigned anything else, such as a null pointer, or
+/// whose address is taken, is left alone with a warning naming the offending
+/// expression, and so is one assigned from another candidate that is left
+/// alone.
+///
+/// Uses of a converted local or field `p` are rewritten to match:
+/// `p.is_null()` becomes `false` and `!p.is_null()` becomes `true`, an `if`
+/// testing one of those is replaced by the branch that runs, and other reads
+/// of `p`, including those under `*p`, become `p.as_ptr()`.
+///
+/// Run `normalize_null` first, so comparisons against null are written as
+/// `is_null` calls.
+pub struct PtrToNonNull {
+    pub unwrap_extern: bool,
+}
+
+/// Where a value assigned to a `ptr_to_nonnull` candidate comes from.
+#[derive(Clone, Copy)]
+enum NonNullSource {
+    /// `Box::into_raw(b)` or `&mut x`.
+    Known,
+    /// Another candidate.
+    Copy(Nullable),
+    /// A local checked to be non-null by the enclosing `if` with the given ID.
+    Checked(NodeId, Ident),
+    /// The result of an `extern` call.
+    Extern,
+    /// A null pointer.
+    Null,
+    Unknown,
+}
+
+/// If `ty` is `*mut T`, return `T`.
+fn mut_pointee(ty: &Ty) -> Option<&P<Ty>> {
+    match ty.kind {
+        TyKind::Ptr(MutTy { ref ty, mutbl: Mutability::Mutable }) => Some(ty),
+        _ => None,
+    }
+}
+
+/// Visitor collecting the values assigned to the `ptr_to_nonnull` candidates.
+struct NonNullSources<'a, 'tcx: 'a> {
+    cx: &'a RefactorCtxt<'a, 'tcx>,
+    candidates: &'a HashMap<Nullable, (Symbol, P<Ty>)>,
+    /// Locals checked to be non-null by the enclosing `if`s, with the IDs of the `if`s.
+    checked: Vec<(HirId, NodeId, Ident)>,
+    /// The candidate, ID, span and source of each assigned value.
+    sources: Vec<(Nullable, NodeId, Span, NonNullSource)>,
+    /// The places where the address of a candidate is taken.
+    escapes: Vec<(Nullable, Span)>,
+}
+
+impl<'a, 'tcx> NonNullSources<'a, 'tcx> {
+    fn classify(&self, e: &P<Expr>) -> NonNullSource {
+        if let Some(n) = nullable_key(self.cx, e) {
+            if self.candidates.contains_key(&n) {
+                return NonNullSource::Copy(n);
+            }
+            if let Nullable::Local(hir_id) = n {
+                if let Some(&(_, if_id, ident)) =
+                        self.checked.iter().rev().find(|c| c.0 == hir_id) {
+                    return NonNullSource::Checked(if_id, ident);
+                }
+            }
+        }
+        if is_null_ptr(e) {
+            return NonNullSource::Null;
+        }
+        let e = strip_casts(e);
+        match e.kind {
+            ExprKind::AddrOf(..) => return NonNullSource::Known,
+            ExprKind::Call(..) => {}
+            _ => return NonNullSource::Unknown,
+        }
+        let def_id = match_or!([self.cx.opt_callee(e)] Some(x) => x;
+                               return NonNullSource::Unknown);
+        let tcx = self.cx.ty_ctxt();
+        if tcx.is_foreign_item(def_id) {
+            NonNullSource::Extern
+        } else if !def_id.is_local() && &*tcx.item_name(def_id).as_str() == "into_raw" {
+            NonNullSource::Known
+        } else {
+            NonNullSource::Unknown
+        }
+    }
+
+    fn record(&mut self, n: Nullable, e: &P<Expr>) {
+        if !self.candidates.contains_key(&n) {
+            return;
+        }
+        let source = self.classify(e);
+        // Copies are left alone, so record the ID of the place itself.
+        let id = match source {
+            NonNullSource::Copy(_) => strip_parens(e).id,
+            _ => e.id,
+        };
+        self.sources.push((n, id, e.span, source));
+    }
+
+    /// If `cond` is `!q.is_null()` for a local `q` not assigned in `then`,
+    /// return `q`.
+    fn checked_local(&self, cond: &Expr, then: &Block) -> Option<(HirId, Ident)> {
+        let q = match_or!([null_check(cond)] Some((q, true)) => q; return None);
+        let ident = match q.kind {
+            ExprKind::Path(None, ref path) if path.segments.len() == 1 => path.segments[0].ident,
+            _ => return None,
+        };
+        let hir_id = self.cx.try_resolve_expr_to_hid(q)?;
+        if assigns_to(self.cx, then, hir_id) {
+            return None;
+        }
+        Some((hir_id, ident))
+    }
+}
+
+impl<'a, 'tcx, 'ast> Visitor<'ast> for NonNullSources<'a, 'tcx> {
+    fn visit_expr(&mut self, e: &'ast Expr) {
+        match e.kind {
+            ExprKind::If(ref cond, ref then, ref els) => {
+                if let Some((hir_id, ident)) = self.checked_local(cond, then) {
+                    self.visit_expr(cond);
+                    self.checked.push((hir_id, e.id, ident));
+                    self.visit_block(then);
+                    self.checked.pop();
+                    if let Some(ref els) = *els {
+                        self.visit_expr(els);
+                    }
+                    return;
+                }
+            }
+
+            ExprKind::Assign(ref lhs, ref rhs) => {
+                if let Some(n) = nullable_key(self.cx, lhs) {
+                    self.record(n, rhs);
+                }
+            }
+
+            ExprKind::Struct(_, ref fields, ref base) => {
+                if let Some(&ty::Adt(def, _)) = self.cx.opt_node_type(e.id).map(|ty| &ty.kind) {
+                    for field in fields {
+                        self.record(Nullable::Field(def.did, field.ident.name), &field.expr);
+                    }
+                    // The fields taken from the base are unknown.
+                    if base.is_some() {
+                        let missing = self.candidates.keys().filter(|&&n| match n {
+                            Nullable::Field(did, name) => did == def.did &&
+                                !fields.iter().any(|f| f.ident.name == name),
+                            Nullable::Local(_) => false,
+                        }).cloned().collect::<Vec<_>>();
+                        for n in missing {
+                            self.sources.push((n, e.id, e.span, NonNullSource::Unknown));
+                        }
+                    }
+                }
+            }
+
+            ExprKind::AddrOf(_, _, ref place) => {
+                if let Some(n) = nullable_key(self.cx, place) {
+                    if self.candidates.contains_key(&n) {
+                        self.escapes.push((n, e.span));
+                    }
+                }
+            }
+
+            _ => {}
+        }
+        visit::walk_expr(self, e);
+    }
+
+    fn visit_local(&mut self, l: &'ast Local) {
+        if let Some(ref init) = l.init {
+            self.record(Nullable::Local(self.cx.hir_map().node_to_hir_id(l.pat.id)), init);
+        }
+        visit::walk_local(self, l);
+    }
+
+    fn visit_mac(&mut self, _mac: &'ast Mac) {}
+}
+
+impl Transform for PtrToNonNull {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        // (1) Collect the marked locals and fields of type `*mut T`.
+
+        let mut candidates = HashMap::new();
+        visit_nodes(krate, |l: &Local| {
+            if !st.marked(l.pat.id, "target") {
+                return;
+            }
+            let pointee = match_or!([l.ty.as_ref().and_then(|ty| mut_pointee(ty))] Some(x) => x;
+                                    return);
+            let name = match_or!([l.pat.kind] PatKind::Ident(_, ident, _) => ident.name; return);
+            let hir_id = cx.hir_map().node_to_hir_id(l.pat.id);
+            candidates.insert(Nullable::Local(hir_id), (name, pointee.clone()));
+        });
+        visit_nodes(krate, |i: &Item| {
+            let fields = match_or!([i.kind] ItemKind::Struct(VariantData::Struct(ref fields, _), _)
+                                   => fields; return);
+            for field in fields {
+                if !st.marked(field.id, "target") {
+                    continue;
+                }
+                let pointee = match_or!([mut_pointee(&field.ty)] Some(x) => x; continue);
+                let name = match_or!([field.ident] Some(ident) => ident.name; continue);
+                let n = Nullable::Field(cx.node_def_id(i.id), name);
+                candidates.insert(n, (name, pointee.clone()));
+            }
+        });
+
+        // (2) Check the values assigned to each candidate.
+
+        let mut sources = NonNullSources {
+            cx,
+            candidates: &candidates,
+            checked: Vec::new(),
+            sources: Vec::new(),
+            escapes: Vec::new(),
+        };
+        visit::walk_crate(&mut sources, krate);
+        let NonNullSources { sources, escapes, .. } = sources;
+
+        let span_str = |span: Span| cx.session().source_map().span_to_string(span);
+        let mut rejected = HashSet::new();
+        for &(n, span) in &escapes {
+            if rejected.insert(n) {
+                warn!("ptr_to_nonnull: the address of `{}` is taken at {}; skipping",
+                      candidates[&n].0, span_str(span));
+            }
+        }
+        for &(n, _, span, source) in &sources {
+            let reason = match source {
+                NonNullSource::Null => "null",
+                NonNullSource::Unknown => "a pointer that may be null",
+                NonNullSource::Extern if !self.unwrap_extern =>
+                    "the result of an extern call (use `extern=unwrap` to check it at run time)",
+                _ => continue,
+            };
+            if rejected.insert(n) {
+                warn!("ptr_to_nonnull: `{}` is assigned {} at {}; skipping",
+                      candidates[&n].0, reason, span_str(span));
+            }
+        }
+        loop {
+            let mut changed = false;
+            for &(n, _, span, source) in &sources {
+                let from = match_or!([source] NonNullSource::Copy(m) => m; continue);
+                if rejected.contains(&from) && rejected.insert(n) {
+                    warn!("ptr_to_nonnull: `{}` is assigned from `{}` at {}, which is not \
+                           converted; skipping", candidates[&n].0, candidates[&from].0,
+                          span_str(span));
+                    changed = true;
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+
+        candidates.retain(|n, _| !rejected.contains(n));
+        if candidates.is_empty() {
+            return;
+        }
+
+        let mut wrapped = HashMap::new();
+        let mut kept = HashSet::new();
+        let mut checked_ifs = HashMap::new();
+        for &(n, id, _, source) in &sources {
+            if !candidates.contains_key(&n) {
+                continue;
+            }
+            match source {
+                NonNullSource::Copy(_) => {
+                    kept.insert(id);
+                }
+                NonNullSource::Checked(if_id, ident) => {
+                    checked_ifs.insert(if_id, ident);
+                    wrapped.insert(id, source);
+                }
+                _ => {
+                    wrapped.insert(id, source);
+                }
+            }
+        }
+
+        // The null checks of the candidates, with their values, and the `if`s
+        // testing them.
+        let is_candidate = |e: &Expr| {
+            nullable_key(cx, e).map_or(false, |n| candidates.contains_key(&n))
+        };
+        let mut null_checks = HashMap::new();
+        let mut null_ifs = HashMap::new();
+        visit_nodes(krate, |e: &Expr| {
+            if let Some((p, negated)) = null_check(e) {
+                if is_candidate(p) {
+                    null_checks.insert(e.id, negated);
+                }
+            }
+            if let ExprKind::If(ref cond, _, _) = e.kind {
+                if let Some((p, negated)) = null_check(cond) {
+                    if is_candidate(p) {
+                        null_ifs.insert(e.id, negated);
+                    }
+                }
+            }
+        });
+
+        // (3) Retype the candidates.
+
+        let nonnull_ty = |pointee: &P<Ty>| {
+            driver::parse_ty(cx.session(), &format!("::std::ptr::NonNull<{}>",
+                                                    pprust::ty_to_string(pointee)))
+        };
+        MutVisitNodes::visit(krate, |l: &mut P<Local>| {
+            let n = Nullable::Local(cx.hir_map().node_to_hir_id(l.pat.id));
+            if let Some(&(_, ref pointee)) = candidates.get(&n) {
+                l.ty = Some(nonnull_ty(pointee));
+            }
+        });
+        FlatMapNodes::visit(krate, |mut i: P<Item>| {
+            let struct_did = cx.node_def_id(i.id);
+            if let ItemKind::Struct(VariantData::Struct(ref mut fields, _), _) = i.kind {
+                for field in fields.iter_mut() {
+                    let name = match_or!([field.ident] Some(ident) => ident.name; continue);
+                    if let Some(&(_, ref pointee)) =
+                            candidates.get(&Nullable::Field(struct_did, name)) {
+                        field.ty = nonnull_ty(pointee);
+                    }
+                }
+            }
+            smallvec![i]
+        });
+
+        // (4) Rewrite the assigned values and the null checks.  `if`s whose
+        // `else` branch runs, but has none, are removed first.
+
+        MutVisitNodes::visit(krate, |b: &mut P<Block>| {
+            b.stmts.retain(|s| match s.kind {
+                StmtKind::Expr(ref e) | StmtKind::Semi(ref e) => match e.kind {
+                    ExprKind::If(_, _, None) => null_ifs.get(&e.id) != Some(&false),
+                    _ => true,
+                },
+                _ => true,
+            });
+        });
+
+        let mut mcx = MatchCtxt::new(st, cx);
+        let known_repl = mcx.parse_expr("unsafe { ::std::ptr::NonNull::new_unchecked($e) }");
+        let extern_repl = mcx.parse_expr("::std::ptr::NonNull::new($e).unwrap()");
+        let as_ptr_repl = mcx.parse_expr("$e.as_ptr()");
+        let subst = |repl: &P<Expr>, e: P<Expr>| {
+            let mut bnd = Bindings::new();
+            bnd.add("$e", e);
+            repl.clone().subst(st, cx, &bnd)
+        };
+
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            if let Some(&value) = null_checks.get(&e.id) {
+                *e = driver::parse_expr(cx.session(), if value { "true" } else { "false" });
+                return;
+            }
+            if let Some(&value) = null_ifs.get(&e.id) {
+                let taken = match e.kind {
+                    ExprKind::If(_, ref then, _) if value =>
+                        Some(ExprKind::Block(then.clone(), None)),
+                    ExprKind::If(_, _, ref els) => els.as_ref().map(|els| els.kind.clone()),
+                    _ => None,
+                };
+                if let Some(kind) = taken {
+                    e.kind = kind;
+                }
+                return;
+            }
+            if let Some(&ident) = checked_ifs.get(&e.id) {
+                if let ExprKind::If(ref mut cond, _, _) = e.kind {
+                    *cond = if_let_cond(cx, &format!("Some({}_nonnull)", ident),
+                                        &format!("::std::ptr::NonNull::new({})", ident));
+                }
+                return;
+            }
+            let new_e = match wrapped.get(&e.id) {
+                Some(NonNullSource::Known) => subst(&known_repl, e.clone()),
+                Some(NonNullSource::Extern) => subst(&extern_repl, e.clone()),
+                Some(NonNullSource::Checked(_, ident)) =>
+                    driver::parse_expr(cx.session(), &format!("{}_nonnull", ident)),
+                _ => return,
+            };
+            *e = new_e;
+        });
+
+        // (5) Read the other uses through `as_ptr`.  Mutable places are the
+        // candidates being assigned, since taking their address is rejected.
+
+        fold_exprs_with_context(krate, |e, ectx| {
+            if ectx == lr_expr::Context::LvalueMut || kept.contains(&e.id) || !is_candidate(e) {
+                return;
+            }
+            *e = subst(&as_ptr_repl, e.clone());
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
+pub fn register_commands(reg: &mut Registry) {
+    use super::mk;
+
+    reg.register("option_null_checks", |_args| mk(OptionNullChecks));
+    reg.register("normalize_null", |_args| mk(NormalizeNull));
+    reg.register("ptr_to_nonnull", |args| mk(PtrToNonNull {
+        unwrap_extern: args.iter().any(|arg| arg == "extern=unwrap"),
+    }));
+}
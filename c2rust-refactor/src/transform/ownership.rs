@@ -1,26 +1,34 @@
 use std::collections::HashMap;
 use std::collections::HashSet;
+use std::fs;
 
 use arena::SyncDroplessArena;
+use rustc::hir::HirId;
 use rustc::hir::def_id::DefId;
 use rustc_index::vec::IndexVec;
 use syntax::ast::*;
 use syntax::source_map::DUMMY_SP;
 use syntax::mut_visit::{self, MutVisitor};
+use syntax::print::pprust;
 use syntax::token::{self, Token, TokenKind, DelimToken};
 use syntax::ptr::P;
 use syntax::symbol::Symbol;
 use syntax::tokenstream::{TokenTree, TokenStream, DelimSpan};
+use syntax_pos::Span;
 use smallvec::{smallvec, SmallVec};
 
-use crate::ast_manip::{MutVisitNodes, MutVisit};
+use crate::ast_manip::{FlatMapNodes, MutVisitNodes, MutVisit, visit_nodes};
 use crate::ast_manip::fn_edit::flat_map_fns;
 use crate::analysis::labeled_ty::LabeledTyCtxt;
 use crate::analysis::ownership::{self, ConcretePerm, Var, PTy};
 use crate::analysis::ownership::constraint::{ConstraintSet, Perm};
 use crate::command::{CommandState, Registry, DriverCommand};
 use crate::context::HirMap;
-use crate::driver::{Phase};
+use crate::driver::{parse_stmts, parse_ty, Phase};
+use crate::matcher::{Bindings, MatchCtxt, Subst};
+use crate::transform::canonicalize_refs::strip_parens;
+use crate::transform::mem::strip_casts;
+use crate::transform::null_ptrs::{is_null_ptr, null_check};
 use crate::RefactorCtxt;
 use crate::type_map;
 use c2rust_ast_builder::{mk, IntoSymbol};
@@ -47,6 +55,14 @@ pub fn register_commands(reg: &mut Registry) {
             do_mark_pointers(st, cx);
         }))
     });
+
+    reg.register("apply_ownership", |args| {
+        let path = args[0].clone();
+
+        Box::new(DriverCommand::new(Phase::Phase3, move |st, cx| {
+            do_apply_ownership(st, cx, &path);
+        }))
+    });
 }
 
 /// # `ownership_annotate` Command
@@ -600,3 +616,565 @@ fn do_mark_pointers(st: &CommandState, cx: &RefactorCtxt) {
         st.add_mark(ast_ty.id, label);
     });
 }
+
+
+/// # `apply_ownership` Command
+///
+/// Usage: `apply_ownership RESULTS`
+///
+/// Rewrite function signatures according to the ownership results in the JSON
+/// file `RESULTS`, converting raw pointer parameters and return types to `&T`
+/// for `read`, `&mut T` for `write`, and `Box<T>` for `move`.  The file maps the
+/// path of each function, as printed by rustc without the crate name, to the
+/// permissions of its parameters, by index, and of its return value:
+///
+/// ```json
+/// {
+///     "list::list_new": { "return": "move" },
+///     "list::list_push": { "0": "write", "1": "move" },
+///     "list::list_first": { "0": "read 'a", "return": "read 'a" }
+/// }
+/// ```
+///
+/// A lifetime after a `read` or `write` permission marks a borrow that outlives
+/// the call; it's added as a lifetime parameter of the function.  A `read` or
+/// `write` return value needs one, shared with one of the parameters.
+///
+/// In the body of a rewritten function, uses of a converted `read` or `write`
+/// parameter, apart from dereferences, convert it back to a raw pointer.  A
+/// `move` parameter is converted back with `Box::into_raw` at the start of the
+/// body.  Returned values are converted with `&*`, `&mut *` or `Box::from_raw`.
+///
+/// At call sites, arguments are converted the same way, except for `&x`,
+/// `Box::into_raw(b)`, converted parameters of the caller, and results of
+/// other converted functions, which are passed directly.  Other results are
+/// converted back to raw pointers with a cast, or `Box::into_raw`.
+///
+/// A function is skipped with a warning when the file doesn't fit the code:
+/// when it's not a function of the crate, when an index isn't a raw pointer
+/// parameter with a simple binding, when `write` or `move` is given for a
+/// `*const` pointer, when a borrowed parameter is reassigned, has its address
+/// taken, or is checked for null, when a converted return value may be null,
+/// or when the function is exported or used other than by calling it.  A
+/// `move` return value may be null if the function returns a null pointer
+/// literal; a `read` or `write` one unless every returned value is `&x`, `&mut
+/// x`, or a converted parameter.
+fn do_apply_ownership(st: &CommandState, cx: &RefactorCtxt, path: &str) {
+    let mut results = read_ownership_results(path);
+    let span_str = |span: Span| cx.session().source_map().span_to_string(span);
+
+    st.map_krate(|krate| {
+        // (1) Check the results against the signatures.
+
+        let mut fns = HashMap::new();
+        visit_nodes(krate, |i: &Item| {
+            let (sig, generics, body) = match_or!([i.kind]
+                ItemKind::Fn(ref sig, ref generics, ref body) => (sig, generics, body); return);
+            let def_id = cx.node_def_id(i.id);
+            let name = cx.ty_ctxt().def_path_str(def_id);
+            let fr = match_or!([results.remove(&name)] Some(x) => x; return);
+            match convert_fn(cx, def_id, fr, sig, generics, body) {
+                Ok(f) => {
+                    fns.insert(def_id, (name, f));
+                }
+                Err(msg) => warn!("apply_ownership: `{}` {}; skipping", name, msg),
+            }
+        });
+        for name in results.keys() {
+            warn!("apply_ownership: `{}` is not a function in this crate; skipping", name);
+        }
+
+        let mut callees = HashSet::new();
+        visit_nodes(krate, |e: &Expr| {
+            if let ExprKind::Call(ref func, _) = e.kind {
+                callees.insert(func.id);
+            }
+        });
+        let mut misused = Vec::new();
+        visit_nodes(krate, |e: &Expr| {
+            if let ExprKind::Path(..) = e.kind {
+                match cx.try_resolve_expr(e) {
+                    Some(def_id) if fns.contains_key(&def_id) && !callees.contains(&e.id) =>
+                        misused.push((def_id, e.span)),
+                    _ => {}
+                }
+            }
+        });
+        for (def_id, span) in misused {
+            if let Some((name, _)) = fns.remove(&def_id) {
+                warn!("apply_ownership: `{}` is used at {} other than in a call; skipping",
+                      name, span_str(span));
+            }
+        }
+
+        // `read` and `write` parameters, which keep their new types in the body.
+        let params = fns.values()
+            .flat_map(|(_, f)| f.inputs.values())
+            .filter(|&&(_, ref ptr)| ptr.perm != ConcretePerm::Move)
+            .map(|&(hir_id, ref ptr)| (hir_id, ptr))
+            .collect::<HashMap<_, _>>();
+        let param_ptr = |e: &Expr| {
+            let e = strip_parens(e);
+            match e.kind {
+                ExprKind::Path(None, _) =>
+                    cx.try_resolve_expr_to_hid(e).and_then(|hir_id| params.get(&hir_id).cloned()),
+                _ => None,
+            }
+        };
+
+        // The uses of parameters that don't need a raw pointer.
+        let mut kept = HashSet::new();
+        visit_nodes(krate, |e: &Expr| {
+            if let ExprKind::Unary(UnOp::Deref, ref p) = e.kind {
+                if param_ptr(p).is_some() {
+                    kept.insert(strip_parens(p).id);
+                }
+            }
+        });
+
+        let subst = |src: &str, e: P<Expr>| {
+            let mut bnd = Bindings::new();
+            bnd.add("$e", e);
+            MatchCtxt::new(st, cx).parse_expr(src).subst(st, cx, &bnd)
+        };
+
+        // Convert `e`, a raw pointer passed or returned as `ptr`.
+        let convert = |e: &P<Expr>, ptr: &ConvertedPtr, kept: &mut HashSet<NodeId>| {
+            let inner = strip_casts(e);
+            match inner.kind {
+                ExprKind::AddrOf(_, mutbl, _) if ptr.perm == ConcretePerm::Read ||
+                        (ptr.perm == ConcretePerm::Write && mutbl == Mutability::Mutable) =>
+                    return inner.clone(),
+                _ => {}
+            }
+            if ptr.perm == ConcretePerm::Move {
+                if let Some(b) = into_raw_arg(inner) {
+                    return b.clone();
+                }
+            }
+            // The result of a converted function, already turned back into a raw pointer.
+            // `write` results are reborrowed as `&mut *r`, which the case above passes on.
+            if let ExprKind::Call(..) = inner.kind {
+                let callee_perm = cx.opt_callee(inner)
+                    .and_then(|def_id| fns.get(&def_id))
+                    .and_then(|&(_, ref f)| f.output.as_ref())
+                    .map(|out| out.perm);
+                if callee_perm == Some(ConcretePerm::Read) && ptr.perm == ConcretePerm::Read {
+                    return inner.clone();
+                }
+            }
+            if let Some(param) = param_ptr(e) {
+                match (param.perm, ptr.perm) {
+                    (ConcretePerm::Read, ConcretePerm::Read) |
+                    (ConcretePerm::Write, ConcretePerm::Write) => {
+                        kept.insert(strip_parens(e).id);
+                        let src = if ptr.perm == ConcretePerm::Write { "&mut *$e" } else { "$e" };
+                        return subst(src, e.clone());
+                    }
+                    (ConcretePerm::Write, ConcretePerm::Read) => {
+                        kept.insert(strip_parens(e).id);
+                        return subst("&*$e", e.clone());
+                    }
+                    _ => warn!("apply_ownership: `{}` is read-only, but is used as a `write` \
+                                pointer at {}", pprust::expr_to_string(e), span_str(e.span)),
+                }
+            }
+            subst(ptr.from_raw(), e.clone())
+        };
+
+        // (2) Convert the arguments and results at the call sites.
+
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            let def_id = match e.kind {
+                ExprKind::Call(..) => match_or!([cx.opt_callee(e)] Some(x) => x; return),
+                _ => return,
+            };
+            let (_, f) = match_or!([fns.get(&def_id)] Some(x) => x; return);
+            if let ExprKind::Call(_, ref mut args) = e.kind {
+                for (&i, &(_, ref ptr)) in &f.inputs {
+                    if let Some(arg) = args.get_mut(i) {
+                        *arg = convert(arg, ptr, &mut kept);
+                    }
+                }
+            }
+            if let Some(ref ptr) = f.output {
+                *e = subst(&ptr.to_raw(), e.clone());
+            }
+        });
+
+        // (3) Rewrite the signatures and bodies of the functions.
+
+        FlatMapNodes::visit(krate, |i: P<Item>| {
+            let (_, f) = match_or!([fns.get(&cx.node_def_id(i.id))] Some(x) => x;
+                                   return smallvec![i]);
+            smallvec![i.map(|mut i| {
+                let (sig, generics, body) = expect!([i.kind]
+                    ItemKind::Fn(ref mut sig, ref mut generics, ref mut body) =>
+                        (sig, generics, body));
+
+                for (&idx, &(_, ref ptr)) in &f.inputs {
+                    sig.decl.inputs[idx].ty = parse_ty(cx.session(), &ptr.safe_ty());
+                }
+                if let Some(ref ptr) = f.output {
+                    sig.decl.output = FunctionRetTy::Ty(parse_ty(cx.session(), &ptr.safe_ty()));
+                    fold_returns(body, |e| *e = convert(e, ptr, &mut kept));
+                }
+
+                let lifetimes = f.lifetimes();
+                if !lifetimes.is_empty() {
+                    let items = st.parse_items(cx, &format!("fn f<{}>() {{}}",
+                                                            lifetimes.join(", ")));
+                    let params = expect!([items[0].kind]
+                        ItemKind::Fn(_, ref g, _) => g.params.clone());
+                    generics.params.splice(0..0, params);
+                }
+
+                MutVisitNodes::visit(body, |e: &mut P<Expr>| {
+                    if kept.contains(&e.id) {
+                        return;
+                    }
+                    if let ExprKind::Path(..) = e.kind {
+                        if let Some(ptr) = param_ptr(e) {
+                            *e = subst(&ptr.to_raw(), e.clone());
+                        }
+                    }
+                });
+
+                let mut moved = f.inputs.iter()
+                    .filter(|&(_, &(_, ref ptr))| ptr.perm == ConcretePerm::Move)
+                    .map(|(&idx, _)| idx)
+                    .collect::<Vec<_>>();
+                moved.sort();
+                for &idx in moved.iter().rev() {
+                    let ident = expect!([sig.decl.inputs[idx].pat.kind]
+                                        PatKind::Ident(_, ident, _) => ident);
+                    let stmts = parse_stmts(cx.session(),
+                                            &format!("let {0} = Box::into_raw({0});", ident));
+                    body.stmts.splice(0..0, stmts);
+                }
+            }
+            i
+            })]
+        });
+    });
+}
+
+/// A pointer permission read from an `apply_ownership` results file, with the
+/// lifetime of the reference, if one was given.
+struct ResultPerm {
+    perm: ConcretePerm,
+    lifetime: Option<String>,
+}
+
+/// The pointer permissions of a function signature.
+#[derive(Default)]
+struct FnResults {
+    inputs: HashMap<usize, ResultPerm>,
+    output: Option<ResultPerm>,
+}
+
+/// Parse a permission such as `read`, `write 'a` or `move`.
+fn parse_perm(s: &str) -> Option<ResultPerm> {
+    let mut words = s.split_whitespace();
+    let perm = match words.next()? {
+        "read" => ConcretePerm::Read,
+        "write" => ConcretePerm::Write,
+        "move" => ConcretePerm::Move,
+        _ => return None,
+    };
+    let lifetime = match words.next() {
+        Some(lt) if lt.starts_with('\'') && lt.len() > 1 && perm != ConcretePerm::Move =>
+            Some(lt.to_owned()),
+        Some(_) => return None,
+        None => None,
+    };
+    if words.next().is_some() {
+        return None;
+    }
+    Some(ResultPerm { perm, lifetime })
+}
+
+/// Read an `apply_ownership` results file.  Malformed entries are reported and
+/// skipped.
+fn read_ownership_results(path: &str) -> HashMap<String, FnResults> {
+    let src = fs::read_to_string(path)
+        .unwrap_or_else(|e| panic!("Could not read ownership results {:?}: {}", path, e));
+    let results = json::parse(&src)
+        .unwrap_or_else(|e| panic!("Could not parse ownership results {:?}: {}", path, e));
+
+    let mut fns = HashMap::new();
+    'fns: for (name, entry) in results.entries() {
+        let mut fr = FnResults::default();
+        for (key, value) in entry.entries() {
+            let perm = match value.as_str().and_then(parse_perm) {
+                Some(x) => x,
+                None => {
+                    warn!("apply_ownership: `{}` has an invalid permission {} for `{}`; skipping",
+                          name, value.dump(), key);
+                    continue 'fns;
+                }
+            };
+            match key.parse::<usize>() {
+                Ok(idx) => {
+                    fr.inputs.insert(idx, perm);
+                }
+                Err(_) if key == "return" => fr.output = Some(perm),
+                Err(_) => {
+                    warn!("apply_ownership: `{}` has `{}`, which is not a parameter index or \
+                           `return`; skipping", name, key);
+                    continue 'fns;
+                }
+            }
+        }
+        fns.insert(name.to_owned(), fr);
+    }
+    fns
+}
+
+/// A raw pointer in a function signature, and the permission it's converted to.
+struct ConvertedPtr {
+    perm: ConcretePerm,
+    lifetime: Option<String>,
+    pointee: P<Ty>,
+    mutbl: Mutability,
+}
+
+impl ConvertedPtr {
+    fn new(raw_ty: &Ty, rp: &ResultPerm) -> Result<ConvertedPtr, &'static str> {
+        let (pointee, mutbl) = match raw_ty.kind {
+            TyKind::Ptr(MutTy { ref ty, mutbl }) => (ty.clone(), mutbl),
+            _ => return Err("is not a raw pointer"),
+        };
+        if mutbl == Mutability::Immutable && rp.perm != ConcretePerm::Read {
+            return Err("is a `*const` pointer, so it can only be `read`");
+        }
+        Ok(ConvertedPtr { perm: rp.perm, lifetime: rp.lifetime.clone(), pointee, mutbl })
+    }
+
+    fn safe_ty(&self) -> String {
+        let pointee = pprust::ty_to_string(&self.pointee);
+        let lifetime = self.lifetime.as_ref().map_or(String::new(), |lt| format!("{} ", lt));
+        match self.perm {
+            ConcretePerm::Read => format!("&{}{}", lifetime, pointee),
+            ConcretePerm::Write => format!("&{}mut {}", lifetime, pointee),
+            ConcretePerm::Move => format!("Box<{}>", pointee),
+        }
+    }
+
+    /// The template converting a raw pointer `$e` to the new type.
+    fn from_raw(&self) -> &'static str {
+        match self.perm {
+            ConcretePerm::Read => "&*$e",
+            ConcretePerm::Write => "&mut *$e",
+            ConcretePerm::Move => "Box::from_raw($e)",
+        }
+    }
+
+    /// The template converting `$e` of the new type back to the raw pointer.
+    /// Mutable references are reborrowed, so `$e` can still be used afterward.
+    fn to_raw(&self) -> String {
+        let pointee = pprust::ty_to_string(&self.pointee);
+        match (self.perm, self.mutbl) {
+            (ConcretePerm::Read, Mutability::Immutable) => format!("$e as *const {}", pointee),
+            (ConcretePerm::Read, Mutability::Mutable) =>
+                format!("$e as *const {0} as *mut {0}", pointee),
+            (ConcretePerm::Write, _) => format!("&mut *$e as *mut {}", pointee),
+            (ConcretePerm::Move, _) => "Box::into_raw($e)".to_owned(),
+        }
+    }
+}
+
+/// The converted pointers of a function signature.
+struct ConvertedFn {
+    /// The converted parameters by index, with the IDs of their bindings.
+    inputs: HashMap<usize, (HirId, ConvertedPtr)>,
+    output: Option<ConvertedPtr>,
+}
+
+impl ConvertedFn {
+    /// The lifetime parameters the signature needs.
+    fn lifetimes(&self) -> Vec<String> {
+        let mut lifetimes = self.inputs.values().map(|&(_, ref ptr)| ptr)
+            .chain(self.output.iter())
+            .filter_map(|ptr| ptr.lifetime.clone())
+            .collect::<Vec<_>>();
+        lifetimes.sort();
+        lifetimes.dedup();
+        lifetimes
+    }
+}
+
+/// Check the results `fr` for a function against its code, and find the
+/// pointers to convert.  On failure, returns the conflict found.
+fn convert_fn(cx: &RefactorCtxt, def_id: DefId, fr: FnResults, sig: &FnSig,
+              generics: &Generics, body: &P<Block>) -> Result<ConvertedFn, String> {
+    if cx.is_exported_def(def_id) {
+        return Err("is exported, so its signature can't change".to_owned());
+    }
+
+    let mut inputs = HashMap::new();
+    for (idx, rp) in fr.inputs {
+        let param = sig.decl.inputs.get(idx)
+            .ok_or_else(|| format!("has no parameter {}", idx))?;
+        let ptr = ConvertedPtr::new(&param.ty, &rp)
+            .map_err(|msg| format!("has a parameter {} that {}", idx, msg))?;
+        let hir_id = match param.pat.kind {
+            PatKind::Ident(BindingMode::ByValue(_), _, None) =>
+                cx.hir_map().node_to_hir_id(param.pat.id),
+            _ => return Err(format!("has a parameter {} that is not a simple binding", idx)),
+        };
+        if ptr.perm != ConcretePerm::Move && changes_local(cx, body, hir_id) {
+            return Err(format!("reassigns or takes the address of parameter {}", idx));
+        }
+        if ptr.perm != ConcretePerm::Move && checks_null(cx, body, hir_id) {
+            return Err(format!("checks parameter {} for null, so it can't be a reference",
+                               idx));
+        }
+        inputs.insert(idx, (hir_id, ptr));
+    }
+
+    let output = match fr.output {
+        Some(rp) => {
+            let ty = match sig.decl.output {
+                FunctionRetTy::Ty(ref ty) => ty,
+                FunctionRetTy::Default(_) => return Err("has no return value".to_owned()),
+            };
+            let ptr = ConvertedPtr::new(ty, &rp)
+                .map_err(|msg| format!("has a return type that {}", msg))?;
+            if ptr.perm != ConcretePerm::Move {
+                let lt = ptr.lifetime.as_ref()
+                    .ok_or("returns a borrow, but gives it no lifetime")?;
+                if !inputs.values().any(|&(_, ref p)| p.lifetime.as_ref() == Some(lt)) {
+                    return Err(format!("returns a borrow with lifetime `{}`, which no \
+                                        parameter has", lt));
+                }
+            }
+            let refs = inputs.values()
+                .filter(|&&(_, ref p)| p.perm != ConcretePerm::Move)
+                .map(|&(hir_id, _)| hir_id)
+                .collect::<HashSet<_>>();
+            let mut null_span = None;
+            fold_returns(&mut body.clone(), |e| {
+                let may_be_null = if ptr.perm == ConcretePerm::Move {
+                    is_null_ptr(e)
+                } else {
+                    !is_non_null(cx, e, &refs)
+                };
+                if may_be_null {
+                    null_span = null_span.or(Some(e.span));
+                }
+            });
+            if let Some(span) = null_span {
+                return Err(format!("may return null at {}",
+                                   cx.session().source_map().span_to_string(span)));
+            }
+            Some(ptr)
+        }
+        None => None,
+    };
+
+    let f = ConvertedFn { inputs, output };
+    for lt in f.lifetimes() {
+        if generics.params.iter().any(|p| &*p.ident.as_str() == lt) {
+            return Err(format!("already has a lifetime parameter `{}`", lt));
+        }
+    }
+    Ok(f)
+}
+
+/// Check if `body` assigns to the local `hir_id`, or takes its address.
+fn changes_local(cx: &RefactorCtxt, body: &Block, hir_id: HirId) -> bool {
+    let mut found = false;
+    visit_nodes(body, |e: &Expr| match e.kind {
+        ExprKind::Assign(ref lhs, _) | ExprKind::AssignOp(_, ref lhs, _) |
+        ExprKind::AddrOf(_, _, ref lhs) => {
+            found |= cx.try_resolve_expr_to_hid(lhs) == Some(hir_id);
+        }
+        _ => {}
+    });
+    found
+}
+
+/// Check if `body` compares the local `hir_id` against null.
+fn checks_null(cx: &RefactorCtxt, body: &Block, hir_id: HirId) -> bool {
+    let is_local = |e: &Expr| cx.try_resolve_expr_to_hid(strip_parens(e)) == Some(hir_id);
+    let mut found = false;
+    visit_nodes(body, |e: &Expr| {
+        if let Some((p, _)) = null_check(e) {
+            found |= is_local(p);
+        }
+        if let ExprKind::Binary(op, ref l, ref r) = e.kind {
+            if op.node == BinOpKind::Eq || op.node == BinOpKind::Ne {
+                found |= (is_null_ptr(l) && is_local(strip_casts(r))) ||
+                    (is_null_ptr(r) && is_local(strip_casts(l)));
+            }
+        }
+    });
+    found
+}
+
+/// Check if the returned value `e` is known not to be null: it is `&x` or `&mut x` for a
+/// place `x` that isn't behind a raw pointer, or one of the `refs` parameters, which are
+/// references.
+fn is_non_null(cx: &RefactorCtxt, e: &Expr, refs: &HashSet<HirId>) -> bool {
+    match e.kind {
+        ExprKind::Paren(ref e) | ExprKind::Cast(ref e, _) => is_non_null(cx, e, refs),
+        ExprKind::AddrOf(_, _, ref place) => match strip_parens(place).kind {
+            ExprKind::Unary(UnOp::Deref, ref p) => is_non_null(cx, p, refs),
+            _ => true,
+        },
+        ExprKind::Path(None, _) => cx.try_resolve_expr_to_hid(e)
+            .map_or(false, |hir_id| refs.contains(&hir_id)),
+        _ => false,
+    }
+}
+
+/// If `e` is `Box::into_raw(b)`, return `b`.
+fn into_raw_arg(e: &Expr) -> Option<&P<Expr>> {
+    let (func, args) = match_or!([e.kind] ExprKind::Call(ref f, ref a) if a.len() == 1 => (f, a);
+                                 return None);
+    let path = match_or!([func.kind] ExprKind::Path(None, ref p) => p; return None);
+    let names = path.segments.iter().rev().take(2).map(|seg| seg.ident.name).collect::<Vec<_>>();
+    if &names[..] == [Symbol::intern("into_raw"), Symbol::intern("Box")] {
+        Some(&args[0])
+    } else {
+        None
+    }
+}
+
+/// Call `f` on each value returned by the function body `body`: its tail
+/// expression, and the operands of its `return`s outside of closures.
+pub(super) fn fold_returns<F: FnMut(&mut P<Expr>)>(body: &mut P<Block>, f: F) {
+    struct ReturnFolder<F> {
+        f: F,
+    }
+
+    impl<F: FnMut(&mut P<Expr>)> MutVisitor for ReturnFolder<F> {
+        fn visit_expr(&mut self, e: &mut P<Expr>) {
+            if let ExprKind::Closure(..) = e.kind {
+                return;
+            }
+            mut_visit::noop_visit_expr(e, self);
+            if let ExprKind::Ret(Some(ref mut value)) = e.kind {
+                (self.f)(value);
+            }
+        }
+
+        fn flat_map_item(&mut self, i: P<Item>) -> SmallVec<[P<Item>; 1]> {
+            smallvec![i]
+        }
+
+        fn visit_mac(&mut self, mac: &mut Mac) {
+            mut_visit::noop_visit_mac(mac, self)
+        }
+    }
+
+    let mut folder = ReturnFolder { f };
+    folder.visit_block(body);
+    if let Some(&mut Stmt { kind: StmtKind::Expr(ref mut e), .. }) = body.stmts.last_mut() {
+        match e.kind {
+            ExprKind::Ret(_) => {}
+            _ => (folder.f)(e),
+        }
+    }
+}
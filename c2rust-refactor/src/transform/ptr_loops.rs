@@ -0,0 +1,557 @@
+//! Transforms that turn pointer arithmetic over buffers into Rust indexing and iteration.
+
+use std::collections::{HashMap, HashSet};
+use rustc::hir::HirId;
+use rustc::hir::def::Res;
+use rustc::ty::{self, TyKind};
+use syntax::ast::*;
+use syntax::ptr::P;
+
+use crate::ast_manip::{visit_nodes, AstEquiv, MutVisitNodes};
+use crate::command::{CommandState, Registry};
+use crate::driver::{Phase, parse_expr};
+use crate::matcher::{Bindings, MatchCtxt, Subst};
+use crate::transform::Transform;
+use crate::transform::enums::is_simple_place;
+use crate::transform::funcs::needs_unsafe;
+use crate::transform::mem::strip_casts;
+use crate::RefactorCtxt;
+use c2rust_ast_builder::mk;
+
+
+/// Check if `e` is a method call named `name`, and if so, return its receiver and arguments.
+fn method_call<'a>(e: &'a Expr, name: &str) -> Option<(&'a P<Expr>, &'a [P<Expr>])> {
+    match e.kind {
+        ExprKind::MethodCall(ref seg, ref args) if &*seg.ident.as_str() == name =>
+            Some((&args[0], &args[1..])),
+        _ => None,
+    }
+}
+
+/// If `e` is `buf.as_ptr()` or `buf.as_mut_ptr()`, where `buf` is an array, slice or `Vec`,
+/// return `buf`, whether the pointer is mutable, and the element type.
+pub(super) fn buf_ptr<'a, 'tcx>(cx: &RefactorCtxt<'_, 'tcx>, e: &'a Expr)
+                     -> Option<(&'a P<Expr>, bool, ty::Ty<'tcx>)> {
+    let (buf, mutable) = if let Some((buf, _)) = method_call(e, "as_ptr") {
+        (buf, false)
+    } else if let Some((buf, _)) = method_call(e, "as_mut_ptr") {
+        (buf, true)
+    } else {
+        return None;
+    };
+    let mut buf_ty = cx.opt_node_type(buf.id)?;
+    while let TyKind::Ref(_, ty, _) = buf_ty.kind {
+        buf_ty = ty;
+    }
+    let elem = match buf_ty.kind {
+        TyKind::Array(elem, _) | TyKind::Slice(elem) => elem,
+        TyKind::Adt(def, substs) if &*cx.ty_ctxt().item_name(def.did).as_str() == "Vec" =>
+            substs.type_at(0),
+        _ => return None,
+    };
+    Some((buf, mutable, elem))
+}
+
+/// Split the pointer expression `e` into the pointer it starts from and the arguments of the
+/// `offset` calls applied to it, innermost first.
+fn offset_chain(e: &P<Expr>) -> (&P<Expr>, Vec<&P<Expr>>) {
+    let mut base = e;
+    let mut offsets = Vec::new();
+    while let Some((inner, args)) = method_call(base, "offset") {
+        offsets.push(&args[0]);
+        base = inner;
+    }
+    offsets.reverse();
+    (base, offsets)
+}
+
+/// Check if `e` has an unsigned integer type, so it can't wrap around when cast to `usize`.
+pub(super) fn is_unsigned_expr(cx: &RefactorCtxt, e: &Expr) -> bool {
+    match cx.opt_node_type(e.id).map(|ty| &ty.kind) {
+        Some(&TyKind::Uint(_)) => true,
+        _ => false,
+    }
+}
+
+/// A pointer local declared with `let`, and what it refers to.
+struct PtrLocal {
+    index: usize,
+    ident: Ident,
+    hir_id: HirId,
+}
+
+impl PtrLocal {
+    fn from_stmt(cx: &RefactorCtxt, index: usize, stmt: &Stmt) -> Option<(PtrLocal, &P<Expr>)> {
+        let local = match stmt.kind {
+            StmtKind::Local(ref l) => l,
+            _ => return None,
+        };
+        let ident = match local.pat.kind {
+            PatKind::Ident(BindingMode::ByValue(_), ident, None) => ident,
+            _ => return None,
+        };
+        let init = local.init.as_ref()?;
+        let hir_id = cx.hir_map().node_to_hir_id(local.pat.id);
+        Some((PtrLocal { index, ident, hir_id }, init))
+    }
+
+    fn is_use(&self, cx: &RefactorCtxt, e: &Expr) -> bool {
+        match e.kind {
+            ExprKind::Path(None, ref path) if path.segments.len() == 1 &&
+                path.segments[0].ident == self.ident => {}
+            _ => return false,
+        }
+        cx.try_resolve_expr_hir(e) == Some(Res::Local(self.hir_id))
+    }
+
+    fn count_uses(&self, cx: &RefactorCtxt, stmts: &[Stmt]) -> usize {
+        let mut count = 0;
+        for s in stmts {
+            visit_nodes(s, |e: &Expr| {
+                if self.is_use(cx, e) {
+                    count += 1;
+                }
+            });
+        }
+        count
+    }
+}
+
+/// A marked `while p < end { ...; p = p.offset(1); }` loop over a buffer, and the pointer locals
+/// it walks with.
+struct PtrLoop<'a> {
+    p: PtrLocal,
+    end: PtrLocal,
+    /// The array, slice or `Vec` that `p` starts at the base of.
+    buf: &'a P<Expr>,
+    /// `end` minus `p`.
+    len: &'a P<Expr>,
+    mutable: bool,
+}
+
+impl<'a> PtrLoop<'a> {
+    fn recognize(
+        cx: &RefactorCtxt,
+        stmts: &'a [Stmt],
+        loop_idx: usize,
+        cond: &Expr,
+    ) -> Option<PtrLoop<'a>> {
+        let (lhs, rhs) = match cond.kind {
+            ExprKind::Binary(op, ref lhs, ref rhs)
+                if op.node == BinOpKind::Lt || op.node == BinOpKind::Ne => (lhs, rhs),
+            _ => return None,
+        };
+
+        let find_local = |e: &Expr| {
+            let res = cx.try_resolve_expr_hir(e)?;
+            stmts[..loop_idx].iter().enumerate().rev()
+                .filter_map(|(i, s)| PtrLocal::from_stmt(cx, i, s))
+                .find(|&(ref l, _)| Res::Local(l.hir_id) == res)
+        };
+        let (p, p_init) = find_local(lhs)?;
+        let (end, end_init) = find_local(rhs)?;
+
+        let p_init = strip_casts(p_init);
+        let (buf, mutable, _) = buf_ptr(cx, p_init)?;
+
+        let (base, args) = method_call(strip_casts(end_init), "offset")?;
+        let base = strip_casts(base);
+        if !p.is_use(cx, base) && !base.ast_equiv(p_init) {
+            return None;
+        }
+
+        Some(PtrLoop { p, end, buf, len: strip_casts(&args[0]), mutable })
+    }
+}
+
+/// How the body of a pointer-walking loop accesses the pointer.
+enum Access {
+    /// Only through `*p`.
+    Deref,
+    /// Through `*p` and `*p.offset(k)`.
+    Offset,
+}
+
+/// # `ptr_loop_to_iter` Command
+///
+/// Usage: `ptr_loop_to_iter`
+///
+/// Marks: `target`
+///
+/// Replace marked loops that walk a pointer over a buffer,
+///
+///     let mut p = buf.as_mut_ptr();
+///     let end = p.offset(len);
+///     while p < end {
+///         ...;
+///         p = p.offset(1);
+///     }
+///
+/// with `for p in buf[..len as usize].iter_mut() { ...; }`, where `buf` is an
+/// array, slice or `Vec`.  The loop variable keeps the name of the pointer, so
+/// `*p` in the body reads (or writes) the current element.  A walk starting at
+/// `as_ptr()` uses `iter()` instead.  The condition may also be `p != end`.
+///
+/// If the body also reads relative to the pointer, as in `*p.offset(1)`, the
+/// loop becomes `for p_i in 0..len as usize` instead, with `*p` replaced by
+/// `buf[p_i]` and `*p.offset(k)` by `buf[p_i + k]`.
+///
+/// A `len` of signed type is clamped as `::std::cmp::max(len, 0) as usize`, so
+/// that a negative length runs the loop zero times, as the C loop did, rather
+/// than wrapping around to a huge bound.
+///
+/// Both pointer locals are removed.  Loops whose pointers are used anywhere
+/// else, including after the loop, are left alone with a warning.
+pub struct PtrLoopToIter;
+
+impl Transform for PtrLoopToIter {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        let mut mcx = MatchCtxt::new(st, cx);
+        let incr_pat = mcx.parse_expr("$p:Expr = $p.offset(cast!(1))");
+        let iter_repl = mcx.parse_expr("$buf[..$len as usize].iter()");
+        let iter_mut_repl = mcx.parse_expr("$buf[..$len as usize].iter_mut()");
+        let full_iter_repl = mcx.parse_expr("$buf.iter()");
+        let full_iter_mut_repl = mcx.parse_expr("$buf.iter_mut()");
+        let full_len_pat = mcx.parse_expr("$buf:Expr.len()");
+        let range_repl = mcx.parse_expr("0..$len as usize");
+        let clamp_repl = mcx.parse_expr("::std::cmp::max($len, 0)");
+        let index_repl = mcx.parse_expr("$buf[$i]");
+        let index_lit_repl = mcx.parse_expr("$buf[$i + $k]");
+        let index_offset_repl = mcx.parse_expr("$buf[($i as isize + $k) as usize]");
+
+        MutVisitNodes::visit(krate, |b: &mut P<Block>| {
+            let mut idx = 0;
+            while idx < b.stmts.len() {
+                let (p_idx, end_idx, new_loop) = {
+                    let stmt = &b.stmts[idx];
+                    let e = match stmt.kind {
+                        StmtKind::Expr(ref e) | StmtKind::Semi(ref e) => e,
+                        _ => { idx += 1; continue; }
+                    };
+                    if !st.marked(stmt.id, "target") && !st.marked(e.id, "target") {
+                        idx += 1;
+                        continue;
+                    }
+                    let (cond, body) = match e.kind {
+                        ExprKind::While(ref cond, ref body, _) => (cond, body),
+                        _ => { idx += 1; continue; }
+                    };
+                    let span = cx.session().source_map().span_to_string(e.span);
+
+                    let lp = match PtrLoop::recognize(cx, &b.stmts, idx, cond) {
+                        Some(lp) => lp,
+                        None => {
+                            warn!("ptr_loop_to_iter: loop at {} doesn't walk a pointer over a \
+                                   buffer; skipping", span);
+                            idx += 1;
+                            continue;
+                        }
+                    };
+
+                    // The body must end in `p = p.offset(1)`, and otherwise only use `p` through
+                    // `*p` and `*p.offset(k)`.
+                    let (last, rest) = match body.stmts.split_last() {
+                        Some(x) => x,
+                        None => { idx += 1; continue; }
+                    };
+                    let incr_ok = match last.kind {
+                        StmtKind::Semi(ref incr) | StmtKind::Expr(ref incr) => {
+                            match mcx.clone_match(&*incr_pat, &**incr) {
+                                Ok(m) => {
+                                    let p = m.bindings.get::<_, P<Expr>>("$p").unwrap();
+                                    lp.p.is_use(cx, p)
+                                }
+                                Err(_) => false,
+                            }
+                        }
+                        _ => false,
+                    };
+                    if !incr_ok {
+                        warn!("ptr_loop_to_iter: loop at {} doesn't end by advancing `{}`; \
+                               skipping", span, lp.p.ident);
+                        idx += 1;
+                        continue;
+                    }
+
+                    let (mut derefs, mut offsets) = (0, 0);
+                    for s in rest {
+                        visit_nodes(s, |e: &Expr| {
+                            if let ExprKind::Unary(UnOp::Deref, ref inner) = e.kind {
+                                if lp.p.is_use(cx, inner) {
+                                    derefs += 1;
+                                } else if let Some((base, _)) = method_call(inner, "offset") {
+                                    if lp.p.is_use(cx, base) {
+                                        offsets += 1;
+                                    }
+                                }
+                            }
+                        });
+                    }
+                    let p_uses = lp.p.count_uses(cx, rest);
+                    let end_uses = lp.end.count_uses(cx, rest);
+
+                    // Aside from their declarations and the loop itself, the pointers must not be
+                    // used anywhere in the block.
+                    let first_decl = lp.p.index.min(lp.end.index);
+                    let mut others = b.stmts[first_decl..idx].iter().enumerate()
+                        .filter(|&(i, _)| first_decl + i != lp.p.index &&
+                                          first_decl + i != lp.end.index)
+                        .map(|(_, s)| s.clone())
+                        .collect::<Vec<_>>();
+                    others.extend(b.stmts[idx + 1..].iter().cloned());
+                    let escapes = p_uses != derefs + offsets ||
+                        end_uses > 0 ||
+                        lp.p.count_uses(cx, &others) > 0 ||
+                        lp.end.count_uses(cx, &others) > 0;
+                    if escapes {
+                        warn!("ptr_loop_to_iter: `{}` escapes the loop at {}; skipping",
+                              lp.p.ident, span);
+                        idx += 1;
+                        continue;
+                    }
+
+                    let access = if offsets > 0 { Access::Offset } else { Access::Deref };
+                    let mut bnd = Bindings::new();
+                    bnd.add("$buf", lp.buf.clone());
+                    if is_unsigned_expr(cx, lp.len) {
+                        bnd.add("$len", lp.len.clone());
+                    } else {
+                        let len = match lp.len.kind {
+                            ExprKind::Paren(ref len) => len.clone(),
+                            _ => lp.len.clone(),
+                        };
+                        let mut len_bnd = Bindings::new();
+                        len_bnd.add("$len", len);
+                        bnd.add("$len", clamp_repl.clone().subst(st, cx, &len_bnd));
+                    }
+                    let covers_buf = mcx.clone_match(&*full_len_pat, &**lp.len)
+                        .map_or(false, |m| {
+                            m.bindings.get::<_, P<Expr>>("$buf").unwrap().ast_equiv(lp.buf)
+                        });
+
+                    let mut new_body = body.clone();
+                    new_body.stmts.pop();
+                    let (pat_ident, iter) = match access {
+                        Access::Deref => {
+                            let repl = match (lp.mutable, covers_buf) {
+                                (false, false) => &iter_repl,
+                                (true, false) => &iter_mut_repl,
+                                (false, true) => &full_iter_repl,
+                                (true, true) => &full_iter_mut_repl,
+                            };
+                            (lp.p.ident, repl.clone().subst(st, cx, &bnd))
+                        }
+                        Access::Offset => {
+                            let i = Ident::from_str(&format!("{}_i", lp.p.ident));
+                            bnd.add("$i", mk().ident_expr(i));
+                            MutVisitNodes::visit(&mut new_body, |e: &mut P<Expr>| {
+                                let inner = match e.kind {
+                                    ExprKind::Unary(UnOp::Deref, ref inner) => inner,
+                                    _ => return,
+                                };
+                                let offset = method_call(inner, "offset")
+                                    .filter(|&(base, _)| lp.p.is_use(cx, base));
+                                let repl = if lp.p.is_use(cx, inner) {
+                                    index_repl.clone().subst(st, cx, &bnd)
+                                } else if let Some((_, args)) = offset {
+                                    let k = strip_casts(&args[0]);
+                                    let mut bnd = bnd.clone();
+                                    let repl = if let ExprKind::Lit(_) = k.kind {
+                                        bnd.add("$k", k.clone());
+                                        &index_lit_repl
+                                    } else {
+                                        bnd.add("$k", args[0].clone());
+                                        &index_offset_repl
+                                    };
+                                    repl.clone().subst(st, cx, &bnd)
+                                } else {
+                                    return;
+                                };
+                                *e = repl;
+                            });
+                            (i, range_repl.clone().subst(st, cx, &bnd))
+                        }
+                    };
+
+                    let label = match e.kind {
+                        ExprKind::While(_, _, label) => label,
+                        _ => unreachable!(),
+                    };
+                    let mut new_loop = e.clone();
+                    new_loop.kind = ExprKind::ForLoop(
+                        mk().ident_pat(pat_ident), iter, new_body, label);
+                    (lp.p.index, lp.end.index, new_loop)
+                };
+
+                match b.stmts[idx].kind {
+                    StmtKind::Expr(ref mut e) | StmtKind::Semi(ref mut e) => *e = new_loop,
+                    _ => unreachable!(),
+                }
+                b.stmts.remove(p_idx.max(end_idx));
+                b.stmts.remove(p_idx.min(end_idx));
+                // The loop moved up by two statements; continue after it.
+                idx -= 1;
+            }
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
+/// # `offset_to_index` Command
+///
+/// Usage: `offset_to_index`
+///
+/// Replace dereferences of pointers into a buffer with indexing, so that
+/// `*p.offset(i as isize)` becomes `buf[i as usize]`, both when reading and
+/// when writing.  Offsets applied one after another are added up, as in
+/// `*p.offset(i).offset(j)`, which becomes `buf[(i + j) as usize]`, and `*p`
+/// becomes `buf[0]`.  Out-of-bounds accesses panic instead of being undefined.
+///
+/// The pointer may be `buf.as_ptr()` or `buf.as_mut_ptr()` itself, where `buf`
+/// is an array, slice or `Vec`, or a local initialized with one of those that
+/// points to elements of the same type.  A local is only converted if `buf` can
+/// be evaluated again without side effects and the local is used for nothing
+/// but dereferences, so pointers that are reassigned or passed elsewhere are
+/// left alone, and its declaration is removed.
+///
+/// `unsafe` blocks that needed to be `unsafe` before, but no longer perform any
+/// unsafe operation, become ordinary blocks.
+pub struct OffsetToIndex;
+
+impl Transform for OffsetToIndex {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        // (1) Find the pointer locals to convert.
+
+        let mut bufs = HashMap::new();
+        let mut decls = HashSet::new();
+        visit_nodes(krate, |b: &Block| {
+            for (idx, s) in b.stmts.iter().enumerate() {
+                let (p, init) = match_or!([PtrLocal::from_stmt(cx, idx, s)] Some(x) => x; continue);
+                let (buf, _, elem) = match_or!([buf_ptr(cx, strip_casts(init))] Some(x) => x;
+                                               continue);
+                let same_elem = match cx.opt_node_type(init.id).map(|ty| &ty.kind) {
+                    Some(&TyKind::RawPtr(ty::TypeAndMut { ty, .. })) => ty == elem,
+                    _ => false,
+                };
+                if !same_elem || !is_simple_place(buf) {
+                    continue;
+                }
+
+                let rest = &b.stmts[idx + 1..];
+                let mut derefs = 0;
+                for stmt in rest {
+                    visit_nodes(stmt, |e: &Expr| {
+                        if let ExprKind::Unary(UnOp::Deref, ref inner) = e.kind {
+                            if p.is_use(cx, offset_chain(inner).0) {
+                                derefs += 1;
+                            }
+                        }
+                    });
+                }
+                if derefs > 0 && p.count_uses(cx, rest) == derefs {
+                    bufs.insert(p.hir_id, buf.clone());
+                    decls.insert(s.id);
+                }
+            }
+        });
+
+        // (2) Remember which `unsafe` blocks are needed, to find the ones that no longer are.
+
+        let no_safe_fns = HashSet::new();
+        let mut needed = HashSet::new();
+        visit_nodes(krate, |b: &Block| {
+            if b.rules == BlockCheckMode::Unsafe(UnsafeSource::UserProvided) &&
+               needs_unsafe(cx, &no_safe_fns, b) {
+                needed.insert(b.id);
+            }
+        });
+
+        // (3) Rewrite the dereferences.
+
+        let mut mcx = MatchCtxt::new(st, cx);
+        let index_repl = mcx.parse_expr("$buf[$i as usize]");
+        let index_lit_repl = mcx.parse_expr("$buf[$i]");
+        let add_repl = mcx.parse_expr("$a + $b");
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            let new_e = {
+                let inner = match_or!([e.kind] ExprKind::Unary(UnOp::Deref, ref x) => x; return);
+                let (base, offsets) = offset_chain(inner);
+                let buf = match cx.try_resolve_expr_hir(base) {
+                    Some(Res::Local(hir_id)) if bufs.contains_key(&hir_id) => &bufs[&hir_id],
+                    _ => match_or!([buf_ptr(cx, base)] Some((buf, _, _)) => buf; return),
+                };
+
+                let mut bnd = Bindings::new();
+                bnd.add("$buf", buf.clone());
+                let repl = match offsets[..] {
+                    [] => {
+                        bnd.add("$i", parse_expr(cx.session(), "0"));
+                        &index_lit_repl
+                    }
+                    [off] => match off.kind {
+                        ExprKind::Lit(_) => {
+                            bnd.add("$i", off.clone());
+                            &index_lit_repl
+                        }
+                        ExprKind::Cast(ref i, _) => {
+                            bnd.add("$i", i.clone());
+                            &index_repl
+                        }
+                        _ => {
+                            bnd.add("$i", off.clone());
+                            &index_repl
+                        }
+                    },
+                    _ => {
+                        let sum = offsets[1..].iter().fold(offsets[0].clone(), |a, &b| {
+                            let mut bnd = Bindings::new();
+                            bnd.add("$a", a);
+                            bnd.add("$b", b.clone());
+                            add_repl.clone().subst(st, cx, &bnd)
+                        });
+                        bnd.add("$i", sum);
+                        &index_repl
+                    }
+                };
+                repl.clone().subst(st, cx, &bnd)
+            };
+            *e = new_e;
+        });
+
+        // (4) Remove the declarations of the converted locals, and the `unsafe` from blocks that
+        // don't need it anymore.  Blocks holding a single expression are unwrapped.
+
+        let unneeded = |b: &Block| needed.contains(&b.id) && !needs_unsafe(cx, &no_safe_fns, b);
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            let inner = match e.kind {
+                ExprKind::Block(ref b, None) if unneeded(b) => match &b.stmts[..] {
+                    [Stmt { kind: StmtKind::Expr(inner), .. }] => inner.clone(),
+                    _ => return,
+                },
+                _ => return,
+            };
+            *e = inner;
+        });
+        MutVisitNodes::visit(krate, |b: &mut P<Block>| {
+            b.stmts.retain(|s| !decls.contains(&s.id));
+            if unneeded(b) {
+                b.rules = BlockCheckMode::Default;
+            }
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+pub fn register_commands(reg: &mut Registry) {
+    use super::mk;
+
+    reg.register("ptr_loop_to_iter", |_args| mk(PtrLoopToIter));
+    reg.register("offset_to_index", |_args| mk(OffsetToIndex));
+}
@@ -0,0 +1,269 @@
+//! Transform that replaces the C `rand` and `srand` with the `rand` crate.
+
+use std::collections::HashSet;
+use rustc::hir::def_id::DefId;
+use syntax::ast::*;
+use syntax::ptr::P;
+
+use c2rust_ast_builder::mk;
+use crate::ast_manip::{MutVisitNodes, visit_nodes};
+use crate::ast_manip::fn_edit::{mut_visit_fns, visit_fns};
+use crate::command::{CommandState, Registry};
+use crate::driver::{self, Phase};
+use crate::transform::Transform;
+use crate::transform::assert::foreign_call;
+use crate::RefactorCtxt;
+
+
+/// # `rand_to_rand_crate` Command
+///
+/// Usage: `rand_to_rand_crate [mode=thread_local|param] [fix_bias=1]`
+///
+/// Marks: `target` (with `mode=param`)
+///
+/// Replace the C random number generator with a `StdRng` from the `rand`
+/// crate.  `srand(seed)` reseeds it with `SeedableRng::seed_from_u64(seed as
+/// u64)`, and `rand()` becomes `rng.gen_range(0..=libc::RAND_MAX)`, so the
+/// values keep their range.  Like the C generator, it starts out seeded with
+/// `1`.  The generated helpers `rand_seed`, `rand_next` and `rand_below` are
+/// added to the crate root, along with `extern crate rand;` and the `use`s
+/// they need.  The crate needs `rand = "0.8"` among its dependencies, which the
+/// command notes.
+///
+/// With `mode=thread_local`, the default, the generator is a `thread_local!`
+/// at the crate root, and every call of `rand` and `srand` is rewritten, like
+/// `crate::rand_next()`.
+///
+/// With `mode=param`, the marked functions take the generator as a new last
+/// parameter, `rng: &mut StdRng`, which their calls of `rand`, `srand` and of
+/// other marked functions use, as in `crate::rand_next(rng)`.  A function that
+/// isn't marked but calls marked ones gets a generator of its own in a new
+/// local, `rng`, seeded with `1` each time the function runs, which is noted.
+/// The calls of `rand` and `srand` in other functions are left alone, with a
+/// warning.
+///
+/// `rand() % n` keeps its modulo bias unless `fix_bias=1` is passed, which
+/// turns it into `rng.gen_range(0..n)`.  That panics if `n` isn't positive,
+/// where C would do the modulo of a negative number or divide by zero.
+pub struct RandToRandCrate {
+    pub mode: RngMode,
+    pub fix_bias: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RngMode {
+    ThreadLocal,
+    Param,
+}
+
+/// If `e` is a call of `rand()`.
+fn is_rand_call(cx: &RefactorCtxt, e: &Expr) -> bool {
+    foreign_call(cx, e, "rand").map_or(false, |args| args.is_empty())
+}
+
+impl Transform for RandToRandCrate {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        let param = self.mode == RngMode::Param;
+        let warn_skip = |e: &Expr| {
+            warn!("rand_to_rand_crate: skipping call outside the marked functions and their \
+                   callers at {}", cx.session().source_map().span_to_string(e.span));
+        };
+
+        // (1) Find the marked functions, and the `rand()` calls that are taken modulo something,
+        // which are rewritten along with the `%`.
+
+        let mut marked_fns: HashSet<DefId> = HashSet::new();
+        if param {
+            visit_fns(krate, |fl| {
+                if st.marked(fl.id, "target") {
+                    marked_fns.insert(cx.node_def_id(fl.id));
+                }
+            });
+        }
+
+        let mut rem_rands = HashSet::new();
+        if self.fix_bias {
+            visit_nodes(krate, |e: &Expr| {
+                if let ExprKind::Binary(op, ref l, _) = e.kind {
+                    if op.node == BinOpKind::Rem && is_rand_call(cx, l) {
+                        rem_rands.insert(l.id);
+                    }
+                }
+            });
+        }
+
+        // (2) Rewrite the calls, function by function.
+
+        let mut rewrote = false;
+        let mut used_below = false;
+        mut_visit_fns(krate, |fl| {
+            let is_marked = marked_fns.contains(&cx.node_def_id(fl.id));
+            let mut calls_marked = false;
+            if let Some(ref block) = fl.block {
+                visit_nodes(&**block, |e: &Expr| {
+                    if let ExprKind::Call(ref func, _) = e.kind {
+                        if cx.try_resolve_expr(func).map_or(false, |id| marked_fns.contains(&id)) {
+                            calls_marked = true;
+                        }
+                    }
+                });
+            }
+
+            // The arguments that refer to the generator.
+            let rng_args = |e: &Expr| -> Option<Vec<P<Expr>>> {
+                if !param {
+                    Some(vec![])
+                } else if is_marked {
+                    Some(vec![mk().ident_expr("rng")])
+                } else if calls_marked {
+                    Some(vec![mk().mutbl().addr_of_expr(mk().ident_expr("rng"))])
+                } else {
+                    warn_skip(e);
+                    None
+                }
+            };
+            let helper_call = |name: &str, rng: Vec<P<Expr>>, args: Vec<P<Expr>>| {
+                let mut all_args = rng;
+                all_args.extend(args);
+                mk().call_expr(mk().path_expr(vec!["crate", name]), all_args)
+            };
+
+            MutVisitNodes::visit(&mut fl.block, |e: &mut P<Expr>| {
+                if rem_rands.contains(&e.id) {
+                    return;
+                }
+                let new_e = if let ExprKind::Binary(op, ref l, ref r) = e.kind {
+                    if op.node != BinOpKind::Rem || !rem_rands.contains(&l.id) {
+                        return;
+                    }
+                    let rng = match_or!([rng_args(e)] Some(x) => x; return);
+                    used_below = true;
+                    helper_call("rand_below", rng, vec![r.clone()])
+                } else if is_rand_call(cx, e) {
+                    let rng = match_or!([rng_args(e)] Some(x) => x; return);
+                    helper_call("rand_next", rng, vec![])
+                } else if let Some(args) = foreign_call(cx, e, "srand") {
+                    if args.len() != 1 {
+                        return;
+                    }
+                    let rng = match_or!([rng_args(e)] Some(x) => x; return);
+                    helper_call("rand_seed", rng, vec![args[0].clone()])
+                } else if let ExprKind::Call(ref func, ref args) = e.kind {
+                    if !cx.try_resolve_expr(func).map_or(false, |id| marked_fns.contains(&id)) {
+                        return;
+                    }
+                    let mut args = args.clone();
+                    args.extend(rng_args(e).unwrap());
+                    mk().call_expr(func.clone(), args)
+                } else {
+                    return;
+                };
+                rewrote = true;
+                *e = new_e;
+            });
+
+            if is_marked {
+                fl.decl.inputs.push(
+                    driver::parse_arg(cx.session(), "rng: &mut ::rand::rngs::StdRng"));
+            } else if param && calls_marked {
+                warn!("rand_to_rand_crate: {} calls marked functions, so it gets a generator of \
+                       its own, seeded with 1", fl.ident);
+                if let Some(ref mut block) = fl.block {
+                    let local = driver::parse_stmts(cx.session(), "
+                        let mut rng: ::rand::rngs::StdRng = ::rand::SeedableRng::seed_from_u64(1);
+                    ");
+                    block.stmts.splice(0..0, local);
+                }
+            }
+        });
+
+        if !rewrote {
+            return;
+        }
+
+        // (3) Add the generator and its helpers to the crate root.
+
+        let pos = krate.module.items.iter()
+            .rposition(|i| match i.kind {
+                ItemKind::ExternCrate(_) => true,
+                _ => false,
+            })
+            .map_or(0, |i| i + 1);
+        let mut uses = st.parse_items(cx, r#"
+            use rand::rngs::StdRng;
+            use rand::{Rng, SeedableRng};
+        "#);
+        let has_extern_crate = krate.module.items.iter().any(|i| match i.kind {
+            ItemKind::ExternCrate(_) => &*i.ident.as_str() == "rand",
+            _ => false,
+        });
+        if !has_extern_crate {
+            uses.splice(0..0, st.parse_items(cx, "extern crate rand;"));
+        }
+        krate.module.items.splice(pos..pos, uses);
+
+        let mut helpers = match self.mode {
+            RngMode::ThreadLocal => String::from(r#"
+                thread_local! {
+                    static RAND_RNG: ::std::cell::RefCell<StdRng> =
+                        ::std::cell::RefCell::new(StdRng::seed_from_u64(1));
+                }
+
+                fn rand_seed(seed: libc::c_uint) {
+                    RAND_RNG.with(|rng| *rng.borrow_mut() = StdRng::seed_from_u64(seed as u64));
+                }
+
+                fn rand_next() -> libc::c_int {
+                    RAND_RNG.with(|rng| rng.borrow_mut().gen_range(0..=libc::RAND_MAX))
+                }
+            "#),
+            RngMode::Param => String::from(r#"
+                fn rand_seed(rng: &mut StdRng, seed: libc::c_uint) {
+                    *rng = StdRng::seed_from_u64(seed as u64);
+                }
+
+                fn rand_next(rng: &mut StdRng) -> libc::c_int {
+                    rng.gen_range(0..=libc::RAND_MAX)
+                }
+            "#),
+        };
+        if used_below {
+            helpers.push_str(match self.mode {
+                RngMode::ThreadLocal => r#"
+                    fn rand_below(n: libc::c_int) -> libc::c_int {
+                        RAND_RNG.with(|rng| rng.borrow_mut().gen_range(0..n))
+                    }
+                "#,
+                RngMode::Param => r#"
+                    fn rand_below(rng: &mut StdRng, n: libc::c_int) -> libc::c_int {
+                        rng.gen_range(0..n)
+                    }
+                "#,
+            });
+        }
+        krate.module.items.extend(st.parse_items(cx, &helpers));
+
+        warn!("rand_to_rand_crate: add `rand = \"0.8\"` to the dependencies of the crate");
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
+pub fn register_commands(reg: &mut Registry) {
+    use super::mk;
+
+    reg.register("rand_to_rand_crate", |args| mk(RandToRandCrate {
+        mode: match args.iter().find(|arg| arg.starts_with("mode=")) {
+            None => RngMode::ThreadLocal,
+            Some(arg) => match &arg["mode=".len()..] {
+                "thread_local" => RngMode::ThreadLocal,
+                "param" => RngMode::Param,
+                mode => panic!("rand_to_rand_crate: unknown mode `{}`", mode),
+            },
+        },
+        fix_bias: args.iter().any(|arg| arg == "fix_bias=1"),
+    }));
+}
@@ -0,0 +1,639 @@
+//! Transform that turns the `current_block` state machines emitted by the translator back into
+//! structured control flow.
+
+use std::collections::{HashMap, HashSet};
+use syntax::ast::*;
+use syntax::ptr::P;
+use syntax::symbol::Symbol;
+use syntax::visit;
+
+use c2rust_ast_builder::mk;
+use crate::ast_manip::{visit_nodes, MutVisitNodes};
+use crate::ast_manip::fn_edit::mut_visit_fns;
+use crate::command::{CommandState, Registry};
+use crate::transform::Transform;
+use crate::transform::control_flow::LoopJumpFinder;
+use crate::transform::enums::lit_value;
+use crate::RefactorCtxt;
+
+
+/// # `relooper_cleanup` Command
+///
+/// Usage: `relooper_cleanup`
+///
+/// Rewrite the state machines the translator emits for C `goto`s into
+/// structured control flow.  A state machine is a local like
+/// `current_block: u64` that is set to the label of the first block and then
+/// dispatched on:
+///
+/// ```ignore
+///     let mut current_block: u64;
+///     current_block = 1;
+///     loop {
+///         match current_block {
+///             1 => {
+///                 a();
+///                 if c() {
+///                     current_block = 3;
+///                 } else {
+///                     current_block = 2;
+///                 }
+///             }
+///             2 => {
+///                 b();
+///                 current_block = 1;
+///             }
+///             3 => {
+///                 break;
+///             }
+///             _ => {}
+///         }
+///     }
+/// ```
+///
+/// Each arm of the `match` is a block of the C function.  It ends by setting
+/// `current_block` to the block to run next, possibly followed by `continue`,
+/// by `break`ing out of the state machine, or by returning, and may choose
+/// between these with `if`s.  When the graph of blocks is reducible, the state
+/// machine is replaced with the code of its blocks, in `loop`s and `if`s that
+/// follow the same paths, and the state variable is removed.  The example
+/// above becomes
+///
+/// ```ignore
+///     loop {
+///         a();
+///         if c() {
+///             break;
+///         }
+///         b();
+///     }
+/// ```
+///
+/// Blocks that can't be reached from the first one are dropped.  State
+/// machines whose graph is irreducible, or that would need labeled `break`s or
+/// code duplicated between paths, are left alone with a note naming the
+/// function.  So are state variables used anywhere else.
+pub struct RelooperCleanup;
+
+/// How a block of the state machine ends.
+enum Term {
+    /// Run another block next.
+    Goto(usize),
+    /// Run one of two blocks, depending on a condition.
+    If(P<Expr>, usize, usize),
+    /// Return from the function.  The `return` is the last of the block's statements.
+    Return,
+    /// Leave the state machine.  Only the `EXIT` node ends like this.
+    Exit,
+}
+
+struct Node {
+    stmts: Vec<Stmt>,
+    term: Term,
+}
+
+/// The node that stands for the code after the state machine.
+const EXIT: usize = 0;
+
+/// Reads the arms of the dispatching `match` into a graph of blocks.  Besides the arms, branches
+/// of `if`s that jump get nodes of their own.
+struct GraphBuilder {
+    var: Symbol,
+    label: Option<Ident>,
+    keys: HashMap<i128, usize>,
+    nodes: Vec<Node>,
+    /// The number of uses of the state variable the graph accounts for.
+    uses: usize,
+}
+
+/// Check if `e` is a path naming the local `var`.
+fn is_var(e: &Expr, var: Symbol) -> bool {
+    match e.kind {
+        ExprKind::Path(None, ref path) => path.segments.len() == 1 &&
+            path.segments[0].ident.name == var,
+        _ => false,
+    }
+}
+
+fn stmt_expr(s: &Stmt) -> Option<&P<Expr>> {
+    match s.kind {
+        StmtKind::Semi(ref e) | StmtKind::Expr(ref e) => Some(e),
+        _ => None,
+    }
+}
+
+impl GraphBuilder {
+    fn new_node(&mut self) -> usize {
+        self.nodes.push(Node { stmts: Vec::new(), term: Term::Exit });
+        self.nodes.len() - 1
+    }
+
+    fn targets_loop(&self, label: &Option<Label>) -> bool {
+        label.map_or(true, |l| Some(l.ident) == self.label)
+    }
+
+    /// If `s` is `current_block = K;` for a block `K`, return the node of the block.
+    fn state_write(&self, s: &Stmt) -> Option<usize> {
+        let (lhs, rhs) = match_or!([stmt_expr(s)?.kind] ExprKind::Assign(ref l, ref r) => (l, r);
+                                   return None);
+        if !is_var(lhs, self.var) {
+            return None;
+        }
+        self.keys.get(&lit_value(rhs)?).cloned()
+    }
+
+    fn is_break(&self, s: &Stmt) -> bool {
+        match stmt_expr(s).map(|e| &e.kind) {
+            Some(ExprKind::Break(ref label, None)) => self.targets_loop(label),
+            _ => false,
+        }
+    }
+
+    fn is_continue(&self, s: &Stmt) -> bool {
+        match stmt_expr(s).map(|e| &e.kind) {
+            Some(ExprKind::Continue(ref label)) => self.targets_loop(label),
+            _ => false,
+        }
+    }
+
+    /// Check if `s` uses the state variable or jumps out of the state machine.
+    fn has_jumps(&self, s: &Stmt) -> bool {
+        let mut uses_var = false;
+        visit_nodes(s, |e: &Expr| {
+            if is_var(e, self.var) {
+                uses_var = true;
+            }
+        });
+        let mut finder = LoopJumpFinder::new(self.label);
+        visit::walk_stmt(&mut finder, s);
+        uses_var || finder.breaks > 0 || finder.continues > 0
+    }
+
+    /// Read the block `stmts` into the node `idx`.
+    fn read_block(&mut self, idx: usize, stmts: &[Stmt]) -> Option<()> {
+        let mut body = Vec::new();
+        let mut i = 0;
+        let term = loop {
+            // Running off the end would dispatch on the same block again.
+            let s = stmts.get(i)?;
+            let rest = &stmts[i + 1..];
+            if let Some(target) = self.state_write(s) {
+                if rest.len() > 1 || !rest.iter().all(|s| self.is_continue(s)) {
+                    return None;
+                }
+                self.uses += 1;
+                break Term::Goto(target);
+            }
+            if self.is_break(s) {
+                if !rest.is_empty() {
+                    return None;
+                }
+                break Term::Goto(EXIT);
+            }
+            if !self.has_jumps(s) {
+                body.push(s.clone());
+                if let Some(&ExprKind::Ret(_)) = stmt_expr(s).map(|e| &e.kind) {
+                    if !rest.is_empty() {
+                        return None;
+                    }
+                    break Term::Return;
+                }
+                i += 1;
+                continue;
+            }
+
+            let (cond, then, els) = match_or!([stmt_expr(s)?.kind]
+                                              ExprKind::If(ref c, ref t, ref e) => (c, t, e);
+                                              return None);
+            let t = self.new_node();
+            self.read_block(t, &then.stmts)?;
+            let e = self.new_node();
+            match *els {
+                None => self.read_block(e, rest)?,
+                Some(ref els) => {
+                    if !rest.is_empty() {
+                        return None;
+                    }
+                    match els.kind {
+                        ExprKind::Block(ref b, None) => self.read_block(e, &b.stmts)?,
+                        _ => self.read_block(e, &[mk().expr_stmt(els.clone())])?,
+                    }
+                }
+            }
+            break Term::If(cond.clone(), t, e);
+        };
+        self.nodes[idx] = Node { stmts: body, term };
+        Some(())
+    }
+}
+
+fn succs(node: &Node) -> Vec<usize> {
+    match node.term {
+        Term::Goto(n) => vec![n],
+        Term::If(_, t, e) => vec![t, e],
+        Term::Return | Term::Exit => vec![],
+    }
+}
+
+struct Loop {
+    body: HashSet<usize>,
+    /// The block that runs after the loop, if it can exit other than by returning.
+    follow: Option<usize>,
+}
+
+/// The ways turning a state machine into structured code can fail.
+enum Failure {
+    Irreducible,
+    Unstructured,
+}
+
+/// Find the loops of the graph `nodes` starting at `entry`, by their headers.
+fn find_loops(nodes: &[Node], entry: usize) -> Result<HashMap<usize, Loop>, Failure> {
+    // Reachable nodes, in depth-first preorder.
+    let mut order = Vec::new();
+    let mut seen = HashSet::new();
+    let mut stack = vec![entry];
+    while let Some(n) = stack.pop() {
+        if seen.insert(n) {
+            order.push(n);
+            stack.extend(succs(&nodes[n]).into_iter().rev());
+        }
+    }
+    let mut preds: HashMap<usize, Vec<usize>> = HashMap::new();
+    for &n in &order {
+        for s in succs(&nodes[n]) {
+            preds.entry(s).or_default().push(n);
+        }
+    }
+
+    let all: HashSet<usize> = order.iter().cloned().collect();
+    let mut doms: HashMap<usize, HashSet<usize>> =
+        order.iter().map(|&n| (n, all.clone())).collect();
+    doms.insert(entry, Some(entry).into_iter().collect());
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for &n in order.iter().filter(|&&n| n != entry) {
+            let mut new = preds[&n].iter()
+                .map(|p| doms[p].clone())
+                .fold(None, |acc: Option<HashSet<usize>>, d| Some(match acc {
+                    Some(acc) => acc.intersection(&d).cloned().collect(),
+                    None => d,
+                }))
+                .unwrap_or_default();
+            new.insert(n);
+            if new != doms[&n] {
+                doms.insert(n, new);
+                changed = true;
+            }
+        }
+    }
+
+    // Every edge back to a node on the depth-first path must go to a dominator.
+    let mut back_edges = Vec::new();
+    let mut on_path = HashSet::new();
+    let mut visited = HashSet::new();
+    fn dfs(nodes: &[Node], n: usize, doms: &HashMap<usize, HashSet<usize>>,
+           on_path: &mut HashSet<usize>, visited: &mut HashSet<usize>,
+           back_edges: &mut Vec<(usize, usize)>) -> Result<(), Failure> {
+        visited.insert(n);
+        on_path.insert(n);
+        for s in succs(&nodes[n]) {
+            if on_path.contains(&s) {
+                if !doms[&n].contains(&s) {
+                    return Err(Failure::Irreducible);
+                }
+                back_edges.push((n, s));
+            } else if !visited.contains(&s) {
+                dfs(nodes, s, doms, on_path, visited, back_edges)?;
+            }
+        }
+        on_path.remove(&n);
+        Ok(())
+    }
+    dfs(nodes, entry, &doms, &mut on_path, &mut visited, &mut back_edges)?;
+
+    let mut loops: HashMap<usize, Loop> = HashMap::new();
+    for (latch, header) in back_edges {
+        let lp = loops.entry(header).or_insert_with(|| Loop {
+            body: Some(header).into_iter().collect(),
+            follow: None,
+        });
+        let mut work = vec![latch];
+        while let Some(n) = work.pop() {
+            if lp.body.insert(n) {
+                work.extend(preds[&n].iter().cloned());
+            }
+        }
+    }
+    for lp in loops.values_mut() {
+        let exits = lp.body.iter()
+            .flat_map(|&n| succs(&nodes[n]))
+            .filter(|s| !lp.body.contains(s))
+            .collect::<HashSet<_>>();
+        if exits.len() > 1 {
+            return Err(Failure::Unstructured);
+        }
+        lp.follow = exits.into_iter().next();
+    }
+    Ok(loops)
+}
+
+/// Builds the structured code for a graph of blocks.
+struct Emitter<'a> {
+    nodes: &'a [Node],
+    loops: &'a HashMap<usize, Loop>,
+    emitted: HashSet<usize>,
+    /// The headers of the loops being emitted, innermost last.
+    ctx: Vec<usize>,
+}
+
+impl<'a> Emitter<'a> {
+    /// The first block that both `a` and `b` always reach inside the innermost loop, or the
+    /// innermost loop's header or follow.
+    fn join(&self, a: usize, b: usize) -> Option<usize> {
+        let header = self.ctx.last().cloned();
+        let in_region = |n: usize| match header {
+            Some(h) => n != h && self.loops[&h].body.contains(&n),
+            None => true,
+        };
+
+        let mut region = Vec::new();
+        let mut work = vec![a, b];
+        let mut seen = HashSet::new();
+        while let Some(n) = work.pop() {
+            if seen.insert(n) && in_region(n) {
+                region.push(n);
+                work.extend(succs(&self.nodes[n]));
+            }
+        }
+
+        // Post-dominators; nodes outside the region end the paths.
+        let all: HashSet<usize> = seen.iter().cloned().collect();
+        let mut pdoms: HashMap<usize, HashSet<usize>> = seen.iter()
+            .map(|&n| (n, if in_region(n) { all.clone() } else { Some(n).into_iter().collect() }))
+            .collect();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &n in &region {
+                let mut new = succs(&self.nodes[n]).iter()
+                    .map(|s| pdoms[s].clone())
+                    .fold(None, |acc: Option<HashSet<usize>>, d| Some(match acc {
+                        Some(acc) => acc.intersection(&d).cloned().collect(),
+                        None => d,
+                    }))
+                    .unwrap_or_default();
+                new.insert(n);
+                if new != pdoms[&n] {
+                    pdoms.insert(n, new);
+                    changed = true;
+                }
+            }
+        }
+
+        let common = pdoms[&a].intersection(&pdoms[&b]).cloned().collect::<Vec<_>>();
+        common.into_iter().max_by_key(|n| pdoms[n].len())
+    }
+
+    /// Emit the code that runs from block `n` on, until it reaches `stop`.
+    fn emit_from(&mut self, mut n: usize, stop: Option<usize>, out: &mut Vec<Stmt>)
+                 -> Result<(), Failure> {
+        loop {
+            if Some(n) == stop {
+                return Ok(());
+            }
+            if let Some(&h) = self.ctx.last() {
+                if n == h {
+                    out.push(mk().semi_stmt(mk().continue_expr(None::<Ident>)));
+                    return Ok(());
+                }
+                if Some(n) == self.loops[&h].follow {
+                    out.push(mk().semi_stmt(mk().break_expr(None::<Ident>)));
+                    return Ok(());
+                }
+            }
+            // Jumps to outer loops would need labels.
+            if self.ctx.iter().any(|h| *h == n || self.loops[h].follow == Some(n)) {
+                return Err(Failure::Unstructured);
+            }
+            if n == EXIT {
+                return Ok(());
+            }
+
+            let loops = self.loops;
+            if let Some(lp) = loops.get(&n) {
+                self.ctx.push(n);
+                let mut body = Vec::new();
+                let res = self.emit_block(n, &mut body).and_then(|next| match next {
+                    Some(next) => self.emit_from(next, None, &mut body),
+                    None => Ok(()),
+                });
+                self.ctx.pop();
+                res?;
+                if let Some(&ExprKind::Continue(None)) = body.last().and_then(stmt_expr)
+                                                           .map(|e| &e.kind) {
+                    body.pop();
+                }
+                out.push(mk().expr_stmt(mk().loop_expr(mk().block(body), None::<Ident>)));
+                match lp.follow {
+                    Some(f) => n = f,
+                    None => return Ok(()),
+                }
+                continue;
+            }
+
+            match self.emit_block(n, out)? {
+                Some(next) => n = next,
+                None => return Ok(()),
+            }
+        }
+    }
+
+    /// Emit the code of block `n`, and return the block that runs after it, if any.
+    fn emit_block(&mut self, n: usize, out: &mut Vec<Stmt>) -> Result<Option<usize>, Failure> {
+        // Blocks reached along separate paths would have to be duplicated.
+        if !self.emitted.insert(n) {
+            return Err(Failure::Unstructured);
+        }
+        let node = &self.nodes[n];
+        out.extend(node.stmts.iter().cloned());
+        let (cond, t, e) = match node.term {
+            Term::Goto(next) => return Ok(Some(next)),
+            Term::Return | Term::Exit => return Ok(None),
+            Term::If(ref cond, t, e) => (cond.clone(), t, e),
+        };
+
+        let join = self.join(t, e);
+        let mut then_stmts = Vec::new();
+        self.emit_from(t, join, &mut then_stmts)?;
+        let mut else_stmts = Vec::new();
+        self.emit_from(e, join, &mut else_stmts)?;
+
+        let diverges = match then_stmts.last().and_then(stmt_expr).map(|e| &e.kind) {
+            Some(ExprKind::Break(..)) | Some(ExprKind::Continue(..)) | Some(ExprKind::Ret(..)) =>
+                true,
+            _ => false,
+        };
+        let if_expr = if then_stmts.is_empty() && !else_stmts.is_empty() {
+            let cond = mk().unary_expr("!", cond);
+            mk().ifte_expr(cond, mk().block(else_stmts.split_off(0)), None)
+        } else if diverges || else_stmts.is_empty() {
+            mk().ifte_expr(cond, mk().block(then_stmts), None)
+        } else {
+            let els = mk().block_expr(mk().block(else_stmts.split_off(0)));
+            mk().ifte_expr(cond, mk().block(then_stmts), Some(els))
+        };
+        out.push(mk().expr_stmt(if_expr));
+        out.extend(else_stmts);
+        Ok(join)
+    }
+}
+
+/// Count the uses of the local `var` in `stmts`.
+fn count_uses(stmts: &[Stmt], var: Symbol) -> usize {
+    let mut count = 0;
+    for s in stmts {
+        visit_nodes(s, |e: &Expr| {
+            if is_var(e, var) {
+                count += 1;
+            }
+        });
+    }
+    count
+}
+
+/// If `s` declares a state variable, return its name and its initial value, if any.
+fn state_decl(s: &Stmt) -> Option<(Symbol, Option<&P<Expr>>)> {
+    let l = match_or!([s.kind] StmtKind::Local(ref l) => l; return None);
+    let ident = match_or!([l.pat.kind] PatKind::Ident(_, ident, None) => ident; return None);
+    if !ident.as_str().starts_with("current_block") {
+        return None;
+    }
+    Some((ident.name, l.init.as_ref()))
+}
+
+/// Rewrite the state machine declared by `b.stmts[decl]`.
+fn clean_up_state_machine(b: &mut Block, decl: usize) -> Result<bool, Failure> {
+    let (var, init) = state_decl(&b.stmts[decl]).unwrap();
+    let init = init.cloned();
+
+    // The dispatch loop, after the initial assignment.
+    let pos = match b.stmts[decl + 1..].iter().position(|s| match stmt_expr(s) {
+        Some(e) => match e.kind {
+            ExprKind::Loop(ref body, _) => body.stmts.len() == 1 &&
+                stmt_expr(&body.stmts[0]).map_or(false, |e| match e.kind {
+                    ExprKind::Match(ref scrut, _) => is_var(scrut, var),
+                    _ => false,
+                }),
+            _ => false,
+        },
+        None => false,
+    }) {
+        Some(pos) => decl + 1 + pos,
+        None => return Ok(false),
+    };
+    let (init_stmt, entry_val) = match b.stmts.get(pos - 1).and_then(stmt_expr).map(|e| &e.kind) {
+        Some(ExprKind::Assign(ref lhs, ref rhs)) if pos - 1 > decl && is_var(lhs, var) =>
+            (Some(pos - 1), lit_value(rhs)),
+        _ => (None, init.as_ref().and_then(|e| lit_value(e))),
+    };
+    let entry_val = match_or!([entry_val] Some(x) => x; return Ok(false));
+
+    let (label, arms) = match stmt_expr(&b.stmts[pos]).unwrap().kind {
+        ExprKind::Loop(ref body, label) => match stmt_expr(&body.stmts[0]).unwrap().kind {
+            ExprKind::Match(_, ref arms) => (label.map(|l| l.ident), arms),
+            _ => unreachable!(),
+        },
+        _ => unreachable!(),
+    };
+
+    let mut builder = GraphBuilder {
+        var,
+        label,
+        keys: HashMap::new(),
+        nodes: vec![Node { stmts: Vec::new(), term: Term::Exit }],
+        uses: 0,
+    };
+    let mut arm_nodes = Vec::new();
+    for arm in arms {
+        if arm.guard.is_some() {
+            return Ok(false);
+        }
+        match arm.pat.kind {
+            PatKind::Lit(ref e) => {
+                let key = match_or!([lit_value(e)] Some(x) => x; return Ok(false));
+                let idx = builder.new_node();
+                builder.keys.insert(key, idx);
+                arm_nodes.push((idx, &arm.body));
+            }
+            PatKind::Wild => {}
+            _ => return Ok(false),
+        }
+    }
+    for (idx, body) in arm_nodes {
+        let stmts = match body.kind {
+            ExprKind::Block(ref b, None) => b.stmts.clone(),
+            _ => vec![mk().expr_stmt(body.clone())],
+        };
+        if builder.read_block(idx, &stmts).is_none() {
+            return Ok(false);
+        }
+    }
+    let entry = match_or!([builder.keys.get(&entry_val)] Some(&x) => x; return Ok(false));
+
+    // Only the state machine may use the variable.
+    let expected = builder.uses + 1 + if init_stmt.is_some() { 1 } else { 0 };
+    if count_uses(&b.stmts, var) != expected {
+        return Ok(false);
+    }
+
+    let loops = find_loops(&builder.nodes, entry)?;
+    let mut emitter = Emitter {
+        nodes: &builder.nodes,
+        loops: &loops,
+        emitted: HashSet::new(),
+        ctx: Vec::new(),
+    };
+    let mut stmts = Vec::new();
+    emitter.emit_from(entry, None, &mut stmts)?;
+
+    b.stmts.splice(pos..pos + 1, stmts);
+    if let Some(i) = init_stmt {
+        b.stmts.remove(i);
+    }
+    b.stmts.remove(decl);
+    Ok(true)
+}
+
+impl Transform for RelooperCleanup {
+    fn transform(&self, krate: &mut Crate, _st: &CommandState, _cx: &RefactorCtxt) {
+        mut_visit_fns(krate, |fl| {
+            let name = fl.ident;
+            MutVisitNodes::visit(&mut fl.block, |b: &mut P<Block>| {
+                let decls = b.stmts.iter().enumerate()
+                    .filter(|(_, s)| state_decl(s).is_some())
+                    .map(|(i, _)| i)
+                    .collect::<Vec<_>>();
+                // Rewriting a state machine removes the statements before the later ones.
+                for &decl in decls.iter().rev() {
+                    match clean_up_state_machine(b, decl) {
+                        Ok(_) => {}
+                        Err(Failure::Irreducible) => warn!(
+                            "relooper_cleanup: leaving irreducible control flow in {}", name),
+                        Err(Failure::Unstructured) => warn!(
+                            "relooper_cleanup: leaving control flow that needs labels or \
+                             duplicated code in {}", name),
+                    }
+                }
+            });
+        });
+    }
+}
+
+
+pub fn register_commands(reg: &mut Registry) {
+    use super::mk;
+
+    reg.register("relooper_cleanup", |_args| mk(RelooperCleanup));
+}
@@ -12,18 +12,22 @@ use rustc::ty::{self, ParamEnv};
 use rustc_target::spec::abi::{self, Abi};
 use syntax::ast::*;
 use syntax::attr::HasAttrs;
+use syntax::source_map::dummy_spanned;
 use syntax::util::comments::{Comment, CommentStyle};
 use syntax::ptr::P;
-use syntax::symbol::kw;
+use syntax::symbol::{kw, Symbol};
 use syntax::util::map_in_place::MapInPlace;
 use syntax_pos::{BytePos, DUMMY_SP};
 use smallvec::smallvec;
 
-use crate::ast_manip::util::{is_relative_path, join_visibility, namespace, split_uses, is_exported, is_c2rust_attr};
+use crate::ast_manip::util::{
+    is_relative_path, join_visibility, namespace, split_uses, is_exported, is_c2rust_attr, use_idents,
+    source_header_attr,
+};
 use crate::ast_manip::{visit_nodes, AstEquiv, FlatMapNodes, MutVisitNodes};
 use crate::command::{CommandState, Registry};
 use crate::driver::Phase;
-use crate::path_edit::fold_resolved_paths_with_id;
+use crate::path_edit::{fold_resolved_paths, fold_resolved_paths_with_id};
 use crate::RefactorCtxt;
 use crate::util::Lone;
 use c2rust_ast_builder::mk;
@@ -1125,9 +1129,9 @@ impl HasAttrs for DeclKind {
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
-struct SrcLoc {
-    line: usize,
-    col: usize,
+pub(super) struct SrcLoc {
+    pub(super) line: usize,
+    pub(super) col: usize,
 }
 
 impl From<&Attribute> for SrcLoc {
@@ -1647,12 +1651,12 @@ fn foreign_equiv(foreign: &ForeignItem, item: &Item) -> bool {
 
 /// Check if the `Item` has the `#[header_src = "/some/path"]` attribute
 fn has_source_header(attrs: &[Attribute]) -> bool {
-    attrs.iter().any(|attr| is_c2rust_attr(attr, "header_src"))
+    source_header_attr(attrs).is_some()
 }
 
 /// Check if the `Item` has the `#[header_src = "/some/path"]` attribute
-fn parse_source_header(attrs: &[Attribute]) -> Option<(String, usize)> {
-    attrs.iter().find(|a| is_c2rust_attr(a, "header_src")).map(|attr| {
+pub(super) fn parse_source_header(attrs: &[Attribute]) -> Option<(String, usize)> {
+    source_header_attr(attrs).map(|attr| {
         let value_str = attr
             .value_str()
             .expect("Expected a value for header_src attribute")
@@ -1688,8 +1692,217 @@ impl Transform for ReorganizeDefinitions {
     }
 }
 
+/// # `split_module_by_src` Command
+///
+/// Usage: `split_module_by_src`
+///
+/// Marks: `target`
+///
+/// Split each module marked `target` by the original source file of its items,
+/// as recorded in their `#[c2rust::header_src]` attributes.  The items of each
+/// source file are moved into a new `pub mod` named after the file stem, and
+/// items without the attribute stay in the marked module.
+///
+/// Adds the `use` items needed for names to keep resolving after the move: the
+/// marked module imports the moved items it refers to, and each new module
+/// imports the names it refers to from the marked module and from the other new
+/// modules.  Moved items that are used outside their new module are made at
+/// least `pub(crate)`, and moved items that were visible outside the marked
+/// module are re-exported from it, so paths from the rest of the crate keep
+/// working.
+pub struct SplitModuleBySrc;
+
+/// The name of the module for the items of the source file at `path`.
+fn src_module_name(path: &str) -> String {
+    let stem = std::path::Path::new(path)
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or(path);
+    let mut name: String = stem
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if name.starts_with(|c: char| c.is_ascii_digit()) {
+        name.insert(0, '_');
+    }
+    if Ident::from_str(&name).is_reserved() {
+        name.push('_');
+    }
+    name
+}
+
+/// The names an item defines in its module, with their visibilities.
+fn defined_names(item: &Item) -> Vec<(Ident, VisibilityKind)> {
+    match &item.kind {
+        ItemKind::Use(u) => match u.kind {
+            UseTreeKind::Glob => vec![],
+            _ => use_idents(u).into_iter().map(|i| (i, item.vis.node.clone())).collect(),
+        },
+        ItemKind::ForeignMod(fm) => {
+            fm.items.iter().map(|fi| (fi.ident, fi.vis.node.clone())).collect()
+        }
+        ItemKind::Impl(..) | ItemKind::Mac(..) | ItemKind::GlobalAsm(..) => vec![],
+        _ => vec![(item.ident, item.vis.node.clone())],
+    }
+}
+
+/// Make an item that defines one of `names` visible to the rest of the crate.
+fn expose_names(item: &mut Item, names: &HashSet<Symbol>) {
+    let crate_vis = VisibilityKind::Crate(CrateSugar::PubCrate);
+    if let ItemKind::ForeignMod(fm) = &mut item.kind {
+        for fi in &mut fm.items {
+            if names.contains(&fi.ident.name) {
+                fi.vis.node = join_visibility(&fi.vis.node, &crate_vis);
+            }
+        }
+    } else if item.ident.name != kw::Invalid && names.contains(&item.ident.name) {
+        item.vis.node = join_visibility(&item.vis.node, &crate_vis);
+    }
+}
+
+fn split_module_by_src(m: &mut Mod, cx: &RefactorCtxt) {
+    // Group 0 holds the items that stay in `m`, and group `i + 1` the items
+    // moved into the module named `mod_names[i]`.
+    let mut mod_names: Vec<String> = vec![];
+    let groups: Vec<usize> = m.items.iter().map(|item| {
+        let path = match parse_source_header(&item.attrs) {
+            Some((path, _)) => path,
+            None => return 0,
+        };
+        let name = src_module_name(&path);
+        match mod_names.iter().position(|n| *n == name) {
+            Some(i) => i + 1,
+            None => {
+                mod_names.push(name);
+                mod_names.len()
+            }
+        }
+    }).collect();
+    if mod_names.is_empty() {
+        return;
+    }
+    let num_groups = mod_names.len() + 1;
+
+    // The names each group defines, and the first group that defines each
+    // name, with the visibility of that definition.
+    let mut defined = vec![HashSet::new(); num_groups];
+    let mut owners = IndexMap::new();
+    for (item, &g) in m.items.iter().zip(&groups) {
+        for (ident, vis) in defined_names(item) {
+            defined[g].insert(ident.name);
+            owners.entry(ident.name).or_insert((g, vis));
+        }
+    }
+    for name in &mut mod_names {
+        while defined[0].contains(&Symbol::intern(name)) {
+            name.push('_');
+        }
+    }
+
+    // The names each group refers to.  Only the first segment of a path is
+    // looked up in the enclosing module, and only if the path resolves to a
+    // definition, not to a local or a primitive type.
+    let mut refs = vec![HashSet::new(); num_groups];
+    for (item, &g) in m.items.iter_mut().zip(&groups) {
+        if let ItemKind::Use(_) = item.kind {
+            continue;
+        }
+        let refs = &mut refs[g];
+        fold_resolved_paths(item, cx, |qself, path, defs| {
+            if let (None, Some(seg), Some(Res::Def(..))) =
+                    (&qself, path.segments.first(), defs.first()) {
+                refs.insert(seg.ident.name);
+            }
+            (qself, path)
+        });
+    }
+
+    // The `(source group, name, visibility)` of the imports into each group.
+    let mut uses = vec![vec![]; num_groups];
+    let mut exposed = HashSet::new();
+    for (&name, &(src, ref vis)) in &owners {
+        if src == 0 || defined[0].contains(&name) {
+            continue;
+        }
+        let vis = match vis {
+            VisibilityKind::Inherited if refs[0].contains(&name) => VisibilityKind::Inherited,
+            VisibilityKind::Inherited => continue,
+            _ => join_visibility(vis, &VisibilityKind::Crate(CrateSugar::PubCrate)),
+        };
+        uses[0].push((src, name, vis));
+        exposed.insert(name);
+    }
+    for g in 1..num_groups {
+        for &name in &refs[g] {
+            if defined[g].contains(&name) {
+                continue;
+            }
+            let src = if defined[0].contains(&name) {
+                0
+            } else {
+                match owners.get(&name) {
+                    Some(&(src, _)) => src,
+                    None => continue,
+                }
+            };
+            if src != 0 {
+                exposed.insert(name);
+            }
+            uses[g].push((src, name, VisibilityKind::Inherited));
+        }
+    }
+
+    let mut new_items = vec![vec![]; num_groups];
+    for (mut item, g) in mem::replace(&mut m.items, vec![]).into_iter().zip(groups) {
+        if g != 0 {
+            expose_names(&mut item, &exposed);
+        }
+        new_items[g].push(item);
+    }
+
+    let mk_uses = |g: usize, uses: &mut Vec<(usize, Symbol, VisibilityKind)>| {
+        uses.sort_by_key(|&(src, name, _)| (src, name.to_string()));
+        uses.drain(..).map(|(src, name, vis)| {
+            let mut path = vec![mk().path_segment(if g == 0 { kw::SelfLower } else { kw::Super })];
+            if src != 0 {
+                path.push(mk().path_segment(&*mod_names[src - 1]));
+            }
+            path.push(mk().path_segment(name));
+            mk().vis(dummy_spanned(vis)).use_simple_item(mk().path(path), None as Option<Ident>)
+        }).collect::<Vec<_>>()
+    };
+    let mut items = mk_uses(0, &mut uses[0]);
+    for g in 1..num_groups {
+        let mut mod_items = mk_uses(g, &mut uses[g]);
+        mod_items.append(&mut new_items[g]);
+        items.push(mk().pub_().mod_item(&*mod_names[g - 1], mk().mod_(mod_items)));
+    }
+    items.append(&mut new_items[0]);
+    m.items = items;
+}
+
+impl Transform for SplitModuleBySrc {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        MutVisitNodes::visit(krate, |item: &mut P<Item>| {
+            if !st.marked(item.id, "target") {
+                return;
+            }
+            let ident = item.ident;
+            match &mut item.kind {
+                ItemKind::Mod(m) => split_module_by_src(m, cx),
+                _ => warn!("split_module_by_src: skipping `{}`, which is not a module", ident),
+            }
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
 pub fn register_commands(reg: &mut Registry) {
     use super::mk;
 
-    reg.register("reorganize_definitions", |_args| mk(ReorganizeDefinitions))
+    reg.register("reorganize_definitions", |_args| mk(ReorganizeDefinitions));
+    reg.register("split_module_by_src", |_args| mk(SplitModuleBySrc));
 }
@@ -3,6 +3,7 @@ use std::ops::DerefMut;
 use rustc::hir;
 use rustc::hir::def_id::DefId;
 use rustc::ty::{self, TyKind, TyCtxt, ParamEnv};
+use syntax::ast;
 use syntax::ast::*;
 use syntax::mut_visit::{self, MutVisitor};
 use rustc_errors::PResult;
@@ -14,7 +15,7 @@ use syntax_pos::Span;
 use smallvec::{smallvec, SmallVec};
 
 use c2rust_ast_builder::{mk, IntoSymbol};
-use crate::ast_manip::{FlatMapNodes, MutVisit, MutVisitNodes, fold_output_exprs};
+use crate::ast_manip::{FlatMapNodes, MutVisit, MutVisitNodes, fold_output_exprs, visit_nodes};
 use crate::ast_manip::fn_edit::{mut_visit_fns, visit_fns};
 use crate::ast_manip::lr_expr::{self, fold_expr_with_context, fold_exprs_with_context};
 use crate::command::{Command, CommandState, RefactorState, Registry, TypeckLoopResult};
@@ -23,6 +24,8 @@ use crate::illtyped::{IlltypedFolder, fold_illtyped};
 use crate::matcher::{Bindings, MatchCtxt, Subst, mut_visit_match};
 use crate::reflect::{self, reflect_tcx_ty};
 use crate::transform::Transform;
+use crate::transform::mem::strip_casts;
+use crate::transform::null_ptrs::is_null_ptr;
 use crate::RefactorCtxt;
 
 /// # `retype_argument` Command
@@ -1399,6 +1402,547 @@ fn can_coerce<'a, 'tcx>(
     }
 }
 
+/// # `ptr_params_to_refs` Command
+///
+/// Usage: `ptr_params_to_refs`
+///
+/// Marks: `target`
+///
+/// For each function marked `target`, change its `*const T` and `*mut T`
+/// arguments to `&T` and `&mut T` where the argument is only ever dereferenced:
+/// it must be dereferenced at least once, and never compared to null, stored,
+/// offset, or passed along.  Other pointer arguments are left alone with a
+/// warning.  Within the function body, `(*p).field` becomes `p.field`.
+///
+/// At each call site, an argument of the form `&x` (possibly cast to a raw
+/// pointer) is passed as `&x` or `&mut x`, and any other pointer `q` is passed as
+/// `&*q` or `&mut *q`.  A function is skipped with a warning if a caller passes
+/// null for one of the converted arguments, or if the function is used other
+/// than by calling it, for example by storing it in a table of function
+/// pointers.
+pub struct PtrParamsToRefs;
+
+impl Transform for PtrParamsToRefs {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        // (1) Find the pointer arguments of marked functions that are only dereferenced.
+
+        // Maps each function to the index, mutability, and `HirId` of its converted arguments.
+        let mut fns: HashMap<DefId, Vec<(usize, Mutability, hir::HirId)>> = HashMap::new();
+        visit_fns(krate, |fl| {
+            if !st.marked(fl.id, "target") {
+                return;
+            }
+            let block = match fl.block {
+                Some(ref block) => block,
+                None => return,
+            };
+
+            let mut args = Vec::new();
+            for (index, arg) in fl.decl.inputs.iter().enumerate() {
+                let mutbl = match arg.ty.kind {
+                    ast::TyKind::Ptr(MutTy { mutbl, .. }) => mutbl,
+                    _ => continue,
+                };
+                let hir_id = cx.hir_map().node_to_hir_id(arg.pat.id);
+                let is_use = |e: &Expr| match e.kind {
+                    ExprKind::Path(None, _) => cx.try_resolve_expr_to_hid(e) == Some(hir_id),
+                    _ => false,
+                };
+
+                let mut uses = 0;
+                let mut derefs = 0;
+                visit_nodes(&**block, |e: &Expr| {
+                    if is_use(e) {
+                        uses += 1;
+                    }
+                    if let ExprKind::Unary(UnOp::Deref, ref inner) = e.kind {
+                        if is_use(inner) {
+                            derefs += 1;
+                        }
+                    }
+                });
+                if uses > 0 && uses == derefs {
+                    args.push((index, mutbl, hir_id));
+                } else {
+                    warn!("ptr_params_to_refs: argument `{}` of `{}` is used as a raw pointer; \
+                           skipping",
+                          pprust::pat_to_string(&arg.pat), fl.ident);
+                }
+            }
+            if !args.is_empty() {
+                fns.insert(cx.node_def_id(fl.id), args);
+            }
+        });
+
+        // (2) Check the uses of each function.  Every use must be a call, and no call may pass
+        // null for a converted argument.
+
+        let mut rejected = HashSet::new();
+        let mut calls: HashMap<DefId, usize> = HashMap::new();
+        let mut refs: HashMap<DefId, usize> = HashMap::new();
+        visit_nodes(krate, |e: &Expr| {
+            if let ExprKind::Path(..) = e.kind {
+                if let Some(def_id) = cx.try_resolve_expr(e) {
+                    if fns.contains_key(&def_id) {
+                        *refs.entry(def_id).or_insert(0) += 1;
+                    }
+                }
+            }
+
+            let args = match e.kind {
+                ExprKind::Call(_, ref args) | ExprKind::MethodCall(_, ref args) => args,
+                _ => return,
+            };
+            let def_id = match cx.opt_callee(e) {
+                Some(def_id) if fns.contains_key(&def_id) => def_id,
+                _ => return,
+            };
+            if let ExprKind::Call(..) = e.kind {
+                *calls.entry(def_id).or_insert(0) += 1;
+            }
+            for &(index, _, _) in &fns[&def_id] {
+                if is_null_ptr(&args[index]) {
+                    warn!("ptr_params_to_refs: call at {} passes null to `{}`; skipping",
+                          cx.session().source_map().span_to_string(e.span),
+                          cx.ty_ctxt().def_path_str(def_id));
+                    rejected.insert(def_id);
+                }
+            }
+        });
+
+        for (&def_id, &count) in &refs {
+            if calls.get(&def_id).cloned().unwrap_or(0) != count {
+                warn!("ptr_params_to_refs: `{}` is used other than by calling it; skipping",
+                      cx.ty_ctxt().def_path_str(def_id));
+                rejected.insert(def_id);
+            }
+        }
+        fns.retain(|def_id, _| !rejected.contains(def_id));
+
+        // (3) Retype the arguments and simplify `(*p).field` in the function bodies.
+
+        mut_visit_fns(krate, |fl| {
+            let args = match fns.get(&cx.node_def_id(fl.id)) {
+                Some(args) => args,
+                None => return,
+            };
+
+            for &(index, mutbl, _) in args {
+                let arg = &mut fl.decl.inputs[index];
+                let pointee = match arg.ty.kind {
+                    ast::TyKind::Ptr(MutTy { ref ty, .. }) => pprust::ty_to_string(ty),
+                    _ => unreachable!(),
+                };
+                let mut_str = if mutbl == Mutability::Mutable { "mut " } else { "" };
+                let mut new_ty = parse_ty(cx.session(), &format!("&{}{}", mut_str, pointee));
+                new_ty.id = arg.ty.id;
+                arg.ty = new_ty;
+            }
+
+            let hir_ids = args.iter().map(|&(_, _, hir_id)| hir_id).collect::<HashSet<_>>();
+            let block = match fl.block {
+                Some(ref mut block) => block,
+                None => return,
+            };
+            MutVisitNodes::visit(block, |e: &mut P<Expr>| {
+                let base = match e.kind {
+                    ExprKind::Field(ref mut base, _) => base,
+                    ExprKind::MethodCall(_, ref mut args) => &mut args[0],
+                    _ => return,
+                };
+                let mut inner = &**base;
+                while let ExprKind::Paren(ref e) = inner.kind {
+                    inner = e;
+                }
+                let ptr = match inner.kind {
+                    ExprKind::Unary(UnOp::Deref, ref ptr) => match ptr.kind {
+                        ExprKind::Path(None, _) => match cx.try_resolve_expr_to_hid(ptr) {
+                            Some(hir_id) if hir_ids.contains(&hir_id) => ptr.clone(),
+                            _ => return,
+                        },
+                        _ => return,
+                    },
+                    _ => return,
+                };
+                *base = ptr;
+            });
+        });
+
+        // (4) Pass references at the call sites.
+
+        let mut mcx = MatchCtxt::new(st, cx);
+        let ref_repl = mcx.parse_expr("&$e");
+        let ref_mut_repl = mcx.parse_expr("&mut $e");
+        let reborrow_repl = mcx.parse_expr("&*$e");
+        let reborrow_mut_repl = mcx.parse_expr("&mut *$e");
+
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            let conv_args = match cx.opt_callee(e).and_then(|def_id| fns.get(&def_id)) {
+                Some(conv_args) => conv_args,
+                None => return,
+            };
+            let args = match e.kind {
+                ExprKind::Call(_, ref mut args) | ExprKind::MethodCall(_, ref mut args) => args,
+                _ => return,
+            };
+            for &(index, mutbl, _) in conv_args {
+                let arg = &mut args[index];
+                let mut_ref = mutbl == Mutability::Mutable;
+                let mut bnd = Bindings::new();
+                let repl = match strip_casts(arg).kind {
+                    ExprKind::AddrOf(_, _, ref place) => {
+                        bnd.add("$e", place.clone());
+                        if mut_ref { &ref_mut_repl } else { &ref_repl }
+                    }
+                    _ => {
+                        bnd.add("$e", arg.clone());
+                        if mut_ref { &reborrow_mut_repl } else { &reborrow_repl }
+                    }
+                };
+                *arg = repl.clone().subst(st, cx, &bnd);
+            }
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
+/// # `ptr_len_to_slice` Command
+///
+/// Usage: `ptr_len_to_slice [LEN]`
+///
+/// Marks: `target`
+///
+/// For each pointer argument marked `target`, combine it and its length
+/// argument into a single `&[T]` or `&mut [T]` argument.  The length argument
+/// is the one named `LEN`, if given, and otherwise the argument right after the
+/// pointer.  Within the function body, `*p.offset(i)` and `*p.add(i)` become
+/// `p[i as usize]`, `*p` becomes `p[0]`, and uses of the length become
+/// `p.len()`, cast back to the length's original type.
+///
+/// At each call site, a pointer of the form `a.as_ptr()` or `a.as_mut_ptr()`
+/// is passed as `&a[..n as usize]` (or `&mut a[..n as usize]`), and any other
+/// pointer `q` as `unsafe { ::std::slice::from_raw_parts(q, n as usize) }` (or
+/// `from_raw_parts_mut`).
+///
+/// A pointer argument is skipped with a warning if it is used other than by
+/// dereferencing it at an offset, if its length argument does not directly
+/// follow it, is the length of another marked pointer as well, or is assigned
+/// to.  A function is skipped if it is used other than by calling it.
+pub struct PtrLenToSlice {
+    pub len_name: Option<String>,
+}
+
+/// A pointer argument to convert, along with its length argument.
+struct PtrLen {
+    ptr_index: usize,
+    ptr_id: hir::HirId,
+    ptr_ident: Ident,
+    len_id: hir::HirId,
+    len_ty: P<Ty>,
+    mutbl: Mutability,
+}
+
+/// If `e` is a use of the pointer `ptr_id` in a position that can be indexed, such as `p` or
+/// `p.offset(i)`, return the offset, if any.
+fn ptr_offset<'a>(cx: &RefactorCtxt, ptr_id: hir::HirId, e: &'a Expr)
+                  -> Option<Option<&'a P<Expr>>> {
+    let is_ptr = |e: &Expr| match e.kind {
+        ExprKind::Path(None, _) => cx.try_resolve_expr_to_hid(e) == Some(ptr_id),
+        _ => false,
+    };
+    match e.kind {
+        _ if is_ptr(e) => Some(None),
+        ExprKind::MethodCall(ref seg, ref args) if args.len() == 2 && is_ptr(&args[0]) => {
+            match &*seg.ident.as_str() {
+                "offset" | "add" => Some(Some(&args[1])),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+impl Transform for PtrLenToSlice {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        // (1) Find the marked pointer arguments, and their length arguments.
+
+        let mut fns: HashMap<DefId, Vec<PtrLen>> = HashMap::new();
+        visit_fns(krate, |fl| {
+            let block = match fl.block {
+                Some(ref block) => block,
+                None => return,
+            };
+            let inputs = &fl.decl.inputs;
+            let arg_name = |arg: &Param| pprust::pat_to_string(&arg.pat);
+            let len_index_of = |ptr_index: usize| match self.len_name {
+                Some(ref name) => inputs.iter().position(|arg| arg_name(arg) == *name),
+                None => Some(ptr_index + 1),
+            };
+
+            let mut ptr_lens = Vec::new();
+            for (ptr_index, arg) in inputs.iter().enumerate() {
+                if !st.marked(arg.id, "target") {
+                    continue;
+                }
+                let mutbl = match arg.ty.kind {
+                    ast::TyKind::Ptr(MutTy { mutbl, .. }) => mutbl,
+                    _ => {
+                        warn!("ptr_len_to_slice: argument `{}` of `{}` is not a raw pointer; \
+                               skipping", arg_name(arg), fl.ident);
+                        continue;
+                    }
+                };
+                let ptr_ident = match arg.pat.kind {
+                    PatKind::Ident(BindingMode::ByValue(Mutability::Immutable), ident, None) =>
+                        ident,
+                    _ => continue,
+                };
+
+                let len_index = len_index_of(ptr_index);
+                let len = match len_index.and_then(|i| inputs.get(i)) {
+                    Some(len) => len,
+                    None => {
+                        warn!("ptr_len_to_slice: found no length argument for `{}` in `{}`; \
+                               skipping", ptr_ident, fl.ident);
+                        continue;
+                    }
+                };
+                // A length shared by several buffers can't be replaced by any one of their
+                // lengths.
+                let shared = inputs.iter().enumerate().any(|(i, other)| {
+                    i != ptr_index && st.marked(other.id, "target") && len_index_of(i) == len_index
+                });
+                if shared {
+                    warn!("ptr_len_to_slice: length argument `{}` of `{}` in `{}` is shared with \
+                           another buffer; skipping", arg_name(len), ptr_ident, fl.ident);
+                    continue;
+                }
+                if len_index != Some(ptr_index + 1) {
+                    warn!("ptr_len_to_slice: length argument `{}` of `{}` in `{}` does not \
+                           follow it; skipping", arg_name(len), ptr_ident, fl.ident);
+                    continue;
+                }
+                let is_int = cx.opt_node_type(len.pat.id).map_or(false, |ty| match ty.kind {
+                    TyKind::Int(_) | TyKind::Uint(_) => true,
+                    _ => false,
+                });
+                if !is_int {
+                    warn!("ptr_len_to_slice: argument `{}` of `{}` is not an integer length for \
+                           `{}`; skipping", arg_name(len), fl.ident, ptr_ident);
+                    continue;
+                }
+
+                // Every use of the pointer must be a dereference, possibly at an offset, and the
+                // length must never change.
+                let ptr_id = cx.hir_map().node_to_hir_id(arg.pat.id);
+                let len_id = cx.hir_map().node_to_hir_id(len.pat.id);
+                let is_use = |e: &Expr, id| match e.kind {
+                    ExprKind::Path(None, _) => cx.try_resolve_expr_to_hid(e) == Some(id),
+                    _ => false,
+                };
+                let mut uses = 0;
+                let mut derefs = 0;
+                let mut len_written = false;
+                visit_nodes(&**block, |e: &Expr| {
+                    match e.kind {
+                        ExprKind::Unary(UnOp::Deref, ref inner) => {
+                            if ptr_offset(cx, ptr_id, inner).is_some() {
+                                derefs += 1;
+                            }
+                        }
+                        ExprKind::Assign(ref lhs, _) |
+                        ExprKind::AssignOp(_, ref lhs, _) |
+                        ExprKind::AddrOf(_, Mutability::Mutable, ref lhs) => {
+                            len_written |= is_use(lhs, len_id);
+                        }
+                        _ => {}
+                    }
+                    if is_use(e, ptr_id) {
+                        uses += 1;
+                    }
+                });
+                if uses == 0 || uses != derefs {
+                    warn!("ptr_len_to_slice: `{}` in `{}` is used other than by dereferencing \
+                           it; skipping", ptr_ident, fl.ident);
+                    continue;
+                }
+                if len_written {
+                    warn!("ptr_len_to_slice: length `{}` of `{}` in `{}` is assigned to; \
+                           skipping", arg_name(len), ptr_ident, fl.ident);
+                    continue;
+                }
+
+                ptr_lens.push(PtrLen {
+                    ptr_index, ptr_id, ptr_ident, len_id,
+                    len_ty: len.ty.clone(),
+                    mutbl,
+                });
+            }
+
+            if !ptr_lens.is_empty() {
+                fns.insert(cx.node_def_id(fl.id), ptr_lens);
+            }
+        });
+
+        // (2) Check that every use of each function is a call.
+
+        let mut calls: HashMap<DefId, usize> = HashMap::new();
+        let mut refs: HashMap<DefId, usize> = HashMap::new();
+        visit_nodes(krate, |e: &Expr| {
+            if let ExprKind::Path(..) = e.kind {
+                if let Some(def_id) = cx.try_resolve_expr(e) {
+                    if fns.contains_key(&def_id) {
+                        *refs.entry(def_id).or_insert(0) += 1;
+                    }
+                }
+            }
+            if let ExprKind::Call(..) = e.kind {
+                if let Some(def_id) = cx.opt_callee(e) {
+                    if fns.contains_key(&def_id) {
+                        *calls.entry(def_id).or_insert(0) += 1;
+                    }
+                }
+            }
+        });
+        for (&def_id, &count) in &refs {
+            if calls.get(&def_id).cloned().unwrap_or(0) != count {
+                warn!("ptr_len_to_slice: `{}` is used other than by calling it; skipping",
+                      cx.ty_ctxt().def_path_str(def_id));
+                fns.remove(&def_id);
+            }
+        }
+
+        let mut mcx = MatchCtxt::new(st, cx);
+        let index_repl = mcx.parse_expr("$p[$i as usize]");
+        let first_repl = mcx.parse_expr("$p[0]");
+        let len_repl = mcx.parse_expr("$p.len() as $t");
+        let usize_len_repl = mcx.parse_expr("$p.len()");
+        let subslice_repl = mcx.parse_expr("&$a[..$n as usize]");
+        let subslice_mut_repl = mcx.parse_expr("&mut $a[..$n as usize]");
+        let from_raw_repl = mcx.parse_expr(
+            "unsafe { ::std::slice::from_raw_parts($a, $n as usize) }");
+        let from_raw_mut_repl = mcx.parse_expr(
+            "unsafe { ::std::slice::from_raw_parts_mut($a, $n as usize) }");
+
+        // (3) Rewrite the function signatures and bodies.
+
+        mut_visit_fns(krate, |fl| {
+            let ptr_lens = match fns.get(&cx.node_def_id(fl.id)) {
+                Some(ptr_lens) => ptr_lens,
+                None => return,
+            };
+
+            if let Some(ref mut block) = fl.block {
+                MutVisitNodes::visit(block, |e: &mut P<Expr>| {
+                    let mut bnd = Bindings::new();
+                    let repl = match e.kind {
+                        ExprKind::Unary(UnOp::Deref, ref inner) => {
+                            let found = ptr_lens.iter().filter_map(|pl| {
+                                ptr_offset(cx, pl.ptr_id, inner).map(|off| (pl, off))
+                            }).next();
+                            let (pl, off) = match found {
+                                Some(x) => x,
+                                None => return,
+                            };
+                            bnd.add("$p", mk().ident_expr(pl.ptr_ident));
+                            match off {
+                                Some(i) => {
+                                    bnd.add("$i", strip_casts(i).clone());
+                                    &index_repl
+                                }
+                                None => &first_repl,
+                            }
+                        }
+                        ExprKind::Path(None, _) => {
+                            let hir_id = cx.try_resolve_expr_to_hid(e);
+                            let pl = match ptr_lens.iter().find(|pl| Some(pl.len_id) == hir_id) {
+                                Some(pl) => pl,
+                                None => return,
+                            };
+                            bnd.add("$p", mk().ident_expr(pl.ptr_ident));
+                            match cx.opt_node_type(e.id).map(|ty| &ty.kind) {
+                                Some(TyKind::Uint(UintTy::Usize)) => &usize_len_repl,
+                                _ => {
+                                    bnd.add("$t", pl.len_ty.clone());
+                                    &len_repl
+                                }
+                            }
+                        }
+                        _ => return,
+                    };
+                    *e = repl.clone().subst(st, cx, &bnd);
+                });
+            }
+
+            for pl in ptr_lens.iter().rev() {
+                let arg = &mut fl.decl.inputs[pl.ptr_index];
+                let elem = match arg.ty.kind {
+                    ast::TyKind::Ptr(MutTy { ref ty, .. }) => pprust::ty_to_string(ty),
+                    _ => unreachable!(),
+                };
+                let mut_str = if pl.mutbl == Mutability::Mutable { "mut " } else { "" };
+                let mut new_ty = parse_ty(cx.session(), &format!("&{}[{}]", mut_str, elem));
+                new_ty.id = arg.ty.id;
+                arg.ty = new_ty;
+                fl.decl.inputs.remove(pl.ptr_index + 1);
+            }
+        });
+
+        // (4) Pass slices at the call sites.
+
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            let ptr_lens = match e.kind {
+                ExprKind::Call(..) => match cx.opt_callee(e).and_then(|def_id| fns.get(&def_id)) {
+                    Some(ptr_lens) => ptr_lens,
+                    None => return,
+                },
+                _ => return,
+            };
+            let args = match e.kind {
+                ExprKind::Call(_, ref mut args) => args,
+                _ => return,
+            };
+            for pl in ptr_lens.iter().rev() {
+                let len = args.remove(pl.ptr_index + 1);
+                let ptr = &mut args[pl.ptr_index];
+                let mut_slice = pl.mutbl == Mutability::Mutable;
+
+                let mut bnd = Bindings::new();
+                bnd.add("$n", strip_casts(&len).clone());
+                let array = match strip_casts(ptr).kind {
+                    ExprKind::MethodCall(ref seg, ref args) if args.len() == 1 => {
+                        match &*seg.ident.as_str() {
+                            "as_ptr" | "as_mut_ptr" => Some(args[0].clone()),
+                            _ => None,
+                        }
+                    }
+                    _ => None,
+                };
+                let repl = match array {
+                    Some(array) => {
+                        bnd.add("$a", array);
+                        if mut_slice { &subslice_mut_repl } else { &subslice_repl }
+                    }
+                    None => {
+                        bnd.add("$a", ptr.clone());
+                        if mut_slice { &from_raw_mut_repl } else { &from_raw_repl }
+                    }
+                };
+                *ptr = repl.clone().subst(st, cx, &bnd);
+            }
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
 pub fn register_commands(reg: &mut Registry) {
     use super::mk;
 
@@ -1430,4 +1974,10 @@ pub fn register_commands(reg: &mut Registry) {
     reg.register("type_fix_rules", |args| Box::new(TypeFixRules { rules: args.to_owned() }));
 
     reg.register("autoretype", |args| Box::new(AutoRetype::new(args)));
+
+    reg.register("ptr_params_to_refs", |_args| mk(PtrParamsToRefs));
+
+    reg.register("ptr_len_to_slice", |args| mk(PtrLenToSlice {
+        len_name: args.get(0).cloned(),
+    }));
 }
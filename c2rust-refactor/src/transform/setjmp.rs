@@ -0,0 +1,473 @@
+//! Transform that turns `setjmp`/`longjmp` pairs into early returns of `Result`s.
+
+use std::collections::{HashMap, HashSet};
+use rustc::hir::HirId;
+use rustc::hir::def::{DefKind, Res};
+use rustc::hir::def_id::DefId;
+use syntax::ast::*;
+use syntax::print::pprust;
+use syntax::ptr::P;
+use syntax::visit;
+use syntax_pos::Span;
+
+use c2rust_ast_builder::mk;
+use crate::ast_manip::{MutVisitNodes, visit_nodes};
+use crate::ast_manip::fn_edit::{mut_visit_fns, visit_fns};
+use crate::command::{CommandState, Registry};
+use crate::driver::{self, Phase};
+use crate::matcher::{Bindings, MatchCtxt, Subst};
+use crate::transform::Transform;
+use crate::transform::assert::foreign_call;
+use crate::transform::canonicalize_refs::strip_parens;
+use crate::transform::control_flow::LoopJumpFinder;
+use crate::transform::enums::lit_value;
+use crate::RefactorCtxt;
+
+
+/// # `setjmp_to_result` Command
+///
+/// Usage: `setjmp_to_result`
+///
+/// Replace the common use of `setjmp` and `longjmp` for error recovery,
+///
+/// ```ignore
+///     let mut env: jmp_buf = ...;
+///     if _setjmp(env.as_mut_ptr()) == 0 {
+///         body;
+///     } else {
+///         recovery;
+///     }
+/// ```
+///
+/// with a closure that returns `Result<(), libc::c_int>`:
+///
+/// ```ignore
+///     match (|| -> Result<(), libc::c_int> {
+///         body;
+///         Ok(())
+///     })() {
+///         Ok(()) => {}
+///         Err(_) => {
+///             recovery;
+///         }
+///     }
+/// ```
+///
+/// Each `longjmp(env.as_mut_ptr(), v)` in `body` becomes `return Err(v)`, and
+/// the `jmp_buf` is removed.  `body` may also pass `env.as_mut_ptr()` to a
+/// function of the crate that calls `longjmp` on it, or passes it on to one
+/// that does.  Such a function loses its `jmp_buf` parameter and returns a
+/// `Result` instead, with its own `longjmp`s becoming `return Err(v)` and its
+/// calls of other such functions getting a `?`.  Note that `longjmp(env, 0)`
+/// makes `setjmp` return `1` in C but now produces `Err(0)`, which doesn't
+/// change `recovery`, as it can't see the value.
+///
+/// Each `setjmp` is skipped, with a note saying why, when its `jmp_buf` is a
+/// `static`, when it's passed to a foreign function or used in a closure, which
+/// may run on another thread, when it's used in any other way, or when `body`
+/// returns or jumps out of the `if`.  So is a function that is also called
+/// from code that isn't converted, along with the `setjmp`s that reach it.
+pub struct SetjmpToResult;
+
+/// What a `jmp_buf` argument of `setjmp`, `longjmp` or a function of the crate refers to.
+enum JmpBuf {
+    Local(HirId, NodeId),
+    Static,
+}
+
+/// Resolve a `jmp_buf` argument, like `env.as_mut_ptr()` or `buf`, returning the local or
+/// parameter along with the path that names it.
+fn jmp_buf(cx: &RefactorCtxt, e: &Expr) -> Option<JmpBuf> {
+    let e = strip_parens(e);
+    let path = match e.kind {
+        ExprKind::MethodCall(ref seg, ref args) if &*seg.ident.as_str() == "as_mut_ptr" => {
+            &args[0]
+        }
+        ExprKind::Path(..) => e,
+        _ => return None,
+    };
+    match_or!([path.kind] ExprKind::Path(None, _) => {}; return None);
+    match cx.try_resolve_expr_hir(path)? {
+        Res::Local(hir_id) => Some(JmpBuf::Local(hir_id, path.id)),
+        Res::Def(DefKind::Static, _) => Some(JmpBuf::Static),
+        _ => None,
+    }
+}
+
+/// If `e` is a call of `setjmp`, return its argument.
+fn setjmp_arg<'a>(cx: &RefactorCtxt, e: &'a Expr) -> Option<&'a P<Expr>> {
+    foreign_call(cx, e, "_setjmp").or_else(|| foreign_call(cx, e, "setjmp"))?.first()
+}
+
+/// If `e` is `if setjmp(buf) == 0 { body } else { recovery }`, return `buf`, `body` and
+/// `recovery`.
+fn setjmp_if<'a>(cx: &RefactorCtxt, e: &'a Expr)
+                 -> Option<(&'a P<Expr>, &'a P<Block>, Option<&'a P<Expr>>)> {
+    let (cond, then, els) = match_or!([e.kind] ExprKind::If(ref c, ref t, ref e) => (c, t, e);
+                                      return None);
+    let (l, r) = match strip_parens(cond).kind {
+        ExprKind::Binary(op, ref l, ref r) if op.node == BinOpKind::Eq => (l, r),
+        _ => return None,
+    };
+    let call = if lit_value(r) == Some(0) {
+        l
+    } else if lit_value(l) == Some(0) {
+        r
+    } else {
+        return None;
+    };
+    Some((setjmp_arg(cx, strip_parens(call))?, then, els.as_ref()))
+}
+
+/// A `setjmp` that may be rewritten.
+struct Site {
+    if_id: NodeId,
+    span: Span,
+    env: HirId,
+    /// The path naming `env` in the `setjmp` call.
+    path: NodeId,
+    /// The expressions of the body, which run before any `longjmp`.
+    body_ids: HashSet<NodeId>,
+    jumps_out: bool,
+}
+
+/// A `longjmp` on a local or parameter.
+struct Longjmp {
+    call: NodeId,
+    fn_id: DefId,
+    buf: HirId,
+    path: NodeId,
+}
+
+/// A call of a function of the crate that passes it a `jmp_buf` local or parameter.
+struct BufCall {
+    call: NodeId,
+    func: NodeId,
+    caller: DefId,
+    callee: DefId,
+    idx: usize,
+    buf: HirId,
+    path: NodeId,
+}
+
+impl Transform for SetjmpToResult {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        let mut mcx = MatchCtxt::new(st, cx);
+        let err_repl = mcx.parse_expr("return Err($v)");
+        let ok_repl = mcx.parse_expr("return Ok($v)");
+        let ok_unit_repl = mcx.parse_expr("return Ok(())");
+        let try_repl = mcx.parse_expr("$e?");
+        let subst = |repl: &P<Expr>, args: Vec<(&str, P<Expr>)>| {
+            let mut bnd = Bindings::new();
+            for (name, e) in args {
+                bnd.add(name, e);
+            }
+            repl.clone().subst(st, cx, &bnd)
+        };
+        let warn_skip = |why: &str, span: Span| {
+            warn!("setjmp_to_result: skipping setjmp that {} at {}", why,
+                  cx.session().source_map().span_to_string(span));
+        };
+
+        // (1) Find the `setjmp`s, `longjmp`s and the calls passing `jmp_buf`s around.
+
+        let mut crate_fns = HashSet::new();
+        let mut fn_params: HashMap<DefId, Vec<HirId>> = HashMap::new();
+        visit_fns(krate, |fl| {
+            if fl.block.is_some() {
+                let def_id = cx.node_def_id(fl.id);
+                crate_fns.insert(def_id);
+                fn_params.insert(def_id, fl.decl.inputs.iter()
+                    .map(|param| cx.hir_map().node_to_hir_id(param.pat.id))
+                    .collect());
+            }
+        });
+
+        let mut sites = Vec::new();
+        let mut longjmps = Vec::new();
+        let mut buf_calls = Vec::new();
+        let mut local_uses: HashMap<HirId, Vec<NodeId>> = HashMap::new();
+        let mut fn_refs: HashMap<DefId, Vec<NodeId>> = HashMap::new();
+        let mut ffi_uses = HashSet::new();
+        let mut closure_uses = HashSet::new();
+        visit_fns(krate, |fl| {
+            let block = match_or!([fl.block] Some(ref x) => x; return);
+            let fn_id = cx.node_def_id(fl.id);
+            visit_nodes(&**block, |e: &Expr| {
+                match e.kind {
+                    ExprKind::Path(None, _) => match cx.try_resolve_expr_hir(e) {
+                        Some(Res::Local(hir_id)) => {
+                            local_uses.entry(hir_id).or_default().push(e.id);
+                        }
+                        Some(Res::Def(_, def_id)) if crate_fns.contains(&def_id) => {
+                            fn_refs.entry(def_id).or_default().push(e.id);
+                        }
+                        _ => {}
+                    },
+                    ExprKind::Closure(_, _, _, _, ref body, _) => {
+                        visit_nodes(&**body, |e: &Expr| { closure_uses.insert(e.id); });
+                    }
+                    _ => {}
+                }
+
+                if let Some(args) = foreign_call(cx, e, "longjmp") {
+                    let buf = args.first().and_then(|a| jmp_buf(cx, a));
+                    if let Some(JmpBuf::Local(buf, path)) = buf {
+                        longjmps.push(Longjmp { call: e.id, fn_id, buf, path });
+                    }
+                } else if let (ExprKind::Call(ref func, ref args), None) =
+                        (&e.kind, setjmp_arg(cx, e)) {
+                    let callee = cx.try_resolve_expr(func).filter(|id| crate_fns.contains(id));
+                    for (idx, arg) in args.iter().enumerate() {
+                        let (buf, path) = match_or!([jmp_buf(cx, arg)]
+                                                    Some(JmpBuf::Local(b, p)) => (b, p); continue);
+                        match callee {
+                            Some(callee) => buf_calls.push(BufCall {
+                                call: e.id, func: func.id, caller: fn_id, callee, idx, buf, path,
+                            }),
+                            None => { ffi_uses.insert(path); }
+                        }
+                    }
+                }
+
+                let (buf, body, _) = match_or!([setjmp_if(cx, e)] Some(x) => x; return);
+                let (env, path) = match jmp_buf(cx, buf) {
+                    Some(JmpBuf::Local(env, path)) => (env, path),
+                    Some(JmpBuf::Static) => return warn_skip("uses a static jmp_buf", e.span),
+                    None => return,
+                };
+                let mut body_ids = HashSet::new();
+                let mut returns = false;
+                visit_nodes(&**body, |e: &Expr| {
+                    body_ids.insert(e.id);
+                    if let ExprKind::Ret(_) = e.kind {
+                        returns = true;
+                    }
+                });
+                let mut finder = LoopJumpFinder::new(None);
+                for s in &body.stmts {
+                    visit::walk_stmt(&mut finder, s);
+                }
+                let jumps_out = returns || finder.breaks > 0 || finder.continues > 0;
+                sites.push(Site { if_id: e.id, span: e.span, env, path, body_ids, jumps_out });
+            });
+        });
+
+        // (2) Find the functions that `longjmp` on a parameter, or pass it on to one that does.
+
+        let mut throwers: HashMap<DefId, (usize, HirId)> = HashMap::new();
+        let param_idx = |fn_id: DefId, buf: HirId| {
+            fn_params.get(&fn_id).and_then(|params| params.iter().position(|&p| p == buf))
+        };
+        for lj in &longjmps {
+            if let Some(idx) = param_idx(lj.fn_id, lj.buf) {
+                throwers.entry(lj.fn_id).or_insert((idx, lj.buf));
+            }
+        }
+        loop {
+            let mut changed = false;
+            for bc in &buf_calls {
+                if throwers.get(&bc.callee).map(|t| t.0) != Some(bc.idx) ||
+                   throwers.contains_key(&bc.caller) {
+                    continue;
+                }
+                if let Some(idx) = param_idx(bc.caller, bc.buf) {
+                    throwers.insert(bc.caller, (idx, bc.buf));
+                    changed = true;
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+
+        // (3) Keep the sites and functions whose `jmp_buf`s are only used by the `setjmp`s,
+        // `longjmp`s and calls being rewritten, and the functions only called that way.
+
+        let mut valid_throwers = throwers.keys().cloned().collect::<HashSet<_>>();
+        let mut valid_sites = sites.iter().map(|s| !s.jumps_out).collect::<Vec<_>>();
+        let mut allowed;
+        let mut allowed_calls;
+        loop {
+            let in_valid_ctx = |call: NodeId, fn_id: DefId, buf: HirId| {
+                (valid_throwers.contains(&fn_id) && throwers[&fn_id].1 == buf) ||
+                sites.iter().zip(&valid_sites)
+                    .any(|(s, &ok)| ok && s.env == buf && s.body_ids.contains(&call))
+            };
+            allowed = HashSet::new();
+            allowed_calls = HashMap::new();
+            for lj in &longjmps {
+                if in_valid_ctx(lj.call, lj.fn_id, lj.buf) {
+                    allowed.insert(lj.path);
+                }
+            }
+            let mut allowed_funcs = HashSet::new();
+            for bc in &buf_calls {
+                if valid_throwers.contains(&bc.callee) && throwers[&bc.callee].0 == bc.idx &&
+                   in_valid_ctx(bc.call, bc.caller, bc.buf) {
+                    allowed.insert(bc.path);
+                    allowed_funcs.insert(bc.func);
+                    allowed_calls.insert(bc.call, bc.idx);
+                }
+            }
+            for s in &sites {
+                allowed.insert(s.path);
+            }
+
+            let only_allowed = |buf: HirId| local_uses.get(&buf)
+                .map_or(true, |ids| ids.iter().all(|id| allowed.contains(id)));
+            let new_throwers = valid_throwers.iter().cloned()
+                .filter(|id| only_allowed(throwers[id].1))
+                .filter(|id| fn_refs.get(id).map_or(false, |ids| {
+                    ids.iter().all(|id| allowed_funcs.contains(id))
+                }))
+                .collect::<HashSet<_>>();
+            let new_sites = sites.iter().zip(&valid_sites)
+                .map(|(s, &ok)| ok && only_allowed(s.env))
+                .collect::<Vec<_>>();
+            if new_throwers == valid_throwers && new_sites == valid_sites {
+                break;
+            }
+            valid_throwers = new_throwers;
+            valid_sites = new_sites;
+        }
+
+        let mut envs = HashSet::new();
+        let mut site_ifs = HashSet::new();
+        for (s, &ok) in sites.iter().zip(&valid_sites) {
+            if ok {
+                envs.insert(s.env);
+                site_ifs.insert(s.if_id);
+                continue;
+            }
+            if s.jumps_out {
+                warn_skip("returns or jumps out of its body", s.span);
+                continue;
+            }
+            let bad_uses = local_uses[&s.env].iter()
+                .filter(|id| !allowed.contains(id))
+                .collect::<Vec<_>>();
+            if bad_uses.iter().any(|id| ffi_uses.contains(id)) {
+                warn_skip("passes its jmp_buf to a foreign function", s.span);
+            } else if bad_uses.iter().any(|id| closure_uses.contains(id)) {
+                warn_skip("uses its jmp_buf in a closure, which may run on another thread",
+                          s.span);
+            } else if bad_uses.iter().any(|id| buf_calls.iter().any(|bc| bc.path == **id)) {
+                warn_skip("passes its jmp_buf to a function that can't be converted", s.span);
+            } else {
+                warn_skip("uses its jmp_buf other than by setjmp and longjmp", s.span);
+            }
+        }
+        let longjmp_calls = longjmps.iter()
+            .filter(|lj| allowed.contains(&lj.path))
+            .map(|lj| lj.call)
+            .collect::<HashSet<_>>();
+
+        // (4) Make the functions return `Result`s.
+
+        mut_visit_fns(krate, |fl| {
+            let def_id = match_or!([fl.block] Some(_) => cx.node_def_id(fl.id); return);
+            if !valid_throwers.contains(&def_id) {
+                return;
+            }
+            fl.decl.inputs.remove(throwers[&def_id].0);
+            let ret_unit = match fl.decl.output {
+                FunctionRetTy::Default(_) => true,
+                _ => false,
+            };
+            let ret_ty = match fl.decl.output {
+                FunctionRetTy::Default(_) => "()".to_owned(),
+                FunctionRetTy::Ty(ref ty) => pprust::ty_to_string(ty),
+            };
+            let result_ty = format!("Result<{}, libc::c_int>", ret_ty);
+            fl.decl.output = FunctionRetTy::Ty(driver::parse_ty(cx.session(), &result_ty));
+
+            let block = fl.block.as_mut().unwrap();
+            MutVisitNodes::visit(block, |e: &mut P<Expr>| {
+                let new_e = match e.kind {
+                    ExprKind::Ret(Some(ref v)) => subst(&ok_repl, vec![("$v", v.clone())]),
+                    ExprKind::Ret(None) => subst(&ok_unit_repl, vec![]),
+                    _ => return,
+                };
+                *e = new_e;
+            });
+            let ends_with_jump = match block.stmts.last() {
+                Some(&Stmt { kind: StmtKind::Semi(ref e), .. }) |
+                Some(&Stmt { kind: StmtKind::Expr(ref e), .. }) => match e.kind {
+                    ExprKind::Ret(_) => true,
+                    _ => longjmp_calls.contains(&e.id),
+                },
+                _ => false,
+            };
+            match block.stmts.last_mut() {
+                Some(&mut Stmt { kind: StmtKind::Expr(ref mut e), .. }) if !ends_with_jump => {
+                    *e = mk().call_expr(mk().path_expr(vec!["Ok"]), vec![e.clone()]);
+                }
+                _ if ret_unit && !ends_with_jump => {
+                    block.stmts.push(mk().expr_stmt(driver::parse_expr(cx.session(), "Ok(())")));
+                }
+                _ => {}
+            }
+        });
+
+        // (5) Rewrite the `longjmp`s and the calls that pass the `jmp_buf`s.
+
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            if longjmp_calls.contains(&e.id) {
+                let v = expect!([e.kind] ExprKind::Call(_, ref args) => args[1].clone());
+                *e = subst(&err_repl, vec![("$v", v)]);
+            } else if let Some(&idx) = allowed_calls.get(&e.id) {
+                let mut call = e.clone();
+                expect!([call.kind] ExprKind::Call(_, ref mut args) => args.remove(idx));
+                *e = subst(&try_repl, vec![("$e", call)]);
+            }
+        });
+
+        // (6) Turn the `setjmp`s into calls of closures, and remove their `jmp_buf`s.
+
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            if !site_ifs.contains(&e.id) {
+                return;
+            }
+            let (body, rec) = expect!([e.kind] ExprKind::If(_, ref body, ref rec) =>
+                                      (body.clone(), rec.clone()));
+            let mut new_e = driver::parse_expr(cx.session(), r#"
+                match (|| -> Result<(), libc::c_int> { Ok(()) })() {
+                    Ok(()) => {}
+                    Err(_) => {}
+                }"#);
+            {
+                let (scrut, arms) = expect!([new_e.kind] ExprKind::Match(ref mut s, ref mut a) =>
+                                            (s, a));
+                let closure = expect!([scrut.kind] ExprKind::Call(ref mut f, _) => f);
+                let closure = expect!([closure.kind] ExprKind::Paren(ref mut c) => c);
+                let closure_body = expect!([closure.kind]
+                                           ExprKind::Closure(_, _, _, _, ref mut b, _) => b);
+                let closure_body = expect!([closure_body.kind] ExprKind::Block(ref mut b, _) => b);
+                closure_body.stmts.splice(0..0, body.stmts.iter().cloned());
+                if let Some(rec) = rec {
+                    arms[1].body = rec;
+                }
+            }
+            *e = new_e;
+        });
+
+        MutVisitNodes::visit(krate, |b: &mut P<Block>| {
+            b.stmts.retain(|s| match s.kind {
+                StmtKind::Local(ref l) => !envs.contains(&cx.hir_map().node_to_hir_id(l.pat.id)),
+                _ => true,
+            });
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
+pub fn register_commands(reg: &mut Registry) {
+    use super::mk;
+
+    reg.register("setjmp_to_result", |_args| mk(SetjmpToResult));
+}
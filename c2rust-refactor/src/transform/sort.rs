@@ -0,0 +1,210 @@
+//! Transforms that replace C `qsort` and `bsearch` calls with slice methods.
+
+use std::collections::{HashMap, HashSet};
+use rustc::hir::HirId;
+use rustc::hir::def::Res;
+use rustc::ty;
+use syntax::ast::*;
+use syntax::ptr::P;
+
+use crate::ast_manip::{visit_nodes, MutVisitNodes};
+use crate::command::{CommandState, Registry};
+use crate::driver::Phase;
+use crate::matcher::{Bindings, MatchCtxt, Subst};
+use crate::transform::Transform;
+use crate::transform::mem::{is_zero_lit, strip_casts};
+use crate::transform::ptr_loops::buf_ptr;
+use crate::RefactorCtxt;
+
+
+/// # `qsort_to_sort` Command
+///
+/// Usage: `qsort_to_sort`
+///
+/// Rewrite `qsort` and `bsearch` calls over arrays, slices and `Vec`s into slice methods:
+///
+///  * `qsort(base, n, size_of::<T>(), Some(cmp));` becomes
+///    `buf[..n as usize].sort_by(|a: &T, b: &T| cmp(a as *const T as *const _, ...).cmp(&0))`.
+///  * `bsearch(key, base, n, size_of::<T>(), Some(cmp)) as *mut T` becomes a
+///    `binary_search_by` on `buf[..n as usize]`, mapping `Ok(i)` to a pointer to `buf[i]` and
+///    `Err(_)` to a null pointer, so the code that checks the result keeps working.
+///
+/// `base` must be `buf.as_ptr()`, `buf.as_mut_ptr()` or `&mut buf[0]` (possibly cast to
+/// `*mut c_void`), or a pointer local initialized with one of those and never reassigned, and the
+/// element type of `buf` must be the `T` of the size argument.  Calls on any other base pointer
+/// are left alone with a warning.
+///
+/// The comparator keeps its C signature, and is called from the generated closure with pointers
+/// to the elements.  Its `c_int` result is mapped to an `Ordering` by comparing it with zero.
+///
+/// Uses type information to find the element types, so it needs a crate that typechecks.
+pub struct QsortToSort;
+
+/// If `e` is a pointer to the start of an array, slice or `Vec`, return the buffer and its element
+/// type.
+fn base_buffer<'a, 'tcx>(cx: &RefactorCtxt<'_, 'tcx>, e: &'a P<Expr>)
+                         -> Option<(&'a P<Expr>, ty::Ty<'tcx>)> {
+    let e = strip_casts(e);
+    if let Some((buf, _, elem)) = buf_ptr(cx, e) {
+        return Some((buf, elem));
+    }
+    let place = match_or!([e.kind] ExprKind::AddrOf(_, _, ref place) => place; return None);
+    let (buf, idx) = match_or!([place.kind] ExprKind::Index(ref b, ref i) => (b, i);
+                               return None);
+    if !is_zero_lit(strip_casts(idx)) {
+        return None;
+    }
+    let mut buf_ty = cx.opt_node_type(buf.id)?;
+    while let ty::TyKind::Ref(_, ty, _) = buf_ty.kind {
+        buf_ty = ty;
+    }
+    match buf_ty.kind {
+        ty::TyKind::Array(elem, _) | ty::TyKind::Slice(elem) => Some((buf, elem)),
+        ty::TyKind::Adt(def, substs) if &*cx.ty_ctxt().item_name(def.did).as_str() == "Vec" =>
+            Some((buf, substs.type_at(0))),
+        _ => None,
+    }
+}
+
+impl Transform for QsortToSort {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        let mut mcx = MatchCtxt::new(st, cx);
+        let qsort_pat = mcx.parse_expr("qsort($base:Expr, $n:Expr, $size:Expr, $cmp:Expr)");
+        let bsearch_pat = mcx.parse_expr(
+            "bsearch($key:Expr, $base:Expr, $n:Expr, $size:Expr, $cmp:Expr)");
+        let size_pat = mcx.parse_expr("::std::mem::size_of::<$t:Ty>()");
+        let some_pat = mcx.parse_expr("Some($f:Expr)");
+        let unwrap_repl = mcx.parse_expr("$cmp.unwrap()");
+        let sort_repl = mcx.parse_expr(
+            "$buf[..$n as usize].sort_by(|a: &$t, b: &$t|
+                 $f(a as *const $t as *const _, b as *const $t as *const _).cmp(&0))");
+        let search_repl = mcx.parse_expr(
+            "match $buf[..$n as usize].binary_search_by(|elem: &$t|
+                 $f($key, elem as *const $t as *const _).cmp(&0).reverse()) {
+                 Ok(i) => &$buf[i] as *const $t as *mut _,
+                 Err(_) => ::std::ptr::null_mut(),
+             }");
+        let search_cast_repl = mcx.parse_expr(
+            "match $buf[..$n as usize].binary_search_by(|elem: &$t|
+                 $f($key, elem as *const $t as *const _).cmp(&0).reverse()) {
+                 Ok(i) => &$buf[i] as *const $t as $u,
+                 Err(_) => 0 as $u,
+             }");
+
+        // (1) Find the pointer locals that point to the start of a buffer, and are never changed
+        // after that.
+
+        let mut ptr_locals = HashMap::new();
+        visit_nodes(krate, |l: &Local| {
+            if let (PatKind::Ident(BindingMode::ByValue(_), _, None), Some(init)) =
+                    (&l.pat.kind, &l.init) {
+                if base_buffer(cx, init).is_some() {
+                    ptr_locals.insert(cx.hir_map().node_to_hir_id(l.pat.id), init.clone());
+                }
+            }
+        });
+        let mut changed = HashSet::new();
+        visit_nodes(krate, |e: &Expr| {
+            let lhs = match e.kind {
+                ExprKind::Assign(ref lhs, _) | ExprKind::AssignOp(_, ref lhs, _) |
+                ExprKind::AddrOf(_, Mutability::Mutable, ref lhs) => lhs,
+                _ => return,
+            };
+            if let Some(Res::Local(hir_id)) = cx.try_resolve_expr_hir(lhs) {
+                changed.insert(hir_id);
+            }
+        });
+        ptr_locals.retain(|hir_id: &HirId, _| !changed.contains(hir_id));
+
+        // The buffer that `base` points to the start of, if its element type is the type `t` of
+        // the size argument.
+        let buffer = |base: &P<Expr>, t: &P<Ty>| -> Option<P<Expr>> {
+            let (buf, elem) = match base_buffer(cx, base) {
+                Some(x) => x,
+                None => match cx.try_resolve_expr_hir(strip_casts(base))? {
+                    Res::Local(hir_id) => base_buffer(cx, ptr_locals.get(&hir_id)?)?,
+                    _ => return None,
+                },
+            };
+            if cx.opt_node_type(t.id) == Some(elem) { Some(buf.clone()) } else { None }
+        };
+
+        // Fill in `$buf`, `$n`, `$t` and `$f` for a call, or return `None` if it can't be
+        // converted.
+        let call_bindings = |m: &Bindings| -> Option<Bindings> {
+            let size = strip_casts(m.get::<_, P<Expr>>("$size").unwrap());
+            let t = mcx.clone_match(&*size_pat, &**size).ok()?
+                .bindings.get::<_, P<Ty>>("$t").unwrap().clone();
+            let buf = buffer(m.get::<_, P<Expr>>("$base").unwrap(), &t)?;
+
+            let cmp = m.get::<_, P<Expr>>("$cmp").unwrap();
+            let f = match mcx.clone_match(&*some_pat, &**cmp) {
+                Ok(fm) => strip_casts(fm.bindings.get::<_, P<Expr>>("$f").unwrap()).clone(),
+                Err(_) => {
+                    let mut bnd = Bindings::new();
+                    bnd.add("$cmp", cmp.clone());
+                    unwrap_repl.clone().subst(st, cx, &bnd)
+                }
+            };
+
+            let mut bnd = Bindings::new();
+            bnd.add("$buf", buf);
+            bnd.add("$n", strip_casts(m.get::<_, P<Expr>>("$n").unwrap()).clone());
+            bnd.add("$t", t);
+            bnd.add("$f", f);
+            Some(bnd)
+        };
+        let warn_skip = |name: &str, e: &Expr| {
+            warn!("qsort_to_sort: skipping `{}` on an unrecognized buffer at {}", name,
+                  cx.session().source_map().span_to_string(e.span));
+        };
+
+        // (2) Rewrite the calls.
+
+        MutVisitNodes::visit(krate, |b: &mut P<Block>| {
+            for s in &mut b.stmts {
+                let e = match_or!([s.kind] StmtKind::Semi(ref mut e) => e; continue);
+                let m = match_or!([mcx.clone_match(&*qsort_pat, &*e)] Ok(m) => m; continue);
+                match call_bindings(&m.bindings) {
+                    Some(bnd) => *e = sort_repl.clone().subst(st, cx, &bnd),
+                    None => warn_skip("qsort", e),
+                }
+            }
+        });
+
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            // A cast of the result gives the pointer type to produce.
+            let (call, cast_ty) = match e.kind {
+                ExprKind::Cast(ref inner, ref ty) => (strip_casts(inner), Some(ty)),
+                _ => (&*e, None),
+            };
+            let m = match_or!([mcx.clone_match(&*bsearch_pat, &**call)] Ok(m) => m; return);
+            let mut bnd = match call_bindings(&m.bindings) {
+                Some(x) => x,
+                // The call itself is visited next, so only warn about it once.
+                None if cast_ty.is_some() => return,
+                None => return warn_skip("bsearch", call),
+            };
+            bnd.add("$key", m.bindings.get::<_, P<Expr>>("$key").unwrap().clone());
+            let new_e = match cast_ty {
+                Some(ty) => {
+                    bnd.add("$u", ty.clone());
+                    search_cast_repl.clone().subst(st, cx, &bnd)
+                }
+                None => search_repl.clone().subst(st, cx, &bnd),
+            };
+            *e = new_e;
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
+pub fn register_commands(reg: &mut Registry) {
+    use super::mk;
+
+    reg.register("qsort_to_sort", |_args| mk(QsortToSort));
+}
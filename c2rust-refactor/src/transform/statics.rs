@@ -1,19 +1,29 @@
 use std::collections::{HashMap, HashSet};
 use std::mem;
-use rustc::hir::def_id::DefId;
+use rustc::hir::def::DefKind;
+use rustc::hir::def_id::{DefId, LOCAL_CRATE};
+use rustc::ty::{self, ParamEnv};
 use syntax::ast::*;
+use syntax::print::pprust;
 use syntax::ptr::P;
 use syntax::symbol::Symbol;
-use smallvec::smallvec;
+use syntax::util::comments::{Comment, CommentStyle};
+use syntax::visit::{self, Visitor};
+use syntax_pos::BytePos;
+use smallvec::{smallvec, SmallVec};
 
-use crate::ast_manip::{FlatMapNodes, MutVisitNodes, fold_modules};
-use crate::ast_manip::fn_edit::mut_visit_fns;
+use crate::ast_manip::{FlatMapNodes, MutVisitNodes, fold_modules, visit_nodes};
+use crate::ast_manip::fn_edit::{mut_visit_fns, visit_fns};
+use crate::ast_manip::lr_expr::{self, fold_exprs_with_context};
 use crate::command::{CommandState, Registry};
-use crate::driver::{parse_expr};
+use crate::driver::{Phase, parse_expr, parse_ty};
 use crate::matcher::{Bindings, BindingType, MatchCtxt, Subst, mut_visit_match_with};
 use crate::path_edit::fold_resolved_paths;
 use crate::transform::Transform;
+use crate::transform::canonicalize_refs::strip_parens;
+use crate::transform::mem::strip_casts;
 use c2rust_ast_builder::{mk, IntoSymbol};
+use crate::util::Lone;
 use crate::util::dataflow;
 use crate::RefactorCtxt;
 
@@ -497,6 +507,1627 @@ impl Transform for StaticToLocal {
 
 
 
+/// # `static_mut_to_safe` Command
+///
+/// Usage: `static_mut_to_safe [rwlock=1]`
+///
+/// Marks: `target`
+///
+/// Convert each `static mut` marked `target` into an immutable static with interior mutability,
+/// so accessing it no longer requires `unsafe`:
+///
+///  * A static of integer or `bool` type becomes the matching atomic, such as `AtomicI32` or
+///    `AtomicBool`.  Reads become `FOO.load(Ordering::SeqCst)` and assignments become
+///    `FOO.store(..)`.  Compound assignment statements become `fetch_add` and similar calls
+///    where the atomic has one.
+///  * A static of another `Copy` type that is assigned exactly once, as a whole, by a
+///    top-level statement of `main` becomes a `OnceCell`.  The assignment becomes `FOO.set(..)`
+///    and reads become `*FOO.get().unwrap()`.  This is only done when the assignment provably
+///    runs once, before any read: no earlier statement of `main` mentions the static or calls a
+///    function, and `main` is never called or referenced itself.  The original initializer is
+///    then never observed, so it is dropped.
+///  * Any other `Copy` static becomes a `Mutex`, lazily created from the original initializer,
+///    and each access becomes `*FOO.lock().unwrap()`.  With `rwlock=1`, an `RwLock` is used
+///    instead, with `read()` for reads and `write()` for writes.
+///
+/// The rewrites are all expression-level, so accesses from `extern "C"` functions keep
+/// working.  `OnceCell` and the lazy initialization of locks come from the `once_cell` crate,
+/// which the crate being refactored must depend on.
+///
+/// Statics whose address is taken and statics of non-`Copy` types are skipped with a warning.
+/// A lock guard lives until the end of the statement that took it, or of the whole `match` or
+/// `if let` whose scrutinee took it, so statics that would become locks are also skipped if any
+/// single statement accesses them more than once, counting the blocks nested inside it.
+///
+/// Example:
+///
+/// ```ignore
+///     static mut COUNT: i32 = 0;
+///
+///     unsafe fn bump() {
+///         COUNT += 1;
+///     }
+/// ```
+///
+/// After running `static_mut_to_safe`, with `COUNT` marked:
+///
+/// ```ignore
+///     static COUNT: ::std::sync::atomic::AtomicI32 = ::std::sync::atomic::AtomicI32::new(0);
+///
+///     unsafe fn bump() {
+///         COUNT.fetch_add(1, ::std::sync::atomic::Ordering::SeqCst);
+///     }
+/// ```
+pub struct StaticMutToSafe {
+    pub rwlock: bool,
+}
+
+/// What a `static mut` is converted into.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum SafeStatic {
+    Atomic,
+    Once,
+    Lock,
+}
+
+/// If `e` is a path to one of `statics`, return its `DefId`.
+fn static_use<V>(cx: &RefactorCtxt, statics: &HashMap<DefId, V>, e: &Expr) -> Option<DefId> {
+    match e.kind {
+        ExprKind::Path(..) => cx.try_resolve_expr(e).filter(|def_id| statics.contains_key(def_id)),
+        _ => None,
+    }
+}
+
+/// The `std` atomic type for the integer or `bool` type `ty`, like
+/// `::std::sync::atomic::AtomicI32`.
+fn atomic_type(ty: ty::Ty) -> String {
+    let name = ty.to_string();
+    format!("::std::sync::atomic::Atomic{}{}", name[..1].to_uppercase(), &name[1..])
+}
+
+/// Strip fields, indexing, and parentheses from the place `e`, to get at the variable it is
+/// part of.
+fn place_root(e: &Expr) -> &Expr {
+    match e.kind {
+        ExprKind::Field(ref base, _) |
+        ExprKind::Index(ref base, _) |
+        ExprKind::Paren(ref base) => place_root(base),
+        _ => e,
+    }
+}
+
+/// Counts the uses of each item in a statement.  Uses inside nested blocks are only counted if
+/// `nested` is set.
+struct StmtUseCounter<'a, 'tcx: 'a> {
+    cx: &'a RefactorCtxt<'a, 'tcx>,
+    counts: HashMap<DefId, usize>,
+    nested: bool,
+}
+
+impl<'a, 'tcx, 'ast> Visitor<'ast> for StmtUseCounter<'a, 'tcx> {
+    fn visit_expr(&mut self, e: &'ast Expr) {
+        if let ExprKind::Path(..) = e.kind {
+            if let Some(def_id) = self.cx.try_resolve_expr(e) {
+                *self.counts.entry(def_id).or_insert(0) += 1;
+            }
+        }
+        visit::walk_expr(self, e);
+    }
+
+    fn visit_block(&mut self, b: &'ast Block) {
+        if self.nested {
+            visit::walk_block(self, b);
+        }
+    }
+
+    fn visit_mac(&mut self, _mac: &'ast Mac) {}
+}
+
+/// Find the `statics` that are assigned by a top-level statement of `main` that provably runs
+/// once, before anything else could read them: `main` is never called or referenced, and no
+/// earlier statement of `main` mentions the static or calls any function.  The statement itself
+/// must not call anything or read the static either.
+fn main_init_writes<V>(cx: &RefactorCtxt, krate: &Crate, statics: &HashMap<DefId, V>)
+                       -> HashSet<DefId> {
+    let mut found = HashSet::new();
+    let main_id = match cx.ty_ctxt().entry_fn(LOCAL_CRATE) {
+        Some((def_id, _)) if def_id.is_local() => def_id,
+        _ => return found,
+    };
+    let mut main_refs = 0;
+    visit_nodes(krate, |e: &Expr| {
+        if let ExprKind::Path(..) = e.kind {
+            if cx.try_resolve_expr(e) == Some(main_id) {
+                main_refs += 1;
+            }
+        }
+    });
+    if main_refs > 0 {
+        return found;
+    }
+
+    visit_fns(krate, |fl| {
+        if cx.node_def_id(fl.id) != main_id {
+            return;
+        }
+        let mut block = match fl.block {
+            Some(ref block) => block,
+            None => return,
+        };
+        // Look inside the `unsafe` block that makes up the body of a transpiled `main`.
+        if block.stmts.len() == 1 {
+            if let StmtKind::Expr(ref e) = block.stmts[0].kind {
+                if let ExprKind::Block(ref inner, None) = e.kind {
+                    block = inner;
+                }
+            }
+        }
+
+        let mut seen = HashSet::new();
+        for s in &block.stmts {
+            let mut calls = false;
+            let mut mentioned = Vec::new();
+            visit_nodes(s, |e: &Expr| match e.kind {
+                ExprKind::Call(..) | ExprKind::MethodCall(..) | ExprKind::Mac(..) => calls = true,
+                _ => mentioned.extend(static_use(cx, statics, e)),
+            });
+            if let StmtKind::Semi(ref e) = s.kind {
+                if let ExprKind::Assign(ref lhs, _) = e.kind {
+                    if let Some(def_id) = static_use(cx, statics, lhs) {
+                        let reads = mentioned.iter().filter(|&&id| id == def_id).count() - 1;
+                        if !calls && reads == 0 && !seen.contains(&def_id) {
+                            found.insert(def_id);
+                        }
+                    }
+                }
+            }
+            if calls {
+                break;
+            }
+            seen.extend(mentioned);
+        }
+    });
+    found
+}
+
+impl Transform for StaticMutToSafe {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        // (1) Collect the marked statics, and decide what to convert each one into.
+
+        let mut statics: HashMap<DefId, (Ident, SafeStatic)> = HashMap::new();
+        visit_nodes(krate, |i: &Item| {
+            if !st.marked(i.id, "target") {
+                return;
+            }
+            match i.kind {
+                ItemKind::Static(_, Mutability::Mutable, _) => {}
+                _ => return,
+            }
+            let def_id = cx.node_def_id(i.id);
+            let static_ty = cx.ty_ctxt().type_of(def_id);
+            let kind = match static_ty.kind {
+                ty::TyKind::Bool | ty::TyKind::Int(_) | ty::TyKind::Uint(_) => SafeStatic::Atomic,
+                _ if static_ty.is_copy_modulo_regions(cx.ty_ctxt(), ParamEnv::empty(), i.span) =>
+                    SafeStatic::Lock,
+                _ => {
+                    warn!("static_mut_to_safe: `{}` is not `Copy`; skipping", i.ident);
+                    return;
+                }
+            };
+            statics.insert(def_id, (i.ident, kind));
+        });
+
+        // (2) Check how each static is used.
+
+        let mut rejected = HashSet::new();
+        let mut writes: HashMap<DefId, usize> = HashMap::new();
+        let mut whole_writes: HashMap<DefId, usize> = HashMap::new();
+        visit_nodes(krate, |e: &Expr| {
+            match e.kind {
+                ExprKind::AddrOf(_, _, ref place) => {
+                    if let Some(def_id) = static_use(cx, &statics, place_root(place)) {
+                        warn!("static_mut_to_safe: address of `{}` is taken at {}; skipping",
+                              statics[&def_id].0,
+                              cx.session().source_map().span_to_string(e.span));
+                        rejected.insert(def_id);
+                    }
+                }
+                ExprKind::Assign(ref lhs, _) | ExprKind::AssignOp(_, ref lhs, _) => {
+                    if let Some(def_id) = static_use(cx, &statics, place_root(lhs)) {
+                        *writes.entry(def_id).or_insert(0) += 1;
+                    }
+                    if let ExprKind::Assign(..) = e.kind {
+                        if let Some(def_id) = static_use(cx, &statics, lhs) {
+                            *whole_writes.entry(def_id).or_insert(0) += 1;
+                        }
+                    }
+                }
+                _ => {}
+            }
+        });
+
+        let init_writes = main_init_writes(cx, krate, &statics);
+        for (def_id, &mut (_, ref mut kind)) in &mut statics {
+            let writes = writes.get(def_id).cloned().unwrap_or(0);
+            let whole_writes = whole_writes.get(def_id).cloned().unwrap_or(0);
+            if *kind == SafeStatic::Lock && writes == 1 && whole_writes == 1 &&
+               init_writes.contains(def_id) {
+                *kind = SafeStatic::Once;
+            }
+        }
+
+        visit_nodes(krate, |b: &Block| {
+            for s in &b.stmts {
+                let mut counter = StmtUseCounter { cx, counts: HashMap::new(), nested: true };
+                visit::walk_stmt(&mut counter, s);
+                for (def_id, count) in counter.counts {
+                    let is_lock = statics.get(&def_id)
+                        .map_or(false, |&(_, kind)| kind == SafeStatic::Lock);
+                    if is_lock && count > 1 && rejected.insert(def_id) {
+                        warn!("static_mut_to_safe: `{}` is locked twice in the statement at {}; \
+                               skipping",
+                              statics[&def_id].0,
+                              cx.session().source_map().span_to_string(s.span));
+                    }
+                }
+            }
+        });
+
+        statics.retain(|def_id, _| !rejected.contains(def_id));
+
+        // (3) Change the types and initializers of the statics.
+
+        let mut mcx = MatchCtxt::new(st, cx);
+        let lock = if self.rwlock { "RwLock" } else { "Mutex" };
+        let ordering = "::std::sync::atomic::Ordering::SeqCst";
+
+        FlatMapNodes::visit(krate, |i: P<Item>| {
+            if !st.marked(i.id, "target") {
+                return smallvec![i];
+            }
+            let def_id = cx.node_def_id(i.id);
+            let kind = match statics.get(&def_id) {
+                Some(&(_, kind)) => kind,
+                None => return smallvec![i],
+            };
+
+            smallvec![i.map(|mut i| {
+                if let ItemKind::Static(ref mut ty, ref mut mutbl, ref mut init) = i.kind {
+                    let (new_ty, new_init) = match kind {
+                        SafeStatic::Atomic => {
+                            let atomic = atomic_type(cx.ty_ctxt().type_of(def_id));
+                            let init = format!("{}::new($e)", atomic);
+                            (atomic, init)
+                        }
+                        SafeStatic::Once => (
+                            format!("::once_cell::sync::OnceCell<{}>", pprust::ty_to_string(ty)),
+                            "::once_cell::sync::OnceCell::new()".to_owned(),
+                        ),
+                        SafeStatic::Lock => (
+                            format!("::once_cell::sync::Lazy<::std::sync::{}<{}>>",
+                                    lock, pprust::ty_to_string(ty)),
+                            format!("::once_cell::sync::Lazy::new(|| ::std::sync::{}::new($e))",
+                                    lock),
+                        ),
+                    };
+                    let mut bnd = Bindings::new();
+                    bnd.add("$e", init.clone());
+                    *init = mcx.parse_expr(&new_init).subst(st, cx, &bnd);
+                    *ty = parse_ty(cx.session(), &new_ty);
+                    *mutbl = Mutability::Immutable;
+                }
+                i
+            })]
+        });
+
+        // (4) Rewrite assignments to the statics.  As in `retype_static`, the IDs of the
+        // rewritten left-hand sides are recorded so the next step leaves them alone.
+
+        let mut handled_ids: HashSet<NodeId> = HashSet::new();
+
+        // `fetch_add` and friends return the old value, so they're only used in statements,
+        // where the value of the assignment is discarded.
+        MutVisitNodes::visit(krate, |b: &mut P<Block>| {
+            for s in &mut b.stmts {
+                let e = match s.kind {
+                    StmtKind::Semi(ref mut e) => e,
+                    _ => continue,
+                };
+                let (op, lhs, rhs) = match e.kind {
+                    ExprKind::AssignOp(op, ref lhs, ref rhs) => (op.node, lhs, rhs),
+                    _ => continue,
+                };
+                match static_use(cx, &statics, lhs).map(|def_id| statics[&def_id].1) {
+                    Some(SafeStatic::Atomic) => {}
+                    _ => continue,
+                }
+                let method = match op {
+                    BinOpKind::Add => "fetch_add",
+                    BinOpKind::Sub => "fetch_sub",
+                    BinOpKind::BitAnd => "fetch_and",
+                    BinOpKind::BitOr => "fetch_or",
+                    BinOpKind::BitXor => "fetch_xor",
+                    _ => continue,
+                };
+                let mut bnd = Bindings::new();
+                bnd.add("$x", lhs.clone());
+                bnd.add("$e", rhs.clone());
+                handled_ids.insert(lhs.id);
+                *e = mcx.parse_expr(&format!("$x.{}($e, {})", method, ordering))
+                    .subst(st, cx, &bnd);
+            }
+        });
+
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            let (op, lhs, rhs) = match e.kind {
+                ExprKind::Assign(ref lhs, ref rhs) => (None, lhs, rhs),
+                ExprKind::AssignOp(op, ref lhs, ref rhs) => (Some(op.node), lhs, rhs),
+                _ => return,
+            };
+            let (ident, kind) = match static_use(cx, &statics, lhs) {
+                Some(def_id) => statics[&def_id],
+                None => return,
+            };
+            let src = match (kind, op) {
+                (SafeStatic::Atomic, None) => format!("$x.store($e, {})", ordering),
+                (SafeStatic::Atomic, Some(op)) =>
+                    format!("$x.store($x.load({o}) {} $e, {o})", op.to_string(), o = ordering),
+                (SafeStatic::Once, _) =>
+                    format!("$x.set($e).ok().expect(\"`{}` was already set\")", ident),
+                (SafeStatic::Lock, _) => return,
+            };
+            let mut bnd = Bindings::new();
+            bnd.add("$x", lhs.clone());
+            bnd.add("$e", rhs.clone());
+            handled_ids.insert(lhs.id);
+            *e = mcx.parse_expr(&src).subst(st, cx, &bnd);
+        });
+
+        // (5) Rewrite the remaining uses of the statics.
+
+        fold_exprs_with_context(krate, |e, ectx| {
+            if handled_ids.contains(&e.id) {
+                return;
+            }
+            let kind = match static_use(cx, &statics, e) {
+                Some(def_id) => statics[&def_id].1,
+                None => return,
+            };
+            let src = match kind {
+                SafeStatic::Atomic => format!("$x.load({})", ordering),
+                SafeStatic::Once => "*$x.get().unwrap()".to_owned(),
+                SafeStatic::Lock if !self.rwlock => "*$x.lock().unwrap()".to_owned(),
+                SafeStatic::Lock => match ectx {
+                    lr_expr::Context::LvalueMut => "*$x.write().unwrap()".to_owned(),
+                    _ => "*$x.read().unwrap()".to_owned(),
+                },
+            };
+            let mut bnd = Bindings::new();
+            bnd.add("$x", e.clone());
+            *e = mcx.parse_expr(&src).subst(st, cx, &bnd);
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
+/// # `volatile_to_atomic` Command
+///
+/// Usage: `volatile_to_atomic [escaping=keep]`
+///
+/// Marks: `target`
+///
+/// Convert marked integer statics of up to 64 bits, which were `volatile` in C,
+/// into `std` atomics:
+///
+///  * The type of the static becomes the matching
+///    `::std::sync::atomic::AtomicXxx`, and a `static mut` becomes a `static`.
+///  * `X = v` and `write_volatile(&mut X, v)` become `X.store(v, SeqCst)`.
+///  * `X += v`, `X = X + v` and `X = X.wrapping_add(v)` statements, and
+///    their `write_volatile` forms, become `X.fetch_add(v, SeqCst);`.  `-`,
+///    `&`, `|` and `^` work the same way; other compound assignments become a
+///    `load` and a `store`.
+///  * `read_volatile(&X)` and other reads of `X` become `X.load(SeqCst)`.
+///
+/// Every access uses `SeqCst`, and each statement with a converted access gets
+/// a comment noting it, so weaker orderings can be chosen by hand later.
+///
+/// A static whose address is taken other than for `read_volatile` and
+/// `write_volatile`, such as to pass it to extern code, is left alone with a
+/// warning.  With `escaping=keep`, such a static is converted anyway when its
+/// address is only ever cast to a raw pointer: the atomic types have the same
+/// in-memory representation as their integer types, so `&mut X as *mut T`
+/// becomes `&X as *const AtomicXxx as *mut xxx as *mut T`, and a `#[no_mangle]`
+/// static keeps its symbol for extern code.
+pub struct VolatileToAtomic {
+    pub keep_escaping: bool,
+}
+
+/// If `e` is a call to `read_volatile` or `write_volatile`, return the name of
+/// the function and the arguments.
+fn volatile_call<'a>(cx: &RefactorCtxt, e: &'a Expr) -> Option<(&'static str, &'a [P<Expr>])> {
+    let args = match e.kind {
+        ExprKind::Call(_, ref args) if !args.is_empty() => args,
+        _ => return None,
+    };
+    let def_id = cx.opt_callee(e)?;
+    if def_id.is_local() {
+        return None;
+    }
+    let name = cx.ty_ctxt().item_name(def_id);
+    ["read_volatile", "write_volatile"].iter()
+        .find(|&&n| &*name.as_str() == n)
+        .map(|&n| (n, &args[..]))
+}
+
+/// If the pointer `ptr` is the address of one of `statics`, possibly cast,
+/// return the address-of expression and the static.
+fn static_addr<'a, V>(cx: &RefactorCtxt, statics: &HashMap<DefId, V>, ptr: &'a P<Expr>)
+                      -> Option<(&'a Expr, &'a P<Expr>)> {
+    let addr = strip_casts(ptr);
+    match addr.kind {
+        ExprKind::AddrOf(_, _, ref place) if static_use(cx, statics, place).is_some() =>
+            Some((addr, place)),
+        _ => None,
+    }
+}
+
+/// If `e` reads one of `statics`, as `X` or as `read_volatile(&X)`, return the
+/// path to the static.
+fn static_read<'a, V>(cx: &RefactorCtxt, statics: &HashMap<DefId, V>, e: &'a Expr)
+                      -> Option<&'a Expr> {
+    let e = strip_parens(e);
+    if static_use(cx, statics, e).is_some() {
+        return Some(e);
+    }
+    match volatile_call(cx, e)? {
+        ("read_volatile", args) => static_addr(cx, statics, &args[0]).map(|(_, x)| &**x),
+        _ => None,
+    }
+}
+
+/// If `e` writes one of `statics`, as `X = v` or as `write_volatile(&mut X, v)`,
+/// return the path to the static and the written value.
+fn static_write<'a, V>(cx: &RefactorCtxt, statics: &HashMap<DefId, V>, e: &'a Expr)
+                       -> Option<(&'a P<Expr>, &'a P<Expr>)> {
+    if let ExprKind::Assign(ref lhs, ref rhs) = e.kind {
+        return static_use(cx, statics, lhs).map(|_| (lhs, rhs));
+    }
+    match volatile_call(cx, e)? {
+        ("write_volatile", args) if args.len() == 2 =>
+            static_addr(cx, statics, &args[0]).map(|(_, x)| (x, &args[1])),
+        _ => None,
+    }
+}
+
+/// The atomic read-modify-write method for `op`, if there is one.
+fn fetch_method(op: BinOpKind) -> Option<&'static str> {
+    match op {
+        BinOpKind::Add => Some("fetch_add"),
+        BinOpKind::Sub => Some("fetch_sub"),
+        BinOpKind::BitAnd => Some("fetch_and"),
+        BinOpKind::BitOr => Some("fetch_or"),
+        BinOpKind::BitXor => Some("fetch_xor"),
+        _ => None,
+    }
+}
+
+/// If the value `v` written to the static `def_id` is computed from its old
+/// value with an atomic read-modify-write operation, return the operation and
+/// its other operand.
+fn rmw_operand<'a, V>(cx: &RefactorCtxt, statics: &HashMap<DefId, V>, def_id: DefId,
+                      v: &'a Expr) -> Option<(BinOpKind, &'a P<Expr>)> {
+    let is_old = |e: &P<Expr>| {
+        static_read(cx, statics, e).and_then(|x| cx.try_resolve_expr(x)) == Some(def_id)
+    };
+    let (op, operand) = match strip_parens(v).kind {
+        ExprKind::Binary(op, ref a, ref b) if is_old(a) => (op.node, b),
+        ExprKind::Binary(op, ref a, ref b) if is_old(b) && op.node != BinOpKind::Sub =>
+            (op.node, a),
+        ExprKind::MethodCall(ref seg, ref args) if args.len() == 2 && is_old(&args[0]) =>
+            match &*seg.ident.as_str() {
+                "wrapping_add" => (BinOpKind::Add, &args[1]),
+                "wrapping_sub" => (BinOpKind::Sub, &args[1]),
+                _ => return None,
+            },
+        _ => return None,
+    };
+    fetch_method(op).map(|_| (op, operand))
+}
+
+impl Transform for VolatileToAtomic {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        // (1) Collect the marked statics.
+
+        let mut statics: HashMap<DefId, Ident> = HashMap::new();
+        visit_nodes(krate, |i: &Item| {
+            if !st.marked(i.id, "target") {
+                return;
+            }
+            match i.kind {
+                ItemKind::Static(..) => {}
+                _ => return,
+            }
+            let def_id = cx.node_def_id(i.id);
+            match cx.ty_ctxt().type_of(def_id).kind {
+                ty::TyKind::Int(IntTy::I128) | ty::TyKind::Uint(UintTy::U128) => {}
+                ty::TyKind::Int(_) | ty::TyKind::Uint(_) => {
+                    statics.insert(def_id, i.ident);
+                    return;
+                }
+                _ => {}
+            }
+            warn!("volatile_to_atomic: `{}` is not an integer of up to 64 bits; skipping",
+                  i.ident);
+        });
+
+        // (2) Find the statics whose address escapes.  The addresses taken for
+        // `read_volatile` and `write_volatile` don't count, and with `escaping=keep`,
+        // neither do addresses that are cast to raw pointers.
+
+        let mut volatile_addrs = HashSet::new();
+        let mut ptr_addrs = HashSet::new();
+        visit_nodes(krate, |e: &Expr| {
+            match e.kind {
+                ExprKind::Cast(ref inner, ref ty) => {
+                    if let TyKind::Ptr(_) = ty.kind {
+                        ptr_addrs.insert(inner.id);
+                    }
+                }
+                _ => {
+                    if let Some((_, args)) = volatile_call(cx, e) {
+                        if let Some((addr, _)) = static_addr(cx, &statics, &args[0]) {
+                            volatile_addrs.insert(addr.id);
+                        }
+                    }
+                }
+            }
+        });
+
+        let mut escaping = HashSet::new();
+        let mut rejected = HashSet::new();
+        visit_nodes(krate, |e: &Expr| {
+            let place = match e.kind {
+                ExprKind::AddrOf(_, _, ref place) => place,
+                _ => return,
+            };
+            let def_id = match static_use(cx, &statics, place_root(place)) {
+                Some(x) => x,
+                None => return,
+            };
+            if volatile_addrs.contains(&e.id) {
+                return;
+            }
+            if self.keep_escaping && ptr_addrs.contains(&e.id) &&
+                    static_use(cx, &statics, place).is_some() {
+                escaping.insert(e.id);
+                return;
+            }
+            if rejected.insert(def_id) {
+                warn!("volatile_to_atomic: address of `{}` escapes at {}; skipping",
+                      statics[&def_id], cx.session().source_map().span_to_string(e.span));
+            }
+        });
+
+        statics.retain(|def_id, _| !rejected.contains(def_id));
+
+        // (3) Change the types and initializers of the statics.
+
+        let mut mcx = MatchCtxt::new(st, cx);
+        let ordering = "::std::sync::atomic::Ordering::SeqCst";
+
+        FlatMapNodes::visit(krate, |i: P<Item>| {
+            if !st.marked(i.id, "target") {
+                return smallvec![i];
+            }
+            let def_id = cx.node_def_id(i.id);
+            if !statics.contains_key(&def_id) {
+                return smallvec![i];
+            }
+            smallvec![i.map(|mut i| {
+                if let ItemKind::Static(ref mut ty, ref mut mutbl, ref mut init) = i.kind {
+                    let atomic = atomic_type(cx.ty_ctxt().type_of(def_id));
+                    let mut bnd = Bindings::new();
+                    bnd.add("$e", init.clone());
+                    *init = mcx.parse_expr(&format!("{}::new($e)", atomic)).subst(st, cx, &bnd);
+                    *ty = parse_ty(cx.session(), &atomic);
+                    *mutbl = Mutability::Immutable;
+                }
+                i
+            })]
+        });
+
+        // (4) Rewrite the writes, then the reads.  The IDs of the paths to the
+        // statics in rewritten accesses are recorded, so later steps leave them
+        // alone.
+
+        let mut handled_ids: HashSet<NodeId> = HashSet::new();
+        let mut access = |src: &str, x: &P<Expr>, v: Option<&P<Expr>>,
+                          handled_ids: &mut HashSet<_>| {
+            let mut bnd = Bindings::new();
+            bnd.add("$x", x.clone());
+            if let Some(v) = v {
+                bnd.add("$e", v.clone());
+            }
+            handled_ids.insert(x.id);
+            mcx.parse_expr(src).subst(st, cx, &bnd)
+        };
+
+        // Read-modify-write statements become `fetch_add` and friends, which
+        // return the old value, so only statements discarding it are handled here.
+        MutVisitNodes::visit(krate, |b: &mut P<Block>| {
+            for s in &mut b.stmts {
+                let e = match s.kind {
+                    StmtKind::Semi(ref mut e) => e,
+                    _ => continue,
+                };
+                let (x, op, v) = match e.kind {
+                    ExprKind::AssignOp(op, ref lhs, ref rhs)
+                            if static_use(cx, &statics, lhs).is_some() => (lhs, op.node, rhs),
+                    _ => match static_write(cx, &statics, e) {
+                        Some((x, v)) => {
+                            let def_id = cx.resolve_expr(x);
+                            match rmw_operand(cx, &statics, def_id, v) {
+                                Some((op, v)) => (x, op, v),
+                                None => continue,
+                            }
+                        }
+                        None => continue,
+                    },
+                };
+                let method = match fetch_method(op) {
+                    Some(x) => x,
+                    None => continue,
+                };
+                let src = format!("$x.{}($e, {})", method, ordering);
+                *e = access(&src, x, Some(v), &mut handled_ids);
+            }
+        });
+
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            let new_e = if let Some((x, v)) = static_write(cx, &statics, e) {
+                access(&format!("$x.store($e, {})", ordering), x, Some(v), &mut handled_ids)
+            } else if let ExprKind::AssignOp(op, ref lhs, ref rhs) = e.kind {
+                if static_use(cx, &statics, lhs).is_none() {
+                    return;
+                }
+                let src = format!("$x.store($x.load({o}) {} $e, {o})", op.node.to_string(),
+                                  o = ordering);
+                access(&src, lhs, Some(rhs), &mut handled_ids)
+            } else if let Some(("read_volatile", args)) = volatile_call(cx, e) {
+                match static_addr(cx, &statics, &args[0]) {
+                    Some((_, x)) => access(&format!("$x.load({})", ordering), x, None,
+                                           &mut handled_ids),
+                    None => return,
+                }
+            } else if escaping.contains(&e.id) {
+                let x = expect!([e.kind] ExprKind::AddrOf(_, _, ref x) => x);
+                let ty = cx.ty_ctxt().type_of(cx.resolve_expr(x));
+                let src = format!("&$x as *const {} as *mut {}", atomic_type(ty), ty);
+                access(&src, x, None, &mut handled_ids)
+            } else {
+                return;
+            };
+            *e = new_e;
+        });
+
+        fold_exprs_with_context(krate, |e, ectx| {
+            if handled_ids.contains(&e.id) || static_use(cx, &statics, e).is_none() {
+                return;
+            }
+            if let lr_expr::Context::Rvalue = ectx {
+                let new_e = access(&format!("$x.load({})", ordering), e, None,
+                                   &mut handled_ids);
+                *e = new_e;
+            }
+        });
+
+        // (5) Note the chosen ordering on each statement with an access.
+
+        visit_nodes(krate, |b: &Block| {
+            for s in &b.stmts {
+                let mut counter = StmtUseCounter { cx, counts: HashMap::new(), nested: false };
+                visit::walk_stmt(&mut counter, s);
+                let mut names = counter.counts.keys()
+                    .filter_map(|def_id| statics.get(def_id))
+                    .map(|ident| format!("`{}`", ident))
+                    .collect::<Vec<_>>();
+                if names.is_empty() {
+                    continue;
+                }
+                names.sort();
+                st.add_comment(s.id, Comment {
+                    style: CommentStyle::Isolated,
+                    lines: vec![format!("// volatile_to_atomic: chose `SeqCst` for {}; a \
+                                         weaker ordering may be enough", names.join(", "))],
+                    pos: BytePos(0),
+                });
+            }
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
+/// # `fnptr_table_to_match` Command
+///
+/// Usage: `fnptr_table_to_match [mode=match|trait] [delete_unused=1]`
+///
+/// Marks: `target`
+///
+/// Replace calls through a marked static array of function pointers, whose
+/// initializer lists the functions directly.
+///
+/// Example:
+///
+/// ```ignore
+///     static mut HANDLERS: [Option<unsafe extern "C" fn(c_int) -> c_int>; 2] =
+///         [Some(op_inc), Some(op_dec)];
+///
+///     unsafe fn run(op: c_int, x: c_int) -> c_int {
+///         HANDLERS[op as usize].expect("non-null function pointer")(x)
+///     }
+/// ```
+///
+/// After running `fnptr_table_to_match`, with `HANDLERS` marked:
+///
+/// ```ignore
+///     static mut HANDLERS: [Option<unsafe extern "C" fn(c_int) -> c_int>; 2] =
+///         [Some(op_inc), Some(op_dec)];
+///     unsafe fn dispatch_handlers(index: usize, arg0: c_int) -> c_int {
+///         match index {
+///             0 => op_inc(arg0),
+///             1 => op_dec(arg0),
+///             _ => panic!("no function at index {} of `HANDLERS`", index),
+///         }
+///     }
+///
+///     unsafe fn run(op: c_int, x: c_int) -> c_int {
+///         dispatch_handlers(op as usize, x)
+///     }
+/// ```
+///
+/// Tables that are written at runtime are left alone with a warning.  With
+/// `delete_unused=1`, tables that have no uses left after the calls are
+/// rewritten are deleted.
+///
+/// With `mode=trait`, which handles `static mut` tables written at runtime, the
+/// table becomes a table of trait objects instead.  A trait named after the
+/// table gets a `call` method with the signature of the functions, and each
+/// function stored in the table gets a unit struct implementing it:
+///
+/// ```ignore
+///     trait Handlers {
+///         unsafe fn call(&self, arg0: c_int) -> c_int;
+///     }
+///     struct OpInc;
+///     impl Handlers for OpInc {
+///         unsafe fn call(&self, arg0: c_int) -> c_int {
+///             op_inc(arg0)
+///         }
+///     }
+///     // ... and `OpDec`
+///     static mut HANDLERS: ::once_cell::sync::Lazy<[Option<Box<dyn Handlers>>; 2]> =
+///         ::once_cell::sync::Lazy::new(|| [Some(Box::new(OpInc)), Some(Box::new(OpDec))]);
+///
+///     unsafe fn run(op: c_int, x: c_int) -> c_int {
+///         HANDLERS[op as usize].as_ref().expect("non-null function pointer").call(x)
+///     }
+/// ```
+///
+/// Stores into the table, like `HANDLERS[i] = Some(op_inc)`, store the struct
+/// of the function instead.  In this mode, the table must only be used in
+/// calls, in stores of functions or `None`, and in `is_some()` and `is_none()`
+/// checks; other tables are left alone with a warning.
+pub struct FnptrTableToMatch {
+    pub trait_mode: bool,
+    pub delete_unused: bool,
+}
+
+/// A static array of function pointers, and the functions its initializer lists.
+struct FnTable {
+    name: Ident,
+    fn_ty: P<BareFnTy>,
+    /// Whether the entries are `Option`s.
+    optional: bool,
+    len: AnonConst,
+    /// The function of each entry, or `None` for a `None` entry.
+    entries: Vec<Option<P<Expr>>>,
+}
+
+impl FnTable {
+    fn new(cx: &RefactorCtxt, name: Ident, ty: &Ty, init: &Expr) -> Option<FnTable> {
+        let (elem, len) = match ty.kind {
+            TyKind::Array(ref elem, ref len) => (elem, len),
+            _ => return None,
+        };
+        let (fn_ty, optional) = match elem.kind {
+            TyKind::BareFn(ref fn_ty) => (fn_ty, false),
+            TyKind::Path(None, ref path) => {
+                let seg = path.segments.last()?;
+                let args = match seg.args.as_ref().map(|a| &**a) {
+                    Some(GenericArgs::AngleBracketed(a)) if &*seg.ident.as_str() == "Option" =>
+                        &a.args,
+                    _ => return None,
+                };
+                match &args[..] {
+                    [GenericArg::Type(ty)] => match ty.kind {
+                        TyKind::BareFn(ref fn_ty) => (fn_ty, true),
+                        _ => return None,
+                    },
+                    _ => return None,
+                }
+            }
+            _ => return None,
+        };
+        if fn_ty.decl.c_variadic() || !fn_ty.generic_params.is_empty() {
+            return None;
+        }
+        let entries = match init.kind {
+            ExprKind::Array(ref es) => es.iter()
+                .map(|e| fn_entry(cx, e, optional))
+                .collect::<Option<Vec<_>>>()?,
+            _ => return None,
+        };
+        Some(FnTable { name, fn_ty: fn_ty.clone(), optional, len: len.clone(), entries })
+    }
+
+    /// The header of a function with the signature of the entries, and with
+    /// `first` as its first parameter.
+    fn fn_header(&self, name: &str, first: &str) -> String {
+        let decl = &self.fn_ty.decl;
+        let mut params = vec![first.to_owned()];
+        params.extend(decl.inputs.iter().enumerate()
+            .map(|(i, param)| format!("arg{}: {}", i, pprust::ty_to_string(&param.ty))));
+        let ret = match decl.output {
+            FunctionRetTy::Ty(ref ty) => format!(" -> {}", pprust::ty_to_string(ty)),
+            FunctionRetTy::Default(_) => String::new(),
+        };
+        let unsafety = match self.fn_ty.unsafety {
+            Unsafety::Unsafe => "unsafe ",
+            Unsafety::Normal => "",
+        };
+        format!("{}fn {}({}){}", unsafety, name, params.join(", "), ret)
+    }
+
+    /// A call of `f` with the parameters of `fn_header`.
+    fn fn_call(&self, f: &Expr) -> String {
+        let args = (0..self.fn_ty.decl.inputs.len())
+            .map(|i| format!("arg{}", i))
+            .collect::<Vec<_>>();
+        let f = match f.kind {
+            ExprKind::Path(..) => pprust::expr_to_string(f),
+            _ => format!("({})", pprust::expr_to_string(f)),
+        };
+        format!("{}({})", f, args.join(", "))
+    }
+
+    fn dispatch_name(&self) -> String {
+        format!("dispatch_{}", self.name.as_str().to_lowercase())
+    }
+
+    /// The function that replaces calls through the table in `mode=match`.
+    fn dispatch_fn(&self, vis: &str) -> String {
+        let arms = self.entries.iter().enumerate()
+            .filter_map(|(i, f)| Some(format!("{} => {},", i, self.fn_call(f.as_ref()?))))
+            .collect::<Vec<_>>();
+        format!("{}{} {{ match index {{ {} _ => panic!(\"no function at index {{}} of `{}`\", \
+                 index), }} }}",
+                vis, self.fn_header(&self.dispatch_name(), "index: usize"), arms.join(" "),
+                self.name)
+    }
+
+    /// The trait of the entries in `mode=trait`, and the struct and impl of
+    /// each of `fns`.
+    fn trait_items(&self, cx: &RefactorCtxt, vis: &str, fns: &[P<Expr>]) -> String {
+        let trait_name = camel_case(&self.name.as_str());
+        let header = self.fn_header("call", "&self");
+        let mut src = format!("{}trait {} {{ {}; }}", vis, trait_name, header);
+        let mut done = HashSet::new();
+        for f in fns {
+            let name = handler_name(cx, f);
+            if done.insert(name.clone()) {
+                src.push_str(&format!(" {}struct {}; impl {} for {} {{ {} {{ {} }} }}",
+                                      vis, name, trait_name, name, header, self.fn_call(f)));
+            }
+        }
+        src
+    }
+
+    /// The value stored into the table from `f` in `mode=trait`, naming the
+    /// struct of the function with a path like `table_path`.
+    fn boxed_entry(&self, cx: &RefactorCtxt, f: Option<&P<Expr>>, table_path: &Path) -> String {
+        let f = match f {
+            Some(f) => f,
+            None => return "None".to_owned(),
+        };
+        let mut path = table_path.clone();
+        *path.segments.last_mut().unwrap() =
+            PathSegment::from_ident(Ident::from_str(&handler_name(cx, f)));
+        let boxed = format!("Box::new({})", pprust::path_to_string(&path));
+        if self.optional { format!("Some({})", boxed) } else { boxed }
+    }
+}
+
+/// `name` in `CamelCase`, like `Handlers` for `HANDLERS` and `OpInc` for `op_inc`.
+fn camel_case(name: &str) -> String {
+    name.split('_').map(|word| {
+        let rest = if word.chars().all(|c| !c.is_lowercase()) {
+            word.to_lowercase()
+        } else {
+            word.to_owned()
+        };
+        let mut chars = rest.chars();
+        chars.next().map_or(String::new(), |c| c.to_uppercase().chain(chars).collect())
+    }).collect()
+}
+
+/// The name of the struct for the function `f` in `mode=trait`.
+fn handler_name(cx: &RefactorCtxt, f: &P<Expr>) -> String {
+    camel_case(&cx.ty_ctxt().item_name(cx.resolve_expr(strip_casts(f))).as_str())
+}
+
+/// Parse an entry of a table: `Some(f)` or `None` for a table of `Option`s, or
+/// `f` otherwise, where `f` is the path of a function, possibly cast.  Returns
+/// `Some(None)` for a `None` entry.
+fn fn_entry(cx: &RefactorCtxt, e: &P<Expr>, optional: bool) -> Option<Option<P<Expr>>> {
+    let is_named = |e: &Expr, name: &str| match e.kind {
+        ExprKind::Path(None, ref path) =>
+            path.segments.last().map_or(false, |seg| &*seg.ident.as_str() == name),
+        _ => false,
+    };
+    let f = match e.kind {
+        _ if !optional => e,
+        ExprKind::Call(ref callee, ref args) if args.len() == 1 && is_named(&**callee, "Some") =>
+            &args[0],
+        _ if is_named(&**e, "None") => return Some(None),
+        _ => return None,
+    };
+    let def_id = cx.try_resolve_expr(strip_casts(f))?;
+    match cx.ty_ctxt().def_kind(def_id) {
+        Some(DefKind::Fn) => Some(Some(f.clone())),
+        _ => None,
+    }
+}
+
+/// If `e` is `TABLE[index]` for one of `tables`, return the `DefId` of the
+/// table, the path expression naming it, and the index.
+fn table_index<'a>(cx: &RefactorCtxt, tables: &HashMap<DefId, FnTable>, e: &'a Expr)
+                   -> Option<(DefId, &'a Expr, &'a P<Expr>)> {
+    match e.kind {
+        ExprKind::Index(ref table, ref index) =>
+            Some((static_use(cx, tables, table)?, table, index)),
+        _ => None,
+    }
+}
+
+/// A call through one of the tables: `TABLE[index].expect(...)(args)` or
+/// `TABLE[index].unwrap()(args)` for a table of `Option`s, or
+/// `TABLE[index](args)`.
+struct TableCall<'a> {
+    def_id: DefId,
+    /// The path expression naming the table.
+    table: &'a Expr,
+    /// The `TABLE[index]` expression.
+    indexing: &'a P<Expr>,
+    index: &'a P<Expr>,
+    /// The function pointer that is called.
+    callee: &'a Expr,
+    args: &'a [P<Expr>],
+}
+
+fn table_call<'a>(cx: &RefactorCtxt, tables: &HashMap<DefId, FnTable>, e: &'a Expr)
+                  -> Option<TableCall<'a>> {
+    let (callee, args) = match e.kind {
+        ExprKind::Call(ref callee, ref args) => (callee, args),
+        _ => return None,
+    };
+    let callee_expr = strip_parens(callee);
+    let (indexing, optional) = match callee_expr.kind {
+        ExprKind::MethodCall(ref seg, ref margs)
+            if ["expect", "unwrap"].contains(&&*seg.ident.as_str()) => (&margs[0], true),
+        _ => (callee, false),
+    };
+    let (def_id, table, index) = table_index(cx, tables, strip_parens(indexing))?;
+    if tables[&def_id].optional != optional {
+        return None;
+    }
+    Some(TableCall { def_id, table, indexing, index, callee: callee_expr, args })
+}
+
+impl Transform for FnptrTableToMatch {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        // (1) Collect the marked tables.
+
+        let mut tables = HashMap::new();
+        let mut vis = HashMap::new();
+        visit_nodes(krate, |i: &Item| {
+            if !st.marked(i.id, "target") {
+                return;
+            }
+            let table = match i.kind {
+                ItemKind::Static(_, Mutability::Immutable, _) if self.trait_mode => {
+                    warn!("fnptr_table_to_match: `{}` is not a `static mut`, so `mode=trait` \
+                           can't convert it; skipping", i.ident);
+                    return;
+                }
+                ItemKind::Static(ref ty, _, ref init) => FnTable::new(cx, i.ident, ty, init),
+                _ => None,
+            };
+            match table {
+                Some(table) => {
+                    let def_id = cx.node_def_id(i.id);
+                    tables.insert(def_id, table);
+                    vis.insert(def_id, pprust::vis_to_string(&i.vis));
+                }
+                None => warn!("fnptr_table_to_match: `{}` is not an array of function pointers \
+                               whose initializer lists the functions; skipping", i.ident),
+            }
+        });
+
+        // (2) Check how each table is used.  The IDs of the paths to the tables in calls, stores
+        // and checks are recorded, so the other uses can be found.
+
+        let mut handled = HashSet::new();
+        let mut stored: HashMap<DefId, Vec<P<Expr>>> = HashMap::new();
+        let mut rejected = HashSet::new();
+        let span_str = |sp| cx.session().source_map().span_to_string(sp);
+        visit_nodes(krate, |e: &Expr| {
+            if let Some(call) = table_call(cx, &tables, e) {
+                handled.insert(call.table.id);
+                return;
+            }
+            match e.kind {
+                ExprKind::MethodCall(ref seg, ref args) if self.trait_mode &&
+                        ["is_some", "is_none"].contains(&&*seg.ident.as_str()) => {
+                    if let Some((_, table, _)) = table_index(cx, &tables, strip_parens(&args[0])) {
+                        handled.insert(table.id);
+                    }
+                }
+                ExprKind::Assign(ref lhs, _) | ExprKind::AssignOp(_, ref lhs, _) |
+                ExprKind::AddrOf(_, Mutability::Mutable, ref lhs) => {
+                    let def_id = match static_use(cx, &tables, place_root(lhs)) {
+                        Some(x) => x,
+                        None => return,
+                    };
+                    let store = match (self.trait_mode, &e.kind, table_index(cx, &tables, lhs)) {
+                        (true, ExprKind::Assign(_, rhs), Some((_, table, _))) =>
+                            fn_entry(cx, rhs, tables[&def_id].optional).map(|f| (table, f)),
+                        _ => None,
+                    };
+                    match store {
+                        Some((table, f)) => {
+                            handled.insert(table.id);
+                            stored.entry(def_id).or_default().extend(f);
+                        }
+                        None if self.trait_mode => {
+                            if rejected.insert(def_id) {
+                                warn!("fnptr_table_to_match: `{}` is written with something \
+                                       other than a function at {}; skipping",
+                                      tables[&def_id].name, span_str(e.span));
+                            }
+                        }
+                        None => {
+                            if rejected.insert(def_id) {
+                                warn!("fnptr_table_to_match: `{}` is written at {}; skipping \
+                                       (`mode=trait` handles tables written at runtime)",
+                                      tables[&def_id].name, span_str(e.span));
+                            }
+                        }
+                    }
+                }
+                _ => {}
+            }
+        });
+
+        let mut used = HashSet::new();
+        visit_nodes(krate, |e: &Expr| {
+            let def_id = match static_use(cx, &tables, e) {
+                Some(x) if !handled.contains(&e.id) => x,
+                _ => return,
+            };
+            if !self.trait_mode {
+                used.insert(def_id);
+            } else if rejected.insert(def_id) {
+                warn!("fnptr_table_to_match: `{}` is used at {} in a way `mode=trait` can't \
+                       convert; skipping", tables[&def_id].name, span_str(e.span));
+            }
+        });
+
+        tables.retain(|def_id, _| !rejected.contains(def_id));
+
+        // (3) Generate the dispatch functions, or the traits and the new tables.
+
+        FlatMapNodes::visit(krate, |i: P<Item>| {
+            if !st.marked(i.id, "target") {
+                return smallvec![i];
+            }
+            let def_id = cx.node_def_id(i.id);
+            let table = match tables.get(&def_id) {
+                Some(x) => x,
+                None => return smallvec![i],
+            };
+
+            if !self.trait_mode {
+                let mut items = smallvec![];
+                if !self.delete_unused || used.contains(&def_id) {
+                    items.push(i);
+                }
+                items.extend(st.parse_items(cx, &table.dispatch_fn(&vis[&def_id])));
+                return items;
+            }
+
+            let mut fns = table.entries.iter().flatten().cloned().collect::<Vec<_>>();
+            fns.extend(stored.get(&def_id).into_iter().flatten().cloned());
+            let mut items = st.parse_items(cx, &table.trait_items(cx, &vis[&def_id], &fns))
+                .into_iter().collect::<SmallVec<[_; 1]>>();
+            items.push(i.map(|mut i| {
+                if let ItemKind::Static(ref mut ty, _, ref mut init) = i.kind {
+                    let trait_name = camel_case(&table.name.as_str());
+                    let boxed = format!("Box<dyn {}>", trait_name);
+                    let elem = if table.optional { format!("Option<{}>", boxed) } else { boxed };
+                    let table_path = Path::from_ident(table.name);
+                    let entries = table.entries.iter()
+                        .map(|f| table.boxed_entry(cx, f.as_ref(), &table_path))
+                        .collect::<Vec<_>>();
+                    *ty = parse_ty(cx.session(), &format!(
+                        "::once_cell::sync::Lazy<[{}; {}]>",
+                        elem, pprust::expr_to_string(&table.len.value)));
+                    *init = parse_expr(cx.session(), &format!(
+                        "::once_cell::sync::Lazy::new(|| [{}])", entries.join(", ")));
+                }
+                i
+            }));
+            items
+        });
+
+        // (4) Rewrite the calls through the tables, and the stores into them.
+
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            if let ExprKind::Assign(ref lhs, ref mut rhs) = e.kind {
+                if !self.trait_mode {
+                    return;
+                }
+                if let Some((def_id, table, _)) = table_index(cx, &tables, lhs) {
+                    let table_path = expect!([table.kind] ExprKind::Path(None, ref p) => p);
+                    let table = &tables[&def_id];
+                    if let Some(f) = fn_entry(cx, rhs, table.optional) {
+                        *rhs = parse_expr(cx.session(),
+                                          &table.boxed_entry(cx, f.as_ref(), table_path));
+                    }
+                }
+                return;
+            }
+
+            let new_e = {
+                let call = match table_call(cx, &tables, e) {
+                    Some(x) => x,
+                    None => return,
+                };
+                let table = &tables[&call.def_id];
+                if !self.trait_mode {
+                    let mut path = expect!([call.table.kind] ExprKind::Path(None, ref p) => p)
+                        .clone();
+                    *path.segments.last_mut().unwrap() =
+                        PathSegment::from_ident(Ident::from_str(&table.dispatch_name()));
+                    let mut args = vec![call.index.clone()];
+                    args.extend(call.args.iter().cloned());
+                    mk().call_expr(mk().path_expr(path), args)
+                } else {
+                    let mut callee = P(call.callee.clone());
+                    if let ExprKind::MethodCall(_, ref mut margs) = callee.kind {
+                        margs[0] = mk().method_call_expr(call.indexing.clone(), "as_ref",
+                                                         Vec::<P<Expr>>::new());
+                    }
+                    mk().method_call_expr(callee, "call", call.args.to_vec())
+                }
+            };
+            *e = new_e;
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+/// # `parallel_arrays_to_struct` Command
+///
+/// Usage: `parallel_arrays_to_struct STRUCT TABLE`
+///
+/// Marks: `target`
+///
+/// Merge parallel arrays, which hold the parts of each entry at the same index,
+/// into one array of structs.  The arrays marked `target` must be either statics
+/// of one module or fields of one struct, and have the same length expression.
+///
+/// A new struct `STRUCT` gets a field for each array, holding its element.  The
+/// field is named after the array, less a final `s`, so `names` gives `name`.
+/// The struct is added before the first array, which is replaced with `TABLE:
+/// [STRUCT; N]`, and the other arrays are removed.  Then `names[i]` becomes
+/// `TABLE[i].name`, and `names.len()` becomes `TABLE.len()`.
+///
+/// The initializers of the arrays, whether of statics or in struct literals, are
+/// merged element by element.  They must all be lists of elements, like `[1, 2,
+/// 3]`, or all be repeats, like `[0; 3]`.
+///
+/// Nothing is changed if the arrays have different lengths or initializers that
+/// can't be merged, or if any of them is used other than by indexing it or
+/// calling `len()`, such as by taking the address of the whole array.  A warning
+/// reports why.
+///
+/// Example:
+///
+/// ```ignore
+///     static mut names: [*const c_char; 2] = [b"a\0" as *const u8 as *const c_char,
+///                                             b"b\0" as *const u8 as *const c_char];
+///     static mut values: [c_int; 2] = [1, 2];
+///
+///     unsafe fn get(i: usize) -> c_int {
+///         values[i]
+///     }
+/// ```
+///
+/// After running `parallel_arrays_to_struct Entry table`, with both statics
+/// marked:
+///
+/// ```ignore
+///     #[derive(Copy, Clone)]
+///     #[repr(C)]
+///     struct Entry {
+///         name: *const c_char,
+///         value: c_int,
+///     }
+///     static mut table: [Entry; 2] = [
+///         Entry { name: b"a\0" as *const u8 as *const c_char, value: 1 },
+///         Entry { name: b"b\0" as *const u8 as *const c_char, value: 2 },
+///     ];
+///
+///     unsafe fn get(i: usize) -> c_int {
+///         table[i].value
+///     }
+/// ```
+pub struct ParallelArraysToStruct {
+    pub struct_name: String,
+    pub table_name: String,
+}
+
+/// An array marked for `parallel_arrays_to_struct`.
+struct ParallelArray {
+    ident: Ident,
+    /// The `NodeId` of the static, or of the struct the field belongs to.
+    item_id: NodeId,
+    elem_ty: P<Ty>,
+    /// The length expression, as source.
+    len: String,
+    /// The initializer of a static.
+    init: Option<P<Expr>>,
+    vis: Visibility,
+    mutbl: Mutability,
+    /// The name of the field of the new struct holding the elements.
+    field: Ident,
+}
+
+impl ParallelArraysToStruct {
+    /// Merge `inits`, the initializers of the arrays, into one for the array of `STRUCT`s, as
+    /// source.
+    fn merge_inits(&self, arrays: &[ParallelArray], inits: &[&Expr]) -> Option<String> {
+        let entry = |elems: Vec<&P<Expr>>| {
+            let fields = arrays.iter().zip(elems).map(|(a, e)| {
+                format!("{}: {}", a.field, pprust::expr_to_string(e))
+            }).collect::<Vec<_>>();
+            format!("{} {{ {} }}", self.struct_name, fields.join(", "))
+        };
+
+        let lists = inits.iter().map(|e| match e.kind {
+            ExprKind::Array(ref elems) => Some(elems),
+            _ => None,
+        }).collect::<Option<Vec<_>>>();
+        if let Some(lists) = lists {
+            let n = lists[0].len();
+            if lists.iter().any(|elems| elems.len() != n) {
+                return None;
+            }
+            let entries = (0..n)
+                .map(|i| entry(lists.iter().map(|elems| &elems[i]).collect()))
+                .collect::<Vec<_>>();
+            return Some(format!("[{}]", entries.join(", ")));
+        }
+
+        let repeats = inits.iter().map(|e| match e.kind {
+            ExprKind::Repeat(ref elem, ref n) => Some((elem, pprust::expr_to_string(&n.value))),
+            _ => None,
+        }).collect::<Option<Vec<_>>>()?;
+        if repeats.iter().any(|&(_, ref n)| *n != repeats[0].1) {
+            return None;
+        }
+        let elem = entry(repeats.iter().map(|&(elem, _)| elem).collect());
+        Some(format!("[{}; {}]", elem, repeats[0].1))
+    }
+}
+
+/// The name of the field holding the elements of the array `name`: `names` becomes `name`.
+fn entry_field_name(name: &str) -> &str {
+    if name.len() > 1 && name.ends_with('s') && !name.ends_with("ss") {
+        &name[..name.len() - 1]
+    } else {
+        name
+    }
+}
+
+impl Transform for ParallelArraysToStruct {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        // (1) Collect the marked arrays.
+
+        let mut arrays = Vec::new();
+        let mut owners = HashSet::new();
+        let mut is_field = false;
+        visit_nodes(krate, |i: &Item| {
+            let mut add = |ident: Ident, ty: &Ty, init: Option<&P<Expr>>, vis: &Visibility,
+                           mutbl: Mutability| {
+                let (elem_ty, len) = match ty.kind {
+                    TyKind::Array(ref elem, ref len) => (elem, len),
+                    _ => {
+                        warn!("parallel_arrays_to_struct: `{}` is not an array; skipping", ident);
+                        return;
+                    }
+                };
+                arrays.push(ParallelArray {
+                    ident,
+                    item_id: i.id,
+                    elem_ty: elem_ty.clone(),
+                    len: pprust::expr_to_string(&len.value),
+                    init: init.cloned(),
+                    vis: vis.clone(),
+                    mutbl,
+                    field: ident,
+                });
+            };
+            match i.kind {
+                ItemKind::Static(ref ty, mutbl, ref init) if st.marked(i.id, "target") => {
+                    add(i.ident, ty, Some(init), &i.vis, mutbl);
+                    let hir_id = cx.hir_map().node_to_hir_id(i.id);
+                    owners.insert(cx.hir_map().get_module_parent_node(hir_id));
+                }
+                ItemKind::Struct(VariantData::Struct(ref fields, _), _) => {
+                    for f in fields {
+                        if st.marked(f.id, "target") {
+                            add(f.ident.unwrap(), &f.ty, None, &f.vis, Mutability::Mutable);
+                            owners.insert(cx.hir_map().node_to_hir_id(i.id));
+                            is_field = true;
+                        }
+                    }
+                }
+                _ => {}
+            }
+        });
+
+        let report = |msg: String| {
+            warn!("parallel_arrays_to_struct: {}; leaving the arrays alone", msg);
+        };
+        if arrays.len() < 2 {
+            report(format!("found {} marked arrays, but need at least two", arrays.len()));
+            return;
+        }
+        if owners.len() != 1 || arrays.iter().any(|a| a.init.is_some() == is_field) {
+            report("the arrays must all be statics of one module, or fields of one struct".into());
+            return;
+        }
+        if let Some(a) = arrays.iter().find(|a| a.len != arrays[0].len) {
+            report(format!("`{}` has length `{}`, but `{}` has length `{}`",
+                           arrays[0].ident, arrays[0].len, a.ident, a.len));
+            return;
+        }
+        let mut field_names = HashSet::new();
+        for a in &mut arrays {
+            let field = Ident::from_str(entry_field_name(&a.ident.as_str()));
+            if !field.is_reserved() && field_names.insert(field.name) {
+                a.field = field;
+            }
+        }
+        let struct_def_id = cx.node_def_id(arrays[0].item_id);
+
+        // (2) Find the uses of the arrays, and check they're all indexed or measured.
+
+        let array_use = |e: &Expr| -> Option<usize> {
+            match e.kind {
+                ExprKind::Path(..) if !is_field => {
+                    let def_id = cx.try_resolve_expr(e)?;
+                    arrays.iter().position(|a| cx.node_def_id(a.item_id) == def_id)
+                }
+                ExprKind::Field(ref base, ident) if is_field => {
+                    let mut ty = cx.opt_node_type(strip_parens(base).id)?;
+                    while let ty::TyKind::Ref(_, inner, _) = ty.kind {
+                        ty = inner;
+                    }
+                    match ty.kind {
+                        ty::TyKind::Adt(adt, _) if adt.did == struct_def_id => {}
+                        _ => return None,
+                    }
+                    arrays.iter().position(|a| a.ident.name == ident.name)
+                }
+                _ => None,
+            }
+        };
+
+        let mut uses = HashMap::new();
+        let mut handled = HashSet::new();
+        let mut addr_taken = HashSet::new();
+        visit_nodes(krate, |e: &Expr| {
+            if let Some(idx) = array_use(e) {
+                uses.insert(e.id, idx);
+            }
+            match e.kind {
+                ExprKind::Index(ref base, _) => {
+                    handled.insert(base.id);
+                }
+                ExprKind::MethodCall(ref seg, ref args) => {
+                    let name = seg.ident.as_str();
+                    if &*name == "len" {
+                        handled.insert(args[0].id);
+                    } else if &*name == "as_ptr" || &*name == "as_mut_ptr" {
+                        addr_taken.insert(args[0].id);
+                    }
+                }
+                ExprKind::AddrOf(_, _, ref inner) => {
+                    addr_taken.insert(inner.id);
+                }
+                _ => {}
+            }
+        });
+        let mut problems = uses.iter()
+            .filter(|&(id, _)| !handled.contains(id))
+            .map(|(id, &idx)| if addr_taken.contains(id) {
+                format!("the address of the whole of `{}` is taken", arrays[idx].ident)
+            } else {
+                format!("`{}` is used other than by indexing it", arrays[idx].ident)
+            })
+            .collect::<Vec<_>>();
+        problems.sort();
+        problems.dedup();
+        if !problems.is_empty() {
+            report(problems.join(", "));
+            return;
+        }
+
+        // (3) Merge the initializers.
+
+        let table_init = if is_field {
+            None
+        } else {
+            let inits = arrays.iter().map(|a| &**a.init.as_ref().unwrap()).collect::<Vec<_>>();
+            match self.merge_inits(&arrays, &inits) {
+                Some(init) => Some(init),
+                None => {
+                    report("the initializers of the arrays can't be merged".into());
+                    return;
+                }
+            }
+        };
+
+        let mut lit_inits = HashMap::new();
+        let mut bad_lit = false;
+        if is_field {
+            visit_nodes(krate, |e: &Expr| {
+                let fields = match_or!([e.kind] ExprKind::Struct(_, ref fields, _) => fields;
+                                       return);
+                match cx.opt_node_type(e.id).map(|ty| &ty.kind) {
+                    Some(&ty::TyKind::Adt(adt, _)) if adt.did == struct_def_id => {}
+                    _ => return,
+                }
+                let inits = arrays.iter()
+                    .filter_map(|a| fields.iter().find(|f| f.ident.name == a.ident.name))
+                    .map(|f| &*f.expr)
+                    .collect::<Vec<_>>();
+                if inits.is_empty() {
+                    return;
+                }
+                match self.merge_inits(&arrays, &inits) {
+                    Some(init) if inits.len() == arrays.len() => {
+                        lit_inits.insert(e.id, init);
+                    }
+                    _ => bad_lit = true,
+                }
+            });
+        }
+        if bad_lit {
+            report("the arrays have initializers in a struct literal that can't be merged".into());
+            return;
+        }
+
+        // (4) Replace the arrays with the table.
+
+        let vis = pprust::vis_to_string(&arrays[0].vis);
+        let fields = arrays.iter().map(|a| {
+            format!("{}{}: {},", vis, a.field, pprust::ty_to_string(&a.elem_ty))
+        }).collect::<Vec<_>>();
+        let struct_item = st.parse_items(cx, &format!(
+            "#[derive(Copy, Clone)] #[repr(C)] {}struct {} {{ {} }}",
+            vis, self.struct_name, fields.join(" "))).lone();
+        let table_ty = format!("[{}; {}]", self.struct_name, arrays[0].len);
+
+        let removed = arrays.iter().map(|a| a.ident.name).collect::<HashSet<_>>();
+        let mut struct_item = Some(struct_item);
+        FlatMapNodes::visit(krate, |mut i: P<Item>| {
+            if i.id != arrays[0].item_id {
+                if !is_field && arrays.iter().any(|a| a.item_id == i.id) {
+                    return smallvec![];
+                }
+                return smallvec![i];
+            }
+            if !is_field {
+                let mutbl = if arrays.iter().any(|a| a.mutbl == Mutability::Mutable) {
+                    "mut "
+                } else {
+                    ""
+                };
+                let table = st.parse_items(cx, &format!(
+                    "{}static {}{}: {} = {};",
+                    vis, mutbl, self.table_name, table_ty, table_init.as_ref().unwrap())).lone();
+                return smallvec![struct_item.take().unwrap(), table];
+            }
+
+            let table_field = expect!([st.parse_items(cx, &format!(
+                "struct S {{ {}{}: {} }}", vis, self.table_name, table_ty)).lone().kind]
+                ItemKind::Struct(VariantData::Struct(ref fields, _), _) => fields[0].clone());
+            if let ItemKind::Struct(VariantData::Struct(ref mut fields, _), _) = i.kind {
+                let pos = fields.iter()
+                    .position(|f| f.ident.map_or(false, |ident| removed.contains(&ident.name)))
+                    .unwrap();
+                fields.retain(|f| f.ident.map_or(true, |ident| !removed.contains(&ident.name)));
+                fields.insert(pos, table_field);
+            }
+            smallvec![struct_item.take().unwrap(), i]
+        });
+
+        // (5) Rewrite the uses, and the struct literals.
+
+        let table_ident = Ident::from_str(&self.table_name);
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            if uses.contains_key(&e.id) {
+                match e.kind {
+                    ExprKind::Path(_, ref mut path) => {
+                        path.segments.last_mut().unwrap().ident = table_ident;
+                    }
+                    ExprKind::Field(_, ref mut ident) => *ident = table_ident,
+                    _ => unreachable!(),
+                }
+                return;
+            }
+            let field = match e.kind {
+                ExprKind::Index(ref base, _) => uses.get(&base.id).map(|&idx| arrays[idx].field),
+                _ => None,
+            };
+            if let Some(field) = field {
+                *e = mk().field_expr(e.clone(), field);
+                return;
+            }
+            if let Some(init) = lit_inits.get(&e.id) {
+                let table_field = expect!([parse_expr(cx.session(), &format!(
+                    "S {{ {}: {} }}", self.table_name, init)).kind]
+                    ExprKind::Struct(_, ref fields, _) => fields[0].clone());
+                if let ExprKind::Struct(_, ref mut fields, _) = e.kind {
+                    let pos = fields.iter()
+                        .position(|f| removed.contains(&f.ident.name))
+                        .unwrap();
+                    fields.retain(|f| !removed.contains(&f.ident.name));
+                    fields.insert(pos, table_field);
+                }
+            }
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
 
 pub fn register_commands(reg: &mut Registry) {
     use super::mk;
@@ -507,4 +2138,25 @@ pub fn register_commands(reg: &mut Registry) {
     }));
     reg.register("static_to_local_ref", |_args| mk(Localize));
     reg.register("static_to_local", |_args| mk(StaticToLocal));
+    reg.register("static_mut_to_safe", |args| mk(StaticMutToSafe {
+        rwlock: args.iter().any(|arg| arg == "rwlock=1"),
+    }));
+    reg.register("volatile_to_atomic", |args| mk(VolatileToAtomic {
+        keep_escaping: args.iter().any(|arg| arg == "escaping=keep"),
+    }));
+    reg.register("fnptr_table_to_match", |args| mk(FnptrTableToMatch {
+        trait_mode: match args.iter().find(|arg| arg.starts_with("mode=")) {
+            None => false,
+            Some(arg) => match &arg["mode=".len()..] {
+                "match" => false,
+                "trait" => true,
+                mode => panic!("fnptr_table_to_match: unknown mode `{}`", mode),
+            },
+        },
+        delete_unused: args.iter().any(|arg| arg == "delete_unused=1"),
+    }));
+    reg.register("parallel_arrays_to_struct", |args| mk(ParallelArraysToStruct {
+        struct_name: args[0].clone(),
+        table_name: args[1].clone(),
+    }));
 }
@@ -0,0 +1,399 @@
+//! Transform that replaces C `FILE *` handles with `std::fs::File`s.
+
+use std::ascii;
+use std::collections::{HashMap, HashSet};
+use syntax::ast::*;
+use syntax::ptr::P;
+
+use c2rust_ast_builder::IntoSymbol;
+use crate::ast_manip::{MutVisitNodes, visit_nodes};
+use crate::ast_manip::fn_edit::mut_visit_fns;
+use crate::command::{CommandState, Registry};
+use crate::contains_mark::contains_mark;
+use crate::driver::{self, Phase};
+use crate::matcher::{Bindings, MatchCtxt, Subst};
+use crate::reflect;
+use crate::transform::Transform;
+use crate::transform::mem::{Buffer, classify_buffer, is_byte_ty, strip_casts, strip_ptr_conv};
+use crate::transform::null_ptrs::is_null_ptr;
+use crate::transform::strings::StrOperand;
+use crate::RefactorCtxt;
+
+
+/// The `fopen` modes `stdio_to_std` handles, with the `b` flag removed, and the expressions
+/// that open a file the same way.
+const OPEN_MODES: &[(&[u8], &str)] = &[
+    (b"r", "::std::fs::File::open($path)"),
+    (b"w", "::std::fs::File::create($path)"),
+    (b"a", "::std::fs::OpenOptions::new().append(true).create(true).open($path)"),
+    (b"r+", "::std::fs::OpenOptions::new().read(true).write(true).open($path)"),
+    (b"w+", "::std::fs::OpenOptions::new().read(true).write(true).create(true).truncate(true)
+             .open($path)"),
+    (b"a+", "::std::fs::OpenOptions::new().read(true).append(true).create(true).open($path)"),
+];
+
+fn is_one_lit(e: &Expr) -> bool {
+    match e.kind {
+        ExprKind::Lit(ref lit) => match lit.kind {
+            LitKind::Int(1, _) => true,
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// # `stdio_to_std` Command
+///
+/// Usage: `stdio_to_std`
+///
+/// Marks: `target`
+///
+/// Convert `FILE *` locals that are opened with `fopen` into
+/// `Option<std::fs::File>` locals, and rewrite the calls that use them:
+///
+///  * `fopen(path, mode)` becomes `File::open(path).ok()`, `File::create(path).ok()`
+///    or an `OpenOptions` chain, according to `mode`.  `path` is converted from a C
+///    string to an `OsStr` of its bytes, as in
+///    `OsStrExt::from_bytes(CStr::from_ptr(path).to_bytes())`, unless it's a UTF-8
+///    literal, so the rewritten code only builds on Unix.
+///  * A null pointer assigned to the local becomes `None`, and null checks of it
+///    become `f.is_none()` or `f.is_some()`.
+///  * `fread(buf, 1, n, f)` becomes `Read::read` into `&mut buf[..n]`, returning the
+///    number of bytes read, or 0 on error.  `fread(buf, n, 1, f)` becomes
+///    `Read::read_exact`, returning 1 if it succeeded and 0 otherwise.
+///  * `fwrite` becomes `Write::write_all`, returning `n` or 1 if it succeeded and 0
+///    otherwise, since `fwrite` doesn't return until everything is written or an
+///    error occurs.
+///  * `fclose(f);` becomes `drop(f.take());`.
+///
+/// The mode must be a string literal; `b` flags are ignored, and modes other than
+/// `r`, `w`, `a`, `r+`, `w+` and `a+` cause the local to be skipped with a
+/// warning.  `fread` and `fwrite` buffers must be byte arrays or slices passed
+/// through `.as_ptr()` or `.as_mut_ptr()`.  A local that is used in any other
+/// way, such as being passed to another function (including `feof`, `ferror`,
+/// or `fprintf`), or whose `fclose` result is used, is skipped with a warning.
+///
+/// If any node is marked `target`, only locals whose declaration contains a
+/// marked node are converted.
+pub struct StdioToStd;
+
+impl Transform for StdioToStd {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        let target = "target".into_symbol();
+        let marked_only = st.marks().iter().any(|&(_, label)| label == target);
+
+        let mut mcx = MatchCtxt::new(st, cx);
+        let fopen_pat = mcx.parse_expr("fopen($path:Expr, $mode:Expr)");
+        let fread_pat = mcx.parse_expr("fread($buf:Expr, $size:Expr, $n:Expr, $f:Expr)");
+        let fwrite_pat = mcx.parse_expr("fwrite($buf:Expr, $size:Expr, $n:Expr, $f:Expr)");
+        let fclose_pat = mcx.parse_expr("fclose($f:Expr)");
+
+        let open_repls = OPEN_MODES.iter()
+            .map(|&(mode, src)| (mode, mcx.parse_expr(&format!("{}.ok()", src))))
+            .collect::<Vec<_>>();
+        let os_path_repl = mcx.parse_expr("::std::os::unix::ffi::OsStrExt::from_bytes($bytes)");
+        let cstr_bytes_repl = mcx.parse_expr("$path.to_bytes()");
+        let ptr_bytes_repl = mcx.parse_expr("::std::ffi::CStr::from_ptr($path).to_bytes()");
+        let read_repl = mcx.parse_expr(
+            "::std::io::Read::read($f.as_mut().unwrap(), &mut $buf[..$n as usize])
+             .unwrap_or(0) as $t");
+        let read_exact_repl = mcx.parse_expr(
+            "::std::io::Read::read_exact($f.as_mut().unwrap(), &mut $buf[..$n as usize])
+             .is_ok() as $t");
+        let write_repl = mcx.parse_expr(
+            "::std::io::Write::write_all($f.as_mut().unwrap(), &$buf[..$n as usize])
+             .map_or(0, |_| $n as usize) as $t");
+        let write_all_repl = mcx.parse_expr(
+            "::std::io::Write::write_all($f.as_mut().unwrap(), &$buf[..$n as usize])
+             .is_ok() as $t");
+        let close_repl = mcx.parse_expr("drop($f.take())");
+        let is_none_repl = mcx.parse_expr("$f.is_none()");
+        let is_some_repl = mcx.parse_expr("$f.is_some()");
+        let file_ty = driver::parse_ty(cx.session(), "Option<::std::fs::File>");
+
+        // The replacement for an `fopen` call, or `None` if its arguments can't be converted.
+        let open_expr = |e: &Expr| -> Option<P<Expr>> {
+            let m = mcx.clone_match(&*fopen_pat, e).ok()?;
+            let mode = match StrOperand::classify(cx, m.bindings.get::<_, P<Expr>>("$mode")?) {
+                StrOperand::Lit(mode) => mode,
+                _ => return None,
+            };
+            let mode = mode.into_iter().filter(|&b| b != b'b').collect::<Vec<_>>();
+            let repl = open_repls.iter().find(|&&(m, _)| m == &mode[..])?.1.clone();
+
+            let subst_one = |repl: &P<Expr>, name: &str, e: P<Expr>| {
+                let mut bnd = Bindings::new();
+                bnd.add(name, e);
+                repl.clone().subst(st, cx, &bnd)
+            };
+            let path = match StrOperand::classify(cx, m.bindings.get::<_, P<Expr>>("$path")?) {
+                StrOperand::Lit(bs) => match String::from_utf8(bs) {
+                    Ok(s) => driver::parse_expr(cx.session(), &format!("{:?}", s)),
+                    Err(err) => {
+                        let escaped = err.into_bytes().into_iter()
+                            .flat_map(ascii::escape_default)
+                            .map(char::from)
+                            .collect::<String>();
+                        let bytes = driver::parse_expr(cx.session(), &format!("b\"{}\"", escaped));
+                        subst_one(&os_path_repl, "$bytes", bytes)
+                    }
+                },
+                StrOperand::Str(e) => e.clone(),
+                StrOperand::CStr(e) => {
+                    let bytes = subst_one(&cstr_bytes_repl, "$path", e.clone());
+                    subst_one(&os_path_repl, "$bytes", bytes)
+                }
+                StrOperand::Ptr(e) => {
+                    let bytes = subst_one(&ptr_bytes_repl, "$path", e.clone());
+                    subst_one(&os_path_repl, "$bytes", bytes)
+                }
+            };
+            Some(subst_one(&repl, "$path", path))
+        };
+        let is_fopen = |e: &Expr| mcx.clone_match(&*fopen_pat, e).is_ok();
+        let warn_skip = |name: Ident, e: &Expr, why: &str| {
+            warn!("stdio_to_std: skipping `{}`, which {} at {}", name, why,
+                  cx.session().source_map().span_to_string(e.span));
+        };
+
+        // The buffer and length of an `fread` or `fwrite` call that can be converted, and whether
+        // it transfers a single whole item.
+        let rw_parts = |bnd: &Bindings| -> Option<(P<Expr>, P<Expr>, bool)> {
+            let buf = strip_ptr_conv(bnd.get::<_, P<Expr>>("$buf")?);
+            match classify_buffer(cx, buf) {
+                Buffer::Slice(elem) if is_byte_ty(elem) => {}
+                _ => return None,
+            }
+            let size = strip_casts(bnd.get::<_, P<Expr>>("$size")?);
+            let n = strip_casts(bnd.get::<_, P<Expr>>("$n")?);
+            if is_one_lit(size) {
+                Some((buf.clone(), n.clone(), false))
+            } else if is_one_lit(n) {
+                Some((buf.clone(), size.clone(), true))
+            } else {
+                None
+            }
+        };
+
+        mut_visit_fns(krate, |fl| {
+            let block = match fl.block {
+                Some(ref mut block) => block,
+                None => return,
+            };
+
+            // (1) Find the raw pointer locals that are initialized with `fopen` or a null
+            // pointer.
+
+            let mut handles = HashMap::new();
+            let mut opened = HashSet::new();
+            let mut skipped = HashSet::new();
+            visit_nodes(&**block, |l: &Local| {
+                let ident = match_or!([l.pat.kind] PatKind::Ident(_, ident, None) => ident;
+                                      return);
+                let init = match_or!([l.init] Some(ref x) => x; return);
+                if marked_only && !contains_mark(l, target, st) {
+                    return;
+                }
+                if !is_fopen(strip_casts(init)) && !is_null_ptr(init) {
+                    return;
+                }
+                let hir_id = cx.hir_map().node_to_hir_id(l.pat.id);
+                handles.insert(hir_id, ident);
+                if is_fopen(strip_casts(init)) {
+                    if open_expr(strip_casts(init)).is_some() {
+                        opened.insert(hir_id);
+                    } else {
+                        warn_skip(ident, init, "is opened with an unsupported mode");
+                        skipped.insert(hir_id);
+                    }
+                }
+            });
+            if handles.is_empty() {
+                return;
+            }
+            let handle_of = |e: &P<Expr>| {
+                cx.try_resolve_expr_to_hid(strip_casts(e)).filter(|id| handles.contains_key(id))
+            };
+
+            // (2) Check that each handle is only used in ways we can convert.
+
+            let mut stmt_exprs = HashSet::new();
+            visit_nodes(&**block, |s: &Stmt| {
+                if let StmtKind::Semi(ref e) = s.kind {
+                    stmt_exprs.insert(e.id);
+                }
+            });
+
+            let mut allowed = HashSet::new();
+            visit_nodes(&**block, |e: &Expr| {
+                match e.kind {
+                    ExprKind::Assign(ref lhs, ref rhs) => {
+                        let hir_id = match_or!([handle_of(lhs)] Some(x) => x; return);
+                        allowed.insert(lhs.id);
+                        if is_fopen(strip_casts(rhs)) {
+                            if open_expr(strip_casts(rhs)).is_some() {
+                                opened.insert(hir_id);
+                            } else {
+                                warn_skip(handles[&hir_id], e,
+                                          "is opened with an unsupported mode");
+                                skipped.insert(hir_id);
+                            }
+                        } else if !is_null_ptr(rhs) {
+                            warn_skip(handles[&hir_id], e, "is assigned something else");
+                            skipped.insert(hir_id);
+                        }
+                    }
+                    ExprKind::Binary(op, ref lhs, ref rhs)
+                            if op.node == BinOpKind::Eq || op.node == BinOpKind::Ne => {
+                        if handle_of(lhs).is_some() && is_null_ptr(rhs) {
+                            allowed.insert(strip_casts(lhs).id);
+                        } else if handle_of(rhs).is_some() && is_null_ptr(lhs) {
+                            allowed.insert(strip_casts(rhs).id);
+                        }
+                    }
+                    ExprKind::MethodCall(ref seg, ref args)
+                            if &*seg.ident.as_str() == "is_null" && args.len() == 1 => {
+                        if handle_of(&args[0]).is_some() {
+                            allowed.insert(strip_casts(&args[0]).id);
+                        }
+                    }
+                    _ => {}
+                }
+
+                for pat in &[&fread_pat, &fwrite_pat] {
+                    if let Ok(m) = mcx.clone_match(&***pat, e) {
+                        let f = m.bindings.get::<_, P<Expr>>("$f").unwrap();
+                        if handle_of(f).is_some() && rw_parts(&m.bindings).is_some() {
+                            allowed.insert(strip_casts(f).id);
+                        }
+                    }
+                }
+                if let Ok(m) = mcx.clone_match(&*fclose_pat, e) {
+                    let f = m.bindings.get::<_, P<Expr>>("$f").unwrap();
+                    if handle_of(f).is_some() && stmt_exprs.contains(&e.id) {
+                        allowed.insert(strip_casts(f).id);
+                    }
+                }
+            });
+
+            visit_nodes(&**block, |e: &Expr| {
+                if let ExprKind::Path(..) = e.kind {
+                    let hir_id = match_or!([cx.try_resolve_expr_to_hid(e)] Some(x) => x; return);
+                    if handles.contains_key(&hir_id) && !allowed.contains(&e.id) &&
+                       skipped.insert(hir_id) {
+                        warn_skip(handles[&hir_id], e, "is used in a way that can't be converted");
+                    }
+                }
+            });
+
+            let converted = opened.difference(&skipped).cloned().collect::<HashSet<_>>();
+            if converted.is_empty() {
+                return;
+            }
+            let is_converted = |e: &P<Expr>| {
+                cx.try_resolve_expr_to_hid(strip_casts(e))
+                    .map_or(false, |id| converted.contains(&id))
+            };
+            let source = |e: &P<Expr>| {
+                if is_null_ptr(e) {
+                    driver::parse_expr(cx.session(), "None")
+                } else {
+                    open_expr(strip_casts(e)).unwrap()
+                }
+            };
+
+            // (3) Rewrite the uses of the converted handles, and then their declarations.
+
+            MutVisitNodes::visit(block, |e: &mut P<Expr>| {
+                let mut bnd = Bindings::new();
+                let repl = match e.kind {
+                    ExprKind::Assign(ref lhs, ref mut rhs) => {
+                        if is_converted(lhs) {
+                            *rhs = source(rhs);
+                        }
+                        return;
+                    }
+                    ExprKind::Binary(op, ref lhs, ref rhs)
+                            if op.node == BinOpKind::Eq || op.node == BinOpKind::Ne => {
+                        let f = if is_converted(lhs) && is_null_ptr(rhs) {
+                            lhs
+                        } else if is_converted(rhs) && is_null_ptr(lhs) {
+                            rhs
+                        } else {
+                            return;
+                        };
+                        bnd.add("$f", strip_casts(f).clone());
+                        if op.node == BinOpKind::Eq { &is_none_repl } else { &is_some_repl }
+                    }
+                    ExprKind::MethodCall(ref seg, ref args)
+                            if &*seg.ident.as_str() == "is_null" && args.len() == 1 => {
+                        if !is_converted(&args[0]) {
+                            return;
+                        }
+                        bnd.add("$f", strip_casts(&args[0]).clone());
+                        &is_none_repl
+                    }
+                    _ => {
+                        let (m, read) = if let Ok(m) = mcx.clone_match(&*fread_pat, &**e) {
+                            (m, true)
+                        } else if let Ok(m) = mcx.clone_match(&*fwrite_pat, &**e) {
+                            (m, false)
+                        } else if let Ok(m) = mcx.clone_match(&*fclose_pat, &**e) {
+                            let f = m.bindings.get::<_, P<Expr>>("$f").unwrap();
+                            if !is_converted(f) {
+                                return;
+                            }
+                            bnd.add("$f", strip_casts(f).clone());
+                            *e = close_repl.clone().subst(st, cx, &bnd);
+                            return;
+                        } else {
+                            return;
+                        };
+
+                        let f = m.bindings.get::<_, P<Expr>>("$f").unwrap();
+                        if !is_converted(f) {
+                            return;
+                        }
+                        let (buf, n, whole) = rw_parts(&m.bindings).unwrap();
+                        bnd.add("$f", strip_casts(f).clone());
+                        bnd.add("$buf", buf);
+                        bnd.add("$n", n);
+                        bnd.add("$t", reflect::reflect_tcx_ty(cx.ty_ctxt(), cx.node_type(e.id)));
+                        match (read, whole) {
+                            (true, false) => &read_repl,
+                            (true, true) => &read_exact_repl,
+                            (false, false) => &write_repl,
+                            (false, true) => &write_all_repl,
+                        }
+                    }
+                };
+                *e = repl.clone().subst(st, cx, &bnd);
+            });
+
+            MutVisitNodes::visit(block, |l: &mut P<Local>| {
+                if !converted.contains(&cx.hir_map().node_to_hir_id(l.pat.id)) {
+                    return;
+                }
+                let l = &mut **l;
+                if let PatKind::Ident(ref mut mode, _, _) = l.pat.kind {
+                    *mode = BindingMode::ByValue(Mutability::Mutable);
+                }
+                l.ty = Some(file_ty.clone());
+                if let Some(ref mut init) = l.init {
+                    *init = source(init);
+                }
+            });
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
+pub fn register_commands(reg: &mut Registry) {
+    use super::mk;
+
+    reg.register("stdio_to_std", |_args| mk(StdioToStd));
+}
@@ -0,0 +1,489 @@
+//! Transforms that replace C string functions with their Rust equivalents.
+
+use std::ascii;
+
+use rustc::ty::TyKind;
+use syntax::ast::*;
+use syntax::ptr::P;
+
+use c2rust_ast_builder::mk;
+
+use crate::ast_manip::MutVisitNodes;
+use crate::ast_manip::util::unsafe_exprs;
+use crate::command::{CommandState, Registry};
+use crate::driver::{self, Phase};
+use crate::matcher::{Bindings, MatchCtxt, Subst};
+use crate::transform::Transform;
+use crate::transform::enums::lit_value;
+use crate::transform::format::{c_str_to_bytes, c_str_to_str};
+use crate::transform::funcs::declared_fn_decls;
+use crate::transform::mem::{is_zero_lit, strip_casts, strip_ptr_conv};
+use crate::transform::null_ptrs::is_null_ptr;
+use crate::RefactorCtxt;
+
+
+/// A string argument to a C string function.
+pub(super) enum StrOperand<'a> {
+    /// A NUL-terminated string or bytestring literal, with the terminator (and anything after
+    /// it) removed.
+    Lit(Vec<u8>),
+    /// A `&CStr` that was passed through `.as_ptr()`.
+    CStr(&'a P<Expr>),
+    /// A `&str` or `String` that was passed through `.as_ptr()`.
+    Str(&'a P<Expr>),
+    /// Any other `*const c_char`.
+    Ptr(&'a P<Expr>),
+}
+
+impl<'a> StrOperand<'a> {
+    pub(super) fn classify(cx: &RefactorCtxt, e: &'a P<Expr>) -> StrOperand<'a> {
+        let inner = strip_ptr_conv(e);
+        if let ExprKind::Lit(ref lit) = inner.kind {
+            let bytes = match lit.kind {
+                LitKind::ByteStr(ref bs) => Some((**bs).clone()),
+                LitKind::Str(s, _) => Some(s.as_str().as_bytes().to_owned()),
+                _ => None,
+            };
+            if let Some(mut bytes) = bytes {
+                if let Some(pos) = bytes.iter().position(|&b| b == 0) {
+                    bytes.truncate(pos);
+                    return StrOperand::Lit(bytes);
+                }
+            }
+        }
+
+        if !std::ptr::eq(inner, strip_casts(e)) {
+            if let Some(mut ty) = cx.opt_node_type(inner.id) {
+                while let TyKind::Ref(_, inner_ty, _) = ty.kind {
+                    ty = inner_ty;
+                }
+                match ty.kind {
+                    TyKind::Str => return StrOperand::Str(inner),
+                    TyKind::Adt(def, _) => match &*cx.ty_ctxt().item_name(def.did).as_str() {
+                        "CStr" => return StrOperand::CStr(inner),
+                        "String" => return StrOperand::Str(inner),
+                        _ => {}
+                    },
+                    _ => {}
+                }
+            }
+        }
+
+        StrOperand::Ptr(strip_casts(e))
+    }
+
+    fn is_ptr(&self) -> bool {
+        match *self {
+            StrOperand::Ptr(_) => true,
+            _ => false,
+        }
+    }
+}
+
+/// # `strcmp_to_eq` Command
+///
+/// Usage: `strcmp_to_eq`
+///
+/// Rewrite equality tests on C string comparisons into Rust comparisons:
+///
+///  * `strcmp(a, b) == 0` becomes `CStr::from_ptr(a) == CStr::from_ptr(b)`, or
+///    `a == b` when both arguments are `&CStr`s (or both `&str`s) passed
+///    through `.as_ptr()`;
+///  * `strncmp(a, b, n) == 0` compares the first `n` bytes of both strings;
+///  * `strcasecmp(a, b) == 0` uses `eq_ignore_ascii_case` on the bytes of both
+///    strings.
+///
+/// `!= 0` tests become the negated comparison.  A NUL-terminated string or
+/// bytestring literal argument is compared as a bytestring literal, without the
+/// terminator.  Rewritten comparisons that read through raw pointers are
+/// wrapped in `unsafe`, unless they're already in an unsafe context.
+///
+/// Ordering tests like `strcmp(a, b) < 0` are left alone with a warning.
+pub struct StrcmpToEq;
+
+impl Transform for StrcmpToEq {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        let mut mcx = MatchCtxt::new(st, cx);
+        let strcmp_pat = mcx.parse_expr("strcmp($a:Expr, $b:Expr)");
+        let strncmp_pat = mcx.parse_expr("strncmp($a:Expr, $b:Expr, $n:Expr)");
+        let strcasecmp_pat = mcx.parse_expr("strcasecmp($a:Expr, $b:Expr)");
+
+        let cstr_eq_repl = mcx.parse_expr(
+            "::std::ffi::CStr::from_ptr($a) == ::std::ffi::CStr::from_ptr($b)");
+        let eq_repl = mcx.parse_expr("$a == $b");
+        let prefix_eq_repl = mcx.parse_expr(
+            "$a.iter().take($n as usize).eq($b.iter().take($n as usize))");
+        let case_eq_repl = mcx.parse_expr("$a.eq_ignore_ascii_case($b)");
+        let not_repl = mcx.parse_expr("!$e");
+        let unsafe_repl = mcx.parse_expr("unsafe { $e }");
+
+        let ptr_bytes_repl = mcx.parse_expr("::std::ffi::CStr::from_ptr($e).to_bytes()");
+        let cstr_bytes_repl = mcx.parse_expr("$e.to_bytes()");
+        let str_bytes_repl = mcx.parse_expr("$e.as_bytes()");
+
+        let wrap = |repl: &P<Expr>, e: P<Expr>| {
+            let mut bnd = Bindings::new();
+            bnd.add("$e", e);
+            repl.clone().subst(st, cx, &bnd)
+        };
+        let bytes = |op: &StrOperand| match *op {
+            StrOperand::Lit(ref bs) => {
+                let escaped = bs.iter()
+                    .flat_map(|&b| std::ascii::escape_default(b))
+                    .map(|b| b as char)
+                    .collect::<String>();
+                driver::parse_expr(cx.session(), &format!("b\"{}\"", escaped))
+            }
+            StrOperand::CStr(e) => wrap(&cstr_bytes_repl, e.clone()),
+            StrOperand::Str(e) => wrap(&str_bytes_repl, e.clone()),
+            StrOperand::Ptr(e) => wrap(&ptr_bytes_repl, e.clone()),
+        };
+        let in_unsafe = unsafe_exprs(krate);
+
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            let (op, call) = match e.kind {
+                ExprKind::Binary(op, ref lhs, ref rhs) if is_zero_lit(strip_casts(rhs)) =>
+                    (op.node, strip_casts(lhs)),
+                _ => return,
+            };
+
+            let m = mcx.clone_match(&*strcmp_pat, &**call)
+                .or_else(|_| mcx.clone_match(&*strncmp_pat, &**call))
+                .or_else(|_| mcx.clone_match(&*strcasecmp_pat, &**call));
+            let m = match m {
+                Ok(m) => m,
+                Err(_) => return,
+            };
+
+            let negate = match op {
+                BinOpKind::Eq => false,
+                BinOpKind::Ne => true,
+                BinOpKind::Lt | BinOpKind::Le | BinOpKind::Gt | BinOpKind::Ge => {
+                    warn!("strcmp_to_eq: leaving ordering comparison at {} in place",
+                          cx.session().source_map().span_to_string(e.span));
+                    return;
+                }
+                _ => return,
+            };
+
+            let a = StrOperand::classify(cx, m.bindings.get::<_, P<Expr>>("$a").unwrap());
+            let b = StrOperand::classify(cx, m.bindings.get::<_, P<Expr>>("$b").unwrap());
+            let n = m.bindings.get::<_, P<Expr>>("$n").map(|n| strip_casts(n).clone());
+            let is_case = mcx.clone_match(&*strcasecmp_pat, &**call).is_ok();
+
+            let mut bnd = Bindings::new();
+            let mut repl = match (&a, &b, n) {
+                (_, _, Some(n)) => {
+                    bnd.add("$a", bytes(&a));
+                    bnd.add("$b", bytes(&b));
+                    bnd.add("$n", n);
+                    prefix_eq_repl.clone().subst(st, cx, &bnd)
+                }
+                _ if is_case => {
+                    bnd.add("$a", bytes(&a));
+                    bnd.add("$b", bytes(&b));
+                    case_eq_repl.clone().subst(st, cx, &bnd)
+                }
+                (&StrOperand::CStr(x), &StrOperand::CStr(y), None) |
+                (&StrOperand::Str(x), &StrOperand::Str(y), None) |
+                (&StrOperand::Ptr(x), &StrOperand::Ptr(y), None) => {
+                    bnd.add("$a", x.clone());
+                    bnd.add("$b", y.clone());
+                    let repl = if a.is_ptr() { &cstr_eq_repl } else { &eq_repl };
+                    repl.clone().subst(st, cx, &bnd)
+                }
+                _ => {
+                    bnd.add("$a", bytes(&a));
+                    bnd.add("$b", bytes(&b));
+                    eq_repl.clone().subst(st, cx, &bnd)
+                }
+            };
+
+            if negate {
+                match repl.kind {
+                    ExprKind::Binary(ref mut op, _, _) if op.node == BinOpKind::Eq =>
+                        op.node = BinOpKind::Ne,
+                    _ => repl = wrap(&not_repl, repl),
+                }
+            }
+            if (a.is_ptr() || b.is_ptr()) && !in_unsafe.contains(&e.id) {
+                repl = wrap(&unsafe_repl, repl);
+            }
+            *e = repl;
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
+/// # `strlen_to_len` Command
+///
+/// Usage: `strlen_to_len [unsafe_cstr=1]`
+///
+/// Rewrite `strlen(s)` calls whose argument has a known length:
+///
+///  * a `&CStr` passed through `.as_ptr()` becomes `s.to_bytes().len()`;
+///  * a `&str` or `String` passed through `.as_ptr()` becomes `s.len()`;
+///  * a NUL-terminated string or bytestring literal becomes its length, not
+///    counting the terminator.
+///
+/// With `unsafe_cstr=1`, any other pointer argument `s` also becomes
+/// `CStr::from_ptr(s).to_bytes().len()`, wrapped in `unsafe` unless it's
+/// already in an unsafe context.
+///
+/// The result is cast back to the return type `strlen` is declared with, such
+/// as `libc::c_ulong`, or to `libc::size_t` if its declaration isn't part of
+/// the crate, so surrounding arithmetic keeps typechecking on every target.
+pub struct StrlenToLen {
+    pub unsafe_cstr: bool,
+}
+
+impl Transform for StrlenToLen {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        let mut mcx = MatchCtxt::new(st, cx);
+        let strlen_pat = mcx.parse_expr("strlen($s:Expr)");
+        let cstr_len_repl = mcx.parse_expr("$s.to_bytes().len() as $t");
+        let str_len_repl = mcx.parse_expr("$s.len() as $t");
+        let ptr_len_repl = mcx.parse_expr("::std::ffi::CStr::from_ptr($s).to_bytes().len() as $t");
+        let unsafe_len_repl = mcx.parse_expr(
+            "unsafe { ::std::ffi::CStr::from_ptr($s).to_bytes().len() }");
+        let lit_len_repl = mcx.parse_expr("$s as $t");
+        let decls = declared_fn_decls(cx, krate);
+        let in_unsafe = unsafe_exprs(krate);
+
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            let m = match mcx.clone_match(&*strlen_pat, &**e) {
+                Ok(m) => m,
+                Err(_) => return,
+            };
+            let ty = match cx.opt_callee(e).and_then(|def_id| decls.get(&def_id)) {
+                Some(decl) => match decl.output {
+                    FunctionRetTy::Ty(ref ty) => ty.clone(),
+                    FunctionRetTy::Default(_) => return,
+                },
+                None => driver::parse_ty(cx.session(), "libc::size_t"),
+            };
+
+            let arg = StrOperand::classify(cx, m.bindings.get::<_, P<Expr>>("$s").unwrap());
+            let mut bnd = Bindings::new();
+            let repl = match arg {
+                StrOperand::Lit(ref bs) => {
+                    bnd.add("$s", driver::parse_expr(cx.session(), &bs.len().to_string()));
+                    &lit_len_repl
+                }
+                StrOperand::CStr(s) => {
+                    bnd.add("$s", s.clone());
+                    &cstr_len_repl
+                }
+                StrOperand::Str(s) => {
+                    bnd.add("$s", s.clone());
+                    &str_len_repl
+                }
+                StrOperand::Ptr(s) if self.unsafe_cstr && !in_unsafe.contains(&e.id) => {
+                    // Parenthesized, since `unsafe { .. } as T` doesn't parse as a statement.
+                    bnd.add("$s", s.clone());
+                    let len = unsafe_len_repl.clone().subst(st, cx, &bnd);
+                    *e = mk().cast_expr(mk().paren_expr(len), ty);
+                    return;
+                }
+                StrOperand::Ptr(s) if self.unsafe_cstr => {
+                    bnd.add("$s", s.clone());
+                    &ptr_len_repl
+                }
+                StrOperand::Ptr(_) => return,
+            };
+            bnd.add("$t", ty);
+            *e = repl.clone().subst(st, cx, &bnd);
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
+/// # `strtox_to_parse` Command
+///
+/// Usage: `strtox_to_parse`
+///
+/// Rewrite C number-parsing calls into `str::parse`:
+///
+///  * `atoi(s)` becomes `s.trim_start().parse::<T>().unwrap_or(0)`;
+///  * `strtol(s, null, base)` becomes `strtol_radix(s, base) as T` when `base` is a literal 0,
+///    8, 10 or 16, using a helper at the crate root that is generated unless the crate
+///    already has one.  Like `strtol`, it reads the bytes of `s` rather than requiring UTF-8,
+///    takes an optional `0x` prefix with base 16, picks the base from a `0x` or `0` prefix with
+///    base 0, parses as many digits as it can, and clamps a value that doesn't fit, setting
+///    `errno` to `ERANGE`;
+///  * `strtod(s, null)` becomes `s.trim_start().parse::<T>().unwrap_or(0.0)`.
+///
+/// Here `T` is the return type in the C function's declaration.  A `&CStr` or `&str` passed
+/// through `.as_ptr()`, or a string literal, is used directly, and any other pointer is read
+/// with `CStr::from_ptr(s)`.
+///
+/// Note that the `atoi` and `strtod` rewrites fall back to 0 when the whole string isn't a
+/// number, while the C functions parse as much of it as they can, and panic on a C string that
+/// isn't UTF-8.  Calls that pass an `endptr`, which depends on the parse position, and `strtol`
+/// calls with a non-literal base, are left alone with a warning.
+pub struct StrtoxToParse;
+
+impl Transform for StrtoxToParse {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        let mut mcx = MatchCtxt::new(st, cx);
+        let atoi_pat = mcx.parse_expr("atoi($s:Expr)");
+        let strtol_pat = mcx.parse_expr("strtol($s:Expr, $end:Expr, $base:Expr)");
+        let strtod_pat = mcx.parse_expr("strtod($s:Expr, $end:Expr)");
+        let cstr_repl = mcx.parse_expr("$s.to_str().unwrap()");
+        let cstr_bytes_repl = mcx.parse_expr("$s.to_bytes()");
+        let str_bytes_repl = mcx.parse_expr("$s.as_bytes()");
+        let parse_int_repl = mcx.parse_expr("$s.trim_start().parse::<$t>().unwrap_or(0)");
+        let parse_float_repl = mcx.parse_expr("$s.trim_start().parse::<$t>().unwrap_or(0.0)");
+        let radix_repl = mcx.parse_expr("crate::strtol_radix($s, $base) as $t");
+        let mut need_radix = false;
+        let decls = declared_fn_decls(cx, krate);
+
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            let (m, repl) = if let Ok(m) = mcx.clone_match(&*atoi_pat, &**e) {
+                (m, &parse_int_repl)
+            } else if let Ok(m) = mcx.clone_match(&*strtol_pat, &**e) {
+                (m, &radix_repl)
+            } else if let Ok(m) = mcx.clone_match(&*strtod_pat, &**e) {
+                (m, &parse_float_repl)
+            } else {
+                return;
+            };
+            let warn_skip = |why: &str| {
+                warn!("strtox_to_parse: skipping call that {} at {}", why,
+                      cx.session().source_map().span_to_string(e.span));
+            };
+            let ty = match cx.opt_callee(e).and_then(|def_id| decls.get(&def_id)) {
+                Some(decl) => match decl.output {
+                    FunctionRetTy::Ty(ref ty) => ty.clone(),
+                    FunctionRetTy::Default(_) => return,
+                },
+                None => return warn_skip("calls a function without a declaration"),
+            };
+            if let Some(end) = m.bindings.get::<_, P<Expr>>("$end") {
+                if !is_null_ptr(end) {
+                    return warn_skip("uses its end pointer");
+                }
+            }
+
+            let mut bnd = Bindings::new();
+            // `strtol` reads the bytes of its operand, so it doesn't need the string to be UTF-8.
+            let bytes = m.bindings.get::<_, P<Expr>>("$base").is_some();
+            if let Some(base) = m.bindings.get::<_, P<Expr>>("$base") {
+                match lit_value(base) {
+                    Some(radix @ 0) | Some(radix @ 8) | Some(radix @ 10) | Some(radix @ 16) => {
+                        need_radix = true;
+                        bnd.add("$base", driver::parse_expr(cx.session(), &radix.to_string()));
+                    }
+                    _ => return warn_skip("has an unsupported base"),
+                }
+            }
+
+            let subst_s = |repl: &P<Expr>, s: &P<Expr>| {
+                let mut s_bnd = Bindings::new();
+                s_bnd.add("$s", s.clone());
+                repl.clone().subst(st, cx, &s_bnd)
+            };
+            let s = m.bindings.get::<_, P<Expr>>("$s").unwrap();
+            let s = match (StrOperand::classify(cx, s), bytes) {
+                (StrOperand::Lit(bs), true) => {
+                    let escaped = bs.iter().flat_map(|&b| ascii::escape_default(b))
+                        .map(char::from).collect::<String>();
+                    driver::parse_expr(cx.session(), &format!("b\"{}\"", escaped))
+                }
+                (StrOperand::Lit(bs), false) => match String::from_utf8(bs) {
+                    Ok(s) => driver::parse_expr(cx.session(), &format!("{:?}", s)),
+                    Err(_) => return warn_skip("parses a literal that isn't UTF-8"),
+                },
+                (StrOperand::CStr(s), true) => subst_s(&cstr_bytes_repl, s),
+                (StrOperand::CStr(s), false) => subst_s(&cstr_repl, s),
+                (StrOperand::Str(s), true) => subst_s(&str_bytes_repl, s),
+                (StrOperand::Str(s), false) => s.clone(),
+                (StrOperand::Ptr(s), true) => c_str_to_bytes(s.clone()),
+                (StrOperand::Ptr(s), false) => c_str_to_str(s.clone()),
+            };
+            bnd.add("$s", s);
+            bnd.add("$t", ty);
+            *e = repl.clone().subst(st, cx, &bnd);
+        });
+
+        let has_radix = krate.module.items.iter().any(|i| {
+            &*i.ident.as_str() == "strtol_radix"
+        });
+        if need_radix && !has_radix {
+            krate.module.items.extend(st.parse_items(cx, r#"
+                /// Parse the C `long` at the start of `s` the way `strtol` does.  With a base of
+                /// 0, the base comes from a `0x` or `0` prefix, and with a base of 16, a `0x`
+                /// prefix is skipped.  Parsing stops at the first byte that isn't a digit, and a
+                /// value that doesn't fit is clamped and sets `errno` to `ERANGE`.
+                fn strtol_radix(s: &[u8], base: u32) -> ::std::os::raw::c_long {
+                    use ::std::os::raw::{c_int, c_long};
+                    extern "C" {
+                        #[cfg_attr(any(target_os = "macos", target_os = "ios",
+                                       target_os = "freebsd"),
+                                   link_name = "__error")]
+                        #[cfg_attr(not(any(target_os = "macos", target_os = "ios",
+                                           target_os = "freebsd")),
+                                   link_name = "__errno_location")]
+                        fn errno_location() -> *mut c_int;
+                    }
+                    const ERANGE: c_int = 34;
+
+                    let start = s.iter().position(|c| !c.is_ascii_whitespace()).unwrap_or(s.len());
+                    let s = &s[start..];
+                    let (neg, s) = match s.first() {
+                        Some(b'-') => (true, &s[1..]),
+                        Some(b'+') => (false, &s[1..]),
+                        _ => (false, s),
+                    };
+                    let is_hex = (s.starts_with(b"0x") || s.starts_with(b"0X")) &&
+                        s.get(2).map_or(false, |&c| (c as char).is_digit(16));
+                    let (radix, digits) = match base {
+                        0 | 16 if is_hex => (16, &s[2..]),
+                        0 if s.starts_with(b"0") => (8, s),
+                        0 => (10, s),
+                        _ => (base, s),
+                    };
+                    let mut magnitude: u128 = 0;
+                    for &c in digits {
+                        let d = match (c as char).to_digit(radix) {
+                            Some(d) => d,
+                            None => break,
+                        };
+                        magnitude = magnitude.saturating_mul(radix as u128)
+                            .saturating_add(d as u128);
+                    }
+                    let limit = c_long::max_value() as u128 + neg as u128;
+                    if magnitude > limit {
+                        unsafe { *errno_location() = ERANGE; }
+                        return if neg { c_long::min_value() } else { c_long::max_value() };
+                    }
+                    let x = magnitude as c_long;
+                    if neg { x.wrapping_neg() } else { x }
+                }
+            "#));
+        }
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
+pub fn register_commands(reg: &mut Registry) {
+    use super::mk;
+
+    reg.register("strcmp_to_eq", |_args| mk(StrcmpToEq));
+    reg.register("strlen_to_len", |args| mk(StrlenToLen {
+        unsafe_cstr: args.iter().any(|arg| arg == "unsafe_cstr=1"),
+    }));
+    reg.register("strtox_to_parse", |_args| mk(StrtoxToParse));
+}
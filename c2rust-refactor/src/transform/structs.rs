@@ -1,16 +1,25 @@
-use rustc::ty;
+use std::collections::{HashMap, HashSet};
+use std::mem;
+use rustc::hir::def_id::DefId;
+use rustc::ty::{self, ParamEnv};
 use syntax::ast::*;
+use syntax::print::pprust;
 use syntax::ptr::P;
+use syntax::source_map::DUMMY_SP;
+use syntax::symbol::{sym, Symbol};
 
 use smallvec::smallvec;
 
-use crate::ast_manip::{fold_blocks, FlatMapNodes, AstEquiv};
+use crate::ast_manip::{fold_blocks, visit_nodes, FlatMapNodes, MutVisitNodes, AstEquiv};
 use crate::command::{CommandState, Registry};
-use crate::driver::{Phase, parse_expr};
+use crate::driver::{Phase, parse_expr, parse_items, parse_pat};
 use crate::matcher::{mut_visit_match, Subst};
 use crate::path_edit::fold_resolved_paths;
 use crate::transform::Transform;
+use crate::transform::canonicalize_refs::strip_parens;
+use crate::transform::enums::is_simple_place;
 use c2rust_ast_builder::{mk, IntoSymbol};
+use crate::util::Lone;
 use crate::RefactorCtxt;
 
 
@@ -209,10 +218,831 @@ fn is_struct(i: &Item) -> bool {
 }
 
 
+/// # `zeroed_to_default` Command
+///
+/// Usage: `zeroed_to_default`
+///
+/// Replace zero-initialized structs, `::std::mem::zeroed()` and
+/// `::std::mem::MaybeUninit::zeroed().assume_init()`, with `S::default()`, for
+/// structs `S` defined in the crate.  An `unsafe` block that only contained the
+/// zeroed value is removed.
+///
+/// Each struct without a `Default` impl gets one.  When every field is a
+/// number, `bool`, `char`, `Option`, `Vec`, `String`, short array, or struct
+/// with a `Default` impl, that's a `#[derive(Default)]`.  Otherwise it's an
+/// `impl Default` that fills in the fields with zero values, such as
+/// `::std::ptr::null_mut()` for raw pointers and `[0; 64]` for long arrays.
+///
+/// Structs with a field of a type that has no zero value, such as a function
+/// pointer outside an `Option`, or a reference, are left alone with a warning,
+/// along with generic structs and unions.
+pub struct ZeroedToDefault;
+
+/// If `e` is a call of `zeroed` with no arguments, return the path of the function.
+fn zeroed_fn(e: &Expr) -> Option<&Path> {
+    let (func, args) = match_or!([e.kind] ExprKind::Call(ref f, ref a) => (f, a); return None);
+    let path = match_or!([func.kind] ExprKind::Path(None, ref p) => p; return None);
+    if args.is_empty() && &*path.segments.last()?.ident.as_str() == "zeroed" {
+        Some(path)
+    } else {
+        None
+    }
+}
+
+/// Check if `e` is `mem::zeroed()` or `MaybeUninit::zeroed().assume_init()`.
+pub(super) fn is_zeroed(e: &Expr) -> bool {
+    let parent_name = |path: &Path| {
+        let n = path.segments.len();
+        if n >= 2 { Some(path.segments[n - 2].ident.as_str()) } else { None }
+    };
+    match e.kind {
+        ExprKind::MethodCall(ref seg, ref args) if &*seg.ident.as_str() == "assume_init" => {
+            zeroed_fn(&args[0]).and_then(parent_name)
+                .map_or(false, |name| &*name == "MaybeUninit")
+        }
+        _ => zeroed_fn(e).map_or(false, |path| {
+            parent_name(path).map_or(true, |name| &*name == "mem")
+        }),
+    }
+}
+
+/// How a struct gets its `Default` impl.
+#[derive(Clone)]
+enum DefaultImpl {
+    Existing,
+    Derive,
+    /// An `impl Default`, with the value of each field.
+    Manual(Vec<(String, String)>),
+}
+
+struct DefaultPlanner<'a, 'tcx> {
+    cx: &'a RefactorCtxt<'a, 'tcx>,
+    /// The structs that already implement `Default`.
+    existing: HashSet<DefId>,
+    /// The `Default` impl of each struct, or why it can't have one.
+    impls: HashMap<DefId, Result<DefaultImpl, String>>,
+}
+
+impl<'a, 'tcx> DefaultPlanner<'a, 'tcx> {
+    fn struct_impl(&mut self, did: DefId) -> Result<DefaultImpl, String> {
+        if let Some(imp) = self.impls.get(&did) {
+            return imp.clone();
+        }
+        self.impls.insert(did, Err("contains itself".to_owned()));
+        let imp = self.compute_impl(did);
+        self.impls.insert(did, imp.clone());
+        imp
+    }
+
+    fn compute_impl(&mut self, did: DefId) -> Result<DefaultImpl, String> {
+        if self.existing.contains(&did) {
+            return Ok(DefaultImpl::Existing);
+        }
+        let tcx = self.cx.ty_ctxt();
+        let adt = tcx.adt_def(did);
+        if adt.is_union() {
+            return Err("is a union".to_owned());
+        }
+        if !tcx.generics_of(did).params.is_empty() {
+            return Err("is generic".to_owned());
+        }
+
+        let mut derive = true;
+        let mut values = Vec::new();
+        for f in &adt.non_enum_variant().fields {
+            let ty = tcx.type_of(f.did);
+            let (value, derivable) = self.zero_value(ty).ok_or_else(|| {
+                format!("has a field `{}` of type `{}`, which has no zero value", f.ident, ty)
+            })?;
+            derive &= derivable;
+            values.push((f.ident.to_string(), value));
+        }
+        Ok(if derive { DefaultImpl::Derive } else { DefaultImpl::Manual(values) })
+    }
+
+    /// The zero value of `ty`, and whether `Default::default()` gives the same value.
+    fn zero_value(&mut self, ty: ty::Ty<'tcx>) -> Option<(String, bool)> {
+        let tcx = self.cx.ty_ctxt();
+        Some(match ty.kind {
+            ty::TyKind::Bool => ("false".to_owned(), true),
+            ty::TyKind::Char => ("'\\0'".to_owned(), true),
+            ty::TyKind::Int(_) | ty::TyKind::Uint(_) => ("0".to_owned(), true),
+            ty::TyKind::Float(_) => ("0.0".to_owned(), true),
+            ty::TyKind::RawPtr(ty::TypeAndMut { mutbl, .. }) => {
+                let value = if mutbl == Mutability::Mutable { "null_mut" } else { "null" };
+                (format!("::std::ptr::{}()", value), false)
+            }
+            ty::TyKind::Array(elem, len) => {
+                let len = len.try_eval_usize(tcx, ParamEnv::empty())?;
+                let (value, derivable) = self.zero_value(elem)?;
+                // `Default` is only implemented for arrays of up to 32 elements.
+                let derivable = derivable && len <= 32;
+                if elem.is_copy_modulo_regions(tcx, ParamEnv::empty(), DUMMY_SP) {
+                    (format!("[{}; {}]", value, len), derivable)
+                } else if derivable {
+                    ("Default::default()".to_owned(), true)
+                } else {
+                    return None;
+                }
+            }
+            ty::TyKind::Adt(adt, _) if adt.did.is_local() && adt.is_struct() => {
+                self.struct_impl(adt.did).ok()?;
+                ("Default::default()".to_owned(), true)
+            }
+            ty::TyKind::Adt(adt, _) => match &*tcx.item_name(adt.did).as_str() {
+                "Option" => ("None".to_owned(), true),
+                "Vec" | "String" => ("Default::default()".to_owned(), true),
+                _ => return None,
+            },
+            _ => return None,
+        })
+    }
+}
+
+impl Transform for ZeroedToDefault {
+    fn transform(&self, krate: &mut Crate, _st: &CommandState, cx: &RefactorCtxt) {
+        let tcx = cx.ty_ctxt();
+        let hir_map = cx.hir_map();
+        let default_sym = Symbol::intern("Default");
+
+        // (1) Find the structs that already implement `Default`.
+
+        let mut existing = HashSet::new();
+        visit_nodes(krate, |i: &Item| match i.kind {
+            ItemKind::Struct(..) => {
+                let derived = i.attrs.iter().filter(|attr| attr.check_name(sym::derive))
+                    .flat_map(|attr| attr.meta_item_list().unwrap_or_default())
+                    .any(|item| item.check_name(default_sym));
+                if derived {
+                    existing.insert(cx.node_def_id(i.id));
+                }
+            }
+            ItemKind::Impl(_, _, _, _, Some(ref trait_ref), ref self_ty, _) => {
+                let is_default = trait_ref.path.segments.last()
+                    .map_or(false, |seg| seg.ident.name == default_sym);
+                if let (true, Some(did)) = (is_default, cx.try_resolve_ty(self_ty)) {
+                    existing.insert(did);
+                }
+            }
+            _ => {}
+        });
+
+        // (2) Find the zeroed structs, along with the `unsafe` blocks around them, and work out
+        // the `Default` impls they need.
+
+        let zeroed_struct = |e: &Expr| -> Option<DefId> {
+            if !is_zeroed(e) {
+                return None;
+            }
+            match cx.opt_node_type(e.id)?.kind {
+                ty::TyKind::Adt(adt, _) if adt.did.is_local() && adt.is_struct() => Some(adt.did),
+                _ => None,
+            }
+        };
+        let mut planner = DefaultPlanner { cx, existing, impls: HashMap::new() };
+        let mut zeroed = HashMap::new();
+        let mut skipped = HashMap::new();
+        visit_nodes(krate, |e: &Expr| {
+            let inner = match e.kind {
+                ExprKind::Block(ref b, None) if b.stmts.len() == 1 => {
+                    match (b.rules, &b.stmts[0].kind) {
+                        (BlockCheckMode::Unsafe(_), StmtKind::Expr(inner)) => &**inner,
+                        _ => return,
+                    }
+                }
+                _ => e,
+            };
+            let did = match_or!([zeroed_struct(inner)] Some(x) => x; return);
+            match planner.struct_impl(did) {
+                Ok(_) => {
+                    zeroed.insert(e.id, did);
+                }
+                Err(msg) => {
+                    skipped.insert(did, msg);
+                }
+            }
+        });
+        for (did, msg) in skipped {
+            warn!("zeroed_to_default: `{}` {}; skipping", tcx.def_path_str(did), msg);
+        }
+
+        // (3) Replace the zeroed values, and add the missing impls.
+
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            let did = match_or!([zeroed.get(&e.id)] Some(&x) => x; return);
+            let module = hir_map.get_module_parent_node(hir_map.node_to_hir_id(e.id));
+            let def_hir_id = hir_map.as_local_hir_id(did).unwrap();
+            let def_module = hir_map.get_module_parent_node(def_hir_id);
+            let path = if module == def_module {
+                tcx.item_name(did).to_string()
+            } else {
+                pprust::path_to_string(&cx.def_path(did))
+            };
+            *e = parse_expr(cx.session(), &format!("{}::default()", path));
+        });
+
+        let derive_attr = parse_items(cx.session(), "#[derive(Default)] struct S;")[0]
+            .attrs[0].clone();
+        FlatMapNodes::visit(krate, |i: P<Item>| {
+            let imp = match i.kind {
+                ItemKind::Struct(..) => planner.impls.get(&cx.node_def_id(i.id)),
+                _ => None,
+            };
+            match imp {
+                Some(Ok(DefaultImpl::Derive)) => smallvec![i.map(|mut i| {
+                    i.attrs.push(derive_attr.clone());
+                    i
+                })],
+                Some(Ok(DefaultImpl::Manual(values))) => {
+                    let fields = values.iter()
+                        .map(|(field, value)| format!("            {}: {},\n", field, value))
+                        .collect::<String>();
+                    let src = format!("impl Default for {0} {{\n    fn default() -> {0} {{\n        \
+                                       {0} {{\n{1}        }}\n    }}\n}}\n", i.ident, fields);
+                    let mut items = smallvec![i];
+                    items.extend(parse_items(cx.session(), &src));
+                    items
+                }
+                _ => smallvec![i],
+            }
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
+/// # `derive_structs` Command
+///
+/// Usage: `derive_structs [traits=Debug,Clone,Copy,PartialEq] [only=marked] [ptr_eq=1]`
+///
+/// Marks: `target` (with `only=marked`)
+///
+/// Add a `#[derive]` of the listed traits to each struct of the crate, or with `only=marked`,
+/// to the marked structs.  The traits default to `Debug`, `Clone`, `Copy` and `PartialEq`, and
+/// may also include `Eq` and `Hash`.
+///
+/// A struct only derives the traits that all of its fields implement.  Numbers, `bool` and
+/// `char` implement all of them, except that floats don't implement `Eq` and `Hash`.  Arrays,
+/// tuples and `Option`s implement what their elements do, but arrays of more than 32 elements
+/// only implement `Clone` and `Copy`.  Raw pointers and function pointers implement `Debug`,
+/// `Clone` and `Copy`, and with `ptr_eq=1`, also `PartialEq`, `Eq` and `Hash`, which compare
+/// the addresses.  The structs of the crate implement what they derive or have impls of,
+/// including the derives added by this command.  Other types only implement `Clone` and
+/// `Copy`, if they are `Copy`.
+///
+/// The traits a struct already derives or has an impl of are left alone.  Each struct notes
+/// the traits it can't derive, along with the field that blocks them.  Generic structs are
+/// skipped.
+pub struct DeriveStructs {
+    pub traits: Vec<Symbol>,
+    pub only_marked: bool,
+    pub ptr_eq: bool,
+}
+
+const DERIVE_TRAITS: &[&str] = &["Debug", "Clone", "Copy", "PartialEq", "Eq", "Hash"];
+
+struct DerivePlanner<'a, 'tcx> {
+    cx: &'a RefactorCtxt<'a, 'tcx>,
+    /// The traits to derive.
+    traits: &'a [Symbol],
+    ptr_eq: bool,
+    /// The structs that get the new derives.
+    targets: HashSet<DefId>,
+    /// The traits each struct already derives or has an impl of.
+    existing: HashMap<DefId, HashSet<Symbol>>,
+    /// Whether each struct implements each trait once the derives are added, or why not.
+    impls: HashMap<(DefId, Symbol), Result<(), String>>,
+}
+
+impl<'a, 'tcx> DerivePlanner<'a, 'tcx> {
+    fn struct_impl(&mut self, did: DefId, trait_: Symbol) -> Result<(), String> {
+        if let Some(imp) = self.impls.get(&(did, trait_)) {
+            return imp.clone();
+        }
+        self.impls.insert((did, trait_), Err("it contains itself".to_owned()));
+        let imp = self.compute_impl(did, trait_);
+        self.impls.insert((did, trait_), imp.clone());
+        imp
+    }
+
+    fn compute_impl(&mut self, did: DefId, trait_: Symbol) -> Result<(), String> {
+        if self.existing.get(&did).map_or(false, |traits| traits.contains(&trait_)) {
+            return Ok(());
+        }
+        if !self.targets.contains(&did) {
+            return Err("it isn't a target".to_owned());
+        }
+        if !self.traits.contains(&trait_) {
+            return Err("it isn't derived".to_owned());
+        }
+        let supertrait = match &*trait_.as_str() {
+            "Copy" => Some("Clone"),
+            "Eq" => Some("PartialEq"),
+            _ => None,
+        };
+        if let Some(supertrait) = supertrait {
+            self.struct_impl(did, Symbol::intern(supertrait))
+                .map_err(|_| format!("it needs `{}`", supertrait))?;
+        }
+
+        let tcx = self.cx.ty_ctxt();
+        for f in &tcx.adt_def(did).non_enum_variant().fields {
+            let ty = tcx.type_of(f.did);
+            if !self.ty_impl(ty, trait_) {
+                return Err(format!("its field `{}` of type `{}` doesn't implement it",
+                                   f.ident, ty));
+            }
+        }
+        Ok(())
+    }
+
+    /// Check whether `ty` implements `trait_`, as far as the derives can tell.
+    fn ty_impl(&mut self, ty: ty::Ty<'tcx>, trait_: Symbol) -> bool {
+        let tcx = self.cx.ty_ctxt();
+        let trait_name = trait_.as_str();
+        let is_copy_or_clone = &*trait_name == "Copy" || &*trait_name == "Clone";
+        match ty.kind {
+            ty::TyKind::Bool | ty::TyKind::Char | ty::TyKind::Int(_) | ty::TyKind::Uint(_) => true,
+            ty::TyKind::Float(_) => &*trait_name != "Eq" && &*trait_name != "Hash",
+            ty::TyKind::RawPtr(_) | ty::TyKind::FnPtr(_) => {
+                is_copy_or_clone || &*trait_name == "Debug" || self.ptr_eq
+            }
+            ty::TyKind::Array(elem, len) => {
+                // The traits other than `Copy` and `Clone` are only implemented for arrays of up
+                // to 32 elements.
+                let short = len.try_eval_usize(tcx, ParamEnv::empty()).map_or(false, |n| n <= 32);
+                (is_copy_or_clone || short) && self.ty_impl(elem, trait_)
+            }
+            ty::TyKind::Tuple(_) => ty.tuple_fields().all(|ty| self.ty_impl(ty, trait_)),
+            ty::TyKind::Adt(adt, _) if adt.did.is_local() && adt.is_struct() => {
+                self.struct_impl(adt.did, trait_).is_ok()
+            }
+            ty::TyKind::Adt(adt, substs) if &*tcx.item_name(adt.did).as_str() == "Option" => {
+                substs.types().all(|ty| self.ty_impl(ty, trait_))
+            }
+            _ => is_copy_or_clone && ty.is_copy_modulo_regions(tcx, ParamEnv::empty(), DUMMY_SP),
+        }
+    }
+}
+
+impl Transform for DeriveStructs {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        let tcx = cx.ty_ctxt();
+
+        // (1) Find the structs to derive the traits for, and the traits the structs already
+        // derive or have impls of.
+
+        let mut targets = HashSet::new();
+        let mut derived: HashMap<DefId, HashSet<Symbol>> = HashMap::new();
+        let mut manual: HashMap<DefId, HashSet<Symbol>> = HashMap::new();
+        visit_nodes(krate, |i: &Item| match i.kind {
+            ItemKind::Struct(_, ref generics) => {
+                let did = cx.node_def_id(i.id);
+                if self.only_marked && !st.marked(i.id, "target") {
+                    return;
+                }
+                if !generics.params.is_empty() {
+                    warn!("derive_structs: `{}` is generic; skipping", tcx.def_path_str(did));
+                    return;
+                }
+                targets.insert(did);
+                derived.entry(did).or_default().extend(
+                    i.attrs.iter().filter(|attr| attr.check_name(sym::derive))
+                        .flat_map(|attr| attr.meta_item_list().unwrap_or_default())
+                        .map(|item| item.name_or_empty()));
+            }
+            ItemKind::Impl(_, _, _, _, Some(ref trait_ref), ref self_ty, _) => {
+                let trait_ = match_or!([trait_ref.path.segments.last()] Some(x) => x; return);
+                if let Some(did) = cx.try_resolve_ty(self_ty) {
+                    manual.entry(did).or_default().insert(trait_.ident.name);
+                }
+            }
+            _ => {}
+        });
+        // The impls generated by the derives don't count as manual ones.
+        for (did, traits) in &mut manual {
+            if let Some(derived) = derived.get(did) {
+                traits.retain(|trait_| !derived.contains(trait_));
+            }
+        }
+        let mut existing = derived.clone();
+        for (&did, traits) in &manual {
+            existing.entry(did).or_default().extend(traits.iter().cloned());
+        }
+
+        // (2) Work out which traits each struct can derive.
+
+        let mut planner = DerivePlanner {
+            cx,
+            traits: &self.traits,
+            ptr_eq: self.ptr_eq,
+            targets,
+            existing,
+            impls: HashMap::new(),
+        };
+        let mut derives = HashMap::new();
+        let mut target_list = planner.targets.iter().cloned().collect::<Vec<_>>();
+        target_list.sort_by_key(|did| tcx.def_path_str(*did));
+        for did in target_list {
+            let mut new_traits = Vec::new();
+            let mut skipped = Vec::new();
+            for &trait_ in &self.traits {
+                if derived[&did].contains(&trait_) {
+                    continue;
+                }
+                if manual.get(&did).map_or(false, |traits| traits.contains(&trait_)) {
+                    skipped.push(format!("`{}`, which has a manual impl", trait_));
+                    continue;
+                }
+                match planner.struct_impl(did, trait_) {
+                    Ok(()) => new_traits.push(trait_.to_string()),
+                    Err(msg) => skipped.push(format!("`{}`, as {}", trait_, msg)),
+                }
+            }
+            if !skipped.is_empty() {
+                warn!("derive_structs: `{}` doesn't derive {}",
+                      tcx.def_path_str(did), skipped.join("; "));
+            }
+            if !new_traits.is_empty() {
+                derives.insert(did, new_traits.join(", "));
+            }
+        }
+
+        // (3) Add the derives.
+
+        MutVisitNodes::visit(krate, |i: &mut P<Item>| {
+            if let ItemKind::Struct(..) = i.kind {
+                let traits = match_or!([derives.get(&cx.node_def_id(i.id))] Some(x) => x; return);
+                let src = format!("#[derive({})] struct S;", traits);
+                let attr = parse_items(cx.session(), &src)[0].attrs[0].clone();
+                i.attrs.push(attr);
+            }
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+/// # `split_struct` Command
+///
+/// Usage: `split_struct group=NAME:FIELD,FIELD,... [group=...] [force=1]`
+///
+/// Marks: `target`
+///
+/// Split the fields of the struct marked `target` into groups.  Each
+/// `group=NAME:FIELDS` argument moves the listed fields into a new struct,
+/// which replaces them in the marked struct as a single field `NAME`.  Fields
+/// that aren't in any group stay where they are.  The new struct is named after
+/// the marked struct and the group, so group `io` of `Context` gives
+/// `ContextIo`, and of `ctx_t` gives `ctx_t_io`.  It's added after the marked
+/// struct, with its derives and `repr`, and the visibility of the first field of
+/// the group is used for the new field.
+///
+/// Throughout the crate, accesses `s.f` to a grouped field become `s.NAME.f`.
+/// In struct literals and struct patterns, the grouped fields are gathered into
+/// a nested literal or pattern of the new struct.  A literal with a base
+/// expression that sets only some of the fields of a group takes the rest from
+/// `base.NAME`, which requires the base to be a place, such as a local.
+///
+/// Splitting a `#[repr(C)]` struct changes its layout, which breaks any C code
+/// sharing it, so such structs are refused unless `force=1` is passed.  Generic
+/// structs, unknown or repeated fields, group names that clash with the
+/// remaining fields, and literals that can't be split are also reported, and
+/// leave the struct alone.
+pub struct SplitStruct {
+    pub groups: Vec<(String, Vec<String>)>,
+    pub force: bool,
+}
+
+fn is_repr_c(attrs: &[Attribute]) -> bool {
+    attrs.iter().filter(|a| a.check_name(sym::repr)).any(|a| {
+        a.meta_item_list().map_or(false, |items| {
+            items.iter().any(|m| m.check_name(Symbol::intern("C")))
+        })
+    })
+}
+
+/// The name of the struct holding the fields of `group` split off from `struct_name`.
+fn group_struct_name(struct_name: &str, group: &str) -> String {
+    let camel = struct_name.starts_with(|c: char| c.is_ascii_uppercase()) &&
+        !struct_name.contains('_');
+    if camel {
+        let mut chars = group.chars();
+        let first = chars.next().map_or(String::new(), |c| c.to_uppercase().collect());
+        format!("{}{}{}", struct_name, first, chars.as_str())
+    } else {
+        format!("{}_{}", struct_name, group)
+    }
+}
+
+impl Transform for SplitStruct {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        // (1) Find the marked struct, and check the groups.
+
+        let mut target = None;
+        visit_nodes(krate, |i: &Item| {
+            if target.is_none() && st.marked(i.id, "target") && is_struct(i) {
+                target = Some(i.clone());
+            }
+        });
+        let item = match target {
+            Some(i) => i,
+            None => {
+                warn!("split_struct: no struct is marked `target`");
+                return;
+            }
+        };
+        let report = |msg: String| {
+            warn!("split_struct: {}; leaving `{}` alone", msg, item.ident);
+        };
+        let (fields, generics) = expect!([item.kind]
+            ItemKind::Struct(VariantData::Struct(ref fields, _), ref generics) => (fields, generics));
+        if !generics.params.is_empty() {
+            report("it is generic".into());
+            return;
+        }
+        if is_repr_c(&item.attrs) && !self.force {
+            report("it is `#[repr(C)]`, so splitting it would change its C layout \
+                    (pass `force=1` to split it anyway)".into());
+            return;
+        }
+
+        let mut group_of = HashMap::new();
+        for (g, &(_, ref members)) in self.groups.iter().enumerate() {
+            for m in members {
+                let name = Symbol::intern(m);
+                if !fields.iter().any(|f| f.ident.map_or(false, |i| i.name == name)) {
+                    report(format!("it has no field `{}`", m));
+                    return;
+                }
+                if group_of.insert(name, g).is_some() {
+                    report(format!("`{}` is in more than one group", m));
+                    return;
+                }
+            }
+        }
+        for &(ref group, _) in &self.groups {
+            let name = Symbol::intern(group);
+            let clash = fields.iter().any(|f| {
+                f.ident.map_or(false, |i| i.name == name && !group_of.contains_key(&name))
+            });
+            if clash || self.groups.iter().filter(|&&(ref g, _)| g == group).count() > 1 {
+                report(format!("group `{}` clashes with another field", group));
+                return;
+            }
+        }
+
+        let struct_def_id = cx.node_def_id(item.id);
+        let is_target = |ty: Option<ty::Ty>| {
+            let mut ty = match ty {
+                Some(ty) => ty,
+                None => return false,
+            };
+            while let ty::TyKind::Ref(_, inner, _) = ty.kind {
+                ty = inner;
+            }
+            match ty.kind {
+                ty::TyKind::Adt(adt, _) => adt.did == struct_def_id,
+                _ => false,
+            }
+        };
+
+        // (2) Find the accesses to grouped fields, and the struct literals and patterns to
+        // split.  For each literal and pattern, also record how it should name the new
+        // structs: by name alone in the module of the marked struct, and by full path
+        // elsewhere.
+
+        let hir_map = cx.hir_map();
+        let def_module = hir_map.get_module_parent_node(hir_map.node_to_hir_id(item.id));
+        let mut def_prefix = cx.def_path(struct_def_id);
+        def_prefix.segments.pop();
+        let def_prefix = format!("{}::", pprust::path_to_string(&def_prefix));
+        let prefix_for = |id: NodeId| {
+            if hir_map.get_module_parent_node(hir_map.node_to_hir_id(id)) == def_module {
+                String::new()
+            } else {
+                def_prefix.clone()
+            }
+        };
+
+        let mut accesses = HashSet::new();
+        let mut literals = HashMap::new();
+        let mut problems = vec![];
+        visit_nodes(krate, |e: &Expr| match e.kind {
+            ExprKind::Field(ref base, ident) if group_of.contains_key(&ident.name) => {
+                let base = strip_parens(base);
+                if is_target(cx.opt_node_type(base.id)) {
+                    accesses.insert(e.id);
+                }
+            }
+            ExprKind::Struct(_, ref lit_fields, ref base) if is_target(cx.opt_node_type(e.id)) => {
+                for (g, &(ref group, ref members)) in self.groups.iter().enumerate() {
+                    let present = lit_fields.iter()
+                        .filter(|f| group_of.get(&f.ident.name) == Some(&g))
+                        .count();
+                    if present > 0 && present < members.len() &&
+                       !base.as_ref().map_or(false, |b| is_simple_place(b)) {
+                        problems.push(format!(
+                            "the literal at {} sets only some fields of group `{}`, and has no \
+                             base place to take the others from",
+                            cx.session().source_map().span_to_string(e.span), group));
+                    }
+                }
+                literals.insert(e.id, prefix_for(e.id));
+            }
+            _ => {}
+        });
+        let mut patterns = HashMap::new();
+        visit_nodes(krate, |p: &Pat| {
+            if let PatKind::Struct(..) = p.kind {
+                if is_target(cx.opt_node_type(p.id)) {
+                    patterns.insert(p.id, prefix_for(p.id));
+                }
+            }
+        });
+        if !problems.is_empty() {
+            report(problems.join(", "));
+            return;
+        }
+
+        // (3) Build the new structs, and replace the grouped fields with them.
+
+        let vis = pprust::vis_to_string(&item.vis);
+        let kept_attrs = item.attrs.iter()
+            .filter(|a| a.check_name(sym::derive) || a.check_name(sym::repr))
+            .cloned()
+            .collect::<Vec<_>>();
+        let struct_names = self.groups.iter()
+            .map(|&(ref group, _)| group_struct_name(&item.ident.as_str(), group))
+            .collect::<Vec<_>>();
+        let mut new_items = vec![];
+        let mut group_fields = vec![];
+        for ((group, members), name) in self.groups.iter().zip(&struct_names) {
+            let members = members.iter().map(|m| Symbol::intern(m)).collect::<Vec<_>>();
+            let moved = fields.iter()
+                .filter(|f| f.ident.map_or(false, |i| members.contains(&i.name)))
+                .cloned()
+                .collect::<Vec<_>>();
+            let mut new_item = st.parse_items(cx, &format!("{}struct {} {{}}", vis, name)).lone();
+            new_item.attrs = kept_attrs.clone();
+            if let ItemKind::Struct(VariantData::Struct(ref mut new_fields, _), _) =
+                new_item.kind {
+                *new_fields = moved;
+            }
+            new_items.push(new_item);
+
+            let first = fields.iter()
+                .find(|f| f.ident.map_or(false, |i| members.contains(&i.name)))
+                .unwrap();
+            group_fields.push(expect!([st.parse_items(cx, &format!(
+                "struct S {{ {}{}: {} }}", pprust::vis_to_string(&first.vis), group, name))
+                .lone().kind]
+                ItemKind::Struct(VariantData::Struct(ref fields, _), _) => fields[0].clone()));
+        }
+
+        let mut new_items = Some(new_items);
+        FlatMapNodes::visit(krate, |mut i: P<Item>| {
+            if i.id != item.id {
+                return smallvec![i];
+            }
+            if let ItemKind::Struct(VariantData::Struct(ref mut fields, _), _) = i.kind {
+                let old_fields = mem::replace(fields, vec![]);
+                let mut added = HashSet::new();
+                for f in old_fields {
+                    match f.ident.and_then(|i| group_of.get(&i.name)) {
+                        Some(&g) => if added.insert(g) {
+                            fields.push(group_fields[g].clone());
+                        },
+                        None => fields.push(f),
+                    }
+                }
+            }
+            let mut items = smallvec![i];
+            items.extend(new_items.take().unwrap());
+            items
+        });
+
+        // (4) Rewrite the accesses, literals and patterns.
+
+        let group_idents = self.groups.iter()
+            .map(|&(ref group, _)| Ident::from_str(group))
+            .collect::<Vec<_>>();
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            if accesses.contains(&e.id) {
+                if let ExprKind::Field(ref mut base, ident) = e.kind {
+                    let g = group_of[&ident.name];
+                    *base = mk().field_expr(base.clone(), group_idents[g]);
+                }
+                return;
+            }
+            let prefix = match_or!([literals.get(&e.id)] Some(x) => x; return);
+            if let ExprKind::Struct(_, ref mut lit_fields, ref base) = e.kind {
+                let old_fields = mem::replace(lit_fields, vec![]);
+                let mut added: HashMap<usize, usize> = HashMap::new();
+                for f in old_fields {
+                    let g = match group_of.get(&f.ident.name) {
+                        Some(&g) => g,
+                        None => {
+                            lit_fields.push(f);
+                            continue;
+                        }
+                    };
+                    let pos = *added.entry(g).or_insert_with(|| {
+                        let gf = expect!([parse_expr(cx.session(), &format!(
+                            "S {{ {}: {}{} {{}} }}", group_idents[g], prefix, struct_names[g])).kind]
+                            ExprKind::Struct(_, ref fields, _) => fields[0].clone());
+                        lit_fields.push(gf);
+                        lit_fields.len() - 1
+                    });
+                    if let ExprKind::Struct(_, ref mut inner, _) = lit_fields[pos].expr.kind {
+                        inner.push(f);
+                    }
+                }
+                for (&g, &pos) in &added {
+                    if let ExprKind::Struct(_, ref inner, ref mut inner_base) =
+                        lit_fields[pos].expr.kind {
+                        if inner.len() < self.groups[g].1.len() {
+                            let base = base.as_ref().unwrap();
+                            *inner_base = Some(mk().field_expr(base.clone(), group_idents[g]));
+                        }
+                    }
+                }
+            }
+        });
+
+        MutVisitNodes::visit(krate, |p: &mut P<Pat>| {
+            let prefix = match_or!([patterns.get(&p.id)] Some(x) => x; return);
+            if let PatKind::Struct(_, ref mut pat_fields, _) = p.kind {
+                let old_fields = mem::replace(pat_fields, vec![]);
+                let mut added: HashMap<usize, usize> = HashMap::new();
+                for f in old_fields {
+                    let g = match group_of.get(&f.ident.name) {
+                        Some(&g) => g,
+                        None => {
+                            pat_fields.push(f);
+                            continue;
+                        }
+                    };
+                    let pos = *added.entry(g).or_insert_with(|| {
+                        let gp = expect!([parse_pat(cx.session(), &format!(
+                            "S {{ {}: {}{} {{ .. }} }}", group_idents[g], prefix, struct_names[g])).kind]
+                            PatKind::Struct(_, ref fields, _) => fields[0].clone());
+                        pat_fields.push(gp);
+                        pat_fields.len() - 1
+                    });
+                    if let PatKind::Struct(_, ref mut inner, _) = pat_fields[pos].pat.kind {
+                        inner.push(f);
+                    }
+                }
+                for (&g, &pos) in &added {
+                    if let PatKind::Struct(_, ref inner, ref mut rest) =
+                        pat_fields[pos].pat.kind {
+                        *rest = inner.len() < self.groups[g].1.len();
+                    }
+                }
+            }
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
 pub fn register_commands(reg: &mut Registry) {
     use super::mk;
 
     reg.register("struct_assign_to_update", |_args| mk(AssignToUpdate));
     reg.register("struct_merge_updates", |_args| mk(MergeUpdates));
     reg.register("rename_struct", |args| mk(Rename(args[0].clone())));
+    reg.register("zeroed_to_default", |_args| mk(ZeroedToDefault));
+    reg.register("derive_structs", |args| mk(DeriveStructs {
+        traits: match args.iter().find(|arg| arg.starts_with("traits=")) {
+            None => vec!["Debug", "Clone", "Copy", "PartialEq"],
+            Some(arg) => arg["traits=".len()..].split(',').collect(),
+        }.into_iter().map(|trait_| {
+            if !DERIVE_TRAITS.contains(&trait_) {
+                panic!("derive_structs: can't derive `{}`", trait_);
+            }
+            trait_.into_symbol()
+        }).collect(),
+        only_marked: args.iter().any(|arg| arg == "only=marked"),
+        ptr_eq: args.iter().any(|arg| arg == "ptr_eq=1"),
+    }));
+    reg.register("split_struct", |args| mk(SplitStruct {
+        groups: args.iter().filter(|arg| arg.starts_with("group=")).map(|arg| {
+            let spec = &arg["group=".len()..];
+            let colon = spec.find(':')
+                .unwrap_or_else(|| panic!("split_struct: expected `group=NAME:FIELDS`, got `{}`", arg));
+            let fields = spec[colon + 1..].split(',').map(|f| f.to_owned()).collect();
+            (spec[..colon].to_owned(), fields)
+        }).collect(),
+        force: args.iter().any(|arg| arg == "force=1"),
+    }));
 }
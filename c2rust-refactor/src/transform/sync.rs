@@ -0,0 +1,885 @@
+//! Transforms that replace `pthread` mutexes and threads with their `std` equivalents.
+
+use std::collections::{HashMap, HashSet};
+use std::mem;
+use rustc::hir::HirId;
+use rustc::hir::def::{DefKind, Res};
+use rustc::hir::def_id::DefId;
+use rustc::traits;
+use rustc::ty::{self, ParamEnv};
+use syntax::ast::*;
+use syntax::print::pprust;
+use syntax::ptr::P;
+use syntax::visit::{self, Visitor};
+use syntax_pos::{sym, Span, DUMMY_SP};
+
+use c2rust_ast_builder::IntoSymbol;
+use crate::ast_manip::{AstEquiv, MutVisitNodes, visit_nodes};
+use crate::command::{CommandState, Registry};
+use crate::contains_mark::contains_mark;
+use crate::driver::{self, Phase};
+use crate::matcher::{Bindings, MatchCtxt, Subst};
+use crate::transform::Transform;
+use crate::transform::enums::field_def;
+use crate::transform::mem::{is_zero_lit, strip_casts};
+use crate::transform::null_ptrs::is_null_ptr;
+use crate::util::Lone;
+use crate::RefactorCtxt;
+
+
+/// # `pthread_to_std_sync` Command
+///
+/// Usage: `pthread_to_std_sync [protects=FIELD]`
+///
+/// Marks: `target`
+///
+/// Replace the `pthread_mutex_t` statics and struct fields marked `target` with
+/// `std::sync::Mutex<()>`, and turn the calls that use them into guards:
+///
+///  * `pthread_mutex_lock(&mut m);` becomes `let _guard = m.lock().unwrap();`.
+///  * The matching `pthread_mutex_unlock(&mut m);` later in the same block is
+///    removed if it ends the block, and becomes `drop(_guard);` otherwise.  An
+///    unlock in a nested block that is immediately followed by a `return`,
+///    `break` or `continue` is removed.
+///  * `if pthread_mutex_trylock(&mut m) == 0 { ...; pthread_mutex_unlock(&mut m); }`
+///    becomes `if let Ok(_guard) = m.try_lock() { ... }`.
+///  * `pthread_mutex_init` and `pthread_mutex_destroy` calls are removed, and
+///    struct literals use `Mutex::new(())` instead.  Converted statics become
+///    immutable `once_cell::sync::Lazy<Mutex<()>>`s, created on first use, as in
+///    `static_to_safe`, since `Mutex::new` isn't `const`.  The crate being
+///    refactored must depend on `once_cell`.
+///
+/// With `protects=FIELD`, a marked field protects the field `FIELD` of the same
+/// struct: the mutex becomes a `Mutex<T>` holding `FIELD`'s value, `FIELD` is
+/// removed, and the accesses to it while the mutex is locked become `*_guard`.
+/// A mutex whose protected field is accessed anywhere else is skipped.
+///
+/// A mutex is skipped with a warning if it is locked without being unlocked in
+/// the same block, such as when it is locked in one function and unlocked in
+/// another; if the locked region can be left without unlocking it; or if it is
+/// used in any other way, such as by being passed to `pthread_cond_wait`.
+pub struct PthreadToStdSync {
+    pub protects: Option<String>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+enum MutexDef {
+    Static(DefId),
+    Field(DefId),
+}
+
+struct MutexInfo {
+    name: Ident,
+    /// The field this mutex protects, and its type.
+    protected: Option<(DefId, P<Ty>)>,
+}
+
+/// A locked region that `pthread_to_std_sync` turns into a guard.
+struct LockedRegion {
+    mutex: MutexDef,
+    place: P<Expr>,
+    /// The `pthread_mutex_lock` statement, or the `pthread_mutex_trylock` `if` expression.
+    lock: NodeId,
+    is_try: bool,
+    /// The `pthread_mutex_unlock` statement that ends the region.
+    unlock: NodeId,
+    /// Whether the guard must be dropped explicitly at `unlock`.
+    needs_drop: bool,
+    /// Unlocks that are followed by leaving the region, and can be removed.
+    exit_unlocks: Vec<NodeId>,
+    /// Accesses to the protected field within the region.
+    accesses: Vec<NodeId>,
+}
+
+/// If `e` is a call to the `pthread_mutex_*` function `name` on `&mut place`, return `place`.
+fn mutex_call<'a>(e: &'a Expr, name: &str) -> Option<&'a P<Expr>> {
+    let (func, args) = match_or!([e.kind] ExprKind::Call(ref func, ref args) => (func, args);
+                                 return None);
+    let path = match_or!([func.kind] ExprKind::Path(None, ref path) => path; return None);
+    if &*path.segments.last()?.ident.as_str() != name {
+        return None;
+    }
+    match args.get(0)?.kind {
+        ExprKind::AddrOf(_, Mutability::Mutable, ref place) => Some(place),
+        _ => None,
+    }
+}
+
+/// If `s` is a statement consisting of a call to `name`, return the place it's called on.
+fn mutex_call_stmt<'a>(s: &'a Stmt, name: &str) -> Option<&'a P<Expr>> {
+    match s.kind {
+        StmtKind::Semi(ref e) | StmtKind::Expr(ref e) => mutex_call(e, name),
+        _ => None,
+    }
+}
+
+/// If `s` is `if pthread_mutex_trylock(&mut place) == 0 { ... }`, return `place` and the
+/// `if`'s blocks.
+fn trylock_if(s: &Stmt) -> Option<(&P<Expr>, &P<Expr>, &P<Block>, Option<&P<Expr>>)> {
+    let e = match s.kind {
+        StmtKind::Semi(ref e) | StmtKind::Expr(ref e) => e,
+        _ => return None,
+    };
+    let (cond, then, els) = match_or!([e.kind] ExprKind::If(ref c, ref t, ref e) => (c, t, e);
+                                      return None);
+    match cond.kind {
+        ExprKind::Binary(op, ref lhs, ref rhs) if op.node == BinOpKind::Eq && is_zero_lit(rhs) =>
+            mutex_call(lhs, "pthread_mutex_trylock").map(|place| (e, place, then, els.as_ref())),
+        _ => None,
+    }
+}
+
+fn is_exit_stmt(s: &Stmt) -> Option<NodeId> {
+    match s.kind {
+        StmtKind::Semi(ref e) | StmtKind::Expr(ref e) => match e.kind {
+            ExprKind::Ret(..) | ExprKind::Break(..) | ExprKind::Continue(..) => Some(e.id),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Finds the expressions that leave a region of code: `return`s and `?`s, and `break`s and
+/// `continue`s that aren't inside a loop within the region.
+struct ExitFinder {
+    loop_depth: usize,
+    exits: Vec<NodeId>,
+}
+
+impl<'ast> Visitor<'ast> for ExitFinder {
+    fn visit_expr(&mut self, e: &'ast Expr) {
+        match e.kind {
+            ExprKind::Ret(..) | ExprKind::Try(..) => self.exits.push(e.id),
+            ExprKind::Break(label, _) | ExprKind::Continue(label)
+                    if self.loop_depth == 0 || label.is_some() => self.exits.push(e.id),
+            ExprKind::Loop(..) | ExprKind::While(..) | ExprKind::ForLoop(..) => {
+                self.loop_depth += 1;
+                visit::walk_expr(self, e);
+                self.loop_depth -= 1;
+                return;
+            }
+            ExprKind::Closure(..) => return,
+            _ => {}
+        }
+        visit::walk_expr(self, e);
+    }
+
+    fn visit_item(&mut self, _i: &'ast Item) {}
+
+    fn visit_mac(&mut self, mac: &'ast Mac) {
+        visit::walk_mac(self, mac);
+    }
+}
+
+impl Transform for PthreadToStdSync {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        let target = "target".into_symbol();
+
+        // (1) Find the marked mutexes.
+
+        let mut mutexes = HashMap::new();
+        visit_nodes(krate, |i: &Item| {
+            match i.kind {
+                ItemKind::Static(..) if st.marked(i.id, target) => {
+                    mutexes.insert(MutexDef::Static(cx.node_def_id(i.id)), MutexInfo {
+                        name: i.ident,
+                        protected: None,
+                    });
+                }
+                ItemKind::Struct(VariantData::Struct(ref fields, _), _) => {
+                    for field in fields.iter().filter(|f| st.marked(f.id, target)) {
+                        let name = match_or!([field.ident] Some(x) => x; continue);
+                        let protected = match self.protects {
+                            Some(ref protects) => {
+                                let pf = fields.iter()
+                                    .find(|f| f.ident.map_or(false, |i| &*i.as_str() == protects));
+                                match pf {
+                                    Some(pf) => Some((cx.node_def_id(pf.id), pf.ty.clone())),
+                                    None => {
+                                        warn!("pthread_to_std_sync: `{}` has no field `{}`; \
+                                               skipping `{}`", i.ident, protects, name);
+                                        continue;
+                                    }
+                                }
+                            }
+                            None => None,
+                        };
+                        mutexes.insert(MutexDef::Field(cx.node_def_id(field.id)), MutexInfo {
+                            name,
+                            protected,
+                        });
+                    }
+                }
+                _ => {}
+            }
+        });
+        if mutexes.is_empty() {
+            return;
+        }
+
+        let mutex_of = |e: &Expr| mutex_of_inner(cx, &mutexes, e);
+        let mut skipped = HashSet::new();
+        let skip = |skipped: &mut HashSet<MutexDef>, m: MutexDef, why: &str, span: Span| {
+            if skipped.insert(m) {
+                warn!("pthread_to_std_sync: skipping `{}`, which {} at {}", mutexes[&m].name,
+                      why, cx.session().source_map().span_to_string(span));
+            }
+        };
+
+        // (2) Find the locked regions, and check that they can be converted.
+
+        let mut regions = Vec::new();
+        let mut allowed = HashSet::new();
+        let mut init_stmts = Vec::new();
+
+        // Check the statements of a locked region.  Records the unlocks that leave the region
+        // and accesses to the protected field, or returns why the region can't be converted.
+        let check_region = |stmts: &[Stmt], m: MutexDef, place: &P<Expr>,
+                            allowed: &mut HashSet<NodeId>|
+                            -> Result<(Vec<NodeId>, Vec<NodeId>), &'static str> {
+            let mut exit_unlocks = Vec::new();
+            let mut covered = HashSet::new();
+            let mut check_stmts = |stmts: &[Stmt]| {
+                for (k, s) in stmts.iter().enumerate() {
+                    if let Some(p) = mutex_call_stmt(s, "pthread_mutex_unlock") {
+                        if let Some(exit) = stmts.get(k + 1).and_then(is_exit_stmt) {
+                            if p.ast_equiv(place) {
+                                exit_unlocks.push(s.id);
+                                covered.insert(exit);
+                                allowed.insert(p.id);
+                            }
+                        }
+                    }
+                }
+            };
+            for s in stmts {
+                visit_nodes(s, |b: &Block| check_stmts(&b.stmts));
+            }
+
+            let mut finder = ExitFinder { loop_depth: 0, exits: Vec::new() };
+            for s in stmts {
+                finder.visit_stmt(s);
+            }
+            if finder.exits.iter().any(|id| !covered.contains(id)) {
+                return Err("can be left while it is locked");
+            }
+
+            let mut accesses = Vec::new();
+            let mut relocked = false;
+            for s in stmts {
+                visit_nodes(s, |e: &Expr| {
+                    if let Some((pm, base)) = protected_field_of(cx, &mutexes, e) {
+                        let same_base = match place.kind {
+                            ExprKind::Field(ref pbase, _) => pbase.ast_equiv(base),
+                            _ => false,
+                        };
+                        if pm == m && same_base {
+                            accesses.push(e.id);
+                        }
+                    }
+                    for name in &["pthread_mutex_lock", "pthread_mutex_trylock"] {
+                        if mutex_call(e, name).and_then(|p| mutex_of(p)) == Some(m) {
+                            relocked = true;
+                        }
+                    }
+                });
+            }
+            if relocked {
+                return Err("is locked again while it is locked");
+            }
+            Ok((exit_unlocks, accesses))
+        };
+
+        visit_nodes(krate, |b: &Block| {
+            for (i, s) in b.stmts.iter().enumerate() {
+                let (place, rest, lock, is_try, els) =
+                    if let Some(place) = mutex_call_stmt(s, "pthread_mutex_lock") {
+                        (place, &b.stmts[i + 1..], s.id, false, None)
+                    } else if let Some((e, place, then, els)) = trylock_if(s) {
+                        (place, &then.stmts[..], e.id, true, els)
+                    } else {
+                        continue;
+                    };
+                let m = match_or!([mutex_of(place)] Some(x) => x; continue);
+
+                let j = rest.iter().position(|s| {
+                    mutex_call_stmt(s, "pthread_mutex_unlock").map_or(false, |p| p.ast_equiv(place))
+                });
+                let j = match j {
+                    Some(j) => j,
+                    None => {
+                        skip(&mut skipped, m, "is not unlocked in the block it is locked in",
+                             s.span);
+                        continue;
+                    }
+                };
+                if let Some(els) = els {
+                    let mut touched = false;
+                    visit_nodes(&**els, |e: &Expr| touched |= mutex_of(e) == Some(m));
+                    if touched {
+                        skip(&mut skipped, m, "is used when `pthread_mutex_trylock` fails", s.span);
+                        continue;
+                    }
+                }
+
+                let region = &rest[..j];
+                let (exit_unlocks, accesses) = match check_region(region, m, place, &mut allowed) {
+                    Ok(x) => x,
+                    Err(why) => {
+                        skip(&mut skipped, m, why, s.span);
+                        continue;
+                    }
+                };
+                let needs_drop = j + 1 < rest.len();
+                // The guard of another mutex locked in the same block would shadow this one.
+                if needs_drop && region.iter().any(|s| {
+                    mutex_call_stmt(s, "pthread_mutex_lock").and_then(|p| mutex_of(p)).is_some()
+                }) {
+                    skip(&mut skipped, m, "is locked around another mutex", s.span);
+                    continue;
+                }
+
+                allowed.insert(place.id);
+                allowed.insert(mutex_call_stmt(&rest[j], "pthread_mutex_unlock").unwrap().id);
+                regions.push(LockedRegion {
+                    mutex: m,
+                    place: place.clone(),
+                    lock,
+                    is_try,
+                    unlock: rest[j].id,
+                    needs_drop,
+                    exit_unlocks,
+                    accesses,
+                });
+            }
+        });
+        visit_nodes(krate, |s: &Stmt| {
+            for name in &["pthread_mutex_init", "pthread_mutex_destroy"] {
+                if let Some(place) = mutex_call_stmt(s, name) {
+                    if let Some(m) = mutex_of(place) {
+                        allowed.insert(place.id);
+                        init_stmts.push((m, s.id));
+                    }
+                }
+            }
+        });
+
+        // Every other use of a mutex, or of a protected field outside its regions, is an error.
+        let accesses = regions.iter()
+            .flat_map(|r| r.accesses.iter().cloned())
+            .collect::<HashSet<_>>();
+        visit_nodes(krate, |e: &Expr| {
+            if let Some(m) = mutex_of(e) {
+                if !allowed.contains(&e.id) {
+                    skip(&mut skipped, m, "is used other than by locking it", e.span);
+                }
+            }
+            if let Some((m, _)) = protected_field_of(cx, &mutexes, e) {
+                if !accesses.contains(&e.id) {
+                    skip(&mut skipped, m, "has its protected field accessed while unlocked",
+                         e.span);
+                }
+            }
+            if let ExprKind::Struct(_, ref fields, ref base) = e.kind {
+                for f in fields {
+                    let did = match_or!([field_def(cx, e.id, f.ident)] Some(x) => x; continue);
+                    let info = match_or!([mutexes.get(&MutexDef::Field(did))] Some(x) => x;
+                                         continue);
+                    if let Some((pdid, _)) = info.protected {
+                        let has_value = fields.iter()
+                            .any(|f| field_def(cx, e.id, f.ident) == Some(pdid));
+                        if base.is_some() || !has_value {
+                            skip(&mut skipped, MutexDef::Field(did),
+                                 "is built without the value it protects", e.span);
+                        }
+                    }
+                }
+            }
+        });
+
+        regions.retain(|r| !skipped.contains(&r.mutex));
+        let converted = mutexes.iter()
+            .filter(|(m, _)| !skipped.contains(*m))
+            .collect::<HashMap<_, _>>();
+        if converted.is_empty() {
+            return;
+        }
+
+        // (3) Rewrite the locked regions.
+
+        let mut mcx = MatchCtxt::new(st, cx);
+        let lock_repl = mcx.parse_stmts("let _guard = $m.lock().unwrap();");
+        let try_lock_repl = mcx.parse_expr("$m.try_lock()");
+        let drop_repl = mcx.parse_stmts("drop(_guard);");
+        let guard_repl = driver::parse_expr(cx.session(), "(*_guard)");
+
+        let mut removed = init_stmts.into_iter()
+            .filter(|(m, _)| converted.contains_key(m))
+            .map(|(_, id)| id)
+            .collect::<HashSet<_>>();
+        let mut lock_stmts = HashMap::new();
+        let mut try_locks = HashMap::new();
+        let mut drops = HashSet::new();
+        let mut guard_accesses = HashSet::new();
+        for r in &regions {
+            let mut bnd = Bindings::new();
+            bnd.add("$m", r.place.clone());
+            if r.is_try {
+                try_locks.insert(r.lock, try_lock_repl.clone().subst(st, cx, &bnd));
+            } else {
+                lock_stmts.insert(r.lock, lock_repl.clone().subst(st, cx, &bnd).lone());
+            }
+            if r.needs_drop {
+                drops.insert(r.unlock);
+            } else {
+                removed.insert(r.unlock);
+            }
+            removed.extend(r.exit_unlocks.iter().cloned());
+            guard_accesses.extend(r.accesses.iter().cloned());
+        }
+
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            if guard_accesses.contains(&e.id) {
+                *e = guard_repl.clone();
+                return;
+            }
+            if let Some(scrutinee) = try_locks.get(&e.id) {
+                let mut new_if = driver::parse_expr(cx.session(), "if let Ok(_guard) = __m {}");
+                if let ExprKind::If(ref mut new_cond, _, _) = new_if.kind {
+                    if let ExprKind::Let(_, ref mut s) = new_cond.kind {
+                        *s = scrutinee.clone();
+                    }
+                    if let ExprKind::If(ref mut cond, _, _) = e.kind {
+                        *cond = new_cond.clone();
+                    }
+                }
+            }
+        });
+
+        MutVisitNodes::visit(krate, |b: &mut P<Block>| {
+            let old_stmts = mem::replace(&mut b.stmts, Vec::new());
+            for s in old_stmts {
+                if let Some(new_s) = lock_stmts.get(&s.id) {
+                    b.stmts.push(new_s.clone());
+                } else if drops.contains(&s.id) {
+                    b.stmts.push(drop_repl.clone().lone());
+                } else if !removed.contains(&s.id) {
+                    b.stmts.push(s);
+                }
+            }
+        });
+
+        // (4) Retype the mutexes, and build them with `Mutex::new`.  Statics need a constant
+        // initializer, so they're wrapped in a `Lazy`.
+
+        let mutex_ty = |info: &MutexInfo| {
+            let inner = info.protected.as_ref()
+                .map_or("()".to_owned(), |p| pprust::ty_to_string(&p.1));
+            driver::parse_ty(cx.session(), &format!("::std::sync::Mutex<{}>", inner))
+        };
+        let new_mutex = |val: Option<&P<Expr>>| {
+            let mut e = driver::parse_expr(cx.session(), "::std::sync::Mutex::new(())");
+            if let (Some(val), ExprKind::Call(_, ref mut args)) = (val, &mut e.kind) {
+                args[0] = val.clone();
+            }
+            e
+        };
+
+        MutVisitNodes::visit(krate, |i: &mut P<Item>| {
+            let id = i.id;
+            match i.kind {
+                ItemKind::Static(ref mut ty, ref mut mutbl, ref mut init) => {
+                    if let Some(info) = converted.get(&MutexDef::Static(cx.node_def_id(id))) {
+                        let lazy_ty = format!("::once_cell::sync::Lazy<{}>",
+                                              pprust::ty_to_string(&mutex_ty(info)));
+                        let lazy_init = format!("::once_cell::sync::Lazy::new(|| {})",
+                                                pprust::expr_to_string(&new_mutex(None)));
+                        *ty = driver::parse_ty(cx.session(), &lazy_ty);
+                        *mutbl = Mutability::Immutable;
+                        *init = driver::parse_expr(cx.session(), &lazy_init);
+                    }
+                }
+                ItemKind::Struct(VariantData::Struct(ref mut fields, _), _) => {
+                    let mut protected = Vec::new();
+                    for field in fields.iter_mut() {
+                        let did = cx.node_def_id(field.id);
+                        if let Some(info) = converted.get(&MutexDef::Field(did)) {
+                            field.ty = mutex_ty(info);
+                            protected.extend(info.protected.as_ref().map(|p| p.0));
+                        }
+                    }
+                    fields.retain(|f| !protected.contains(&cx.node_def_id(f.id)));
+                }
+                _ => {}
+            }
+        });
+
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            let id = e.id;
+            if let ExprKind::Struct(_, ref mut fields, _) = e.kind {
+                let mut protected = Vec::new();
+                let values = fields.iter()
+                    .filter_map(|f| field_def(cx, id, f.ident).map(|did| (did, f.expr.clone())))
+                    .collect::<HashMap<_, _>>();
+                for f in fields.iter_mut() {
+                    let did = match_or!([field_def(cx, id, f.ident)] Some(x) => x; continue);
+                    let info = match_or!([converted.get(&MutexDef::Field(did))] Some(x) => x;
+                                         continue);
+                    f.expr = match info.protected {
+                        Some((pdid, _)) => {
+                            protected.push(pdid);
+                            new_mutex(values.get(&pdid))
+                        }
+                        None => new_mutex(None),
+                    };
+                }
+                fields.retain(|f| {
+                    field_def(cx, id, f.ident).map_or(true, |did| !protected.contains(&did))
+                });
+            }
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+/// The mutex that the place `e` refers to, if it's one of `mutexes`.
+fn mutex_of_inner(cx: &RefactorCtxt, mutexes: &HashMap<MutexDef, MutexInfo>,
+                  e: &Expr) -> Option<MutexDef> {
+    let def = match e.kind {
+        ExprKind::Path(..) => MutexDef::Static(cx.try_resolve_expr(e)?),
+        ExprKind::Field(ref base, ident) => MutexDef::Field(field_def(cx, base.id, ident)?),
+        _ => return None,
+    };
+    if mutexes.contains_key(&def) { Some(def) } else { None }
+}
+
+/// If `e` accesses a field protected by one of `mutexes`, return the mutex and the struct
+/// `e` accesses the field of.
+fn protected_field_of<'a>(cx: &RefactorCtxt, mutexes: &HashMap<MutexDef, MutexInfo>,
+                          e: &'a Expr) -> Option<(MutexDef, &'a P<Expr>)> {
+    let (base, ident) = match_or!([e.kind] ExprKind::Field(ref b, i) => (b, i); return None);
+    let did = field_def(cx, base.id, ident)?;
+    mutexes.iter()
+        .find(|(_, info)| info.protected.as_ref().map_or(false, |p| p.0 == did))
+        .map(|(&m, _)| (m, base))
+}
+
+
+/// # `pthread_to_std_thread` Command
+///
+/// Usage: `pthread_to_std_thread`
+///
+/// Marks: `target`
+///
+/// Replace the `pthread_t` locals and struct fields marked `target` with
+/// `Option<std::thread::JoinHandle<usize>>`, and the `pthread` calls on them with `std::thread`
+/// operations:
+///
+///  * `pthread_create(&mut t, null, Some(worker), arg);` becomes
+///    `let thread_arg = arg as usize;` followed by
+///    `t = Some(::std::thread::spawn(move || worker(thread_arg as _) as usize));`.
+///  * `pthread_join(t, null);` becomes `t.take().unwrap().join().unwrap();`, and
+///    `pthread_join(t, &mut ret);` assigns the result of `join` to `ret`.
+///
+/// The attributes passed to `pthread_create` must be null.  The argument is sent to the new
+/// thread as an address, so a handle is skipped with a warning naming the type if the argument
+/// points to a type that isn't `Send`.  A handle that is used in any other way, such as by being
+/// passed to `pthread_detach`, or whose `pthread_create` or `pthread_join` result is used, is also
+/// skipped with a warning.
+///
+/// A worker function whose only uses are in converted `pthread_create` calls becomes a plain Rust
+/// function, unless it is exported with `#[no_mangle]` or `#[export_name]`.  Otherwise it keeps
+/// its `extern "C"` signature, and the new threads call it directly.
+pub struct PthreadToStdThread;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+enum ThreadHandle {
+    Local(HirId),
+    Field(DefId),
+}
+
+/// A `pthread_create` or `pthread_join` call that `pthread_to_std_thread` will convert.
+struct ThreadCall {
+    handle: ThreadHandle,
+    stmt: NodeId,
+    bnd: Bindings,
+    /// The worker function of a `pthread_create` call, and the path that refers to it.
+    worker: Option<(DefId, NodeId)>,
+}
+
+/// The function called by the new thread, if `e` is a (possibly `Some`-wrapped and cast) path
+/// to a function.
+fn thread_worker<'a>(cx: &RefactorCtxt, e: &'a P<Expr>) -> Option<(DefId, &'a P<Expr>)> {
+    let mut e = strip_casts(e);
+    if let ExprKind::Call(ref func, ref args) = e.kind {
+        match func.kind {
+            ExprKind::Path(None, ref path) if args.len() == 1 &&
+                    path.segments.last().map_or(false, |seg| &*seg.ident.as_str() == "Some") =>
+                e = strip_casts(&args[0]),
+            _ => return None,
+        }
+    }
+    let def_id = cx.try_resolve_expr(e)?;
+    match cx.ty_ctxt().def_kind(def_id) {
+        Some(DefKind::Fn) if def_id.is_local() => Some((def_id, e)),
+        _ => None,
+    }
+}
+
+/// The type that the pointer `e` points to, looking through any casts to `*mut c_void`.
+fn thread_arg_pointee<'tcx>(cx: &RefactorCtxt<'_, 'tcx>, e: &P<Expr>) -> Option<ty::Ty<'tcx>> {
+    match cx.opt_node_type(strip_casts(e).id)?.kind {
+        ty::TyKind::RawPtr(ty::TypeAndMut { ty, .. }) | ty::TyKind::Ref(_, ty, _) => Some(ty),
+        _ => None,
+    }
+}
+
+fn is_send<'tcx>(cx: &RefactorCtxt<'_, 'tcx>, ty: ty::Ty<'tcx>) -> bool {
+    let tcx = cx.ty_ctxt();
+    let send = match_or!([tcx.lang_items().send_trait()] Some(x) => x; return true);
+    tcx.infer_ctxt().enter(|infcx| {
+        traits::type_known_to_meet_bound_modulo_regions(
+            &infcx, ParamEnv::empty(), ty, send, DUMMY_SP)
+    })
+}
+
+impl Transform for PthreadToStdThread {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        let target = "target".into_symbol();
+
+        // (1) Find the marked handles.
+
+        let mut handles = HashMap::new();
+        visit_nodes(krate, |l: &Local| {
+            let ident = match_or!([l.pat.kind] PatKind::Ident(_, ident, None) => ident; return);
+            if contains_mark(l, target, st) {
+                handles.insert(ThreadHandle::Local(cx.hir_map().node_to_hir_id(l.pat.id)), ident);
+            }
+        });
+        visit_nodes(krate, |i: &Item| {
+            if let ItemKind::Struct(VariantData::Struct(ref fields, _), _) = i.kind {
+                for f in fields.iter().filter(|f| st.marked(f.id, target)) {
+                    if let Some(ident) = f.ident {
+                        handles.insert(ThreadHandle::Field(cx.node_def_id(f.id)), ident);
+                    }
+                }
+            }
+        });
+        if handles.is_empty() {
+            return;
+        }
+
+        let handle_of = |e: &Expr| -> Option<ThreadHandle> {
+            let handle = match e.kind {
+                ExprKind::Path(..) => match cx.try_resolve_expr_hir(e)? {
+                    Res::Local(hir_id) => ThreadHandle::Local(hir_id),
+                    _ => return None,
+                },
+                ExprKind::Field(ref base, ident) =>
+                    ThreadHandle::Field(field_def(cx, base.id, ident)?),
+                _ => return None,
+            };
+            if handles.contains_key(&handle) { Some(handle) } else { None }
+        };
+        let mut skipped = HashSet::new();
+        let skip = |skipped: &mut HashSet<ThreadHandle>, h: ThreadHandle, why: &str, span: Span| {
+            if skipped.insert(h) {
+                warn!("pthread_to_std_thread: skipping `{}`, which {} at {}", handles[&h],
+                      why, cx.session().source_map().span_to_string(span));
+            }
+        };
+
+        // (2) Find the calls to convert, and check that nothing else uses the handles.
+
+        let mut mcx = MatchCtxt::new(st, cx);
+        let create_pat = mcx.parse_expr("pthread_create(&mut $h:Expr, $attr:Expr, \
+                                         $f:Expr, $arg:Expr)");
+        let join_pat = mcx.parse_expr("pthread_join($h:Expr, $ret:Expr)");
+
+        let mut calls = Vec::new();
+        let mut allowed = HashSet::new();
+        visit_nodes(krate, |s: &Stmt| {
+            let e = match_or!([s.kind] StmtKind::Semi(ref e) => e; return);
+            if let Ok(m) = mcx.clone_match(&*create_pat, e) {
+                let place = m.bindings.get::<_, P<Expr>>("$h").unwrap();
+                let h = match_or!([handle_of(place)] Some(x) => x; return);
+                let attr = m.bindings.get::<_, P<Expr>>("$attr").unwrap();
+                let arg = m.bindings.get::<_, P<Expr>>("$arg").unwrap();
+                let f = m.bindings.get::<_, P<Expr>>("$f").unwrap();
+                if !is_null_ptr(attr) {
+                    skip(&mut skipped, h, "is created with thread attributes", e.span);
+                    return;
+                }
+                if let Some(pointee) = thread_arg_pointee(cx, arg) {
+                    if !is_send(cx, pointee) {
+                        skip(&mut skipped, h, &format!("is passed a pointer to `{}`, which is not \
+                                                        `Send`", pointee), arg.span);
+                        return;
+                    }
+                }
+                let (worker, worker_path) = match thread_worker(cx, f) {
+                    Some(x) => x,
+                    None => {
+                        skip(&mut skipped, h, "is created with an unknown function", f.span);
+                        return;
+                    }
+                };
+                let mut bnd = m.bindings.clone();
+                bnd.add("$worker", worker_path.clone());
+                allowed.insert(place.id);
+                calls.push(ThreadCall {
+                    handle: h,
+                    stmt: s.id,
+                    worker: Some((worker, worker_path.id)),
+                    bnd,
+                });
+            } else if let Ok(m) = mcx.clone_match(&*join_pat, e) {
+                let place = m.bindings.get::<_, P<Expr>>("$h").unwrap();
+                let h = match_or!([handle_of(place)] Some(x) => x; return);
+                let ret = m.bindings.get::<_, P<Expr>>("$ret").unwrap().clone();
+                let mut bnd = m.bindings.clone();
+                if !is_null_ptr(&ret) {
+                    match ret.kind {
+                        ExprKind::AddrOf(_, Mutability::Mutable, ref r) => bnd.add("$r", r.clone()),
+                        _ => {
+                            skip(&mut skipped, h, "is joined with an unsupported result pointer",
+                                 ret.span);
+                            return;
+                        }
+                    }
+                }
+                allowed.insert(place.id);
+                calls.push(ThreadCall { handle: h, stmt: s.id, worker: None, bnd });
+            }
+        });
+        visit_nodes(krate, |e: &Expr| {
+            if let Some(h) = handle_of(e) {
+                if !allowed.contains(&e.id) {
+                    skip(&mut skipped, h, "is used other than by creating or joining it", e.span);
+                }
+            }
+        });
+
+        calls.retain(|c| !skipped.contains(&c.handle));
+        let converted = handles.keys()
+            .filter(|h| !skipped.contains(h))
+            .cloned()
+            .collect::<HashSet<_>>();
+        if converted.is_empty() {
+            return;
+        }
+
+        // Workers whose every use is in a converted `pthread_create` become plain functions.
+        // Exported workers keep their C ABI symbol.
+        let worker_paths = calls.iter()
+            .filter_map(|c| c.worker.map(|w| w.1))
+            .collect::<HashSet<_>>();
+        let mut plain_workers = calls.iter()
+            .filter_map(|c| c.worker.map(|w| w.0))
+            .filter(|&def_id| !cx.is_exported_def(def_id))
+            .collect::<HashSet<_>>();
+        visit_nodes(krate, |e: &Expr| {
+            if let ExprKind::Path(..) = e.kind {
+                if let Some(def_id) = cx.try_resolve_expr(e) {
+                    if !worker_paths.contains(&e.id) {
+                        plain_workers.remove(&def_id);
+                    }
+                }
+            }
+        });
+
+        // (3) Rewrite the calls, and retype the handles.
+
+        let create_repl = mcx.parse_stmts(
+            "let thread_arg = $arg as usize;
+             $h = Some(::std::thread::spawn(move || $worker(thread_arg as _) as usize));");
+        let join_repl = mcx.parse_stmts("$h.take().unwrap().join().unwrap();");
+        let join_ret_repl = mcx.parse_stmts("$r = $h.take().unwrap().join().unwrap() as _;");
+
+        let new_stmts = calls.iter().map(|c| {
+            let repl = if c.worker.is_some() {
+                &create_repl
+            } else if c.bnd.get::<_, P<Expr>>("$r").is_some() {
+                &join_ret_repl
+            } else {
+                &join_repl
+            };
+            (c.stmt, repl.clone().subst(st, cx, &c.bnd))
+        }).collect::<HashMap<_, _>>();
+
+        MutVisitNodes::visit(krate, |b: &mut P<Block>| {
+            if !b.stmts.iter().any(|s| new_stmts.contains_key(&s.id)) {
+                return;
+            }
+            let old_stmts = mem::replace(&mut b.stmts, Vec::new());
+            for s in old_stmts {
+                match new_stmts.get(&s.id) {
+                    Some(new) => b.stmts.extend(new.iter().cloned()),
+                    None => b.stmts.push(s),
+                }
+            }
+        });
+
+        let handle_ty = driver::parse_ty(cx.session(),
+                                         "Option<::std::thread::JoinHandle<usize>>");
+        let none = driver::parse_expr(cx.session(), "None");
+
+        MutVisitNodes::visit(krate, |l: &mut P<Local>| {
+            let hir_id = match_or!([cx.hir_map().opt_node_to_hir_id(l.pat.id)] Some(x) => x;
+                                   return);
+            if converted.contains(&ThreadHandle::Local(hir_id)) {
+                l.ty = Some(handle_ty.clone());
+                l.init = Some(none.clone());
+            }
+        });
+        MutVisitNodes::visit(krate, |i: &mut P<Item>| {
+            if let ItemKind::Struct(VariantData::Struct(ref mut fields, _), _) = i.kind {
+                for f in fields.iter_mut() {
+                    if converted.contains(&ThreadHandle::Field(cx.node_def_id(f.id))) {
+                        f.ty = handle_ty.clone();
+                    }
+                }
+            }
+        });
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            let id = e.id;
+            if let ExprKind::Struct(_, ref mut fields, _) = e.kind {
+                for f in fields.iter_mut() {
+                    let did = match_or!([field_def(cx, id, f.ident)] Some(x) => x; continue);
+                    if converted.contains(&ThreadHandle::Field(did)) {
+                        f.expr = none.clone();
+                    }
+                }
+            }
+        });
+
+        MutVisitNodes::visit(krate, |i: &mut P<Item>| {
+            if let ItemKind::Fn(ref mut sig, _, _) = i.kind {
+                if plain_workers.contains(&cx.node_def_id(i.id)) {
+                    sig.header.ext = Extern::None;
+                    i.attrs.retain(|attr| !attr.check_name(sym::no_mangle));
+                }
+            }
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
+pub fn register_commands(reg: &mut Registry) {
+    use super::mk;
+
+    reg.register("pthread_to_std_sync", |args| mk(PthreadToStdSync {
+        protects: args.iter()
+            .find(|arg| arg.starts_with("protects="))
+            .map(|arg| arg["protects=".len()..].to_owned()),
+    }));
+
+    reg.register("pthread_to_std_thread", |_args| mk(PthreadToStdThread));
+}
@@ -0,0 +1,312 @@
+//! Transform that replaces the C clock functions with `std::time`.
+
+use std::collections::{HashMap, HashSet};
+use rustc::hir::HirId;
+use syntax::ast::*;
+use syntax::ptr::P;
+
+use c2rust_ast_builder::mk;
+use crate::ast_manip::{MutVisitNodes, visit_nodes};
+use crate::command::{CommandState, Registry};
+use crate::driver::Phase;
+use crate::matcher::{Bindings, MatchCtxt, Subst};
+use crate::transform::Transform;
+use crate::transform::assert::foreign_call;
+use crate::transform::canonicalize_refs::strip_parens;
+use crate::transform::enums::{is_simple_place, lit_value};
+use crate::transform::null_ptrs::is_null_ptr;
+use crate::RefactorCtxt;
+
+
+/// # `time_to_std` Command
+///
+/// Usage: `time_to_std`
+///
+/// Marks: `target`
+///
+/// Replace the marked calls of the C clock functions with `std::time`:
+///
+///  * `time(ptr::null_mut())` becomes
+///    `SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as libc::time_t`.
+///  * `gettimeofday(&mut tv, ptr::null_mut())` sets `tv.tv_sec` and
+///    `tv.tv_usec` from the same `Duration`.
+///  * `clock_gettime(CLOCK_REALTIME, &mut ts)` sets `ts.tv_sec` and
+///    `ts.tv_nsec` from it.
+///  * `clock_gettime(CLOCK_MONOTONIC, &mut ts)` becomes
+///    `ts = Instant::now()`, and the type of the local `ts` becomes `Instant`,
+///    when the only other uses of `ts` are differences of its fields from the
+///    same fields of another such local, like `end.tv_sec - start.tv_sec` and
+///    `end.tv_nsec - start.tv_nsec`.  These become
+///    `end.duration_since(start).as_secs() as libc::time_t` and
+///    `end.duration_since(start).subsec_nanos() as libc::c_long`.  Unlike the
+///    C difference of the nanoseconds, the new one is never negative, which
+///    only changes expressions that don't add it to the seconds before
+///    rounding.  Other monotonic clock reads are set from `SystemTime` like
+///    `CLOCK_REALTIME` ones, with a warning.
+///
+/// A call whose result is used becomes a block that evaluates to `0`, the
+/// result of a successful C call.  Calls passing a time zone to `gettimeofday`
+/// or a pointer to `time`, and `clock_gettime` calls reading other clocks, are
+/// skipped.
+pub struct TimeToStd;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Clock {
+    Realtime,
+    Monotonic,
+}
+
+/// Identify the clock named by the `clock_gettime` argument `e`.  The numbers are those of Linux,
+/// as the translator expands the `CLOCK_*` macros.
+fn clock_id(e: &Expr) -> Option<Clock> {
+    if let ExprKind::Path(None, ref path) = strip_parens(e).kind {
+        return match &*path.segments.last()?.ident.as_str() {
+            "CLOCK_REALTIME" => Some(Clock::Realtime),
+            "CLOCK_MONOTONIC" => Some(Clock::Monotonic),
+            _ => None,
+        };
+    }
+    match lit_value(e)? {
+        0 => Some(Clock::Realtime),
+        1 => Some(Clock::Monotonic),
+        _ => None,
+    }
+}
+
+/// The struct that the out-pointer `e` of `gettimeofday` or `clock_gettime` points to, as `x`
+/// for `&mut x` and as `(*p)` for a pointer `p`.
+fn out_place(e: &P<Expr>) -> Option<P<Expr>> {
+    match strip_parens(e).kind {
+        ExprKind::AddrOf(BorrowKind::Ref, Mutability::Mutable, ref place)
+            if is_simple_place(place) => Some(place.clone()),
+        ExprKind::Path(..) => Some(mk().paren_expr(mk().unary_expr("*", e.clone()))),
+        _ => None,
+    }
+}
+
+/// If `e` is `&mut x` for a local `x`, return the local.
+fn out_local(cx: &RefactorCtxt, e: &Expr) -> Option<HirId> {
+    match strip_parens(e).kind {
+        ExprKind::AddrOf(BorrowKind::Ref, Mutability::Mutable, ref place) => match place.kind {
+            ExprKind::Path(None, _) => cx.try_resolve_expr_to_hid(place),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// If `e` is a difference like `end.tv_sec - start.tv_sec` of the same field of two locals,
+/// return the locals and the field.
+fn field_diff(cx: &RefactorCtxt, e: &Expr) -> Option<(HirId, HirId, Ident)> {
+    let (l, r) = match e.kind {
+        ExprKind::Binary(op, ref l, ref r) if op.node == BinOpKind::Sub => (l, r),
+        _ => return None,
+    };
+    let local_field = |e: &Expr| match strip_parens(e).kind {
+        ExprKind::Field(ref base, field) if &*field.as_str() == "tv_sec" ||
+                                            &*field.as_str() == "tv_nsec" => match base.kind {
+            ExprKind::Path(None, _) => Some((cx.try_resolve_expr_to_hid(base)?, field)),
+            _ => None,
+        },
+        _ => None,
+    };
+    let (end, field) = local_field(l)?;
+    let (start, other_field) = local_field(r)?;
+    if field.name != other_field.name || end == start {
+        return None;
+    }
+    Some((end, start, field))
+}
+
+impl Transform for TimeToStd {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        let mut mcx = MatchCtxt::new(st, cx);
+        let time_repl = mcx.parse_expr(
+            "::std::time::SystemTime::now().duration_since(::std::time::UNIX_EPOCH).unwrap()
+                .as_secs() as libc::time_t");
+        let timeval_repl = mcx.parse_expr(r#"
+            {
+                let since_epoch = ::std::time::SystemTime::now()
+                    .duration_since(::std::time::UNIX_EPOCH).unwrap();
+                $tv.tv_sec = since_epoch.as_secs() as libc::time_t;
+                $tv.tv_usec = since_epoch.subsec_micros() as libc::suseconds_t;
+            }"#);
+        let timespec_repl = mcx.parse_expr(r#"
+            {
+                let since_epoch = ::std::time::SystemTime::now()
+                    .duration_since(::std::time::UNIX_EPOCH).unwrap();
+                $tv.tv_sec = since_epoch.as_secs() as libc::time_t;
+                $tv.tv_nsec = since_epoch.subsec_nanos() as libc::c_long;
+            }"#);
+        let instant_repl = mcx.parse_expr("$tv = ::std::time::Instant::now()");
+        let instant_ty = mcx.parse_ty("::std::time::Instant");
+        let now_repl = mcx.parse_expr("::std::time::Instant::now()");
+        let zero = mcx.parse_expr("0");
+        let secs_repl = mcx.parse_expr(
+            "$end.duration_since($start).as_secs() as libc::time_t");
+        let nanos_repl = mcx.parse_expr(
+            "$end.duration_since($start).subsec_nanos() as libc::c_long");
+
+        let subst = |repl: &P<Expr>, args: Vec<(&str, P<Expr>)>| {
+            let mut bnd = Bindings::new();
+            for (name, e) in args {
+                bnd.add(name, e);
+            }
+            repl.clone().subst(st, cx, &bnd)
+        };
+        let warn_skip = |why: &str, e: &Expr| {
+            warn!("time_to_std: skipping call that {} at {}", why,
+                  cx.session().source_map().span_to_string(e.span));
+        };
+
+        // (1) Find the locals that only hold monotonic clock reads and take their differences.
+
+        let mut calls: HashMap<HirId, usize> = HashMap::new();
+        let mut diffs = Vec::new();
+        visit_nodes(krate, |e: &Expr| {
+            if st.marked(e.id, "target") {
+                if let Some(args) = foreign_call(cx, e, "clock_gettime") {
+                    if args.len() == 2 && clock_id(&args[0]) == Some(Clock::Monotonic) {
+                        if let Some(hir_id) = out_local(cx, &args[1]) {
+                            *calls.entry(hir_id).or_insert(0) += 1;
+                        }
+                    }
+                }
+            }
+            if let Some((end, start, _)) = field_diff(cx, e) {
+                diffs.push((end, start));
+            }
+        });
+
+        let mut uses: HashMap<HirId, usize> = HashMap::new();
+        visit_nodes(krate, |e: &Expr| {
+            if let ExprKind::Path(None, _) = e.kind {
+                if let Some(hir_id) = cx.try_resolve_expr_to_hid(e) {
+                    if calls.contains_key(&hir_id) {
+                        *uses.entry(hir_id).or_insert(0) += 1;
+                    }
+                }
+            }
+        });
+
+        let mut instants = calls.keys().cloned().collect::<HashSet<_>>();
+        loop {
+            let mut expected = calls.clone();
+            for &(end, start) in &diffs {
+                if instants.contains(&end) && instants.contains(&start) {
+                    *expected.get_mut(&end).unwrap() += 1;
+                    *expected.get_mut(&start).unwrap() += 1;
+                }
+            }
+            let before = instants.len();
+            instants.retain(|hir_id| uses.get(hir_id) == expected.get(hir_id));
+            if instants.len() == before {
+                break;
+            }
+        }
+
+        // (2) Rewrite the calls, and the differences of the monotonic clock reads.
+
+        let is_instant = |e: &Expr| out_local(cx, e).map_or(false, |id| instants.contains(&id));
+        // The replacement of a `gettimeofday` or `clock_gettime` call, without its result, or why
+        // the call is skipped.
+        let fill_repl = |e: &Expr| -> Result<P<Expr>, Option<&str>> {
+            if !st.marked(e.id, "target") {
+                return Err(None);
+            }
+            if let Some(args) = foreign_call(cx, e, "gettimeofday") {
+                if args.len() != 2 || !is_null_ptr(&args[1]) {
+                    return Err(Some("passes a time zone"));
+                }
+                let tv = out_place(&args[0]).ok_or(Some("passes an unsupported pointer"))?;
+                return Ok(subst(&timeval_repl, vec![("$tv", tv)]));
+            }
+            let args = foreign_call(cx, e, "clock_gettime").ok_or(None)?;
+            if args.len() != 2 {
+                return Err(None);
+            }
+            let clock = clock_id(&args[0]).ok_or(Some("reads an unknown clock"))?;
+            let tv = out_place(&args[1]).ok_or(Some("passes an unsupported pointer"))?;
+            if clock == Clock::Monotonic {
+                if is_instant(&args[1]) {
+                    return Ok(subst(&instant_repl, vec![("$tv", tv)]));
+                }
+                warn!("time_to_std: reading the system clock for a monotonic clock read at {}, \
+                       as its result is used for more than differences",
+                      cx.session().source_map().span_to_string(e.span));
+            }
+            Ok(subst(&timespec_repl, vec![("$tv", tv)]))
+        };
+
+        MutVisitNodes::visit(krate, |b: &mut P<Block>| {
+            for s in &mut b.stmts {
+                let new_e = match_or!([s.kind] StmtKind::Semi(ref e) => fill_repl(e); continue);
+                // Skipped calls are reported along with the other calls.
+                let new_e = match_or!([new_e] Ok(x) => x; continue);
+                s.kind = match new_e.kind {
+                    ExprKind::Block(..) => StmtKind::Expr(new_e),
+                    _ => StmtKind::Semi(new_e),
+                };
+            }
+        });
+
+        MutVisitNodes::visit(krate, |l: &mut P<Local>| {
+            if !instants.contains(&cx.hir_map().node_to_hir_id(l.pat.id)) {
+                return;
+            }
+            l.ty = Some(instant_ty.clone());
+            if l.init.is_some() {
+                l.init = Some(now_repl.clone());
+            }
+        });
+
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            if let Some((end, start, field)) = field_diff(cx, e) {
+                if !instants.contains(&end) || !instants.contains(&start) {
+                    return;
+                }
+                let (l, r) = expect!([e.kind] ExprKind::Binary(_, ref l, ref r) => (l, r));
+                let base = |e: &P<Expr>| expect!([strip_parens(e).kind]
+                                                 ExprKind::Field(ref base, _) => base.clone());
+                let repl = if &*field.as_str() == "tv_sec" { &secs_repl } else { &nanos_repl };
+                *e = subst(repl, vec![("$end", base(l)), ("$start", base(r))]);
+                return;
+            }
+
+            if !st.marked(e.id, "target") {
+                return;
+            }
+            if let Some(args) = foreign_call(cx, e, "time") {
+                if args.len() != 1 || !is_null_ptr(&args[0]) {
+                    return warn_skip("stores the time through a pointer", e);
+                }
+                *e = subst(&time_repl, vec![]);
+                return;
+            }
+            match fill_repl(e) {
+                Ok(new_e) => {
+                    // Keep the result of the C call, which succeeds.
+                    let mut stmts = match new_e.kind {
+                        ExprKind::Block(ref b, None) => b.stmts.clone(),
+                        _ => vec![mk().semi_stmt(new_e.clone())],
+                    };
+                    stmts.push(mk().expr_stmt(zero.clone()));
+                    *e = mk().block_expr(mk().block(stmts));
+                }
+                Err(Some(why)) => warn_skip(why, e),
+                Err(None) => {}
+            }
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
+pub fn register_commands(reg: &mut Registry) {
+    use super::mk;
+
+    reg.register("time_to_std", |_args| mk(TimeToStd));
+}
@@ -1,16 +1,20 @@
 use std::collections::hash_map::{HashMap, Entry};
 use std::collections::HashSet;
+use std::cmp::Reverse;
 use std::mem;
+use rustc::hir::def::{DefKind, Res};
 use rustc::hir::def_id::LOCAL_CRATE;
 use rustc::hir::HirId;
 use rustc::ty::{TyKind, ParamEnv};
 use syntax::ast::*;
 use syntax::mut_visit::{self, MutVisitor};
+use syntax::print::pprust;
 use syntax::ptr::P;
 use syntax::visit::{self, Visitor};
 
 use c2rust_ast_builder::mk;
-use crate::ast_manip::{MutVisit, MutVisitNodes, fold_blocks, visit_nodes};
+use crate::ast_manip::{MutVisit, MutVisitNodes, fold_blocks, lr_expr, visit_nodes};
+use crate::ast_manip::fn_edit::mut_visit_fns;
 use crate::command::{CommandState, DriverCommand, Registry};
 use crate::driver::{Phase};
 use crate::matcher::{MatchCtxt, Subst, mut_visit_match_with, replace_stmts};
@@ -545,6 +549,399 @@ fn expand_local_ptr_tys(st: &CommandState, cx: &RefactorCtxt) {
     st.map_krate(|krate| { krate.visit(&mut LocalVisitor { cx }) });
 }
 
+/// # `hoist_subexprs` Command
+///
+/// Usage: `hoist_subexprs [min=N]`
+///
+/// Marks: `target`
+///
+/// In each function marked `target`, bind repeated subexpressions to locals.  When
+/// an expression that reads memory (through a dereference, an index, or a pointer
+/// method like `offset`) and produces a number or a pointer occurs at least `N`
+/// times (default 2) in the statements of a block, a `let NAME: T = EXPR;` is
+/// added before the first statement using it, and each occurrence is replaced with
+/// `NAME`.  The local is named after the field, variable or method the expression
+/// ends in.  Its type is the target type of the expression's outermost cast, if
+/// there is one, and the type from typeck otherwise.
+///
+/// Only occurrences that are always evaluated count: those in the condition of an
+/// `if` or the left operand of `&&` do, but those in nested blocks and branches
+/// don't.  The largest repeated expressions are hoisted first.  An expression is
+/// left alone, with a warning, if the statements from its first use to its last
+/// may modify what it reads: by assigning to or mutably borrowing a variable it
+/// uses, by writing through a pointer when it dereferences one, or by calling a
+/// function, unless the call only happens after the last use.
+pub struct HoistSubexprs {
+    pub min: usize,
+}
+
+/// Methods that compute their result from their receiver and arguments alone.
+const PURE_METHODS: &[&str] = &[
+    "as_ptr", "as_mut_ptr", "offset", "wrapping_offset", "add", "sub", "is_null", "len",
+    "wrapping_add", "wrapping_sub", "wrapping_mul", "abs",
+];
+
+fn is_pure_method(seg: &PathSegment) -> bool {
+    PURE_METHODS.contains(&&*seg.ident.as_str())
+}
+
+/// Resolve the path expression `e`, if it's one that was in the crate when it was typechecked.
+fn resolve_path_expr(cx: &RefactorCtxt, e: &Expr) -> Option<Res> {
+    match e.kind {
+        ExprKind::Path(..) if e.id != DUMMY_NODE_ID => cx.try_resolve_expr_hir(e),
+        _ => None,
+    }
+}
+
+/// Check that evaluating `e` has no side effects, so evaluating it once gives the same
+/// result as evaluating it several times in a row.
+pub(super) fn is_pure_expr(cx: &RefactorCtxt, e: &Expr) -> bool {
+    match e.kind {
+        ExprKind::Lit(_) => true,
+        ExprKind::Path(..) => match resolve_path_expr(cx, e) {
+            Some(Res::Local(_)) |
+            Some(Res::Def(DefKind::Static, _)) |
+            Some(Res::Def(DefKind::Const, _)) => true,
+            _ => false,
+        },
+        ExprKind::Paren(ref e) |
+        ExprKind::Cast(ref e, _) |
+        ExprKind::Field(ref e, _) |
+        ExprKind::Unary(_, ref e) => is_pure_expr(cx, e),
+        ExprKind::Binary(_, ref a, ref b) |
+        ExprKind::Index(ref a, ref b) => is_pure_expr(cx, a) && is_pure_expr(cx, b),
+        ExprKind::MethodCall(ref seg, ref args) => {
+            is_pure_method(seg) && args.iter().all(|a| is_pure_expr(cx, a))
+        }
+        _ => false,
+    }
+}
+
+/// Collect `e` and its subexpressions that are evaluated whenever `e` is.
+fn collect_unconditional<'a>(e: &'a P<Expr>, out: &mut Vec<&'a P<Expr>>) {
+    out.push(e);
+    match e.kind {
+        ExprKind::Binary(op, ref a, ref b) => {
+            collect_unconditional(a, out);
+            match op.node {
+                BinOpKind::And | BinOpKind::Or => {}
+                _ => collect_unconditional(b, out),
+            }
+        }
+        ExprKind::Index(ref a, ref b) |
+        ExprKind::Assign(ref a, ref b) |
+        ExprKind::AssignOp(_, ref a, ref b) => {
+            collect_unconditional(a, out);
+            collect_unconditional(b, out);
+        }
+        ExprKind::Call(ref f, ref args) => {
+            collect_unconditional(f, out);
+            for a in args {
+                collect_unconditional(a, out);
+            }
+        }
+        ExprKind::MethodCall(_, ref args) |
+        ExprKind::Array(ref args) |
+        ExprKind::Tup(ref args) => {
+            for a in args {
+                collect_unconditional(a, out);
+            }
+        }
+        ExprKind::Struct(_, ref fields, ref base) => {
+            for f in fields {
+                collect_unconditional(&f.expr, out);
+            }
+            if let Some(ref base) = *base {
+                collect_unconditional(base, out);
+            }
+        }
+        ExprKind::Paren(ref e) |
+        ExprKind::Cast(ref e, _) |
+        ExprKind::Type(ref e, _) |
+        ExprKind::Field(ref e, _) |
+        ExprKind::Unary(_, ref e) |
+        ExprKind::AddrOf(_, _, ref e) |
+        ExprKind::If(ref e, _, _) |
+        ExprKind::Match(ref e, _) |
+        ExprKind::ForLoop(_, ref e, _, _) |
+        ExprKind::Ret(Some(ref e)) => collect_unconditional(e, out),
+        _ => {}
+    }
+}
+
+/// A name for a local holding the value of `e`.
+fn hoisted_name(e: &Expr) -> String {
+    match e.kind {
+        ExprKind::Paren(ref e) |
+        ExprKind::Cast(ref e, _) |
+        ExprKind::Unary(_, ref e) |
+        ExprKind::Index(ref e, _) => hoisted_name(e),
+        ExprKind::Field(_, ident) => ident.to_string(),
+        ExprKind::Path(None, ref path) => path.segments.last().unwrap().ident.to_string(),
+        ExprKind::MethodCall(ref seg, ref args) => {
+            if is_pure_method(seg) && &*seg.ident.as_str() != "len" {
+                hoisted_name(&args[0])
+            } else {
+                seg.ident.to_string()
+            }
+        }
+        _ => "value".to_owned(),
+    }
+}
+
+/// The place expression `e` writes to, and whether it's behind a dereference.
+fn written_root(e: &Expr) -> (&Expr, bool) {
+    match e.kind {
+        ExprKind::Field(ref base, _) |
+        ExprKind::Paren(ref base) |
+        ExprKind::Index(ref base, _) => written_root(base),
+        ExprKind::Unary(UnOp::Deref, ref base) => (written_root(base).0, true),
+        _ => (e, false),
+    }
+}
+
+/// A subexpression that occurs several times in a block.
+struct Repeated<'a> {
+    /// The first occurrence.
+    expr: &'a P<Expr>,
+    /// The statement index and ID of each occurrence.
+    uses: Vec<(usize, NodeId)>,
+}
+
+impl HoistSubexprs {
+    /// Hoist one repeated subexpression out of the statements of `block`.  Returns `false`
+    /// if there was nothing to hoist.
+    fn hoist_one(
+        &self,
+        block: &mut P<Block>,
+        used_names: &mut HashSet<String>,
+        warned: &mut Vec<String>,
+        cx: &RefactorCtxt,
+    ) -> bool {
+        let tcx = cx.ty_ctxt();
+
+        let mut not_rvalue = HashSet::new();
+        let mut lvalue_mut = HashSet::new();
+        let mut block_copy = block.clone();
+        lr_expr::fold_exprs_with_context(&mut block_copy, |e, ctx| {
+            match ctx {
+                lr_expr::Context::Rvalue => {}
+                lr_expr::Context::LvalueMut => {
+                    not_rvalue.insert(e.id);
+                    lvalue_mut.insert(e.id);
+                }
+                _ => {
+                    not_rvalue.insert(e.id);
+                }
+            }
+        });
+
+        let mut repeated: HashMap<String, Repeated> = HashMap::new();
+        let mut order = vec![];
+        for (idx, s) in block.stmts.iter().enumerate() {
+            let e = match s.kind {
+                StmtKind::Local(ref l) => match l.init {
+                    Some(ref e) => e,
+                    None => continue,
+                },
+                StmtKind::Expr(ref e) | StmtKind::Semi(ref e) => e,
+                _ => continue,
+            };
+            let mut exprs = vec![];
+            collect_unconditional(e, &mut exprs);
+            for e in exprs {
+                if e.id == DUMMY_NODE_ID || not_rvalue.contains(&e.id) {
+                    continue;
+                }
+                let key = pprust::expr_to_string(e);
+                match repeated.entry(key) {
+                    Entry::Occupied(mut o) => o.get_mut().uses.push((idx, e.id)),
+                    Entry::Vacant(v) => {
+                        order.push(v.key().clone());
+                        v.insert(Repeated { expr: e, uses: vec![(idx, e.id)] });
+                    }
+                }
+            }
+        }
+
+        // Try the largest candidates first.
+        let mut candidates = order.into_iter()
+            .filter(|k| repeated[k].uses.len() >= self.min)
+            .collect::<Vec<_>>();
+        candidates.sort_by_key(|k| Reverse(k.len()));
+
+        for key in candidates {
+            let rep = &repeated[&key];
+            let e = rep.expr;
+            let scalar = cx.opt_node_type(e.id).map_or(false, |ty| match ty.kind {
+                TyKind::Bool | TyKind::Char | TyKind::Int(_) | TyKind::Uint(_) |
+                TyKind::Float(_) | TyKind::RawPtr(_) => true,
+                _ => false,
+            });
+            let mut reads_memory = false;
+            let mut derefs = false;
+            let mut names = HashSet::new();
+            let mut roots = HashSet::new();
+            visit_nodes(&**e, |e: &Expr| match e.kind {
+                ExprKind::Unary(UnOp::Deref, _) => {
+                    reads_memory = true;
+                    derefs = true;
+                }
+                ExprKind::Index(..) | ExprKind::MethodCall(..) => reads_memory = true,
+                ExprKind::Path(_, ref path) => {
+                    names.insert(path.segments.last().unwrap().ident.name);
+                    roots.extend(resolve_path_expr(cx, e));
+                }
+                _ => {}
+            });
+            if !scalar || !reads_memory || !is_pure_expr(cx, e) {
+                continue;
+            }
+            let first = rep.uses[0].0;
+            let last = rep.uses[rep.uses.len() - 1].0;
+
+            // Locals declared before the last use must not shadow a variable the
+            // expression uses.
+            let shadowed = block.stmts[first..last].iter().any(|s| match s.kind {
+                StmtKind::Local(ref l) => {
+                    let mut found = false;
+                    visit_nodes(&*l.pat, |p: &Pat| {
+                        if let PatKind::Ident(_, ident, _) = p.kind {
+                            found |= names.contains(&ident.name);
+                        }
+                    });
+                    found
+                }
+                _ => false,
+            });
+            if shadowed {
+                continue;
+            }
+
+            // Nothing from the first use to the last may modify what the expression reads.
+            // Calls and assignments in the last statement are fine if they enclose all the uses
+            // there, since they only happen afterwards.
+            let last_uses = rep.uses.iter()
+                .filter(|&&(idx, _)| idx == last)
+                .map(|&(_, id)| id)
+                .collect::<HashSet<_>>();
+            let after_last_uses = |e: &Expr| {
+                let mut found = 0;
+                visit_nodes(e, |e: &Expr| {
+                    if last_uses.contains(&e.id) {
+                        found += 1;
+                    }
+                });
+                found == last_uses.len()
+            };
+            let mut modified = false;
+            for (idx, s) in block.stmts.iter().enumerate().take(last + 1).skip(first) {
+                let mut after = HashSet::new();
+                visit_nodes(s, |w: &Expr| {
+                    let calls = match w.kind {
+                        ExprKind::Call(..) | ExprKind::Mac(..) => true,
+                        ExprKind::MethodCall(ref seg, _) => !is_pure_method(seg),
+                        _ => false,
+                    };
+                    if calls && !(idx == last && after_last_uses(w)) {
+                        modified = true;
+                    }
+                    match w.kind {
+                        ExprKind::Assign(ref lhs, ref rhs) |
+                        ExprKind::AssignOp(_, ref lhs, ref rhs)
+                                if idx == last && after_last_uses(rhs) => {
+                            visit_nodes(&**lhs, |l: &Expr| {
+                                after.insert(l.id);
+                            });
+                        }
+                        _ => {}
+                    }
+                });
+                visit_nodes(s, |w: &Expr| {
+                    if !lvalue_mut.contains(&w.id) || after.contains(&w.id) {
+                        return;
+                    }
+                    let (root, behind_ptr) = written_root(w);
+                    if (behind_ptr && derefs) ||
+                       resolve_path_expr(cx, root).map_or(false, |r| roots.contains(&r)) {
+                        modified = true;
+                    }
+                });
+            }
+            if modified {
+                if !warned.iter().any(|w| w.contains(&key)) {
+                    warn!("hoist_subexprs: `{}` may be modified between its uses; \
+                           not hoisting it", key);
+                    warned.push(key);
+                }
+                continue;
+            }
+
+            // Hoist it.
+            let base_name = hoisted_name(e);
+            let mut name = base_name.clone();
+            let mut n = 2;
+            while used_names.contains(&name) {
+                name = format!("{}_{}", base_name, n);
+                n += 1;
+            }
+            used_names.insert(name.clone());
+            let ty = match e.kind {
+                ExprKind::Cast(_, ref ty) => ty.clone(),
+                _ => reflect_tcx_ty(tcx, cx.node_type(e.id)),
+            };
+            let local = mk().local(mk().ident_pat(&name), Some(ty), Some(e.clone()));
+            let stmt = mk().local_stmt(P(local));
+            let uses = rep.uses.iter().map(|&(_, id)| id).collect::<HashSet<_>>();
+            MutVisitNodes::visit(block, |e: &mut P<Expr>| {
+                if uses.contains(&e.id) {
+                    *e = mk().ident_expr(&name);
+                }
+            });
+            block.stmts.insert(first, stmt);
+            return true;
+        }
+        false
+    }
+}
+
+impl Transform for HoistSubexprs {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        mut_visit_fns(krate, |fl| {
+            if !st.marked(fl.id, "target") {
+                return;
+            }
+            let mut used_names = HashSet::new();
+            let mut add_pat_names = |p: &Pat| {
+                if let PatKind::Ident(_, ident, _) = p.kind {
+                    used_names.insert(ident.to_string());
+                }
+            };
+            for param in &fl.decl.inputs {
+                visit_nodes(&*param.pat, &mut add_pat_names);
+            }
+            if let Some(ref block) = fl.block {
+                visit_nodes(&**block, &mut add_pat_names);
+                visit_nodes(&**block, |e: &Expr| {
+                    if let ExprKind::Path(None, ref path) = e.kind {
+                        used_names.insert(path.segments.last().unwrap().ident.to_string());
+                    }
+                });
+            }
+
+            let mut warned = vec![];
+            MutVisitNodes::visit(&mut fl.block, |b: &mut P<Block>| {
+                while self.hoist_one(b, &mut used_names, &mut warned, cx) {}
+            });
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
 pub fn register_commands(reg: &mut Registry) {
     use super::mk;
 
@@ -553,6 +950,12 @@ pub fn register_commands(reg: &mut Registry) {
     reg.register("fold_let_assign", |_args| mk(FoldLetAssign));
     reg.register("uninit_to_default", |_args| mk(UninitToDefault));
     reg.register("remove_redundant_let_types", |_args| mk(RemoveRedundantLetTypes));
+    reg.register("hoist_subexprs", |args| mk(HoistSubexprs {
+        min: args.iter()
+            .find(|arg| arg.starts_with("min="))
+            .map_or(2, |arg| arg["min=".len()..].parse()
+                .unwrap_or_else(|_| panic!("hoist_subexprs: bad threshold `{}`", arg))),
+    }));
     reg.register("expand_local_ptr_tys", |_args| {
         Box::new(DriverCommand::new(Phase::Phase3, move |st, cx| {
             expand_local_ptr_tys(st, cx);
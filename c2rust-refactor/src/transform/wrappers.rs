@@ -0,0 +1,337 @@
+//! Transform that generates safe wrappers for `extern "C"` functions.
+
+use std::collections::HashMap;
+use syntax::ast::*;
+use syntax::print::pprust;
+
+use crate::ast_manip::fold_modules;
+use crate::command::{CommandState, Registry};
+use crate::driver::Phase;
+use crate::transform::Transform;
+use crate::RefactorCtxt;
+
+
+/// # `safe_wrappers` Command
+///
+/// Usage: `safe_wrappers [suffix=SUFFIX | module=NAME] [errors=negative|nonzero]
+/// [FUNC.PARAM=KIND ...]`
+///
+/// Marks: `target`
+///
+/// For each marked `extern` function `foo`, add a safe function that converts
+/// its arguments to the raw ones, calls `foo` in an `unsafe` block, and
+/// converts its result back.  `foo` itself is left as it is, so C callers keep
+/// using it.  The wrapper is named `foo_safe`, or `fooSUFFIX` with
+/// `suffix=SUFFIX`, and is added right after `foo`.  With `module=NAME`, the
+/// wrappers keep the names of the functions they wrap, and go in a new `pub mod
+/// NAME` at the end of the module of the functions, which glob-imports its
+/// parent.
+///
+/// Each parameter of `foo` is converted according to its kind:
+///
+///  * `str`: the wrapper takes a `&str`, and passes a NUL-terminated copy of
+///    it.  The wrapper panics if the string contains a NUL byte.
+///  * `slice:LEN`: for a parameter `*const T` or `*mut T`, the wrapper takes a
+///    `&[T]` or `&mut [T]`, and passes its pointer, along with its length in
+///    the integer parameter `LEN`, which the wrapper doesn't take.
+///  * `opt`: for a parameter `*const T` or `*mut T`, the wrapper takes an
+///    `Option<&T>` or `Option<&mut T>`, and passes `None` as a null pointer.
+///  * `raw`: the wrapper takes and passes the parameter as it is.
+///
+/// The kind of the parameter `PARAM` of `FUNC` is given by `FUNC.PARAM=KIND`.
+/// A pointer followed by an integer parameter whose name mentions `len`,
+/// `size` or `count`, or is `n`, is a `slice` by default.  Otherwise, a
+/// `*const c_char` is a `str`, and other pointers are `opt`s.  Everything
+/// else is `raw`.
+///
+/// A pointer result is returned as an `Option<NonNull<T>>`, which is `None`
+/// for a null pointer.  With `errors=negative`, an integer result is returned
+/// as a `Result`, which is an `Err` with the result if the result is
+/// negative, or else `Ok` with the result.  With `errors=nonzero`, an integer
+/// result is returned as a `Result<(), T>`, which is an `Err` with the result
+/// if the result isn't zero.
+pub struct SafeWrappers {
+    pub placement: WrapperPlacement,
+    pub errors: Option<ErrorConvention>,
+    /// The kinds of the parameters, by function and parameter name.
+    pub param_kinds: HashMap<(String, String), String>,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum WrapperPlacement {
+    Suffix(String),
+    Module(String),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ErrorConvention {
+    Negative,
+    Nonzero,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum ParamKind {
+    Raw,
+    Str,
+    /// The pointer of a slice, whose length is passed in the parameter with the given index.
+    Slice(usize),
+    Opt,
+    /// The length of a slice, which the wrapper doesn't take.
+    Len,
+}
+
+fn is_len_name(name: &str) -> bool {
+    name == "n" || name.contains("len") || name.contains("size") || name.contains("count")
+}
+
+/// If `ty` is a pointer, the pointee type and mutability.
+fn ptr_ty(ty: &Ty) -> Option<(&Ty, Mutability)> {
+    match ty.kind {
+        TyKind::Ptr(ref mt) => Some((&mt.ty, mt.mutbl)),
+        _ => None,
+    }
+}
+
+fn is_c_char(ty: &Ty) -> bool {
+    match ty.kind {
+        TyKind::Path(None, ref path) =>
+            path.segments.last().map_or(false, |seg| &*seg.ident.as_str() == "c_char"),
+        _ => false,
+    }
+}
+
+impl SafeWrappers {
+    /// Build the source of the wrapper of `i`, or return `None` if it can't be wrapped.
+    fn build_wrapper(&self, i: &Item, cx: &RefactorCtxt) -> Option<String> {
+        let (sig, generics) = match i.kind {
+            ItemKind::Fn(ref sig, ref generics, _) => (sig, generics),
+            _ => return None,
+        };
+        let func = i.ident.as_str();
+        if let Extern::None = sig.header.ext {
+            warn!("safe_wrappers: `{}` isn't an extern function; skipping", func);
+            return None;
+        }
+        if !generics.params.is_empty() || sig.decl.c_variadic() {
+            warn!("safe_wrappers: can't wrap `{}`, which is generic or variadic", func);
+            return None;
+        }
+        let fn_sig = cx.ty_ctxt().fn_sig(cx.node_def_id(i.id));
+        let input_tys = fn_sig.skip_binder().inputs();
+
+        let names = sig.decl.inputs.iter().enumerate().map(|(idx, arg)| match arg.pat.kind {
+            PatKind::Ident(_, ident, _) => ident.to_string(),
+            _ => format!("arg{}", idx),
+        }).collect::<Vec<_>>();
+        let is_len = |idx: usize| idx < names.len() && input_tys[idx].is_integral();
+
+        // (1) Work out the kind of each parameter.
+
+        let mut kinds = vec![ParamKind::Raw; names.len()];
+        for (idx, arg) in sig.decl.inputs.iter().enumerate() {
+            if kinds[idx] == ParamKind::Len {
+                continue;
+            }
+            let annotation = self.param_kinds.get(&(func.to_string(), names[idx].clone()));
+            let kind = match annotation.map(|s| &s[..]) {
+                Some("raw") => ParamKind::Raw,
+                Some("str") => ParamKind::Str,
+                Some("opt") => ParamKind::Opt,
+                Some(kind) if kind.starts_with("slice:") => {
+                    let len = &kind["slice:".len()..];
+                    match names.iter().position(|name| name == len) {
+                        Some(len_idx) if is_len(len_idx) && len_idx != idx => {
+                            ParamKind::Slice(len_idx)
+                        }
+                        _ => {
+                            warn!("safe_wrappers: `{}` has no integer parameter `{}`; skipping",
+                                  func, len);
+                            return None;
+                        }
+                    }
+                }
+                Some(kind) => panic!("safe_wrappers: unknown parameter kind `{}`", kind),
+                None => match ptr_ty(&arg.ty) {
+                    None => ParamKind::Raw,
+                    Some(_) if is_len(idx + 1) && is_len_name(&names[idx + 1]) => {
+                        ParamKind::Slice(idx + 1)
+                    }
+                    Some((pointee, Mutability::Immutable)) if is_c_char(pointee) => {
+                        ParamKind::Str
+                    }
+                    Some(_) => ParamKind::Opt,
+                },
+            };
+            if kind != ParamKind::Raw && ptr_ty(&arg.ty).is_none() {
+                warn!("safe_wrappers: parameter `{}` of `{}` isn't a pointer; skipping",
+                      names[idx], func);
+                return None;
+            }
+            if let ParamKind::Slice(len_idx) = kind {
+                kinds[len_idx] = ParamKind::Len;
+            }
+            kinds[idx] = kind;
+        }
+
+        // (2) Build the parameters of the wrapper, the conversions of its arguments, and the
+        // arguments of the call.
+
+        let mut params = Vec::new();
+        let mut lets = String::new();
+        let mut args = vec![String::new(); names.len()];
+        for (idx, arg) in sig.decl.inputs.iter().enumerate() {
+            let name = &names[idx];
+            let ty = pprust::ty_to_string(&arg.ty);
+            match kinds[idx] {
+                ParamKind::Raw => {
+                    params.push(format!("{}: {}", name, ty));
+                    args[idx] = name.clone();
+                }
+                ParamKind::Str => {
+                    params.push(format!("{}: &str", name));
+                    lets.push_str(&format!(
+                        "let {0} = ::std::ffi::CString::new({0})\
+                            .expect(\"`{0}` contains a NUL byte\");\n", name));
+                    args[idx] = format!("{}.as_ptr() as {}", name, ty);
+                }
+                ParamKind::Slice(len_idx) => {
+                    let (pointee, mutbl) = ptr_ty(&arg.ty).unwrap();
+                    let pointee = pprust::ty_to_string(pointee);
+                    let len_ty = pprust::ty_to_string(&sig.decl.inputs[len_idx].ty);
+                    let (ref_, as_ptr) = match mutbl {
+                        Mutability::Mutable => ("&mut ", "as_mut_ptr"),
+                        Mutability::Immutable => ("&", "as_ptr"),
+                    };
+                    params.push(format!("{}: {}[{}]", name, ref_, pointee));
+                    args[idx] = format!("{}.{}()", name, as_ptr);
+                    args[len_idx] = format!("{}.len() as {}", name, len_ty);
+                }
+                ParamKind::Opt => {
+                    let (pointee, mutbl) = ptr_ty(&arg.ty).unwrap();
+                    let pointee = pprust::ty_to_string(pointee);
+                    let (ref_, null, ptr) = match mutbl {
+                        Mutability::Mutable => ("&mut ", "null_mut", "*mut _"),
+                        Mutability::Immutable => ("&", "null", "*const _"),
+                    };
+                    params.push(format!("{}: Option<{}{}>", name, ref_, pointee));
+                    args[idx] = format!("{}.map_or(::std::ptr::{}(), |x| x as {})",
+                                        name, null, ptr);
+                }
+                ParamKind::Len => {}
+            }
+        }
+
+        // (3) Build the conversion of the result.
+
+        let ret_ty = match sig.decl.output {
+            FunctionRetTy::Ty(ref ty) => Some(&**ty),
+            FunctionRetTy::Default(_) => None,
+        };
+        let ret_is_int = fn_sig.skip_binder().output().is_integral();
+        let (wrapper_ret, convert) = match ret_ty {
+            None => (String::new(), "ret".to_owned()),
+            Some(ty) => {
+                let ty_str = pprust::ty_to_string(ty);
+                match (ptr_ty(ty), self.errors) {
+                    (Some((pointee, _)), _) => (
+                        format!(" -> Option<::std::ptr::NonNull<{}>>",
+                                pprust::ty_to_string(pointee)),
+                        "::std::ptr::NonNull::new(ret as *mut _)".to_owned(),
+                    ),
+                    (None, Some(ErrorConvention::Negative)) if ret_is_int => (
+                        format!(" -> Result<{0}, {0}>", ty_str),
+                        "if ret < 0 { Err(ret) } else { Ok(ret) }".to_owned(),
+                    ),
+                    (None, Some(ErrorConvention::Nonzero)) if ret_is_int => (
+                        format!(" -> Result<(), {}>", ty_str),
+                        "if ret != 0 { Err(ret) } else { Ok(()) }".to_owned(),
+                    ),
+                    (None, _) => (format!(" -> {}", ty_str), "ret".to_owned()),
+                }
+            }
+        };
+
+        let (wrapper_name, callee) = match self.placement {
+            WrapperPlacement::Suffix(ref suffix) => {
+                (format!("{}{}", func, suffix), func.to_string())
+            }
+            WrapperPlacement::Module(_) => (func.to_string(), format!("super::{}", func)),
+        };
+        let call = format!("unsafe {{ {}({}) }}", callee,
+                           args.into_iter().filter(|arg| !arg.is_empty())
+                               .collect::<Vec<_>>().join(", "));
+        let body = if convert == "ret" {
+            call
+        } else {
+            format!("let ret = {};\n{}", call, convert)
+        };
+        Some(format!("{}fn {}({}){} {{\n{}{}\n}}",
+                     pprust::vis_to_string(&i.vis), wrapper_name, params.join(", "),
+                     wrapper_ret, lets, body))
+    }
+}
+
+impl Transform for SafeWrappers {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        fold_modules(krate, |curs| {
+            let mut module_wrappers = Vec::new();
+            while !curs.eof() {
+                if !st.marked(curs.next().id, "target") {
+                    curs.advance();
+                    continue;
+                }
+                let src = match_or!([self.build_wrapper(curs.next(), cx)] Some(x) => x;
+                                    { curs.advance(); continue });
+                curs.advance();
+                match self.placement {
+                    WrapperPlacement::Suffix(_) => curs.insert_multi(st.parse_items(cx, &src)),
+                    WrapperPlacement::Module(_) => module_wrappers.push(src),
+                }
+            }
+
+            if let WrapperPlacement::Module(ref name) = self.placement {
+                if !module_wrappers.is_empty() {
+                    let src = format!("pub mod {} {{\nuse super::*;\n\n{}\n}}",
+                                      name, module_wrappers.join("\n\n"));
+                    curs.insert_multi(st.parse_items(cx, &src));
+                }
+            }
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
+pub fn register_commands(reg: &mut Registry) {
+    use super::mk;
+
+    reg.register("safe_wrappers", |args| {
+        let mut placement = WrapperPlacement::Suffix("_safe".to_owned());
+        let mut errors = None;
+        let mut param_kinds = HashMap::new();
+        for arg in args {
+            let eq = arg.find('=')
+                .unwrap_or_else(|| panic!("safe_wrappers: bad argument `{}`", arg));
+            let (key, value) = (&arg[..eq], &arg[eq + 1..]);
+            match key {
+                "suffix" => placement = WrapperPlacement::Suffix(value.to_owned()),
+                "module" => placement = WrapperPlacement::Module(value.to_owned()),
+                "errors" => errors = Some(match value {
+                    "negative" => ErrorConvention::Negative,
+                    "nonzero" => ErrorConvention::Nonzero,
+                    _ => panic!("safe_wrappers: unknown error convention `{}`", value),
+                }),
+                _ => {
+                    let dot = key.find('.')
+                        .unwrap_or_else(|| panic!("safe_wrappers: bad argument `{}`", arg));
+                    param_kinds.insert((key[..dot].to_owned(), key[dot + 1..].to_owned()),
+                                       value.to_owned());
+                }
+            }
+        }
+        mk(SafeWrappers { placement, errors, param_kinds })
+    });
+}
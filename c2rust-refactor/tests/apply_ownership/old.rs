@@ -0,0 +1,61 @@
+extern crate libc;
+
+#[derive(Copy, Clone)]
+pub struct node {
+    pub value: libc::c_int,
+    pub next: *mut node,
+}
+
+unsafe fn node_new(value: libc::c_int) -> *mut node {
+    let n = Box::into_raw(Box::new(node { value: value, next: 0 as *mut node }));
+    return n;
+}
+
+unsafe fn node_push(head: *mut node, n: *mut node) {
+    (*n).next = (*head).next;
+    (*head).next = n;
+}
+
+unsafe fn node_value(n: *const node) -> libc::c_int {
+    if n.is_null() {
+        return -1;
+    }
+    (*n).value
+}
+
+unsafe fn node_get(n: *const node) -> libc::c_int {
+    (*n).value
+}
+
+unsafe fn node_next(n: *mut node) -> *mut node {
+    (*n).next
+}
+
+unsafe fn node_self(n: *mut node) -> *mut node {
+    n
+}
+
+unsafe fn node_last(mut n: *mut node) -> *mut node {
+    if n.is_null() {
+        return 0 as *mut node;
+    }
+    while !(*n).next.is_null() {
+        n = (*n).next;
+    }
+    n
+}
+
+unsafe fn first_value(head: *mut node) -> libc::c_int {
+    node_value(node_next(head)) + node_get(node_self(head))
+}
+
+fn main() {
+    unsafe {
+        let head = node_new(0);
+        node_push(head, node_new(1));
+        let mut local = node { value: 2, next: 0 as *mut node };
+        node_push(&mut local as *mut node, node_new(3));
+        node_last(head);
+        first_value(head);
+    }
+}
@@ -0,0 +1,138 @@
+#![feature(rustc_private, register_attr)]
+#![register_attr(bitfield)]
+extern crate libc;
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+#[repr(u32)]
+pub enum mode_t {
+    MODE_OFF = 0,
+    MODE_READ = 1,
+    MODE_WRITE = 2,
+    MODE_ALL = 7,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+#[repr(u32)]
+pub enum level_t {
+    LEVEL_LOW = 0,
+    LEVEL_MID = 1,
+    LEVEL_HIGH = 2,
+    LEVEL_MAX = 3,
+}
+
+#[derive(Copy, Clone)]
+#[repr(C, align(1))]
+pub struct settings {
+    #[bitfield(name = "enabled", ty = "libc::c_uint", bits = "0..=0")]
+    #[bitfield(name = "mode", ty = "libc::c_uint", bits = "1..=3")]
+    #[bitfield(name = "level", ty = "libc::c_uint", bits = "4..=5")]
+    pub enabled_mode: [u8; 1],
+}
+
+impl settings {
+    pub fn enabled_flag(&self) -> bool {
+        self.enabled() != 0
+    }
+
+    pub fn set_enabled_flag(&mut self, value: bool) {
+        self.set_enabled(value as libc::c_uint)
+    }
+
+    pub fn mode_enum(&self) -> mode_t {
+        match self.mode() {
+            0 => mode_t::MODE_OFF,
+            1 => mode_t::MODE_READ,
+            2 => mode_t::MODE_WRITE,
+            7 => mode_t::MODE_ALL,
+            value => panic!("invalid `mode_t` value: {}", value),
+        }
+    }
+
+    pub fn set_mode_enum(&mut self, value: mode_t) {
+        self.set_mode(value as libc::c_uint)
+    }
+
+    pub fn level_enum(&self) -> level_t {
+        match self.level() {
+            0 => level_t::LEVEL_LOW,
+            1 => level_t::LEVEL_MID,
+            2 => level_t::LEVEL_HIGH,
+            3 => level_t::LEVEL_MAX,
+            value => panic!("invalid `level_t` value: {}", value),
+        }
+    }
+
+    pub fn set_level_enum(&mut self, value: level_t) {
+        self.set_level(value as libc::c_uint)
+    }
+}
+
+// Stands in for the methods generated by the `BitfieldStruct` derive, which is not available
+// to this test.
+impl settings {
+    pub fn enabled(&self) -> libc::c_uint {
+        (self.enabled_mode[0] & 1) as libc::c_uint
+    }
+    pub fn set_enabled(&mut self, value: libc::c_uint) {
+        self.enabled_mode[0] = self.enabled_mode[0] & !1 | value as u8 & 1;
+    }
+    pub fn mode(&self) -> libc::c_uint {
+        (self.enabled_mode[0] >> 1 & 7) as libc::c_uint
+    }
+    pub fn set_mode(&mut self, value: libc::c_uint) {
+        self.enabled_mode[0] = self.enabled_mode[0] & !14 | (value as u8 & 7) << 1;
+    }
+    pub fn level(&self) -> libc::c_uint {
+        (self.enabled_mode[0] >> 4 & 3) as libc::c_uint
+    }
+    pub fn set_level(&mut self, value: libc::c_uint) {
+        self.enabled_mode[0] = self.enabled_mode[0] & !48 | (value as u8 & 3) << 4;
+    }
+}
+
+pub mod config {
+    use super::{level_t, mode_t, settings};
+
+    pub unsafe fn enable(s: *mut settings, write: bool) {
+        (*s).set_enabled_flag(true);
+        if write {
+            (*s).set_mode_enum(mode_t::MODE_WRITE);
+        } else {
+            (*s).set_mode_enum(mode_t::MODE_READ);
+        }
+    }
+
+    pub fn disable(s: &mut settings) {
+        s.set_enabled_flag(false);
+        s.set_mode_enum(mode_t::MODE_OFF);
+        s.set_level_enum(level_t::LEVEL_MAX);
+    }
+}
+
+pub mod report {
+    use super::{level_t, mode_t, settings};
+
+    pub fn is_writable(s: &settings) -> bool {
+        s.enabled_flag() && s.mode() == mode_t::MODE_WRITE as libc::c_uint
+    }
+
+    pub fn is_off(s: &settings) -> bool {
+        !s.enabled_flag() || s.mode() == mode_t::MODE_OFF as libc::c_uint
+    }
+
+    pub fn is_high(s: &settings) -> bool {
+        s.level_enum() == level_t::LEVEL_HIGH
+    }
+
+    pub fn raw_mode(s: &settings) -> libc::c_uint {
+        s.mode()
+    }
+}
+
+fn main() {
+    let mut s = settings { enabled_mode: [0; 1] };
+    unsafe { config::enable(&mut s, true) };
+    assert!(report::is_writable(&s));
+    config::disable(&mut s);
+    assert!(report::is_off(&s) && report::raw_mode(&s) == 0 && !report::is_high(&s));
+}
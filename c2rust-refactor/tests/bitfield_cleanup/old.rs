@@ -0,0 +1,100 @@
+#![feature(rustc_private, register_attr)]
+#![register_attr(bitfield)]
+extern crate libc;
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+#[repr(u32)]
+pub enum mode_t {
+    MODE_OFF = 0,
+    MODE_READ = 1,
+    MODE_WRITE = 2,
+    MODE_ALL = 7,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+#[repr(u32)]
+pub enum level_t {
+    LEVEL_LOW = 0,
+    LEVEL_MID = 1,
+    LEVEL_HIGH = 2,
+    LEVEL_MAX = 3,
+}
+
+#[derive(Copy, Clone)]
+#[repr(C, align(1))]
+pub struct settings {
+    #[bitfield(name = "enabled", ty = "libc::c_uint", bits = "0..=0")]
+    #[bitfield(name = "mode", ty = "libc::c_uint", bits = "1..=3")]
+    #[bitfield(name = "level", ty = "libc::c_uint", bits = "4..=5")]
+    pub enabled_mode: [u8; 1],
+}
+
+// Stands in for the methods generated by the `BitfieldStruct` derive, which is not available
+// to this test.
+impl settings {
+    pub fn enabled(&self) -> libc::c_uint {
+        (self.enabled_mode[0] & 1) as libc::c_uint
+    }
+    pub fn set_enabled(&mut self, value: libc::c_uint) {
+        self.enabled_mode[0] = self.enabled_mode[0] & !1 | value as u8 & 1;
+    }
+    pub fn mode(&self) -> libc::c_uint {
+        (self.enabled_mode[0] >> 1 & 7) as libc::c_uint
+    }
+    pub fn set_mode(&mut self, value: libc::c_uint) {
+        self.enabled_mode[0] = self.enabled_mode[0] & !14 | (value as u8 & 7) << 1;
+    }
+    pub fn level(&self) -> libc::c_uint {
+        (self.enabled_mode[0] >> 4 & 3) as libc::c_uint
+    }
+    pub fn set_level(&mut self, value: libc::c_uint) {
+        self.enabled_mode[0] = self.enabled_mode[0] & !48 | (value as u8 & 3) << 4;
+    }
+}
+
+pub mod config {
+    use super::{level_t, mode_t, settings};
+
+    pub unsafe fn enable(s: *mut settings, write: bool) {
+        (*s).set_enabled(1);
+        if write {
+            (*s).set_mode(mode_t::MODE_WRITE as libc::c_uint);
+        } else {
+            (*s).set_mode(1);
+        }
+    }
+
+    pub fn disable(s: &mut settings) {
+        s.set_enabled(0 as libc::c_uint);
+        s.set_mode(0);
+        s.set_level(3);
+    }
+}
+
+pub mod report {
+    use super::{level_t, mode_t, settings};
+
+    pub fn is_writable(s: &settings) -> bool {
+        s.enabled() != 0 && s.mode() == mode_t::MODE_WRITE as libc::c_uint
+    }
+
+    pub fn is_off(s: &settings) -> bool {
+        s.enabled() == 0 || s.mode() == 0
+    }
+
+    pub fn is_high(s: &settings) -> bool {
+        s.level() == 2
+    }
+
+    pub fn raw_mode(s: &settings) -> libc::c_uint {
+        s.mode()
+    }
+}
+
+fn main() {
+    let mut s = settings { enabled_mode: [0; 1] };
+    unsafe { config::enable(&mut s, true) };
+    assert!(report::is_writable(&s));
+    config::disable(&mut s);
+    assert!(report::is_off(&s) && report::raw_mode(&s) == 0 && !report::is_high(&s));
+}
@@ -0,0 +1,41 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+extern "C" {
+    fn puts(_: *const libc::c_char) -> libc::c_int;
+    fn printf(_: *const libc::c_char, _: ...) -> libc::c_int;
+}
+
+unsafe fn greet() {
+    puts(::std::ffi::CStr::from_bytes_with_nul(crate::C_STR_02935131).unwrap().as_ptr());
+}
+
+unsafe fn greet_again() {
+    puts(::std::ffi::CStr::from_bytes_with_nul(crate::C_STR_02935131).unwrap().as_ptr());
+}
+
+unsafe fn usage(name: *const libc::c_char) {
+    printf(
+        ::std::ffi::CStr::from_bytes_with_nul(b"usage: %s FILE\n\x00")
+            .unwrap()
+            .as_ptr(),
+        name,
+    );
+}
+
+// This name is taken by another item, so the `const` for "bye" gets a suffix.
+static C_STR_C583E8D9: libc::c_int = 0;
+
+unsafe fn farewell() {
+    puts(::std::ffi::CStr::from_bytes_with_nul(crate::C_STR_C583E8D9_1).unwrap().as_ptr());
+    puts(::std::ffi::CStr::from_bytes_with_nul(crate::C_STR_C583E8D9_1).unwrap().as_ptr());
+}
+
+unsafe fn two_lines() {
+    // The interior NUL would end the string early.
+    puts(b"one\x00two\x00" as *const u8 as *const libc::c_char);
+}
+
+fn main() {}
+const C_STR_C583E8D9_1: &[u8] = b"bye\x00";
+const C_STR_02935131: &[u8] = b"hello\x00";
@@ -0,0 +1,34 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+extern "C" {
+    fn puts(_: *const libc::c_char) -> libc::c_int;
+    fn printf(_: *const libc::c_char, _: ...) -> libc::c_int;
+}
+
+unsafe fn greet() {
+    puts(b"hello\x00" as *const u8 as *const libc::c_char);
+}
+
+unsafe fn greet_again() {
+    puts(b"hello\x00" as *const u8 as *const libc::c_char);
+}
+
+unsafe fn usage(name: *const libc::c_char) {
+    printf(b"usage: %s FILE\n\x00" as *const u8 as *const libc::c_char, name);
+}
+
+// This name is taken by another item, so the `const` for "bye" gets a suffix.
+static C_STR_C583E8D9: libc::c_int = 0;
+
+unsafe fn farewell() {
+    puts(b"bye\x00" as *const u8 as *const libc::c_char);
+    puts(b"bye\x00" as *const u8 as *const libc::c_char);
+}
+
+unsafe fn two_lines() {
+    // The interior NUL would end the string early.
+    puts(b"one\x00two\x00" as *const u8 as *const libc::c_char);
+}
+
+fn main() {}
@@ -0,0 +1,24 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+fn log_message(msg: &str) {
+    eprintln!("{}", msg);
+}
+
+fn start() {
+    log_message("starting");
+}
+
+unsafe fn stop() {
+    log_message("stopping");
+}
+
+unsafe fn latin1() {
+    log_message(
+        ::std::ffi::CStr::from_ptr(b"caf\xe9\x00" as *const u8 as *const libc::c_char)
+            .to_str()
+            .unwrap(),
+    );
+}
+
+fn main() {}
@@ -0,0 +1,34 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+fn log_message(msg: &str) {
+    eprintln!("{}", msg);
+}
+
+fn start() {
+    log_message(unsafe {
+        ::std::ffi::CStr::from_ptr(
+            b"starting\x00" as *const u8 as *const libc::c_char as *const libc::c_char,
+        )
+        .to_str()
+        .unwrap()
+    });
+}
+
+unsafe fn stop() {
+    log_message(
+        ::std::ffi::CStr::from_ptr(b"stopping\x00" as *const u8 as *const libc::c_char)
+            .to_str()
+            .unwrap(),
+    );
+}
+
+unsafe fn latin1() {
+    log_message(
+        ::std::ffi::CStr::from_ptr(b"caf\xe9\x00" as *const u8 as *const libc::c_char)
+            .to_str()
+            .unwrap(),
+    );
+}
+
+fn main() {}
@@ -0,0 +1,40 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+extern "C" {
+    fn __assert_fail(assertion: *const libc::c_char, file: *const libc::c_char,
+                     line: libc::c_uint, function: *const libc::c_char) -> !;
+    fn abort() -> !;
+    fn exit(status: libc::c_int) -> !;
+}
+
+unsafe fn check_len(len: libc::c_int) {
+    assert!(len > 0 as libc::c_int, "len > 0");
+}
+
+unsafe fn check_ptr(p: *const libc::c_int) {
+    assert!(!p.is_null(), "p != NULL");
+}
+
+unsafe fn check_flags(flags: libc::c_int) {
+    assert!(flags & 1 as libc::c_int != 0, "flags & 1");
+}
+
+unsafe fn fail(code: libc::c_int) -> ! {
+    if code == 0 as libc::c_int {
+        ::std::process::abort();
+    }
+    ::std::process::exit(code);
+}
+
+unsafe fn main_0() -> libc::c_int {
+    check_len(1 as libc::c_int);
+    if check_len as usize == 0 {
+        ::std::process::exit(2);
+    }
+    return 0;
+}
+
+pub fn main() {
+    unsafe { ::std::process::exit(main_0() as i32) }
+}
@@ -0,0 +1,55 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+extern "C" {
+    fn __assert_fail(assertion: *const libc::c_char, file: *const libc::c_char,
+                     line: libc::c_uint, function: *const libc::c_char) -> !;
+    fn abort() -> !;
+    fn exit(status: libc::c_int) -> !;
+}
+
+unsafe fn check_len(len: libc::c_int) {
+    if len > 0 as libc::c_int {
+    } else {
+        __assert_fail(b"len > 0\x00" as *const u8 as *const libc::c_char,
+                      b"check.c\x00" as *const u8 as *const libc::c_char,
+                      4 as libc::c_int as libc::c_uint,
+                      b"check_len\x00" as *const u8 as *const libc::c_char);
+    }
+}
+
+unsafe fn check_ptr(p: *const libc::c_int) {
+    if !p.is_null() as libc::c_int == 0 {
+        __assert_fail(b"p != NULL\x00" as *const u8 as *const libc::c_char,
+                      b"check.c\x00" as *const u8 as *const libc::c_char,
+                      9 as libc::c_int as libc::c_uint,
+                      b"check_ptr\x00" as *const u8 as *const libc::c_char);
+    }
+}
+
+unsafe fn check_flags(flags: libc::c_int) {
+    (flags & 1 as libc::c_int != 0 ||
+         __assert_fail(b"flags & 1\x00" as *const u8 as *const libc::c_char,
+                       b"check.c\x00" as *const u8 as *const libc::c_char,
+                       14 as libc::c_int as libc::c_uint,
+                       b"check_flags\x00" as *const u8 as *const libc::c_char));
+}
+
+unsafe fn fail(code: libc::c_int) -> ! {
+    if code == 0 as libc::c_int {
+        abort();
+    }
+    exit(code);
+}
+
+unsafe fn main_0() -> libc::c_int {
+    check_len(1 as libc::c_int);
+    if check_len as usize == 0 {
+        exit(2 as libc::c_int);
+    }
+    exit(0 as libc::c_int);
+}
+
+pub fn main() {
+    unsafe { ::std::process::exit(main_0() as i32) }
+}
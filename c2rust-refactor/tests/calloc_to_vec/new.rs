@@ -0,0 +1,88 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+extern "C" {
+    fn malloc(_: libc::c_ulong) -> *mut libc::c_void;
+    fn calloc(_: libc::c_ulong, _: libc::c_ulong) -> *mut libc::c_void;
+    fn free(_: *mut libc::c_void);
+}
+
+#[derive(Copy, Clone, Default)]
+#[repr(C)]
+pub struct Point {
+    pub x: libc::c_int,
+    pub y: libc::c_int,
+}
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct Handler {
+    pub name: &'static str,
+}
+
+unsafe fn zeroed_ints(n: libc::c_ulong) {
+    let arr: *mut libc::c_int = Box::into_raw(
+        (0..n as usize)
+            .map(|_| <libc::c_int as Default>::default())
+            .collect::<Box<[libc::c_int]>>(),
+    ) as *mut libc::c_int;
+    *arr.offset(0) = 1;
+    drop(Box::from_raw(::std::ptr::slice_from_raw_parts_mut(
+        arr,
+        n as usize,
+    )));
+}
+
+unsafe fn zeroed_points(n: libc::c_ulong) {
+    let pts: *mut Point = Box::into_raw(
+        (0..n as usize)
+            .map(|_| ::std::mem::zeroed::<Point>())
+            .collect::<Box<[Point]>>(),
+    ) as *mut Point;
+    drop(Box::from_raw(::std::ptr::slice_from_raw_parts_mut(
+        pts,
+        n as usize,
+    )));
+}
+
+// A zeroed `&str` isn't valid, so this is left alone.
+unsafe fn zeroed_handlers(n: libc::c_ulong) {
+    let hs: *mut Handler =
+        calloc(n, ::std::mem::size_of::<Handler>() as libc::c_ulong) as *mut Handler;
+    free(hs as *mut libc::c_void);
+}
+
+unsafe fn points(count: libc::c_int) {
+    let mut pts: *mut Point = 0 as *mut Point;
+    pts = Box::into_raw(
+        (0..count as usize)
+            .map(|_| <Point as Default>::default())
+            .collect::<Box<[Point]>>(),
+    ) as *mut Point;
+    (*pts).x = 1;
+    drop(Box::from_raw(::std::ptr::slice_from_raw_parts_mut(
+        pts,
+        count as usize,
+    )));
+}
+
+unsafe fn points_reversed(count: libc::c_ulong) {
+    let pts: *mut Point = Box::into_raw(
+        (0..count as usize)
+            .map(|_| <Point as Default>::default())
+            .collect::<Box<[Point]>>(),
+    ) as *mut Point;
+    drop(Box::from_raw(::std::ptr::slice_from_raw_parts_mut(
+        pts,
+        count as usize,
+    )));
+}
+
+unsafe fn changing_length(mut n: libc::c_ulong) {
+    let buf: *mut libc::c_int =
+        calloc(n, ::std::mem::size_of::<libc::c_int>() as libc::c_ulong) as *mut libc::c_int;
+    n = n.wrapping_add(1);
+    free(buf as *mut libc::c_void);
+}
+
+fn main() {}
@@ -0,0 +1,65 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+extern "C" {
+    fn malloc(_: libc::c_ulong) -> *mut libc::c_void;
+    fn calloc(_: libc::c_ulong, _: libc::c_ulong) -> *mut libc::c_void;
+    fn free(_: *mut libc::c_void);
+}
+
+#[derive(Copy, Clone, Default)]
+#[repr(C)]
+pub struct Point {
+    pub x: libc::c_int,
+    pub y: libc::c_int,
+}
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct Handler {
+    pub name: &'static str,
+}
+
+unsafe fn zeroed_ints(n: libc::c_ulong) {
+    let arr: *mut libc::c_int =
+        calloc(n, ::std::mem::size_of::<libc::c_int>() as libc::c_ulong) as *mut libc::c_int;
+    *arr.offset(0) = 1;
+    free(arr as *mut libc::c_void);
+}
+
+unsafe fn zeroed_points(n: libc::c_ulong) {
+    let pts: *mut Point =
+        calloc(n, ::std::mem::size_of::<Point>() as libc::c_ulong) as *mut Point;
+    free(pts as *mut libc::c_void);
+}
+
+// A zeroed `&str` isn't valid, so this is left alone.
+unsafe fn zeroed_handlers(n: libc::c_ulong) {
+    let hs: *mut Handler =
+        calloc(n, ::std::mem::size_of::<Handler>() as libc::c_ulong) as *mut Handler;
+    free(hs as *mut libc::c_void);
+}
+
+unsafe fn points(count: libc::c_int) {
+    let mut pts: *mut Point = 0 as *mut Point;
+    pts = malloc(
+        (count as libc::c_ulong).wrapping_mul(::std::mem::size_of::<Point>() as libc::c_ulong),
+    ) as *mut Point;
+    (*pts).x = 1;
+    free(pts as *mut libc::c_void);
+}
+
+unsafe fn points_reversed(count: libc::c_ulong) {
+    let pts: *mut Point =
+        malloc((::std::mem::size_of::<Point>() as libc::c_ulong).wrapping_mul(count)) as *mut Point;
+    free(pts as *mut libc::c_void);
+}
+
+unsafe fn changing_length(mut n: libc::c_ulong) {
+    let buf: *mut libc::c_int =
+        calloc(n, ::std::mem::size_of::<libc::c_int>() as libc::c_ulong) as *mut libc::c_int;
+    n = n.wrapping_add(1);
+    free(buf as *mut libc::c_void);
+}
+
+fn main() {}
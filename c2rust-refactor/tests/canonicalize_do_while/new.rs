@@ -0,0 +1,70 @@
+fn nested(n: i32) -> i32 {
+    let mut total = 0;
+    let mut i = 0;
+    {
+        let mut do_while_first = true;
+        while do_while_first || i < n {
+            do_while_first = false;
+            let mut j = 0;
+            {
+                let mut do_while_first = true;
+                while do_while_first || j < n {
+                    do_while_first = false;
+                    total += j;
+                    j += 1;
+                }
+            }
+            i += 1;
+        }
+    }
+    total
+}
+
+fn labeled(n: i32) -> i32 {
+    let mut total = 0;
+    let mut i = 0;
+    'outer: loop {
+        let mut j = 0;
+        {
+            let mut do_while_first = true;
+            'inner: while do_while_first || j < n {
+                do_while_first = false;
+                if total > 100 {
+                    break 'outer;
+                }
+                total += j;
+                j += 1;
+            }
+        }
+        i += 1;
+        if !(i < n) {
+            break;
+        }
+    }
+    total
+}
+
+fn skipped_check(n: i32) -> i32 {
+    let mut i = 0;
+    loop {
+        i += 1;
+        if i % 2 == 0 {
+            continue;
+        }
+        if !(i < n) {
+            break;
+        }
+    }
+    i
+}
+
+fn step(n: &mut i32) -> i32 {
+    *n -= 1;
+    *n
+}
+
+fn empty(n: &mut i32) {
+    while step(n) > 0 {}
+}
+
+fn main() {}
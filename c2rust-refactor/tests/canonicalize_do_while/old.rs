@@ -0,0 +1,71 @@
+fn nested(n: i32) -> i32 {
+    let mut total = 0;
+    let mut i = 0;
+    loop {
+        let mut j = 0;
+        loop {
+            total += j;
+            j += 1;
+            if !(j < n) {
+                break;
+            }
+        }
+        i += 1;
+        if !(i < n) {
+            break;
+        }
+    }
+    total
+}
+
+fn labeled(n: i32) -> i32 {
+    let mut total = 0;
+    let mut i = 0;
+    'outer: loop {
+        let mut j = 0;
+        'inner: loop {
+            if total > 100 {
+                break 'outer;
+            }
+            total += j;
+            j += 1;
+            if !(j < n) {
+                break 'inner;
+            }
+        }
+        i += 1;
+        if !(i < n) {
+            break;
+        }
+    }
+    total
+}
+
+fn skipped_check(n: i32) -> i32 {
+    let mut i = 0;
+    loop {
+        i += 1;
+        if i % 2 == 0 {
+            continue;
+        }
+        if !(i < n) {
+            break;
+        }
+    }
+    i
+}
+
+fn step(n: &mut i32) -> i32 {
+    *n -= 1;
+    *n
+}
+
+fn empty(n: &mut i32) {
+    loop {
+        if !(step(n) > 0) {
+            break;
+        }
+    }
+}
+
+fn main() {}
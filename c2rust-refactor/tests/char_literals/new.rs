@@ -0,0 +1,64 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+unsafe fn sign_of(s: *const libc::c_char, i: isize) -> libc::c_int {
+    if *s.offset(i) == b'-' as libc::c_char {
+        -1
+    } else if *s.offset(i) == b'+' as libc::c_char {
+        1
+    } else {
+        0
+    }
+}
+
+fn classify(c: u8) -> libc::c_int {
+    match c {
+        b'0'..=b'9' => 0,
+        b' ' | b'\t' | b'\n' => 1,
+        b'\'' => 2,
+        200 => 3,
+        _ => 4,
+    }
+}
+
+fn is_sign(c: libc::c_char) -> bool {
+    match c as u8 {
+        b'+' | b'-' => true,
+        _ => false,
+    }
+}
+
+fn sign_or_digit(c: libc::c_char) -> libc::c_int {
+    match c {
+        -1 => -1,
+        45 => 1,
+        _ => 0,
+    }
+}
+
+fn digit_value(c: u8) -> u8 {
+    if c >= b'0' && c <= b'9' {
+        c - 48
+    } else {
+        0
+    }
+}
+
+fn is_dash(c: char) -> bool {
+    c == '-'
+}
+
+fn main() {
+    let mut sep: u8 = b'"';
+    if classify(sep) != 0 {
+        sep = b',';
+    }
+    let end: u8 = 0;
+    let high: u8 = 200;
+    println!("{} {} {} {}", digit_value(sep), is_dash('-'), end, high);
+    println!("{} {}", is_sign(45), sign_or_digit(-1));
+    unsafe {
+        let s = b"-1\0";
+        sign_of(s.as_ptr() as *const libc::c_char, 0);
+    }
+}
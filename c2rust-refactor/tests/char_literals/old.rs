@@ -0,0 +1,64 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+unsafe fn sign_of(s: *const libc::c_char, i: isize) -> libc::c_int {
+    if *s.offset(i) == 45 as libc::c_char {
+        -1
+    } else if *s.offset(i) == 43 as libc::c_char {
+        1
+    } else {
+        0
+    }
+}
+
+fn classify(c: u8) -> libc::c_int {
+    match c {
+        48..=57 => 0,
+        32 | 9 | 10 => 1,
+        39 => 2,
+        200 => 3,
+        _ => 4,
+    }
+}
+
+fn is_sign(c: libc::c_char) -> bool {
+    match c {
+        43 | 45 => true,
+        _ => false,
+    }
+}
+
+fn sign_or_digit(c: libc::c_char) -> libc::c_int {
+    match c {
+        -1 => -1,
+        45 => 1,
+        _ => 0,
+    }
+}
+
+fn digit_value(c: u8) -> u8 {
+    if c >= 48 && c <= 57 {
+        c - 48
+    } else {
+        0
+    }
+}
+
+fn is_dash(c: char) -> bool {
+    c == 45u8 as char
+}
+
+fn main() {
+    let mut sep: u8 = 34;
+    if classify(sep) != 0 {
+        sep = 44;
+    }
+    let end: u8 = 0;
+    let high: u8 = 200;
+    println!("{} {} {} {}", digit_value(sep), is_dash('-'), end, high);
+    println!("{} {}", is_sign(45), sign_or_digit(-1));
+    unsafe {
+        let s = b"-1\0";
+        sign_of(s.as_ptr() as *const libc::c_char, 0);
+    }
+}
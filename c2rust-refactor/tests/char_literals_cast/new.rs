@@ -0,0 +1,15 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+unsafe fn is_comma(s: *const libc::c_char) -> bool {
+    *s == ',' as libc::c_char
+}
+
+fn main() {
+    let sep: libc::c_char = 'A' as libc::c_char;
+    let n: libc::c_int = 65;
+    println!("{} {}", sep, n);
+    unsafe {
+        is_comma(b",\0".as_ptr() as *const libc::c_char);
+    }
+}
@@ -0,0 +1,55 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+::bitflags::bitflags! {
+    pub struct OpenFlags: u32 {
+        const OPEN_READ = 0x1;
+        const OPEN_WRITE = 0x2;
+        const OPEN_CREATE = 0x4;
+        const OPEN_RW = 0x3;
+    }
+}
+
+pub type mode_t = libc::c_uint;
+pub const MODE_USER: mode_t = 1;
+pub const MODE_GROUP: mode_t = 6;
+
+pub struct File {
+    pub flags: OpenFlags,
+    pub mode: mode_t,
+}
+
+extern "C" {
+    fn sys_open(flags: libc::c_uint) -> libc::c_int;
+    fn sys_default_flags() -> libc::c_uint;
+}
+
+fn can_write(f: &File) -> bool {
+    f.flags.contains(OpenFlags::OPEN_WRITE)
+}
+
+fn open(f: &mut File, create: bool) -> libc::c_int {
+    let mut flags: OpenFlags = OpenFlags::OPEN_READ | OpenFlags::OPEN_WRITE;
+    if create {
+        flags |= OpenFlags::OPEN_CREATE;
+    }
+    f.flags = flags;
+    unsafe { sys_open(flags.bits()) }
+}
+
+fn reset(f: &mut File) {
+    f.flags = OpenFlags::from_bits_truncate(unsafe { sys_default_flags() });
+    if !f.flags.intersects(OpenFlags::OPEN_RW) {
+        f.flags = OpenFlags::empty();
+    }
+}
+
+fn main() {
+    let mut f = File { flags: OpenFlags::empty(), mode: MODE_USER };
+    let fd = open(&mut f, true);
+    reset(&mut f);
+    let writable = can_write(&f);
+    let closed = f.flags.is_empty();
+    let n = fd as u32 + f.mode + writable as u32 + closed as u32;
+    assert!(n > 0);
+}
@@ -0,0 +1,52 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+pub type open_flags_t = libc::c_uint;
+pub const OPEN_READ: open_flags_t = 1;
+pub const OPEN_WRITE: open_flags_t = 2;
+pub const OPEN_CREATE: open_flags_t = 4;
+pub const OPEN_RW: open_flags_t = 3;
+
+pub type mode_t = libc::c_uint;
+pub const MODE_USER: mode_t = 1;
+pub const MODE_GROUP: mode_t = 6;
+
+pub struct File {
+    pub flags: open_flags_t,
+    pub mode: mode_t,
+}
+
+extern "C" {
+    fn sys_open(flags: open_flags_t) -> libc::c_int;
+    fn sys_default_flags() -> open_flags_t;
+}
+
+fn can_write(f: &File) -> bool {
+    f.flags & OPEN_WRITE != 0
+}
+
+fn open(f: &mut File, create: bool) -> libc::c_int {
+    let mut flags: open_flags_t = OPEN_READ | OPEN_WRITE;
+    if create {
+        flags |= OPEN_CREATE;
+    }
+    f.flags = flags;
+    unsafe { sys_open(flags) }
+}
+
+fn reset(f: &mut File) {
+    f.flags = unsafe { sys_default_flags() };
+    if f.flags & OPEN_RW == 0 {
+        f.flags = 0;
+    }
+}
+
+fn main() {
+    let mut f = File { flags: 0, mode: MODE_USER };
+    let fd = open(&mut f, true);
+    reset(&mut f);
+    let writable = can_write(&f);
+    let closed = f.flags == 0;
+    let n = fd as u32 + f.mode + writable as u32 + closed as u32;
+    assert!(n > 0);
+}
@@ -0,0 +1,13 @@
+#!/bin/sh
+
+# work around System Integrity Protection on macOS
+if [ `uname` = 'Darwin' ]; then
+    export LD_LIBRARY_PATH=$not_LD_LIBRARY_PATH
+fi
+
+$refactor \
+    select target 'crate; desc(item && name("open_flags_t"));' \; \
+    consts_to_bitflags OpenFlags \; commit \; clear_marks \; \
+    select target 'crate; desc(item && name("MODE_USER"));' \; \
+    consts_to_bitflags Mode \
+    -- old.rs $rustflags
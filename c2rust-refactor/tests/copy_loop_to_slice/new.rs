@@ -0,0 +1,45 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+#[derive(Clone)]
+pub struct name {
+    pub text: String,
+}
+
+static mut TABLE: [libc::c_int; 16] = [0; 16];
+static mut BACKUP: [libc::c_int; 32] = [0; 32];
+
+unsafe fn plain(dst: &mut [libc::c_int], src: &[libc::c_int], n: libc::c_int) {
+    if n < 0 {
+        return;
+    }
+    dst[..n as usize].copy_from_slice(&src[..n as usize]);
+}
+
+unsafe fn offset(n: libc::c_int) {
+    if n > 0 {
+        BACKUP[4 as usize..(n + 4) as usize].copy_from_slice(&TABLE[..n as usize]);
+    }
+}
+
+unsafe fn unguarded(dst: &mut [libc::c_int], src: &[libc::c_int], n: libc::c_int) {
+    let mut i: libc::c_int = 0;
+    while i < n {
+        dst[i as usize] = src[i as usize];
+        i += 1
+    }
+}
+
+fn clone_names(dst: &mut [name], src: &[name]) {
+    dst[1..4].clone_from_slice(&src[1..4]);
+}
+
+unsafe fn shift(buf: &mut [libc::c_int], n: libc::c_int) {
+    let mut i: libc::c_int = 0;
+    while i < n {
+        buf[(i + 1) as usize] = buf[i as usize];
+        i += 1
+    }
+}
+
+fn main() {}
@@ -0,0 +1,52 @@
+extern crate libc;
+
+extern "C" {
+    fn isdigit(c: libc::c_int) -> libc::c_int;
+    fn isalpha(c: libc::c_int) -> libc::c_int;
+    fn isspace(c: libc::c_int) -> libc::c_int;
+    fn isupper(c: libc::c_int) -> libc::c_int;
+    fn toupper(c: libc::c_int) -> libc::c_int;
+    fn getc(f: *mut libc::FILE) -> libc::c_int;
+}
+
+unsafe fn count_digits(s: &[u8]) -> libc::c_int {
+    let mut n: libc::c_int = 0;
+    for &c in s {
+        if isdigit(c as libc::c_int) != 0 {
+            n += 1;
+        }
+        if isspace(c as libc::c_int) == 0 {
+            n += 0;
+        }
+    }
+    n
+}
+
+unsafe fn count_letters(s: &[libc::c_char]) -> libc::c_int {
+    let mut n: libc::c_int = 0;
+    for &c in s {
+        n += isalpha(c as libc::c_int) + isupper(c as libc::c_int);
+        n += isspace(c as libc::c_int);
+    }
+    n
+}
+
+unsafe fn upcase(s: &mut [u8]) {
+    for c in s.iter_mut() {
+        *c = toupper(*c as libc::c_int) as u8;
+    }
+}
+
+unsafe fn upcase_int(c: u8) -> libc::c_int {
+    toupper(c as libc::c_int)
+}
+
+unsafe fn skip_spaces(f: *mut libc::FILE) -> libc::c_int {
+    let mut c = getc(f);
+    while isspace(c) != 0 {
+        c = getc(f);
+    }
+    c
+}
+
+fn main() {}
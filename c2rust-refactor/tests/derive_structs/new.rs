@@ -0,0 +1,38 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+#[derive(Debug, PartialEq)]
+pub struct point {
+    pub x: libc::c_int,
+    pub y: libc::c_double,
+    pub tag: [libc::c_char; 8],
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct node {
+    pub next: *mut node,
+    pub value: libc::c_int,
+}
+
+#[repr(C)]
+pub struct guard {
+    pub lock: ::std::sync::Mutex<libc::c_int>,
+    pub count: libc::c_int,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq)]
+pub struct counter {
+    pub n: libc::c_int,
+}
+
+impl ::std::fmt::Debug for counter {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        write!(f, "counter({})", self.n)
+    }
+}
+
+fn main() {}
@@ -0,0 +1,80 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+extern "C" {
+    fn free(_: *mut libc::c_void);
+    fn puts(_: *const libc::c_char) -> libc::c_int;
+}
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct node {
+    pub value: libc::c_int,
+    pub next: *mut node,
+}
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct list {
+    pub head: *mut node,
+    pub len: libc::c_int,
+}
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct buf {
+    pub data: *mut libc::c_char,
+}
+
+// `buf` is assigned by value, which would free the old `data` if it implemented
+// `Drop`, so `buf_destroy` is left alone.
+unsafe fn buf_reset(b: *mut buf) {
+    *b = buf {
+        data: 0 as *mut libc::c_char,
+    };
+}
+
+unsafe fn buf_destroy(b: *mut buf) {
+    free((*b).data as *mut libc::c_void);
+    free(b as *mut libc::c_void);
+}
+
+unsafe fn node_new(value: libc::c_int, next: *mut node) -> *mut node {
+    Box::into_raw(Box::new(node {
+        value: value,
+        next: next,
+    }))
+}
+
+unsafe fn node_destroy(n: *mut node) {
+    if n.is_null() {
+        return;
+    }
+    node_destroy((*n).next);
+    free(n as *mut libc::c_void);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn list_destroy(l: *mut list) {
+    puts(b"list_destroy\x00" as *const u8 as *const libc::c_char);
+    node_destroy((*l).head);
+    (*l).len = 0;
+    free(l as *mut libc::c_void);
+}
+
+fn main() {
+    unsafe {
+        let b = Box::into_raw(Box::new(buf {
+            data: 0 as *mut libc::c_char,
+        }));
+        buf_reset(b);
+        buf_destroy(b);
+        let n = node_new(3, 0 as *mut node);
+        node_destroy(n);
+        let l = Box::new(list {
+            head: node_new(1, node_new(2, 0 as *mut node)),
+            len: 2,
+        });
+        list_destroy(Box::into_raw(l));
+    }
+}
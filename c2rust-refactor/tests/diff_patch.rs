@@ -0,0 +1,89 @@
+//! Check that the patches printed by the `diff` rewrite mode apply cleanly with a patch library,
+//! and give back the rewritten text.
+
+use std::env;
+use std::fs;
+use std::process;
+
+use c2rust_refactor::file_io::{DiffOptions, FileIO, OutputMode, RealFileIO};
+use c2rust_refactor::rewrite::files::unified_diff;
+
+fn apply(old: &str, patch: &str) -> String {
+    let patch = diffy::Patch::from_str(patch).unwrap();
+    diffy::apply(old, &patch).unwrap()
+}
+
+fn check_roundtrip(old: &str, new: &str) {
+    for &context in &[0, 1, 3, 10] {
+        let patch = format!("--- a/f.rs\n+++ b/f.rs\n{}", unified_diff(old, new, context));
+        assert_eq!(apply(old, &patch), new, "context {}:\n{}", context, patch);
+    }
+}
+
+const OLD: &str = "fn first() -> i32 {
+    let a = 1;
+    a
+}
+
+fn unchanged_a() -> i32 {
+    10
+}
+
+fn unchanged_b() -> i32 {
+    20
+}
+
+fn last() -> i32 {
+    let b = 2;
+    b
+}
+";
+
+#[test]
+fn separate_hunks() {
+    let new = OLD.replace("let a = 1;", "let a = 2;").replace("let b = 2;", "let b = 3;");
+    check_roundtrip(OLD, &new);
+}
+
+#[test]
+fn first_and_last_lines() {
+    let new = format!("// header\n{}// footer\n", &OLD[OLD.find('\n').unwrap() + 1..]);
+    check_roundtrip(OLD, &new);
+}
+
+#[test]
+fn final_newline() {
+    check_roundtrip(OLD, OLD.trim_end());
+    check_roundtrip(OLD.trim_end(), OLD);
+    check_roundtrip(OLD.trim_end(), &OLD.trim_end().replace("b\n}", "b + 1\n}"));
+}
+
+#[test]
+fn new_file() {
+    check_roundtrip("", OLD);
+}
+
+#[test]
+fn one_patch_per_file() {
+    let dir = env::temp_dir().join(format!("c2rust-refactor-diff-{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("old.rs");
+    let out = dir.join("old.patch");
+    fs::write(&path, OLD).unwrap();
+
+    // Rewrite the same file twice, as two committed commands would.
+    let io = RealFileIO::new(vec![OutputMode::PrintDiff]).with_diff_options(DiffOptions {
+        context: 1,
+        out: Some(out.clone()),
+    });
+    let first = OLD.replace("let a = 1;", "let a = 2;");
+    let second = first.replace("let b = 2;", "let b = 3;");
+    io.write_file(&path, &first).unwrap();
+    io.write_file(&path, &second).unwrap();
+    io.finish().unwrap();
+
+    let patch = fs::read_to_string(&out).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(patch.matches("\n+++ ").count(), 1, "{}", patch);
+    assert_eq!(apply(OLD, &patch), second, "{}", patch);
+}
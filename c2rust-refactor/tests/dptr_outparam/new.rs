@@ -0,0 +1,85 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+extern "C" {
+    fn malloc(_: libc::c_ulong) -> *mut libc::c_void;
+    fn free(_: *mut libc::c_void);
+}
+
+#[derive(Copy, Clone, Default)]
+#[repr(C)]
+pub struct foo {
+    pub x: libc::c_int,
+}
+
+pub struct holder {
+    pub item: Option<Box<foo>>,
+    pub count: libc::c_int,
+}
+
+#[derive(Copy, Clone)]
+pub struct pair {
+    pub first: *mut foo,
+}
+
+unsafe fn foo_create(x: libc::c_int, out: &mut Option<Box<foo>>) -> libc::c_int {
+    if x < 0 {
+        *out = None;
+        return -1;
+    }
+    let p: *mut foo = Box::into_raw(Box::new(foo { x: x }));
+    *out = Some(Box::from_raw(p));
+    0
+}
+
+unsafe fn foo_alloc(out: &mut Option<Box<foo>>) {
+    *out = Some(Box::new(<foo as Default>::default()));
+}
+
+unsafe fn foo_boxed(out: &mut Option<Box<foo>>) {
+    *out = Some(Box::new(foo { x: 7 }));
+}
+
+unsafe fn foo_clear(out: *mut *mut foo) {
+    *out = 0 as *mut foo;
+}
+
+unsafe fn use_foo(p: *mut foo) -> libc::c_int {
+    (*p).x
+}
+
+fn main() {
+    unsafe {
+        let mut a: Option<Box<foo>> = None;
+        if foo_create(1, &mut a) == 0 {
+            (*a.as_deref_mut().unwrap()).x += 1;
+        }
+        if !a.is_none() {
+            a = None;
+        }
+
+        let mut b: Option<Box<foo>> = None;
+        foo_create(-1, &mut b);
+        if b.is_none() {
+            b = None;
+        }
+
+        let mut c: *mut foo = 0 as *mut foo;
+        {
+            let mut __out0: Option<Box<foo>> = None;
+            let __ret = foo_boxed(&mut __out0);
+            c = __out0.map_or(::std::ptr::null_mut(), Box::into_raw);
+            __ret
+        };
+        use_foo(c);
+
+        let mut h = holder { item: None, count: 0 };
+        foo_alloc(&mut h.item);
+        (*h.item.as_deref_mut().unwrap()).x = 3;
+        h.count += 1;
+        h.item = None;
+
+        let mut pr = pair { first: 0 as *mut foo };
+        foo_clear(&mut pr.first);
+    }
+}
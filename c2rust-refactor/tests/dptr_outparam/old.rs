@@ -0,0 +1,83 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+extern "C" {
+    fn malloc(_: libc::c_ulong) -> *mut libc::c_void;
+    fn free(_: *mut libc::c_void);
+}
+
+#[derive(Copy, Clone, Default)]
+#[repr(C)]
+pub struct foo {
+    pub x: libc::c_int,
+}
+
+pub struct holder {
+    pub item: *mut foo,
+    pub count: libc::c_int,
+}
+
+#[derive(Copy, Clone)]
+pub struct pair {
+    pub first: *mut foo,
+}
+
+unsafe fn foo_create(x: libc::c_int, out: *mut *mut foo) -> libc::c_int {
+    if out.is_null() {
+        return -1;
+    }
+    if x < 0 {
+        *out = 0 as *mut foo;
+        return -1;
+    }
+    let p: *mut foo = Box::into_raw(Box::new(foo { x: x }));
+    *out = p;
+    0
+}
+
+unsafe fn foo_alloc(out: *mut *mut foo) {
+    *out = malloc(::std::mem::size_of::<foo>() as libc::c_ulong) as *mut foo;
+}
+
+unsafe fn foo_boxed(out: *mut *mut foo) {
+    *out = Box::into_raw(Box::new(foo { x: 7 }));
+}
+
+unsafe fn foo_clear(out: *mut *mut foo) {
+    *out = 0 as *mut foo;
+}
+
+unsafe fn use_foo(p: *mut foo) -> libc::c_int {
+    (*p).x
+}
+
+fn main() {
+    unsafe {
+        let mut a: *mut foo = 0 as *mut foo;
+        if foo_create(1, &mut a) == 0 {
+            (*a).x += 1;
+        }
+        if !a.is_null() {
+            free(a as *mut libc::c_void);
+        }
+
+        let mut b: *mut foo = ::std::ptr::null_mut();
+        foo_create(-1, &mut b);
+        if b.is_null() {
+            b = 0 as *mut foo;
+        }
+
+        let mut c: *mut foo = 0 as *mut foo;
+        foo_boxed(&mut c);
+        use_foo(c);
+
+        let mut h = holder { item: 0 as *mut foo, count: 0 };
+        foo_alloc(&mut h.item);
+        (*h.item).x = 3;
+        h.count += 1;
+        free(h.item as *mut libc::c_void);
+
+        let mut pr = pair { first: 0 as *mut foo };
+        foo_clear(&mut pr.first);
+    }
+}
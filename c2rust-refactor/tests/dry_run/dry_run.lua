@@ -0,0 +1,3 @@
+refactor:run_command("rewrite_expr", {"1", "2"})
+refactor:run_command("rewrite_expr", {"2", "3"})
+refactor:save_crate()
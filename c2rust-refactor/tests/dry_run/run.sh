@@ -0,0 +1,40 @@
+#!/bin/sh
+
+# work around System Integrity Protection on macOS
+if [ `uname` = 'Darwin' ]; then
+    export LD_LIBRARY_PATH=$not_LD_LIBRARY_PATH
+fi
+
+cp old.rs old.orig
+
+# With `--dry-run`, both commands run (the second one sees the result of the
+# first), but nothing is written, and each command gets a summary.
+$refactor --dry-run \
+    rewrite_expr 1 2 \; \
+    rewrite_expr 2 3 \
+    -- old.rs $rustflags > dry_run.out
+
+status=0
+cmp -s old.rs old.orig || status=1
+[ -f old.new ] && status=1
+grep -q '^dry run: rewrite_expr 1 2: [1-9][0-9]* nodes rewritten, 1 files would change$' dry_run.out || status=1
+grep -q '^dry run: rewrite_expr 2 3: [1-9][0-9]* nodes rewritten, 1 files would change$' dry_run.out || status=1
+
+# The same holds for commands run by a script.
+$refactor --dry-run script dry_run.lua -- old.rs $rustflags > script_dry_run.out
+
+cmp -s old.rs old.orig || status=1
+[ -f old.new ] && status=1
+grep -q '^dry run: rewrite_expr 1 2: [1-9][0-9]* nodes rewritten, 1 files would change$' script_dry_run.out || status=1
+grep -q '^dry run: rewrite_expr 2 3: [1-9][0-9]* nodes rewritten, 1 files would change$' script_dry_run.out || status=1
+
+# Without it, the same commands write their output, and print no summaries.
+$refactor \
+    rewrite_expr 1 2 \; \
+    rewrite_expr 2 3 \
+    -- old.rs $rustflags > real_run.out
+
+grep -q '^dry run:' real_run.out && status=1
+
+rm -f old.orig dry_run.out script_dry_run.out real_run.out
+exit $status
@@ -0,0 +1,46 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+#[repr(u32)]
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum color_t {
+    RED = 0,
+    GREEN = 1,
+    BLUE = 2,
+}
+
+#[repr(i32)]
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum level_t {
+    LOW = 0,
+    HIGH = 1,
+}
+impl level_t {
+    pub const DEFAULT: level_t = level_t::LOW;
+}
+
+extern "C" {
+    fn set_level(level: libc::c_int);
+}
+
+fn color_name(c: color_t) -> i32 {
+    match c { color_t::RED => { 1 }, color_t::GREEN => { 2 }, _ => { 3 } }
+}
+
+fn brightness(c: color_t) -> u32 {
+    c as u32 * 10 + 5
+}
+
+fn main() {
+    unsafe {
+        let c: color_t = color_t::GREEN;
+        let first: color_t = color_t::RED;
+        let l: level_t = level_t::DEFAULT;
+        set_level(l as i32);
+        if l == level_t::HIGH {
+            set_level(level_t::LOW as i32);
+        }
+        let n = color_name(c) as u32 + brightness(color_t::BLUE) + brightness(first);
+        set_level(n as i32);
+    }
+}
@@ -0,0 +1,38 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+pub type color_t = libc::c_uint;
+pub const RED: color_t = 0;
+pub const GREEN: color_t = 1;
+pub const BLUE: color_t = 2;
+
+pub type level_t = libc::c_int;
+pub const LOW: level_t = 0;
+pub const HIGH: level_t = 1;
+pub const DEFAULT: level_t = 0;
+
+extern "C" {
+    fn set_level(level: level_t);
+}
+
+fn color_name(c: color_t) -> i32 {
+    if c == RED { 1 } else if c == GREEN { 2 } else { 3 }
+}
+
+fn brightness(c: color_t) -> u32 {
+    c * 10 + 5
+}
+
+fn main() {
+    unsafe {
+        let c: color_t = GREEN;
+        let first: color_t = 0;
+        let l: level_t = DEFAULT;
+        set_level(l);
+        if l == 1 {
+            set_level(LOW);
+        }
+        let n = color_name(c) as u32 + brightness(BLUE) + brightness(first);
+        set_level(n as i32);
+    }
+}
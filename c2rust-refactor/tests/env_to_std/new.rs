@@ -0,0 +1,73 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+extern "C" {
+    fn getenv(__name: *const libc::c_char) -> *mut libc::c_char;
+    fn setenv(__name: *const libc::c_char, __value: *const libc::c_char,
+              __replace: libc::c_int) -> libc::c_int;
+    fn unsetenv(__name: *const libc::c_char) -> libc::c_int;
+    fn puts(__s: *const libc::c_char) -> libc::c_int;
+}
+
+unsafe fn is_dumb_term() -> bool {
+    let term: ::std::option::Option<::std::ffi::OsString> = ::std::env::var_os("TERM");
+    term.is_some() && unsafe {
+        ::std::os::unix::ffi::OsStrExt::as_bytes(term.as_ref().unwrap().as_os_str()) == b"dumb"
+    }
+}
+
+unsafe fn columns() -> libc::c_int {
+    let cols: ::std::option::Option<::std::ffi::OsString> = ::std::env::var_os("COLUMNS");
+    if cols.is_none() {
+        return 80 as libc::c_int;
+    }
+    return unsafe {
+        cols.as_ref().unwrap().to_str().unwrap()
+    }.trim_start().parse::<i32>().unwrap_or(0);
+}
+
+unsafe fn has_var(name: *const libc::c_char) -> bool {
+    ::std::env::var_os(::std::os::unix::ffi::OsStrExt::from_bytes(unsafe {
+        ::std::ffi::CStr::from_ptr(name as *const libc::c_char).to_bytes()
+    })).is_some()
+}
+
+unsafe fn print_home() {
+    let home: *mut libc::c_char = crate::getenv_cstring("HOME");
+    if !home.is_null() {
+        puts(home);
+    }
+}
+
+unsafe fn set_defaults() {
+    if ::std::env::var_os("PAGER").is_none() { ::std::env::set_var("PAGER", "less") }
+    ::std::env::set_var("LANG", "C");
+    ::std::env::remove_var("TMPDIR");
+}
+
+fn main() {}
+
+/// Read the environment variable `name` into a C string, or return null if it
+/// isn't set, like `getenv`.  The string stays valid until the variable is read
+/// again by the current thread after it changed.
+#[cfg(unix)]
+fn getenv_cstring<K: AsRef<::std::ffi::OsStr>>(name: K) -> *mut libc::c_char {
+    thread_local! {
+        static CACHE: ::std::cell::RefCell<::std::collections::HashMap<
+            ::std::ffi::OsString, ::std::ffi::CString>> =
+            ::std::cell::RefCell::new(::std::collections::HashMap::new());
+    }
+    let name = name.as_ref();
+    let value = match ::std::env::var_os(name) {
+        Some(x) => ::std::os::unix::ffi::OsStringExt::into_vec(x),
+        None => return ::std::ptr::null_mut(),
+    };
+    let value = ::std::ffi::CString::new(value).unwrap();
+    CACHE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        if cache.get(name) != Some(&value) {
+            cache.insert(name.to_owned(), value);
+        }
+        cache[name].as_ptr() as *mut libc::c_char
+    })
+}
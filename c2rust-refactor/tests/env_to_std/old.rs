@@ -0,0 +1,46 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+extern "C" {
+    fn getenv(__name: *const libc::c_char) -> *mut libc::c_char;
+    fn setenv(__name: *const libc::c_char, __value: *const libc::c_char,
+              __replace: libc::c_int) -> libc::c_int;
+    fn unsetenv(__name: *const libc::c_char) -> libc::c_int;
+    fn puts(__s: *const libc::c_char) -> libc::c_int;
+}
+
+unsafe fn is_dumb_term() -> bool {
+    let term: *mut libc::c_char = getenv(b"TERM\x00" as *const u8 as *const libc::c_char);
+    !term.is_null() && unsafe { ::std::ffi::CStr::from_ptr(term).to_bytes() == b"dumb" }
+}
+
+unsafe fn columns() -> libc::c_int {
+    let cols: *mut libc::c_char = getenv(b"COLUMNS\x00" as *const u8 as *const libc::c_char);
+    if cols.is_null() {
+        return 80 as libc::c_int;
+    }
+    return unsafe {
+        ::std::ffi::CStr::from_ptr(cols as *const libc::c_char).to_str().unwrap()
+    }.trim_start().parse::<i32>().unwrap_or(0);
+}
+
+unsafe fn has_var(name: *const libc::c_char) -> bool {
+    !getenv(name).is_null()
+}
+
+unsafe fn print_home() {
+    let home: *mut libc::c_char = getenv(b"HOME\x00" as *const u8 as *const libc::c_char);
+    if !home.is_null() {
+        puts(home);
+    }
+}
+
+unsafe fn set_defaults() {
+    setenv(b"PAGER\x00" as *const u8 as *const libc::c_char,
+           b"less\x00" as *const u8 as *const libc::c_char, 0 as libc::c_int);
+    setenv(b"LANG\x00" as *const u8 as *const libc::c_char,
+           b"C\x00" as *const u8 as *const libc::c_char, 1 as libc::c_int);
+    unsetenv(b"TMPDIR\x00" as *const u8 as *const libc::c_char);
+}
+
+fn main() {}
@@ -0,0 +1,46 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+unsafe fn check_range(x: libc::c_int) -> Result<(), i32> {
+    if x < 0 {
+        return Err(-22);
+    }
+    if x > 100 {
+        return Err(-34);
+    }
+    Ok(())
+}
+
+unsafe fn process(x: libc::c_int) -> Result<(), i32> {
+    check_range(x)?;
+    if check_range(x * 2).is_err() {
+        return Err(-1);
+    }
+    Ok(())
+}
+
+unsafe fn read_bytes(buf: &mut [u8]) -> libc::c_int {
+    if buf.is_empty() {
+        return -1;
+    }
+    buf[0] = 1;
+    buf.len() as libc::c_int
+}
+
+fn report(_code: libc::c_int) {}
+
+fn main() {
+    unsafe {
+        if let Err(rc) = process(5) {
+            report(rc);
+        }
+
+        let mut buf = [0u8; 4];
+        let n = read_bytes(&mut buf);
+        let total = n + match check_range(3) {
+            Ok(()) => 0,
+            Err(e) => e,
+        };
+        report(total);
+    }
+}
@@ -0,0 +1,47 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+unsafe fn check_range(x: libc::c_int) -> libc::c_int {
+    if x < 0 {
+        return -22;
+    }
+    if x > 100 {
+        return -34;
+    }
+    0
+}
+
+unsafe fn process(x: libc::c_int) -> libc::c_int {
+    let rc = check_range(x);
+    if rc < 0 {
+        return rc;
+    }
+    if check_range(x * 2) != 0 {
+        return -1;
+    }
+    0
+}
+
+unsafe fn read_bytes(buf: &mut [u8]) -> libc::c_int {
+    if buf.is_empty() {
+        return -1;
+    }
+    buf[0] = 1;
+    buf.len() as libc::c_int
+}
+
+fn report(_code: libc::c_int) {}
+
+fn main() {
+    unsafe {
+        let rc = process(5);
+        if rc != 0 {
+            report(rc);
+        }
+
+        let mut buf = [0u8; 4];
+        let n = read_bytes(&mut buf);
+        let total = n + check_range(3);
+        report(total);
+    }
+}
@@ -0,0 +1,46 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+extern "C" {
+    fn __errno_location() -> *mut libc::c_int;
+    fn read(fd: libc::c_int, buf: *mut libc::c_void, count: libc::size_t) -> libc::ssize_t;
+}
+
+pub const EINTR: libc::c_int = 4;
+pub const EACCES: libc::c_int = 13;
+
+unsafe fn read_retry(fd: libc::c_int, buf: *mut libc::c_void, n: libc::size_t) -> libc::ssize_t {
+    loop {
+        let r = read(fd, buf, n);
+        if r < 0 && *__errno_location() == EINTR {
+            continue;
+        }
+        return r;
+    }
+}
+
+unsafe fn would_block() -> bool {
+    *__errno_location() != libc::EAGAIN
+}
+
+unsafe fn denied() -> bool {
+    *__errno_location() == EACCES
+}
+
+unsafe fn last_error() -> libc::c_int {
+    let err = *__errno_location();
+    err
+}
+
+unsafe fn reset_errno() {
+    *__errno_location() = 0;
+}
+
+fn main() {
+    unsafe {
+        let mut buf = [0u8; 16];
+        read_retry(0, buf.as_mut_ptr() as *mut libc::c_void, 16);
+        println!("{} {} {}", would_block(), denied(), last_error());
+        reset_errno();
+    }
+}
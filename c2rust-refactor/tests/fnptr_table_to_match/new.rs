@@ -0,0 +1,56 @@
+extern crate libc;
+
+unsafe extern "C" fn op_inc(x: libc::c_int) -> libc::c_int {
+    x + 1
+}
+
+unsafe extern "C" fn op_dec(x: libc::c_int) -> libc::c_int {
+    x - 1
+}
+
+unsafe extern "C" fn op_add(x: libc::c_int, y: libc::c_int) -> libc::c_int {
+    x + y
+}
+unsafe fn dispatch_unary(index: usize, arg0: libc::c_int) -> libc::c_int {
+    match index {
+        0 => op_inc(arg0),
+        2 => op_dec(arg0),
+        _ => panic!("no function at index {} of `UNARY`", index),
+    }
+}
+
+static mut BINARY: [Option<unsafe extern "C" fn(libc::c_int, libc::c_int) -> libc::c_int>; 1] =
+    [Some(op_add)];
+unsafe fn dispatch_binary(index: usize, arg0: libc::c_int, arg1: libc::c_int) -> libc::c_int {
+    match index {
+        0 => op_add(arg0, arg1),
+        _ => panic!("no function at index {} of `BINARY`", index),
+    }
+}
+
+static mut PATCHED: [Option<unsafe extern "C" fn(libc::c_int) -> libc::c_int>; 2] =
+    [Some(op_inc), Some(op_dec)];
+
+unsafe fn run_unary(op: libc::c_int, x: libc::c_int) -> libc::c_int {
+    dispatch_unary(op as usize, x)
+}
+
+unsafe fn run_binary(op: libc::c_int, x: libc::c_int) -> libc::c_int {
+    if BINARY[op as usize].is_none() {
+        return 0;
+    }
+    dispatch_binary(op as usize, x, x)
+}
+
+unsafe fn run_patched(op: libc::c_int, x: libc::c_int) -> libc::c_int {
+    PATCHED[1] = Some(op_inc);
+    PATCHED[op as usize].expect("non-null function pointer")(x)
+}
+
+fn main() {
+    unsafe {
+        run_unary(0, 1);
+        run_binary(0, 1);
+        run_patched(0, 1);
+    }
+}
@@ -0,0 +1,46 @@
+extern crate libc;
+
+unsafe extern "C" fn op_inc(x: libc::c_int) -> libc::c_int {
+    x + 1
+}
+
+unsafe extern "C" fn op_dec(x: libc::c_int) -> libc::c_int {
+    x - 1
+}
+
+unsafe extern "C" fn op_add(x: libc::c_int, y: libc::c_int) -> libc::c_int {
+    x + y
+}
+
+static mut UNARY: [Option<unsafe extern "C" fn(libc::c_int) -> libc::c_int>; 3] =
+    [Some(op_inc), None, Some(op_dec)];
+
+static mut BINARY: [Option<unsafe extern "C" fn(libc::c_int, libc::c_int) -> libc::c_int>; 1] =
+    [Some(op_add)];
+
+static mut PATCHED: [Option<unsafe extern "C" fn(libc::c_int) -> libc::c_int>; 2] =
+    [Some(op_inc), Some(op_dec)];
+
+unsafe fn run_unary(op: libc::c_int, x: libc::c_int) -> libc::c_int {
+    UNARY[op as usize].expect("non-null function pointer")(x)
+}
+
+unsafe fn run_binary(op: libc::c_int, x: libc::c_int) -> libc::c_int {
+    if BINARY[op as usize].is_none() {
+        return 0;
+    }
+    BINARY[op as usize].expect("non-null function pointer")(x, x)
+}
+
+unsafe fn run_patched(op: libc::c_int, x: libc::c_int) -> libc::c_int {
+    PATCHED[1] = Some(op_inc);
+    PATCHED[op as usize].expect("non-null function pointer")(x)
+}
+
+fn main() {
+    unsafe {
+        run_unary(0, 1);
+        run_binary(0, 1);
+        run_patched(0, 1);
+    }
+}
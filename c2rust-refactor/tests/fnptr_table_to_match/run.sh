@@ -0,0 +1,11 @@
+#!/bin/sh
+
+# work around System Integrity Protection on macOS
+if [ `uname` = 'Darwin' ]; then
+    export LD_LIBRARY_PATH=$not_LD_LIBRARY_PATH
+fi
+
+$refactor \
+    select target 'crate; desc(item && name("^(UNARY|BINARY|PATCHED)$"));' \; \
+    fnptr_table_to_match delete_unused=1 \
+    -- old.rs $rustflags
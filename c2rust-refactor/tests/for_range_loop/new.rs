@@ -0,0 +1,55 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+unsafe fn convertible(n: libc::c_int) -> libc::c_int {
+    let mut sum: libc::c_int = 0;
+    for i in 0..n {
+        sum += i;
+    }
+    for j in (0..=n as libc::c_uint).step_by(2 as usize) {
+        sum += j as libc::c_int;
+    }
+    return sum;
+}
+
+unsafe fn downward(n: libc::c_int) -> libc::c_int {
+    let mut sum: libc::c_int = 0;
+    for i in (0 + 1..=n).rev() {
+        sum += i;
+    }
+    return sum;
+}
+
+unsafe fn escapes(n: libc::c_int) -> libc::c_int {
+    let mut i: libc::c_int = 0;
+    while i < n {
+        i += 1
+    }
+    return i;
+}
+
+unsafe fn bound_modified(mut n: libc::c_int) -> libc::c_int {
+    let mut sum: libc::c_int = 0;
+    let mut i: libc::c_int = 0;
+    while i < n {
+        sum += i;
+        n -= 1;
+        i += 1
+    }
+    return sum;
+}
+
+unsafe fn skipped_increment(n: libc::c_int) -> libc::c_int {
+    let mut sum: libc::c_int = 0;
+    let mut i: libc::c_int = 0;
+    while i < n {
+        if sum > 10 {
+            continue;
+        }
+        sum += i;
+        i += 1
+    }
+    return sum;
+}
+
+fn main() {}
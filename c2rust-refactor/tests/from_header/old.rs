@@ -0,0 +1,29 @@
+#![feature(register_tool)]
+#![register_tool(c2rust)]
+
+#[c2rust::header_src = "/src/include/parser.h:2"]
+pub mod parser_h {
+    pub fn init() -> i32 {
+        1
+    }
+
+    pub fn next_token(pos: i32) -> i32 {
+        pos + 1
+    }
+
+    pub static MAX_DEPTH: i32 = 16;
+}
+
+#[c2rust::header_src = "/src/include/lexer.h:3"]
+pub mod lexer_h {
+    pub fn init() -> i32 {
+        2
+    }
+}
+
+use self::parser_h::{next_token, MAX_DEPTH};
+
+fn main() {
+    let a = parser_h::init() + lexer_h::init();
+    println!("{} {}", next_token(a), MAX_DEPTH);
+}
@@ -0,0 +1,11 @@
+#!/bin/sh
+
+# work around System Integrity Protection on macOS
+if [ `uname` = 'Darwin' ]; then
+    export LD_LIBRARY_PATH=$not_LD_LIBRARY_PATH
+fi
+
+$refactor \
+    select target 'crate; desc(from_header("parser.h") && kind(fn));' \; \
+    rename_items_regex '^(.*)$' 'parser_$1' target \
+    -- old.rs $rustflags
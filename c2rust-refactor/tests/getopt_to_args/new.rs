@@ -0,0 +1,104 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+extern "C" {
+    fn getopt(
+        ___argc: libc::c_int,
+        ___argv: *const *mut libc::c_char,
+        __shortopts: *const libc::c_char,
+    ) -> libc::c_int;
+    static mut optarg: *mut libc::c_char;
+    static mut optind: libc::c_int;
+    fn atoi(__nptr: *const libc::c_char) -> libc::c_int;
+    fn puts(__s: *const libc::c_char) -> libc::c_int;
+}
+
+unsafe fn main_0(mut argc: libc::c_int, mut argv: *mut *mut libc::c_char) -> libc::c_int {
+    let mut c: libc::c_int = 0;
+    let mut all: libc::c_int = 0 as libc::c_int;
+    let mut verbose: libc::c_int = 0 as libc::c_int;
+    let mut count: libc::c_int = 1 as libc::c_int;
+    let getopt_args: Vec<String> = ::std::env::args().collect();
+    let mut getopt_optargs: Vec<::std::ffi::CString> = Vec::new();
+    let mut getopt_ind: usize = 1;
+    while getopt_ind < getopt_args.len() {
+        let getopt_arg = getopt_args[getopt_ind].clone();
+        if getopt_arg == "--" {
+            getopt_ind += 1;
+            break;
+        }
+        if !getopt_arg.starts_with('-') || getopt_arg == "-" {
+            break;
+        }
+        getopt_ind += 1;
+        let mut getopt_pos = 1;
+        while getopt_pos < getopt_arg.len() {
+            let mut getopt_opt = getopt_arg.as_bytes()[getopt_pos];
+            getopt_pos += 1;
+            let mut getopt_optarg = String::new();
+            if b"c".contains(&getopt_opt) {
+                if getopt_pos < getopt_arg.len() {
+                    getopt_optarg =
+                        String::from_utf8_lossy(&getopt_arg.as_bytes()[getopt_pos..]).into_owned();
+                    getopt_pos = getopt_arg.len();
+                } else if getopt_ind < getopt_args.len() {
+                    getopt_optarg = getopt_args[getopt_ind].clone();
+                    getopt_ind += 1;
+                } else {
+                    getopt_opt = b'?';
+                }
+            } else if !b"acv".contains(&getopt_opt) {
+                getopt_opt = b'?';
+            }
+            let getopt_optarg_c = ::std::ffi::CString::new(
+                getopt_optarg.split('\0').next().unwrap_or_default(),
+            )
+            .unwrap_or_default();
+            let getopt_optarg_ptr = getopt_optarg_c.as_ptr() as *mut ::std::os::raw::c_char;
+            getopt_optargs.push(getopt_optarg_c);
+            c = getopt_opt as i32;
+            match c {
+                97 => {
+                    all = 1 as libc::c_int;
+                }
+                99 => {
+                    count = atoi(getopt_optarg_ptr);
+                }
+                118 => {
+                    verbose = 1 as libc::c_int;
+                }
+                _ => return 2 as libc::c_int,
+            }
+        }
+    }
+    let mut i: libc::c_int = getopt_ind as i32;
+    while i < argc {
+        let mut n: libc::c_int = 0 as libc::c_int;
+        while n < count {
+            if all != 0 || verbose != 0 {
+                puts(*argv.offset(i as isize));
+            }
+            n += 1;
+        }
+        i += 1;
+    }
+    return 0 as libc::c_int;
+}
+
+pub fn main() {
+    let mut args: Vec<*mut libc::c_char> = Vec::new();
+    for arg in ::std::env::args() {
+        args.push(
+            ::std::ffi::CString::new(arg)
+                .expect("Failed to convert argument into CString.")
+                .into_raw(),
+        );
+    }
+    args.push(::std::ptr::null_mut());
+    unsafe {
+        ::std::process::exit(main_0(
+            (args.len() - 1) as libc::c_int,
+            args.as_mut_ptr() as *mut *mut libc::c_char,
+        ) as i32)
+    }
+}
@@ -0,0 +1,73 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+extern "C" {
+    fn getopt(
+        ___argc: libc::c_int,
+        ___argv: *const *mut libc::c_char,
+        __shortopts: *const libc::c_char,
+    ) -> libc::c_int;
+    static mut optarg: *mut libc::c_char;
+    static mut optind: libc::c_int;
+    fn atoi(__nptr: *const libc::c_char) -> libc::c_int;
+    fn puts(__s: *const libc::c_char) -> libc::c_int;
+}
+
+unsafe fn main_0(mut argc: libc::c_int, mut argv: *mut *mut libc::c_char) -> libc::c_int {
+    let mut c: libc::c_int = 0;
+    let mut all: libc::c_int = 0 as libc::c_int;
+    let mut verbose: libc::c_int = 0 as libc::c_int;
+    let mut count: libc::c_int = 1 as libc::c_int;
+    loop {
+        c = getopt(
+            argc,
+            argv as *const *mut libc::c_char,
+            b"ac:v\x00" as *const u8 as *const libc::c_char,
+        );
+        if !(c != -(1 as libc::c_int)) {
+            break;
+        }
+        match c {
+            97 => {
+                all = 1 as libc::c_int;
+            }
+            99 => {
+                count = atoi(optarg);
+            }
+            118 => {
+                verbose = 1 as libc::c_int;
+            }
+            _ => return 2 as libc::c_int,
+        }
+    }
+    let mut i: libc::c_int = optind;
+    while i < argc {
+        let mut n: libc::c_int = 0 as libc::c_int;
+        while n < count {
+            if all != 0 || verbose != 0 {
+                puts(*argv.offset(i as isize));
+            }
+            n += 1;
+        }
+        i += 1;
+    }
+    return 0 as libc::c_int;
+}
+
+pub fn main() {
+    let mut args: Vec<*mut libc::c_char> = Vec::new();
+    for arg in ::std::env::args() {
+        args.push(
+            ::std::ffi::CString::new(arg)
+                .expect("Failed to convert argument into CString.")
+                .into_raw(),
+        );
+    }
+    args.push(::std::ptr::null_mut());
+    unsafe {
+        ::std::process::exit(main_0(
+            (args.len() - 1) as libc::c_int,
+            args.as_mut_ptr() as *mut *mut libc::c_char,
+        ) as i32)
+    }
+}
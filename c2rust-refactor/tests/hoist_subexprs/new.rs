@@ -0,0 +1,50 @@
+#[derive(Copy, Clone)]
+pub struct Item {
+    pub weight: u8,
+    pub flags: u8,
+}
+
+pub struct Node {
+    pub data: Vec<Item>,
+    pub total: i64,
+}
+
+pub unsafe fn score(node: *mut Node, i: i32) -> i32 {
+    let weight: i32 = (*(*node).data.as_mut_ptr().offset(i as isize)).weight as i32;
+    let hi = weight * 256 + weight;
+    if weight > 100 {
+        return hi;
+    }
+    hi / 2
+}
+
+pub unsafe fn bump(node: *mut Node, i: i32) -> i64 {
+    let before = (*(*node).data.as_mut_ptr().offset(i as isize)).weight as i64;
+    (*(*node).data.as_mut_ptr().offset(i as isize)).weight += 1;
+    before + (*(*node).data.as_mut_ptr().offset(i as isize)).weight as i64 + (*node).total
+}
+
+pub unsafe fn unmarked(node: *mut Node) -> i32 {
+    (*(*node).data.as_mut_ptr()).flags as i32 + (*(*node).data.as_mut_ptr()).flags as i32
+}
+
+fn main() {
+    let mut node = Node {
+        data: vec![
+            Item {
+                weight: 7,
+                flags: 1
+            };
+            4
+        ],
+        total: 0,
+    };
+    unsafe {
+        println!(
+            "{} {} {}",
+            score(&mut node, 1),
+            bump(&mut node, 2),
+            unmarked(&mut node)
+        );
+    }
+}
@@ -0,0 +1,59 @@
+#[derive(PartialEq)]
+enum Color {
+    Red,
+    Green,
+    Blue,
+}
+
+fn describe(x: i32) -> &'static str {
+    match x {
+        0 => {
+            "zero"
+        }
+        1 | 2 => {
+            "small"
+        }
+        -1 => {
+            "minus one"
+        }
+        100 => {
+            "hundred"
+        }
+        _ => {
+            "other"
+        }
+    }
+}
+
+fn count(c: Color, counts: &mut [u32; 3]) {
+    match c {
+        Color::Red => {
+            counts[0] += 1;
+        }
+        Color::Green => {
+            counts[1] += 1;
+        }
+        Color::Blue => {
+            counts[2] += 1;
+        }
+        _ => {}
+    }
+}
+
+fn bucket(x: i32) -> i32 {
+    if x == 0 {
+        0
+    } else if x < 10 {
+        1
+    } else if x == 10 {
+        2
+    } else {
+        3
+    }
+}
+
+fn main() {
+    let mut counts = [0; 3];
+    count(Color::Red, &mut counts);
+    println!("{} {}", describe(1), bucket(11));
+}
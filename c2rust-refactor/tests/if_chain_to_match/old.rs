@@ -0,0 +1,48 @@
+#[derive(PartialEq)]
+enum Color {
+    Red,
+    Green,
+    Blue,
+}
+
+fn describe(x: i32) -> &'static str {
+    if x == 0 {
+        "zero"
+    } else if x == 1 || x == 2 {
+        "small"
+    } else if x == -1 {
+        "minus one"
+    } else if 100 == x {
+        "hundred"
+    } else {
+        "other"
+    }
+}
+
+fn count(c: Color, counts: &mut [u32; 3]) {
+    if c == Color::Red {
+        counts[0] += 1;
+    } else if c == Color::Green {
+        counts[1] += 1;
+    } else if c == Color::Blue {
+        counts[2] += 1;
+    }
+}
+
+fn bucket(x: i32) -> i32 {
+    if x == 0 {
+        0
+    } else if x < 10 {
+        1
+    } else if x == 10 {
+        2
+    } else {
+        3
+    }
+}
+
+fn main() {
+    let mut counts = [0; 3];
+    count(Color::Red, &mut counts);
+    println!("{} {}", describe(1), bucket(11));
+}
@@ -0,0 +1,69 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+pub struct Point {
+    pub x: i32,
+    pub y: i32,
+}
+
+pub struct List {
+    pub items: [Point; 16],
+    pub count: u32,
+}
+
+impl List {
+    pub fn sum_x(&self) -> i32 {
+        let mut total = 0;
+        for i in 0..self.count {
+            let item = &self.items[i as usize];
+            total += item.x;
+        }
+        total
+    }
+
+    pub fn shift(&mut self, dx: i32) {
+        for i in 0..self.count {
+            self.items[i as usize].x += dx;
+        }
+    }
+}
+
+pub fn dot(a: &[f64], b: &[f64], n: usize) -> f64 {
+    let mut sum = 0.0;
+    for i in 0..n {
+        sum += a[i] * b[i];
+    }
+    sum
+}
+
+pub fn scale_by_index(vals: &mut [i32; 8], n: i32) {
+    if n < 0 {
+        return;
+    }
+    for i in 0..n {
+        vals[i as usize] *= i;
+    }
+}
+
+pub fn clear(flags: &mut [bool; 8]) {
+    for i in 0..flags.len() {
+        flags[i] = false;
+    }
+}
+
+// `n` may be negative, so it's left alone.
+pub fn scale_first(vals: &mut [i32; 8], n: i32) {
+    for i in 0..n {
+        vals[i as usize] *= 2;
+    }
+}
+
+// `v` grows inside the loop, so it's left alone.
+pub fn dup_all(v: &mut Vec<i32>, n: usize) {
+    for i in 0..n {
+        let x = v[i];
+        v.push(x);
+    }
+}
+
+fn main() {}
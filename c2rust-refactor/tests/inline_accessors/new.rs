@@ -0,0 +1,27 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct widget {
+    pub width: libc::c_int,
+    pub height: libc::c_int,
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn widget_get_height(mut w: *const widget) -> libc::c_int {
+    return (*w).height;
+}
+
+unsafe fn grow(mut w: *mut widget) -> libc::c_int {
+    (*w).width = (*w).width + 1 as libc::c_int;
+    return (*w).width * (*w).height;
+}
+
+fn main() {
+    let mut w = widget { width: 2, height: 3 };
+    unsafe {
+        w.width = 4 as libc::c_int;
+        grow(&mut w);
+    }
+}
@@ -0,0 +1,39 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct widget {
+    pub width: libc::c_int,
+    pub height: libc::c_int,
+}
+impl widget {
+    fn width(&self) -> libc::c_int {
+        self.width
+    }
+    fn set_width(&mut self, width: libc::c_int) {
+        self.width = width;
+    }
+    pub fn height(&self) -> libc::c_int {
+        self.height
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn widget_get_height(mut w: *const widget) -> libc::c_int {
+    return (*w).height;
+}
+
+unsafe fn grow(mut w: *mut widget) -> libc::c_int {
+    (*w).set_width((*w).width() + 1 as libc::c_int);
+    return (*w).width() * (*w).height();
+}
+
+fn main() {
+    let mut w = widget { width: 2, height: 3 };
+    let h = w.height();
+    unsafe {
+        w.set_width(h);
+        grow(&mut w);
+    }
+}
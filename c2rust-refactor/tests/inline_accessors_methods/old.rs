@@ -0,0 +1,40 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct widget {
+    pub width: libc::c_int,
+    pub height: libc::c_int,
+}
+
+unsafe extern "C" fn widget_get_width(mut w: *const widget) -> libc::c_int {
+    return (*w).width;
+}
+
+unsafe extern "C" fn widget_set_width(mut w: *mut widget, mut width: libc::c_int) {
+    (*w).width = width;
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn widget_get_height(mut w: *const widget) -> libc::c_int {
+    return (*w).height;
+}
+
+fn widget_height_ref(w: &widget) -> libc::c_int {
+    w.height
+}
+
+unsafe fn grow(mut w: *mut widget) -> libc::c_int {
+    widget_set_width(w, widget_get_width(w) + 1 as libc::c_int);
+    return widget_get_width(w) * widget_get_height(w);
+}
+
+fn main() {
+    let mut w = widget { width: 2, height: 3 };
+    let h = widget_height_ref(&w);
+    unsafe {
+        widget_set_width(&mut w, h);
+        grow(&mut w);
+    }
+}
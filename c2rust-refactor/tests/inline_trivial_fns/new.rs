@@ -0,0 +1,28 @@
+mod imp {
+    pub fn area_impl(w: i32, h: i32) -> i32 {
+        w * h
+    }
+
+    pub fn scale_impl(p: &mut i32, by: i32) {
+        *p *= by;
+    }
+}
+
+use imp::{area_impl, scale_impl};
+
+#[no_mangle]
+pub extern "C" fn c_area(w: i32, h: i32) -> i32 {
+    area_impl(w, h)
+}
+
+mod user {
+    pub fn unit_area() -> i32 {
+        crate::imp::area_impl(1, 1)
+    }
+}
+
+fn main() {
+    let mut x = area_impl(2, 3);
+    scale_impl(&mut x, 2);
+    println!("{} {} {}", x, c_area(4, 5), user::unit_area());
+}
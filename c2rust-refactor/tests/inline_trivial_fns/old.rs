@@ -0,0 +1,40 @@
+mod imp {
+    pub fn area_impl(w: i32, h: i32) -> i32 {
+        w * h
+    }
+
+    pub fn scale_impl(p: &mut i32, by: i32) {
+        *p *= by;
+    }
+}
+
+use imp::{area_impl, scale_impl};
+
+fn area(w: i32, h: i32) -> i32 {
+    area_impl(w, h)
+}
+
+fn scale_ptr(p: &mut i32, by: i32) {
+    scale_impl(p, by)
+}
+
+fn scale(p: &mut i32, by: i32) {
+    scale_ptr(p, by);
+}
+
+#[no_mangle]
+pub extern "C" fn c_area(w: i32, h: i32) -> i32 {
+    area(w, h)
+}
+
+mod user {
+    pub fn unit_area() -> i32 {
+        super::area(1, 1)
+    }
+}
+
+fn main() {
+    let mut x = area(2, 3);
+    scale(&mut x, 2);
+    println!("{} {} {}", x, c_area(4, 5), user::unit_area());
+}
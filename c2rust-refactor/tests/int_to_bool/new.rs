@@ -0,0 +1,61 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+extern "C" {
+    fn abs(x: libc::c_int) -> libc::c_int;
+}
+
+mod state {
+    pub struct Parser {
+        pub pos: libc::c_int,
+        pub done: bool,
+    }
+
+    pub fn new_parser() -> Parser {
+        Parser { pos: 0, done: false }
+    }
+
+    pub fn finish(p: &mut Parser) {
+        p.done = true;
+    }
+}
+
+mod run {
+    use crate::state::Parser;
+
+    pub fn step(p: &mut Parser, limit: libc::c_int) {
+        if p.done {
+            return;
+        }
+        p.pos += 1;
+        p.done = p.pos >= limit;
+    }
+}
+
+fn count_negatives(xs: &[libc::c_int]) -> libc::c_int {
+    let mut found: bool = false;
+    let mut mode: libc::c_int = 0;
+    let mut n: libc::c_int = 0;
+    for &x in xs {
+        if x < 0 {
+            found = true;
+            n += 1;
+        }
+        if x > 100 {
+            mode = 2;
+        }
+    }
+    if !found {
+        return unsafe { abs(mode) };
+    }
+    unsafe { abs(found as libc::c_int) + n }
+}
+
+fn main() {
+    let mut p = state::new_parser();
+    while !p.done {
+        run::step(&mut p, 10);
+    }
+    state::finish(&mut p);
+    println!("{}", count_negatives(&[1, -2, 300]));
+}
@@ -0,0 +1,12 @@
+#!/bin/sh
+
+# work around System Integrity Protection on macOS
+if [ `uname` = 'Darwin' ]; then
+    export LD_LIBRARY_PATH=$not_LD_LIBRARY_PATH
+fi
+
+$refactor \
+    select target 'crate; desc(match_pat(found) || match_pat(mode) ||
+        (field && name("done")));' \; \
+    int_to_bool \
+    -- old.rs $rustflags
@@ -0,0 +1,38 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+extern "C" {
+    fn abs(x: libc::c_int) -> libc::c_int;
+}
+
+struct Point {
+    x: i32,
+    y: i64,
+    tag: i8,
+}
+
+static mut COUNT: usize = 0;
+
+mod math {
+
+    pub fn double(x: i32) -> i32 {
+        x * 2
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn exported(x: libc::c_int) -> libc::c_uint {
+    let y: f64 = x as f64;
+    y as u32
+}
+
+fn main() {
+    let p = Point { x: 1, y: 2, tag: 0 };
+    let n: u64 = p.x as i64 as u64;
+    let ptr: *const u8 = &(n as u8);
+    let _ = (p.y, p.tag, ptr);
+    unsafe {
+        COUNT += math::double(abs(-3)) as usize;
+        exported(COUNT as i32);
+    }
+}
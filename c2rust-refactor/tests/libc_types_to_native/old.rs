@@ -0,0 +1,39 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+extern "C" {
+    fn abs(x: libc::c_int) -> libc::c_int;
+}
+
+struct Point {
+    x: libc::c_int,
+    y: libc::c_long,
+    tag: libc::c_char,
+}
+
+static mut COUNT: libc::size_t = 0;
+
+mod math {
+    use libc;
+
+    pub fn double(x: libc::c_int) -> libc::c_int {
+        x * 2
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn exported(x: libc::c_int) -> libc::c_uint {
+    let y: libc::c_double = x as libc::c_double;
+    y as libc::c_uint
+}
+
+fn main() {
+    let p = Point { x: 1, y: 2, tag: 0 };
+    let n: libc::c_ulong = p.x as libc::c_long as libc::c_ulong;
+    let ptr: *const libc::c_uchar = &(n as libc::c_uchar);
+    let _ = (p.y, p.tag, ptr);
+    unsafe {
+        COUNT += math::double(abs(-3)) as libc::size_t;
+        exported(COUNT as libc::c_int);
+    }
+}
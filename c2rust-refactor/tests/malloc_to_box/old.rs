@@ -0,0 +1,72 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+extern "C" {
+    fn malloc(_: libc::c_ulong) -> *mut libc::c_void;
+    fn calloc(_: libc::c_ulong, _: libc::c_ulong) -> *mut libc::c_void;
+    fn free(_: *mut libc::c_void);
+}
+
+#[derive(Copy, Clone, Default)]
+#[repr(C)]
+pub struct Point {
+    pub x: libc::c_int,
+    pub y: libc::c_int,
+}
+
+#[repr(C)]
+pub struct Handler {
+    pub name: &'static str,
+}
+
+unsafe fn point_roundtrip() {
+    let p: *mut Point = malloc(::std::mem::size_of::<Point>() as libc::c_ulong) as *mut Point;
+    (*p).x = 1;
+    free(p as *mut libc::c_void);
+}
+
+unsafe fn zeroed_point() -> libc::c_int {
+    let mut q: *mut Point = 0 as *mut Point;
+    q = calloc(1 as libc::c_ulong, ::std::mem::size_of::<Point>() as libc::c_ulong) as *mut Point;
+    let x = (*q).x;
+    free(q as *mut libc::c_void);
+    x
+}
+
+unsafe fn variable_size(n: libc::c_ulong) {
+    let buf: *mut libc::c_char = malloc(n) as *mut libc::c_char;
+    free(buf as *mut libc::c_void);
+}
+
+unsafe fn free_unconverted(p: *mut Point) {
+    free(p as *mut libc::c_void);
+}
+
+unsafe fn maybe_borrowed(borrowed: *mut Point, own: bool) {
+    let mut p: *mut Point = borrowed;
+    if own {
+        p = malloc(::std::mem::size_of::<Point>() as libc::c_ulong) as *mut Point;
+    }
+    (*p).y = 2;
+    free(p as *mut libc::c_void);
+}
+
+unsafe fn keep(_: *mut Point) {}
+
+// The pointer in `p` is passed on and returned, so it may still be freed elsewhere.
+unsafe fn escaping() -> *mut Point {
+    let p: *mut Point = malloc(::std::mem::size_of::<Point>() as libc::c_ulong) as *mut Point;
+    keep(p);
+    return p;
+}
+
+// A zeroed `&str` isn't valid, so this is left alone.
+unsafe fn zeroed_handler() {
+    let h: *mut Handler = calloc(
+        1 as libc::c_ulong,
+        ::std::mem::size_of::<Handler>() as libc::c_ulong,
+    ) as *mut Handler;
+    free(h as *mut libc::c_void);
+}
+
+fn main() {}
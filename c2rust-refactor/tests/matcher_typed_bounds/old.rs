@@ -0,0 +1,22 @@
+#[derive(Clone, Copy)]
+struct Counter(u32);
+
+impl Counter {
+    fn wrapping_add(self, n: u32) -> Counter {
+        Counter(self.0.wrapping_add(n))
+    }
+
+    fn saturating_sub(self, n: u32) -> Counter {
+        Counter(self.0.saturating_sub(n))
+    }
+}
+
+fn main() {
+    let a = 1u32;
+    let b = Counter(2);
+    let c = a.wrapping_add(3);
+    let d = b.wrapping_add(3);
+    let e = a.saturating_sub(1);
+    let f = b.saturating_sub(1);
+    println!("{} {} {} {}", c, d.0, e, f.0);
+}
@@ -0,0 +1,22 @@
+#!/bin/sh
+
+# work around System Integrity Protection on macOS
+if [ `uname` = 'Darwin' ]; then
+    export LD_LIBRARY_PATH=$not_LD_LIBRARY_PATH
+fi
+
+$refactor \
+    rewrite_expr 'typed!(__x, u32,).wrapping_add(__y)' '__x + __y' \; \
+    rewrite_expr 'typed!(__x, ?Sized + ::std::ops::Add).saturating_sub(__y)' '__x - __y' \; \
+    -- old.rs $rustflags
+
+# Malformed patterns are reported as errors instead of never matching.
+status=0
+for pat in 'typed!(__x u32)' 'typed!(__x,)' 'typed!(__x, u32 u64)'; do
+    $refactor_bin -r print rewrite_expr "$pat" '__x' -- old.rs $rustflags \
+        >/dev/null 2>typed_error.out && status=1
+    grep -q '^error' typed_error.out || status=1
+    grep -q 'panicked' typed_error.out && status=1
+done
+rm -f typed_error.out
+exit $status
@@ -0,0 +1,42 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+extern "C" {
+    fn memcpy(_: *mut libc::c_void, _: *const libc::c_void, _: libc::c_ulong) -> *mut libc::c_void;
+    fn memmove(_: *mut libc::c_void, _: *const libc::c_void, _: libc::c_ulong)
+        -> *mut libc::c_void;
+}
+
+unsafe fn arrays() {
+    let mut a: [libc::c_int; 4] = [0; 4];
+    let b: [libc::c_int; 4] = [1, 2, 3, 4];
+    a[..4 as usize].copy_from_slice(&b[..4 as usize]);
+}
+
+unsafe fn pointers(dst: *mut libc::c_int, src: *const libc::c_int, n: libc::c_int) {
+    ::std::ptr::copy_nonoverlapping(src, dst, n as usize);
+    ::std::ptr::copy(dst.offset(1), dst, n as usize);
+}
+
+unsafe fn bytes(dst: *mut libc::c_char, src: *const libc::c_char) {
+    ::std::ptr::copy_nonoverlapping(src as *const u8, dst as *mut u8, 16 as usize);
+}
+
+#[repr(C)]
+pub struct S {
+    pub x: libc::c_int,
+    pub y: libc::c_int,
+}
+
+unsafe fn structs() {
+    let mut d: S = S { x: 0, y: 0 };
+    let s: S = S { x: 1, y: 2 };
+    ::std::ptr::copy_nonoverlapping(&s as *const S, &mut d as *mut S, 1 as usize);
+    ::std::ptr::copy_nonoverlapping(
+        &s as *const S as *const u8,
+        &mut d as *mut S as *mut u8,
+        4 as usize,
+    );
+}
+
+fn main() {}
@@ -0,0 +1,62 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+extern "C" {
+    fn memcpy(_: *mut libc::c_void, _: *const libc::c_void, _: libc::c_ulong) -> *mut libc::c_void;
+    fn memmove(_: *mut libc::c_void, _: *const libc::c_void, _: libc::c_ulong)
+        -> *mut libc::c_void;
+}
+
+unsafe fn arrays() {
+    let mut a: [libc::c_int; 4] = [0; 4];
+    let b: [libc::c_int; 4] = [1, 2, 3, 4];
+    memcpy(
+        a.as_mut_ptr() as *mut libc::c_void,
+        b.as_ptr() as *const libc::c_void,
+        (4 as libc::c_ulong).wrapping_mul(::std::mem::size_of::<libc::c_int>() as libc::c_ulong),
+    );
+}
+
+unsafe fn pointers(dst: *mut libc::c_int, src: *const libc::c_int, n: libc::c_int) {
+    memcpy(
+        dst as *mut libc::c_void,
+        src as *const libc::c_void,
+        (n as libc::c_ulong).wrapping_mul(::std::mem::size_of::<libc::c_int>() as libc::c_ulong),
+    );
+    memmove(
+        dst as *mut libc::c_void,
+        dst.offset(1) as *const libc::c_void,
+        (n as libc::c_ulong).wrapping_mul(::std::mem::size_of::<libc::c_int>() as libc::c_ulong),
+    );
+}
+
+unsafe fn bytes(dst: *mut libc::c_char, src: *const libc::c_char) {
+    memcpy(
+        dst as *mut libc::c_void,
+        src as *const libc::c_void,
+        16 as libc::c_ulong,
+    );
+}
+
+#[repr(C)]
+pub struct S {
+    pub x: libc::c_int,
+    pub y: libc::c_int,
+}
+
+unsafe fn structs() {
+    let mut d: S = S { x: 0, y: 0 };
+    let s: S = S { x: 1, y: 2 };
+    memcpy(
+        &mut d as *mut S as *mut libc::c_void,
+        &s as *const S as *const libc::c_void,
+        ::std::mem::size_of::<S>() as libc::c_ulong,
+    );
+    memcpy(
+        &mut d as *mut S as *mut libc::c_void,
+        &s as *const S as *const libc::c_void,
+        4 as libc::c_ulong,
+    );
+}
+
+fn main() {}
@@ -0,0 +1,36 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+extern "C" {
+    fn memset(_: *mut libc::c_void, _: libc::c_int, _: libc::c_ulong) -> *mut libc::c_void;
+}
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct Point {
+    pub x: libc::c_int,
+    pub y: libc::c_int,
+}
+
+unsafe fn bytes(p: *mut libc::c_char, n: libc::c_int) {
+    let mut buf: [libc::c_char; 64] = [1; 64];
+    buf[..64 as usize].iter_mut().for_each(|x| *x = 0);
+    ::std::ptr::write_bytes(p, 32 as u8, n as usize);
+}
+
+unsafe fn typed(arr: *mut libc::c_int, n: libc::c_int) {
+    ::std::ptr::write_bytes(arr, 0, n as usize);
+    memset(
+        arr as *mut libc::c_void,
+        1 as libc::c_int,
+        (n as libc::c_ulong).wrapping_mul(::std::mem::size_of::<libc::c_int>() as libc::c_ulong),
+    );
+}
+
+unsafe fn structs() {
+    let mut s: Point = Point { x: 1, y: 2 };
+    ::std::ptr::write_bytes(&mut s as *mut Point as *mut u8, 0, 4 as usize);
+    s = ::std::mem::zeroed();
+}
+
+fn main() {}
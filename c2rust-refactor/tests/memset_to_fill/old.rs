@@ -0,0 +1,52 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+extern "C" {
+    fn memset(_: *mut libc::c_void, _: libc::c_int, _: libc::c_ulong) -> *mut libc::c_void;
+}
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct Point {
+    pub x: libc::c_int,
+    pub y: libc::c_int,
+}
+
+unsafe fn bytes(p: *mut libc::c_char, n: libc::c_int) {
+    let mut buf: [libc::c_char; 64] = [1; 64];
+    memset(
+        buf.as_mut_ptr() as *mut libc::c_void,
+        0 as libc::c_int,
+        64 as libc::c_ulong,
+    );
+    memset(p as *mut libc::c_void, 32 as libc::c_int, n as libc::c_ulong);
+}
+
+unsafe fn typed(arr: *mut libc::c_int, n: libc::c_int) {
+    memset(
+        arr as *mut libc::c_void,
+        0 as libc::c_int,
+        (n as libc::c_ulong).wrapping_mul(::std::mem::size_of::<libc::c_int>() as libc::c_ulong),
+    );
+    memset(
+        arr as *mut libc::c_void,
+        1 as libc::c_int,
+        (n as libc::c_ulong).wrapping_mul(::std::mem::size_of::<libc::c_int>() as libc::c_ulong),
+    );
+}
+
+unsafe fn structs() {
+    let mut s: Point = Point { x: 1, y: 2 };
+    memset(
+        &mut s as *mut Point as *mut libc::c_void,
+        0 as libc::c_int,
+        4 as libc::c_ulong,
+    );
+    memset(
+        &mut s as *mut Point as *mut libc::c_void,
+        0 as libc::c_int,
+        ::std::mem::size_of::<Point>() as libc::c_ulong,
+    );
+}
+
+fn main() {}
@@ -0,0 +1,50 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+extern "C" {
+    fn rand() -> libc::c_int;
+}
+
+pub struct Buf {
+    pub len: libc::c_int,
+    pub cap: libc::c_int,
+}
+
+unsafe fn smaller(a: libc::c_int, b: libc::c_int) -> libc::c_int {
+    a.min(b)
+}
+
+unsafe fn larger(a: libc::c_int, b: libc::c_int) -> libc::c_int {
+    return a.max(b);
+}
+
+unsafe fn capped_len(buf: *mut Buf) -> libc::c_int {
+    let mut n: libc::c_int = 0;
+    n = (*buf).len.min((*buf).cap);
+    n = n.min(4096 as libc::c_int);
+    return n;
+}
+
+unsafe fn level(x: libc::c_int) -> libc::c_int {
+    x.clamp(0 as libc::c_int, 9 as libc::c_int)
+}
+
+unsafe fn empty_range(x: libc::c_int) -> libc::c_int {
+    if x < 9 as libc::c_int {
+        9 as libc::c_int
+    } else if x > 0 as libc::c_int {
+        0 as libc::c_int
+    } else {
+        x
+    }
+}
+
+unsafe fn random_below(n: libc::c_int) -> libc::c_int {
+    if rand() < n { rand() } else { n }
+}
+
+unsafe fn smaller_float(a: libc::c_double, b: libc::c_double) -> libc::c_double {
+    if a < b { a } else { b }
+}
+
+fn main() {}
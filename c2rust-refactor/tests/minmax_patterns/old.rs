@@ -0,0 +1,62 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+extern "C" {
+    fn rand() -> libc::c_int;
+}
+
+pub struct Buf {
+    pub len: libc::c_int,
+    pub cap: libc::c_int,
+}
+
+unsafe fn smaller(a: libc::c_int, b: libc::c_int) -> libc::c_int {
+    if a < b { a } else { b }
+}
+
+unsafe fn larger(a: libc::c_int, b: libc::c_int) -> libc::c_int {
+    return if a >= b { a } else { b };
+}
+
+unsafe fn capped_len(buf: *mut Buf) -> libc::c_int {
+    let mut n: libc::c_int = 0;
+    if (*buf).len < (*buf).cap {
+        n = (*buf).len;
+    } else {
+        n = (*buf).cap;
+    }
+    if n > 4096 as libc::c_int {
+        n = 4096 as libc::c_int;
+    }
+    return n;
+}
+
+unsafe fn level(x: libc::c_int) -> libc::c_int {
+    if x < 0 as libc::c_int {
+        0 as libc::c_int
+    } else if x > 9 as libc::c_int {
+        9 as libc::c_int
+    } else {
+        x
+    }
+}
+
+unsafe fn empty_range(x: libc::c_int) -> libc::c_int {
+    if x < 9 as libc::c_int {
+        9 as libc::c_int
+    } else if x > 0 as libc::c_int {
+        0 as libc::c_int
+    } else {
+        x
+    }
+}
+
+unsafe fn random_below(n: libc::c_int) -> libc::c_int {
+    if rand() < n { rand() } else { n }
+}
+
+unsafe fn smaller_float(a: libc::c_double, b: libc::c_double) -> libc::c_double {
+    if a < b { a } else { b }
+}
+
+fn main() {}
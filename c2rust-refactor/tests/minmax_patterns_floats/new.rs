@@ -0,0 +1,17 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+unsafe fn smaller(a: libc::c_double, b: libc::c_double) -> libc::c_double {
+    a.min(b)
+}
+
+unsafe fn gain(mut g: libc::c_float) -> libc::c_float {
+    g = g.max(0.0f32);
+    return g;
+}
+
+unsafe fn unit(x: libc::c_double) -> libc::c_double {
+    x.clamp(0.0f64, 1.0f64)
+}
+
+fn main() {}
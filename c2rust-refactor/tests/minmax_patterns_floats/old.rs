@@ -0,0 +1,19 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+unsafe fn smaller(a: libc::c_double, b: libc::c_double) -> libc::c_double {
+    if a < b { a } else { b }
+}
+
+unsafe fn gain(mut g: libc::c_float) -> libc::c_float {
+    if g < 0.0f32 {
+        g = 0.0f32;
+    }
+    return g;
+}
+
+unsafe fn unit(x: libc::c_double) -> libc::c_double {
+    if x > 1.0f64 { 1.0f64 } else if x < 0.0f64 { 0.0f64 } else { x }
+}
+
+fn main() {}
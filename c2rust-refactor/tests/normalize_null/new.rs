@@ -0,0 +1,31 @@
+extern crate libc;
+
+extern "C" {
+    fn free(p: *mut libc::c_void);
+}
+
+#[derive(Copy, Clone)]
+pub struct node {
+    pub next: *mut node,
+    pub name: *const libc::c_char,
+}
+
+unsafe fn spellings(n: *mut node) {
+    (*n).next = ::std::ptr::null_mut();
+    (*n).name = ::std::ptr::null();
+    (*n).next = ::std::ptr::null_mut();
+    (*n).name = ::std::ptr::null();
+    free(::std::ptr::null_mut());
+    let first = ::std::ptr::null_mut::<node>();
+    let second: *mut node = ::std::ptr::null_mut();
+    let device = 0xb8000 as *mut u16;
+}
+
+unsafe fn compare(n: *mut node) -> bool {
+    if (*n).next.is_null() {
+        return false;
+    }
+    !(*n).name.is_null()
+}
+
+fn main() {}
@@ -0,0 +1,33 @@
+extern crate libc;
+
+pub const NULL: *mut libc::c_void = 0 as *mut libc::c_void;
+
+extern "C" {
+    fn free(p: *mut libc::c_void);
+}
+
+#[derive(Copy, Clone)]
+pub struct node {
+    pub next: *mut node,
+    pub name: *const libc::c_char,
+}
+
+unsafe fn spellings(n: *mut node) {
+    (*n).next = 0 as *mut node;
+    (*n).name = ::std::ptr::null::<libc::c_char>();
+    (*n).next = ::std::mem::transmute(0usize);
+    (*n).name = NULL as *const libc::c_char;
+    free(0 as *mut libc::c_void);
+    let first = 0 as *mut node;
+    let second: *mut node = ::std::ptr::null_mut::<node>();
+    let device = 0xb8000 as *mut u16;
+}
+
+unsafe fn compare(n: *mut node) -> bool {
+    if (*n).next == 0 as *mut node {
+        return false;
+    }
+    (*n).name != NULL as *const libc::c_char
+}
+
+fn main() {}
@@ -0,0 +1,45 @@
+fn sum(xs: &[i32], n: i32) -> i32 {
+    let mut total = 0;
+    let mut i = 0;
+    while i < n {
+        total += xs[i as usize];
+        i += 1;
+    }
+    total
+}
+
+fn fill(buf: &mut [u8; 16], v: u8) {
+    let mut i = 0;
+    while i < 4 {
+        let mut j = 0;
+        while j < 4 {
+            {
+                buf[(i * 4 + j) as usize] = v;
+            }
+            j += 1;
+        }
+        i += 1;
+    }
+    {
+        buf[0] = 0;
+    }
+}
+
+fn first(v: &Vec<u32>) -> u32 {
+    v[1] + v[0]
+}
+
+fn skip(xs: &[i32]) -> i32 {
+    let mut p = xs.as_ptr();
+    unsafe {
+        p = p.offset(1);
+        *p.offset(1)
+    }
+}
+
+fn main() {
+    let mut buf = [0u8; 16];
+    fill(&mut buf, 3);
+    let v = vec![1, 2, 3];
+    println!("{} {} {}", sum(&[1, 2, 3], 3), first(&v), skip(&[1, 2, 3]));
+}
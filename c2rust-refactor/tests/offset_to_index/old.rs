@@ -0,0 +1,47 @@
+fn sum(xs: &[i32], n: i32) -> i32 {
+    let p = xs.as_ptr();
+    let mut total = 0;
+    let mut i = 0;
+    while i < n {
+        total += unsafe { *p.offset(i as isize) };
+        i += 1;
+    }
+    total
+}
+
+fn fill(buf: &mut [u8; 16], v: u8) {
+    let p = buf.as_mut_ptr();
+    let mut i = 0;
+    while i < 4 {
+        let mut j = 0;
+        while j < 4 {
+            unsafe {
+                *p.offset(i * 4).offset(j) = v;
+            }
+            j += 1;
+        }
+        i += 1;
+    }
+    unsafe {
+        *p = 0;
+    }
+}
+
+fn first(v: &Vec<u32>) -> u32 {
+    unsafe { *v.as_ptr().offset(1) + *v.as_ptr() }
+}
+
+fn skip(xs: &[i32]) -> i32 {
+    let mut p = xs.as_ptr();
+    unsafe {
+        p = p.offset(1);
+        *p.offset(1)
+    }
+}
+
+fn main() {
+    let mut buf = [0u8; 16];
+    fill(&mut buf, 3);
+    let v = vec![1, 2, 3];
+    println!("{} {} {}", sum(&[1, 2, 3], 3), first(&v), skip(&[1, 2, 3]));
+}
@@ -0,0 +1,51 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+use std::ptr;
+
+extern "C" {
+    fn free(p: *mut libc::c_void);
+}
+
+struct Node {
+    value: i32,
+}
+
+struct List<'a> {
+    name: &'a str,
+    head: Option<&'a mut Node>,
+}
+
+unsafe fn value_or(node: Option<&Node>, default: i32) -> i32 {
+    if let Some(node) = node {
+        (*node).value
+    } else {
+        default
+    }
+}
+
+fn has_head(l: &List) -> bool {
+    l.head.is_some()
+}
+
+fn main() {
+    unsafe {
+        let mut a = Node { value: 1 };
+        let mut l = List { name: "l", head: Some(&mut a) };
+        if has_head(&l) {
+            (*l.head.as_deref_mut().unwrap()).value += 1;
+        }
+        l.head = None;
+        let mut b = Node { value: l.name.len() as i32 };
+        let total = value_or(Some(&b), 0) + value_or(None, 0);
+
+        let mut cur: Option<&mut Node> = Some(&mut b);
+        if cur.is_none() {
+            return;
+        }
+        (*cur.as_deref_mut().unwrap()).value = total;
+
+        let mut buf: Option<&mut libc::c_void> = unsafe { libc::malloc(16).as_mut() };
+        free(buf.as_deref_mut().map_or(::std::ptr::null_mut(), |r| r as *mut libc::c_void));
+    }
+}
@@ -0,0 +1,51 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+use std::ptr;
+
+extern "C" {
+    fn free(p: *mut libc::c_void);
+}
+
+struct Node {
+    value: i32,
+}
+
+struct List<'a> {
+    name: &'a str,
+    head: *mut Node,
+}
+
+unsafe fn value_or(node: *const Node, default: i32) -> i32 {
+    if !node.is_null() {
+        (*node).value
+    } else {
+        default
+    }
+}
+
+fn has_head(l: &List) -> bool {
+    !l.head.is_null()
+}
+
+fn main() {
+    unsafe {
+        let mut a = Node { value: 1 };
+        let mut l = List { name: "l", head: &mut a };
+        if has_head(&l) {
+            (*l.head).value += 1;
+        }
+        l.head = ptr::null_mut();
+        let mut b = Node { value: l.name.len() as i32 };
+        let total = value_or(&b, 0) + value_or(ptr::null(), 0);
+
+        let cur: *mut Node = &mut b;
+        if cur.is_null() {
+            return;
+        }
+        (*cur).value = total;
+
+        let buf: *mut libc::c_void = libc::malloc(16);
+        free(buf);
+    }
+}
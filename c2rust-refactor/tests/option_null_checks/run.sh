@@ -0,0 +1,12 @@
+#!/bin/sh
+
+# work around System Integrity Protection on macOS
+if [ `uname` = 'Darwin' ]; then
+    export LD_LIBRARY_PATH=$not_LD_LIBRARY_PATH
+fi
+
+$refactor \
+    select target 'crate; desc((arg && any_child(match_pat(node))) ||
+        (field && name("head")) || match_pat(cur) || match_pat(buf));' \; \
+    option_null_checks \
+    -- old.rs $rustflags
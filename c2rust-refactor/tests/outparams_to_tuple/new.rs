@@ -0,0 +1,63 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+use std::ptr;
+
+unsafe fn parse_digit(c: u8, out: *mut i32) -> i32 {
+    if c < b'0' || c > b'9' {
+        return -1;
+    }
+    *out = (c - b'0') as i32;
+    0
+}
+
+unsafe fn checked_digit(c: u8) -> (i32, i32) {
+    let mut out: i32 = 0;
+    if c < b'0' || c > b'9' {
+        return (-1, out);
+    }
+    out = (c - b'0') as i32;
+    (0, out)
+}
+
+unsafe fn divmod(a: i32, b: i32) -> (i32, i32) {
+    let q: i32 = a / b;
+    let r: i32 = a % b;
+    (q, r)
+}
+
+unsafe fn get_len(s: &[u8], len: *mut usize) -> i32 {
+    *len = s.len();
+    0
+}
+
+fn main() {
+    unsafe {
+        let mut d = 0;
+        let rc = parse_digit(b'7', &mut d);
+        if rc < 0 {
+            return;
+        }
+
+        let mut e = 0;
+        let rc = {
+            let (__ret, __out0) = checked_digit(b'8');
+            e = __out0;
+            __ret
+        };
+        if rc < 0 {
+            return;
+        }
+
+        let mut q = 0;
+        let mut r = 0;
+        {
+            let (__out0, __out1) = divmod(17, d);
+            q = __out0;
+            r = __out1;
+        };
+
+        get_len(b"abc", ptr::null_mut());
+        println!("{} {} {} {}", d, e, q, r);
+    }
+}
@@ -0,0 +1,54 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+use std::ptr;
+
+unsafe fn parse_digit(c: u8, out: *mut i32) -> i32 {
+    if c < b'0' || c > b'9' {
+        return -1;
+    }
+    *out = (c - b'0') as i32;
+    0
+}
+
+unsafe fn checked_digit(c: u8, out: *mut i32) -> i32 {
+    *out = 0;
+    if c < b'0' || c > b'9' {
+        return -1;
+    }
+    *out = (c - b'0') as i32;
+    0
+}
+
+unsafe fn divmod(a: i32, b: i32, q: *mut i32, r: *mut i32) {
+    *q = a / b;
+    *r = a % b;
+}
+
+unsafe fn get_len(s: &[u8], len: *mut usize) -> i32 {
+    *len = s.len();
+    0
+}
+
+fn main() {
+    unsafe {
+        let mut d = 0;
+        let rc = parse_digit(b'7', &mut d);
+        if rc < 0 {
+            return;
+        }
+
+        let mut e = 0;
+        let rc = checked_digit(b'8', &mut e);
+        if rc < 0 {
+            return;
+        }
+
+        let mut q = 0;
+        let mut r = 0;
+        divmod(17, d, &mut q, &mut r);
+
+        get_len(b"abc", ptr::null_mut());
+        println!("{} {} {} {}", d, e, q, r);
+    }
+}
@@ -0,0 +1,11 @@
+#!/bin/sh
+
+# work around System Integrity Protection on macOS
+if [ `uname` = 'Darwin' ]; then
+    export LD_LIBRARY_PATH=$not_LD_LIBRARY_PATH
+fi
+
+$refactor \
+    select target 'crate; desc(fn && (name("parse_digit") || name("checked_digit") || name("divmod") || name("get_len")));' \; \
+    outparams_to_tuple \
+    -- old.rs $rustflags
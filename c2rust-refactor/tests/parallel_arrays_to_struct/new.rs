@@ -0,0 +1,52 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+extern "C" {
+    fn strcmp(_: *const libc::c_char, _: *const libc::c_char) -> libc::c_int;
+    fn puts(__s: *const libc::c_char) -> libc::c_int;
+}
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+struct Entry {
+    name: *const libc::c_char,
+    value: libc::c_int,
+}
+static mut table: [Entry; 3] = [
+    Entry {
+        name: b"one\x00" as *const u8 as *const libc::c_char,
+        value: 1 as libc::c_int,
+    },
+    Entry {
+        name: b"two\x00" as *const u8 as *const libc::c_char,
+        value: 2 as libc::c_int,
+    },
+    Entry {
+        name: b"three\x00" as *const u8 as *const libc::c_char,
+        value: 3 as libc::c_int,
+    },
+];
+
+unsafe fn lookup(mut name: *const libc::c_char) -> libc::c_int {
+    let mut i: usize = 0;
+    while i < table.len() {
+        if strcmp(table[i].name, name) == 0 as libc::c_int {
+            return table[i].value;
+        }
+        i = i.wrapping_add(1);
+    }
+    return -(1 as libc::c_int);
+}
+
+unsafe fn set_value(mut i: usize, mut value: libc::c_int) {
+    table[i].value = value;
+    table[i].value += 1 as libc::c_int;
+    puts(table[i].name);
+}
+
+fn main() {
+    unsafe {
+        set_value(1, 5 as libc::c_int);
+        lookup(b"two\x00" as *const u8 as *const libc::c_char);
+    }
+}
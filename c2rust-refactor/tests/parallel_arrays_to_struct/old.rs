@@ -0,0 +1,38 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+extern "C" {
+    fn strcmp(_: *const libc::c_char, _: *const libc::c_char) -> libc::c_int;
+    fn puts(__s: *const libc::c_char) -> libc::c_int;
+}
+
+static mut names: [*const libc::c_char; 3] = [
+    b"one\x00" as *const u8 as *const libc::c_char,
+    b"two\x00" as *const u8 as *const libc::c_char,
+    b"three\x00" as *const u8 as *const libc::c_char,
+];
+static mut values: [libc::c_int; 3] = [1 as libc::c_int, 2 as libc::c_int, 3 as libc::c_int];
+
+unsafe fn lookup(mut name: *const libc::c_char) -> libc::c_int {
+    let mut i: usize = 0;
+    while i < names.len() {
+        if strcmp(names[i], name) == 0 as libc::c_int {
+            return values[i];
+        }
+        i = i.wrapping_add(1);
+    }
+    return -(1 as libc::c_int);
+}
+
+unsafe fn set_value(mut i: usize, mut value: libc::c_int) {
+    values[i] = value;
+    values[i] += 1 as libc::c_int;
+    puts(names[i]);
+}
+
+fn main() {
+    unsafe {
+        set_value(1, 5 as libc::c_int);
+        lookup(b"two\x00" as *const u8 as *const libc::c_char);
+    }
+}
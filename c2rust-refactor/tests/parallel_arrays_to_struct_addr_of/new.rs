@@ -0,0 +1,34 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+extern "C" {
+    fn memset(_: *mut libc::c_void, _: libc::c_int, _: libc::c_ulong) -> *mut libc::c_void;
+}
+
+static mut names: [*const libc::c_char; 2] = [
+    b"one\x00" as *const u8 as *const libc::c_char,
+    b"two\x00" as *const u8 as *const libc::c_char,
+];
+static mut values: [libc::c_int; 2] = [1 as libc::c_int, 2 as libc::c_int];
+
+unsafe fn reset() {
+    memset(
+        values.as_mut_ptr() as *mut libc::c_void,
+        0 as libc::c_int,
+        ::std::mem::size_of::<[libc::c_int; 2]>() as libc::c_ulong,
+    );
+}
+
+unsafe fn get(mut i: usize) -> libc::c_int {
+    if names[i].is_null() {
+        return 0 as libc::c_int;
+    }
+    return values[i];
+}
+
+fn main() {
+    unsafe {
+        reset();
+        get(1);
+    }
+}
@@ -0,0 +1,50 @@
+#![allow(non_camel_case_types)]
+
+pub struct widget {
+    pub width: i32,
+    pub height: i32,
+}
+impl widget {
+    pub fn new(width: i32, height: i32) -> widget {
+        widget { width, height }
+    }
+    pub unsafe fn area(&self) -> i32 {
+        (*self).width * (*self).height
+    }
+    pub fn grow(&mut self, by: i32) {
+        self.width += by;
+        self.height += by;
+    }
+    pub unsafe fn destroy(&mut self) {
+        (*self).width = 0;
+        (*self).height = 0;
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn widget_destroy(w: *mut widget) {
+    (*w).destroy()
+}
+
+mod user {
+    use super::*;
+
+    pub fn total(ws: &mut [widget]) -> i32 {
+        let mut sum = 0;
+        for w in ws.iter_mut() {
+            w.grow(1);
+            sum += unsafe { w.area() };
+        }
+        sum
+    }
+}
+
+fn main() {
+    let mut w = crate::widget::new(2, 3);
+    let p = &mut w as *mut widget;
+    unsafe {
+        println!("{}", (*p).area());
+        (*p).destroy();
+    }
+    println!("{}", user::total(&mut [crate::widget::new(1, 1)]));
+}
@@ -0,0 +1,48 @@
+#![allow(non_camel_case_types)]
+
+pub struct widget {
+    pub width: i32,
+    pub height: i32,
+}
+
+pub fn widget_new(width: i32, height: i32) -> widget {
+    widget { width, height }
+}
+
+pub unsafe fn widget_area(w: *const widget) -> i32 {
+    (*w).width * (*w).height
+}
+
+pub fn widget_grow(w: &mut widget, by: i32) {
+    w.width += by;
+    w.height += by;
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn widget_destroy(w: *mut widget) {
+    (*w).width = 0;
+    (*w).height = 0;
+}
+
+mod user {
+    use super::*;
+
+    pub fn total(ws: &mut [widget]) -> i32 {
+        let mut sum = 0;
+        for w in ws.iter_mut() {
+            widget_grow(w, 1);
+            sum += unsafe { widget_area(w) };
+        }
+        sum
+    }
+}
+
+fn main() {
+    let mut w = widget_new(2, 3);
+    let p = &mut w as *mut widget;
+    unsafe {
+        println!("{}", widget_area(p));
+        widget_destroy(p);
+    }
+    println!("{}", user::total(&mut [widget_new(1, 1)]));
+}
@@ -0,0 +1,65 @@
+#![allow(non_camel_case_types)]
+extern crate libc;
+
+use libc::pthread_mutex_t;
+
+extern "C" {
+    fn pthread_mutex_init(m: *mut pthread_mutex_t,
+                          attr: *const libc::pthread_mutexattr_t) -> libc::c_int;
+    fn pthread_mutex_destroy(m: *mut pthread_mutex_t) -> libc::c_int;
+    fn pthread_mutex_lock(m: *mut pthread_mutex_t) -> libc::c_int;
+    fn pthread_mutex_trylock(m: *mut pthread_mutex_t) -> libc::c_int;
+    fn pthread_mutex_unlock(m: *mut pthread_mutex_t) -> libc::c_int;
+}
+
+static COUNT_LOCK: ::once_cell::sync::Lazy<::std::sync::Mutex<()>> =
+    ::once_cell::sync::Lazy::new(|| ::std::sync::Mutex::new(()));
+static mut COUNT: libc::c_int = 0;
+
+static mut LOG_LOCK: pthread_mutex_t = libc::PTHREAD_MUTEX_INITIALIZER;
+static mut LOG_LEN: libc::c_int = 0;
+
+pub struct queue {
+    pub lock: ::std::sync::Mutex<libc::c_int>,
+}
+
+unsafe fn bump() {
+    let _guard = COUNT_LOCK.lock().unwrap();
+    COUNT += 1;
+}
+
+unsafe fn try_bump() {
+    if let Ok(_guard) = COUNT_LOCK.try_lock() {
+        COUNT += 1;
+    }
+}
+
+unsafe fn new_queue() -> queue {
+    let mut q = queue { lock: ::std::sync::Mutex::new(0) };
+    q
+}
+
+unsafe fn push(q: &mut queue) -> libc::c_int {
+    let _guard = q.lock.lock().unwrap();
+    if (*_guard) >= 16 {
+        return -1;
+    }
+    (*_guard) += 1;
+    let n = (*_guard);
+    drop(_guard);
+    n
+}
+
+unsafe fn free_queue(q: &mut queue) {
+}
+
+unsafe fn log_begin() {
+    pthread_mutex_lock(&mut LOG_LOCK);
+}
+
+unsafe fn log_end() {
+    LOG_LEN += 1;
+    pthread_mutex_unlock(&mut LOG_LOCK);
+}
+
+fn main() {}
@@ -0,0 +1,70 @@
+#![allow(non_camel_case_types)]
+extern crate libc;
+
+use libc::pthread_mutex_t;
+
+extern "C" {
+    fn pthread_mutex_init(m: *mut pthread_mutex_t,
+                          attr: *const libc::pthread_mutexattr_t) -> libc::c_int;
+    fn pthread_mutex_destroy(m: *mut pthread_mutex_t) -> libc::c_int;
+    fn pthread_mutex_lock(m: *mut pthread_mutex_t) -> libc::c_int;
+    fn pthread_mutex_trylock(m: *mut pthread_mutex_t) -> libc::c_int;
+    fn pthread_mutex_unlock(m: *mut pthread_mutex_t) -> libc::c_int;
+}
+
+static mut COUNT_LOCK: pthread_mutex_t = libc::PTHREAD_MUTEX_INITIALIZER;
+static mut COUNT: libc::c_int = 0;
+
+static mut LOG_LOCK: pthread_mutex_t = libc::PTHREAD_MUTEX_INITIALIZER;
+static mut LOG_LEN: libc::c_int = 0;
+
+pub struct queue {
+    pub lock: pthread_mutex_t,
+    pub len: libc::c_int,
+}
+
+unsafe fn bump() {
+    pthread_mutex_lock(&mut COUNT_LOCK);
+    COUNT += 1;
+    pthread_mutex_unlock(&mut COUNT_LOCK);
+}
+
+unsafe fn try_bump() {
+    if pthread_mutex_trylock(&mut COUNT_LOCK) == 0 {
+        COUNT += 1;
+        pthread_mutex_unlock(&mut COUNT_LOCK);
+    }
+}
+
+unsafe fn new_queue() -> queue {
+    let mut q = queue { lock: libc::PTHREAD_MUTEX_INITIALIZER, len: 0 };
+    pthread_mutex_init(&mut q.lock, 0 as *const libc::pthread_mutexattr_t);
+    q
+}
+
+unsafe fn push(q: &mut queue) -> libc::c_int {
+    pthread_mutex_lock(&mut q.lock);
+    if q.len >= 16 {
+        pthread_mutex_unlock(&mut q.lock);
+        return -1;
+    }
+    q.len += 1;
+    let n = q.len;
+    pthread_mutex_unlock(&mut q.lock);
+    n
+}
+
+unsafe fn free_queue(q: &mut queue) {
+    pthread_mutex_destroy(&mut q.lock);
+}
+
+unsafe fn log_begin() {
+    pthread_mutex_lock(&mut LOG_LOCK);
+}
+
+unsafe fn log_end() {
+    LOG_LEN += 1;
+    pthread_mutex_unlock(&mut LOG_LOCK);
+}
+
+fn main() {}
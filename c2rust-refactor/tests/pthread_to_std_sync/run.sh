@@ -0,0 +1,11 @@
+#!/bin/sh
+
+# work around System Integrity Protection on macOS
+if [ `uname` = 'Darwin' ]; then
+    export LD_LIBRARY_PATH=$not_LD_LIBRARY_PATH
+fi
+
+$refactor \
+    select target 'crate; desc((item || field) && name("^(COUNT_LOCK|LOG_LOCK|lock)$"));' \; \
+    pthread_to_std_sync protects=len \
+    -- old.rs $rustflags
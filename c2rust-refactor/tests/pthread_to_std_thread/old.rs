@@ -0,0 +1,62 @@
+#![allow(non_camel_case_types)]
+extern crate libc;
+
+use libc::pthread_t;
+use std::rc::Rc;
+
+extern "C" {
+    fn pthread_create(native: *mut pthread_t, attr: *const libc::pthread_attr_t,
+                      f: Option<unsafe extern "C" fn(*mut libc::c_void) -> *mut libc::c_void>,
+                      value: *mut libc::c_void) -> libc::c_int;
+    fn pthread_join(native: pthread_t, value: *mut *mut libc::c_void) -> libc::c_int;
+}
+
+pub struct pool {
+    pub worker: pthread_t,
+    pub count: libc::c_int,
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn count_up(arg: *mut libc::c_void) -> *mut libc::c_void {
+    *(arg as *mut libc::c_int) += 1;
+    arg
+}
+
+unsafe extern "C" fn fill_pool(arg: *mut libc::c_void) -> *mut libc::c_void {
+    *(arg as *mut libc::c_int) = 1;
+    arg
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn hold(arg: *mut libc::c_void) -> *mut libc::c_void {
+    arg
+}
+
+unsafe fn run_once() -> libc::c_int {
+    let mut n: libc::c_int = 0;
+    let mut tid: pthread_t = 0;
+    let mut ret: *mut libc::c_void = 0 as *mut libc::c_void;
+    pthread_create(&mut tid, 0 as *const libc::pthread_attr_t, Some(count_up),
+                   &mut n as *mut libc::c_int as *mut libc::c_void);
+    pthread_join(tid, &mut ret);
+    n
+}
+
+unsafe fn start_pool(p: &mut pool) {
+    pthread_create(&mut p.worker, 0 as *const libc::pthread_attr_t, Some(fill_pool),
+                   &mut p.count as *mut libc::c_int as *mut libc::c_void);
+}
+
+unsafe fn stop_pool(p: &mut pool) {
+    pthread_join(p.worker, 0 as *mut *mut libc::c_void);
+}
+
+unsafe fn run_shared() {
+    let mut shared: Rc<libc::c_int> = Rc::new(0);
+    let mut tid2: pthread_t = 0;
+    pthread_create(&mut tid2, 0 as *const libc::pthread_attr_t, Some(hold),
+                   &mut shared as *mut Rc<libc::c_int> as *mut libc::c_void);
+    pthread_join(tid2, 0 as *mut *mut libc::c_void);
+}
+
+fn main() {}
@@ -0,0 +1,11 @@
+#!/bin/sh
+
+# work around System Integrity Protection on macOS
+if [ `uname` = 'Darwin' ]; then
+    export LD_LIBRARY_PATH=$not_LD_LIBRARY_PATH
+fi
+
+$refactor \
+    select target 'crate; desc((pat || field) && name("^(tid|tid2|worker)$"));' \; \
+    pthread_to_std_thread \
+    -- old.rs $rustflags
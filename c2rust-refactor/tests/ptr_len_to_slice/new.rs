@@ -0,0 +1,54 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+unsafe fn checksum(buf: &[u8]) -> u32 {
+    let mut sum = 0u32;
+    let mut i = 0;
+    while i < buf.len() {
+        sum = sum.wrapping_add(buf[i as usize] as u32);
+        i += 1;
+    }
+    sum
+}
+
+unsafe fn fill(vals: &mut [i32], v: i32) {
+    if vals.len() as i32 > 0 {
+        vals[0] = v;
+    }
+    let mut i = 1;
+    while i < vals.len() as i32 {
+        vals[i as usize] = vals[(i - 1) as usize] + v;
+        i += 1;
+    }
+}
+
+unsafe fn scale(out: &mut [i32], k: i32) {
+    let mut i = 0;
+    while i < out.len() {
+        out[i as usize] *= k;
+        i += 1;
+    }
+}
+
+unsafe fn copy_bytes(dst: *mut u8, src: *const u8, n: usize) {
+    let mut i = 0;
+    while i < n {
+        *dst.offset(i as isize) = *src.offset(i as isize);
+        i += 1;
+    }
+}
+
+fn main() {
+    unsafe {
+        let data: [u8; 4] = [1, 2, 3, 4];
+        let mut arr: [i32; 8] = [0; 8];
+        let raw: *const u8 = data.as_ptr();
+        let mut out: [u8; 4] = [0; 4];
+        fill(&mut arr[..8 as usize], 7);
+        scale(&mut arr[..4 as usize], 2);
+        copy_bytes(out.as_mut_ptr(), raw, 4);
+        let all = checksum(&data[..4 as usize]);
+        let some = checksum(unsafe { ::std::slice::from_raw_parts(raw, 2 as usize) });
+        println!("{} {} {} {}", all, some, arr[7], out[3]);
+    }
+}
@@ -0,0 +1,54 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+unsafe fn checksum(buf: *const u8, len: usize) -> u32 {
+    let mut sum = 0u32;
+    let mut i = 0;
+    while i < len {
+        sum = sum.wrapping_add(*buf.offset(i as isize) as u32);
+        i += 1;
+    }
+    sum
+}
+
+unsafe fn fill(vals: *mut i32, n: i32, v: i32) {
+    if n > 0 {
+        *vals = v;
+    }
+    let mut i = 1;
+    while i < n {
+        *vals.offset(i as isize) = *vals.offset((i - 1) as isize) + v;
+        i += 1;
+    }
+}
+
+unsafe fn scale(out: *mut i32, count: usize, k: i32) {
+    let mut i = 0;
+    while i < count {
+        *out.offset(i as isize) *= k;
+        i += 1;
+    }
+}
+
+unsafe fn copy_bytes(dst: *mut u8, src: *const u8, n: usize) {
+    let mut i = 0;
+    while i < n {
+        *dst.offset(i as isize) = *src.offset(i as isize);
+        i += 1;
+    }
+}
+
+fn main() {
+    unsafe {
+        let data: [u8; 4] = [1, 2, 3, 4];
+        let mut arr: [i32; 8] = [0; 8];
+        let raw: *const u8 = data.as_ptr();
+        let mut out: [u8; 4] = [0; 4];
+        fill(arr.as_mut_ptr(), 8, 7);
+        scale(arr.as_mut_ptr(), 4, 2);
+        copy_bytes(out.as_mut_ptr(), raw, 4);
+        let all = checksum(data.as_ptr(), 4);
+        let some = checksum(raw, 2);
+        println!("{} {} {} {}", all, some, arr[7], out[3]);
+    }
+}
@@ -0,0 +1,21 @@
+#!/bin/sh
+
+# work around System Integrity Protection on macOS
+if [ `uname` = 'Darwin' ]; then
+    export LD_LIBRARY_PATH=$not_LD_LIBRARY_PATH
+fi
+
+# The first step converts pointers whose length directly follows them.  The
+# second names the length of the mutable buffer in `scale`, which converts.
+# The last is the shared-length rejection case: `n` is the length of both
+# `dst` and `src` in `copy_bytes`, so neither is converted.
+$refactor \
+    select target 'crate; desc(arg && (any_child(match_pat(buf)) ||
+                                       any_child(match_pat(vals))));' \; \
+    ptr_len_to_slice \; commit \; clear_marks \; \
+    select target 'crate; desc(fn && name("scale")); desc(arg && any_child(match_pat(out)));' \; \
+    ptr_len_to_slice count \; commit \; clear_marks \; \
+    select target 'crate; desc(fn && name("copy_bytes"));
+                   desc(arg && (any_child(match_pat(dst)) || any_child(match_pat(src))));' \; \
+    ptr_len_to_slice n \
+    -- old.rs $rustflags
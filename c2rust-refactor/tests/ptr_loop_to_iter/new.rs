@@ -0,0 +1,40 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+unsafe fn sum(buf: &[libc::c_int]) -> libc::c_int {
+    let mut total: libc::c_int = 0;
+    for p in buf.iter() {
+        total += *p;
+    }
+    return total;
+}
+
+unsafe fn clear(buf: &mut [libc::c_int; 16], n: libc::c_int) {
+    for p in buf[..::std::cmp::max(n, 0) as usize].iter_mut() {
+        *p = 0;
+    }
+}
+
+unsafe fn count_rises(buf: &Vec<libc::c_int>, n: libc::c_int) -> libc::c_int {
+    let mut rises: libc::c_int = 0;
+    for p_i in 0..::std::cmp::max(n - 1, 0) as usize {
+        if buf[p_i + 1] > buf[p_i] {
+            rises += 1
+        }
+    }
+    return rises;
+}
+
+unsafe fn find_zero(buf: &[libc::c_int]) -> *const libc::c_int {
+    let mut p: *const libc::c_int = buf.as_ptr();
+    let end: *const libc::c_int = p.offset(buf.len() as isize);
+    while p < end {
+        if *p == 0 {
+            break;
+        }
+        p = p.offset(1)
+    }
+    return p;
+}
+
+fn main() {}
@@ -0,0 +1,49 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+unsafe fn sum(buf: &[libc::c_int]) -> libc::c_int {
+    let mut total: libc::c_int = 0;
+    let mut p: *const libc::c_int = buf.as_ptr();
+    let end: *const libc::c_int = p.offset(buf.len() as isize);
+    while p < end {
+        total += *p;
+        p = p.offset(1)
+    }
+    return total;
+}
+
+unsafe fn clear(buf: &mut [libc::c_int; 16], n: libc::c_int) {
+    let mut p: *mut libc::c_int = buf.as_mut_ptr();
+    let end: *mut libc::c_int = p.offset(n as isize);
+    while p != end {
+        *p = 0;
+        p = p.offset(1)
+    }
+}
+
+unsafe fn count_rises(buf: &Vec<libc::c_int>, n: libc::c_int) -> libc::c_int {
+    let mut rises: libc::c_int = 0;
+    let mut p: *const libc::c_int = buf.as_ptr();
+    let end: *const libc::c_int = p.offset((n - 1) as isize);
+    while p < end {
+        if *p.offset(1) > *p {
+            rises += 1
+        }
+        p = p.offset(1)
+    }
+    return rises;
+}
+
+unsafe fn find_zero(buf: &[libc::c_int]) -> *const libc::c_int {
+    let mut p: *const libc::c_int = buf.as_ptr();
+    let end: *const libc::c_int = p.offset(buf.len() as isize);
+    while p < end {
+        if *p == 0 {
+            break;
+        }
+        p = p.offset(1)
+    }
+    return p;
+}
+
+fn main() {}
@@ -0,0 +1,11 @@
+#!/bin/sh
+
+# work around System Integrity Protection on macOS
+if [ `uname` = 'Darwin' ]; then
+    export LD_LIBRARY_PATH=$not_LD_LIBRARY_PATH
+fi
+
+$refactor \
+    select target 'crate; desc(match_expr(while $c:Expr { $b:MultiStmt; }));' \; \
+    ptr_loop_to_iter \
+    -- old.rs $rustflags
@@ -0,0 +1,41 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+struct Point {
+    x: i32,
+    y: i32,
+}
+
+unsafe fn norm1(p: *const Point) -> i32 {
+    (*p).x.abs() + (*p).y.abs()
+}
+
+unsafe fn scale(p: *mut Point, k: i32) {
+    (*p).x *= k;
+    (*p).y *= k;
+}
+
+unsafe fn get_x_or(p: *const Point, d: i32) -> i32 {
+    if p.is_null() {
+        return d;
+    }
+    (*p).x
+}
+
+unsafe fn deref_int(p: *const i32) -> i32 {
+    *p
+}
+
+fn main() {
+    unsafe {
+        let mut pt = Point { x: 3, y: -4 };
+        let raw: *mut Point = &mut pt;
+        scale(&mut pt, 2);
+        scale(raw, 3);
+        let n = norm1(&pt) + norm1(raw);
+        let d = get_x_or(&pt, 0);
+        let f: unsafe fn(*const i32) -> i32 = deref_int;
+        let i = 1;
+        println!("{} {} {}", n, d, f(&i));
+    }
+}
@@ -0,0 +1,12 @@
+#!/bin/sh
+
+# work around System Integrity Protection on macOS
+if [ `uname` = 'Darwin' ]; then
+    export LD_LIBRARY_PATH=$not_LD_LIBRARY_PATH
+fi
+
+$refactor \
+    select target 'crate; desc(fn && (name("norm1") || name("scale") || name("get_x_or") ||
+                                      name("deref_int")));' \; \
+    ptr_params_to_refs \
+    -- old.rs $rustflags
@@ -0,0 +1,39 @@
+extern crate libc;
+
+extern "C" {
+    fn lookup(key: libc::c_int) -> *mut libc::c_int;
+}
+
+pub struct Entry {
+    pub boxed: ::std::ptr::NonNull<libc::c_int>,
+    pub found: ::std::ptr::NonNull<libc::c_int>,
+    pub cached: *mut libc::c_int,
+}
+
+unsafe fn make(key: libc::c_int) -> Entry {
+    let mut e = Entry {
+        boxed: unsafe { ::std::ptr::NonNull::new_unchecked(Box::into_raw(Box::new(key))) },
+        found: ::std::ptr::NonNull::new(lookup(key)).unwrap(),
+        cached: 0 as *mut libc::c_int,
+    };
+    {
+        *e.boxed.as_ptr() += 1;
+    }
+    e
+}
+
+unsafe fn refresh(e: &mut Entry, key: libc::c_int) {
+    let p = lookup(key);
+    if let Some(p_nonnull) = ::std::ptr::NonNull::new(p) {
+        e.boxed = p_nonnull;
+    }
+}
+
+unsafe fn get(e: &mut Entry) -> libc::c_int {
+    if e.cached.is_null() {
+        e.cached = e.found.as_ptr();
+    }
+    *e.boxed.as_ptr() + *e.found.as_ptr() + *e.cached
+}
+
+fn main() {}
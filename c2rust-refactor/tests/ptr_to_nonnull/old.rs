@@ -0,0 +1,39 @@
+extern crate libc;
+
+extern "C" {
+    fn lookup(key: libc::c_int) -> *mut libc::c_int;
+}
+
+pub struct Entry {
+    pub boxed: *mut libc::c_int,
+    pub found: *mut libc::c_int,
+    pub cached: *mut libc::c_int,
+}
+
+unsafe fn make(key: libc::c_int) -> Entry {
+    let mut e = Entry {
+        boxed: Box::into_raw(Box::new(key)),
+        found: lookup(key),
+        cached: 0 as *mut libc::c_int,
+    };
+    if !e.boxed.is_null() {
+        *e.boxed += 1;
+    }
+    e
+}
+
+unsafe fn refresh(e: &mut Entry, key: libc::c_int) {
+    let p = lookup(key);
+    if !p.is_null() {
+        e.boxed = p;
+    }
+}
+
+unsafe fn get(e: &mut Entry) -> libc::c_int {
+    if e.cached.is_null() {
+        e.cached = e.found;
+    }
+    *e.boxed + *e.found + *e.cached
+}
+
+fn main() {}
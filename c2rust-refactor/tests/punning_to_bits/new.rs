@@ -0,0 +1,57 @@
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub union FloatBits {
+    pub f: f32,
+    pub i: u32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub union DoubleBits {
+    pub u: u64,
+    pub d: f64,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub union IntBytes {
+    pub i: i32,
+    pub b: [u8; 4],
+}
+
+pub unsafe fn float_to_bits(x: f32) -> u32 {
+    let mut u: f32 = 0.0;
+    u = x;
+    return u.to_bits();
+}
+
+pub unsafe fn bits_to_double(x: u64) -> f64 {
+    let mut u: f64 = f64::from_bits(x);
+    if u.to_bits() == 0 {
+        u = 1.0;
+    }
+    return u;
+}
+
+pub unsafe fn low_byte(x: i32) -> u8 {
+    let u: i32 = x;
+    return u.to_ne_bytes()[0];
+}
+
+pub unsafe fn set_low_byte(x: i32) -> i32 {
+    let mut u: IntBytes = IntBytes { i: x };
+    u.b[0] = 1;
+    return u.i;
+}
+
+unsafe fn clear(u: *mut FloatBits) {
+    (*u).i = 0;
+}
+
+pub unsafe fn cleared(x: f32) -> f32 {
+    let mut u: FloatBits = FloatBits { f: x };
+    clear(&mut u);
+    return u.f;
+}
+
+fn main() {}
@@ -0,0 +1,57 @@
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub union FloatBits {
+    pub f: f32,
+    pub i: u32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub union DoubleBits {
+    pub u: u64,
+    pub d: f64,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub union IntBytes {
+    pub i: i32,
+    pub b: [u8; 4],
+}
+
+pub unsafe fn float_to_bits(x: f32) -> u32 {
+    let mut u: FloatBits = ::std::mem::zeroed();
+    u.f = x;
+    return u.i;
+}
+
+pub unsafe fn bits_to_double(x: u64) -> f64 {
+    let mut u: DoubleBits = DoubleBits { u: x };
+    if u.u == 0 {
+        u.d = 1.0;
+    }
+    return u.d;
+}
+
+pub unsafe fn low_byte(x: i32) -> u8 {
+    let u: IntBytes = IntBytes { i: x };
+    return u.b[0];
+}
+
+pub unsafe fn set_low_byte(x: i32) -> i32 {
+    let mut u: IntBytes = IntBytes { i: x };
+    u.b[0] = 1;
+    return u.i;
+}
+
+unsafe fn clear(u: *mut FloatBits) {
+    (*u).i = 0;
+}
+
+pub unsafe fn cleared(x: f32) -> f32 {
+    let mut u: FloatBits = FloatBits { f: x };
+    clear(&mut u);
+    return u.f;
+}
+
+fn main() {}
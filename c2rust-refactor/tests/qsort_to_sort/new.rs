@@ -0,0 +1,66 @@
+#![allow(non_camel_case_types)]
+extern crate libc;
+
+extern "C" {
+    fn qsort(base: *mut libc::c_void, n: libc::size_t, size: libc::size_t,
+             cmp: Option<unsafe extern "C" fn(*const libc::c_void,
+                                              *const libc::c_void) -> libc::c_int>);
+    fn bsearch(key: *const libc::c_void, base: *const libc::c_void, n: libc::size_t,
+               size: libc::size_t,
+               cmp: Option<unsafe extern "C" fn(*const libc::c_void,
+                                                *const libc::c_void) -> libc::c_int>)
+               -> *mut libc::c_void;
+}
+
+#[derive(Copy, Clone)]
+pub struct entry {
+    pub key: libc::c_int,
+    pub value: libc::c_int,
+}
+
+unsafe extern "C" fn cmp_ints(a: *const libc::c_void, b: *const libc::c_void) -> libc::c_int {
+    *(a as *const libc::c_int) - *(b as *const libc::c_int)
+}
+
+unsafe extern "C" fn cmp_entries(a: *const libc::c_void,
+                                 b: *const libc::c_void) -> libc::c_int {
+    (*(a as *const entry)).key - (*(b as *const entry)).key
+}
+
+unsafe fn sort_ints(xs: &mut [libc::c_int; 8]) {
+    xs[..8 as usize].sort_by(|a: &libc::c_int, b: &libc::c_int|
+                                 cmp_ints(a as *const libc::c_int as *const _,
+                                          b as *const libc::c_int as *const _).cmp(&0));
+}
+
+unsafe fn sort_entries(entries: &mut Vec<entry>) {
+    let p = entries.as_mut_ptr();
+    entries[..entries.len() as usize].sort_by(|a: &entry, b: &entry|
+                                                  cmp_entries(a as *const entry as *const _,
+                                                              b as *const entry as
+                                                                  *const _).cmp(&0));
+}
+
+unsafe fn lookup(entries: &mut Vec<entry>, key: libc::c_int) -> libc::c_int {
+    let probe = entry { key: key, value: 0 };
+    let found =
+        match entries[..entries.len() as usize].binary_search_by(|elem: &entry|
+                                                                     cmp_entries(&probe as
+                                                                                     *const entry
+                                                                                     as
+                                                                                     *const libc::c_void,
+                                                                                 elem as
+                                                                                     *const entry
+                                                                                     as
+                                                                                     *const _).cmp(&0).reverse())
+            {
+            Ok(i) => &entries[i] as *const entry as *mut entry,
+            Err(_) => 0 as *mut entry,
+        };
+    if found.is_null() {
+        return -1;
+    }
+    (*found).value
+}
+
+fn main() {}
@@ -0,0 +1,54 @@
+#![allow(non_camel_case_types)]
+extern crate libc;
+
+extern "C" {
+    fn qsort(base: *mut libc::c_void, n: libc::size_t, size: libc::size_t,
+             cmp: Option<unsafe extern "C" fn(*const libc::c_void,
+                                              *const libc::c_void) -> libc::c_int>);
+    fn bsearch(key: *const libc::c_void, base: *const libc::c_void, n: libc::size_t,
+               size: libc::size_t,
+               cmp: Option<unsafe extern "C" fn(*const libc::c_void,
+                                                *const libc::c_void) -> libc::c_int>)
+               -> *mut libc::c_void;
+}
+
+#[derive(Copy, Clone)]
+pub struct entry {
+    pub key: libc::c_int,
+    pub value: libc::c_int,
+}
+
+unsafe extern "C" fn cmp_ints(a: *const libc::c_void, b: *const libc::c_void) -> libc::c_int {
+    *(a as *const libc::c_int) - *(b as *const libc::c_int)
+}
+
+unsafe extern "C" fn cmp_entries(a: *const libc::c_void,
+                                 b: *const libc::c_void) -> libc::c_int {
+    (*(a as *const entry)).key - (*(b as *const entry)).key
+}
+
+unsafe fn sort_ints(xs: &mut [libc::c_int; 8]) {
+    qsort(xs.as_mut_ptr() as *mut libc::c_void, 8 as libc::size_t,
+          ::std::mem::size_of::<libc::c_int>() as libc::size_t, Some(cmp_ints));
+}
+
+unsafe fn sort_entries(entries: &mut Vec<entry>) {
+    let p = entries.as_mut_ptr();
+    qsort(p as *mut libc::c_void, entries.len() as libc::size_t,
+          ::std::mem::size_of::<entry>() as libc::size_t, Some(cmp_entries));
+}
+
+unsafe fn lookup(entries: &mut Vec<entry>, key: libc::c_int) -> libc::c_int {
+    let probe = entry { key: key, value: 0 };
+    let found = bsearch(&probe as *const entry as *const libc::c_void,
+                        entries.as_mut_ptr() as *const libc::c_void,
+                        entries.len() as libc::size_t,
+                        ::std::mem::size_of::<entry>() as libc::size_t,
+                        Some(cmp_entries)) as *mut entry;
+    if found.is_null() {
+        return -1;
+    }
+    (*found).value
+}
+
+fn main() {}
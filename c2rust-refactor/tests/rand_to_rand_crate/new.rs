@@ -0,0 +1,43 @@
+#![feature(rustc_private)]
+extern crate libc;
+extern crate rand;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+extern "C" {
+    fn rand() -> libc::c_int;
+    fn srand(__seed: libc::c_uint);
+}
+
+unsafe fn roll_die() -> libc::c_int {
+    return crate::rand_next() % 6 as libc::c_int + 1 as libc::c_int;
+}
+
+unsafe fn reseed(seed: libc::c_uint) {
+    crate::rand_seed(seed);
+}
+
+unsafe fn noise() -> libc::c_double {
+    return crate::rand_next() as libc::c_double / 2147483647 as libc::c_int as libc::c_double;
+}
+
+fn main() {
+    unsafe {
+        reseed(42 as libc::c_int as libc::c_uint);
+        let _die = roll_die();
+        let _noise = noise();
+    }
+}
+
+thread_local! {
+    static RAND_RNG: ::std::cell::RefCell<StdRng> =
+        ::std::cell::RefCell::new(StdRng::seed_from_u64(1));
+}
+
+fn rand_seed(seed: libc::c_uint) {
+    RAND_RNG.with(|rng| *rng.borrow_mut() = StdRng::seed_from_u64(seed as u64));
+}
+
+fn rand_next() -> libc::c_int {
+    RAND_RNG.with(|rng| rng.borrow_mut().gen_range(0..=libc::RAND_MAX))
+}
@@ -0,0 +1,27 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+extern "C" {
+    fn rand() -> libc::c_int;
+    fn srand(__seed: libc::c_uint);
+}
+
+unsafe fn roll_die() -> libc::c_int {
+    return rand() % 6 as libc::c_int + 1 as libc::c_int;
+}
+
+unsafe fn reseed(seed: libc::c_uint) {
+    srand(seed);
+}
+
+unsafe fn noise() -> libc::c_double {
+    return rand() as libc::c_double / 2147483647 as libc::c_int as libc::c_double;
+}
+
+fn main() {
+    unsafe {
+        reseed(42 as libc::c_int as libc::c_uint);
+        let _die = roll_die();
+        let _noise = noise();
+    }
+}
@@ -0,0 +1,44 @@
+#![feature(rustc_private)]
+extern crate libc;
+extern crate rand;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+extern "C" {
+    fn rand() -> libc::c_int;
+    fn srand(__seed: libc::c_uint);
+}
+
+unsafe fn roll_die(rng: &mut ::rand::rngs::StdRng) -> libc::c_int {
+    return crate::rand_below(rng, 6 as libc::c_int) + 1 as libc::c_int;
+}
+
+unsafe fn roll_dice(n: libc::c_int, rng: &mut ::rand::rngs::StdRng) -> libc::c_int {
+    let mut total: libc::c_int = 0;
+    let mut i: libc::c_int = 0;
+    while i < n {
+        total += roll_die(rng);
+        i += 1
+    }
+    return total;
+}
+
+fn main() {
+    let mut rng: ::rand::rngs::StdRng = ::rand::SeedableRng::seed_from_u64(1);
+    unsafe {
+        crate::rand_seed(&mut rng, 7 as libc::c_int as libc::c_uint);
+        roll_dice(3 as libc::c_int, &mut rng);
+    }
+}
+
+fn rand_seed(rng: &mut StdRng, seed: libc::c_uint) {
+    *rng = StdRng::seed_from_u64(seed as u64);
+}
+
+fn rand_next(rng: &mut StdRng) -> libc::c_int {
+    rng.gen_range(0..=libc::RAND_MAX)
+}
+
+fn rand_below(rng: &mut StdRng, n: libc::c_int) -> libc::c_int {
+    rng.gen_range(0..n)
+}
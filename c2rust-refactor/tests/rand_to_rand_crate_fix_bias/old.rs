@@ -0,0 +1,28 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+extern "C" {
+    fn rand() -> libc::c_int;
+    fn srand(__seed: libc::c_uint);
+}
+
+unsafe fn roll_die() -> libc::c_int {
+    return rand() % 6 as libc::c_int + 1 as libc::c_int;
+}
+
+unsafe fn roll_dice(n: libc::c_int) -> libc::c_int {
+    let mut total: libc::c_int = 0;
+    let mut i: libc::c_int = 0;
+    while i < n {
+        total += roll_die();
+        i += 1
+    }
+    return total;
+}
+
+fn main() {
+    unsafe {
+        srand(7 as libc::c_int as libc::c_uint);
+        roll_dice(3 as libc::c_int);
+    }
+}
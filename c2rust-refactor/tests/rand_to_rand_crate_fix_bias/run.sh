@@ -0,0 +1,11 @@
+#!/bin/sh
+
+# work around System Integrity Protection on macOS
+if [ `uname` = 'Darwin' ]; then
+    export LD_LIBRARY_PATH=$not_LD_LIBRARY_PATH
+fi
+
+$refactor \
+    select target 'crate; desc(fn && (name("roll_die") || name("roll_dice")));' \; \
+    rand_to_rand_crate mode=param fix_bias=1 \
+    -- old.rs $rustflags
@@ -0,0 +1,44 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+extern "C" {
+    fn realloc(_: *mut libc::c_void, _: libc::c_ulong) -> *mut libc::c_void;
+    fn free(_: *mut libc::c_void);
+}
+
+#[repr(C)]
+pub struct Stack {
+    pub items: *mut libc::c_int,
+    pub len: libc::c_int,
+}
+
+unsafe fn squares(limit: libc::c_int) -> libc::c_int {
+    let mut buf: Vec<libc::c_int> = Vec::new();
+    let mut cap: libc::c_int = 0;
+    let mut sum: libc::c_int = 0;
+    let mut i: libc::c_int = 0;
+    while i < limit {
+        if i >= cap {
+            cap = cap * 2 + 1;
+            buf.resize(cap as usize, Default::default());
+        }
+        buf[i as usize] = i * i;
+        sum += buf[i as usize];
+        i += 1
+    }
+    drop(buf);
+    sum
+}
+
+unsafe fn fill_stack(s: *mut Stack, n: libc::c_int) {
+    let mut items: *mut libc::c_int = 0 as *mut libc::c_int;
+    items = realloc(
+        items as *mut libc::c_void,
+        (n as libc::c_ulong).wrapping_mul(::std::mem::size_of::<libc::c_int>() as libc::c_ulong),
+    ) as *mut libc::c_int;
+    *items.offset(0) = n;
+    (*s).items = items;
+    (*s).len = n;
+}
+
+fn main() {}
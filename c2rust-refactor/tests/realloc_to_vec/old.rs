@@ -0,0 +1,47 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+extern "C" {
+    fn realloc(_: *mut libc::c_void, _: libc::c_ulong) -> *mut libc::c_void;
+    fn free(_: *mut libc::c_void);
+}
+
+#[repr(C)]
+pub struct Stack {
+    pub items: *mut libc::c_int,
+    pub len: libc::c_int,
+}
+
+unsafe fn squares(limit: libc::c_int) -> libc::c_int {
+    let mut buf: *mut libc::c_int = 0 as *mut libc::c_int;
+    let mut cap: libc::c_int = 0;
+    let mut sum: libc::c_int = 0;
+    let mut i: libc::c_int = 0;
+    while i < limit {
+        if i >= cap {
+            cap = cap * 2 + 1;
+            buf = realloc(
+                buf as *mut libc::c_void,
+                (cap as libc::c_ulong).wrapping_mul(::std::mem::size_of::<libc::c_int>() as libc::c_ulong),
+            ) as *mut libc::c_int;
+        }
+        *buf.offset(i as isize) = i * i;
+        sum += *buf.offset(i as isize);
+        i += 1
+    }
+    free(buf as *mut libc::c_void);
+    sum
+}
+
+unsafe fn fill_stack(s: *mut Stack, n: libc::c_int) {
+    let mut items: *mut libc::c_int = 0 as *mut libc::c_int;
+    items = realloc(
+        items as *mut libc::c_void,
+        (n as libc::c_ulong).wrapping_mul(::std::mem::size_of::<libc::c_int>() as libc::c_ulong),
+    ) as *mut libc::c_int;
+    *items.offset(0) = n;
+    (*s).items = items;
+    (*s).len = n;
+}
+
+fn main() {}
@@ -0,0 +1,90 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+extern "C" {
+    fn log_step(_: libc::c_int);
+}
+
+unsafe fn straight(x: libc::c_int) -> libc::c_int {
+    let mut y: libc::c_int = 0;
+    let mut current_block: u64;
+    current_block = 1234;
+    loop {
+        match current_block {
+            1234 => {
+                y = x + 1;
+                current_block = 5678;
+            }
+            5678 => {
+                y = y * 2;
+                current_block = 9012;
+            }
+            9012 => {
+                break;
+            }
+            _ => {}
+        }
+    }
+    return y;
+}
+
+unsafe fn count_to(limit: libc::c_int) -> libc::c_int {
+    let mut i: libc::c_int = 0;
+    let mut current_block: u64;
+    current_block = 11;
+    loop {
+        match current_block {
+            11 => {
+                i += 1;
+                if i >= limit {
+                    current_block = 33;
+                } else {
+                    current_block = 22;
+                }
+            }
+            22 => {
+                log_step(i);
+                current_block = 11;
+                continue;
+            }
+            33 => {
+                break;
+            }
+            _ => {}
+        }
+    }
+    return i;
+}
+
+// Both blocks of the cycle can be entered first, so it isn't a loop.
+unsafe fn ping_pong(start_left: libc::c_int) -> libc::c_int {
+    let mut n: libc::c_int = 0;
+    let mut current_block: u64;
+    current_block = 100;
+    loop {
+        match current_block {
+            100 => {
+                if start_left != 0 {
+                    current_block = 200;
+                } else {
+                    current_block = 300;
+                }
+            }
+            200 => {
+                n += 1;
+                if n > 10 {
+                    break;
+                }
+                current_block = 300;
+            }
+            300 => {
+                n += 2;
+                current_block = 200;
+            }
+            _ => {}
+        }
+    }
+    return n;
+}
+
+fn main() {}
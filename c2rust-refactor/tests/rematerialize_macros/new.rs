@@ -0,0 +1,72 @@
+#![feature(rustc_private)]
+#![register_tool(c2rust)]
+#![allow(non_upper_case_globals)]
+extern crate libc;
+
+pub mod screen {
+    #[c2rust::header_src = "/src/config.h:3"]
+    pub mod config_h {
+        #[c2rust::src_loc = "12:0"]
+        pub static mut frame_rate: libc::c_int = 60;
+
+        #[c2rust::src_loc = "14:0"]
+        pub unsafe fn default_depth() -> libc::c_int {
+            DEPTH
+        }
+        pub const WIDTH: i32 = 640;
+        pub const HEIGHT: i32 = 480;
+        pub const DEPTH: i32 = 24;
+        pub const SCALE: f32 = 2.5;
+        pub const MIN_LEVEL: i32 = -255;
+    }
+
+    #[c2rust::header_src = "/src/limits.h:9"]
+    pub mod limits_h {
+        #[c2rust::src_loc = "5:0"]
+        pub type count_t = libc::c_int;
+        pub const FLAG_MASK: u32 = 0x10;
+    }
+    use self::config_h::frame_rate;
+    use self::config_h::HEIGHT;
+    use self::config_h::MIN_LEVEL;
+    use self::config_h::SCALE;
+    use self::config_h::WIDTH;
+    use self::limits_h::count_t;
+    use self::limits_h::FLAG_MASK;
+
+    // Before the includes
+    #[c2rust::src_loc = "1:0"]
+    static mut early_width: libc::c_int = 640;
+
+    #[c2rust::src_loc = "20:0"]
+    pub unsafe fn area() -> libc::c_long {
+        WIDTH as libc::c_long * HEIGHT as libc::c_long
+    }
+
+    #[c2rust::src_loc = "25:0"]
+    pub unsafe fn scaled(x: libc::c_double) -> libc::c_double {
+        x * SCALE as f64 - 1.0
+    }
+
+    #[c2rust::src_loc = "30:0"]
+    pub unsafe fn clamp(n: count_t) -> count_t {
+        // 255 could be either `MAX_LEVEL` or `UCHAR_MAX`
+        if n > 255 {
+            255
+        } else if n < MIN_LEVEL {
+            MIN_LEVEL
+        } else {
+            n
+        }
+    }
+
+    #[c2rust::src_loc = "35:0"]
+    pub unsafe fn check(n: libc::c_int) -> bool {
+        match n {
+            640 => true,
+            _ => n & FLAG_MASK as i32 != 0 || n == frame_rate + 1,
+        }
+    }
+}
+
+fn main() {}
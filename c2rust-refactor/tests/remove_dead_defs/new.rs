@@ -0,0 +1,42 @@
+#![allow(dead_code, non_upper_case_globals)]
+extern crate libc;
+
+extern "C" {
+    fn abs(x: libc::c_int) -> libc::c_int;
+}
+
+mod helpers {
+    pub fn twice(x: i32) -> i32 {
+        double(x)
+    }
+
+    fn double(x: i32) -> i32 {
+        x * 2
+    }
+
+    #[cfg(not(feature = "fast"))]
+    fn guarded() -> i32 {
+        0
+    }
+
+    #[no_mangle]
+    extern "C" fn exported() -> i32 {
+        for_export()
+    }
+
+    fn for_export() -> i32 {
+        1
+    }
+}
+
+mod consts {
+    pub(crate) const LIMIT: i32 = 10;
+}
+
+use consts::{LIMIT};
+
+fn main() {
+    unsafe {
+        println!("{}", abs(helpers::twice(-LIMIT)));
+    }
+}
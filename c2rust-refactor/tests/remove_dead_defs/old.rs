@@ -0,0 +1,60 @@
+#![allow(dead_code, non_upper_case_globals)]
+extern crate libc;
+
+extern "C" {
+    fn abs(x: libc::c_int) -> libc::c_int;
+    fn never_called(x: libc::c_int) -> libc::c_int;
+}
+
+extern "C" {
+    fn also_never_called();
+    static mut never_read: libc::c_int;
+}
+
+mod helpers {
+    pub fn twice(x: i32) -> i32 {
+        double(x)
+    }
+
+    fn double(x: i32) -> i32 {
+        x * 2
+    }
+
+    fn unused(x: i32) -> i32 {
+        only_used_by_unused(x)
+    }
+
+    fn only_used_by_unused(x: i32) -> i32 {
+        x
+    }
+
+    #[cfg(not(feature = "fast"))]
+    fn guarded() -> i32 {
+        0
+    }
+
+    #[no_mangle]
+    extern "C" fn exported() -> i32 {
+        for_export()
+    }
+
+    fn for_export() -> i32 {
+        1
+    }
+}
+
+mod consts {
+    pub(crate) const LIMIT: i32 = 10;
+    pub(crate) const UNUSED: i32 = 20;
+    pub(crate) type Word = u32;
+    static mut COUNTER: i32 = 0;
+}
+
+use consts::{LIMIT, UNUSED};
+use consts::Word;
+
+fn main() {
+    unsafe {
+        println!("{}", abs(helpers::twice(-LIMIT)));
+    }
+}
@@ -0,0 +1,18 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+// 14 casts before, 8 after.
+unsafe fn checksum(buf: *const u8, len: libc::c_int) -> libc::c_uint {
+    let mut sum: libc::c_uint = 0 as libc::c_uint;
+    let mut i: libc::c_int = 0 as libc::c_int;
+    while i < len as libc::c_int {
+        sum = sum.wrapping_add(*buf.offset(i as isize) as libc::c_uint) as libc::c_uint;
+        i += 1 as libc::c_int
+    }
+    let wide: u64 = sum as u64 as u64;
+    let p: *mut u8 = buf as *mut u8;
+    println!("{}", len as libc::c_int);
+    return (wide as libc::c_int + *p as libc::c_int) as libc::c_uint;
+}
+
+fn main() {}
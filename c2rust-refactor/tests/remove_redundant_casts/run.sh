@@ -0,0 +1,17 @@
+#!/bin/sh
+
+# work around System Integrity Protection on macOS
+if [ `uname` = 'Darwin' ]; then
+    export LD_LIBRARY_PATH=$not_LD_LIBRARY_PATH
+fi
+
+$refactor remove_redundant_casts -- old.rs $rustflags
+
+# Count the casts `checksum` has before and after.
+casts() {
+    grep -o ' as ' $1 | wc -l
+}
+status=0
+[ `casts old.rs` -eq 14 ] || status=1
+[ `casts old.new` -eq 8 ] || status=1
+exit $status
@@ -0,0 +1,62 @@
+#![feature(label_break_value)]
+
+fn add(a: i32, b: i32) -> i32 {
+    let c = a + b;
+    c
+}
+
+fn clamp(x: i32) -> i32 {
+    if x < 0 {
+        return 0;
+    }
+    x
+}
+
+fn report(x: i32) {
+    println!("{}", x);
+}
+
+fn sum(xs: &[i32]) -> i32 {
+    let mut total = 0;
+    {
+        for &x in xs {
+            total += x;
+        }
+    }
+    total
+}
+
+fn reset(xs: &mut [i32]) {
+    for x in xs.iter_mut() {
+        *x = 0;
+    }
+}
+
+fn classify(x: i32) -> i32 {
+    let mut y = 0;
+    {
+        if x > 10 {
+            y = 1;
+        }
+    }
+    y
+}
+
+fn first_negative(xs: &[i32]) -> i32 {
+    let mut found = -1;
+    'search: loop {
+        for &x in xs {
+            if x < 0 {
+                found = x;
+                break 'search;
+            }
+        }
+        break 'search;
+    }
+    found
+}
+
+fn main() {
+    reset(&mut [1, 2]);
+    report(add(1, 2) + clamp(-3) + sum(&[1, 2]) + classify(4) + first_negative(&[1, -1]));
+}
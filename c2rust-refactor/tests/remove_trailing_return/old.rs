@@ -0,0 +1,69 @@
+#![feature(label_break_value)]
+
+fn add(a: i32, b: i32) -> i32 {
+    let c = a + b;
+    return c;
+}
+
+fn clamp(x: i32) -> i32 {
+    if x < 0 {
+        return 0;
+    }
+    return x;
+}
+
+fn report(x: i32) {
+    println!("{}", x);
+    return;
+}
+
+fn sum(xs: &[i32]) -> i32 {
+    let mut total = 0;
+    'body: loop {
+        for &x in xs {
+            total += x;
+        }
+        break 'body;
+    }
+    return total;
+}
+
+fn reset(xs: &mut [i32]) {
+    'return_0: loop {
+        for x in xs.iter_mut() {
+            *x = 0;
+        }
+        return;
+        break 'return_0;
+    }
+}
+
+fn classify(x: i32) -> i32 {
+    let mut y = 0;
+    's_10: {
+        if x > 10 {
+            y = 1;
+        }
+        break 's_10;
+    }
+    return y;
+}
+
+fn first_negative(xs: &[i32]) -> i32 {
+    let mut found = -1;
+    'search: loop {
+        for &x in xs {
+            if x < 0 {
+                found = x;
+                break 'search;
+            }
+        }
+        break 'search;
+    }
+    return found;
+}
+
+fn main() {
+    reset(&mut [1, 2]);
+    report(add(1, 2) + clamp(-3) + sum(&[1, 2]) + classify(4) + first_negative(&[1, -1]));
+}
@@ -0,0 +1,61 @@
+static mut COUNTER: i32 = 0;
+
+struct Point {
+    x: i32,
+    y: i32,
+}
+
+impl Point {
+    unsafe fn sum(&self) -> i32 {
+        self.x + self.y
+    }
+}
+
+unsafe fn add(a: i32, b: i32) -> i32 {
+    a + b
+}
+
+unsafe fn twice(a: i32) -> i32 {
+    add(a, a)
+}
+
+unsafe fn read(p: *const i32) -> i32 {
+    *p
+}
+
+unsafe fn bump() -> i32 {
+    COUNTER += 1;
+    COUNTER
+}
+
+unsafe fn callback(x: i32) -> i32 {
+    x + 1
+}
+
+fn apply(f: unsafe fn(i32) -> i32, x: i32) -> i32 {
+    unsafe { f(x) }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn scale(x: i32) -> i32 {
+    unsafe { twice(x) * 2 }
+}
+
+fn main() {
+    let x = 1;
+    let p = Point { x: 1, y: 2 };
+    let a = unsafe { add(x, p.sum()) };
+    let b = unsafe { read(&x) };
+    let c = unsafe {
+        let t = twice(a);
+        t + bump()
+    };
+    let d = unsafe {
+        let t = scale(c);
+        t + b
+    };
+    let e = apply(callback, d);
+    unsafe {
+        COUNTER = e;
+    }
+}
@@ -0,0 +1,55 @@
+#![allow(non_camel_case_types, non_upper_case_globals)]
+
+pub mod a {
+    pub struct point {
+        pub x: i32,
+        pub y: i32,
+    }
+
+    pub fn origin() -> point {
+        point { x: 0, y: 0 }
+    }
+
+    // Renaming this would collide with `len` below.
+    pub fn rust_len(p: &point) -> i32 {
+        p.x + p.y
+    }
+
+    pub fn len(p: &point) -> i32 {
+        p.x.abs() + p.y.abs()
+    }
+}
+
+pub mod b {
+    use crate::a::origin;
+    use crate::a::point;
+
+    pub static step: i32 = 1;
+
+    pub fn shift(p: point) -> point {
+        let point { x, y } = p;
+        point { x: x + step, y: y + step }
+    }
+
+    pub fn start() -> point {
+        shift(origin())
+    }
+}
+
+pub mod c {
+    #[export_name = "rust_answer"]
+    pub extern "C" fn answer() -> i32 {
+        42
+    }
+
+    // Renaming this would produce a keyword.
+    pub fn rust_type() -> i32 {
+        0
+    }
+}
+
+fn main() {
+    let p = b::start();
+    println!("{} {}", a::rust_len(&p), a::len(&p));
+    println!("{}", c::answer() + c::rust_type());
+}
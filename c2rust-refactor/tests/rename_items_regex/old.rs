@@ -0,0 +1,54 @@
+#![allow(non_camel_case_types, non_upper_case_globals)]
+
+pub mod a {
+    pub struct rust_point {
+        pub x: i32,
+        pub y: i32,
+    }
+
+    pub fn rust_origin() -> rust_point {
+        rust_point { x: 0, y: 0 }
+    }
+
+    // Renaming this would collide with `len` below.
+    pub fn rust_len(p: &rust_point) -> i32 {
+        p.x + p.y
+    }
+
+    pub fn len(p: &rust_point) -> i32 {
+        p.x.abs() + p.y.abs()
+    }
+}
+
+pub mod b {
+    use crate::a::{rust_origin, rust_point};
+
+    pub static rust_step: i32 = 1;
+
+    pub fn rust_shift(p: rust_point) -> rust_point {
+        let rust_point { x, y } = p;
+        rust_point { x: x + rust_step, y: y + rust_step }
+    }
+
+    pub fn start() -> rust_point {
+        rust_shift(rust_origin())
+    }
+}
+
+pub mod c {
+    #[no_mangle]
+    pub extern "C" fn rust_answer() -> i32 {
+        42
+    }
+
+    // Renaming this would produce a keyword.
+    pub fn rust_type() -> i32 {
+        0
+    }
+}
+
+fn main() {
+    let p = b::start();
+    println!("{} {}", a::rust_len(&p), a::len(&p));
+    println!("{}", c::rust_answer() + c::rust_type());
+}
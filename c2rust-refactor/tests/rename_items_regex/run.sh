@@ -0,0 +1,11 @@
+#!/bin/sh
+
+# work around System Integrity Protection on macOS
+if [ `uname` = 'Darwin' ]; then
+    export LD_LIBRARY_PATH=$not_LD_LIBRARY_PATH
+fi
+
+$refactor \
+    rename_items_regex kind=fn,struct,static pattern=^rust_ replace= \
+        no_mangle=export_name \
+    -- old.rs $rustflags
@@ -0,0 +1,78 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+extern "C" {
+    fn malloc(_: libc::c_ulong) -> *mut libc::c_void;
+    fn calloc(_: libc::c_ulong, _: libc::c_ulong) -> *mut libc::c_void;
+    fn free(_: *mut libc::c_void);
+}
+
+#[derive(Copy, Clone, Default)]
+#[repr(C)]
+pub struct foo {
+    pub x: libc::c_int,
+}
+
+#[derive(Copy, Clone, Default)]
+#[repr(C)]
+pub struct named {
+    pub name: &'static str,
+}
+
+static mut LAST: *mut foo = 0 as *mut foo;
+
+unsafe fn foo_new() -> Box<foo> {
+    let p: *mut foo = Box::into_raw(Box::new(<foo as Default>::default()));
+    (*p).x = 1;
+    return Box::from_raw(p);
+}
+
+unsafe fn foo_try_new(x: libc::c_int) -> Option<Box<foo>> {
+    let p: *mut foo = Box::into_raw(Box::new(::std::mem::zeroed::<foo>()));
+    if p.is_null() {
+        return None;
+    }
+    if x < 0 {
+        drop(Box::from_raw(p));
+        return None;
+    }
+    (*p).x = x;
+    Some(Box::from_raw(p))
+}
+
+unsafe fn foo_default() -> Box<foo> {
+    foo_new()
+}
+
+unsafe fn foo_remembered() -> *mut foo {
+    let p: *mut foo = malloc(::std::mem::size_of::<foo>() as libc::c_ulong) as *mut foo;
+    LAST = p;
+    return p;
+}
+
+unsafe fn foo_named() -> *mut named {
+    let p: *mut named = calloc(
+        1 as libc::c_ulong,
+        ::std::mem::size_of::<named>() as libc::c_ulong,
+    ) as *mut named;
+    (*p).name = "foo";
+    return p;
+}
+
+fn main() {
+    unsafe {
+        let a = Box::into_raw(foo_new());
+        (*a).x += 1;
+        drop(Box::from_raw(a));
+        let b = foo_try_new(2).map_or(::std::ptr::null_mut(), Box::into_raw);
+        if !b.is_null() {
+            drop(Box::from_raw(b));
+        };
+        let c = Box::into_raw(foo_default());
+        drop(Box::from_raw(c));
+        let d = foo_remembered();
+        free(d as *mut libc::c_void);
+        let e = foo_named();
+        free(e as *mut libc::c_void);
+    }
+}
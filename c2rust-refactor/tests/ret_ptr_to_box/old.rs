@@ -0,0 +1,75 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+extern "C" {
+    fn malloc(_: libc::c_ulong) -> *mut libc::c_void;
+    fn calloc(_: libc::c_ulong, _: libc::c_ulong) -> *mut libc::c_void;
+    fn free(_: *mut libc::c_void);
+}
+
+#[derive(Copy, Clone, Default)]
+#[repr(C)]
+pub struct foo {
+    pub x: libc::c_int,
+}
+
+#[derive(Copy, Clone, Default)]
+#[repr(C)]
+pub struct named {
+    pub name: &'static str,
+}
+
+static mut LAST: *mut foo = 0 as *mut foo;
+
+unsafe fn foo_new() -> *mut foo {
+    let p: *mut foo = malloc(::std::mem::size_of::<foo>() as libc::c_ulong) as *mut foo;
+    (*p).x = 1;
+    return p;
+}
+
+unsafe fn foo_try_new(x: libc::c_int) -> *mut foo {
+    let p: *mut foo =
+        calloc(1 as libc::c_ulong, ::std::mem::size_of::<foo>() as libc::c_ulong) as *mut foo;
+    if p.is_null() {
+        return 0 as *mut foo;
+    }
+    if x < 0 {
+        free(p as *mut libc::c_void);
+        return 0 as *mut foo;
+    }
+    (*p).x = x;
+    p
+}
+
+unsafe fn foo_default() -> *mut foo {
+    foo_new()
+}
+
+unsafe fn foo_remembered() -> *mut foo {
+    let p: *mut foo = malloc(::std::mem::size_of::<foo>() as libc::c_ulong) as *mut foo;
+    LAST = p;
+    return p;
+}
+
+unsafe fn foo_named() -> *mut named {
+    let p: *mut named =
+        calloc(1 as libc::c_ulong, ::std::mem::size_of::<named>() as libc::c_ulong) as *mut named;
+    (*p).name = "foo";
+    return p;
+}
+
+fn main() {
+    unsafe {
+        let a = foo_new();
+        (*a).x += 1;
+        free(a as *mut libc::c_void);
+        let b = foo_try_new(2);
+        free(b as *mut libc::c_void);
+        let c = foo_default();
+        free(c as *mut libc::c_void);
+        let d = foo_remembered();
+        free(d as *mut libc::c_void);
+        let e = foo_named();
+        free(e as *mut libc::c_void);
+    }
+}
@@ -0,0 +1,26 @@
+fn first() -> i32 {
+    let a = 3;
+    a
+}
+
+fn unchanged_a() -> i32 {
+    10
+}
+
+fn unchanged_b() -> i32 {
+    20
+}
+
+fn unchanged_c() -> i32 {
+    30
+}
+
+fn last() -> i32 {
+    let b = 3;
+    b
+}
+
+fn main() {
+    println!("{} {}", first(), last());
+    println!("{} {} {}", unchanged_a(), unchanged_b(), unchanged_c());
+}
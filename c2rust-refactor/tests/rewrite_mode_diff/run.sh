@@ -0,0 +1,30 @@
+#!/bin/sh
+
+# work around System Integrity Protection on macOS
+if [ `uname` = 'Darwin' ]; then
+    export LD_LIBRARY_PATH=$not_LD_LIBRARY_PATH
+fi
+
+# Rewrite alongside, to get the text that `new.rs` is checked against.
+$refactor \
+    rewrite_expr 1 2 \; commit \; \
+    rewrite_expr 2 3 \; \
+    -- old.rs $rustflags
+
+# Then produce a patch instead.  The patch covers both commits, so it has a
+# single header for `old.rs`.  That it applies to give the rewritten text is
+# checked with a patch library in `tests/diff_patch.rs`.
+$refactor_bin -r diff --diff-context 1 --diff-out old.patch \
+    rewrite_expr 1 2 \; commit \; \
+    rewrite_expr 2 3 \; \
+    -- old.rs $rustflags
+status=0
+[ `grep -c '^+++ ' old.patch` = 1 ] || status=1
+[ `grep -c '^@@ ' old.patch` = 2 ] || status=1
+
+# A context size that isn't a number is a usage error.
+$refactor_bin -r diff --diff-context many rewrite_expr 1 2 -- old.rs $rustflags \
+    2>/dev/null && status=1
+
+rm -f old.patch
+exit $status
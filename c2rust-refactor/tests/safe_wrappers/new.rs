@@ -0,0 +1,76 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+#[repr(C)]
+pub struct point {
+    pub x: libc::c_int,
+    pub y: libc::c_int,
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn name_len(name: *const libc::c_char) -> libc::c_int {
+    libc::strlen(name) as libc::c_int
+}
+pub fn name_len_safe(name: &str) -> Result<libc::c_int, libc::c_int> {
+    let name = ::std::ffi::CString::new(name).expect("`name` contains a NUL byte");
+    let ret = unsafe { name_len(name.as_ptr() as *const libc::c_char) };
+    if ret < 0 {
+        Err(ret)
+    } else {
+        Ok(ret)
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn fill(buf: *mut u8, len: libc::size_t, byte: u8) -> libc::c_int {
+    if buf.is_null() {
+        return -1;
+    }
+    let mut i = 0;
+    while i < len {
+        *buf.offset(i as isize) = byte;
+        i += 1;
+    }
+    0
+}
+pub fn fill_safe(buf: &mut [u8], byte: u8) -> Result<libc::c_int, libc::c_int> {
+    let ret = unsafe { fill(buf.as_mut_ptr(), buf.len() as libc::size_t, byte) };
+    if ret < 0 {
+        Err(ret)
+    } else {
+        Ok(ret)
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn point_x(p: *const point) -> libc::c_int {
+    if p.is_null() {
+        return -1;
+    }
+    (*p).x
+}
+pub fn point_x_safe(p: Option<&point>) -> Result<libc::c_int, libc::c_int> {
+    let ret = unsafe { point_x(p.map_or(::std::ptr::null(), |x| x as *const _)) };
+    if ret < 0 {
+        Err(ret)
+    } else {
+        Ok(ret)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn call_wrappers() {
+        assert_eq!(name_len_safe("hello"), Ok(5));
+        let mut buf = [0u8; 4];
+        assert_eq!(fill_safe(&mut buf, 7), Ok(0));
+        assert_eq!(buf, [7; 4]);
+        assert_eq!(point_x_safe(Some(&point { x: 3, y: 4 })), Ok(3));
+        assert_eq!(point_x_safe(None), Err(-1));
+    }
+}
+
+fn main() {}
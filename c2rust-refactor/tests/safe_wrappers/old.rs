@@ -0,0 +1,51 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+#[repr(C)]
+pub struct point {
+    pub x: libc::c_int,
+    pub y: libc::c_int,
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn name_len(name: *const libc::c_char) -> libc::c_int {
+    libc::strlen(name) as libc::c_int
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn fill(buf: *mut u8, len: libc::size_t, byte: u8) -> libc::c_int {
+    if buf.is_null() {
+        return -1;
+    }
+    let mut i = 0;
+    while i < len {
+        *buf.offset(i as isize) = byte;
+        i += 1;
+    }
+    0
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn point_x(p: *const point) -> libc::c_int {
+    if p.is_null() {
+        return -1;
+    }
+    (*p).x
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn call_wrappers() {
+        assert_eq!(name_len_safe("hello"), Ok(5));
+        let mut buf = [0u8; 4];
+        assert_eq!(fill_safe(&mut buf, 7), Ok(0));
+        assert_eq!(buf, [7; 4]);
+        assert_eq!(point_x_safe(Some(&point { x: 3, y: 4 })), Ok(3));
+        assert_eq!(point_x_safe(None), Err(-1));
+    }
+}
+
+fn main() {}
@@ -0,0 +1,22 @@
+#!/bin/sh
+
+# work around System Integrity Protection on macOS
+if [ `uname` = 'Darwin' ]; then
+    export LD_LIBRARY_PATH=$not_LD_LIBRARY_PATH
+fi
+
+$refactor \
+    select target 'crate; child(fn && (name("name_len") || name("fill") ||
+        name("point_x")));' \; \
+    safe_wrappers errors=negative \
+    -- old.rs $rustflags
+
+# Build the rewritten crate's tests, which call each wrapper, and run them.
+rustc --test --crate-name safe_wrappers $rustflags -o safe_wrappers_test old.new
+status=$?
+if [ $status = 0 ]; then
+    ./safe_wrappers_test -q
+    status=$?
+fi
+rm -f safe_wrappers_test
+exit $status
@@ -0,0 +1,11 @@
+fn new_first(x: i32) -> i32 {
+    x + 1
+}
+
+fn old_second(x: i32) -> i32 {
+    x * 2
+}
+
+fn main() {
+    println!("{} {}", new_first(1), old_second(2));
+}
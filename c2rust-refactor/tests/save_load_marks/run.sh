@@ -0,0 +1,20 @@
+#!/bin/sh
+
+# work around System Integrity Protection on macOS
+if [ `uname` = 'Darwin' ]; then
+    export LD_LIBRARY_PATH=$not_LD_LIBRARY_PATH
+fi
+
+# Mark and save in one run, then load and transform in a second run, where all
+# `NodeId`s are different.
+$refactor \
+    select target 'crate; desc(fn && name("old_first"));' \; \
+    save_marks marks.json \
+    -- old.rs $rustflags
+
+$refactor \
+    load_marks marks.json \; \
+    rename_items_regex '^old_' 'new_' target \
+    -- old.rs $rustflags
+
+rm -f marks.json
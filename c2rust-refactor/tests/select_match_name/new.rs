@@ -0,0 +1,24 @@
+extern "C" {
+    fn png_io_init(x: i32) -> i32;
+}
+
+fn png_read_setup() -> i32 {
+    1
+}
+
+fn png_write_setup() -> i32 {
+    2
+}
+
+fn png_read_init_row() -> i32 {
+    3
+}
+
+fn zlib_init() -> i32 {
+    4
+}
+
+fn main() {
+    let total = png_read_setup() + png_write_setup() + png_read_init_row() + zlib_init();
+    println!("{}", total);
+}
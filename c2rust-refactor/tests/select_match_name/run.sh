@@ -0,0 +1,11 @@
+#!/bin/sh
+
+# work around System Integrity Protection on macOS
+if [ `uname` = 'Darwin' ]; then
+    export LD_LIBRARY_PATH=$not_LD_LIBRARY_PATH
+fi
+
+$refactor \
+    select target 'crate; desc(kind(fn) && !foreign_fn && match_name("^png_.*_init$"));' \; \
+    rename_items_regex '_init$' '_setup' target \
+    -- old.rs $rustflags
@@ -0,0 +1,80 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct __jmp_buf_tag {
+    pub __jmpbuf: [libc::c_long; 8],
+    pub __mask_was_saved: libc::c_int,
+    pub __saved_mask: [libc::c_ulong; 16],
+}
+
+pub type jmp_buf = [__jmp_buf_tag; 1];
+
+extern "C" {
+    fn _setjmp(_: *mut __jmp_buf_tag) -> libc::c_int;
+    fn longjmp(_: *mut __jmp_buf_tag, _: libc::c_int) -> !;
+    fn puts(_: *const libc::c_char) -> libc::c_int;
+}
+
+unsafe fn check(x: libc::c_int) -> libc::c_int {
+    let mut result: libc::c_int = 0;
+    let mut env: jmp_buf = ::std::mem::zeroed();
+    if _setjmp(env.as_mut_ptr()) == 0 {
+        if x < 0 {
+            longjmp(env.as_mut_ptr(), 1);
+        }
+        result = x;
+    } else {
+        result = -1;
+    }
+    return result;
+}
+
+unsafe fn parse_digit(buf: *mut __jmp_buf_tag, c: libc::c_char) -> libc::c_int {
+    if c < '0' as libc::c_char || c > '9' as libc::c_char {
+        longjmp(buf, 2);
+    }
+    return (c - '0' as libc::c_char) as libc::c_int;
+}
+
+unsafe fn parse_pair(buf: *mut __jmp_buf_tag, s: *const libc::c_char) -> libc::c_int {
+    return parse_digit(buf, *s) * 10 + parse_digit(buf, *s.offset(1));
+}
+
+unsafe fn parse(s: *const libc::c_char) -> libc::c_int {
+    let mut n: libc::c_int = -1;
+    let mut env: jmp_buf = ::std::mem::zeroed();
+    if _setjmp(env.as_mut_ptr()) == 0 {
+        n = parse_pair(env.as_mut_ptr(), s);
+    } else {
+        puts(b"bad number\x00" as *const u8 as *const libc::c_char);
+    }
+    return n;
+}
+
+static mut ENV: jmp_buf = [__jmp_buf_tag {
+    __jmpbuf: [0; 8],
+    __mask_was_saved: 0,
+    __saved_mask: [0; 16],
+}; 1];
+
+unsafe fn fail() {
+    longjmp(ENV.as_mut_ptr(), 1);
+}
+
+unsafe fn run() -> libc::c_int {
+    if _setjmp(ENV.as_mut_ptr()) == 0 {
+        fail();
+        return 0;
+    }
+    return 1;
+}
+
+fn main() {
+    unsafe {
+        check(1);
+        parse(b"42\x00" as *const u8 as *const libc::c_char);
+        run();
+    }
+}
@@ -0,0 +1,41 @@
+struct Node {
+    value: i32,
+    next: *mut Node,
+}
+
+struct Pair {
+    a: i32,
+    b: Vec<i32>,
+}
+
+impl Pair {
+    fn total(&self) -> i32 {
+        self.a + self.b.len() as i32
+    }
+}
+
+struct List<'a> {
+    head: &'a mut Node,
+}
+
+fn by_ref(p: &Pair, q: &mut Pair) -> i32 {
+    q.a = p.a;
+    q.b.push(1);
+    p.b.len() as i32 + q.a + p.total()
+}
+
+fn by_box(mut p: Box<Pair>) -> usize {
+    p.a += 1;
+    p.b.push(p.a);
+    p.b.len()
+}
+
+unsafe fn by_raw(p: *mut Node) -> i32 {
+    (*(*p).next).value + (*p).value
+}
+
+fn nested(l: &List) -> i32 {
+    l.head.value
+}
+
+fn main() {}
@@ -0,0 +1,52 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+extern "C" {
+    fn malloc(_: libc::c_ulong) -> *mut libc::c_void;
+    fn memcpy(_: *mut libc::c_void, _: *const libc::c_void, _: libc::c_ulong) -> *mut libc::c_void;
+    fn fwrite(_: *const libc::c_void, _: libc::size_t, _: libc::size_t, _: *mut libc::FILE)
+        -> libc::size_t;
+}
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct foo {
+    pub a: libc::c_int,
+    pub b: libc::c_double,
+}
+
+unsafe fn alloc_foos(n: libc::c_int) -> *mut foo {
+    malloc((::std::mem::size_of::<foo>() * n as usize) as libc::c_ulong) as *mut foo
+}
+
+unsafe fn alloc_name(len: libc::c_int) -> *mut libc::c_char {
+    malloc(len as libc::c_ulong) as *mut libc::c_char
+}
+
+unsafe fn copy_foos(dst: *mut foo, src: *const foo, n: libc::c_int) {
+    memcpy(
+        dst as *mut libc::c_void,
+        src as *const libc::c_void,
+        (n as usize * ::std::mem::size_of::<foo>()) as libc::c_ulong,
+    );
+    memcpy(
+        dst as *mut libc::c_void,
+        src as *const libc::c_void,
+        ::std::mem::size_of::<crate::foo>() as libc::c_ulong,
+    );
+}
+
+unsafe fn write_foos(f: *mut libc::FILE, foos: *const foo, n: libc::c_int) -> libc::size_t {
+    fwrite(
+        foos as *const libc::c_void,
+        ::std::mem::size_of::<foo>(),
+        n as libc::size_t,
+        f,
+    )
+}
+
+unsafe fn foos_bytes(n: libc::c_int) -> usize {
+    ::std::mem::size_of::<foo>() * n as usize
+}
+
+fn main() {}
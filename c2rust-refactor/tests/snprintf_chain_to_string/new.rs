@@ -0,0 +1,44 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+extern "C" {
+    fn snprintf(
+        _: *mut libc::c_char,
+        _: libc::c_ulong,
+        _: *const libc::c_char,
+        _: ...
+    ) -> libc::c_int;
+    fn puts(__s: *const libc::c_char) -> libc::c_int;
+    fn log_message(level: libc::c_int, msg: *const libc::c_char);
+    fn strlen(_: *const libc::c_char) -> libc::c_ulong;
+}
+
+unsafe fn print_point(x: libc::c_int, y: libc::c_int) {
+    let mut n: libc::c_int = 0 as libc::c_int;
+    use std::fmt::Write;
+    let mut buf = String::new();
+    write!(buf, "x={:}", x as libc::c_int).unwrap();
+    write!(buf, ", y={:}", y as libc::c_int).unwrap();
+    println!("{}", buf);
+}
+
+unsafe fn report(code: libc::c_int, retries: libc::c_uint) -> libc::c_int {
+    let mut len: libc::c_int = 0 as libc::c_int;
+    use std::fmt::Write;
+    let mut msg = String::new();
+    write!(msg, "failed with code {:}", code as libc::c_int).unwrap();
+    write!(msg, " after {:} retries", retries as libc::c_uint).unwrap();
+    msg.push_str(".");
+    len = msg.len() as libc::c_int;
+    let msg_c = ::std::ffi::CString::new(msg.as_str()).unwrap();
+    log_message(2 as libc::c_int, msg_c.as_ptr() as *const libc::c_char);
+    let shown: libc::c_ulong = msg.len() as libc::c_ulong;
+    return len - shown as libc::c_int;
+}
+
+fn main() {
+    unsafe {
+        print_point(1 as libc::c_int, 2 as libc::c_int);
+        report(3 as libc::c_int, 4 as libc::c_uint);
+    }
+}
@@ -0,0 +1,47 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+extern "C" {
+    fn snprintf(_: *mut libc::c_char, _: libc::c_ulong, _: *const libc::c_char, _: ...)
+                -> libc::c_int;
+    fn puts(__s: *const libc::c_char) -> libc::c_int;
+    fn log_message(level: libc::c_int, msg: *const libc::c_char);
+    fn strlen(_: *const libc::c_char) -> libc::c_ulong;
+}
+
+unsafe fn print_point(x: libc::c_int, y: libc::c_int) {
+    let mut buf: [libc::c_char; 64] = [0; 64];
+    let mut n: libc::c_int = 0 as libc::c_int;
+    n = snprintf(buf.as_mut_ptr(),
+                 ::std::mem::size_of::<[libc::c_char; 64]>() as libc::c_ulong,
+                 b"x=%d\x00" as *const u8 as *const libc::c_char, x);
+    n += snprintf(buf.as_mut_ptr().offset(n as isize),
+                  (::std::mem::size_of::<[libc::c_char; 64]>() as libc::c_ulong)
+                      .wrapping_sub(n as libc::c_ulong),
+                  b", y=%d\x00" as *const u8 as *const libc::c_char, y);
+    puts(buf.as_mut_ptr());
+}
+
+unsafe fn report(code: libc::c_int, retries: libc::c_uint) -> libc::c_int {
+    let mut msg: [libc::c_char; 128] = [0; 128];
+    let mut len: libc::c_int = 0 as libc::c_int;
+    len += snprintf(msg.as_mut_ptr().offset(len as isize),
+                    (128 as libc::c_int - len) as libc::c_ulong,
+                    b"failed with code %d\x00" as *const u8 as *const libc::c_char, code);
+    len += snprintf(msg.as_mut_ptr().offset(len as isize),
+                    (128 as libc::c_int - len) as libc::c_ulong,
+                    b" after %u retries\x00" as *const u8 as *const libc::c_char, retries);
+    len += snprintf(msg.as_mut_ptr().offset(len as isize),
+                    (128 as libc::c_int - len) as libc::c_ulong,
+                    b".\x00" as *const u8 as *const libc::c_char);
+    log_message(2 as libc::c_int, msg.as_mut_ptr());
+    let shown: libc::c_ulong = strlen(msg.as_mut_ptr());
+    return len - shown as libc::c_int;
+}
+
+fn main() {
+    unsafe {
+        print_point(1 as libc::c_int, 2 as libc::c_int);
+        report(3 as libc::c_int, 4 as libc::c_uint);
+    }
+}
@@ -0,0 +1,72 @@
+#![feature(register_tool)]
+#![register_tool(c2rust)]
+#![allow(non_camel_case_types)]
+#![allow(non_upper_case_globals)]
+#![allow(dead_code)]
+
+pub mod amalg {
+    pub use self::list::list_max;
+    pub use self::list::node;
+    pub use self::alloc::alloc_node;
+    use self::alloc::live_nodes;
+    use self::util::LIMIT;
+    pub mod list {
+        use super::util::max;
+
+        #[c2rust::header_src = "/home/user/proj/src/list.c:2"]
+        #[derive(Copy, Clone)]
+        #[repr(C)]
+        pub struct node {
+            pub value: i32,
+            pub next: *mut node,
+        }
+
+        #[c2rust::header_src = "/home/user/proj/src/list.c:2"]
+        pub unsafe fn list_max(mut head: *mut node) -> i32 {
+            let mut m = 0;
+            while !head.is_null() {
+                m = max(m, (*head).value);
+                head = (*head).next;
+            }
+            m
+        }
+    }
+    pub mod alloc {
+        use super::list::node;
+
+        #[c2rust::header_src = "/home/user/proj/src/alloc.c:1"]
+        pub(crate) static mut live_nodes: i32 = 0;
+
+        #[c2rust::header_src = "/home/user/proj/src/alloc.c:1"]
+        pub unsafe fn alloc_node(value: i32) -> *mut node {
+            live_nodes += 1;
+            Box::into_raw(Box::new(node { value, next: 0 as *mut node }))
+        }
+    }
+    pub mod util {
+        #[c2rust::header_src = "/home/user/proj/src/util.c:3"]
+        pub(crate) fn max(a: i32, b: i32) -> i32 {
+            if a > b { a } else { b }
+        }
+
+        #[c2rust::header_src = "/home/user/proj/src/util.c:3"]
+        pub(crate) const LIMIT: i32 = 100;
+    }
+
+    unsafe fn check(head: *mut node) -> bool {
+        list_max(head) < LIMIT && live_nodes < LIMIT
+    }
+
+    pub unsafe fn run() -> bool {
+        let head = alloc_node(1);
+        (*head).next = alloc_node(2);
+        check(head)
+    }
+}
+
+fn main() {
+    unsafe {
+        amalg::run();
+        amalg::list_max(amalg::alloc_node(3));
+    }
+}
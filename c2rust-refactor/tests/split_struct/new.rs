@@ -0,0 +1,75 @@
+#[derive(Copy, Clone)]
+pub struct Context {
+    pub name: i32,
+    pub io: ContextIo,
+    pub stats: ContextStats,
+}
+#[derive(Copy, Clone)]
+pub struct ContextIo {
+    pub fd: i32,
+    pub buf_len: usize,
+}
+#[derive(Copy, Clone)]
+pub struct ContextStats {
+    pub reads: u64,
+    pub writes: u64,
+}
+
+pub fn new_context(fd: i32) -> Context {
+    Context {
+        name: 0,
+        io: ContextIo {
+            fd: fd,
+            buf_len: 0,
+        },
+        stats: ContextStats {
+            reads: 0,
+            writes: 0,
+        },
+    }
+}
+
+pub fn reopen(ctx: &Context, fd: i32) -> Context {
+    Context {
+        io: ContextIo {
+            fd: fd,
+            ..(*ctx).io
+        },
+        ..*ctx
+    }
+}
+
+mod io {
+    use super::Context;
+
+    pub fn read_some(ctx: &mut Context, n: usize) {
+        if ctx.io.fd >= 0 {
+            ctx.io.buf_len += n;
+            ctx.stats.reads += 1;
+        }
+    }
+}
+
+mod stats {
+    use super::Context;
+
+    pub fn total(ctx: &Context) -> u64 {
+        let Context {
+            stats: crate::ContextStats { reads, writes },
+            ..
+        } = *ctx;
+        reads + writes
+    }
+
+    pub unsafe fn reset(ctx: *mut Context) {
+        (*ctx).stats.reads = 0;
+        (*ctx).stats.writes = 0;
+    }
+}
+
+fn main() {
+    let mut ctx = new_context(3);
+    io::read_some(&mut ctx, 10);
+    let ctx = reopen(&ctx, 4);
+    println!("{} {} {}", ctx.io.fd, ctx.io.buf_len, stats::total(&ctx));
+}
@@ -0,0 +1,54 @@
+#[derive(Copy, Clone)]
+pub struct Context {
+    pub name: i32,
+    pub fd: i32,
+    pub buf_len: usize,
+    pub reads: u64,
+    pub writes: u64,
+}
+
+pub fn new_context(fd: i32) -> Context {
+    Context {
+        name: 0,
+        fd: fd,
+        buf_len: 0,
+        reads: 0,
+        writes: 0,
+    }
+}
+
+pub fn reopen(ctx: &Context, fd: i32) -> Context {
+    Context { fd: fd, ..*ctx }
+}
+
+mod io {
+    use super::Context;
+
+    pub fn read_some(ctx: &mut Context, n: usize) {
+        if ctx.fd >= 0 {
+            ctx.buf_len += n;
+            ctx.reads += 1;
+        }
+    }
+}
+
+mod stats {
+    use super::Context;
+
+    pub fn total(ctx: &Context) -> u64 {
+        let Context { reads, writes, .. } = *ctx;
+        reads + writes
+    }
+
+    pub unsafe fn reset(ctx: *mut Context) {
+        (*ctx).reads = 0;
+        (*ctx).writes = 0;
+    }
+}
+
+fn main() {
+    let mut ctx = new_context(3);
+    io::read_some(&mut ctx, 10);
+    let ctx = reopen(&ctx, 4);
+    println!("{} {} {}", ctx.fd, ctx.buf_len, stats::total(&ctx));
+}
@@ -0,0 +1,16 @@
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct conn_t {
+    pub fd: i32,
+    pub flags: u32,
+    pub timeout: i64,
+}
+
+pub fn is_open(conn: &conn_t) -> bool {
+    conn.fd >= 0 && conn.flags != 0
+}
+
+fn main() {
+    let conn = conn_t { fd: 3, flags: 1, timeout: 0 };
+    println!("{}", is_open(&conn));
+}
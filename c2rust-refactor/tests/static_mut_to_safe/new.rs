@@ -0,0 +1,64 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+#[derive(Clone, Copy)]
+struct Point {
+    x: i32,
+    y: i32,
+}
+
+#[derive(Clone, Copy)]
+struct Config {
+    verbose: bool,
+    level: i32,
+}
+
+static COUNT: ::std::sync::atomic::AtomicI32 = ::std::sync::atomic::AtomicI32::new(0);
+static READY: ::std::sync::atomic::AtomicBool = ::std::sync::atomic::AtomicBool::new(false);
+static POS: ::once_cell::sync::Lazy<::std::sync::Mutex<Point>> = ::once_cell::sync::Lazy::new(|| ::std::sync::Mutex::new(Point { x: 0, y: 0 }));
+static CONFIG: ::once_cell::sync::OnceCell<Config> = ::once_cell::sync::OnceCell::new();
+static ORIGIN: ::once_cell::sync::Lazy<::std::sync::Mutex<Point>> = ::once_cell::sync::Lazy::new(|| ::std::sync::Mutex::new(Point { x: 0, y: 0 }));
+static mut LAST: Point = Point { x: 0, y: 0 };
+static mut TOTAL: i64 = 0;
+
+unsafe fn init() {
+    *ORIGIN.lock().unwrap() = Point { x: 1, y: 1 };
+    READY.store(true, ::std::sync::atomic::Ordering::SeqCst);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn step(dx: i32) -> i32 {
+    COUNT.fetch_add(1, ::std::sync::atomic::Ordering::SeqCst);
+    COUNT.store(COUNT.load(::std::sync::atomic::Ordering::SeqCst) * 2, ::std::sync::atomic::Ordering::SeqCst);
+    (*POS.lock().unwrap()).x += dx;
+    if (*CONFIG.get().unwrap()).verbose {
+        (*POS.lock().unwrap()).y = (*CONFIG.get().unwrap()).level;
+    }
+    COUNT.load(::std::sync::atomic::Ordering::SeqCst)
+}
+
+unsafe fn reset() {
+    match LAST.x {
+        0 => {}
+        _ => {
+            LAST.x = 0;
+        }
+    }
+}
+
+unsafe fn add_total(p: *mut i64, v: i64) {
+    *p += v;
+}
+
+fn main() {
+    unsafe {
+        CONFIG.set(Config { verbose: true, level: 2 }).ok().expect("`CONFIG` was already set");
+        init();
+        let n = step(3);
+        let o = *ORIGIN.lock().unwrap();
+        reset();
+        if READY.load(::std::sync::atomic::Ordering::SeqCst) {
+            add_total(&mut TOTAL, n as i64 + o.x as i64);
+        }
+    }
+}
@@ -0,0 +1,64 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+#[derive(Clone, Copy)]
+struct Point {
+    x: i32,
+    y: i32,
+}
+
+#[derive(Clone, Copy)]
+struct Config {
+    verbose: bool,
+    level: i32,
+}
+
+static mut COUNT: libc::c_int = 0;
+static mut READY: bool = false;
+static mut POS: Point = Point { x: 0, y: 0 };
+static mut CONFIG: Config = Config { verbose: false, level: 0 };
+static mut ORIGIN: Point = Point { x: 0, y: 0 };
+static mut LAST: Point = Point { x: 0, y: 0 };
+static mut TOTAL: i64 = 0;
+
+unsafe fn init() {
+    ORIGIN = Point { x: 1, y: 1 };
+    READY = true;
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn step(dx: i32) -> i32 {
+    COUNT += 1;
+    COUNT *= 2;
+    POS.x += dx;
+    if CONFIG.verbose {
+        POS.y = CONFIG.level;
+    }
+    COUNT
+}
+
+unsafe fn reset() {
+    match LAST.x {
+        0 => {}
+        _ => {
+            LAST.x = 0;
+        }
+    }
+}
+
+unsafe fn add_total(p: *mut i64, v: i64) {
+    *p += v;
+}
+
+fn main() {
+    unsafe {
+        CONFIG = Config { verbose: true, level: 2 };
+        init();
+        let n = step(3);
+        let o = ORIGIN;
+        reset();
+        if READY {
+            add_total(&mut TOTAL, n as i64 + o.x as i64);
+        }
+    }
+}
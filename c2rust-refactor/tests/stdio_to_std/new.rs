@@ -0,0 +1,78 @@
+#![allow(non_camel_case_types)]
+extern crate libc;
+
+pub type FILE = libc::FILE;
+
+extern "C" {
+    fn fopen(path: *const libc::c_char, mode: *const libc::c_char) -> *mut FILE;
+    fn fclose(f: *mut FILE) -> libc::c_int;
+    fn fread(buf: *mut libc::c_void, size: libc::c_ulong, n: libc::c_ulong,
+             f: *mut FILE) -> libc::c_ulong;
+    fn fwrite(buf: *const libc::c_void, size: libc::c_ulong, n: libc::c_ulong,
+              f: *mut FILE) -> libc::c_ulong;
+    fn fputc(c: libc::c_int, f: *mut FILE) -> libc::c_int;
+}
+
+unsafe fn count_bytes(path: *const libc::c_char) -> libc::c_ulong {
+    let mut buf: [u8; 256] = [0; 256];
+    let mut total: libc::c_ulong = 0;
+    let mut f: Option<::std::fs::File> = ::std::fs::File::open(
+        ::std::os::unix::ffi::OsStrExt::from_bytes(::std::ffi::CStr::from_ptr(path).to_bytes()),
+    )
+    .ok();
+    if f.is_none() {
+        return 0;
+    }
+    loop {
+        let n = ::std::io::Read::read(f.as_mut().unwrap(), &mut buf[..256 as usize]).unwrap_or(0)
+            as libc::c_ulong;
+        if n == 0 {
+            break;
+        }
+        total = total.wrapping_add(n);
+    }
+    drop(f.take());
+    total
+}
+
+unsafe fn save(data: &[u8]) -> bool {
+    let mut f: Option<::std::fs::File> = None;
+    f = ::std::fs::File::create("out.bin").ok();
+    if f.is_none() {
+        return false;
+    }
+    let ok = ::std::io::Write::write_all(f.as_mut().unwrap(), &data[..data.len() as usize])
+        .is_ok() as libc::c_ulong == 1;
+    drop(f.take());
+    ok
+}
+
+unsafe fn log_line(line: &[u8]) {
+    let mut f: Option<::std::fs::File> = ::std::fs::OpenOptions::new()
+        .read(true)
+        .append(true)
+        .create(true)
+        .open("log.txt")
+        .ok();
+    if !f.is_none() {
+        ::std::io::Write::write_all(f.as_mut().unwrap(), &line[..line.len() as usize])
+            .map_or(0, |_| line.len() as usize) as libc::c_ulong;
+        drop(f.take());
+    }
+}
+
+unsafe fn log_char(c: libc::c_int) {
+    let mut f: *mut FILE = fopen(b"log.txt\x00" as *const u8 as *const libc::c_char,
+                                 b"a\x00" as *const u8 as *const libc::c_char);
+    fputc(c, f);
+    fclose(f);
+}
+
+fn main() {
+    unsafe {
+        save(b"hello\n");
+        log_line(b"saved\n");
+        log_char('\n' as libc::c_int);
+        println!("{}", count_bytes(b"out.bin\x00" as *const u8 as *const libc::c_char));
+    }
+}
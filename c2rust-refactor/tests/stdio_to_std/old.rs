@@ -0,0 +1,69 @@
+#![allow(non_camel_case_types)]
+extern crate libc;
+
+pub type FILE = libc::FILE;
+
+extern "C" {
+    fn fopen(path: *const libc::c_char, mode: *const libc::c_char) -> *mut FILE;
+    fn fclose(f: *mut FILE) -> libc::c_int;
+    fn fread(buf: *mut libc::c_void, size: libc::c_ulong, n: libc::c_ulong,
+             f: *mut FILE) -> libc::c_ulong;
+    fn fwrite(buf: *const libc::c_void, size: libc::c_ulong, n: libc::c_ulong,
+              f: *mut FILE) -> libc::c_ulong;
+    fn fputc(c: libc::c_int, f: *mut FILE) -> libc::c_int;
+}
+
+unsafe fn count_bytes(path: *const libc::c_char) -> libc::c_ulong {
+    let mut buf: [u8; 256] = [0; 256];
+    let mut total: libc::c_ulong = 0;
+    let mut f: *mut FILE = fopen(path, b"rb\x00" as *const u8 as *const libc::c_char);
+    if f.is_null() {
+        return 0;
+    }
+    loop {
+        let n = fread(buf.as_mut_ptr() as *mut libc::c_void, 1, 256, f);
+        if n == 0 {
+            break;
+        }
+        total = total.wrapping_add(n);
+    }
+    fclose(f);
+    total
+}
+
+unsafe fn save(data: &[u8]) -> bool {
+    let mut f: *mut FILE = 0 as *mut FILE;
+    f = fopen(b"out.bin\x00" as *const u8 as *const libc::c_char,
+              b"w\x00" as *const u8 as *const libc::c_char);
+    if f == 0 as *mut FILE {
+        return false;
+    }
+    let ok = fwrite(data.as_ptr() as *const libc::c_void, data.len() as libc::c_ulong, 1, f) == 1;
+    fclose(f);
+    ok
+}
+
+unsafe fn log_line(line: &[u8]) {
+    let mut f: *mut FILE = fopen(b"log.txt\x00" as *const u8 as *const libc::c_char,
+                                 b"a+\x00" as *const u8 as *const libc::c_char);
+    if !f.is_null() {
+        fwrite(line.as_ptr() as *const libc::c_void, 1, line.len() as libc::c_ulong, f);
+        fclose(f);
+    }
+}
+
+unsafe fn log_char(c: libc::c_int) {
+    let mut f: *mut FILE = fopen(b"log.txt\x00" as *const u8 as *const libc::c_char,
+                                 b"a\x00" as *const u8 as *const libc::c_char);
+    fputc(c, f);
+    fclose(f);
+}
+
+fn main() {
+    unsafe {
+        save(b"hello\n");
+        log_line(b"saved\n");
+        log_char('\n' as libc::c_int);
+        println!("{}", count_bytes(b"out.bin\x00" as *const u8 as *const libc::c_char));
+    }
+}
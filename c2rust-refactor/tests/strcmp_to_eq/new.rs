@@ -0,0 +1,47 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+extern "C" {
+    fn strcmp(_: *const libc::c_char, _: *const libc::c_char) -> libc::c_int;
+    fn strncmp(_: *const libc::c_char, _: *const libc::c_char, _: libc::c_ulong) -> libc::c_int;
+    fn strcasecmp(_: *const libc::c_char, _: *const libc::c_char) -> libc::c_int;
+}
+
+unsafe fn literal(x: *const libc::c_char) -> bool {
+    ::std::ffi::CStr::from_ptr(x).to_bytes() == b"foo"
+}
+
+unsafe fn literal_ptr(x: *const libc::c_char) -> bool {
+    ::std::ffi::CStr::from_ptr(x).to_bytes() != b"foo"
+}
+
+unsafe fn pointers(a: *const libc::c_char, b: *const libc::c_char) -> bool {
+    ::std::ffi::CStr::from_ptr(a) == ::std::ffi::CStr::from_ptr(b)
+}
+
+unsafe fn prefix(a: *const libc::c_char, b: *const libc::c_char, n: libc::c_int) -> bool {
+    ::std::ffi::CStr::from_ptr(a)
+        .to_bytes()
+        .iter()
+        .take(n as usize)
+        .eq(::std::ffi::CStr::from_ptr(b)
+            .to_bytes()
+            .iter()
+            .take(n as usize))
+}
+
+unsafe fn case(a: *const libc::c_char, b: *const libc::c_char) -> bool {
+    !::std::ffi::CStr::from_ptr(a)
+        .to_bytes()
+        .eq_ignore_ascii_case(::std::ffi::CStr::from_ptr(b).to_bytes())
+}
+
+unsafe fn ordering(a: *const libc::c_char, b: *const libc::c_char) -> bool {
+    strcmp(a, b) < 0 as libc::c_int
+}
+
+fn in_block(a: *const libc::c_char, b: *const libc::c_char) -> bool {
+    unsafe { ::std::ffi::CStr::from_ptr(a) == ::std::ffi::CStr::from_ptr(b) }
+}
+
+fn main() {}
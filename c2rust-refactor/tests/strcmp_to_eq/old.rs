@@ -0,0 +1,38 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+extern "C" {
+    fn strcmp(_: *const libc::c_char, _: *const libc::c_char) -> libc::c_int;
+    fn strncmp(_: *const libc::c_char, _: *const libc::c_char, _: libc::c_ulong) -> libc::c_int;
+    fn strcasecmp(_: *const libc::c_char, _: *const libc::c_char) -> libc::c_int;
+}
+
+unsafe fn literal(x: *const libc::c_char) -> bool {
+    strcmp(x, b"foo\x00" as *const u8 as *const libc::c_char) == 0 as libc::c_int
+}
+
+unsafe fn literal_ptr(x: *const libc::c_char) -> bool {
+    strcmp(x, b"foo\x00".as_ptr() as *const libc::c_char) != 0 as libc::c_int
+}
+
+unsafe fn pointers(a: *const libc::c_char, b: *const libc::c_char) -> bool {
+    strcmp(a, b) == 0 as libc::c_int
+}
+
+unsafe fn prefix(a: *const libc::c_char, b: *const libc::c_char, n: libc::c_int) -> bool {
+    strncmp(a, b, n as libc::c_ulong) == 0 as libc::c_int
+}
+
+unsafe fn case(a: *const libc::c_char, b: *const libc::c_char) -> bool {
+    strcasecmp(a, b) != 0 as libc::c_int
+}
+
+unsafe fn ordering(a: *const libc::c_char, b: *const libc::c_char) -> bool {
+    strcmp(a, b) < 0 as libc::c_int
+}
+
+fn in_block(a: *const libc::c_char, b: *const libc::c_char) -> bool {
+    unsafe { strcmp(a, b) == 0 as libc::c_int }
+}
+
+fn main() {}
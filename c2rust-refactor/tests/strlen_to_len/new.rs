@@ -0,0 +1,28 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+use std::ffi::CStr;
+
+extern "C" {
+    fn strlen(_: *const libc::c_char) -> libc::c_ulong;
+}
+
+unsafe fn known(c: &CStr, s: &str, owned: String) -> libc::c_ulong {
+    (c.to_bytes().len() as libc::c_ulong)
+        .wrapping_add(s.len() as libc::c_ulong)
+        .wrapping_add(owned.len() as libc::c_ulong)
+}
+
+unsafe fn literal() -> libc::c_ulong {
+    5 as libc::c_ulong
+}
+
+unsafe fn pointer(p: *const libc::c_char) -> libc::c_ulong {
+    ::std::ffi::CStr::from_ptr(p).to_bytes().len() as libc::c_ulong
+}
+
+fn in_block(p: *const libc::c_char) -> libc::c_ulong {
+    unsafe { ::std::ffi::CStr::from_ptr(p).to_bytes().len() as libc::c_ulong }
+}
+
+fn main() {}
@@ -0,0 +1,28 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+use std::ffi::CStr;
+
+extern "C" {
+    fn strlen(_: *const libc::c_char) -> libc::c_ulong;
+}
+
+unsafe fn known(c: &CStr, s: &str, owned: String) -> libc::c_ulong {
+    strlen(c.as_ptr())
+        .wrapping_add(strlen(s.as_ptr() as *const libc::c_char))
+        .wrapping_add(strlen(owned.as_ptr() as *const libc::c_char))
+}
+
+unsafe fn literal() -> libc::c_ulong {
+    strlen(b"hello\x00" as *const u8 as *const libc::c_char)
+}
+
+unsafe fn pointer(p: *const libc::c_char) -> libc::c_ulong {
+    strlen(p)
+}
+
+fn in_block(p: *const libc::c_char) -> libc::c_ulong {
+    unsafe { strlen(p) }
+}
+
+fn main() {}
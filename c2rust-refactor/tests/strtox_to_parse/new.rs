@@ -0,0 +1,108 @@
+extern crate libc;
+
+use std::ffi::CStr;
+
+extern "C" {
+    fn atoi(s: *const libc::c_char) -> libc::c_int;
+    fn strtol(
+        s: *const libc::c_char,
+        end: *mut *mut libc::c_char,
+        base: libc::c_int,
+    ) -> libc::c_long;
+}
+
+unsafe fn default_port() -> libc::c_int {
+    "8080".trim_start().parse::<libc::c_int>().unwrap_or(0)
+}
+
+unsafe fn parse_color(s: &CStr) -> libc::c_long {
+    crate::strtol_radix(s.to_bytes(), 16) as libc::c_long
+}
+
+unsafe fn default_mask() -> libc::c_long {
+    crate::strtol_radix(b"0xff", 16) as libc::c_long
+}
+
+unsafe fn parse_prefix(p: *const libc::c_char) -> libc::c_long {
+    let mut end: *mut libc::c_char = 0 as *mut libc::c_char;
+    let n = strtol(p, &mut end, 10);
+    if *end != 0 {
+        return -1;
+    }
+    n
+}
+
+unsafe fn parse_mode(p: *const libc::c_char) -> libc::c_long {
+    crate::strtol_radix(
+        unsafe { ::std::ffi::CStr::from_ptr(p as *const libc::c_char).to_bytes() },
+        0,
+    ) as libc::c_long
+}
+
+fn main() {}
+
+/// Parse the C `long` at the start of `s` the way `strtol` does.  With a base of
+/// 0, the base comes from a `0x` or `0` prefix, and with a base of 16, a `0x`
+/// prefix is skipped.  Parsing stops at the first byte that isn't a digit, and a
+/// value that doesn't fit is clamped and sets `errno` to `ERANGE`.
+fn strtol_radix(s: &[u8], base: u32) -> ::std::os::raw::c_long {
+    use ::std::os::raw::{c_int, c_long};
+    extern "C" {
+        #[cfg_attr(
+            any(target_os = "macos", target_os = "ios", target_os = "freebsd"),
+            link_name = "__error"
+        )]
+        #[cfg_attr(
+            not(any(target_os = "macos", target_os = "ios", target_os = "freebsd")),
+            link_name = "__errno_location"
+        )]
+        fn errno_location() -> *mut c_int;
+    }
+    const ERANGE: c_int = 34;
+
+    let start = s
+        .iter()
+        .position(|c| !c.is_ascii_whitespace())
+        .unwrap_or(s.len());
+    let s = &s[start..];
+    let (neg, s) = match s.first() {
+        Some(b'-') => (true, &s[1..]),
+        Some(b'+') => (false, &s[1..]),
+        _ => (false, s),
+    };
+    let is_hex = (s.starts_with(b"0x") || s.starts_with(b"0X"))
+        && s.get(2).map_or(false, |&c| (c as char).is_digit(16));
+    let (radix, digits) = match base {
+        0 | 16 if is_hex => (16, &s[2..]),
+        0 if s.starts_with(b"0") => (8, s),
+        0 => (10, s),
+        _ => (base, s),
+    };
+    let mut magnitude: u128 = 0;
+    for &c in digits {
+        let d = match (c as char).to_digit(radix) {
+            Some(d) => d,
+            None => break,
+        };
+        magnitude = magnitude
+            .saturating_mul(radix as u128)
+            .saturating_add(d as u128);
+    }
+    let limit = c_long::max_value() as u128 + neg as u128;
+    if magnitude > limit {
+        unsafe {
+            *errno_location() = ERANGE;
+        }
+        return if neg {
+            c_long::min_value()
+        } else {
+            c_long::max_value()
+        };
+    }
+    let x = magnitude as c_long;
+    if neg {
+        x.wrapping_neg()
+    } else {
+        x
+    }
+}
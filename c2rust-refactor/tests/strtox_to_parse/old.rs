@@ -0,0 +1,36 @@
+extern crate libc;
+
+use std::ffi::CStr;
+
+extern "C" {
+    fn atoi(s: *const libc::c_char) -> libc::c_int;
+    fn strtol(s: *const libc::c_char, end: *mut *mut libc::c_char,
+              base: libc::c_int) -> libc::c_long;
+}
+
+unsafe fn default_port() -> libc::c_int {
+    atoi(b"8080\x00" as *const u8 as *const libc::c_char)
+}
+
+unsafe fn parse_color(s: &CStr) -> libc::c_long {
+    strtol(s.as_ptr(), 0 as *mut *mut libc::c_char, 16)
+}
+
+unsafe fn default_mask() -> libc::c_long {
+    strtol(b"0xff\x00" as *const u8 as *const libc::c_char, 0 as *mut *mut libc::c_char, 16)
+}
+
+unsafe fn parse_prefix(p: *const libc::c_char) -> libc::c_long {
+    let mut end: *mut libc::c_char = 0 as *mut libc::c_char;
+    let n = strtol(p, &mut end, 10);
+    if *end != 0 {
+        return -1;
+    }
+    n
+}
+
+unsafe fn parse_mode(p: *const libc::c_char) -> libc::c_long {
+    strtol(p, 0 as *mut *mut libc::c_char, 0)
+}
+
+fn main() {}
@@ -0,0 +1,54 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+#[derive(Copy, Clone)]
+pub enum value_data {
+    int_val(libc::c_int),
+    float_val(libc::c_double),
+}
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct value {
+    pub data: value_data,
+}
+
+pub const KIND_FLOAT: libc::c_int = 1;
+
+unsafe fn make_int(n: libc::c_int) -> value {
+    value { data: value_data::int_val(n) }
+}
+
+unsafe fn set_float(v: &mut value, f: libc::c_double) {
+    v.data = value_data::float_val(f);
+}
+
+unsafe fn to_double(v: &value) -> libc::c_double {
+    if let value_data::int_val(int_val) = v.data {
+        return int_val as libc::c_double;
+    }
+    match v.data {
+        value_data::float_val(float_val) => float_val,
+        _ => 0.0,
+    }
+}
+
+unsafe fn is_int(v: &value) -> bool {
+    matches!(v.data, value_data::int_val(_))
+}
+
+unsafe fn raw_bits(v: &value) -> libc::c_int {
+    v.data.int_val
+}
+
+fn main() {
+    unsafe {
+        let mut v = make_int(3);
+        let a = to_double(&v);
+        let bits = raw_bits(&v);
+        set_float(&mut v, 2.5);
+        let b = to_double(&v);
+        assert!(is_int(&make_int(1)));
+        assert!(a + b > 0.0 && bits == 3);
+    }
+}
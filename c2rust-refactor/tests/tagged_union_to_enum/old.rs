@@ -0,0 +1,57 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub union value_data {
+    pub int_val: libc::c_int,
+    pub float_val: libc::c_double,
+}
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct value {
+    pub kind: libc::c_int,
+    pub data: value_data,
+}
+
+pub const KIND_FLOAT: libc::c_int = 1;
+
+unsafe fn make_int(n: libc::c_int) -> value {
+    value { kind: 0, data: value_data { int_val: n } }
+}
+
+unsafe fn set_float(v: &mut value, f: libc::c_double) {
+    v.kind = KIND_FLOAT;
+    v.data.float_val = f;
+}
+
+unsafe fn to_double(v: &value) -> libc::c_double {
+    if v.kind == 0 {
+        return v.data.int_val as libc::c_double;
+    }
+    match v.kind {
+        KIND_FLOAT => v.data.float_val,
+        _ => 0.0,
+    }
+}
+
+unsafe fn is_int(v: &value) -> bool {
+    v.kind == 0
+}
+
+unsafe fn raw_bits(v: &value) -> libc::c_int {
+    v.data.int_val
+}
+
+fn main() {
+    unsafe {
+        let mut v = make_int(3);
+        let a = to_double(&v);
+        let bits = raw_bits(&v);
+        set_float(&mut v, 2.5);
+        let b = to_double(&v);
+        assert!(is_int(&make_int(1)));
+        assert!(a + b > 0.0 && bits == 3);
+    }
+}
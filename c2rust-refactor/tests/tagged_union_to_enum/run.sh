@@ -0,0 +1,11 @@
+#!/bin/sh
+
+# work around System Integrity Protection on macOS
+if [ `uname` = 'Darwin' ]; then
+    export LD_LIBRARY_PATH=$not_LD_LIBRARY_PATH
+fi
+
+$refactor \
+    select target 'crate; desc(item && name("value"));' \; \
+    tagged_union_to_enum kind variant=0:int_val variant=1:float_val \
+    -- old.rs $rustflags
@@ -0,0 +1,90 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct timeval {
+    pub tv_sec: libc::time_t,
+    pub tv_usec: libc::suseconds_t,
+}
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct timespec {
+    pub tv_sec: libc::time_t,
+    pub tv_nsec: libc::c_long,
+}
+
+pub type clockid_t = libc::c_int;
+
+extern "C" {
+    fn time(__timer: *mut libc::time_t) -> libc::time_t;
+    fn gettimeofday(__tv: *mut timeval, __tz: *mut libc::c_void) -> libc::c_int;
+    fn clock_gettime(__clock_id: clockid_t, __tp: *mut timespec) -> libc::c_int;
+    fn work();
+}
+
+unsafe fn now() -> libc::time_t {
+    return ::std::time::SystemTime::now()
+        .duration_since(::std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as libc::time_t;
+}
+
+unsafe fn stamp(tv: *mut timeval) {
+    {
+        let since_epoch = ::std::time::SystemTime::now()
+            .duration_since(::std::time::UNIX_EPOCH)
+            .unwrap();
+        (*tv).tv_sec = since_epoch.as_secs() as libc::time_t;
+        (*tv).tv_usec = since_epoch.subsec_micros() as libc::suseconds_t;
+    }
+}
+
+unsafe fn wall_clock_nanos() -> libc::c_long {
+    let mut ts: timespec = timespec { tv_sec: 0, tv_nsec: 0 };
+    let rc: libc::c_int = {
+        let since_epoch = ::std::time::SystemTime::now()
+            .duration_since(::std::time::UNIX_EPOCH)
+            .unwrap();
+        ts.tv_sec = since_epoch.as_secs() as libc::time_t;
+        ts.tv_nsec = since_epoch.subsec_nanos() as libc::c_long;
+        0
+    };
+    if rc != 0 as libc::c_int {
+        return -1;
+    }
+    return ts.tv_nsec;
+}
+
+unsafe fn elapsed_ms() -> libc::c_long {
+    let mut start: ::std::time::Instant = ::std::time::Instant::now();
+    let mut end: ::std::time::Instant = ::std::time::Instant::now();
+    start = ::std::time::Instant::now();
+    work();
+    end = ::std::time::Instant::now();
+    return (end.duration_since(start).as_secs() as libc::time_t) * 1000 as libc::c_long
+        + (end.duration_since(start).subsec_nanos() as libc::c_long) / 1000000 as libc::c_long;
+}
+
+// The reading itself is used, so it can't be an `Instant`.
+unsafe fn uptime_secs() -> libc::time_t {
+    let mut ts: timespec = timespec { tv_sec: 0, tv_nsec: 0 };
+    {
+        let since_epoch = ::std::time::SystemTime::now()
+            .duration_since(::std::time::UNIX_EPOCH)
+            .unwrap();
+        ts.tv_sec = since_epoch.as_secs() as libc::time_t;
+        ts.tv_nsec = since_epoch.subsec_nanos() as libc::c_long;
+    }
+    return ts.tv_sec;
+}
+
+// `CLOCK_PROCESS_CPUTIME_ID` has no `std` equivalent.
+unsafe fn cpu_secs() -> libc::time_t {
+    let mut ts: timespec = timespec { tv_sec: 0, tv_nsec: 0 };
+    clock_gettime(2 as libc::c_int, &mut ts);
+    return ts.tv_sec;
+}
+
+fn main() {}
@@ -0,0 +1,68 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct timeval {
+    pub tv_sec: libc::time_t,
+    pub tv_usec: libc::suseconds_t,
+}
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct timespec {
+    pub tv_sec: libc::time_t,
+    pub tv_nsec: libc::c_long,
+}
+
+pub type clockid_t = libc::c_int;
+
+extern "C" {
+    fn time(__timer: *mut libc::time_t) -> libc::time_t;
+    fn gettimeofday(__tv: *mut timeval, __tz: *mut libc::c_void) -> libc::c_int;
+    fn clock_gettime(__clock_id: clockid_t, __tp: *mut timespec) -> libc::c_int;
+    fn work();
+}
+
+unsafe fn now() -> libc::time_t {
+    return time(0 as *mut libc::time_t);
+}
+
+unsafe fn stamp(tv: *mut timeval) {
+    gettimeofday(tv, 0 as *mut libc::c_void);
+}
+
+unsafe fn wall_clock_nanos() -> libc::c_long {
+    let mut ts: timespec = timespec { tv_sec: 0, tv_nsec: 0 };
+    let rc: libc::c_int = clock_gettime(0 as libc::c_int, &mut ts);
+    if rc != 0 as libc::c_int {
+        return -1;
+    }
+    return ts.tv_nsec;
+}
+
+unsafe fn elapsed_ms() -> libc::c_long {
+    let mut start: timespec = timespec { tv_sec: 0, tv_nsec: 0 };
+    let mut end: timespec = timespec { tv_sec: 0, tv_nsec: 0 };
+    clock_gettime(1 as libc::c_int, &mut start);
+    work();
+    clock_gettime(1 as libc::c_int, &mut end);
+    return (end.tv_sec - start.tv_sec) * 1000 as libc::c_long
+        + (end.tv_nsec - start.tv_nsec) / 1000000 as libc::c_long;
+}
+
+// The reading itself is used, so it can't be an `Instant`.
+unsafe fn uptime_secs() -> libc::time_t {
+    let mut ts: timespec = timespec { tv_sec: 0, tv_nsec: 0 };
+    clock_gettime(1 as libc::c_int, &mut ts);
+    return ts.tv_sec;
+}
+
+// `CLOCK_PROCESS_CPUTIME_ID` has no `std` equivalent.
+unsafe fn cpu_secs() -> libc::time_t {
+    let mut ts: timespec = timespec { tv_sec: 0, tv_nsec: 0 };
+    clock_gettime(2 as libc::c_int, &mut ts);
+    return ts.tv_sec;
+}
+
+fn main() {}
@@ -0,0 +1,13 @@
+#!/bin/sh
+
+# work around System Integrity Protection on macOS
+if [ `uname` = 'Darwin' ]; then
+    export LD_LIBRARY_PATH=$not_LD_LIBRARY_PATH
+fi
+
+$refactor \
+    select target 'crate; desc(match_expr(time($t:Expr)) ||
+        match_expr(gettimeofday($tv:Expr, $tz:Expr)) ||
+        match_expr(clock_gettime($clk:Expr, $ts:Expr)));' \; \
+    time_to_std \
+    -- old.rs $rustflags
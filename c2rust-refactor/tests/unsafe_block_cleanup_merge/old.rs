@@ -0,0 +1,39 @@
+static mut TOTAL: i32 = 0;
+
+unsafe fn read(p: *const i32) -> i32 {
+    *p
+}
+
+fn log(x: i32) {
+    println!("{}", x);
+}
+
+fn sum(p: *const i32, n: usize) -> i32 {
+    let mut i = 0;
+    let mut acc = 0;
+    while i < n {
+        unsafe { acc += read(p.add(i)); }
+        i += 1;
+        unsafe { TOTAL += 1; }
+    }
+    unsafe { TOTAL += acc; }
+    log(acc);
+    log(n as i32);
+    unsafe { TOTAL -= 1; }
+    acc
+}
+
+fn first(p: *const i32) -> i32 {
+    unsafe { TOTAL += 1; }
+    unsafe {
+        let x = read(p);
+        TOTAL += x;
+    }
+    unsafe { read(p) }
+}
+
+fn main() {
+    let xs = [1, 2, 3];
+    sum(xs.as_ptr(), 3);
+    first(xs.as_ptr());
+}
@@ -0,0 +1,36 @@
+static mut TOTAL: i32 = 0;
+
+unsafe fn read(p: *const i32) -> i32 {
+    *p
+}
+
+fn count(p: *const i32, n: usize) -> i32 {
+    unsafe {
+        let mut i = 0;
+        let mut acc = 0;
+        let first = *p;
+        while i < n {
+            if *p.add(i) > first {
+                acc += 1;
+            }
+            i += 1;
+        }
+        TOTAL += acc;
+        TOTAL -= 1;
+        let label = format!("{}", acc);
+        println!("{}", label);
+        acc + read(p)
+    }
+}
+
+unsafe fn already_unsafe(p: *const i32) -> i32 {
+    unsafe { *p + 1 }
+}
+
+fn main() {
+    let xs = [1, 2, 3];
+    count(xs.as_ptr(), 3);
+    unsafe {
+        already_unsafe(xs.as_ptr());
+    }
+}
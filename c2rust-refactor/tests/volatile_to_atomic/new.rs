@@ -0,0 +1,46 @@
+extern crate libc;
+
+extern "C" {
+    fn watch(p: *mut libc::c_int);
+}
+
+static COUNTER: ::std::sync::atomic::AtomicU32 = ::std::sync::atomic::AtomicU32::new(0);
+static READY: ::std::sync::atomic::AtomicI32 = ::std::sync::atomic::AtomicI32::new(0);
+static mut SHARED: libc::c_int = 0;
+
+unsafe fn tick() {
+    // volatile_to_atomic: chose `SeqCst` for `COUNTER`; a weaker ordering may be enough
+    COUNTER.fetch_add(1, ::std::sync::atomic::Ordering::SeqCst);
+    // volatile_to_atomic: chose `SeqCst` for `COUNTER`; a weaker ordering may be enough
+    COUNTER.fetch_add(2, ::std::sync::atomic::Ordering::SeqCst);
+    // volatile_to_atomic: chose `SeqCst` for `COUNTER`; a weaker ordering may be enough
+    COUNTER.fetch_add(3, ::std::sync::atomic::Ordering::SeqCst);
+}
+
+unsafe fn signal() {
+    // volatile_to_atomic: chose `SeqCst` for `READY`; a weaker ordering may be enough
+    READY.store(1, ::std::sync::atomic::Ordering::SeqCst);
+}
+
+unsafe fn wait() -> libc::c_uint {
+    // volatile_to_atomic: chose `SeqCst` for `READY`; a weaker ordering may be enough
+    while READY.load(::std::sync::atomic::Ordering::SeqCst) == 0 {}
+    // volatile_to_atomic: chose `SeqCst` for `READY`; a weaker ordering may be enough
+    READY.store(0, ::std::sync::atomic::Ordering::SeqCst);
+    // volatile_to_atomic: chose `SeqCst` for `COUNTER`; a weaker ordering may be enough
+    COUNTER.load(::std::sync::atomic::Ordering::SeqCst)
+}
+
+unsafe fn share() {
+    SHARED = 1;
+    watch(&mut SHARED as *mut libc::c_int);
+}
+
+fn main() {
+    unsafe {
+        tick();
+        signal();
+        wait();
+        share();
+    }
+}
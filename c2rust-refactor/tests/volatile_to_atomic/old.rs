@@ -0,0 +1,41 @@
+extern crate libc;
+
+extern "C" {
+    fn watch(p: *mut libc::c_int);
+}
+
+static mut COUNTER: libc::c_uint = 0;
+static mut READY: libc::c_int = 0;
+static mut SHARED: libc::c_int = 0;
+
+unsafe fn tick() {
+    COUNTER = COUNTER.wrapping_add(1);
+    ::std::ptr::write_volatile(&mut COUNTER as *mut libc::c_uint,
+                               ::std::ptr::read_volatile::<libc::c_uint>(
+                                   &COUNTER as *const libc::c_uint).wrapping_add(2));
+    COUNTER += 3;
+}
+
+unsafe fn signal() {
+    ::std::ptr::write_volatile(&mut READY as *mut libc::c_int, 1);
+}
+
+unsafe fn wait() -> libc::c_uint {
+    while ::std::ptr::read_volatile::<libc::c_int>(&READY as *const libc::c_int) == 0 {}
+    READY = 0;
+    COUNTER
+}
+
+unsafe fn share() {
+    SHARED = 1;
+    watch(&mut SHARED as *mut libc::c_int);
+}
+
+fn main() {
+    unsafe {
+        tick();
+        signal();
+        wait();
+        share();
+    }
+}
@@ -0,0 +1,53 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+#[derive(Default)]
+pub struct point {
+    pub x: libc::c_int,
+    pub y: libc::c_double,
+    pub tag: [libc::c_char; 8],
+}
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct list {
+    pub head: *mut point,
+    pub origin: point,
+    pub len: libc::size_t,
+    pub name: [libc::c_char; 64],
+}
+impl Default for list {
+    fn default() -> list {
+        list {
+            head: ::std::ptr::null_mut(),
+            origin: Default::default(),
+            len: 0,
+            name: [0; 64],
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct handler {
+    pub id: libc::c_int,
+    pub callback: unsafe extern "C" fn(libc::c_int) -> libc::c_int,
+}
+
+unsafe extern "C" fn twice(x: libc::c_int) -> libc::c_int {
+    x * 2
+}
+
+fn main() {
+    let mut p: point = point::default();
+    p.x = 1;
+    unsafe {
+        let mut l: list = list::default();
+        l.head = &mut p;
+        let mut h: handler = ::std::mem::zeroed();
+        h.callback = twice;
+        (h.callback)(l.len as libc::c_int);
+    }
+}
@@ -0,0 +1,42 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct point {
+    pub x: libc::c_int,
+    pub y: libc::c_double,
+    pub tag: [libc::c_char; 8],
+}
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct list {
+    pub head: *mut point,
+    pub origin: point,
+    pub len: libc::size_t,
+    pub name: [libc::c_char; 64],
+}
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct handler {
+    pub id: libc::c_int,
+    pub callback: unsafe extern "C" fn(libc::c_int) -> libc::c_int,
+}
+
+unsafe extern "C" fn twice(x: libc::c_int) -> libc::c_int {
+    x * 2
+}
+
+fn main() {
+    let mut p: point = unsafe { ::std::mem::zeroed() };
+    p.x = 1;
+    unsafe {
+        let mut l: list = ::std::mem::MaybeUninit::zeroed().assume_init();
+        l.head = &mut p;
+        let mut h: handler = ::std::mem::zeroed();
+        h.callback = twice;
+        (h.callback)(l.len as libc::c_int);
+    }
+}
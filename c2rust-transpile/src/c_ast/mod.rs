@@ -118,6 +118,24 @@ impl Display for DisplaySrcSpan {
     }
 }
 
+impl DisplaySrcSpan {
+    pub fn new(file: Option<PathBuf>, loc: SrcSpan) -> Self {
+        Self { file, loc }
+    }
+
+    pub fn file(&self) -> Option<&Path> {
+        self.file.as_deref()
+    }
+
+    pub fn line(&self) -> u64 {
+        self.loc.begin_line
+    }
+
+    pub fn column(&self) -> u64 {
+        self.loc.begin_column
+    }
+}
+
 pub type FileId = usize;
 
 /// Represents some AST node possibly with source location information bundled with it
@@ -191,9 +209,11 @@ impl TypedAstContext {
     }
 
     pub fn display_loc(&self, loc: &Option<SrcSpan>) -> Option<DisplaySrcSpan> {
-        loc.as_ref().map(|loc| DisplaySrcSpan {
-            file: self.files[self.file_map[loc.fileid as usize]].path.clone(),
-            loc: *loc,
+        loc.as_ref().map(|loc| {
+            DisplaySrcSpan::new(
+                self.files[self.file_map[loc.fileid as usize]].path.clone(),
+                *loc,
+            )
         })
     }
 
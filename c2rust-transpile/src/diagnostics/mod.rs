@@ -0,0 +1,1221 @@
+mod sarif;
+
+use colored::Colorize;
+use failure::{err_msg, Backtrace, Context, Error, Fail};
+use fern::colors::ColoredLevelConfig;
+use log::{Level, LevelFilter, Log, Metadata, Record, SetLoggerError};
+use std::cell::RefCell;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fmt::{self, Display};
+use std::fs::{File, OpenOptions};
+use std::hash::{Hash, Hasher};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use strum::IntoEnumIterator;
+use strum_macros::{Display, EnumIter, EnumString};
+
+use self::sarif::{SarifLog, SarifResult};
+use crate::c_ast::{ClangAstParseErrorKind, DisplaySrcSpan};
+use crate::TranspilerConfig;
+use c2rust_ast_exporter::get_clang_major_version;
+
+const DEFAULT_WARNINGS: &[Diagnostic] = &[
+    Diagnostic::ClangAst,
+    Diagnostic::InlineAsm,
+    Diagnostic::UnsupportedBuiltins,
+    Diagnostic::Other,
+];
+
+#[derive(PartialEq, Eq, Hash, Debug, Display, EnumString, EnumIter, Clone)]
+#[strum(serialize_all = "kebab_case")]
+pub enum Diagnostic {
+    /// Every category below
+    All,
+    Comments,
+    ClangAst,
+    /// Casts that are lossy or whose kind had to be guessed. These are common in
+    /// pointer-heavy code, so they're only reported with `--warn casts`.
+    Casts,
+    /// Unions that could not be translated as Rust unions
+    Unions,
+    /// Variadic functions, which require the nightly `c_variadic` feature
+    Varargs,
+    /// Builtins that are approximated or not supported at all
+    UnsupportedBuiltins,
+    /// Static initializers that are moved to run at program startup
+    StaticInitializers,
+    /// Macros that could not be translated
+    Macros,
+    /// Inline assembly constraints and clobbers that could not be translated faithfully
+    InlineAsm,
+    /// Records whose target doesn't name a category, e.g. ones logged by dependencies
+    Other,
+}
+
+impl Diagnostic {
+    /// A stable short code identifying this category, e.g. `C2R-0003`.
+    pub fn code(&self) -> &'static str {
+        use Diagnostic::*;
+        match self {
+            All => "C2R-0000",
+            Comments => "C2R-0001",
+            ClangAst => "C2R-0002",
+            Casts => "C2R-0003",
+            Unions => "C2R-0004",
+            Varargs => "C2R-0005",
+            UnsupportedBuiltins => "C2R-0006",
+            StaticInitializers => "C2R-0007",
+            Macros => "C2R-0008",
+            InlineAsm => "C2R-0009",
+            Other => "C2R-0010",
+        }
+    }
+
+    /// A longer description of this category for `--explain`.
+    pub fn explanation(&self) -> &'static str {
+        use Diagnostic::*;
+        match self {
+            All => {
+                "`all` is not a warning itself; it stands for every warning category \
+                and can be passed to -W/--warn, --no-warn and --fail-on."
+            }
+            Comments => {
+                "A C comment could not be attached to the translated item it belongs to, \
+                so it was moved or dropped. This usually happens with comments inside \
+                macros or between tokens of a single expression. Check the translated \
+                code near the reported location and move the comment by hand."
+            }
+            ClangAst => {
+                "The AST exported from clang was missing nodes, children or types the \
+                translator expected. This usually means the exporter does not support a \
+                C construct or extension used by the source. Translation of the affected \
+                declarations may fail; consider reporting the construct upstream."
+            }
+            Casts => {
+                "A cast was translated that may lose information or whose kind clang did \
+                not report, such as pointer/integer conversions or an unknown cast \
+                defaulted to a bitcast. Check that the translated `as` casts preserve \
+                the intended semantics. Afterwards, `c2rust-refactor remove_redundant_casts` \
+                and `convert_cast_as_ptr` can tidy up casts that turned out to be lossless."
+            }
+            Unions => {
+                "A C union could not be translated to an equivalent Rust union, e.g. an \
+                empty GNU union which became an empty struct. Code reading through the \
+                union should be reviewed; `c2rust-refactor ownership_split_variants` and \
+                manual conversion to an enum are typical follow-ups."
+            }
+            Varargs => {
+                "A variadic function was defined, which requires the nightly-only \
+                `c_variadic` feature in the translated crate. Consider replacing the \
+                function with a non-variadic API, e.g. taking a slice of arguments; \
+                `c2rust-refactor convert_printfs` handles printf-style calls."
+            }
+            UnsupportedBuiltins => {
+                "A compiler builtin is not supported or is only approximated, e.g. \
+                __builtin_object_size always reporting an unknown size. Unsupported \
+                builtins make translation of the enclosing function fail; approximated \
+                ones should be reviewed by hand."
+            }
+            StaticInitializers => {
+                "A static's initializer cannot be evaluated at compile time in Rust, so \
+                the static is zero-initialized and assigned in `run_static_initializers`, \
+                which runs at program startup. Code that reads the static before then, \
+                e.g. from other initializers, sees the zero value. `c2rust-refactor \
+                static_to_local` or `static_collect_to_struct` can help restructure it."
+            }
+            Macros => {
+                "A C macro could not be translated to a Rust const, so its uses were \
+                expanded in place. Check whether the macro should be turned into a const \
+                or function by hand; `c2rust-refactor rewrite_expr` can replace the \
+                expanded expressions afterwards."
+            }
+            InlineAsm => {
+                "An inline assembly constraint or clobber has no faithful equivalent in \
+                Rust's `asm!`, so it was dropped or left as is. The translated assembly \
+                may fail to compile or miscompile; correct it by hand."
+            }
+            Other => {
+                "A warning without a specific category, such as skipped input files or \
+                records logged by dependencies. Disable these with `--no-warn other`."
+            }
+        }
+    }
+
+    /// The category with the given stable code.
+    pub fn from_code(code: &str) -> Option<Diagnostic> {
+        Diagnostic::iter().find(|d| d.code().eq_ignore_ascii_case(code))
+    }
+
+    /// The codes of all categories.
+    pub fn codes() -> Vec<&'static str> {
+        Diagnostic::iter().map(|d| d.code()).collect()
+    }
+
+    /// The `-W` names of all categories.
+    pub fn names() -> Vec<String> {
+        Diagnostic::iter().map(|d| d.to_string()).collect()
+    }
+
+    /// The category of records logged with `target`.
+    fn of_target(target: &str) -> Diagnostic {
+        Diagnostic::from_str(target).unwrap_or(Diagnostic::Other)
+    }
+
+    /// The concrete categories `self` names, expanding `All` to every category.
+    fn categories(&self) -> Vec<Diagnostic> {
+        match self {
+            Diagnostic::All => Diagnostic::iter().filter(|d| *d != Diagnostic::All).collect(),
+            _ => vec![self.clone()],
+        }
+    }
+}
+
+macro_rules! diag {
+    ($type:path, $($arg:tt)*) => (log::warn!(target: &$type.to_string(), $($arg)*))
+}
+
+pub(crate) use diag;
+
+/// Like [`diag`], but attaches the C source location `$loc` (an
+/// `Option<DisplaySrcSpan>`) to the emitted record.
+macro_rules! diag_at {
+    ($type:path, $loc:expr, $($arg:tt)*) => (
+        $crate::diagnostics::with_loc($loc, || $crate::diagnostics::diag!($type, $($arg)*))
+    )
+}
+
+pub(crate) use diag_at;
+
+thread_local! {
+    /// Source location of the record currently being logged by [`diag_at`].
+    static CURRENT_LOC: RefCell<Option<DisplaySrcSpan>> = RefCell::new(None);
+}
+
+pub(crate) fn with_loc(loc: Option<DisplaySrcSpan>, log: impl FnOnce()) {
+    CURRENT_LOC.with(|cur| *cur.borrow_mut() = loc);
+    log();
+    CURRENT_LOC.with(|cur| *cur.borrow_mut() = None);
+}
+
+/// The location attached by [`diag_at`], if it names a file.
+fn current_loc() -> Option<DisplaySrcSpan> {
+    CURRENT_LOC.with(|cur| cur.borrow().clone().filter(|loc| loc.file().is_some()))
+}
+
+/// State shared between the installed logger and the transpiler driver.
+struct DiagnosticsState {
+    per_category: Mutex<HashMap<Diagnostic, usize>>,
+    fail_on: HashSet<Diagnostic>,
+    /// Where the JSON form of the summary goes, if JSON diagnostics are enabled.
+    json_summary: Mutex<Option<Box<dyn Write + Send>>>,
+    stderr_level: LevelFilter,
+    /// Warnings seen so far, if deduplication is enabled.
+    seen: Option<Mutex<SeenWarnings>>,
+    /// Per-category counts of the repeated warnings that were dropped.
+    duplicates: Mutex<HashMap<Diagnostic, usize>>,
+    /// Number of error records logged.
+    errors: Mutex<usize>,
+    /// Warnings collected for the SARIF log and the file it's written to.
+    sarif: Mutex<Option<(File, Vec<SarifResult>)>>,
+}
+
+static STATE: Mutex<Option<Arc<DiagnosticsState>>> = Mutex::new(None);
+
+/// Maximum number of distinct warnings remembered for deduplication. Once
+/// full, further warnings are passed through unchecked.
+const MAX_SEEN_WARNINGS: usize = 100_000;
+
+/// Hashes of the (category, message, location) of warnings already emitted.
+struct SeenWarnings {
+    hashes: HashSet<u64>,
+    capacity: usize,
+}
+
+impl SeenWarnings {
+    fn new(capacity: usize) -> Self {
+        Self {
+            hashes: HashSet::new(),
+            capacity,
+        }
+    }
+
+    /// Returns false if `hash` was already seen.
+    fn insert(&mut self, hash: u64) -> bool {
+        if self.hashes.contains(&hash) {
+            return false;
+        }
+        if self.hashes.len() < self.capacity {
+            self.hashes.insert(hash);
+        }
+        true
+    }
+}
+
+/// Wraps the fern logger to drop exact repeats of enabled warnings before
+/// they're printed or counted.
+struct DedupLogger {
+    inner: Box<dyn Log>,
+    state: Arc<DiagnosticsState>,
+}
+
+impl Log for DedupLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if let Some(seen) = &self.state.seen {
+            if record.level() == Level::Warn && self.inner.enabled(record.metadata()) {
+                let mut hasher = DefaultHasher::new();
+                record.target().hash(&mut hasher);
+                record.args().to_string().hash(&mut hasher);
+                current_loc().map(|loc| loc.to_string()).hash(&mut hasher);
+                if !seen.lock().unwrap().insert(hasher.finish()) {
+                    let diag = Diagnostic::of_target(record.target());
+                    *self.state.duplicates.lock().unwrap().entry(diag).or_default() += 1;
+                    return;
+                }
+            }
+        }
+        self.inner.log(record)
+    }
+
+    fn flush(&self) {
+        self.inner.flush()
+    }
+}
+
+/// How diagnostics are rendered.
+#[derive(PartialEq, Eq, Debug, Display, EnumString, Clone, Copy)]
+#[strum(serialize_all = "kebab_case")]
+pub enum DiagnosticsFormat {
+    /// Colored, human-readable text
+    Human,
+    /// One JSON object per line
+    Json,
+    /// A SARIF 2.1.0 log written once the run is over; requires a diagnostics file
+    Sarif,
+}
+
+/// When to color diagnostics printed to stderr.
+#[derive(PartialEq, Eq, Debug, Display, EnumString, Clone, Copy)]
+#[strum(serialize_all = "kebab_case")]
+pub enum ColorChoice {
+    Always,
+    Never,
+    /// Color only if stderr is a terminal and `NO_COLOR` is not set
+    Auto,
+}
+
+fn use_color(choice: ColorChoice, is_tty: bool, no_color: bool) -> bool {
+    match choice {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        ColorChoice::Auto => is_tty && !no_color,
+    }
+}
+
+/// Source files whose warnings are dropped, matched both as absolute paths and
+/// relative to the directory containing `compile_commands.json`.
+struct Suppressions {
+    patterns: Vec<glob::Pattern>,
+    base_dir: PathBuf,
+}
+
+impl Suppressions {
+    fn matches(&self, file: &Path) -> bool {
+        let abs = self.base_dir.join(file);
+        let rel = abs.strip_prefix(&self.base_dir).ok();
+        self.patterns.iter().any(|pattern| {
+            pattern.matches_path(&abs) || rel.map_or(false, |rel| pattern.matches_path(rel))
+        })
+    }
+}
+
+pub fn init(tcfg: &TranspilerConfig, cc_db: &Path) {
+    let enabled_warnings = resolve_enabled(
+        &tcfg.enabled_warnings,
+        &tcfg.disabled_warnings,
+        &tcfg.fail_on_warnings,
+    );
+
+    let suppressions = Suppressions {
+        patterns: tcfg.suppress_warnings_in.clone(),
+        base_dir: cc_db.parent().map(Path::to_path_buf).unwrap_or_default(),
+    };
+
+    // When diagnostics go to a file, that file gets the requested format and
+    // stderr stays human-readable.
+    let stderr_format = match (&tcfg.diagnostics_file, tcfg.diagnostics_format) {
+        (None, DiagnosticsFormat::Json) => DiagnosticsFormat::Json,
+        _ => DiagnosticsFormat::Human,
+    };
+    let color = use_color(
+        tcfg.color,
+        atty::is(atty::Stream::Stderr),
+        env::var_os("NO_COLOR").map_or(false, |v| !v.is_empty()),
+    );
+    // Messages built with `colored` (e.g. `TranslationError`s) follow the same choice.
+    colored::control::set_override(color);
+
+    // `-v`/`--quiet` only affect stderr; files always log at `--log-level`.
+    let stderr_level = stderr_level(tcfg.log_level, tcfg.verbosity, tcfg.quiet);
+    // Warnings and errors are always let through so the totals are accurate.
+    let mut max_level = stderr_level.max(LevelFilter::Warn);
+
+    let mut json_summary: Option<Box<dyn Write + Send>> = None;
+    if stderr_format == DiagnosticsFormat::Json {
+        json_summary = Some(Box::new(io::stderr()));
+    }
+    let mut dispatch = fern::Dispatch::new()
+        .filter(move |metadata| {
+            // Only warnings are subject to the category filter, so verbose runs
+            // still show every info/debug record.
+            if metadata.level() != Level::Warn {
+                return true;
+            }
+            is_enabled(&enabled_warnings, metadata.target())
+                && !current_loc()
+                    .and_then(|loc| loc.file().map(|file| suppressions.matches(file)))
+                    .unwrap_or(false)
+        })
+        .chain(
+            format_dispatch(stderr_format, color)
+                .level(stderr_level)
+                .chain(io::stderr()),
+        );
+    let mut sarif = None;
+    if let Some(path) = &tcfg.diagnostics_file {
+        // `c2rust-transpile` checks that the file can be created before we get
+        // here, so this only fails for other callers.
+        match File::create(path) {
+            // SARIF results are collected as warnings are counted and
+            // written out by `print_summary`.
+            Ok(file) if tcfg.diagnostics_format == DiagnosticsFormat::Sarif => {
+                sarif = Some((file, Vec::new()))
+            }
+            Ok(file) => {
+                if tcfg.diagnostics_format == DiagnosticsFormat::Json {
+                    json_summary = file
+                        .try_clone()
+                        .ok()
+                        .map(|file| Box::new(file) as Box<dyn Write + Send>);
+                }
+                dispatch = dispatch.chain(
+                    format_dispatch(tcfg.diagnostics_format, false)
+                        .level(tcfg.log_level)
+                        .chain(file),
+                );
+                max_level = max_level.max(tcfg.log_level);
+            }
+            Err(e) => eprintln!(
+                "error: could not create diagnostics file {}: {}",
+                path.display(),
+                e
+            ),
+        }
+    }
+    if let Some(path) = &tcfg.log_file {
+        // fern flushes after every record, so the log stays usable even if
+        // we crash partway through.
+        match open_log_file(path, tcfg.log_file_append) {
+            Ok(file) => {
+                let plain = format_dispatch(DiagnosticsFormat::Human, false);
+                dispatch = dispatch.chain(plain.level(tcfg.log_level).chain(file));
+                max_level = max_level.max(tcfg.log_level);
+            }
+            Err(e) => eprintln!(
+                "warning: could not open log file {}: {}",
+                path.display(),
+                e
+            ),
+        }
+    }
+
+    let state = Arc::new(DiagnosticsState {
+        per_category: Mutex::new(HashMap::new()),
+        fail_on: tcfg.fail_on_warnings.clone(),
+        json_summary: Mutex::new(json_summary),
+        stderr_level,
+        seen: tcfg
+            .deduplicate_warnings
+            .then(|| Mutex::new(SeenWarnings::new(MAX_SEEN_WARNINGS))),
+        duplicates: Mutex::new(HashMap::new()),
+        errors: Mutex::new(0),
+        sarif: Mutex::new(sarif),
+    });
+    *STATE.lock().unwrap() = Some(state.clone());
+
+    let counters = state.clone();
+    let (max_level, logger) = dispatch
+        .level(max_level)
+        .chain(fern::Output::call(move |record| {
+            match record.level() {
+                Level::Warn => {
+                    let diag = Diagnostic::of_target(record.target());
+                    if let Some((_, results)) = &mut *counters.sarif.lock().unwrap() {
+                        let message = strip_ansi(&record.args().to_string());
+                        results.push(SarifResult::new(&diag, message, current_loc().as_ref()));
+                    }
+                    *counters.per_category.lock().unwrap().entry(diag).or_default() += 1;
+                }
+                Level::Error => *counters.errors.lock().unwrap() += 1,
+                _ => {}
+            }
+        }))
+        .into_log();
+    // Ignore the [`SetLoggerError`] b/c we just want to make sure it's set at least once.
+    let _: Result<(), SetLoggerError> = log_reroute::init();
+    log_reroute::reroute_boxed(Box::new(DedupLogger {
+        inner: logger,
+        state,
+    }));
+    log::set_max_level(max_level);
+}
+
+/// The level of records shown on stderr: `--quiet` shows only errors and each
+/// `-v` raises `base` by one level, up to `trace`.
+fn stderr_level(base: LevelFilter, verbosity: u64, quiet: bool) -> LevelFilter {
+    if quiet {
+        return LevelFilter::Error;
+    }
+    const LEVELS: [LevelFilter; 6] = [
+        LevelFilter::Off,
+        LevelFilter::Error,
+        LevelFilter::Warn,
+        LevelFilter::Info,
+        LevelFilter::Debug,
+        LevelFilter::Trace,
+    ];
+    let base = LEVELS.iter().position(|&level| level == base).unwrap();
+    let raised = base.saturating_add(verbosity as usize);
+    LEVELS[raised.min(LEVELS.len() - 1)]
+}
+
+fn format_dispatch(format: DiagnosticsFormat, color: bool) -> fern::Dispatch {
+    match format {
+        DiagnosticsFormat::Human => fern::Dispatch::new().format(move |out, message, record| {
+            out.finish(format_args!(
+                "{}",
+                human_line(
+                    record.level(),
+                    record.target(),
+                    &message.to_string(),
+                    current_loc().as_ref(),
+                    color,
+                )
+            ))
+        }),
+        DiagnosticsFormat::Json => fern::Dispatch::new().format(|out, message, record| {
+            out.finish(format_args!(
+                "{}",
+                json_record(
+                    record.level(),
+                    record.target(),
+                    &message.to_string(),
+                    current_loc().as_ref(),
+                )
+            ))
+        }),
+        DiagnosticsFormat::Sarif => unreachable!("SARIF results are not formatted per record"),
+    }
+}
+
+fn human_line(
+    level: Level,
+    target: &str,
+    message: &str,
+    loc: Option<&DisplaySrcSpan>,
+    color: bool,
+) -> String {
+    let loc = loc.map(|loc| format!("{}: ", loc)).unwrap_or_default();
+    let warn_flag = Diagnostic::from_str(target)
+        .map(|d| format!(" [-W{}] [{}]", target, d.code()))
+        .unwrap_or_default();
+    if color {
+        format!(
+            "{}\x1B[{}m{}:\x1B[0m {}{}",
+            loc,
+            ColoredLevelConfig::new().get_color(&level).to_fg_str(),
+            level_label(level),
+            message,
+            warn_flag,
+        )
+    } else {
+        format!(
+            "{}{}: {}{}",
+            loc,
+            level_label(level),
+            strip_ansi(message),
+            warn_flag,
+        )
+    }
+}
+
+fn open_log_file(path: &Path, append: bool) -> io::Result<File> {
+    OpenOptions::new()
+        .write(true)
+        .create(true)
+        .append(append)
+        .truncate(!append)
+        .open(path)
+}
+
+fn level_label(level: Level) -> &'static str {
+    match level {
+        Level::Error => "error",
+        Level::Warn => "warning",
+        Level::Info => "info",
+        Level::Debug => "debug",
+        Level::Trace => "trace",
+    }
+}
+
+fn json_record(
+    level: Level,
+    target: &str,
+    message: &str,
+    loc: Option<&DisplaySrcSpan>,
+) -> serde_json::Value {
+    let diag = Diagnostic::from_str(target).ok();
+    serde_json::json!({
+        "level": level_label(level),
+        "category": diag.as_ref().map(|d| d.to_string()),
+        "code": diag.as_ref().map(|d| d.code()),
+        "message": strip_ansi(message),
+        "file": loc.and_then(|loc| loc.file()).map(|file| file.display().to_string()),
+        "line": loc.map(|loc| loc.line()),
+    })
+}
+
+/// Removes the ANSI color escapes `colored` embeds in some messages.
+fn strip_ansi(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1B' {
+            // Skip the CSI sequence up to and including its final byte.
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+/// Prints the number of warnings emitted in each category over a run that
+/// transpiled `num_files` files.
+pub fn print_summary(num_files: usize) {
+    let state = match &*STATE.lock().unwrap() {
+        Some(state) => state.clone(),
+        None => return,
+    };
+    let counts = sorted_counts(&state.per_category.lock().unwrap());
+    let duplicates = sorted_counts(&state.duplicates.lock().unwrap());
+    let total: usize = counts.iter().map(|(_, count)| count).sum();
+
+    if let Some(out) = &mut *state.json_summary.lock().unwrap() {
+        let _ = writeln!(out, "{}", json_summary(&counts, &duplicates, num_files));
+    }
+    if let Some((file, results)) = state.sarif.lock().unwrap().take() {
+        if let Err(e) = serde_json::to_writer_pretty(file, &SarifLog::new(results)) {
+            eprintln!("error: could not write SARIF log: {}", e);
+        }
+    }
+    if state.stderr_level < LevelFilter::Warn {
+        return;
+    }
+    if total > 0 {
+        eprintln!("{}", summary_line(&counts, num_files));
+    }
+    for (diag, count) in &duplicates {
+        eprintln!("{}: {} duplicate warnings suppressed", diag, count);
+    }
+}
+
+/// Per-category counts, most frequent first.
+fn sorted_counts(counts: &HashMap<Diagnostic, usize>) -> Vec<(Diagnostic, usize)> {
+    let mut counts: Vec<_> = counts
+        .iter()
+        .filter(|&(_, &count)| count > 0)
+        .map(|(diag, &count)| (diag.clone(), count))
+        .collect();
+    counts.sort_by(|(d1, c1), (d2, c2)| {
+        c2.cmp(c1)
+            .then_with(|| d1.to_string().cmp(&d2.to_string()))
+    });
+    counts
+}
+
+fn summary_line(counts: &[(Diagnostic, usize)], num_files: usize) -> String {
+    let total: usize = counts.iter().map(|(_, count)| count).sum();
+    let per_category = counts
+        .iter()
+        .map(|(diag, count)| format!("{}: {}", diag, count))
+        .collect::<Vec<_>>()
+        .join(", ");
+    format!(
+        "{} (total {} warnings across {} files)",
+        per_category, total, num_files
+    )
+}
+
+fn json_summary(
+    counts: &[(Diagnostic, usize)],
+    duplicates: &[(Diagnostic, usize)],
+    num_files: usize,
+) -> serde_json::Value {
+    let to_map = |counts: &[(Diagnostic, usize)]| -> serde_json::Map<String, serde_json::Value> {
+        counts
+            .iter()
+            .map(|(diag, count)| (diag.to_string(), (*count).into()))
+            .collect()
+    };
+    let total: usize = counts.iter().map(|(_, count)| count).sum();
+    serde_json::json!({
+        "summary": to_map(counts),
+        "duplicates": to_map(duplicates),
+        "total": total,
+        "files": num_files,
+    })
+}
+
+/// Overall counts for a run of the transpiler.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Totals {
+    pub files: usize,
+    pub warnings: usize,
+    pub errors: usize,
+}
+
+impl Totals {
+    /// The process exit status for a run with these totals: 1 if there were
+    /// errors, 2 if there were warnings and `warnings_affect_exit` is set, and
+    /// 0 otherwise.
+    pub fn exit_code(&self, warnings_affect_exit: bool) -> i32 {
+        if self.errors > 0 {
+            1
+        } else if warnings_affect_exit && self.warnings > 0 {
+            2
+        } else {
+            0
+        }
+    }
+}
+
+/// The machine-readable footer line, e.g. `c2rust: files=83 warnings=449 errors=0`.
+impl Display for Totals {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "c2rust: files={} warnings={} errors={}",
+            self.files, self.warnings, self.errors
+        )
+    }
+}
+
+/// Checks whether any warnings were emitted in the categories listed in
+/// `fail_on_warnings`, returning the run's totals if not.
+pub fn check_failures(num_files: usize) -> Result<Totals, FailSummary> {
+    let state = match &*STATE.lock().unwrap() {
+        Some(state) => state.clone(),
+        None => {
+            return Ok(Totals {
+                files: num_files,
+                ..Totals::default()
+            })
+        }
+    };
+    let counts = state.per_category.lock().unwrap();
+    let totals = Totals {
+        files: num_files,
+        warnings: counts.values().sum(),
+        errors: *state.errors.lock().unwrap(),
+    };
+    failures(&counts, &state.fail_on, totals)
+}
+
+fn failures(
+    counts: &HashMap<Diagnostic, usize>,
+    fail_on: &HashSet<Diagnostic>,
+    totals: Totals,
+) -> Result<Totals, FailSummary> {
+    let mut failed: Vec<(Diagnostic, usize)> = counts
+        .iter()
+        .filter(|&(diag, &count)| {
+            count > 0 && (fail_on.contains(&Diagnostic::All) || fail_on.contains(diag))
+        })
+        .map(|(diag, &count)| (diag.clone(), count))
+        .collect();
+    if failed.is_empty() {
+        return Ok(totals);
+    }
+    failed.sort_by_key(|(diag, _)| diag.to_string());
+    Err(FailSummary {
+        counts: failed,
+        totals,
+    })
+}
+
+/// Warnings emitted in categories the transpiler was asked to fail on.
+#[derive(Debug, Clone)]
+pub struct FailSummary {
+    counts: Vec<(Diagnostic, usize)>,
+    totals: Totals,
+}
+
+impl FailSummary {
+    /// Per-category counts of the warnings that caused the failure.
+    pub fn counts(&self) -> &[(Diagnostic, usize)] {
+        &self.counts
+    }
+
+    /// Overall counts for the failed run.
+    pub fn totals(&self) -> &Totals {
+        &self.totals
+    }
+}
+
+impl Display for FailSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "translation emitted warnings in failing categories: ")?;
+        for (i, (diag, count)) in self.counts.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}: {}", diag, count)?;
+        }
+        Ok(())
+    }
+}
+
+impl Fail for FailSummary {}
+
+/// The set of concrete categories to emit: the defaults plus those enabled
+/// with `--warn`, minus those disabled with `--no-warn`. Categories we're asked
+/// to fail on are always emitted, since they have to be counted.
+fn resolve_enabled(
+    enabled: &HashSet<Diagnostic>,
+    disabled: &HashSet<Diagnostic>,
+    fail_on: &HashSet<Diagnostic>,
+) -> HashSet<Diagnostic> {
+    let mut resolved: HashSet<Diagnostic> = DEFAULT_WARNINGS
+        .iter()
+        .chain(enabled)
+        .flat_map(Diagnostic::categories)
+        .collect();
+    for diag in disabled.iter().flat_map(Diagnostic::categories) {
+        resolved.remove(&diag);
+    }
+    resolved.extend(fail_on.iter().flat_map(Diagnostic::categories));
+    resolved
+}
+
+/// Whether records logged with `target` pass the warning filter. Targets that
+/// don't name a [`Diagnostic`] fall into [`Diagnostic::Other`].
+fn is_enabled(enabled_warnings: &HashSet<Diagnostic>, target: &str) -> bool {
+    enabled_warnings.contains(&Diagnostic::of_target(target))
+}
+
+#[derive(Debug, Clone)]
+pub struct TranslationError {
+    loc: Vec<DisplaySrcSpan>,
+    inner: Arc<Context<TranslationErrorKind>>,
+}
+
+pub type TranslationResult<T> = Result<T, TranslationError>;
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum TranslationErrorKind {
+    Generic,
+
+    // Not enough simd intrinsics are available in LLVM < 7
+    OldLLVMSimd,
+
+    // We are waiting for va_copy support to land in rustc
+    VaCopyNotImplemented,
+
+    // Clang AST exported by AST-exporter was not valid
+    InvalidClangAst(ClangAstParseErrorKind),
+}
+
+/// Constructs a `TranslationError` using the standard string interpolation syntax.
+#[macro_export]
+macro_rules! format_translation_err {
+    ($loc:expr, $($arg:tt)*) => {
+        TranslationError::new(
+            $loc,
+            failure::err_msg(format!($($arg)*))
+                .context(TranslationErrorKind::Generic),
+        )
+    }
+}
+
+impl Display for TranslationErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::TranslationErrorKind::*;
+        match self {
+            Generic => {}
+
+            OldLLVMSimd => {
+                if let Some(version) = get_clang_major_version() {
+                    if version < 7 {
+                        return write!(f, "SIMD intrinsics require LLVM 7 or newer. Please build C2Rust against a newer LLVM version.");
+                    }
+                }
+            }
+
+            VaCopyNotImplemented => {
+                return write!(f, "Rust does not yet support a C-compatible va_copy which is required to translate this function. See https://github.com/rust-lang/rust/pull/59625");
+            }
+
+            InvalidClangAst(_) => {
+                return write!(f, "Exported Clang AST was invalid. Check warnings above for unimplemented features.");
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Fail for TranslationError {
+    fn cause(&self) -> Option<&dyn Fail> {
+        self.inner.cause()
+    }
+
+    fn backtrace(&self) -> Option<&Backtrace> {
+        self.inner.backtrace()
+    }
+}
+
+impl Display for TranslationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(cause) = self.cause() {
+            writeln!(f, "{}", cause)?;
+        }
+        match self.inner.get_context() {
+            TranslationErrorKind::Generic => {}
+            ref kind => writeln!(f, "{}", kind)?,
+        }
+        for loc in &self.loc {
+            writeln!(f, "{} {}", "-->".blue(), loc)?;
+        }
+        Ok(())
+    }
+}
+
+impl TranslationError {
+    pub fn kind(&self) -> TranslationErrorKind {
+        self.inner.get_context().clone()
+    }
+
+    pub fn new(loc: Option<DisplaySrcSpan>, inner: Context<TranslationErrorKind>) -> Self {
+        Self::from(inner).add_loc(loc)
+    }
+
+    pub fn generic(msg: &'static str) -> Self {
+        msg.into()
+    }
+
+    pub fn add_loc(mut self, loc: Option<DisplaySrcSpan>) -> Self {
+        if let Some(loc) = loc {
+            self.loc.push(loc);
+        }
+        self
+    }
+}
+
+impl From<&'static str> for TranslationError {
+    fn from(msg: &'static str) -> Self {
+        err_msg(msg).context(TranslationErrorKind::Generic).into()
+    }
+}
+
+impl From<Error> for TranslationError {
+    fn from(e: Error) -> Self {
+        e.context(TranslationErrorKind::Generic).into()
+    }
+}
+
+impl From<TranslationErrorKind> for TranslationError {
+    fn from(kind: TranslationErrorKind) -> Self {
+        Context::new(kind).into()
+    }
+}
+
+impl From<Context<TranslationErrorKind>> for TranslationError {
+    fn from(ctx: Context<TranslationErrorKind>) -> Self {
+        Self {
+            loc: Vec::new(),
+            inner: Arc::new(ctx),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use c2rust_ast_exporter::clang_ast::SrcSpan;
+
+    #[test]
+    fn category_targets() {
+        for (diag, target) in [
+            (Diagnostic::Casts, "casts"),
+            (Diagnostic::Unions, "unions"),
+            (Diagnostic::Varargs, "varargs"),
+            (Diagnostic::UnsupportedBuiltins, "unsupported-builtins"),
+            (Diagnostic::StaticInitializers, "static-initializers"),
+            (Diagnostic::Macros, "macros"),
+            (Diagnostic::InlineAsm, "inline-asm"),
+        ] {
+            assert_eq!(diag.to_string(), target);
+            assert_eq!(Diagnostic::from_str(target).unwrap(), diag);
+        }
+    }
+
+    fn set(diags: &[Diagnostic]) -> HashSet<Diagnostic> {
+        diags.iter().cloned().collect()
+    }
+
+    #[test]
+    fn codes() {
+        let codes = Diagnostic::codes();
+        let unique: HashSet<_> = codes.iter().collect();
+        assert_eq!(unique.len(), codes.len());
+
+        assert_eq!(Diagnostic::Casts.code(), "C2R-0003");
+        assert_eq!(Diagnostic::from_code("C2R-0003"), Some(Diagnostic::Casts));
+        assert_eq!(Diagnostic::from_code("c2r-0004"), Some(Diagnostic::Unions));
+        assert_eq!(Diagnostic::from_code("C2R-9999"), None);
+        assert!(Diagnostic::Casts.explanation().contains("remove_redundant_casts"));
+    }
+
+    #[test]
+    fn filter_categories() {
+        let none = HashSet::new();
+        let enabled = resolve_enabled(&set(&[Diagnostic::Unions]), &none, &none);
+        assert!(is_enabled(&enabled, "inline-asm"));
+        assert!(is_enabled(&enabled, "unions"));
+        assert!(!is_enabled(&enabled, "casts"));
+        assert!(!is_enabled(&enabled, "macros"));
+
+        let enabled = resolve_enabled(&set(&[Diagnostic::Casts]), &none, &none);
+        assert!(is_enabled(&enabled, "casts"));
+
+        let enabled = resolve_enabled(&none, &set(&[Diagnostic::InlineAsm]), &none);
+        assert!(!is_enabled(&enabled, "inline-asm"));
+
+        let enabled = resolve_enabled(&set(&[Diagnostic::All]), &none, &none);
+        for diag in Diagnostic::iter().filter(|d| *d != Diagnostic::All) {
+            assert!(is_enabled(&enabled, &diag.to_string()));
+        }
+
+        let enabled = resolve_enabled(&none, &set(&[Diagnostic::All]), &set(&[Diagnostic::Macros]));
+        assert_eq!(enabled, set(&[Diagnostic::Macros]));
+    }
+
+    #[test]
+    fn unknown_targets() {
+        let none = HashSet::new();
+        let enabled = resolve_enabled(&none, &none, &none);
+        assert!(is_enabled(&enabled, "some::module::path"));
+
+        let enabled = resolve_enabled(&none, &set(&[Diagnostic::Other]), &none);
+        assert!(!is_enabled(&enabled, "some::module::path"));
+        assert!(is_enabled(&enabled, "inline-asm"));
+    }
+
+    #[test]
+    fn json_schema() {
+        let record = json_record(
+            Level::Warn,
+            "casts",
+            "\x1B[34m-->\x1B[0m implicit cast may lose data",
+            None,
+        );
+        let record: serde_json::Value = serde_json::from_str(&record.to_string()).unwrap();
+        let object = record.as_object().unwrap();
+        assert_eq!(object.len(), 6);
+        assert_eq!(object["level"], "warning");
+        assert_eq!(object["category"], "casts");
+        assert_eq!(object["code"], "C2R-0003");
+        assert_eq!(object["message"], "--> implicit cast may lose data");
+        assert!(object["file"].is_null());
+        assert!(object["line"].is_null());
+
+        let record = json_record(Level::Info, "c2rust_transpile", "Transpiling", None);
+        assert!(record["category"].is_null());
+        assert!(record["code"].is_null());
+        assert_eq!(record["level"], "info");
+    }
+
+    #[test]
+    fn human_colors() {
+        let message = "\x1B[34m-->\x1B[0m foo.c:1:2";
+        let plain = human_line(Level::Warn, "casts", message, None, false);
+        assert_eq!(plain, "warning: --> foo.c:1:2 [-Wcasts] [C2R-0003]");
+
+        let colored = human_line(Level::Warn, "casts", message, None, true);
+        assert!(colored.contains('\x1B'));
+        assert_eq!(strip_ansi(&colored), plain);
+    }
+
+    #[test]
+    fn color_selection() {
+        assert!(use_color(ColorChoice::Auto, true, false));
+        assert!(!use_color(ColorChoice::Auto, false, false));
+        assert!(!use_color(ColorChoice::Auto, true, true));
+        assert!(use_color(ColorChoice::Always, false, true));
+        assert!(!use_color(ColorChoice::Never, true, false));
+    }
+
+    #[test]
+    fn suppressed_files() {
+        let suppressions = Suppressions {
+            patterns: vec![glob::Pattern::new("vendor/**/*.h").unwrap()],
+            base_dir: PathBuf::from("/src/project"),
+        };
+        assert!(suppressions.matches(Path::new("/src/project/vendor/zlib/zlib.h")));
+        assert!(suppressions.matches(Path::new("vendor/zlib/zlib.h")));
+        assert!(!suppressions.matches(Path::new("/src/project/main.c")));
+        assert!(!suppressions.matches(Path::new("/usr/include/vendor/x.h")));
+
+        let suppressions = Suppressions {
+            patterns: vec![glob::Pattern::new("/usr/include/**").unwrap()],
+            base_dir: PathBuf::from("/src/project"),
+        };
+        assert!(suppressions.matches(Path::new("/usr/include/stdio.h")));
+    }
+
+    #[test]
+    fn verbosity() {
+        let debug = Level::Debug.to_level_filter();
+        assert!(stderr_level(LevelFilter::Warn, 0, false) < debug);
+        assert_eq!(stderr_level(LevelFilter::Warn, 1, false), LevelFilter::Info);
+        assert_eq!(stderr_level(LevelFilter::Warn, 2, false), debug);
+        assert_eq!(stderr_level(LevelFilter::Warn, 5, false), LevelFilter::Trace);
+        assert_eq!(stderr_level(LevelFilter::Warn, 2, true), LevelFilter::Error);
+        assert_eq!(stderr_level(LevelFilter::Off, 1, false), LevelFilter::Error);
+    }
+
+    #[test]
+    fn json_location() {
+        let loc = DisplaySrcSpan::new(
+            Some("foo.c".into()),
+            SrcSpan {
+                fileid: 0,
+                begin_line: 123,
+                begin_column: 7,
+                end_line: 123,
+                end_column: 20,
+            },
+        );
+        assert_eq!(loc.to_string(), "foo.c:123:7");
+
+        let record = json_record(Level::Warn, "unions", "union field", Some(&loc));
+        assert_eq!(record["file"], "foo.c");
+        assert_eq!(record["line"], 123);
+    }
+
+    #[test]
+    fn summary_counts() {
+        let counts: HashMap<_, _> = [
+            (Diagnostic::Comments, 7),
+            (Diagnostic::Casts, 412),
+            (Diagnostic::Unions, 30),
+            (Diagnostic::Macros, 0),
+        ]
+        .into_iter()
+        .collect();
+        let counts = sorted_counts(&counts);
+        assert_eq!(
+            summary_line(&counts, 83),
+            "casts: 412, unions: 30, comments: 7 (total 449 warnings across 83 files)"
+        );
+
+        let json = json_summary(&counts, &[(Diagnostic::Unions, 4)], 83);
+        assert_eq!(json["summary"]["casts"], 412);
+        assert_eq!(json["summary"].as_object().unwrap().len(), 3);
+        assert_eq!(json["duplicates"]["unions"], 4);
+        assert_eq!(json["total"], 449);
+        assert_eq!(json["files"], 83);
+    }
+
+    #[test]
+    fn dedup() {
+        let mut seen = SeenWarnings::new(2);
+        assert!(seen.insert(1));
+        assert!(!seen.insert(1));
+        assert!(seen.insert(2));
+        // Full: new warnings pass through without being remembered.
+        assert!(seen.insert(3));
+        assert!(seen.insert(3));
+        assert!(!seen.insert(2));
+    }
+
+    #[test]
+    fn fail_on_categories() {
+        let counts: HashMap<_, _> = [(Diagnostic::Casts, 2), (Diagnostic::Macros, 1)]
+            .into_iter()
+            .collect();
+
+        let totals = Totals::default();
+        assert!(failures(&counts, &HashSet::new(), totals.clone()).is_ok());
+
+        let unions: HashSet<_> = [Diagnostic::Unions].into_iter().collect();
+        assert!(failures(&counts, &unions, totals.clone()).is_ok());
+
+        let casts: HashSet<_> = [Diagnostic::Casts].into_iter().collect();
+        let summary = failures(&counts, &casts, totals.clone()).unwrap_err();
+        assert_eq!(summary.counts(), &[(Diagnostic::Casts, 2)]);
+
+        let all: HashSet<_> = [Diagnostic::All].into_iter().collect();
+        let summary = failures(&counts, &all, totals).unwrap_err();
+        assert_eq!(
+            summary.to_string(),
+            "translation emitted warnings in failing categories: casts: 2, macros: 1"
+        );
+    }
+
+    #[test]
+    fn exit_codes() {
+        let clean = Totals {
+            files: 1,
+            warnings: 0,
+            errors: 0,
+        };
+        assert_eq!(clean.exit_code(false), 0);
+        assert_eq!(clean.exit_code(true), 0);
+
+        let warned = Totals {
+            warnings: 3,
+            ..clean.clone()
+        };
+        assert_eq!(warned.exit_code(false), 0);
+        assert_eq!(warned.exit_code(true), 2);
+
+        let failed = Totals {
+            errors: 1,
+            ..warned.clone()
+        };
+        assert_eq!(failed.exit_code(false), 1);
+        assert_eq!(failed.exit_code(true), 1);
+    }
+
+    #[test]
+    fn footer() {
+        let totals = Totals {
+            files: 83,
+            warnings: 449,
+            errors: 0,
+        };
+        assert_eq!(totals.to_string(), "c2rust: files=83 warnings=449 errors=0");
+    }
+}
@@ -0,0 +1,191 @@
+//! A minimal model of the SARIF 2.1.0 log format, covering what's needed to
+//! report translation warnings to code-scanning tools.
+
+use serde_derive::Serialize;
+use strum::IntoEnumIterator;
+
+use super::Diagnostic;
+use crate::c_ast::DisplaySrcSpan;
+
+const SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+const VERSION: &str = "2.1.0";
+
+#[derive(Serialize)]
+pub struct SarifLog {
+    #[serde(rename = "$schema")]
+    schema: &'static str,
+    version: &'static str,
+    runs: Vec<Run>,
+}
+
+impl SarifLog {
+    /// A log of a single transpiler run that produced `results`.
+    pub fn new(results: Vec<SarifResult>) -> Self {
+        let rules = Diagnostic::iter()
+            .filter(|d| *d != Diagnostic::All)
+            .map(|d| Rule {
+                id: d.code(),
+                name: d.to_string(),
+                short_description: Message {
+                    text: d.explanation().split(". ").next().unwrap().to_owned(),
+                },
+            })
+            .collect();
+        SarifLog {
+            schema: SCHEMA,
+            version: VERSION,
+            runs: vec![Run {
+                tool: Tool {
+                    driver: Driver {
+                        name: "c2rust-transpile",
+                        version: env!("CARGO_PKG_VERSION"),
+                        information_uri: "https://c2rust.com/",
+                        rules,
+                    },
+                },
+                results,
+            }],
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Run {
+    tool: Tool,
+    results: Vec<SarifResult>,
+}
+
+#[derive(Serialize)]
+struct Tool {
+    driver: Driver,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Driver {
+    name: &'static str,
+    version: &'static str,
+    information_uri: &'static str,
+    rules: Vec<Rule>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Rule {
+    id: &'static str,
+    name: String,
+    short_description: Message,
+}
+
+/// A single reported warning.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SarifResult {
+    rule_id: &'static str,
+    level: &'static str,
+    message: Message,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    locations: Vec<Location>,
+}
+
+impl SarifResult {
+    pub fn new(diag: &Diagnostic, message: String, loc: Option<&DisplaySrcSpan>) -> Self {
+        let locations = loc
+            .and_then(|loc| {
+                let file = loc.file()?;
+                Some(Location {
+                    physical_location: PhysicalLocation {
+                        artifact_location: ArtifactLocation {
+                            uri: file.display().to_string(),
+                        },
+                        region: Region {
+                            start_line: loc.line(),
+                            start_column: loc.column(),
+                        },
+                    },
+                })
+            })
+            .into_iter()
+            .collect();
+        SarifResult {
+            rule_id: diag.code(),
+            level: "warning",
+            message: Message { text: message },
+            locations,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Message {
+    text: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Location {
+    physical_location: PhysicalLocation,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PhysicalLocation {
+    artifact_location: ArtifactLocation,
+    region: Region,
+}
+
+#[derive(Serialize)]
+struct ArtifactLocation {
+    uri: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Region {
+    start_line: u64,
+    start_column: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use c2rust_ast_exporter::clang_ast::SrcSpan;
+
+    #[test]
+    fn log_shape() {
+        let loc = DisplaySrcSpan::new(
+            Some("src/foo.c".into()),
+            SrcSpan {
+                fileid: 0,
+                begin_line: 12,
+                begin_column: 3,
+                end_line: 12,
+                end_column: 9,
+            },
+        );
+        let results = vec![
+            SarifResult::new(&Diagnostic::Casts, "lossy cast".into(), Some(&loc)),
+            SarifResult::new(&Diagnostic::Other, "no location".into(), None),
+        ];
+        let log = serde_json::to_value(SarifLog::new(results)).unwrap();
+
+        assert_eq!(log["version"], "2.1.0");
+        assert!(log["$schema"].as_str().unwrap().contains("sarif-2.1.0"));
+        let run = &log["runs"][0];
+        assert_eq!(run["tool"]["driver"]["name"], "c2rust-transpile");
+        let rules = run["tool"]["driver"]["rules"].as_array().unwrap();
+        assert!(rules.iter().any(|rule| rule["id"] == "C2R-0003"));
+        assert!(rules.iter().all(|rule| rule["id"] != "C2R-0000"));
+
+        let result = &run["results"][0];
+        assert_eq!(result["ruleId"], "C2R-0003");
+        assert_eq!(result["level"], "warning");
+        assert_eq!(result["message"]["text"], "lossy cast");
+        let location = &result["locations"][0]["physicalLocation"];
+        assert_eq!(location["artifactLocation"]["uri"], "src/foo.c");
+        assert_eq!(location["region"]["startLine"], 12);
+        assert_eq!(location["region"]["startColumn"], 3);
+
+        assert!(run["results"][1].get("locations").is_none());
+    }
+}
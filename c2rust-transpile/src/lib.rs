@@ -18,13 +18,13 @@ use std::path::{Path, PathBuf};
 use std::process;
 
 use failure::Error;
-use log::warn;
+use log::{error, warn};
 use regex::Regex;
 use serde_derive::Serialize;
 
 use crate::c_ast::Printer;
 use crate::c_ast::*;
-pub use crate::diagnostics::Diagnostic;
+pub use crate::diagnostics::{ColorChoice, Diagnostic, DiagnosticsFormat, FailSummary, Totals};
 use c2rust_ast_exporter as ast_exporter;
 
 use crate::build_files::{emit_build_files, get_build_dir, CrateConfig};
@@ -71,6 +71,15 @@ pub struct TranspilerConfig {
     pub reduce_type_annotations: bool,
     pub reorganize_definitions: bool,
     pub enabled_warnings: HashSet<Diagnostic>,
+    pub disabled_warnings: HashSet<Diagnostic>,
+    pub fail_on_warnings: HashSet<Diagnostic>,
+    pub diagnostics_format: DiagnosticsFormat,
+    pub diagnostics_file: Option<PathBuf>,
+    pub log_file: Option<PathBuf>,
+    pub log_file_append: bool,
+    pub color: ColorChoice,
+    pub suppress_warnings_in: Vec<glob::Pattern>,
+    pub deduplicate_warnings: bool,
     pub emit_no_std: bool,
     pub output_dir: Option<PathBuf>,
     pub translate_const_macros: bool,
@@ -78,6 +87,10 @@ pub struct TranspilerConfig {
     pub disable_refactoring: bool,
     pub preserve_unused_functions: bool,
     pub log_level: log::LevelFilter,
+    /// Number of `-v` flags, each raising the stderr log level by one
+    pub verbosity: u64,
+    /// Only print errors to stderr
+    pub quiet: bool,
 
     // Options that control build files
     /// Emit `Cargo.toml` and `lib.rs`
@@ -198,9 +211,27 @@ fn get_module_name(
 
 /// Main entry point to transpiler. Called from CLI tools with the result of
 /// clap::App::get_matches().
-pub fn transpile(tcfg: TranspilerConfig, cc_db: &Path, extra_clang_args: &[&str]) {
-    diagnostics::init(tcfg.enabled_warnings.clone(), tcfg.log_level);
+///
+/// Returns the number of files transpiled and warnings and errors emitted.
+/// An input file that is missing or that clang fails to parse is skipped and
+/// counted as an error, which makes `c2rust transpile` exit with status 1.
+/// Fails if any warnings were emitted in the categories listed in
+/// `tcfg.fail_on_warnings`.
+pub fn transpile(
+    tcfg: TranspilerConfig,
+    cc_db: &Path,
+    extra_clang_args: &[&str],
+) -> Result<Totals, FailSummary> {
+    diagnostics::init(&tcfg, cc_db);
+
+    let num_transpiled_files = transpile_all(&tcfg, cc_db, extra_clang_args);
 
+    diagnostics::print_summary(num_transpiled_files);
+    diagnostics::check_failures(num_transpiled_files)
+}
+
+/// Transpiles every input in `cc_db`, returning how many files were transpiled.
+fn transpile_all(tcfg: &TranspilerConfig, cc_db: &Path, extra_clang_args: &[&str]) -> usize {
     let lcmds = get_compile_commands(cc_db, &tcfg.filter).unwrap_or_else(|_| {
         panic!(
             "Could not parse compile commands from {}",
@@ -216,7 +247,7 @@ pub fn transpile(tcfg: TranspilerConfig, cc_db: &Path, extra_clang_args: &[&str]
     let mut top_level_ccfg = None;
     let mut workspace_members = vec![];
     let mut num_transpiled_files = 0;
-    let build_dir = get_build_dir(&tcfg, cc_db);
+    let build_dir = get_build_dir(tcfg, cc_db);
     for lcmd in &lcmds {
         let cmds = &lcmd.cmd_inputs;
         let lcmd_name = lcmd
@@ -263,7 +294,7 @@ pub fn transpile(tcfg: TranspilerConfig, cc_db: &Path, extra_clang_args: &[&str]
             .iter()
             .map(|cmd| {
                 transpile_single(
-                    &tcfg,
+                    tcfg,
                     cmd.abs_file(),
                     &ancestor_path,
                     &build_dir,
@@ -301,7 +332,7 @@ pub fn transpile(tcfg: TranspilerConfig, cc_db: &Path, extra_clang_args: &[&str]
             if modules_skipped {
                 // If we skipped a file, we may not have collected all required pragmas
                 warn!("Can't emit build files after incremental transpiler run; skipped.");
-                return;
+                return num_transpiled_files;
             }
 
             let ccfg = CrateConfig {
@@ -314,8 +345,8 @@ pub fn transpile(tcfg: TranspilerConfig, cc_db: &Path, extra_clang_args: &[&str]
             if lcmd.top_level {
                 top_level_ccfg = Some(ccfg);
             } else {
-                let crate_file = emit_build_files(&tcfg, &build_dir, Some(ccfg), None);
-                reorganize_definitions(&tcfg, &build_dir, crate_file)
+                let crate_file = emit_build_files(tcfg, &build_dir, Some(ccfg), None);
+                reorganize_definitions(tcfg, &build_dir, crate_file)
                     .unwrap_or_else(|e| warn!("Reorganizing definitions failed: {}", e));
                 workspace_members.push(lcmd_name);
             }
@@ -324,15 +355,17 @@ pub fn transpile(tcfg: TranspilerConfig, cc_db: &Path, extra_clang_args: &[&str]
 
     if num_transpiled_files == 0 {
         warn!("No C files found in compile_commands.json; nothing to do.");
-        return;
+        return num_transpiled_files;
     }
 
     if tcfg.emit_build_files {
         let crate_file =
-            emit_build_files(&tcfg, &build_dir, top_level_ccfg, Some(workspace_members));
-        reorganize_definitions(&tcfg, &build_dir, crate_file)
+            emit_build_files(tcfg, &build_dir, top_level_ccfg, Some(workspace_members));
+        reorganize_definitions(tcfg, &build_dir, crate_file)
             .unwrap_or_else(|e| warn!("Reorganizing definitions failed: {}", e));
     }
+
+    num_transpiled_files
 }
 
 /// Ensure that clang can locate the system headers on macOS 10.14+.
@@ -409,7 +442,7 @@ fn transpile_single(
 
     let file = input_path.file_name().unwrap().to_str().unwrap();
     if !input_path.exists() {
-        warn!(
+        error!(
             "Input C file {} does not exist, skipping!",
             input_path.display()
         );
@@ -428,8 +461,8 @@ fn transpile_single(
         tcfg.debug_ast_exporter,
     ) {
         Err(e) => {
-            warn!(
-                "Error: {}. Skipping {}; is it well-formed C?",
+            error!(
+                "{}. Skipping {}; is it well-formed C?",
                 e,
                 input_path.display()
             );
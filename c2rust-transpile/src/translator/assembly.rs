@@ -1,10 +1,9 @@
 #![deny(missing_docs)]
 //! This module provides basic support for converting inline assembly statements.
 
-use crate::diagnostics::TranslationResult;
+use crate::diagnostics::{diag, Diagnostic, TranslationResult};
 
 use super::*;
-use log::warn;
 use proc_macro2::{TokenStream, TokenTree};
 use syn::__private::ToTokens;
 
@@ -162,7 +161,8 @@ fn parse_constraints(
                     constraints = machine_constraints.into();
                     mem_only = is_mem;
                 } else {
-                    warn!(
+                    diag!(
+                        Diagnostic::InlineAsm,
                         "Did not recognize inline asm constraint: {}\n\
                     It is likely that this will cause compilation errors or \
                     incorrect semantics in the translated program; please \
@@ -214,7 +214,8 @@ fn translate_machine_constraint(constraint: &str, arch: Arch) -> Option<(&str, b
             "D" => "\"di\"",
             // "A" => "a_and_d", // rust does not support this
             "U" => {
-                warn!(
+                diag!(
+                    Diagnostic::InlineAsm,
                     "the x86 'U' inline assembly operand constraint cannot \
                 be translated correctly. It corresponds to the `clobber_abi` \
                 option for `asm!`, but c2rust does not know the ABI being \
@@ -966,7 +967,8 @@ impl<'c> Translation<'c> {
             // overwritten. Warn verbosely.
             let quoted = format!("\"{}\"", clobber);
             if reg_is_reserved(&quoted, arch).is_some() {
-                warn!(
+                diag!(
+                    Diagnostic::InlineAsm,
                     "Attempting to clobber reserved register ({}), dropping clobber! \
                 This likely means the potential for miscompilation has been introduced. \
                 Please rewrite this assembly to save/restore the value of this register \
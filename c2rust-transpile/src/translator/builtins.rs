@@ -224,6 +224,10 @@ impl<'c> Translation<'c> {
                 // We can't convert this to Rust, but it should be safe to always return -1/0
                 // (depending on the value of `type`), so we emit the following:
                 // `(if (type & 2) == 0 { -1isize } else { 0isize }) as libc::size_t`
+                diag!(
+                    Diagnostic::UnsupportedBuiltins,
+                    "__builtin_object_size is approximated as an unknown object size"
+                );
                 let ptr_arg = self.convert_expr(ctx.unused(), args[0])?;
                 let type_arg = self.convert_expr(ctx.used(), args[1])?;
                 ptr_arg.and_then(|_| {
@@ -11,13 +11,13 @@ use dtoa;
 use failure::{err_msg, format_err, Fail};
 use indexmap::indexmap;
 use indexmap::{IndexMap, IndexSet};
-use log::{error, info, trace, warn};
+use log::{error, trace, warn};
 use proc_macro2::{Punct, Spacing::*, Span, TokenStream, TokenTree};
 use syn::spanned::Spanned as _;
 use syn::*;
 use syn::{BinOp, UnOp}; // To override c_ast::{BinOp,UnOp} from glob import
 
-use crate::diagnostics::TranslationResult;
+use crate::diagnostics::{diag, diag_at, Diagnostic, TranslationResult};
 use crate::rust_ast::comment_store::CommentStore;
 use crate::rust_ast::item_store::ItemStore;
 use crate::rust_ast::set_span::SetSpan;
@@ -1800,6 +1800,12 @@ impl<'c> Translation<'c> {
 
                 Ok(if field_syns.is_empty() {
                     // Empty unions are a GNU extension, but Rust doesn't allow empty unions.
+                    diag_at!(
+                        Diagnostic::Unions,
+                        self.ast_context.display_loc(&decl.loc),
+                        "Empty union {} translated as an empty struct",
+                        name
+                    );
                     ConvertedDecl::Item(
                         mk().span(span)
                             .pub_()
@@ -2068,6 +2074,14 @@ impl<'c> Translation<'c> {
                 // Collect problematic static initializers and offload them to sections for the linker
                 // to initialize for us
                 let (ty, init) = if self.static_initializer_is_uncompilable(initializer, typ) {
+                    diag_at!(
+                        Diagnostic::StaticInitializers,
+                        self.ast_context.display_loc(&decl.loc),
+                        "Initializer of static {} cannot be evaluated at compile time; \
+                        moving it to run_static_initializers",
+                        new_name
+                    );
+
                     // Note: We don't pass has_static_duration through here. Extracted initializers
                     // are run outside of the static initializer.
                     let ConvertedVariable { ty, mutbl: _, init } =
@@ -2182,7 +2196,7 @@ impl<'c> Translation<'c> {
                     }
                     Err(e) => {
                         self.macro_expansions.borrow_mut().insert(decl_id, None);
-                        info!("Could not expand macro {}: {}", name, e);
+                        diag!(Diagnostic::Macros, "Could not expand macro {}: {}", name, e);
                         Ok(ConvertedDecl::NoItem)
                     }
                 }
@@ -4204,6 +4218,9 @@ impl<'c> Translation<'c> {
             return Ok(val);
         }
 
+        let expr_loc =
+            expr.and_then(|id| self.ast_context.display_loc(&self.ast_context[id].loc));
+
         let kind = kind.unwrap_or_else(|| {
             match (source_ty_kind, target_ty_kind) {
                 (CTypeKind::VariableArray(..), CTypeKind::Pointer(..))
@@ -4266,7 +4283,9 @@ impl<'c> Translation<'c> {
 
                 // Ignoring Complex casts for now
                 _ => {
-                    warn!(
+                    diag_at!(
+                        Diagnostic::Casts,
+                        expr_loc.clone(),
                         "Unknown CastKind for {:?} to {:?} cast. Defaulting to BitCast",
                         source_ty_kind, target_ty_kind,
                     );
@@ -4312,6 +4331,19 @@ impl<'c> Translation<'c> {
             | CastKind::FloatingCast
             | CastKind::FloatingToIntegral
             | CastKind::IntegralToFloating => {
+                if matches!(
+                    kind,
+                    CastKind::IntegralToPointer | CastKind::PointerToIntegral
+                ) {
+                    diag_at!(
+                        Diagnostic::Casts,
+                        expr_loc,
+                        "Cast from {:?} to {:?} converts between pointers and integers",
+                        source_ty_kind,
+                        target_ty_kind,
+                    );
+                }
+
                 let target_ty = self.convert_type(ty.ctype)?;
                 let target_ty_ctype = &self.ast_context.resolve_type(ty.ctype).kind;
 
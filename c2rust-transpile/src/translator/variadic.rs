@@ -232,6 +232,10 @@ impl<'c> Translation<'c> {
     /// building a list of variable declarations to be translated into `VaListImpl`s. Returns the
     /// name of the `VaList` function argument for convenience.
     pub fn register_va_decls(&self, body: CStmtId) -> String {
+        diag!(
+            Diagnostic::Varargs,
+            "Variadic function definitions require the nightly c_variadic feature"
+        );
         self.use_feature("c_variadic");
 
         let va_list_arg_name = self.renamer.borrow_mut().pick_name("args");
@@ -1,15 +1,34 @@
-use clap::{load_yaml, App};
+use clap::{load_yaml, App, ArgMatches};
 use regex::Regex;
 use std::collections::HashSet;
+use std::fs::File;
 use std::path::{Path, PathBuf};
+use std::process;
 use std::str::FromStr;
 
-use c2rust_transpile::{Diagnostic, ReplaceMode, TranspilerConfig};
+use c2rust_transpile::{
+    ColorChoice, Diagnostic, DiagnosticsFormat, ReplaceMode, TranspilerConfig,
+};
 
 fn main() {
     let yaml = load_yaml!("../transpile.yaml");
     let matches = App::from_yaml(yaml).get_matches();
 
+    if let Some(code) = matches.value_of("explain") {
+        match Diagnostic::from_code(code) {
+            Some(diag) => println!("{} [-W{}]\n\n{}", diag.code(), diag, diag.explanation()),
+            None => {
+                eprintln!(
+                    "error: unknown warning code {}; valid codes are: {}",
+                    code,
+                    Diagnostic::codes().join(", ")
+                );
+                process::exit(1);
+            }
+        }
+        return;
+    }
+
     // Build a TranspilerConfig from the command line
     let cc_json_path = Path::new(matches.value_of("COMPILE_COMMANDS").unwrap());
     let cc_json_path = cc_json_path.canonicalize().unwrap_or_else(|_| {
@@ -23,12 +42,38 @@ fn main() {
         None => Vec::new(),
     };
 
-    let enabled_warnings: HashSet<Diagnostic> = matches
-        .values_of("warn")
+    let enabled_warnings = diagnostics_arg(&matches, "warn");
+    let disabled_warnings = diagnostics_arg(&matches, "no-warn");
+    let fail_on_warnings = diagnostics_arg(&matches, "fail-on");
+
+    let suppress_warnings_in: Vec<glob::Pattern> = matches
+        .values_of("suppress-warnings-in")
         .unwrap_or_default()
-        .map(|s| Diagnostic::from_str(s).unwrap())
+        .map(|glob| {
+            glob::Pattern::new(glob).unwrap_or_else(|e| {
+                eprintln!(
+                    "error: invalid glob {} for --suppress-warnings-in: {}",
+                    glob, e
+                );
+                process::exit(1);
+            })
+        })
         .collect();
 
+    let diagnostics_format =
+        DiagnosticsFormat::from_str(matches.value_of("diagnostics-format").unwrap()).unwrap();
+    if diagnostics_format == DiagnosticsFormat::Sarif && !matches.is_present("diagnostics-file")
+    {
+        eprintln!("error: --diagnostics-format sarif requires --diagnostics-file");
+        process::exit(1);
+    }
+    if let Some(path) = matches.value_of("diagnostics-file") {
+        if let Err(e) = File::create(path) {
+            eprintln!("error: could not create diagnostics file {}: {}", path, e);
+            process::exit(1);
+        }
+    }
+
     let log_level = match matches.value_of("log-level") {
         Some("off") => log::LevelFilter::Off,
         Some("error") => log::LevelFilter::Error,
@@ -49,6 +94,8 @@ fn main() {
         dump_structures: matches.is_present("dump-structures"),
         debug_ast_exporter: matches.is_present("debug-ast-exporter"),
         verbose: matches.is_present("verbose"),
+        verbosity: matches.occurrences_of("verbose"),
+        quiet: matches.is_present("quiet"),
 
         incremental_relooper: !matches.is_present("no-incremental-relooper"),
         fail_on_error: matches.is_present("fail-on-error"),
@@ -102,6 +149,15 @@ fn main() {
         replace_unsupported_decls: ReplaceMode::Extern,
         emit_no_std: matches.is_present("emit-no-std"),
         enabled_warnings,
+        disabled_warnings,
+        fail_on_warnings,
+        diagnostics_format,
+        diagnostics_file: matches.value_of("diagnostics-file").map(PathBuf::from),
+        log_file: matches.value_of("log-file").map(PathBuf::from),
+        log_file_append: matches.is_present("log-file-append"),
+        color: ColorChoice::from_str(matches.value_of("color").unwrap()).unwrap(),
+        suppress_warnings_in,
+        deduplicate_warnings: !matches.is_present("no-dedup"),
         log_level,
     };
     // binaries imply emit-build-files
@@ -113,5 +169,38 @@ fn main() {
         tcfg.emit_modules = true
     };
 
-    c2rust_transpile::transpile(tcfg, &cc_json_path, &extra_args);
+    let warnings_affect_exit = matches.is_present("warnings-affect-exit");
+    let (totals, code) = match c2rust_transpile::transpile(tcfg, &cc_json_path, &extra_args) {
+        Ok(totals) => {
+            let code = totals.exit_code(warnings_affect_exit);
+            (totals, code)
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            (e.totals().clone(), 1)
+        }
+    };
+    // Always last, so scripts can find it without parsing the rest of the log.
+    eprintln!("{}", totals);
+    process::exit(code);
+}
+
+/// The diagnostics named by the values of `arg`, exiting with an error if one
+/// of them isn't a category.
+fn diagnostics_arg(matches: &ArgMatches, arg: &str) -> HashSet<Diagnostic> {
+    matches
+        .values_of(arg)
+        .unwrap_or_default()
+        .map(|s| {
+            Diagnostic::from_str(s).unwrap_or_else(|_| {
+                eprintln!(
+                    "error: unknown warning {} for --{}; valid warnings are: {}",
+                    s,
+                    arg,
+                    Diagnostic::names().join(", ")
+                );
+                process::exit(1);
+            })
+        })
+        .collect()
 }
@@ -0,0 +1,406 @@
+//! End-to-end tests of the transpiler's diagnostics: each test runs the
+//! `c2rust-transpile` binary on a few small C files and checks what it reports.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+/// A scratch directory holding some C files and a `compile_commands.json`
+/// that compiles each of them.
+struct Project {
+    dir: PathBuf,
+}
+
+impl Project {
+    fn new(name: &str, files: &[(&str, &str)]) -> Project {
+        let dir = Path::new(env!("CARGO_TARGET_TMPDIR"))
+            .join("diagnostics")
+            .join(name);
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let mut commands = Vec::new();
+        for (file, src) in files {
+            let path = dir.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, src).unwrap();
+            commands.push(format!(
+                r#"{{"directory": "{}", "file": "{}", "arguments": ["cc", "-c", "{}"]}}"#,
+                dir.display(),
+                file,
+                file
+            ));
+        }
+        fs::write(
+            dir.join("compile_commands.json"),
+            format!("[{}]", commands.join(",\n")),
+        )
+        .unwrap();
+
+        Project { dir }
+    }
+
+    fn path(&self, file: &str) -> PathBuf {
+        self.dir.join(file)
+    }
+
+    /// Runs the transpiler over the project with the extra command line `args`.
+    fn transpile(&self, args: &[&str]) -> Output {
+        Command::new(env!("CARGO_BIN_EXE_c2rust-transpile"))
+            .arg(self.path("compile_commands.json"))
+            .arg("--overwrite-existing")
+            .args(args)
+            .output()
+            .unwrap()
+    }
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+const PTR_TO_INT: &str = "long addr(int *p) {\n    return (long)p;\n}\n";
+
+/// A C file exercising each category, by the category's `-W` name. `macros`
+/// isn't covered since it's only reported with `--translate-const-macros`.
+const CATEGORIES: &[(&str, &str)] = &[
+    ("casts", PTR_TO_INT),
+    (
+        "unions",
+        "union empty {};\nint empty_size(union empty *u) {\n    return 0;\n}\n",
+    ),
+    ("varargs", "int first(int n, ...) {\n    return n;\n}\n"),
+    (
+        "unsupported-builtins",
+        "unsigned long size(char *p) {\n    return __builtin_object_size(p, 0);\n}\n",
+    ),
+    ("static-initializers", "unsigned int all_ones = -1u;\n"),
+];
+
+#[test]
+fn categories() {
+    for (category, src) in CATEGORIES {
+        let project = Project::new(category, &[("test.c", src)]);
+        let flag = format!("[-W{}]", category);
+
+        let output = project.transpile(&["-W", "all"]);
+        assert!(output.status.success(), "{}", stderr(&output));
+        assert!(stderr(&output).contains(&flag), "{}", stderr(&output));
+
+        let output = project.transpile(&["-W", "all", "--no-warn", category]);
+        assert!(output.status.success(), "{}", stderr(&output));
+        assert!(!stderr(&output).contains(&flag), "{}", stderr(&output));
+    }
+}
+
+#[test]
+fn casts_are_opt_in() {
+    let project = Project::new("casts_are_opt_in", &[("test.c", PTR_TO_INT)]);
+
+    let output = project.transpile(&[]);
+    assert!(!stderr(&output).contains("[-Wcasts]"), "{}", stderr(&output));
+
+    let output = project.transpile(&["-W", "casts"]);
+    assert!(stderr(&output).contains("[-Wcasts]"), "{}", stderr(&output));
+}
+
+#[test]
+fn summary() {
+    let project = Project::new(
+        "summary",
+        &[
+            ("a.c", PTR_TO_INT),
+            ("b.c", "long lib_addr(char *p) {\n    return (long)p;\n}\n"),
+        ],
+    );
+
+    let output = project.transpile(&["-W", "casts"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(
+        stderr(&output).contains("casts: 2 (total 2 warnings across 2 files)"),
+        "{}",
+        stderr(&output)
+    );
+}
+
+#[test]
+fn unimplemented_builtin_reported_once() {
+    let project = Project::new(
+        "unimplemented_builtin_reported_once",
+        &[("test.c", "void trap(void) {\n    __builtin_debugtrap();\n}\n")],
+    );
+
+    let output = project.transpile(&[]);
+    let reports = stderr(&output)
+        .matches("Unimplemented builtin __builtin_debugtrap")
+        .count();
+    assert_eq!(reports, 1, "{}", stderr(&output));
+}
+
+/// Both casts in the expansion are reported with the same message at the
+/// macro's argument.
+const CAST_TWICE: &str = "#define ADDR_SUM(p) ((long)(p) + (long)(p))\n\
+                          long addr_sum(int *p) {\n    return ADDR_SUM(p);\n}\n";
+const CAST_WARNING: &str = "converts between pointers and integers";
+
+#[test]
+fn duplicate_warnings_reported_once() {
+    let project = Project::new("duplicate_warnings_reported_once", &[("test.c", CAST_TWICE)]);
+
+    let output = project.transpile(&["-W", "casts"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(
+        stderr(&output).matches(CAST_WARNING).count(),
+        1,
+        "{}",
+        stderr(&output)
+    );
+    assert!(
+        stderr(&output).contains("casts: 1 duplicate warnings suppressed"),
+        "{}",
+        stderr(&output)
+    );
+}
+
+#[test]
+fn no_dedup() {
+    let project = Project::new("no_dedup", &[("test.c", CAST_TWICE)]);
+
+    let output = project.transpile(&["-W", "casts", "--no-dedup"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(
+        stderr(&output).matches(CAST_WARNING).count(),
+        2,
+        "{}",
+        stderr(&output)
+    );
+    assert!(
+        !stderr(&output).contains("duplicate warnings suppressed"),
+        "{}",
+        stderr(&output)
+    );
+}
+
+#[test]
+fn unknown_warning_name() {
+    let project = Project::new("unknown_warning_name", &[("test.c", PTR_TO_INT)]);
+
+    for flag in &["--warn", "--no-warn", "--fail-on"] {
+        let output = project.transpile(&[flag, "cast"]);
+        assert_eq!(output.status.code(), Some(1), "{}", stderr(&output));
+        assert!(stderr(&output).contains("unknown warning cast"), "{}", stderr(&output));
+        assert!(!stderr(&output).contains("panicked"), "{}", stderr(&output));
+    }
+}
+
+#[test]
+fn uncreatable_diagnostics_file() {
+    let project = Project::new("uncreatable_diagnostics_file", &[("test.c", PTR_TO_INT)]);
+    let path = project.path("missing/diagnostics.json");
+
+    let output = project.transpile(&[
+        "--diagnostics-format",
+        "json",
+        "--diagnostics-file",
+        path.to_str().unwrap(),
+    ]);
+    assert_eq!(output.status.code(), Some(1), "{}", stderr(&output));
+    assert!(
+        stderr(&output).contains("could not create diagnostics file"),
+        "{}",
+        stderr(&output)
+    );
+    assert!(!stderr(&output).contains("panicked"), "{}", stderr(&output));
+}
+
+#[test]
+fn invalid_suppression_glob() {
+    let project = Project::new("invalid_suppression_glob", &[("test.c", PTR_TO_INT)]);
+
+    let output = project.transpile(&["--suppress-warnings-in", "vendor/[*.c"]);
+    assert_eq!(output.status.code(), Some(1), "{}", stderr(&output));
+    assert!(stderr(&output).contains("invalid glob vendor/[*.c"), "{}", stderr(&output));
+    assert!(!stderr(&output).contains("panicked"), "{}", stderr(&output));
+}
+
+#[test]
+fn suppressed_files() {
+    let project = Project::new(
+        "suppressed_files",
+        &[
+            ("main.c", PTR_TO_INT),
+            ("vendor/lib.c", "long lib_addr(char *p) {\n    return (long)p;\n}\n"),
+        ],
+    );
+
+    let output = project.transpile(&["-W", "casts", "--suppress-warnings-in", "vendor/**"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stderr(&output).contains("main.c:2:"), "{}", stderr(&output));
+    assert!(!stderr(&output).contains("lib.c:"), "{}", stderr(&output));
+}
+
+#[test]
+fn fail_on() {
+    let project = Project::new("fail_on", &[("test.c", PTR_TO_INT)]);
+
+    let output = project.transpile(&["-W", "casts"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let output = project.transpile(&["--fail-on", "casts"]);
+    assert!(!output.status.success(), "{}", stderr(&output));
+    assert!(
+        stderr(&output).contains("translation emitted warnings in failing categories: casts: 1"),
+        "{}",
+        stderr(&output)
+    );
+
+    let output = project.transpile(&["-W", "casts", "--fail-on", "unions"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+}
+
+#[test]
+fn locations() {
+    let project = Project::new(
+        "locations",
+        &[(
+            "test.c",
+            "union empty {};\nlong addr(union empty *u) {\n    return (long)u;\n}\n",
+        )],
+    );
+
+    let output = project.transpile(&["-W", "casts", "-W", "unions"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    // Casts are reported at their operand.
+    assert!(
+        stderr(&output).contains("test.c:3:18: warning: Cast from"),
+        "{}",
+        stderr(&output)
+    );
+    assert!(
+        stderr(&output).contains("test.c:1:1: warning: Empty union empty"),
+        "{}",
+        stderr(&output)
+    );
+}
+
+#[test]
+fn log_file() {
+    let project = Project::new("log_file", &[("test.c", PTR_TO_INT)]);
+    let log = project.path("transpile.log");
+
+    let output = project.transpile(&[
+        "-W",
+        "casts",
+        "--color",
+        "always",
+        "--log-file",
+        log.to_str().unwrap(),
+    ]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stderr(&output).contains("[-Wcasts]"), "{}", stderr(&output));
+    assert!(stderr(&output).contains("\x1B["), "{}", stderr(&output));
+    let logged = fs::read_to_string(&log).unwrap();
+    assert!(logged.contains("[-Wcasts]"), "{}", logged);
+    assert!(!logged.contains('\x1B'), "{}", logged);
+
+    // A log file we can't open is reported once and doesn't stop the transpile.
+    let missing = project.path("missing/transpile.log");
+    let output = project.transpile(&["--log-file", missing.to_str().unwrap()]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(
+        stderr(&output).matches("could not open log file").count(),
+        1,
+        "{}",
+        stderr(&output)
+    );
+}
+
+#[test]
+fn verbosity() {
+    let project = Project::new(
+        "verbosity",
+        &[(
+            "test.c",
+            "// Returns its argument as an integer.\nlong addr(int *p) {\n    return (long)p;\n}\n",
+        )],
+    );
+    let debug = "debug: Attaching comments";
+
+    let output = project.transpile(&["-W", "casts"]);
+    assert!(!stderr(&output).contains(debug), "{}", stderr(&output));
+    assert!(stderr(&output).contains("[-Wcasts]"), "{}", stderr(&output));
+
+    let output = project.transpile(&["-W", "casts", "-v"]);
+    assert!(!stderr(&output).contains(debug), "{}", stderr(&output));
+
+    // Debug records aren't subject to the warning filter.
+    let output = project.transpile(&["--no-warn", "all", "-vv"]);
+    assert!(stderr(&output).contains(debug), "{}", stderr(&output));
+
+    let output = project.transpile(&["-W", "casts", "--quiet"]);
+    assert!(!stderr(&output).contains("[-Wcasts]"), "{}", stderr(&output));
+}
+
+#[test]
+fn sarif() {
+    let project = Project::new("sarif", &[("test.c", PTR_TO_INT)]);
+    let sarif = project.path("out.sarif");
+
+    let output = project.transpile(&[
+        "-W",
+        "casts",
+        "--diagnostics-format",
+        "sarif",
+        "--diagnostics-file",
+        sarif.to_str().unwrap(),
+    ]);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let log: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&sarif).unwrap()).unwrap();
+    assert_eq!(log["version"], "2.1.0");
+    let run = &log["runs"][0];
+    assert_eq!(run["tool"]["driver"]["name"], "c2rust-transpile");
+    let results = run["results"].as_array().unwrap();
+    assert_eq!(results.len(), 1, "{:#}", log);
+    assert_eq!(results[0]["ruleId"], "C2R-0003");
+    assert_eq!(results[0]["level"], "warning");
+    let location = &results[0]["locations"][0]["physicalLocation"];
+    assert!(location["artifactLocation"]["uri"]
+        .as_str()
+        .unwrap()
+        .ends_with("test.c"));
+    assert_eq!(location["region"]["startLine"], 2);
+}
+
+#[test]
+fn exit_codes() {
+    let clean = Project::new(
+        "exit_codes_clean",
+        &[("test.c", "int id(int x) {\n    return x;\n}\n")],
+    );
+    let output = clean.transpile(&["--warnings-affect-exit"]);
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    assert_eq!(
+        stderr(&output).lines().last(),
+        Some("c2rust: files=1 warnings=0 errors=0")
+    );
+
+    let warning = Project::new("exit_codes_warning", &[("test.c", PTR_TO_INT)]);
+    let output = warning.transpile(&["-W", "casts"]);
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+
+    let output = warning.transpile(&["-W", "casts", "--warnings-affect-exit"]);
+    assert_eq!(output.status.code(), Some(2), "{}", stderr(&output));
+    assert_eq!(
+        stderr(&output).lines().last(),
+        Some("c2rust: files=1 warnings=1 errors=0")
+    );
+
+    let error = Project::new(
+        "exit_codes_error",
+        &[("test.c", "void trap(void) {\n    __builtin_debugtrap();\n}\n")],
+    );
+    let output = error.transpile(&["--warnings-affect-exit"]);
+    assert_eq!(output.status.code(), Some(1), "{}", stderr(&output));
+}